@@ -0,0 +1,92 @@
+// Run an agent on a task created at runtime instead of a tasklet loaded from disk:
+//
+//   cargo run -p nerve-core --example custom_task -- "openai://gpt-4o" "what's the time?"
+use std::collections::HashMap;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use nerve_core::{
+    agent::{
+        events::Event,
+        namespaces::{Action, Namespace},
+        state::SharedState,
+    },
+    AgentBuilder, Task,
+};
+
+#[derive(Debug)]
+struct Question {
+    question: String,
+}
+
+impl Task for Question {
+    fn to_system_prompt(&self) -> Result<String> {
+        Ok("You are a helpful assistant answering the questions of the user.".to_string())
+    }
+
+    fn to_prompt(&self) -> Result<String> {
+        Ok(self.question.to_string())
+    }
+
+    fn get_functions(&self) -> Vec<Namespace> {
+        vec![Namespace::new_non_default(
+            "Clock".to_string(),
+            "Use this action to know the current time.".to_string(),
+            vec![Box::new(Now {})],
+            None,
+        )]
+    }
+
+    fn namespaces(&self) -> Option<Vec<String>> {
+        Some(vec!["task".to_string()])
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+struct Now {}
+
+#[async_trait]
+impl Action for Now {
+    fn name(&self) -> &str {
+        "current_time"
+    }
+
+    fn description(&self) -> &str {
+        "Get the current time."
+    }
+
+    async fn run(
+        &self,
+        _: SharedState,
+        _: Option<HashMap<String, String>>,
+        _: Option<String>,
+    ) -> Result<Option<String>> {
+        Ok(Some(chrono::Local::now().to_rfc2822()))
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let mut args = std::env::args().skip(1);
+    let generator = args
+        .next()
+        .unwrap_or("ollama://llama3@localhost:11434".to_string());
+    let question = args.next().unwrap_or("What time is it?".to_string());
+
+    let (mut agent, mut events) = AgentBuilder::new()
+        .generator(&generator)
+        .task(Question { question })
+        .max_iterations(10)
+        .build()
+        .await?;
+
+    tokio::spawn(async move {
+        while let Some(event) = events.recv().await {
+            if let Event::TaskComplete { reason, .. } = event {
+                println!("{}", reason.unwrap_or_default());
+            }
+        }
+    });
+
+    agent.run().await
+}
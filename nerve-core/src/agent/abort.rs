@@ -0,0 +1,61 @@
+use std::fmt;
+
+// why a run was aborted before the task was complete
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AbortReason {
+    // the maximum number of steps, duration or cost was reached
+    BudgetExhausted,
+    // the generator failed to reply
+    Generator,
+    // too many consecutive unparsable responses or failed actions
+    TooManyErrors,
+}
+
+// the errors returned by Agent::run when it aborts the run can be downcast to this
+#[derive(Debug)]
+pub struct Aborted {
+    pub reason: AbortReason,
+    message: String,
+}
+
+impl Aborted {
+    pub fn new(reason: AbortReason, message: impl fmt::Display) -> Self {
+        Self {
+            reason,
+            message: message.to_string(),
+        }
+    }
+
+    // the reason of the abort if the error is one
+    pub fn reason_of(error: &anyhow::Error) -> Option<AbortReason> {
+        error.downcast_ref::<Self>().map(|aborted| aborted.reason)
+    }
+}
+
+impl fmt::Display for Aborted {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for Aborted {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reason_of() {
+        let error: anyhow::Error = Aborted::new(
+            AbortReason::BudgetExhausted,
+            "maximum number of steps reached",
+        )
+        .into();
+        assert_eq!(error.to_string(), "maximum number of steps reached");
+        assert_eq!(
+            Aborted::reason_of(&error),
+            Some(AbortReason::BudgetExhausted)
+        );
+        assert_eq!(Aborted::reason_of(&anyhow!("rejected by user")), None);
+    }
+}
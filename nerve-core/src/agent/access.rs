@@ -0,0 +1,93 @@
+use serde::Deserialize;
+
+// operator defined lists of the actions that can or can't be used, entries are action names,
+// namespace names or action name prefixes ending with *
+#[derive(Debug, Clone, Default, Deserialize, PartialEq)]
+pub struct ActionLists {
+    #[serde(default)]
+    pub allow: Vec<String>,
+    #[serde(default)]
+    pub deny: Vec<String>,
+}
+
+impl ActionLists {
+    pub fn new(allow: Vec<String>, deny: Vec<String>) -> Self {
+        let clean = |list: Vec<String>| -> Vec<String> {
+            list.into_iter()
+                .map(|s| s.trim().to_string())
+                .filter(|s| !s.is_empty())
+                .collect()
+        };
+
+        Self {
+            allow: clean(allow),
+            deny: clean(deny),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.allow.is_empty() && self.deny.is_empty()
+    }
+
+    pub fn extend(&mut self, other: ActionLists) {
+        self.allow.extend(other.allow);
+        self.deny.extend(other.deny);
+    }
+
+    fn matches(entry: &str, namespace: &str, action: &str) -> bool {
+        if let Some(prefix) = entry.strip_suffix('*') {
+            action.starts_with(prefix)
+        } else {
+            entry == action || entry.eq_ignore_ascii_case(namespace)
+        }
+    }
+
+    // denied entries always win, if an allow list is set only its entries are allowed
+    pub fn is_allowed(&self, namespace: &str, action: &str) -> bool {
+        if self
+            .deny
+            .iter()
+            .any(|entry| Self::matches(entry, namespace, action))
+        {
+            return false;
+        }
+
+        self.allow.is_empty()
+            || self
+                .allow
+                .iter()
+                .any(|entry| Self::matches(entry, namespace, action))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lists(allow: &[&str], deny: &[&str]) -> ActionLists {
+        ActionLists::new(
+            allow.iter().map(|s| s.to_string()).collect(),
+            deny.iter().map(|s| s.to_string()).collect(),
+        )
+    }
+
+    #[test]
+    fn test_deny() {
+        let lists = lists(&[], &["shell", "append_to_file", "http_*"]);
+        assert!(!lists.is_allowed("shell", "shell"));
+        assert!(!lists.is_allowed("filesystem", "append_to_file"));
+        assert!(lists.is_allowed("filesystem", "read_file"));
+        assert!(!lists.is_allowed("http", "http_request"));
+        assert!(lists.is_allowed("memory", "save_memory"));
+    }
+
+    #[test]
+    fn test_allow() {
+        let lists = lists(&["filesystem", "task_complete"], &["append_to_file"]);
+        assert!(lists.is_allowed("filesystem", "read_file"));
+        assert!(!lists.is_allowed("filesystem", "append_to_file"));
+        assert!(lists.is_allowed("task", "task_complete"));
+        assert!(!lists.is_allowed("task", "task_impossible"));
+        assert!(!lists.is_allowed("shell", "shell"));
+    }
+}
@@ -0,0 +1,369 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs::{File, OpenOptions},
+    io::{BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, OnceLock},
+    time::Duration,
+};
+
+use anyhow::Result;
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use super::{secrets, Invocation};
+
+// previous hash of the first entry
+const GENESIS: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+// runs of the same process writing to the same log must share its chain
+static SHARED: OnceLock<Mutex<HashMap<PathBuf, Arc<AuditLog>>>> = OnceLock::new();
+
+// how the operator was involved in the execution of an invocation
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Approval {
+    // no confirmation was required
+    NotRequired,
+    Approved,
+    // approved after editing the invocation
+    Edited,
+    Rejected,
+    // the operator skipped it in step mode
+    Skipped,
+    // the operator provided the result in step mode
+    Injected,
+    // blocked by a guardrail before reaching the operator
+    Blocked,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuditEntry {
+    pub seq: u64,
+    pub time: DateTime<Local>,
+    pub tasklet: String,
+    // the user running nerve
+    pub operator: String,
+    pub step: usize,
+    pub namespace: Option<String>,
+    pub action: String,
+    pub payload: Option<String>,
+    pub attributes: Option<BTreeMap<String, String>>,
+    pub approval: Approval,
+    // success, error, timeout, injected or not_executed
+    pub outcome: String,
+    pub error: Option<String>,
+    // the output itself is in the history, the hash ties it to the entry
+    pub result_sha256: Option<String>,
+    pub elapsed_ms: u128,
+    pub prev_hash: String,
+    #[serde(default)]
+    pub hash: String,
+}
+
+// what happened to an invocation
+pub enum Outcome<'a> {
+    Executed(&'a Result<Option<String>>),
+    // not executed, the operator provided this result instead
+    Injected(&'a Result<Option<String>>),
+    TimedOut,
+    NotExecuted,
+}
+
+// sha256 of the entry without its hash, serialized with sorted keys
+fn entry_hash(entry: &Value) -> Result<String> {
+    let mut entry = entry.clone();
+    entry
+        .as_object_mut()
+        .ok_or_else(|| anyhow!("audit entries must be objects"))?
+        .remove("hash");
+    Ok(sha256::digest(serde_json::to_string(&entry)?))
+}
+
+fn operator() -> String {
+    std::env::var("USER")
+        .or_else(|_| std::env::var("USERNAME"))
+        .unwrap_or_else(|_| "unknown".to_string())
+}
+
+struct Chain {
+    file: File,
+    seq: u64,
+    last_hash: String,
+}
+
+// append only log of the executed invocations where every entry includes the hash of the
+// previous one, so that removing or changing any of them breaks the chain
+pub struct AuditLog {
+    path: PathBuf,
+    chain: Mutex<Chain>,
+}
+
+impl AuditLog {
+    // open the log, or return the one already opened by another run with the same path
+    pub fn shared<P: AsRef<Path>>(path: P) -> Result<Arc<Self>> {
+        let path = path.as_ref();
+        let key = std::path::absolute(path).unwrap_or(path.to_path_buf());
+        let mut shared = SHARED.get_or_init(Default::default).lock().unwrap();
+        if let Some(log) = shared.get(&key) {
+            return Ok(log.clone());
+        }
+        let log = Arc::new(Self::open(path)?);
+        shared.insert(key, log.clone());
+        Ok(log)
+    }
+
+    // open the log, continuing the chain of an existing one
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let (seq, last_hash) = if path.exists() {
+            let entries = Self::verify(&path)?;
+            match entries.last() {
+                Some(last) => (last.seq, last.hash.to_string()),
+                None => (0, GENESIS.to_string()),
+            }
+        } else {
+            (0, GENESIS.to_string())
+        };
+
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .map_err(|e| anyhow!("can't open audit log {}: {}", path.display(), e))?;
+
+        Ok(Self {
+            path,
+            chain: Mutex::new(Chain {
+                file,
+                seq,
+                last_hash,
+            }),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    // check the whole chain and return its entries
+    pub fn verify<P: AsRef<Path>>(path: P) -> Result<Vec<AuditEntry>> {
+        let path = path.as_ref();
+        let file = File::open(path)
+            .map_err(|e| anyhow!("can't open audit log {}: {}", path.display(), e))?;
+
+        let mut entries: Vec<AuditEntry> = vec![];
+        let mut prev_hash = GENESIS.to_string();
+        for (idx, line) in BufReader::new(file).lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let number = idx + 1;
+            let value: Value = serde_json::from_str(&line)
+                .map_err(|e| anyhow!("line {}: invalid entry: {}", number, e))?;
+            let entry: AuditEntry = serde_json::from_value(value.clone())
+                .map_err(|e| anyhow!("line {}: invalid entry: {}", number, e))?;
+
+            if entry.prev_hash != prev_hash {
+                return Err(anyhow!(
+                    "line {}: the chain is broken, an entry was removed or reordered",
+                    number
+                ));
+            }
+            if entry_hash(&value)? != entry.hash {
+                return Err(anyhow!("line {}: the entry was modified", number));
+            }
+            let expected = entries.last().map(|e| e.seq + 1).unwrap_or(1);
+            if entry.seq != expected {
+                return Err(anyhow!(
+                    "line {}: expected entry {}, found {}",
+                    number,
+                    expected,
+                    entry.seq
+                ));
+            }
+
+            prev_hash = entry.hash.to_string();
+            entries.push(entry);
+        }
+
+        Ok(entries)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn record(
+        &self,
+        tasklet: &str,
+        step: usize,
+        namespace: Option<String>,
+        invocation: &Invocation,
+        approval: Approval,
+        outcome: Outcome,
+        elapsed: Duration,
+    ) -> Result<()> {
+        let (outcome, error, result_sha256) = match outcome {
+            Outcome::Executed(Ok(result)) => (
+                "success",
+                None,
+                result.as_ref().map(|r| sha256::digest(r.as_str())),
+            ),
+            Outcome::Executed(Err(err)) => ("error", Some(secrets::redact(&err.to_string())), None),
+            Outcome::Injected(Ok(result)) => (
+                "injected",
+                None,
+                result.as_ref().map(|r| sha256::digest(r.as_str())),
+            ),
+            Outcome::Injected(Err(err)) => {
+                ("injected", Some(secrets::redact(&err.to_string())), None)
+            }
+            Outcome::TimedOut => ("timeout", None, None),
+            Outcome::NotExecuted => ("not_executed", None, None),
+        };
+
+        let mut chain = self.chain.lock().unwrap();
+        let mut entry = AuditEntry {
+            seq: chain.seq + 1,
+            time: Local::now(),
+            tasklet: tasklet.to_string(),
+            operator: operator(),
+            step,
+            namespace,
+            action: invocation.action.to_string(),
+            // never store the registered secrets
+            payload: invocation.payload.as_deref().map(secrets::redact),
+            attributes: invocation.attributes.as_ref().map(|attrs| {
+                attrs
+                    .iter()
+                    .map(|(k, v)| (k.to_string(), secrets::redact(v)))
+                    .collect()
+            }),
+            approval,
+            outcome: outcome.to_string(),
+            error,
+            result_sha256,
+            elapsed_ms: elapsed.as_millis(),
+            prev_hash: chain.last_hash.to_string(),
+            hash: String::new(),
+        };
+        entry.hash = entry_hash(&serde_json::to_value(&entry)?)?;
+
+        writeln!(chain.file, "{}", serde_json::to_string(&entry)?)?;
+        chain.file.sync_data()?;
+
+        chain.seq = entry.seq;
+        chain.last_hash = entry.hash;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chain() {
+        let path =
+            std::env::temp_dir().join(format!("nerve-test-audit-{}.jsonl", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let inv = Invocation::new("shell".to_string(), None, Some("id".to_string()));
+        let log = AuditLog::open(&path).unwrap();
+        log.record(
+            "test",
+            1,
+            Some("shell".to_string()),
+            &inv,
+            Approval::Approved,
+            Outcome::Executed(&Ok(Some("uid=0(root)".to_string()))),
+            Duration::from_millis(12),
+        )
+        .unwrap();
+        log.record(
+            "test",
+            2,
+            Some("shell".to_string()),
+            &inv,
+            Approval::Rejected,
+            Outcome::NotExecuted,
+            Duration::ZERO,
+        )
+        .unwrap();
+        drop(log);
+
+        // the chain continues across runs
+        let log = AuditLog::open(&path).unwrap();
+        log.record(
+            "test",
+            3,
+            None,
+            &inv,
+            Approval::NotRequired,
+            Outcome::TimedOut,
+            Duration::from_secs(5),
+        )
+        .unwrap();
+
+        let entries = AuditLog::verify(&path).unwrap();
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[0].prev_hash, GENESIS);
+        assert_eq!(entries[1].prev_hash, entries[0].hash);
+        assert_eq!(entries[2].seq, 3);
+        assert_eq!(
+            entries[0].result_sha256.as_deref(),
+            Some(sha256::digest("uid=0(root)").as_str())
+        );
+
+        let original = std::fs::read_to_string(&path).unwrap();
+        let lines: Vec<&str> = original.lines().collect();
+
+        // changing an entry
+        std::fs::write(&path, original.replace("\"rejected\"", "\"approved\"")).unwrap();
+        assert!(AuditLog::verify(&path)
+            .unwrap_err()
+            .to_string()
+            .contains("line 2: the entry was modified"));
+
+        // removing one
+        std::fs::write(&path, format!("{}\n{}\n", lines[0], lines[2])).unwrap();
+        assert!(AuditLog::verify(&path)
+            .unwrap_err()
+            .to_string()
+            .contains("line 2: the chain is broken"));
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_injected() {
+        let path = std::env::temp_dir().join(format!(
+            "nerve-test-audit-injected-{}.jsonl",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+
+        let inv = Invocation::new("shell".to_string(), None, Some("id".to_string()));
+        let log = AuditLog::open(&path).unwrap();
+        log.record(
+            "test",
+            1,
+            Some("shell".to_string()),
+            &inv,
+            Approval::Injected,
+            Outcome::Injected(&Ok(Some("uid=1000(user)".to_string()))),
+            Duration::ZERO,
+        )
+        .unwrap();
+
+        // not recorded as a result of the action
+        let entries = AuditLog::verify(&path).unwrap();
+        assert_eq!(entries[0].outcome, "injected");
+        assert_eq!(
+            entries[0].result_sha256.as_deref(),
+            Some(sha256::digest("uid=1000(user)").as_str())
+        );
+
+        let _ = std::fs::remove_file(&path);
+    }
+}
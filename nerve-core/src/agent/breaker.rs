@@ -0,0 +1,85 @@
+// what to do when too many consecutive errors happen
+#[derive(Debug, Clone, Copy, Default, PartialEq, clap::ValueEnum)]
+pub enum BreakerAction {
+    // stop the run
+    #[default]
+    Abort,
+    // ask the model to reflect on its mistakes before continuing
+    Reflect,
+    // switch to the fallback generator
+    Fallback,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Decision {
+    Continue,
+    Abort,
+    Reflect,
+    Fallback,
+}
+
+// stops the agent from grinding through the step budget producing garbage
+#[derive(Debug, Clone)]
+pub struct CircuitBreaker {
+    threshold: usize,
+    action: BreakerAction,
+    // number of successful actions when the breaker was last triggered
+    tripped_at: Option<usize>,
+}
+
+impl CircuitBreaker {
+    pub fn new(threshold: usize, action: BreakerAction) -> Self {
+        Self {
+            threshold,
+            action,
+            tripped_at: None,
+        }
+    }
+
+    // if the action didn't help and errors keep coming without any success in between, abort
+    pub fn check(&mut self, consecutive_errors: usize, successes: usize) -> Decision {
+        if consecutive_errors < self.threshold {
+            return Decision::Continue;
+        }
+
+        let tripped = self.tripped_at.is_some_and(|at| at == successes);
+        if tripped || self.action == BreakerAction::Abort {
+            return Decision::Abort;
+        }
+
+        self.tripped_at = Some(successes);
+        match self.action {
+            BreakerAction::Reflect => Decision::Reflect,
+            BreakerAction::Fallback => Decision::Fallback,
+            BreakerAction::Abort => Decision::Abort,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_abort() {
+        let mut breaker = CircuitBreaker::new(3, BreakerAction::Abort);
+        assert_eq!(breaker.check(2, 0), Decision::Continue);
+        assert_eq!(breaker.check(3, 0), Decision::Abort);
+    }
+
+    #[test]
+    fn test_reflect_then_abort() {
+        let mut breaker = CircuitBreaker::new(2, BreakerAction::Reflect);
+        assert_eq!(breaker.check(2, 1), Decision::Reflect);
+        // counter is reset by the agent after triggering
+        assert_eq!(breaker.check(1, 1), Decision::Continue);
+        assert_eq!(breaker.check(2, 1), Decision::Abort);
+    }
+
+    #[test]
+    fn test_success_rearms() {
+        let mut breaker = CircuitBreaker::new(2, BreakerAction::Fallback);
+        assert_eq!(breaker.check(2, 0), Decision::Fallback);
+        assert_eq!(breaker.check(2, 1), Decision::Fallback);
+    }
+}
@@ -0,0 +1,291 @@
+use anyhow::Result;
+use mini_rag::Embedder;
+
+use super::{
+    cassette,
+    confirm::ConfirmPolicy,
+    events::{self, create_channel},
+    generator::{
+        self,
+        cache::{self, CachedEmbedder},
+        cassette::CassetteClient,
+        history::ConversationWindow,
+        Client,
+    },
+    namespaces::Namespace,
+    serialization,
+    task::Task,
+    Agent,
+};
+
+/// Default generator, a local llama3 served by ollama.
+pub const DEFAULT_GENERATOR: &str = "ollama://llama3@localhost:11434";
+/// Default embedder, a local all-minilm served by ollama.
+pub const DEFAULT_EMBEDDER: &str = "ollama://all-minilm@localhost:11434";
+/// Default context window size of the generators.
+pub const DEFAULT_CONTEXT_WINDOW: u32 = 8000;
+
+// either a <type>://<model name>@<host>:<port> string or an existing client
+enum Source<T> {
+    Url(String),
+    Client(T),
+}
+
+/// Configures and creates an [`Agent`].
+///
+/// A task is the only required setting, every other option has the same default as the
+/// command line. Further settings (budgets, guardrails, policies, ...) can be changed on the
+/// built agent with its setters.
+///
+/// ```no_run
+/// # use nerve_core::{agent::task::Task, AgentBuilder};
+/// # async fn example(task: Box<dyn Task>) -> anyhow::Result<()> {
+/// let (mut agent, events) = AgentBuilder::new()
+///     .generator("anthropic://claude-3-5-sonnet-20241022")
+///     .conversation_window("full")?
+///     .max_iterations(50)
+///     .boxed_task(task)
+///     .build()
+///     .await?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct AgentBuilder {
+    generator: Source<Box<dyn Client>>,
+    embedder: Source<Box<dyn Embedder>>,
+    context_window: u32,
+    task: Option<Box<dyn Task>>,
+    serializer: serialization::Strategy,
+    conversation_window: ConversationWindow,
+    force_strategy: bool,
+    user_only: bool,
+    max_iterations: usize,
+    confirm_policy: ConfirmPolicy,
+    step_mode: bool,
+    namespaces: Vec<Namespace>,
+    embeddings_cache: bool,
+}
+
+impl Default for AgentBuilder {
+    fn default() -> Self {
+        Self {
+            generator: Source::Url(DEFAULT_GENERATOR.to_string()),
+            embedder: Source::Url(DEFAULT_EMBEDDER.to_string()),
+            context_window: DEFAULT_CONTEXT_WINDOW,
+            task: None,
+            serializer: serialization::Strategy::default(),
+            conversation_window: ConversationWindow::LastN(15),
+            force_strategy: false,
+            user_only: false,
+            max_iterations: 0,
+            confirm_policy: ConfirmPolicy::default(),
+            step_mode: false,
+            namespaces: vec![],
+            embeddings_cache: true,
+        }
+    }
+}
+
+impl AgentBuilder {
+    /// Create a builder with the default settings.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Generator string as `<type>://<model name>@<host>:<port>`.
+    pub fn generator(mut self, generator: &str) -> Self {
+        self.generator = Source::Url(generator.to_string());
+        self
+    }
+
+    /// Use an existing generator client, for instance a custom implementation of [`Client`].
+    pub fn generator_client(mut self, client: Box<dyn Client>) -> Self {
+        self.generator = Source::Client(client);
+        self
+    }
+
+    /// Embedder string as `<type>://<model name>@<host>:<port>`, used by the RAG namespace.
+    pub fn embedder(mut self, embedder: &str) -> Self {
+        self.embedder = Source::Url(embedder.to_string());
+        self
+    }
+
+    /// Use an existing embedder.
+    pub fn embedder_client(mut self, embedder: Box<dyn Embedder>) -> Self {
+        self.embedder = Source::Client(embedder);
+        self
+    }
+
+    /// Persist the embeddings of the embedder created from a string in `~/.nerve/embeddings`, so
+    /// that the same documents and queries are not embedded again. Enabled by default.
+    pub fn embeddings_cache(mut self, enabled: bool) -> Self {
+        self.embeddings_cache = enabled;
+        self
+    }
+
+    /// Context window size of the generator and embedder created from strings.
+    pub fn context_window(mut self, size: u32) -> Self {
+        self.context_window = size;
+        self
+    }
+
+    /// The task to execute, the prompts and actions of the agent come from here.
+    pub fn task<T: Task + 'static>(self, task: T) -> Self {
+        self.boxed_task(Box::new(task))
+    }
+
+    /// Same as [`AgentBuilder::task`] for an already boxed task.
+    pub fn boxed_task(mut self, task: Box<dyn Task>) -> Self {
+        self.task = Some(task);
+        self
+    }
+
+    /// Serialization strategy used when the model doesn't support tools natively.
+    pub fn serializer(mut self, serializer: serialization::Strategy) -> Self {
+        self.serializer = serializer;
+        self
+    }
+
+    /// Always use the serialization strategy, even if the model supports tools natively.
+    pub fn force_strategy(mut self, force: bool) -> Self {
+        self.force_strategy = force;
+        self
+    }
+
+    /// Conversation window as `full`, `summary` or the number of messages to keep.
+    pub fn conversation_window(mut self, window: &str) -> Result<Self> {
+        self.conversation_window = ConversationWindow::parse(window)?;
+        Ok(self)
+    }
+
+    /// Only use the user prompt, for models that do not accept a system prompt.
+    pub fn user_only(mut self, user_only: bool) -> Self {
+        self.user_only = user_only;
+        self
+    }
+
+    /// Maximum number of steps, 0 for no limit.
+    pub fn max_iterations(mut self, max: usize) -> Self {
+        self.max_iterations = max;
+        self
+    }
+
+    /// Which actions need the user approval before being executed.
+    pub fn confirm_policy(mut self, policy: ConfirmPolicy) -> Self {
+        self.confirm_policy = policy;
+        self
+    }
+
+    /// Ask the user what to do with every invocation before executing it.
+    pub fn step_mode(mut self, enabled: bool) -> Self {
+        self.step_mode = enabled;
+        self
+    }
+
+    /// Register a custom namespace of actions on top of the ones used by the task.
+    pub fn namespace(mut self, namespace: Namespace) -> Self {
+        self.namespaces.push(namespace);
+        self
+    }
+
+    /// Create the agent and the receiving end of its events channel, the receiver must be
+    /// consumed for the agent to make progress.
+    pub async fn build(self) -> Result<(Agent, events::Receiver)> {
+        let task = self
+            .task
+            .ok_or_else(|| anyhow!("no task specified for the agent"))?;
+
+        let (generator, is_local) = match self.generator {
+            // the recorded responses are replayed without a generator
+            Source::Url(url) if cassette::replays_chat() => (
+                Box::new(CassetteClient::replay()) as Box<dyn Client>,
+                generator::Options::parse(&url, self.context_window)?.is_local(),
+            ),
+            Source::Url(url) => {
+                let options = generator::Options::parse(&url, self.context_window)?;
+                (
+                    generator::factory(
+                        &options.type_name,
+                        &options.host,
+                        options.port,
+                        &options.model_name,
+                        options.context_window,
+                    )?,
+                    options.is_local(),
+                )
+            }
+            Source::Client(client) => (client, false),
+        };
+        let generator: Box<dyn Client> = if cassette::is_recording() {
+            Box::new(CassetteClient::record(generator))
+        } else {
+            generator
+        };
+
+        let embedder = match self.embedder {
+            Source::Url(url) => {
+                let options = generator::Options::parse(&url, self.context_window)?;
+                let embedder = generator::factory_embedder(
+                    &options.type_name,
+                    &options.host,
+                    options.port,
+                    &options.model_name,
+                    options.context_window,
+                )?;
+                if self.embeddings_cache {
+                    let path = cache::cache_path(&options.type_name, &options.model_name)?;
+                    Box::new(CachedEmbedder::new(embedder, &path)?)
+                } else {
+                    embedder
+                }
+            }
+            Source::Client(embedder) => embedder,
+        };
+
+        let (tx, rx) = create_channel();
+        let mut agent = Agent::new(
+            tx,
+            generator,
+            embedder,
+            task,
+            self.serializer,
+            self.conversation_window,
+            self.force_strategy,
+            self.user_only,
+            self.max_iterations,
+        )
+        .await?;
+
+        agent.set_confirm_policy(self.confirm_policy);
+        agent.set_step_mode(self.step_mode);
+        // prompts never leave the machine for local models
+        agent.set_prompt_redaction(!is_local);
+
+        if !self.namespaces.is_empty() {
+            let state = agent.get_state();
+            let mut state = state.lock().await;
+            for namespace in self.namespaces {
+                state.register_namespace(namespace)?;
+            }
+        }
+
+        Ok((agent, rx))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_build_requires_task() {
+        let err = AgentBuilder::new().build().await.err().unwrap();
+        assert_eq!(err.to_string(), "no task specified for the agent");
+    }
+
+    #[test]
+    fn test_invalid_window() {
+        assert!(AgentBuilder::new().conversation_window("1").is_err());
+        assert!(AgentBuilder::new().conversation_window("full").is_ok());
+    }
+}
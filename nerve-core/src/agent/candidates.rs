@@ -0,0 +1,70 @@
+// last executions a candidate is compared against to tell if it's repeating them
+pub const RECENT: usize = 5;
+
+// what one of the responses sampled for a step would do
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Candidate {
+    // invocations parsed from the response
+    pub parsed: usize,
+    // of which to existing actions and with the expected payload and attributes
+    pub valid: usize,
+    // of which identical to one of the recent executions
+    pub repeated: usize,
+}
+
+impl Candidate {
+    // unparsable responses score zero, every invalid or repeated invocation costs a point
+    pub fn score(&self) -> i64 {
+        if self.parsed == 0 {
+            return 0;
+        }
+        let invalid = self.parsed - self.valid;
+        1 + 2 * self.valid as i64 - invalid as i64 - self.repeated as i64
+    }
+}
+
+// index of the best candidate, the first one if they score the same
+pub fn best(candidates: &[Candidate]) -> usize {
+    let mut best = 0;
+    for (idx, candidate) in candidates.iter().enumerate() {
+        if candidate.score() > candidates[best].score() {
+            best = idx;
+        }
+    }
+    best
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn candidate(parsed: usize, valid: usize, repeated: usize) -> Candidate {
+        Candidate {
+            parsed,
+            valid,
+            repeated,
+        }
+    }
+
+    #[test]
+    fn test_score() {
+        assert_eq!(candidate(0, 0, 0).score(), 0);
+        assert_eq!(candidate(1, 1, 0).score(), 3);
+        assert_eq!(candidate(1, 1, 1).score(), 2);
+        // a broken invocation is as bad as nothing parsed
+        assert_eq!(candidate(1, 0, 0).score(), 0);
+        assert_eq!(candidate(3, 1, 0).score(), 1);
+    }
+
+    #[test]
+    fn test_best() {
+        assert_eq!(best(&[candidate(0, 0, 0)]), 0);
+        assert_eq!(
+            best(&[candidate(0, 0, 0), candidate(1, 1, 1), candidate(1, 1, 0)]),
+            2
+        );
+        // ties go to the first one
+        assert_eq!(best(&[candidate(1, 1, 0), candidate(1, 1, 0)]), 0);
+        assert_eq!(best(&[]), 0);
+    }
+}
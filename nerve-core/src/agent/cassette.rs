@@ -0,0 +1,398 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use anyhow::Result;
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+
+use crate::agent::{
+    generator::{ChatResponse, SupportedFeatures, Usage},
+    secrets, Invocation,
+};
+
+// what to do with the http interactions and the model responses of the run
+#[derive(Debug, Clone, Copy, Default, PartialEq, clap::ValueEnum)]
+pub enum Mode {
+    // replay the cassette if it exists, record it otherwise
+    #[default]
+    Auto,
+    // record a new cassette, replacing the existing one
+    Record,
+    // only replay the cassette, requests that weren't recorded fail
+    Replay,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HttpRequest {
+    pub method: String,
+    pub url: String,
+    #[serde(default)]
+    pub body: Option<String>,
+}
+
+impl HttpRequest {
+    pub fn new(method: &str, url: &str, body: Option<&[u8]>) -> Self {
+        Self {
+            method: method.to_uppercase(),
+            url: url.to_string(),
+            body: body.map(|body| String::from_utf8_lossy(body).to_string()),
+        }
+    }
+
+    // recorded requests are redacted, so are the ones they are compared to
+    fn redacted(&self) -> Self {
+        Self {
+            method: self.method.to_string(),
+            url: secrets::redact(&self.url),
+            body: self.body.as_deref().map(secrets::redact),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct HttpResponse {
+    pub status: u16,
+    #[serde(default)]
+    pub headers: Vec<(String, String)>,
+    #[serde(default)]
+    pub body: Option<String>,
+}
+
+impl HttpResponse {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HttpInteraction {
+    pub request: HttpRequest,
+    pub response: HttpResponse,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChatInteraction {
+    pub content: String,
+    #[serde(default)]
+    pub invocations: Vec<Invocation>,
+    #[serde(default)]
+    pub usage: Option<Usage>,
+}
+
+impl From<&ChatResponse> for ChatInteraction {
+    fn from(response: &ChatResponse) -> Self {
+        Self {
+            content: response.content.to_string(),
+            invocations: response.invocations.clone(),
+            usage: response.usage.clone(),
+        }
+    }
+}
+
+impl From<ChatInteraction> for ChatResponse {
+    fn from(interaction: ChatInteraction) -> Self {
+        Self {
+            content: interaction.content,
+            invocations: interaction.invocations,
+            usage: interaction.usage,
+        }
+    }
+}
+
+// the recorded interactions of a run, the http ones are matched by method, url and body while
+// the model responses are replayed in order
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Cassette {
+    #[serde(default)]
+    pub http: Vec<HttpInteraction>,
+    #[serde(default)]
+    pub features: Option<SupportedFeatures>,
+    #[serde(default)]
+    pub chat: Vec<ChatInteraction>,
+}
+
+impl Cassette {
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let yaml = std::fs::read_to_string(path)
+            .map_err(|e| anyhow!("can't read cassette {}: {}", path.display(), e))?;
+        serde_yaml::from_str(&yaml)
+            .map_err(|e| anyhow!("can't parse cassette {}: {}", path.display(), e))
+    }
+
+    // the registered secrets are never written to the cassette
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        std::fs::write(path, secrets::redact(&serde_yaml::to_string(self)?))
+            .map_err(|e| anyhow!("can't write cassette {}: {}", path.display(), e))
+    }
+}
+
+// a cassette being recorded or replayed
+#[derive(Debug)]
+pub struct Tape {
+    path: PathBuf,
+    recording: bool,
+    cassette: Cassette,
+    // http interactions already replayed, each one is only replayed once
+    replayed: Vec<bool>,
+    next_chat: usize,
+}
+
+impl Tape {
+    pub fn new<P: AsRef<Path>>(path: P, mode: Mode) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let recording = match mode {
+            Mode::Auto => !path.exists(),
+            Mode::Record => true,
+            Mode::Replay => false,
+        };
+        let cassette = if recording {
+            Cassette::default()
+        } else {
+            Cassette::from_path(&path)?
+        };
+
+        Ok(Self {
+            replayed: vec![false; cassette.http.len()],
+            path,
+            recording,
+            cassette,
+            next_chat: 0,
+        })
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.recording
+    }
+
+    // the recorded response to the request, the first one with the same request not replayed yet
+    pub fn replay_http(&mut self, request: &HttpRequest) -> Result<HttpResponse> {
+        let request = request.redacted();
+        let found = self
+            .cassette
+            .http
+            .iter()
+            .zip(&self.replayed)
+            .position(|(recorded, replayed)| !replayed && recorded.request == request);
+        match found {
+            Some(idx) => {
+                self.replayed[idx] = true;
+                Ok(self.cassette.http[idx].response.clone())
+            }
+            None => Err(anyhow!(
+                "no interaction recorded in {} for {} {}",
+                self.path.display(),
+                request.method,
+                request.url
+            )),
+        }
+    }
+
+    pub fn record_http(&mut self, request: &HttpRequest, response: &HttpResponse) -> Result<()> {
+        self.cassette.http.push(HttpInteraction {
+            request: request.clone(),
+            response: response.clone(),
+        });
+        self.replayed.push(true);
+        self.cassette.save(&self.path)
+    }
+
+    // model responses are only replayed if the cassette has them
+    pub fn replays_chat(&self) -> bool {
+        !self.recording && !self.cassette.chat.is_empty()
+    }
+
+    pub fn replay_chat(&mut self) -> Result<ChatResponse> {
+        match self.cassette.chat.get(self.next_chat) {
+            Some(interaction) => {
+                self.next_chat += 1;
+                Ok(interaction.clone().into())
+            }
+            None => Err(anyhow!(
+                "cassette {} exhausted after {} model responses",
+                self.path.display(),
+                self.cassette.chat.len()
+            )),
+        }
+    }
+
+    pub fn record_chat(&mut self, response: &ChatResponse) -> Result<()> {
+        self.cassette.chat.push(response.into());
+        self.cassette.save(&self.path)
+    }
+
+    pub fn features(&self) -> Option<SupportedFeatures> {
+        self.cassette.features.clone()
+    }
+
+    pub fn record_features(&mut self, features: &SupportedFeatures) -> Result<()> {
+        self.cassette.features = Some(features.clone());
+        self.cassette.save(&self.path)
+    }
+}
+
+lazy_static! {
+    // the cassette of the process, used by the http namespace and the generator of the agent
+    static ref TAPE: Mutex<Option<Tape>> = Mutex::new(None);
+}
+
+pub fn load<P: AsRef<Path>>(path: P, mode: Mode) -> Result<()> {
+    let tape = Tape::new(path, mode)?;
+    log::info!(
+        "{} http interactions and model responses {} {}",
+        if tape.recording {
+            "recording"
+        } else {
+            "replaying"
+        },
+        if tape.recording { "to" } else { "from" },
+        tape.path.display()
+    );
+    *TAPE.lock().unwrap() = Some(tape);
+    Ok(())
+}
+
+pub fn unload() {
+    TAPE.lock().unwrap().take();
+}
+
+pub fn is_active() -> bool {
+    TAPE.lock().unwrap().is_some()
+}
+
+pub fn is_recording() -> bool {
+    TAPE.lock()
+        .unwrap()
+        .as_ref()
+        .is_some_and(|tape| tape.is_recording())
+}
+
+pub fn replays_chat() -> bool {
+    TAPE.lock()
+        .unwrap()
+        .as_ref()
+        .is_some_and(|tape| tape.replays_chat())
+}
+
+// run f on the loaded cassette, if any
+pub fn with_tape<T>(f: impl FnOnce(&mut Tape) -> T) -> Option<T> {
+    TAPE.lock().unwrap().as_mut().map(f)
+}
+
+// None if the request must be performed, the recorded response or an error while replaying
+pub fn replay_http(request: &HttpRequest) -> Option<Result<HttpResponse>> {
+    with_tape(|tape| {
+        if tape.is_recording() {
+            None
+        } else {
+            Some(tape.replay_http(request))
+        }
+    })
+    .flatten()
+}
+
+pub fn record_http(request: &HttpRequest, response: &HttpResponse) -> Result<()> {
+    with_tape(|tape| {
+        if tape.is_recording() {
+            tape.record_http(request, response)
+        } else {
+            Ok(())
+        }
+    })
+    .unwrap_or(Ok(()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("nerve-test-{}-{}.yml", name, std::process::id()))
+    }
+
+    fn response(body: &str) -> HttpResponse {
+        HttpResponse {
+            status: 200,
+            headers: vec![("Content-Type".to_string(), "text/html".to_string())],
+            body: Some(body.to_string()),
+        }
+    }
+
+    #[test]
+    fn test_record_and_replay_http() {
+        let path = temp_path("cassette-http");
+        let index = HttpRequest::new("get", "https://example.com/", None);
+        let login = HttpRequest::new("POST", "https://example.com/login", Some(b"user=admin"));
+
+        let mut tape = Tape::new(&path, Mode::Record).unwrap();
+        assert!(tape.is_recording());
+        tape.record_http(&index, &response("first")).unwrap();
+        tape.record_http(&login, &response("welcome")).unwrap();
+        tape.record_http(&index, &response("second")).unwrap();
+
+        // auto replays an existing cassette
+        let mut tape = Tape::new(&path, Mode::Auto).unwrap();
+        assert!(!tape.is_recording());
+        assert_eq!(
+            tape.replay_http(&login).unwrap().body.as_deref(),
+            Some("welcome")
+        );
+        // same requests are replayed in the order they were recorded
+        assert_eq!(
+            tape.replay_http(&index).unwrap().body.as_deref(),
+            Some("first")
+        );
+        let second = tape.replay_http(&index).unwrap();
+        assert_eq!(second.body.as_deref(), Some("second"));
+        assert_eq!(second.header("content-type"), Some("text/html"));
+        assert!(tape.replay_http(&index).is_err());
+
+        let other = HttpRequest::new("POST", "https://example.com/login", Some(b"user=root"));
+        assert!(tape.replay_http(&other).is_err());
+
+        std::fs::remove_file(&path).unwrap();
+        assert!(Tape::new(&path, Mode::Replay).is_err());
+    }
+
+    #[test]
+    fn test_record_and_replay_chat() {
+        let path = temp_path("cassette-chat");
+
+        let mut tape = Tape::new(&path, Mode::Record).unwrap();
+        assert!(!tape.replays_chat());
+        tape.record_features(&SupportedFeatures {
+            system_prompt: true,
+            tools: true,
+        })
+        .unwrap();
+        tape.record_chat(&ChatResponse {
+            content: "".to_string(),
+            invocations: vec![Invocation::new(
+                "shell".to_string(),
+                None,
+                Some("id".to_string()),
+            )],
+            usage: Some(Usage {
+                input_tokens: 10,
+                output_tokens: 2,
+            }),
+        })
+        .unwrap();
+
+        let mut tape = Tape::new(&path, Mode::Replay).unwrap();
+        assert!(tape.replays_chat());
+        assert!(tape.features().unwrap().tools);
+        let response = tape.replay_chat().unwrap();
+        assert_eq!(response.invocations[0].action, "shell");
+        assert_eq!(response.usage.unwrap().input_tokens, 10);
+        assert!(tape.replay_chat().is_err());
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,245 @@
+use std::collections::HashMap;
+
+use anyhow::Result;
+
+use super::{get_user_input, namespaces::Action, Invocation};
+
+// which actions need the operator approval before being executed
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum ConfirmPolicy {
+    // only actions that always require confirmation (like shell)
+    #[default]
+    Default,
+    // every action
+    All,
+    // actions with side effects on the system
+    Destructive,
+    // every action of these namespaces
+    Namespaces(Vec<String>),
+}
+
+// what the operator decided to do with an invocation
+#[derive(Debug, Clone, PartialEq)]
+pub enum Decision {
+    Approve(Invocation),
+    Reject,
+}
+
+// what the operator decided to do with an invocation in step mode
+#[derive(Debug, Clone, PartialEq)]
+pub enum StepDecision {
+    // execute the (possibly edited) invocation
+    Execute(Invocation),
+    // drop the invocation, the model won't see it in its history
+    Skip,
+    // don't execute the action and record this result or error instead
+    Inject(Result<Option<String>, String>),
+}
+
+impl ConfirmPolicy {
+    pub fn parse(raw: &str) -> Result<Self> {
+        let raw = raw.trim();
+        match raw {
+            "" | "default" => Ok(Self::Default),
+            "all" => Ok(Self::All),
+            "destructive" => Ok(Self::Destructive),
+            _ => {
+                let mut namespaces = vec![];
+                for name in raw.split(',').map(|s| s.trim()).filter(|s| !s.is_empty()) {
+                    let name = name.to_lowercase();
+                    if !super::namespaces::NAMESPACES.contains_key(&name) {
+                        return Err(anyhow!(
+                            "invalid confirmation policy '{}': namespace '{}' does not exist",
+                            raw,
+                            name
+                        ));
+                    }
+                    namespaces.push(name);
+                }
+                Ok(Self::Namespaces(namespaces))
+            }
+        }
+    }
+
+    #[allow(clippy::borrowed_box)]
+    pub fn requires_confirmation(&self, namespace: Option<&str>, action: &Box<dyn Action>) -> bool {
+        // these are never skipped regardless of the policy
+        if action.requires_user_confirmation() {
+            return true;
+        }
+
+        match self {
+            Self::Default => false,
+            Self::All => true,
+            Self::Destructive => action.is_destructive(),
+            Self::Namespaces(names) => namespace
+                .map(|ns| names.iter().any(|n| n.eq_ignore_ascii_case(ns)))
+                .unwrap_or(false),
+        }
+    }
+}
+
+// let the operator edit the payload and attributes of an invocation, empty input keeps the current value
+fn edit(inv: &Invocation) -> Invocation {
+    let mut edited = inv.clone();
+
+    if let Some(payload) = &inv.payload {
+        println!("\ncurrent payload: {}", payload);
+        let new_payload = get_user_input("new payload (empty to keep): ");
+        if !new_payload.is_empty() {
+            edited.payload = Some(new_payload);
+        }
+    }
+
+    if let Some(attributes) = &inv.attributes {
+        let mut new_attributes = HashMap::new();
+        for (name, value) in attributes {
+            let new_value = get_user_input(&format!("{} [{}]: ", name, value));
+            new_attributes.insert(
+                name.to_string(),
+                if new_value.is_empty() {
+                    value.to_string()
+                } else {
+                    new_value
+                },
+            );
+        }
+        edited.attributes = Some(new_attributes);
+    }
+
+    edited
+}
+
+// show the invocation to the operator and ask what to do with it
+pub fn ask(inv: &Invocation) -> Decision {
+    let mut inv = inv.clone();
+    loop {
+        let input = get_user_input(&format!(
+            "{} [Y]es / [n]o / [e]dit ",
+            inv.as_function_call_string()
+        ))
+        .to_ascii_lowercase();
+
+        match input.as_str() {
+            "" | "y" | "yes" => return Decision::Approve(inv),
+            "n" | "no" => return Decision::Reject,
+            "e" | "edit" => {
+                if inv.payload.is_none() && inv.attributes.is_none() {
+                    println!("\nnothing to edit");
+                } else {
+                    inv = edit(&inv);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+// parse a synthetic result, "error: ..." records an error and an empty string an empty output
+fn parse_injected(input: &str) -> Result<Option<String>, String> {
+    if let Some(error) = input.strip_prefix("error:") {
+        Err(error.trim().to_string())
+    } else if input.is_empty() {
+        Ok(None)
+    } else {
+        Ok(Some(input.to_string()))
+    }
+}
+
+// show the parsed invocation to the operator in step mode and ask how to continue
+pub fn step(inv: &Invocation) -> StepDecision {
+    let mut inv = inv.clone();
+    loop {
+        println!("\n{}", inv.as_function_call_string());
+        if let Some(attributes) = &inv.attributes {
+            let mut names: Vec<_> = attributes.keys().collect();
+            names.sort();
+            for name in names {
+                println!("  {} = {}", name, attributes[name]);
+            }
+        }
+        if let Some(payload) = &inv.payload {
+            println!("  payload = {}", payload);
+        }
+
+        let input =
+            get_user_input("[C]ontinue / [e]dit / [s]kip / [i]nject result ").to_ascii_lowercase();
+
+        match input.as_str() {
+            "" | "c" | "continue" => return StepDecision::Execute(inv),
+            "s" | "skip" => return StepDecision::Skip,
+            "i" | "inject" => {
+                let result = get_user_input("result (prefix with 'error:' to inject an error): ");
+                return StepDecision::Inject(parse_injected(&result));
+            }
+            "e" | "edit" => {
+                if inv.payload.is_none() && inv.attributes.is_none() {
+                    println!("\nnothing to edit");
+                } else {
+                    inv = edit(&inv);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent::namespaces::NAMESPACES;
+
+    fn action(namespace: &str, name: &str) -> Box<dyn Action> {
+        NAMESPACES.get(namespace).unwrap()()
+            .actions
+            .into_iter()
+            .find(|a| a.name() == name)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_parse_policy() {
+        assert_eq!(ConfirmPolicy::parse("all").unwrap(), ConfirmPolicy::All);
+        assert_eq!(
+            ConfirmPolicy::parse("destructive").unwrap(),
+            ConfirmPolicy::Destructive
+        );
+        assert_eq!(
+            ConfirmPolicy::parse("filesystem, http").unwrap(),
+            ConfirmPolicy::Namespaces(vec!["filesystem".to_string(), "http".to_string()])
+        );
+        assert!(ConfirmPolicy::parse("nope").is_err());
+        assert_eq!(ConfirmPolicy::parse("").unwrap(), ConfirmPolicy::Default);
+    }
+
+    #[test]
+    fn test_requires_confirmation() {
+        let shell = action("shell", "shell");
+        let read = action("filesystem", "read_file");
+        let append = action("filesystem", "append_to_file");
+        let memory = action("memory", "save_memory");
+
+        assert!(ConfirmPolicy::Default.requires_confirmation(Some("shell"), &shell));
+        assert!(!ConfirmPolicy::Default.requires_confirmation(Some("filesystem"), &append));
+
+        assert!(ConfirmPolicy::All.requires_confirmation(Some("memory"), &memory));
+
+        assert!(ConfirmPolicy::Destructive.requires_confirmation(Some("filesystem"), &append));
+        assert!(!ConfirmPolicy::Destructive.requires_confirmation(Some("filesystem"), &read));
+
+        let policy = ConfirmPolicy::parse("filesystem").unwrap();
+        assert!(policy.requires_confirmation(Some("filesystem"), &read));
+        assert!(!policy.requires_confirmation(Some("memory"), &memory));
+        assert!(policy.requires_confirmation(Some("shell"), &shell));
+    }
+
+    #[test]
+    fn test_parse_injected() {
+        assert_eq!(parse_injected(""), Ok(None));
+        assert_eq!(parse_injected("hello"), Ok(Some("hello".to_string())));
+        assert_eq!(
+            parse_injected("error: not found"),
+            Err("not found".to_string())
+        );
+    }
+}
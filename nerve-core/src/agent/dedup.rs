@@ -0,0 +1,86 @@
+use mini_rag::Embeddings;
+
+// shorter outputs cost less than the reference to the earlier one
+pub const MIN_SIZE: usize = 256;
+
+pub fn cosine_similarity(a: &[f64], b: &[f64]) -> f64 {
+    let dot: f64 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm = |v: &[f64]| v.iter().map(|x| x * x).sum::<f64>().sqrt();
+    let norms = norm(a) * norm(b);
+    if norms == 0.0 {
+        0.0
+    } else {
+        dot / norms
+    }
+}
+
+// spots the action outputs that are near duplicates of an earlier one, like the same page fetched twice
+#[derive(Debug, Clone)]
+pub struct Deduplicator {
+    // minimum cosine similarity of two outputs to be considered the same
+    threshold: f64,
+    // embeddings of the distinct outputs so far and the step they belong to
+    seen: Vec<(usize, Embeddings)>,
+    // executions up to this step have already been checked
+    checked_step: usize,
+}
+
+impl Deduplicator {
+    pub fn new(threshold: f64) -> Self {
+        Self {
+            threshold,
+            seen: vec![],
+            checked_step: 0,
+        }
+    }
+
+    pub fn checked_step(&self) -> usize {
+        self.checked_step
+    }
+
+    pub fn set_checked_step(&mut self, step: usize) {
+        self.checked_step = self.checked_step.max(step);
+    }
+
+    // step of the earliest output similar enough to this one, which is remembered otherwise
+    pub fn check(&mut self, step: usize, embeddings: Embeddings) -> Option<usize> {
+        let original = self
+            .seen
+            .iter()
+            .find(|(_, seen)| cosine_similarity(seen, &embeddings) >= self.threshold)
+            .map(|(step, _)| *step);
+        if original.is_none() {
+            self.seen.push((step, embeddings));
+        }
+        original
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_similarity() {
+        assert!((cosine_similarity(&[1.0, 0.0], &[1.0, 0.0]) - 1.0).abs() < 1e-9);
+        assert_eq!(cosine_similarity(&[1.0, 0.0], &[0.0, 1.0]), 0.0);
+        assert_eq!(cosine_similarity(&[1.0, 0.0], &[0.0, 0.0]), 0.0);
+    }
+
+    #[test]
+    fn test_check() {
+        let mut dedup = Deduplicator::new(0.95);
+        assert_eq!(dedup.check(1, vec![1.0, 0.0, 0.0]), None);
+        assert_eq!(dedup.check(2, vec![0.0, 1.0, 0.0]), None);
+        // the same page with a different timestamp
+        assert_eq!(dedup.check(3, vec![0.99, 0.05, 0.0]), Some(1));
+        assert_eq!(dedup.check(4, vec![0.0, 1.0, 0.0]), Some(2));
+        assert_eq!(dedup.check(5, vec![0.5, 0.5, 0.7]), None);
+        // duplicates are not remembered, the reference is always the first output
+        assert_eq!(dedup.seen.len(), 3);
+
+        dedup.set_checked_step(5);
+        dedup.set_checked_step(2);
+        assert_eq!(dedup.checked_step(), 5);
+    }
+}
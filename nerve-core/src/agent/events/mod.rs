@@ -0,0 +1,87 @@
+use serde::{Deserialize, Serialize};
+
+mod channel;
+
+pub use channel::*;
+
+use super::{
+    generator::ChatOptions,
+    state::{metrics::Metrics, storage::StorageType},
+    Invocation,
+};
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum Event {
+    MetricsUpdate(Metrics),
+    StorageUpdate {
+        storage_name: String,
+        storage_type: StorageType,
+        key: String,
+        prev: Option<String>,
+        new: Option<String>,
+    },
+    // an entry was removed to keep the storage within its quota
+    StorageEvicted {
+        storage_name: String,
+        storage_type: StorageType,
+        key: String,
+        data: String,
+    },
+    StateUpdate(ChatOptions),
+    EmptyResponse,
+    InvalidResponse(String),
+    // the response of the model in step mode, before its invocations are reviewed
+    ModelResponse(String),
+    InvalidAction {
+        invocation: Invocation,
+        error: Option<String>,
+    },
+    // an action is about to be executed
+    ActionStarted {
+        invocation: Invocation,
+    },
+    // incremental output of an action while it runs
+    ActionOutput {
+        action: String,
+        output: String,
+    },
+    // an action is still running after this long
+    ActionHeartbeat {
+        invocation: Invocation,
+        elapsed: std::time::Duration,
+    },
+    ActionTimeout {
+        invocation: Invocation,
+        elapsed: std::time::Duration,
+    },
+    ActionExecuted {
+        invocation: Invocation,
+        error: Option<String>,
+        result: Option<String>,
+        elapsed: std::time::Duration,
+        complete_task: bool,
+    },
+    TaskComplete {
+        impossible: bool,
+        reason: Option<String>,
+    },
+    // control of the task was transferred to another agent, continuing the same session
+    Handoff {
+        from: String,
+        to: String,
+        message: String,
+    },
+    // the run was stopped because of a step, time or cost limit
+    BudgetExhausted(String),
+    // the run was stopped by the circuit breaker after this many consecutive errors
+    TooManyErrors(usize),
+    // the run was interrupted by the user, the session was saved if possible
+    Interrupted {
+        checkpoint: Option<String>,
+    },
+    // a guardrail blocked, redacted or asked confirmation for a response or invocation
+    GuardrailTriggered {
+        invocation: Option<Invocation>,
+        outcome: String,
+    },
+}
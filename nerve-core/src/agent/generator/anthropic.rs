@@ -8,11 +8,12 @@ use crate::agent::{
 use anyhow::Result;
 use async_trait::async_trait;
 use clust::messages::{
-    ClaudeModel, MaxTokens, Message, MessagesRequestBody, Role, SystemPrompt, ToolDefinition,
+    ClaudeModel, MaxTokens, Message, MessagesRequestBody, Role, SystemPrompt, Temperature,
+    ToolDefinition,
 };
 use serde::{Deserialize, Serialize};
 
-use super::{ChatOptions, Client};
+use super::{credentials, ChatOptions, Client};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AnthropicToolFunctionParameterProperty {
@@ -66,6 +67,8 @@ impl AnthropicClient {
                         }
                     }
 
+                    required.sort();
+
                     let input_schema = serde_json::json!({
                         "properties": properties,
                         "required": required,
@@ -98,7 +101,8 @@ impl Client for AnthropicClient {
             ClaudeModel::Claude35Sonnet20240620
         };
 
-        let client = clust::Client::from_env()?;
+        let client =
+            clust::Client::from_api_key(clust::ApiKey::new(credentials::api_key("anthropic")?));
         Ok(Self { model, client })
     }
 
@@ -196,6 +200,13 @@ impl Client for AnthropicClient {
             messages,
             max_tokens,
             tools: if tools.is_empty() { None } else { Some(tools) },
+            // no seed support
+            temperature: options
+                .sampling
+                .temperature
+                .map(Temperature::new)
+                .transpose()
+                .map_err(|e| anyhow!("{:?}", e))?,
             ..Default::default()
         };
 
@@ -212,10 +223,7 @@ impl Client for AnthropicClient {
         log::debug!("response = {:?}", response);
 
         let content = response.content.flatten_into_text().unwrap_or_default();
-        let tool_use = match response.content.flatten_into_tool_use() {
-            Ok(m) => Some(m),
-            Err(_) => None,
-        };
+        let tool_use = response.content.flatten_into_tool_use().ok();
 
         let mut invocations = vec![];
 
@@ -0,0 +1,206 @@
+use std::{
+    collections::HashMap,
+    fs::{File, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use anyhow::Result;
+use async_trait::async_trait;
+use mini_rag::{Embedder, Embeddings};
+
+// hex sha256 of the text followed by the number of dimensions
+const HASH_SIZE: usize = 64;
+const HEADER_SIZE: usize = HASH_SIZE + 4;
+
+fn encode(hash: &str, embeddings: &[f64]) -> Vec<u8> {
+    let mut record = Vec::with_capacity(HEADER_SIZE + embeddings.len() * 8);
+    record.extend(hash.as_bytes());
+    record.extend((embeddings.len() as u32).to_le_bytes());
+    for value in embeddings {
+        record.extend(value.to_le_bytes());
+    }
+    record
+}
+
+// returns the records and the size of the valid part of the data, a record might have been
+// partially written if the process was interrupted
+fn decode(data: &[u8]) -> (HashMap<String, Embeddings>, usize) {
+    let mut records = HashMap::new();
+    let mut offset = 0;
+
+    while data.len() - offset >= HEADER_SIZE {
+        let hash = &data[offset..offset + HASH_SIZE];
+        let size = u32::from_le_bytes(
+            data[offset + HASH_SIZE..offset + HEADER_SIZE]
+                .try_into()
+                .unwrap(),
+        ) as usize;
+        let end = offset + HEADER_SIZE + size * 8;
+        if end > data.len() || !hash.iter().all(|c| c.is_ascii_hexdigit()) {
+            break;
+        }
+
+        let embeddings = data[offset + HEADER_SIZE..end]
+            .chunks_exact(8)
+            .map(|raw| f64::from_le_bytes(raw.try_into().unwrap()))
+            .collect();
+        records.insert(String::from_utf8_lossy(hash).to_string(), embeddings);
+        offset = end;
+    }
+
+    (records, offset)
+}
+
+// path of the cache of the given embedding model
+pub fn cache_path(type_name: &str, model_name: &str) -> Result<PathBuf> {
+    let key = sha256::digest(format!("{}://{}", type_name, model_name));
+    Ok(crate::agent::data_path("embeddings")?.join(format!("{}.cache", &key[..16])))
+}
+
+// embeddings persisted by hash of the text, so that documents and queries that have been embedded
+// before by the same model don't need to be embedded again
+pub struct CachedEmbedder {
+    inner: Box<dyn Embedder>,
+    path: PathBuf,
+    cache: Mutex<HashMap<String, Embeddings>>,
+    file: Mutex<Option<File>>,
+}
+
+impl CachedEmbedder {
+    pub fn new(inner: Box<dyn Embedder>, path: &Path) -> Result<Self> {
+        let cache = if path.exists() {
+            let data = std::fs::read(path)?;
+            let (cache, valid) = decode(&data);
+            if valid < data.len() {
+                log::warn!(
+                    "{}: ignoring {} bytes of incomplete records",
+                    path.display(),
+                    data.len() - valid
+                );
+                OpenOptions::new()
+                    .write(true)
+                    .open(path)?
+                    .set_len(valid as u64)?;
+            }
+            log::debug!(
+                "loaded {} cached embeddings from {}",
+                cache.len(),
+                path.display()
+            );
+            cache
+        } else {
+            HashMap::new()
+        };
+
+        Ok(Self {
+            inner,
+            path: path.to_path_buf(),
+            cache: Mutex::new(cache),
+            file: Mutex::new(None),
+        })
+    }
+
+    fn store(&self, hash: &str, embeddings: &[f64]) -> Result<()> {
+        let mut file = self.file.lock().unwrap();
+        if file.is_none() {
+            *file = Some(
+                OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(&self.path)?,
+            );
+        }
+        file.as_mut()
+            .unwrap()
+            .write_all(&encode(hash, embeddings))?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Embedder for CachedEmbedder {
+    async fn embed(&self, text: &str) -> Result<Embeddings> {
+        let hash = sha256::digest(text);
+        if let Some(embeddings) = self.cache.lock().unwrap().get(&hash) {
+            return Ok(embeddings.clone());
+        }
+
+        let embeddings = self.inner.embed(text).await?;
+        // the embeddings are still good if they can't be cached
+        if let Err(err) = self.store(&hash, &embeddings) {
+            log::warn!("can't cache embeddings in {}: {}", self.path.display(), err);
+        }
+        self.cache.lock().unwrap().insert(hash, embeddings.clone());
+
+        Ok(embeddings)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    use super::*;
+
+    struct CountingEmbedder {
+        calls: Arc<AtomicUsize>,
+    }
+
+    #[async_trait]
+    impl Embedder for CountingEmbedder {
+        async fn embed(&self, text: &str) -> Result<Embeddings> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            Ok(vec![text.len() as f64, 0.5])
+        }
+    }
+
+    #[test]
+    fn test_decode_ignores_incomplete_records() {
+        let hash = sha256::digest("foo");
+        let mut data = encode(&hash, &[1.0, -2.5]);
+        let valid = data.len();
+        data.extend(&encode(&sha256::digest("bar"), &[3.0])[..HEADER_SIZE + 3]);
+
+        let (records, size) = decode(&data);
+        assert_eq!(size, valid);
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[&hash], vec![1.0, -2.5]);
+    }
+
+    #[tokio::test]
+    async fn test_embeddings_are_cached() {
+        let path = std::env::temp_dir().join("nerve-embeddings-test.cache");
+        let _ = std::fs::remove_file(&path);
+        let calls = Arc::new(AtomicUsize::new(0));
+
+        let embedder = CachedEmbedder::new(
+            Box::new(CountingEmbedder {
+                calls: calls.clone(),
+            }),
+            &path,
+        )
+        .unwrap();
+        assert_eq!(embedder.embed("hello").await.unwrap(), vec![5.0, 0.5]);
+        assert_eq!(embedder.embed("hello").await.unwrap(), vec![5.0, 0.5]);
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        // persisted across runs
+        let embedder = CachedEmbedder::new(
+            Box::new(CountingEmbedder {
+                calls: calls.clone(),
+            }),
+            &path,
+        )
+        .unwrap();
+        assert_eq!(embedder.embed("hello").await.unwrap(), vec![5.0, 0.5]);
+        assert_eq!(embedder.embed("world!").await.unwrap(), vec![6.0, 0.5]);
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,172 @@
+use std::sync::Arc;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use candle_core::{DType, Device, Tensor};
+use candle_nn::VarBuilder;
+use candle_transformers::models::bert::{BertModel, Config};
+use tokenizers::Tokenizer;
+
+use super::local::{self, ModelFiles, Pooling};
+
+// a sentence-transformers model executed by nerve itself, so that the rag works offline:
+// candle://sentence-transformers/all-MiniLM-L6-v2 from the huggingface hub, or
+// candle:///path/to/model for a folder with its config.json, tokenizer.json and model.safetensors
+pub struct CandleEmbedder {
+    model: Arc<Model>,
+}
+
+struct Model {
+    bert: BertModel,
+    tokenizer: Tokenizer,
+    pooling: Pooling,
+    device: Device,
+}
+
+// the gpu if nerve was built with the cuda or metal feature and there's one
+fn device() -> Result<Device> {
+    if candle_core::utils::cuda_is_available() {
+        Ok(Device::new_cuda(0)?)
+    } else if candle_core::utils::metal_is_available() {
+        Ok(Device::new_metal(0)?)
+    } else {
+        Ok(Device::Cpu)
+    }
+}
+
+impl CandleEmbedder {
+    pub fn new(model_name: &str) -> Result<Self> {
+        let files = ModelFiles::open(model_name)?;
+        let config: Config =
+            serde_json::from_str(&std::fs::read_to_string(files.get("config.json")?)?)
+                .map_err(|e| anyhow!("can't parse the config of {}: {}", model_name, e))?;
+        let tokenizer = local::load_tokenizer(
+            &files.get("tokenizer.json")?,
+            config.max_position_embeddings,
+        )?;
+        let pooling = Pooling::from_model(&files)?;
+        let device = device()?;
+
+        let weights = files.get("model.safetensors")?;
+        // SAFETY: the weights are memory mapped, nerve never writes to the file
+        let vb = unsafe { VarBuilder::from_mmaped_safetensors(&[weights], DType::F32, &device)? };
+        let bert = BertModel::load(vb, &config)
+            .map_err(|e| anyhow!("can't load model {}: {}", model_name, e))?;
+
+        log::debug!(
+            "loaded embedding model {} on {:?} with {:?} pooling",
+            model_name,
+            &device,
+            pooling
+        );
+
+        Ok(Self {
+            model: Arc::new(Model {
+                bert,
+                tokenizer,
+                pooling,
+                device,
+            }),
+        })
+    }
+}
+
+impl Model {
+    fn embed(&self, text: &str) -> Result<Vec<f64>> {
+        let encoding = self
+            .tokenizer
+            .encode(text, true)
+            .map_err(|e| anyhow!("can't tokenize the text to embed: {}", e))?;
+        let input = |values: &[u32]| Tensor::new(values, &self.device)?.unsqueeze(0);
+        let tokens = self
+            .bert
+            .forward(
+                &input(encoding.get_ids())?,
+                &input(encoding.get_type_ids())?,
+                Some(&input(encoding.get_attention_mask())?),
+            )?
+            .squeeze(0)?
+            .to_dtype(DType::F32)?
+            .to_vec2::<f32>()?;
+
+        Ok(self.pooling.pool(&tokens, encoding.get_attention_mask()))
+    }
+}
+
+#[async_trait]
+impl mini_rag::Embedder for CandleEmbedder {
+    async fn embed(&self, text: &str) -> Result<mini_rag::Embeddings> {
+        let model = self.model.clone();
+        let text = text.to_string();
+        // cpu bound, kept off the threads of the runtime
+        tokio::task::spawn_blocking(move || model.embed(&text)).await?
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use candle_nn::VarMap;
+    use mini_rag::Embedder;
+
+    use super::*;
+
+    // a tiny model with random weights, saved like the ones of the hub
+    fn save_model(folder: &std::path::Path) {
+        let config = r#"{
+  "vocab_size": 8,
+  "hidden_size": 8,
+  "num_hidden_layers": 1,
+  "num_attention_heads": 2,
+  "intermediate_size": 16,
+  "hidden_act": "gelu",
+  "hidden_dropout_prob": 0.1,
+  "max_position_embeddings": 16,
+  "type_vocab_size": 2,
+  "initializer_range": 0.02,
+  "layer_norm_eps": 1e-12,
+  "pad_token_id": 0,
+  "classifier_dropout": null,
+  "model_type": "bert"
+}"#;
+        let tokenizer = r#"{
+  "version": "1.0",
+  "truncation": null,
+  "padding": null,
+  "added_tokens": [],
+  "normalizer": { "type": "Lowercase" },
+  "pre_tokenizer": { "type": "Whitespace" },
+  "post_processor": null,
+  "decoder": null,
+  "model": {
+    "type": "WordLevel",
+    "vocab": { "[UNK]": 0, "hello": 1, "world": 2, "nerve": 3 },
+    "unk_token": "[UNK]"
+  }
+}"#;
+        std::fs::create_dir_all(folder).unwrap();
+        std::fs::write(folder.join("config.json"), config).unwrap();
+        std::fs::write(folder.join("tokenizer.json"), tokenizer).unwrap();
+
+        let varmap = VarMap::new();
+        let vb = VarBuilder::from_varmap(&varmap, DType::F32, &Device::Cpu);
+        BertModel::load(vb, &serde_json::from_str(config).unwrap()).unwrap();
+        varmap.save(folder.join("model.safetensors")).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_embed() {
+        let folder = std::env::temp_dir().join("nerve-candle-test");
+        save_model(&folder);
+
+        let embedder = CandleEmbedder::new(folder.to_str().unwrap()).unwrap();
+        let hello = embedder.embed("hello world").await.unwrap();
+        assert_eq!(hello.len(), 8);
+        // normalized
+        assert!((hello.iter().map(|v| v * v).sum::<f64>() - 1.0).abs() < 1e-4);
+        // deterministic
+        assert_eq!(hello, embedder.embed("Hello World").await.unwrap());
+        assert_ne!(hello, embedder.embed("nerve").await.unwrap());
+
+        std::fs::remove_dir_all(&folder).unwrap();
+    }
+}
@@ -0,0 +1,74 @@
+use anyhow::Result;
+use async_trait::async_trait;
+
+use crate::agent::{cassette, state::SharedState};
+
+use super::{ChatOptions, ChatResponse, Client, SupportedFeatures};
+
+// records the responses of the generator to the loaded cassette, or replays them from it without
+// a generator at all
+pub struct CassetteClient {
+    inner: Option<Box<dyn Client>>,
+}
+
+impl CassetteClient {
+    pub fn record(inner: Box<dyn Client>) -> Self {
+        Self { inner: Some(inner) }
+    }
+
+    pub fn replay() -> Self {
+        Self { inner: None }
+    }
+}
+
+#[async_trait]
+impl Client for CassetteClient {
+    fn new(_: &str, _: u16, _: &str, _: u32) -> Result<Self>
+    where
+        Self: Sized,
+    {
+        Err(anyhow!("the cassette client wraps another generator"))
+    }
+
+    async fn chat(&self, state: SharedState, options: &ChatOptions) -> Result<ChatResponse> {
+        match &self.inner {
+            Some(inner) => {
+                let response = inner.chat(state, options).await?;
+                cassette::with_tape(|tape| tape.record_chat(&response)).unwrap_or(Ok(()))?;
+                Ok(response)
+            }
+            None => cassette::with_tape(|tape| tape.replay_chat())
+                .unwrap_or_else(|| Err(anyhow!("no cassette loaded"))),
+        }
+    }
+
+    async fn check_supported_features(&self) -> Result<SupportedFeatures> {
+        match &self.inner {
+            Some(inner) => {
+                let features = inner.check_supported_features().await?;
+                cassette::with_tape(|tape| tape.record_features(&features)).unwrap_or(Ok(()))?;
+                Ok(features)
+            }
+            None => Ok(cassette::with_tape(|tape| tape.features())
+                .flatten()
+                .unwrap_or_default()),
+        }
+    }
+
+    async fn check_rate_limit(&self, error: &str) -> bool {
+        match &self.inner {
+            Some(inner) => inner.check_rate_limit(error).await,
+            None => false,
+        }
+    }
+}
+
+#[async_trait]
+impl mini_rag::Embedder for CassetteClient {
+    async fn embed(&self, text: &str) -> Result<mini_rag::Embeddings> {
+        match &self.inner {
+            Some(inner) => inner.embed(text).await,
+            None => Err(anyhow!("embeddings are not recorded in cassettes")),
+        }
+    }
+}
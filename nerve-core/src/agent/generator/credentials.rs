@@ -0,0 +1,267 @@
+use std::{collections::HashMap, fmt::Display, process::Command, sync::Mutex};
+
+use anyhow::Result;
+use lazy_static::lazy_static;
+use serde::Deserialize;
+
+use crate::agent::secrets;
+
+// service name of the keychain entries, the account is the name of the environment variable
+const KEYCHAIN_SERVICE: &str = "nerve";
+
+// the generators requiring an API key and the environment variable it's read from by default
+const API_KEYS: &[(&str, &str)] = &[
+    ("openai", "OPENAI_API_KEY"),
+    ("anthropic", "ANTHROPIC_API_KEY"),
+    ("groq", "GROQ_API_KEY"),
+    ("fireworks", "LLM_FIREWORKS_KEY"),
+    ("hf", "HF_API_TOKEN"),
+    ("nim", "NIM_API_KEY"),
+    ("deepseek", "DEEPSEEK_API_KEY"),
+    ("xai", "XAI_API_KEY"),
+    ("mistral", "MISTRAL_API_KEY"),
+    ("novita", "NOVITA_API_KEY"),
+];
+
+// the environment variables the API keys of the generators are read from by default
+pub fn env_names() -> impl Iterator<Item = &'static str> {
+    API_KEYS.iter().map(|(_, name)| *name)
+}
+
+lazy_static! {
+    // sources set by the operator configuration, by generator
+    static ref CONFIGURED: Mutex<HashMap<String, Source>> = Mutex::new(HashMap::new());
+}
+
+// where the API key of a generator is read from, as written in the configuration:
+// env:NAME, keychain, keychain:ACCOUNT or the key itself
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(from = "String")]
+pub enum Source {
+    Value(String),
+    Env(String),
+    // the account defaults to the name of the environment variable of the generator
+    Keychain(Option<String>),
+}
+
+impl From<String> for Source {
+    fn from(value: String) -> Self {
+        let value = value.trim();
+        if let Some(name) = value.strip_prefix("env:") {
+            Self::Env(name.trim().to_string())
+        } else if value == "keychain" {
+            Self::Keychain(None)
+        } else if let Some(account) = value.strip_prefix("keychain:") {
+            Self::Keychain(Some(account.trim().to_string()))
+        } else {
+            Self::Value(value.to_string())
+        }
+    }
+}
+
+// where a key was found
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Origin {
+    Config,
+    Env,
+    Keychain,
+}
+
+impl Display for Origin {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                Self::Config => "config",
+                Self::Env => "environment",
+                Self::Keychain => "keychain",
+            }
+        )
+    }
+}
+
+// the same generator names accepted by the factory
+fn canonical(generator: &str) -> &str {
+    match generator {
+        "claude" => "anthropic",
+        "nvidia" => "nim",
+        _ => generator,
+    }
+}
+
+// the environment variable of the API key of the generator, if it requires one
+pub fn env_var(generator: &str) -> Option<&'static str> {
+    let generator = canonical(generator);
+    API_KEYS
+        .iter()
+        .find(|(name, _)| *name == generator)
+        .map(|(_, var)| *var)
+}
+
+// the names of the generators requiring an API key
+pub fn generators() -> Vec<&'static str> {
+    API_KEYS.iter().map(|(name, _)| *name).collect()
+}
+
+// set where the key of the generator is read from instead of its environment variable
+pub fn configure(generator: &str, source: Source) -> Result<()> {
+    if env_var(generator).is_none() {
+        return Err(anyhow!("generator '{}' does not use an API key", generator));
+    }
+    if let Source::Value(value) = &source {
+        secrets::register(value);
+    }
+    CONFIGURED
+        .lock()
+        .unwrap()
+        .insert(canonical(generator).to_string(), source);
+    Ok(())
+}
+
+fn from_keychain(account: &str) -> Option<String> {
+    let mut command = if cfg!(target_os = "macos") {
+        let mut command = Command::new("security");
+        command.args([
+            "find-generic-password",
+            "-s",
+            KEYCHAIN_SERVICE,
+            "-a",
+            account,
+            "-w",
+        ]);
+        command
+    } else if cfg!(unix) {
+        let mut command = Command::new("secret-tool");
+        command.args(["lookup", "service", KEYCHAIN_SERVICE, "account", account]);
+        command
+    } else {
+        return None;
+    };
+
+    // the tool might not be installed, or the entry not exist
+    let output = command.output().ok()?;
+    let key = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    if output.status.success() && !key.is_empty() {
+        Some(key)
+    } else {
+        None
+    }
+}
+
+fn from_env(name: &str) -> Option<String> {
+    std::env::var(name)
+        .ok()
+        .filter(|value| !value.trim().is_empty())
+}
+
+// the key of the generator and where it was found: the configured source, else the
+// environment variable, else the keychain
+pub fn resolve(generator: &str) -> Result<Option<(String, Origin)>> {
+    let var = match env_var(generator) {
+        Some(var) => var,
+        None => return Ok(None),
+    };
+
+    let configured = CONFIGURED
+        .lock()
+        .unwrap()
+        .get(canonical(generator))
+        .cloned();
+    let found = match configured {
+        Some(Source::Value(value)) => Some((value, Origin::Config)),
+        Some(Source::Env(name)) => Some((
+            from_env(&name)
+                .ok_or_else(|| anyhow!("{} for the {} API key is not set", name, generator))?,
+            Origin::Config,
+        )),
+        Some(Source::Keychain(account)) => {
+            let account = account.as_deref().unwrap_or(var);
+            Some((
+                from_keychain(account).ok_or_else(|| {
+                    anyhow!(
+                        "no keychain entry for service {} and account {}",
+                        KEYCHAIN_SERVICE,
+                        account
+                    )
+                })?,
+                Origin::Keychain,
+            ))
+        }
+        None => from_env(var)
+            .map(|key| (key, Origin::Env))
+            .or_else(|| from_keychain(var).map(|key| (key, Origin::Keychain))),
+    };
+
+    if let Some((key, _)) = &found {
+        secrets::register(key);
+    }
+    Ok(found)
+}
+
+// the API key of the generator, registered as a secret so that it's never shown
+pub fn api_key(generator: &str) -> Result<String> {
+    let var = env_var(generator)
+        .ok_or_else(|| anyhow!("generator '{}' does not use an API key", generator))?;
+    match resolve(generator)? {
+        Some((key, _)) => Ok(key),
+        None => Err(anyhow!(
+            "missing API key of {}, set {} or add it to the credentials of the configuration",
+            generator,
+            var
+        )),
+    }
+}
+
+// fail early if the generator requires a key that can't be found
+pub fn check(generator: &str) -> Result<()> {
+    if env_var(generator).is_some() {
+        api_key(generator)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_source() {
+        assert_eq!(
+            Source::from("env:WORK_KEY".to_string()),
+            Source::Env("WORK_KEY".to_string())
+        );
+        assert_eq!(Source::from("keychain".to_string()), Source::Keychain(None));
+        assert_eq!(
+            Source::from("keychain:work".to_string()),
+            Source::Keychain(Some("work".to_string()))
+        );
+        assert_eq!(
+            Source::from("sk-1234".to_string()),
+            Source::Value("sk-1234".to_string())
+        );
+    }
+
+    #[test]
+    fn test_resolve() {
+        assert_eq!(env_var("claude"), Some("ANTHROPIC_API_KEY"));
+        assert_eq!(env_var("ollama"), None);
+        assert!(check("ollama").is_ok());
+        assert!(configure("ollama", Source::Value("key".to_string())).is_err());
+
+        configure("xai", Source::Value("xai-test-key".to_string())).unwrap();
+        assert_eq!(
+            resolve("xai").unwrap(),
+            Some(("xai-test-key".to_string(), Origin::Config))
+        );
+        assert_eq!(secrets::redact("xai-test-key"), "********");
+
+        let mut env = crate::agent::test_env::TestEnv::new();
+        env.set("NERVE_TEST_NOVITA_KEY", "novita-test-key");
+        configure("novita", Source::Env("NERVE_TEST_NOVITA_KEY".to_string())).unwrap();
+        assert_eq!(api_key("novita").unwrap(), "novita-test-key");
+
+        configure("mistral", Source::Env("NERVE_TEST_UNSET_KEY".to_string())).unwrap();
+        assert!(api_key("mistral").is_err());
+    }
+}
@@ -15,11 +15,7 @@ impl Client for DeepSeekClient {
     where
         Self: Sized,
     {
-        let client = OpenAIClient::custom(
-            model_name,
-            "DEEPSEEK_API_KEY",
-            "https://api.deepseek.com/v1/",
-        )?;
+        let client = OpenAIClient::custom(model_name, "deepseek", "https://api.deepseek.com/v1/")?;
 
         Ok(Self { client })
     }
@@ -17,7 +17,7 @@ impl Client for FireworksClient {
     {
         let client = OpenAIClient::custom(
             &format!("accounts/fireworks/models/{}", model_name),
-            "LLM_FIREWORKS_KEY",
+            "fireworks",
             "https://api.fireworks.ai/inference/v1/",
         )?;
 
@@ -17,7 +17,7 @@ use crate::agent::{
     Invocation,
 };
 
-use super::{ChatOptions, Client, SupportedFeatures};
+use super::{credentials, ChatOptions, Client, SupportedFeatures};
 
 lazy_static! {
     static ref RETRY_TIME_PARSER: Regex =
@@ -50,8 +50,7 @@ impl Client for GroqClient {
     where
         Self: Sized,
     {
-        let api_key = std::env::var("GROQ_API_KEY")
-            .map_err(|_| anyhow!("Missing GROQ_API_KEY".to_string()))?;
+        let api_key = credentials::api_key("groq")?;
 
         let model = model_name.to_string();
 
@@ -186,6 +185,12 @@ impl Client for GroqClient {
         }
 
         let mut request = builder::RequestBuilder::new(self.model.clone()).with_stream(false);
+        if let Some(temperature) = options.sampling.temperature {
+            request = request.with_temperature(temperature);
+        }
+        if let Some(seed) = options.sampling.seed {
+            request = request.with_seed(seed as i32);
+        }
 
         if state.lock().await.use_native_tools_format {
             let mut tools = vec![];
@@ -222,6 +227,8 @@ impl Client for GroqClient {
                         }
                     }
 
+                    required.sort();
+
                     let function = Function {
                         name: Some(action.name().to_string()),
                         description: Some(action.description().to_string()),
@@ -108,6 +108,22 @@ impl ChatHistory {
         }
     }
 
+    // prepend few-shot examples, these are never subject to the conversation window
+    pub fn with_examples(mut self, examples: Vec<Message>) -> Self {
+        if !examples.is_empty() {
+            self.history = [examples, self.history].concat();
+        }
+        self
+    }
+
+    pub fn redacted(&self) -> Self {
+        Self {
+            conversation: self.conversation.iter().map(|m| m.redacted()).collect(),
+            history: self.history.iter().map(|m| m.redacted()).collect(),
+            window: self.window,
+        }
+    }
+
     pub fn iter(&self) -> impl Iterator<Item = &Message> {
         self.history.iter()
     }
@@ -332,6 +348,31 @@ mod tests {
         assert_eq!(history.history, expected);
     }
 
+    #[test]
+    fn test_examples_are_not_windowed() {
+        let examples = vec![
+            Message::Agent("example".to_string(), None),
+            Message::Feedback("example feedback".to_string(), None),
+        ];
+        let conv = vec![
+            Message::Agent("test1".to_string(), None),
+            Message::Feedback("feedback1".to_string(), None),
+            Message::Agent("test2".to_string(), None),
+            Message::Feedback("feedback2".to_string(), None),
+        ];
+
+        let expected = vec![
+            Message::Agent("example".to_string(), None),
+            Message::Feedback("example feedback".to_string(), None),
+            Message::Agent("test2".to_string(), None),
+            Message::Feedback("feedback2".to_string(), None),
+        ];
+
+        let history =
+            ChatHistory::create(conv, ConversationWindow::LastN(2)).with_examples(examples);
+        assert_eq!(history.history, expected);
+    }
+
     #[test]
     fn test_last_n_strategy_with_just_enough() {
         let conv = vec![
@@ -16,7 +16,7 @@ impl Client for HuggingfaceMessageClient {
         Self: Sized,
     {
         let message_api = format!("https://{}/v1/", url);
-        let client = OpenAIClient::custom(model_name, "HF_API_TOKEN", &message_api)?;
+        let client = OpenAIClient::custom(model_name, "hf", &message_api)?;
 
         log::debug!("using huggingface message api @ {}", message_api);
 
@@ -0,0 +1,163 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use serde::Deserialize;
+use tokenizers::{Tokenizer, TruncationParams};
+
+// the files of a local embedding model, either in a folder or in a huggingface hub repository,
+// downloaded once to the cache in $HF_HOME and read from there afterwards
+pub enum ModelFiles {
+    Local(PathBuf),
+    Hub(Box<hf_hub::api::sync::ApiRepo>),
+}
+
+impl ModelFiles {
+    pub fn open(model: &str) -> Result<Self> {
+        let path = PathBuf::from(model);
+        if path.is_dir() {
+            return Ok(Self::Local(path));
+        }
+        let api = hf_hub::api::sync::ApiBuilder::from_env()
+            .with_progress(false)
+            .build()
+            .map_err(|e| anyhow!("can't access the huggingface hub: {}", e))?;
+        Ok(Self::Hub(Box::new(api.model(model.to_string()))))
+    }
+
+    pub fn get(&self, file: &str) -> Result<PathBuf> {
+        match self {
+            Self::Local(folder) => {
+                let path = folder.join(file);
+                if path.is_file() {
+                    Ok(path)
+                } else {
+                    Err(anyhow!("model file {} not found", path.display()))
+                }
+            }
+            Self::Hub(repo) => repo
+                .get(file)
+                .map_err(|e| anyhow!("can't get {} from {}: {}", file, repo.url(file), e)),
+        }
+    }
+
+    // for the files that not every model has
+    pub fn find(&self, file: &str) -> Option<PathBuf> {
+        self.get(file).ok()
+    }
+}
+
+// inputs longer than what the model supports are truncated
+pub fn load_tokenizer(path: &Path, max_length: usize) -> Result<Tokenizer> {
+    let mut tokenizer = Tokenizer::from_file(path)
+        .map_err(|e| anyhow!("can't load tokenizer {}: {}", path.display(), e))?;
+    tokenizer
+        .with_padding(None)
+        .with_truncation(Some(TruncationParams {
+            max_length,
+            ..Default::default()
+        }))
+        .map_err(|e| anyhow!("can't set the tokenizer truncation: {}", e))?;
+    Ok(tokenizer)
+}
+
+#[derive(Deserialize, Debug, Default)]
+struct PoolingConfig {
+    #[serde(default)]
+    pooling_mode_cls_token: bool,
+}
+
+// how the embeddings of the tokens are combined in the one of the text, as set by the
+// 1_Pooling/config.json of sentence-transformers models, the mean by default
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Pooling {
+    #[default]
+    Mean,
+    Cls,
+}
+
+impl Pooling {
+    pub fn from_model(files: &ModelFiles) -> Result<Self> {
+        let path = match files.find("1_Pooling/config.json") {
+            Some(path) => path,
+            None => return Ok(Self::default()),
+        };
+        let config: PoolingConfig = serde_json::from_str(&std::fs::read_to_string(&path)?)
+            .map_err(|e| anyhow!("can't parse {}: {}", path.display(), e))?;
+        Ok(if config.pooling_mode_cls_token {
+            Self::Cls
+        } else {
+            Self::Mean
+        })
+    }
+
+    // normalized, so that the dot product of two embeddings is their cosine similarity
+    pub fn pool(&self, tokens: &[Vec<f32>], mask: &[u32]) -> Vec<f64> {
+        let size = tokens.first().map(|token| token.len()).unwrap_or(0);
+        let mut pooled = vec![0.0f64; size];
+        match self {
+            Self::Cls => {
+                if let Some(first) = tokens.first() {
+                    for (value, token) in pooled.iter_mut().zip(first) {
+                        *value = *token as f64;
+                    }
+                }
+            }
+            Self::Mean => {
+                let mut count = 0;
+                for (token, _) in tokens.iter().zip(mask).filter(|(_, mask)| **mask != 0) {
+                    for (value, token) in pooled.iter_mut().zip(token) {
+                        *value += *token as f64;
+                    }
+                    count += 1;
+                }
+                for value in pooled.iter_mut() {
+                    *value /= count.max(1) as f64;
+                }
+            }
+        }
+
+        let norm = pooled.iter().map(|v| v * v).sum::<f64>().sqrt();
+        if norm > 0.0 {
+            for value in pooled.iter_mut() {
+                *value /= norm;
+            }
+        }
+        pooled
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pooling() {
+        let tokens = vec![vec![3.0, 0.0], vec![0.0, 4.0], vec![100.0, 100.0]];
+        let mask = [1, 1, 0];
+
+        assert_eq!(Pooling::Cls.pool(&tokens, &mask), vec![1.0, 0.0]);
+        // the padding is left out of the mean
+        let mean = Pooling::Mean.pool(&tokens, &mask);
+        assert!((mean[0] - 0.6).abs() < 1e-9);
+        assert!((mean[1] - 0.8).abs() < 1e-9);
+        assert_eq!(Pooling::Mean.pool(&[], &[]), Vec::<f64>::new());
+    }
+
+    #[test]
+    fn test_model_files() {
+        let folder = std::env::temp_dir().join("nerve-local-model-test");
+        std::fs::create_dir_all(folder.join("1_Pooling")).unwrap();
+        std::fs::write(
+            folder.join("1_Pooling/config.json"),
+            r#"{"word_embedding_dimension": 384, "pooling_mode_cls_token": true}"#,
+        )
+        .unwrap();
+
+        let files = ModelFiles::open(folder.to_str().unwrap()).unwrap();
+        assert!(matches!(files, ModelFiles::Local(_)));
+        assert!(files.get("model.safetensors").is_err());
+        assert_eq!(Pooling::from_model(&files).unwrap(), Pooling::Cls);
+
+        std::fs::remove_dir_all(&folder).unwrap();
+    }
+}
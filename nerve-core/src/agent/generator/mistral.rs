@@ -15,8 +15,7 @@ impl Client for MistralClient {
     where
         Self: Sized,
     {
-        let client =
-            OpenAIClient::custom(model_name, "MISTRAL_API_KEY", "https://api.mistral.ai/v1/")?;
+        let client = OpenAIClient::custom(model_name, "mistral", "https://api.mistral.ai/v1/")?;
 
         Ok(Self { client })
     }
@@ -0,0 +1,67 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use anyhow::Result;
+use async_trait::async_trait;
+
+use crate::agent::state::SharedState;
+
+use super::{ChatOptions, ChatResponse, Client};
+
+// replays a transcript of model responses in order, used for testing tasklets
+// without a real model: mock:///path/to/transcript.yml
+pub struct MockClient {
+    responses: Vec<String>,
+    current: AtomicUsize,
+}
+
+impl MockClient {
+    pub fn from_responses(responses: Vec<String>) -> Self {
+        Self {
+            responses,
+            current: AtomicUsize::new(0),
+        }
+    }
+}
+
+#[async_trait]
+impl Client for MockClient {
+    fn new(_: &str, _: u16, model_name: &str, _: u32) -> anyhow::Result<Self>
+    where
+        Self: Sized,
+    {
+        let yaml = std::fs::read_to_string(model_name)
+            .map_err(|e| anyhow!("can't read mock transcript {}: {}", model_name, e))?;
+        let responses: Vec<String> = serde_yaml::from_str(&yaml)
+            .map_err(|e| anyhow!("can't parse mock transcript {}: {}", model_name, e))?;
+
+        Ok(Self::from_responses(responses))
+    }
+
+    async fn chat(&self, _: SharedState, _: &ChatOptions) -> anyhow::Result<ChatResponse> {
+        let idx = self.current.fetch_add(1, Ordering::SeqCst);
+        if let Some(content) = self.responses.get(idx) {
+            Ok(ChatResponse {
+                content: content.to_string(),
+                invocations: vec![],
+                usage: None,
+            })
+        } else {
+            Err(anyhow!(
+                "mock transcript exhausted after {} responses",
+                self.responses.len()
+            ))
+        }
+    }
+}
+
+#[async_trait]
+impl mini_rag::Embedder for MockClient {
+    async fn embed(&self, text: &str) -> Result<mini_rag::Embeddings> {
+        // deterministic and meaningless, just enough to index documents
+        let mut embeddings = vec![0.0f64; 16];
+        for (i, byte) in text.bytes().enumerate() {
+            embeddings[i % 16] += byte as f64 / 255.0;
+        }
+        Ok(mini_rag::Embeddings::from(embeddings))
+    }
+}
@@ -1,4 +1,4 @@
-use std::{fmt::Display, time::Duration};
+use std::{fmt::Display, sync::Arc, time::Duration};
 
 use anyhow::Result;
 use async_trait::async_trait;
@@ -8,23 +8,37 @@ use lazy_static::lazy_static;
 use regex::Regex;
 use serde::{Deserialize, Serialize};
 
-use super::{state::SharedState, Invocation};
+use super::{secrets, state::SharedState, Invocation};
 
+#[cfg(feature = "anthropic")]
 mod anthropic;
+pub mod cache;
+#[cfg(feature = "candle")]
+mod candle;
+pub mod cassette;
+pub mod credentials;
 mod deepseek;
 mod fireworks;
+#[cfg(feature = "groq")]
 mod groq;
 mod huggingface;
+#[cfg(any(feature = "candle", feature = "onnx"))]
+mod local;
 mod mistral;
+mod mock;
 mod nim;
 mod novita;
 mod ollama;
+#[cfg(feature = "onnx")]
+mod onnx;
 mod openai;
 mod openai_compatible;
 mod xai;
 
-pub(crate) mod history;
+pub mod history;
 mod options;
+pub mod pricing;
+pub mod tokenizer;
 
 pub use options::*;
 
@@ -34,25 +48,63 @@ lazy_static! {
     static ref CONN_RESET_PARSER: Regex = Regex::new(r"(?m)^.+onnection reset by peer.*").unwrap();
 }
 
+// fixed seed used in deterministic mode
+pub const DETERMINISTIC_SEED: u64 = 42;
+
+// sampling parameters, only used by the generators that support them
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct Sampling {
+    pub temperature: Option<f32>,
+    pub seed: Option<u64>,
+}
+
+impl Sampling {
+    pub fn deterministic() -> Self {
+        Self {
+            temperature: Some(0.0),
+            seed: Some(DETERMINISTIC_SEED),
+        }
+    }
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct ChatOptions {
     pub system_prompt: Option<String>,
     pub prompt: String,
     pub history: ChatHistory,
+    #[serde(default)]
+    pub sampling: Sampling,
 }
 
 impl ChatOptions {
     pub fn new(
         system_prompt: Option<String>,
         prompt: String,
+        examples: Vec<Message>,
         conversation: Vec<Message>,
         history_strategy: ConversationWindow,
     ) -> Self {
-        let history = ChatHistory::create(conversation, history_strategy);
+        let history = ChatHistory::create(conversation, history_strategy).with_examples(examples);
         Self {
             system_prompt,
             prompt,
             history,
+            sampling: Sampling::default(),
+        }
+    }
+
+    pub fn with_sampling(mut self, sampling: Sampling) -> Self {
+        self.sampling = sampling;
+        self
+    }
+
+    // mask the registered secrets
+    pub fn redacted(&self) -> Self {
+        Self {
+            system_prompt: self.system_prompt.as_deref().map(secrets::redact),
+            prompt: secrets::redact(&self.prompt),
+            history: self.history.redacted(),
+            sampling: self.sampling,
         }
     }
 }
@@ -63,6 +115,21 @@ pub enum Message {
     Feedback(String, Option<Invocation>),
 }
 
+impl Message {
+    pub fn redacted(&self) -> Self {
+        match self {
+            Message::Agent(content, invocation) => Message::Agent(
+                secrets::redact(content),
+                invocation.as_ref().map(|inv| inv.redacted()),
+            ),
+            Message::Feedback(content, invocation) => Message::Feedback(
+                secrets::redact(content),
+                invocation.as_ref().map(|inv| inv.redacted()),
+            ),
+        }
+    }
+}
+
 impl Display for Message {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(
@@ -76,6 +143,7 @@ impl Display for Message {
     }
 }
 
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Usage {
     /// The number of input tokens which were used.
     pub input_tokens: u32,
@@ -89,6 +157,7 @@ pub struct ChatResponse {
     pub usage: Option<Usage>,
 }
 
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct SupportedFeatures {
     pub system_prompt: bool,
     pub tools: bool,
@@ -195,6 +264,7 @@ macro_rules! factory_body {
                 $model_name,
                 $context_window,
             )?)),
+            #[cfg(feature = "groq")]
             "groq" => Ok(Box::new(groq::GroqClient::new(
                 $url,
                 $port,
@@ -207,6 +277,7 @@ macro_rules! factory_body {
                 $model_name,
                 $context_window,
             )?)),
+            #[cfg(feature = "anthropic")]
             "anthropic" | "claude" => Ok(Box::new(anthropic::AnthropicClient::new(
                 $url,
                 $port,
@@ -243,6 +314,22 @@ macro_rules! factory_body {
                 $model_name,
                 $context_window,
             )?)),
+            "mock" => Ok(Box::new(mock::MockClient::new(
+                $url,
+                $port,
+                $model_name,
+                $context_window,
+            )?)),
+            #[cfg(not(feature = "anthropic"))]
+            "anthropic" | "claude" => Err(anyhow!(
+                "nerve was built without the {} generator, enable the anthropic feature",
+                $name
+            )),
+            #[cfg(not(feature = "groq"))]
+            "groq" => Err(anyhow!(
+                "nerve was built without the {} generator, enable the groq feature",
+                $name
+            )),
             _ => Err(anyhow!("generator '{}' not supported yet", $name)),
         }
     };
@@ -255,7 +342,20 @@ pub fn factory(
     model_name: &str,
     context_window: u32,
 ) -> Result<Box<dyn Client>> {
-    factory_body!(name, url, port, model_name, context_window)
+    match name {
+        "candle" | "onnx" => Err(anyhow!("{} can only be used as the embedder", name)),
+        _ => factory_body!(name, url, port, model_name, context_window),
+    }
+}
+
+// an embedder used both by the rag store and by other components
+pub struct SharedEmbedder(pub Arc<dyn mini_rag::Embedder>);
+
+#[async_trait]
+impl mini_rag::Embedder for SharedEmbedder {
+    async fn embed(&self, text: &str) -> Result<mini_rag::Embeddings> {
+        self.0.embed(text).await
+    }
 }
 
 pub fn factory_embedder(
@@ -265,5 +365,21 @@ pub fn factory_embedder(
     model_name: &str,
     context_window: u32,
 ) -> Result<Box<dyn mini_rag::Embedder>> {
-    factory_body!(name, url, port, model_name, context_window)
+    match name {
+        #[cfg(feature = "candle")]
+        "candle" => Ok(Box::new(candle::CandleEmbedder::new(model_name)?)),
+        #[cfg(not(feature = "candle"))]
+        "candle" => Err(anyhow!(
+            "nerve was built without the {} embedder, enable the candle feature",
+            name
+        )),
+        #[cfg(feature = "onnx")]
+        "onnx" => Ok(Box::new(onnx::OnnxEmbedder::new(model_name)?)),
+        #[cfg(not(feature = "onnx"))]
+        "onnx" => Err(anyhow!(
+            "nerve was built without the {} embedder, enable the onnx feature",
+            name
+        )),
+        _ => factory_body!(name, url, port, model_name, context_window),
+    }
 }
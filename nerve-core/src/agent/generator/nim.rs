@@ -21,11 +21,8 @@ impl Client for NvidiaNIMClient {
             &format!("nvidia/{}", model_name)
         };
 
-        let client = OpenAIClient::custom(
-            model_name,
-            "NIM_API_KEY",
-            "https://integrate.api.nvidia.com/v1/",
-        )?;
+        let client =
+            OpenAIClient::custom(model_name, "nim", "https://integrate.api.nvidia.com/v1/")?;
 
         Ok(Self { client })
     }
@@ -15,11 +15,8 @@ impl Client for NovitaClient {
     where
         Self: Sized,
     {
-        let client = OpenAIClient::custom(
-            model_name,
-            "NOVITA_API_KEY",
-            "https://api.novita.ai/v3/openai/",
-        )?;
+        let client =
+            OpenAIClient::custom(model_name, "novita", "https://api.novita.ai/v3/openai/")?;
 
         Ok(Self { client })
     }
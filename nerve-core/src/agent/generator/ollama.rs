@@ -161,6 +161,8 @@ impl Client for OllamaClient {
                         }
                     }
 
+                    required.sort();
+
                     let function = ToolFunction {
                         name: action.name().to_string(),
                         description: action.description().to_string(),
@@ -181,10 +183,18 @@ impl Client for OllamaClient {
             log::trace!("ollama.tools={:?}", &tools);
         }
 
+        // Do not provide model options other than the context window size and the sampling parameters
+        // explicitly requested so that we'll use whatever was specified in the modelfile.
+        let mut model_options = self.options.clone();
+        if let Some(temperature) = options.sampling.temperature {
+            model_options = model_options.temperature(temperature);
+        }
+        if let Some(seed) = options.sampling.seed {
+            model_options = model_options.seed(seed as i32);
+        }
+
         let mut request = ChatMessageRequest::new(self.model.to_string(), chat_history)
-            // Do not provide model options other than the context window size so that we'll use whatever was
-            // specified in the modelfile.
-            .options(self.options.clone())
+            .options(model_options)
             // Set tools ( https://ollama.com/blog/tool-support )
             .tools(tools);
 
@@ -0,0 +1,222 @@
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use anyhow::Result;
+use async_trait::async_trait;
+use ort::session::Session;
+use ort::value::Tensor;
+use tokenizers::Tokenizer;
+
+use super::local::{self, ModelFiles, Pooling};
+
+// the onnx files looked for when the model doesn't name one, the quantized ones first
+const MODEL_FILES: [&str; 4] = [
+    "onnx/model_quantized.onnx",
+    "model_quantized.onnx",
+    "onnx/model.onnx",
+    "model.onnx",
+];
+
+// used when the model has no config.json telling its maximum input length
+const DEFAULT_MAX_LENGTH: usize = 512;
+
+// an onnx export of an embedding model executed by nerve itself with onnxruntime:
+// onnx://Xenova/bge-small-en-v1.5 from the huggingface hub, onnx:///path/to/model for a folder
+// with its tokenizer.json and model.onnx, or the path of a specific .onnx file in either of them
+pub struct OnnxEmbedder {
+    model: Arc<Model>,
+}
+
+struct Model {
+    // running the session needs exclusive access to it
+    session: Mutex<Session>,
+    tokenizer: Tokenizer,
+    pooling: Pooling,
+}
+
+// onnxruntime is loaded at runtime from $ORT_DYLIB_PATH, or from the library path of the system,
+// and checked here since ort would abort the process if it's missing
+fn load_runtime() -> Result<()> {
+    let path = match std::env::var("ORT_DYLIB_PATH") {
+        Ok(path) if !path.is_empty() => path,
+        _ => libloading::library_filename("onnxruntime")
+            .to_string_lossy()
+            .to_string(),
+    };
+    // SAFETY: the same library ort loads right after, only opened here to report the error
+    unsafe { libloading::Library::new(&path) }.map_err(|e| {
+        anyhow!(
+            "can't load the onnxruntime library from {}, set ORT_DYLIB_PATH to its path: {}",
+            path,
+            e
+        )
+    })?;
+    ort::init_from(path).commit()?;
+    Ok(())
+}
+
+// the files of the model and, if the model name points to one, its onnx file
+fn locate(model: &str) -> Result<(ModelFiles, Option<String>)> {
+    if !model.ends_with(".onnx") {
+        return Ok((ModelFiles::open(model)?, None));
+    }
+
+    let path = Path::new(model);
+    if path.is_file() {
+        // the folder of the model is the closest one with the tokenizer
+        let folder = path
+            .ancestors()
+            .skip(1)
+            .find(|folder| folder.join("tokenizer.json").is_file())
+            .ok_or_else(|| anyhow!("no tokenizer.json found for {}", model))?;
+        let file = path.strip_prefix(folder)?.to_string_lossy().to_string();
+        return Ok((ModelFiles::Local(folder.to_path_buf()), Some(file)));
+    }
+
+    // owner/name/path/of/the/model.onnx in a huggingface hub repository
+    let mut parts = model.splitn(3, '/');
+    match (parts.next(), parts.next(), parts.next()) {
+        (Some(owner), Some(name), Some(file)) => Ok((
+            ModelFiles::open(&format!("{}/{}", owner, name))?,
+            Some(file.to_string()),
+        )),
+        _ => Err(anyhow!("model file {} not found", model)),
+    }
+}
+
+fn max_length(files: &ModelFiles) -> usize {
+    files
+        .find("config.json")
+        .and_then(|path| std::fs::read_to_string(path).ok())
+        .and_then(|config| serde_json::from_str::<serde_json::Value>(&config).ok())
+        .and_then(|config| config["max_position_embeddings"].as_u64())
+        .map(|max| max as usize)
+        .unwrap_or(DEFAULT_MAX_LENGTH)
+}
+
+impl OnnxEmbedder {
+    pub fn new(model_name: &str) -> Result<Self> {
+        load_runtime()?;
+
+        let (files, file) = locate(model_name)?;
+        let weights = match file {
+            Some(file) => files.get(&file)?,
+            None => MODEL_FILES
+                .iter()
+                .find_map(|file| files.find(file))
+                .ok_or_else(|| {
+                    anyhow!(
+                        "no onnx model found for {}, tried {}",
+                        model_name,
+                        MODEL_FILES.join(", ")
+                    )
+                })?,
+        };
+        let tokenizer = local::load_tokenizer(&files.get("tokenizer.json")?, max_length(&files))?;
+        let pooling = Pooling::from_model(&files)?;
+
+        let session = Session::builder()?
+            .commit_from_file(&weights)
+            .map_err(|e| anyhow!("can't load model {}: {}", weights.display(), e))?;
+
+        log::debug!(
+            "loaded embedding model {} with {:?} pooling",
+            weights.display(),
+            pooling
+        );
+
+        Ok(Self {
+            model: Arc::new(Model {
+                session: Mutex::new(session),
+                tokenizer,
+                pooling,
+            }),
+        })
+    }
+}
+
+impl Model {
+    fn embed(&self, text: &str) -> Result<Vec<f64>> {
+        let encoding = self
+            .tokenizer
+            .encode(text, true)
+            .map_err(|e| anyhow!("can't tokenize the text to embed: {}", e))?;
+        let input = |values: &[u32]| {
+            Tensor::from_array((
+                [1, values.len()],
+                values.iter().map(|v| *v as i64).collect::<Vec<_>>(),
+            ))
+        };
+
+        let mut session = self.session.lock().unwrap();
+        // not every model takes the token types
+        let mut inputs = vec![];
+        for name in session.inputs.iter().map(|input| input.name.as_str()) {
+            let values = match name {
+                "input_ids" => encoding.get_ids(),
+                "attention_mask" => encoding.get_attention_mask(),
+                "token_type_ids" => encoding.get_type_ids(),
+                _ => return Err(anyhow!("unsupported model input {}", name)),
+            };
+            inputs.push((name.to_string(), input(values)?));
+        }
+
+        let outputs = session.run(inputs)?;
+        let (shape, values) = outputs[0].try_extract_tensor::<f32>()?;
+        match shape.len() {
+            // the embeddings of the tokens, to pool
+            3 => {
+                let tokens: Vec<Vec<f32>> = values
+                    .chunks(shape[2] as usize)
+                    .map(|token| token.to_vec())
+                    .collect();
+                Ok(self.pooling.pool(&tokens, encoding.get_attention_mask()))
+            }
+            // the embedding of the text, already pooled by the model
+            2 => Ok(Pooling::Cls.pool(&[values.to_vec()], &[1])),
+            _ => Err(anyhow!("unexpected model output shape {}", shape)),
+        }
+    }
+}
+
+#[async_trait]
+impl mini_rag::Embedder for OnnxEmbedder {
+    async fn embed(&self, text: &str) -> Result<mini_rag::Embeddings> {
+        let model = self.model.clone();
+        let text = text.to_string();
+        // cpu bound, kept off the threads of the runtime
+        tokio::task::spawn_blocking(move || model.embed(&text)).await?
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_locate() {
+        let folder = std::env::temp_dir().join("nerve-onnx-test");
+        std::fs::create_dir_all(folder.join("onnx")).unwrap();
+        std::fs::write(folder.join("tokenizer.json"), "{}").unwrap();
+        std::fs::write(
+            folder.join("config.json"),
+            r#"{"max_position_embeddings": 128}"#,
+        )
+        .unwrap();
+        std::fs::write(folder.join("onnx/model_quantized.onnx"), "").unwrap();
+
+        let (files, file) = locate(folder.to_str().unwrap()).unwrap();
+        assert!(matches!(files, ModelFiles::Local(_)));
+        assert_eq!(file, None);
+        assert_eq!(max_length(&files), 128);
+
+        let model = folder.join("onnx/model_quantized.onnx");
+        let (files, file) = locate(model.to_str().unwrap()).unwrap();
+        assert!(matches!(files, ModelFiles::Local(ref path) if path == &folder));
+        assert_eq!(file.as_deref(), Some("onnx/model_quantized.onnx"));
+
+        assert!(locate("model.onnx").is_err());
+
+        std::fs::remove_dir_all(&folder).unwrap();
+    }
+}
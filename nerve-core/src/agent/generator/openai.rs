@@ -9,7 +9,7 @@ use serde::{Deserialize, Serialize};
 
 use crate::agent::{state::SharedState, Invocation};
 
-use super::{ChatOptions, ChatResponse, Client, Message, SupportedFeatures};
+use super::{credentials, ChatOptions, ChatResponse, Client, Message, SupportedFeatures};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct OpenAiToolFunctionParameterProperty {
@@ -32,12 +32,13 @@ pub struct OpenAIClient {
 }
 
 impl OpenAIClient {
-    pub fn custom(model: &str, api_key_env: &str, endpoint: &str) -> anyhow::Result<Self>
+    // an openai compatible client using the API key of the generator
+    pub fn custom(model: &str, generator: &str, endpoint: &str) -> anyhow::Result<Self>
     where
         Self: Sized,
     {
         let model = model.to_string();
-        let api_key = std::env::var(api_key_env).map_err(|_| anyhow!("Missing {api_key_env}"))?;
+        let api_key = credentials::api_key(generator)?;
         let auth = Auth::new(&api_key);
         let client = OpenAI::new(auth, endpoint);
 
@@ -94,6 +95,8 @@ impl OpenAIClient {
                         }
                     }
 
+                    required.sort();
+
                     let function = FunctionDefinition {
                         name: action.name().to_string(),
                         description: Some(action.description().to_string()),
@@ -127,7 +130,7 @@ impl Client for OpenAIClient {
     where
         Self: Sized,
     {
-        Self::custom(model_name, "OPENAI_API_KEY", "https://api.openai.com/v1/")
+        Self::custom(model_name, "openai", "https://api.openai.com/v1/")
     }
 
     async fn check_supported_features(&self) -> Result<SupportedFeatures> {
@@ -165,6 +168,7 @@ impl Client for OpenAIClient {
             frequency_penalty: None,
             logit_bias: None,
             user: None,
+            seed: None,
             messages: chat_history,
             tools,
         };
@@ -258,7 +262,7 @@ impl Client for OpenAIClient {
         let body = ChatBody {
             model: self.model.to_string(),
             max_tokens: None,
-            temperature: None,
+            temperature: options.sampling.temperature,
             top_p: None,
             n: None,
             stream: Some(false),
@@ -267,6 +271,7 @@ impl Client for OpenAIClient {
             frequency_penalty: None,
             logit_bias: None,
             user: None,
+            seed: options.sampling.seed,
             messages: chat_history,
             tools: if tools.is_empty() { None } else { Some(tools) },
         };
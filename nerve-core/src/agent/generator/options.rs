@@ -18,6 +18,11 @@ pub struct Options {
 }
 
 impl Options {
+    pub fn is_local(&self) -> bool {
+        self.type_name == "ollama"
+            && ["localhost", "127.0.0.1", "::1"].contains(&self.host.as_str())
+    }
+
     pub fn parse(raw: &str, context_window: u32) -> Result<Self> {
         let raw = raw.trim().trim_matches(|c| c == '"' || c == '\'');
         if raw.is_empty() {
@@ -141,4 +146,22 @@ mod tests {
         assert_eq!(ret.model_name, "localhost:8000/v1");
         assert_eq!(ret.context_window, 123);
     }
+
+    #[test]
+    fn test_parse_local_embedder() {
+        let ret = Options::parse("candle:///models/all-MiniLM-L6-v2", 123).unwrap();
+        assert_eq!(ret.type_name, "candle");
+        assert_eq!(ret.model_name, "/models/all-MiniLM-L6-v2");
+
+        let ret = Options::parse("candle://sentence-transformers/all-MiniLM-L6-v2", 123).unwrap();
+        assert_eq!(ret.model_name, "sentence-transformers/all-MiniLM-L6-v2");
+
+        let ret =
+            Options::parse("onnx://Xenova/bge-small-en-v1.5/onnx/model_int8.onnx", 123).unwrap();
+        assert_eq!(ret.type_name, "onnx");
+        assert_eq!(
+            ret.model_name,
+            "Xenova/bge-small-en-v1.5/onnx/model_int8.onnx"
+        );
+    }
 }
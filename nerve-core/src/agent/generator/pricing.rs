@@ -0,0 +1,101 @@
+use anyhow::Result;
+
+// USD per million tokens
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TokenPrices {
+    pub input: f64,
+    pub output: f64,
+}
+
+// generator type, model name prefix, input and output prices, more specific prefixes first
+const KNOWN_PRICES: &[(&str, &str, f64, f64)] = &[
+    ("openai", "gpt-4o-mini", 0.15, 0.6),
+    ("openai", "gpt-4o", 2.5, 10.0),
+    ("openai", "gpt-4-turbo", 10.0, 30.0),
+    ("openai", "gpt-3.5-turbo", 0.5, 1.5),
+    ("openai", "o1-mini", 3.0, 12.0),
+    ("openai", "o1", 15.0, 60.0),
+    ("anthropic", "claude-3-5-haiku", 0.8, 4.0),
+    ("anthropic", "claude-3-5-sonnet", 3.0, 15.0),
+    ("anthropic", "claude-3-haiku", 0.25, 1.25),
+    ("anthropic", "claude-3-opus", 15.0, 75.0),
+    ("deepseek", "deepseek-chat", 0.14, 0.28),
+    ("mistral", "mistral-large", 2.0, 6.0),
+];
+
+impl TokenPrices {
+    // parse "<input>,<output>"
+    pub fn parse(raw: &str) -> Result<Self> {
+        let (input, output) = raw
+            .split_once(',')
+            .ok_or_else(|| anyhow!("token prices must be in the <input>,<output> format"))?;
+
+        let parse = |v: &str| {
+            v.trim()
+                .parse::<f64>()
+                .map_err(|_| anyhow!("invalid token price '{}'", v))
+        };
+
+        Ok(Self {
+            input: parse(input)?,
+            output: parse(output)?,
+        })
+    }
+
+    pub fn lookup(type_name: &str, model_name: &str) -> Option<Self> {
+        // local models are free
+        if type_name == "ollama" || type_name == "mock" {
+            return Some(Self {
+                input: 0.0,
+                output: 0.0,
+            });
+        }
+
+        KNOWN_PRICES
+            .iter()
+            .find(|(type_, prefix, _, _)| *type_ == type_name && model_name.starts_with(prefix))
+            .map(|(_, _, input, output)| Self {
+                input: *input,
+                output: *output,
+            })
+    }
+
+    pub fn cost(&self, input_tokens: u32, output_tokens: u32) -> f64 {
+        (input_tokens as f64 * self.input + output_tokens as f64 * self.output) / 1_000_000.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            TokenPrices::parse("2.5, 10").unwrap(),
+            TokenPrices {
+                input: 2.5,
+                output: 10.0
+            }
+        );
+        assert!(TokenPrices::parse("2.5").is_err());
+        assert!(TokenPrices::parse("a,b").is_err());
+    }
+
+    #[test]
+    fn test_lookup_and_cost() {
+        let prices = TokenPrices::lookup("openai", "gpt-4o-mini-2024-07-18").unwrap();
+        assert_eq!(prices.input, 0.15);
+
+        let prices = TokenPrices::lookup("openai", "gpt-4o").unwrap();
+        assert_eq!(prices.cost(1_000_000, 100_000), 3.5);
+
+        assert!(TokenPrices::lookup("groq", "whatever").is_none());
+        assert_eq!(
+            TokenPrices::lookup("ollama", "llama3")
+                .unwrap()
+                .cost(1000, 1000),
+            0.0
+        );
+    }
+}
@@ -0,0 +1,68 @@
+// approximate number of tokens of a text, modeled after the pre-tokenization of BPE tokenizers
+// like cl100k: common words are a single token, longer words, numbers and symbols are split into
+// multiple ones, and a space before a word is merged with it
+pub fn count_tokens(text: &str) -> usize {
+    let mut tokens: usize = 0;
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if c.is_alphabetic() {
+            let mut len: usize = 1;
+            while chars.next_if(|c| c.is_alphabetic()).is_some() {
+                len += 1;
+            }
+            tokens += if !c.is_ascii() {
+                // non latin scripts are usually a token per character or less
+                len
+            } else if len <= 6 {
+                1
+            } else {
+                len.div_ceil(4)
+            };
+        } else if c.is_numeric() {
+            // numbers are split in groups of up to three digits
+            let mut len: usize = 1;
+            while chars.next_if(|c| c.is_numeric()).is_some() {
+                len += 1;
+            }
+            tokens += len.div_ceil(3);
+        } else if c == ' ' {
+            // a single space is merged with the word that follows it
+            if chars.peek().is_some_and(|c| *c == ' ') {
+                while chars.next_if(|c| *c == ' ').is_some() {}
+                tokens += 1;
+            }
+        } else if c.is_whitespace() {
+            while chars.next_if(|c| c.is_whitespace()).is_some() {}
+            tokens += 1;
+        } else {
+            // runs of the same symbol (like ---- or ====) are merged
+            let mut len: usize = 1;
+            while chars.next_if(|n| *n == c).is_some() {
+                len += 1;
+            }
+            tokens += len.div_ceil(4);
+        }
+    }
+
+    tokens
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_count_tokens() {
+        assert_eq!(count_tokens(""), 0);
+        assert_eq!(count_tokens("hello world"), 2);
+        assert_eq!(count_tokens("hello, world!"), 4);
+        // long words and numbers are split
+        assert_eq!(count_tokens("internationalization"), 5);
+        assert_eq!(count_tokens("1234567"), 3);
+        assert_eq!(count_tokens("<save_memory key=\"a\">x</save_memory>"), 17);
+        assert_eq!(count_tokens("a\n\n    b"), 3);
+        assert_eq!(count_tokens("----------------"), 4);
+        assert_eq!(count_tokens("日本語"), 3);
+    }
+}
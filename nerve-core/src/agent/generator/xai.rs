@@ -15,7 +15,7 @@ impl Client for XAIClient {
     where
         Self: Sized,
     {
-        let client = OpenAIClient::custom(model_name, "XAI_API_KEY", "https://api.x.ai/v1/")?;
+        let client = OpenAIClient::custom(model_name, "xai", "https://api.x.ai/v1/")?;
 
         Ok(Self { client })
     }
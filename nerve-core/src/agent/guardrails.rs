@@ -0,0 +1,504 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use regex::Regex;
+use serde::Deserialize;
+
+use super::{
+    generator::{history::ConversationWindow, ChatOptions, Client},
+    state::SharedState,
+    Invocation,
+};
+
+const REDACTED: &str = "[REDACTED]";
+
+// what a guardrail inspects
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Target {
+    // the raw model response
+    Response,
+    // payload and attributes of the invoked actions
+    Invocation,
+    #[default]
+    Any,
+}
+
+// what happens when a guardrail matches
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Enforcement {
+    #[default]
+    Block,
+    // mask the matching text
+    Redact,
+    // ask the operator before executing
+    Confirm,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Verdict {
+    Allow,
+    Block(String),
+    // the text with the matches masked
+    Redact(String),
+    Confirm(String),
+}
+
+#[async_trait]
+pub trait Guardrail: Send + Sync {
+    fn name(&self) -> &str;
+
+    fn target(&self) -> Target {
+        Target::Any
+    }
+
+    // action is None when the text is a model response
+    async fn check(&self, state: SharedState, text: &str, action: Option<&str>) -> Result<Verdict>;
+}
+
+// a regular expression or denylist rule as defined in the tasklet or guardrails file
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct GuardrailRule {
+    pub name: Option<String>,
+    // regular expression to match
+    pub pattern: Option<String>,
+    // words or sentences to match, case insensitive
+    pub deny: Option<Vec<String>>,
+    #[serde(default)]
+    pub target: Target,
+    #[serde(default)]
+    pub on_match: Enforcement,
+    // only inspect the invocations of these actions
+    pub actions: Option<Vec<String>>,
+}
+
+impl GuardrailRule {
+    fn to_regex(&self) -> Result<Regex> {
+        let mut parts = vec![];
+        if let Some(pattern) = &self.pattern {
+            parts.push(format!("(?:{})", pattern));
+        }
+        if let Some(deny) = &self.deny {
+            for word in deny {
+                parts.push(format!("(?i:{})", regex::escape(word)));
+            }
+        }
+
+        if parts.is_empty() {
+            return Err(anyhow!("guardrail rules need a pattern or a deny list"));
+        }
+
+        Regex::new(&parts.join("|")).map_err(|e| anyhow!("invalid guardrail pattern: {}", e))
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        self.to_regex().map(|_| ())
+    }
+}
+
+struct RuleGuardrail {
+    name: String,
+    regex: Regex,
+    rule: GuardrailRule,
+}
+
+#[async_trait]
+impl Guardrail for RuleGuardrail {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn target(&self) -> Target {
+        self.rule.target
+    }
+
+    async fn check(&self, _: SharedState, text: &str, action: Option<&str>) -> Result<Verdict> {
+        if let Some(actions) = &self.rule.actions {
+            match action {
+                Some(action) if actions.iter().any(|a| a == action) => {}
+                // neither one of the listed actions nor an invocation at all, like a response
+                _ => return Ok(Verdict::Allow),
+            }
+        }
+
+        let found = if let Some(found) = self.regex.find(text) {
+            found.as_str().to_string()
+        } else {
+            return Ok(Verdict::Allow);
+        };
+
+        Ok(match self.rule.on_match {
+            Enforcement::Block => Verdict::Block(format!("'{}' is not allowed", found)),
+            Enforcement::Redact => {
+                Verdict::Redact(self.regex.replace_all(text, REDACTED).to_string())
+            }
+            Enforcement::Confirm => Verdict::Confirm(format!("'{}' requires confirmation", found)),
+        })
+    }
+}
+
+// asks a model to classify the text
+pub struct ModerationGuardrail {
+    generator: Box<dyn Client>,
+    on_match: Enforcement,
+}
+
+impl ModerationGuardrail {
+    pub fn new(generator: Box<dyn Client>, on_match: Enforcement) -> Result<Self> {
+        if on_match == Enforcement::Redact {
+            return Err(anyhow!("moderation can only block or require confirmation"));
+        }
+        Ok(Self {
+            generator,
+            on_match,
+        })
+    }
+}
+
+#[async_trait]
+impl Guardrail for ModerationGuardrail {
+    fn name(&self) -> &str {
+        "moderation"
+    }
+
+    async fn check(&self, state: SharedState, text: &str, action: Option<&str>) -> Result<Verdict> {
+        let prompt = match action {
+            Some(action) => format!(
+                "An agent is about to execute the action '{}' with these arguments:\n\n{}",
+                action, text
+            ),
+            None => format!("An agent generated this response:\n\n{}", text),
+        };
+
+        let options = ChatOptions::new(
+            Some("You are a content moderator reviewing the actions of an autonomous agent. Reply with SAFE if the content is harmless, or with UNSAFE followed by a short reason if it is harmful, destructive or violates common content policies. Only reply with the verdict.".to_string()),
+            prompt,
+            vec![],
+            vec![],
+            ConversationWindow::Full,
+        );
+
+        let response = self.generator.chat(state, &options).await?;
+        let verdict = response.content.trim();
+        if !verdict.to_uppercase().starts_with("UNSAFE") {
+            return Ok(Verdict::Allow);
+        }
+
+        let reason = verdict
+            .get(6..)
+            .unwrap_or_default()
+            .trim_matches(|c: char| c == ':' || c.is_whitespace());
+        let reason = if reason.is_empty() {
+            "flagged as unsafe".to_string()
+        } else {
+            reason.to_string()
+        };
+
+        Ok(match self.on_match {
+            Enforcement::Confirm => Verdict::Confirm(reason),
+            _ => Verdict::Block(reason),
+        })
+    }
+}
+
+// the result of running all the guardrails on some text
+#[derive(Debug, Default, PartialEq)]
+pub struct Outcome {
+    pub blocked: Option<String>,
+    pub confirm: Option<String>,
+    pub redacted: bool,
+}
+
+#[derive(Default)]
+pub struct Guardrails(Vec<Box<dyn Guardrail>>);
+
+impl Guardrails {
+    pub fn from_rules(rules: &[GuardrailRule]) -> Result<Self> {
+        let mut guardrails = Self::default();
+        for (idx, rule) in rules.iter().enumerate() {
+            guardrails.add(Box::new(RuleGuardrail {
+                name: rule.name.clone().unwrap_or(format!("rule #{}", idx + 1)),
+                regex: rule.to_regex()?,
+                rule: rule.clone(),
+            }));
+        }
+        Ok(guardrails)
+    }
+
+    pub fn from_path(path: &str) -> Result<Self> {
+        let yaml = std::fs::read_to_string(path)
+            .map_err(|e| anyhow!("could not read guardrails from {}: {}", path, e))?;
+        let rules: Vec<GuardrailRule> = serde_yaml::from_str(&yaml)?;
+        Self::from_rules(&rules)
+    }
+
+    pub fn add(&mut self, guardrail: Box<dyn Guardrail>) {
+        self.0.push(guardrail);
+    }
+
+    pub fn extend(&mut self, other: Guardrails) {
+        self.0.extend(other.0);
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    // replace the first n guardrails with the given ones, returns how many they are
+    pub fn replace_first(&mut self, n: usize, guardrails: Guardrails) -> usize {
+        let len = guardrails.len();
+        self.0.splice(..n, guardrails.0);
+        len
+    }
+
+    // run every guardrail on the text, redacting it in place, the first block stops the inspection
+    async fn inspect(
+        &self,
+        state: &SharedState,
+        text: &mut String,
+        action: Option<&str>,
+        outcome: &mut Outcome,
+    ) -> Result<()> {
+        let target = if action.is_some() {
+            Target::Invocation
+        } else {
+            Target::Response
+        };
+
+        for guardrail in &self.0 {
+            if guardrail.target() != Target::Any && guardrail.target() != target {
+                continue;
+            }
+
+            match guardrail.check(state.clone(), text, action).await? {
+                Verdict::Allow => {}
+                Verdict::Block(reason) => {
+                    outcome.blocked = Some(format!("{}: {}", guardrail.name(), reason));
+                    return Ok(());
+                }
+                Verdict::Redact(redacted) => {
+                    *text = redacted;
+                    outcome.redacted = true;
+                }
+                Verdict::Confirm(reason) => {
+                    outcome.confirm = Some(format!("{}: {}", guardrail.name(), reason));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    pub async fn inspect_response(
+        &self,
+        state: &SharedState,
+        response: &mut String,
+    ) -> Result<Outcome> {
+        let mut outcome = Outcome::default();
+        self.inspect(state, response, None, &mut outcome).await?;
+        Ok(outcome)
+    }
+
+    // inspect payload and attributes of the invocation, redacting them in place
+    pub async fn inspect_invocation(
+        &self,
+        state: &SharedState,
+        invocation: &mut Invocation,
+    ) -> Result<Outcome> {
+        let mut outcome = Outcome::default();
+        let action = invocation.action.to_string();
+
+        if let Some(payload) = invocation.payload.as_mut() {
+            self.inspect(state, payload, Some(&action), &mut outcome)
+                .await?;
+        }
+
+        if let Some(attributes) = invocation.attributes.as_mut() {
+            let mut keys: Vec<String> = attributes.keys().cloned().collect();
+            keys.sort();
+            for key in keys {
+                if outcome.blocked.is_some() {
+                    break;
+                }
+                let value = attributes.get_mut(&key).unwrap();
+                self.inspect(state, value, Some(&action), &mut outcome)
+                    .await?;
+            }
+        }
+
+        Ok(outcome)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::agent::{
+        generator::{ChatResponse, SupportedFeatures},
+        testing::{new_shared_state, TestTask},
+    };
+
+    struct TestModerator {}
+
+    #[async_trait]
+    impl mini_rag::Embedder for TestModerator {
+        async fn embed(&self, _text: &str) -> Result<mini_rag::Embeddings> {
+            Err(anyhow!("embeddings are not supported in tests"))
+        }
+    }
+
+    #[async_trait]
+    impl Client for TestModerator {
+        fn new(_: &str, _: u16, _: &str, _: u32) -> Result<Self> {
+            Ok(Self {})
+        }
+
+        async fn check_supported_features(&self) -> Result<SupportedFeatures> {
+            Ok(SupportedFeatures::default())
+        }
+
+        async fn chat(&self, _: SharedState, options: &ChatOptions) -> Result<ChatResponse> {
+            Ok(ChatResponse {
+                content: if options.prompt.contains("dd if=") {
+                    "UNSAFE: wipes the disk".to_string()
+                } else {
+                    "SAFE".to_string()
+                },
+                invocations: vec![],
+                usage: None,
+            })
+        }
+    }
+
+    async fn test_state() -> SharedState {
+        new_shared_state(TestTask::default()).await
+    }
+
+    fn rules(yaml: &str) -> Guardrails {
+        let rules: Vec<GuardrailRule> = serde_yaml::from_str(yaml).unwrap();
+        Guardrails::from_rules(&rules).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_block_and_redact() {
+        let state = test_state().await;
+        let guardrails = rules(
+            r#"
+- name: no-rm
+  pattern: 'rm\s+-rf'
+  target: invocation
+  actions: [shell]
+- deny: [hunter2]
+  on_match: redact
+"#,
+        );
+
+        let mut inv = Invocation::new("shell".to_string(), None, Some("rm -rf /".to_string()));
+        let outcome = guardrails
+            .inspect_invocation(&state, &mut inv)
+            .await
+            .unwrap();
+        assert_eq!(outcome.blocked.unwrap(), "no-rm: 'rm -rf' is not allowed");
+
+        // different action
+        let mut inv = Invocation::new("echo".to_string(), None, Some("rm -rf /".to_string()));
+        let outcome = guardrails
+            .inspect_invocation(&state, &mut inv)
+            .await
+            .unwrap();
+        assert_eq!(outcome, Outcome::default());
+
+        let mut attributes = HashMap::new();
+        attributes.insert("password".to_string(), "HUNTER2".to_string());
+        let mut inv = Invocation::new("login".to_string(), Some(attributes), None);
+        let outcome = guardrails
+            .inspect_invocation(&state, &mut inv)
+            .await
+            .unwrap();
+        assert!(outcome.redacted);
+        assert_eq!(inv.attributes.unwrap().get("password").unwrap(), REDACTED);
+
+        // only the any target applies to responses
+        let mut response = "I'll run rm -rf / with hunter2".to_string();
+        let outcome = guardrails
+            .inspect_response(&state, &mut response)
+            .await
+            .unwrap();
+        assert!(outcome.blocked.is_none());
+        assert_eq!(response, "I'll run rm -rf / with [REDACTED]");
+    }
+
+    #[tokio::test]
+    async fn test_actions_only_apply_to_invocations() {
+        let state = test_state().await;
+        let guardrails = rules(
+            r#"
+- pattern: 'rm\s+-rf'
+  actions: [shell]
+- deny: [hunter2]
+  on_match: redact
+  actions: [login]
+"#,
+        );
+
+        // the rules limited to some actions don't inspect the responses
+        let mut response = "I'll run rm -rf / with hunter2".to_string();
+        let outcome = guardrails
+            .inspect_response(&state, &mut response)
+            .await
+            .unwrap();
+        assert_eq!(outcome, Outcome::default());
+        assert_eq!(response, "I'll run rm -rf / with hunter2");
+
+        let mut inv = Invocation::new("shell".to_string(), None, Some("rm -rf /".to_string()));
+        let outcome = guardrails
+            .inspect_invocation(&state, &mut inv)
+            .await
+            .unwrap();
+        assert!(outcome.blocked.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_moderation() {
+        let state = test_state().await;
+        let mut guardrails = Guardrails::default();
+        guardrails.add(Box::new(
+            ModerationGuardrail::new(Box::new(TestModerator {}), Enforcement::Confirm).unwrap(),
+        ));
+
+        let mut inv = Invocation::new("shell".to_string(), None, Some("ls".to_string()));
+        let outcome = guardrails
+            .inspect_invocation(&state, &mut inv)
+            .await
+            .unwrap();
+        assert!(outcome.confirm.is_none());
+
+        let mut inv = Invocation::new(
+            "shell".to_string(),
+            None,
+            Some("dd if=/dev/zero of=/dev/sda".to_string()),
+        );
+        let outcome = guardrails
+            .inspect_invocation(&state, &mut inv)
+            .await
+            .unwrap();
+        assert_eq!(outcome.confirm.unwrap(), "moderation: wipes the disk");
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(GuardrailRule::default().validate().is_err());
+        assert!(GuardrailRule {
+            pattern: Some("(".to_string()),
+            ..Default::default()
+        }
+        .validate()
+        .is_err());
+    }
+}
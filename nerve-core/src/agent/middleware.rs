@@ -0,0 +1,230 @@
+use anyhow::Result;
+use async_trait::async_trait;
+
+use super::{state::metrics::Metrics, Invocation};
+
+// what the middlewares know about the invocation being executed
+#[derive(Debug, Clone, Default)]
+pub struct ActionContext {
+    pub task: String,
+    pub namespace: Option<String>,
+    pub metrics: Metrics,
+    // appended to the result of the action as seen by the model
+    pub notes: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Verdict {
+    Proceed,
+    // don't execute the invocation, the reason is reported to the model as its error
+    Veto(String),
+}
+
+// extension point around the execution of every valid invocation, for custom policy engines,
+// telemetry and so on
+#[async_trait]
+pub trait Middleware: Send + Sync {
+    // inspect, change or veto the invocation before it's executed
+    async fn before_action(
+        &self,
+        _ctx: &mut ActionContext,
+        _invocation: &mut Invocation,
+    ) -> Result<Verdict> {
+        Ok(Verdict::Proceed)
+    }
+
+    // inspect or change the result of the executed invocation
+    async fn after_action(
+        &self,
+        _ctx: &mut ActionContext,
+        _invocation: &Invocation,
+        _result: &mut Result<Option<String>>,
+    ) -> Result<()> {
+        Ok(())
+    }
+}
+
+// called in the order they were added before the actions and in reverse order after them
+#[derive(Default)]
+pub struct Middlewares {
+    chain: Vec<Box<dyn Middleware>>,
+}
+
+impl Middlewares {
+    pub fn len(&self) -> usize {
+        self.chain.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.chain.is_empty()
+    }
+
+    pub fn push(&mut self, middleware: Box<dyn Middleware>) {
+        self.chain.push(middleware);
+    }
+
+    // replace the first n middlewares with the given ones, returns how many they are
+    pub fn replace_first(&mut self, n: usize, middlewares: Middlewares) -> usize {
+        let len = middlewares.chain.len();
+        self.chain.splice(..n, middlewares.chain);
+        len
+    }
+
+    // the first veto stops the chain, a failing middleware vetoes the invocation as well
+    pub async fn before_action(
+        &self,
+        ctx: &mut ActionContext,
+        invocation: &mut Invocation,
+    ) -> Verdict {
+        for middleware in &self.chain {
+            match middleware.before_action(ctx, invocation).await {
+                Ok(Verdict::Proceed) => {}
+                Ok(veto) => return veto,
+                Err(err) => return Verdict::Veto(err.to_string()),
+            }
+        }
+        Verdict::Proceed
+    }
+
+    // failures are only logged, the action has been executed already
+    pub async fn after_action(
+        &self,
+        ctx: &mut ActionContext,
+        invocation: &Invocation,
+        result: &mut Result<Option<String>>,
+    ) {
+        for middleware in self.chain.iter().rev() {
+            if let Err(err) = middleware.after_action(ctx, invocation, result).await {
+                log::warn!(
+                    "after_action middleware failed for {}: {}",
+                    invocation.as_function_call_string(),
+                    err
+                );
+            }
+        }
+
+        if !ctx.notes.is_empty() {
+            let notes = ctx
+                .notes
+                .iter()
+                .map(|note| format!("note: {}", note))
+                .collect::<Vec<_>>()
+                .join("\n");
+            *result = match std::mem::replace(result, Ok(None)) {
+                Ok(Some(output)) => Ok(Some(format!("{}\n\n{}", output, notes))),
+                Ok(None) => Ok(Some(notes)),
+                Err(err) => Err(anyhow!("{}\n\n{}", err, notes)),
+            };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Policy {}
+
+    #[async_trait]
+    impl Middleware for Policy {
+        async fn before_action(
+            &self,
+            ctx: &mut ActionContext,
+            invocation: &mut Invocation,
+        ) -> Result<Verdict> {
+            match invocation.payload.as_deref() {
+                Some("rm -rf /") => Ok(Verdict::Veto("not on my watch".to_string())),
+                Some("fail") => Err(anyhow!("policy engine unreachable")),
+                _ => {
+                    invocation.payload =
+                        invocation.payload.as_ref().map(|p| p.replace("-A", "-sV"));
+                    ctx.notes
+                        .push(format!("checked at step {}", ctx.metrics.current_step));
+                    Ok(Verdict::Proceed)
+                }
+            }
+        }
+    }
+
+    struct Telemetry {
+        tag: &'static str,
+    }
+
+    #[async_trait]
+    impl Middleware for Telemetry {
+        async fn after_action(
+            &self,
+            _ctx: &mut ActionContext,
+            _invocation: &Invocation,
+            result: &mut Result<Option<String>>,
+        ) -> Result<()> {
+            if let Ok(Some(output)) = result {
+                output.push_str(self.tag);
+            }
+            Ok(())
+        }
+    }
+
+    fn invocation(payload: &str) -> Invocation {
+        Invocation::new("shell".to_string(), None, Some(payload.to_string()))
+    }
+
+    #[tokio::test]
+    async fn test_before_action() {
+        let mut middlewares = Middlewares::default();
+        middlewares.push(Box::new(Policy {}));
+
+        let mut ctx = ActionContext::default();
+        ctx.metrics.current_step = 3;
+        let mut inv = invocation("nmap -A 10.0.0.1");
+        assert_eq!(
+            middlewares.before_action(&mut ctx, &mut inv).await,
+            Verdict::Proceed
+        );
+        assert_eq!(inv.payload.as_deref(), Some("nmap -sV 10.0.0.1"));
+        assert_eq!(ctx.notes, ["checked at step 3"]);
+
+        let mut ctx = ActionContext::default();
+        assert_eq!(
+            middlewares
+                .before_action(&mut ctx, &mut invocation("rm -rf /"))
+                .await,
+            Verdict::Veto("not on my watch".to_string())
+        );
+        assert_eq!(
+            middlewares
+                .before_action(&mut ctx, &mut invocation("fail"))
+                .await,
+            Verdict::Veto("policy engine unreachable".to_string())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_after_action() {
+        let mut middlewares = Middlewares::default();
+        middlewares.push(Box::new(Telemetry { tag: " [1]" }));
+        middlewares.push(Box::new(Telemetry { tag: " [2]" }));
+
+        let inv = invocation("ls");
+        let mut ctx = ActionContext::default();
+        let mut result = Ok(Some("files".to_string()));
+        middlewares.after_action(&mut ctx, &inv, &mut result).await;
+        // in reverse order
+        assert_eq!(result.unwrap().as_deref(), Some("files [2] [1]"));
+
+        ctx.notes.push("seen by the blue team".to_string());
+        let mut result = Ok(None);
+        middlewares.after_action(&mut ctx, &inv, &mut result).await;
+        assert_eq!(
+            result.unwrap().as_deref(),
+            Some("note: seen by the blue team")
+        );
+
+        let mut result = Err(anyhow!("permission denied"));
+        middlewares.after_action(&mut ctx, &inv, &mut result).await;
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "permission denied\n\nnote: seen by the blue team"
+        );
+    }
+}
@@ -0,0 +1,1976 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    io::{self, Write},
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, OnceLock,
+    },
+    time::Duration,
+};
+
+use anyhow::Result;
+use mini_rag::Embedder;
+use serde::{Deserialize, Serialize};
+
+use crate::prompt;
+
+use abort::{AbortReason, Aborted};
+use audit::{Approval, AuditLog};
+use breaker::CircuitBreaker;
+use candidates::Candidate;
+use confirm::{ConfirmPolicy, Decision, StepDecision};
+use dedup::Deduplicator;
+use events::Event;
+use generator::{
+    history::{ChatHistory, ConversationWindow},
+    pricing::TokenPrices,
+    ChatOptions, ChatResponse, Client, Sampling,
+};
+use guardrails::Guardrails;
+use middleware::{ActionContext, Middleware, Middlewares, Verdict};
+use namespaces::Action;
+use pinned::PinnedOutputs;
+use ratelimit::RateLimit;
+#[cfg(feature = "sqlite")]
+use state::database::Database;
+use state::{
+    session::{Checkpoint, Session},
+    SharedState, State,
+};
+use task::{hooks::Hooks, report::ReportTemplate, Task};
+
+pub mod abort;
+pub mod access;
+pub mod audit;
+pub mod breaker;
+pub mod builder;
+pub mod candidates;
+pub mod cassette;
+pub mod confirm;
+pub mod dedup;
+pub mod events;
+pub mod generator;
+pub mod guardrails;
+pub mod middleware;
+pub mod namespaces;
+pub mod pinned;
+pub mod policy;
+pub mod prompts;
+pub mod ratelimit;
+pub mod scheduler;
+pub mod secrets;
+pub mod serialization;
+pub mod state;
+pub mod streaming;
+pub mod task;
+#[cfg(any(test, feature = "test-util"))]
+pub mod test_env;
+#[cfg(test)]
+pub(crate) mod testing;
+
+type UserInputHook = Box<dyn Fn(bool) + Send + Sync>;
+
+static USER_INPUT_HOOK: OnceLock<UserInputHook> = OnceLock::new();
+
+// the timeout set by the task for the action wins over the default of the action, which wins
+// over the timeout of the task
+fn action_timeout(
+    task_action: Option<Duration>,
+    action: Option<Duration>,
+    task: Option<Duration>,
+) -> Duration {
+    task_action
+        .or(action)
+        .or(task)
+        // one month by default :D
+        .unwrap_or(Duration::from_secs(60 * 60 * 24 * 30))
+}
+
+// called with true before the user is asked for input and with false once answered, so that a
+// full screen interface can give the terminal back in the meantime
+pub fn set_user_input_hook(hook: UserInputHook) {
+    let _ = USER_INPUT_HOOK.set(hook);
+}
+
+pub fn get_user_input(prompt: &str) -> String {
+    if let Some(hook) = USER_INPUT_HOOK.get() {
+        hook(true);
+    }
+
+    print!("\n{}", prompt);
+    let _ = io::stdout().flush();
+
+    let mut input = String::new();
+    match io::stdin().read_line(&mut input) {
+        Ok(_goes_into_input_above) => {}
+        Err(_no_updates_is_fine) => {}
+    }
+    println!();
+
+    if let Some(hook) = USER_INPUT_HOOK.get() {
+        hook(false);
+    }
+    input.trim().to_string()
+}
+
+// read user input without echoing it to the terminal
+#[cfg(unix)]
+pub fn get_user_secret_input(prompt: &str) -> String {
+    let fd = libc::STDIN_FILENO;
+    let mut term: libc::termios = unsafe { std::mem::zeroed() };
+    let is_tty = unsafe { libc::tcgetattr(fd, &mut term) } == 0;
+
+    if is_tty {
+        let mut hidden = term;
+        hidden.c_lflag &= !libc::ECHO;
+        hidden.c_lflag |= libc::ECHONL;
+        unsafe { libc::tcsetattr(fd, libc::TCSANOW, &hidden) };
+    }
+
+    let input = get_user_input(prompt);
+
+    if is_tty {
+        unsafe { libc::tcsetattr(fd, libc::TCSANOW, &term) };
+    }
+
+    input
+}
+
+#[cfg(windows)]
+pub fn get_user_secret_input(prompt: &str) -> String {
+    use windows_sys::Win32::System::Console::{
+        GetConsoleMode, GetStdHandle, SetConsoleMode, ENABLE_ECHO_INPUT, STD_INPUT_HANDLE,
+    };
+
+    let handle = unsafe { GetStdHandle(STD_INPUT_HANDLE) };
+    let mut mode = 0;
+    let is_console = unsafe { GetConsoleMode(handle, &mut mode) } != 0;
+
+    if is_console {
+        unsafe { SetConsoleMode(handle, mode & !ENABLE_ECHO_INPUT) };
+    }
+
+    let input = get_user_input(prompt);
+
+    if is_console {
+        unsafe { SetConsoleMode(handle, mode) };
+    }
+
+    input
+}
+
+#[cfg(not(any(unix, windows)))]
+pub fn get_user_secret_input(prompt: &str) -> String {
+    get_user_input(prompt)
+}
+
+pub fn data_path(path: &str) -> Result<PathBuf> {
+    let user_home = match simple_home_dir::home_dir() {
+        Some(path) => path,
+        None => return Err(anyhow!("can't get user home folder")),
+    };
+
+    let inner_path = user_home.join(".nerve").join(path);
+    if !inner_path.exists() {
+        log::info!("creating {} ...", inner_path.display());
+        std::fs::create_dir_all(&inner_path)?;
+    }
+
+    Ok(inner_path)
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Invocation {
+    pub action: String,
+    pub attributes: Option<HashMap<String, String>>,
+    pub payload: Option<String>,
+}
+
+impl std::hash::Hash for Invocation {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.action.hash(state);
+        if let Some(attributes) = &self.attributes {
+            let mut attributes: Vec<_> = attributes.iter().collect();
+            attributes.sort();
+            attributes.hash(state);
+        }
+        self.payload.hash(state);
+    }
+}
+
+impl Invocation {
+    pub fn new(
+        action: String,
+        attributes: Option<HashMap<String, String>>,
+        payload: Option<String>,
+    ) -> Self {
+        Self {
+            action,
+            attributes,
+            payload,
+        }
+    }
+
+    // mask the registered secrets in payload and attributes
+    pub fn redacted(&self) -> Self {
+        Self {
+            action: self.action.to_string(),
+            attributes: self.attributes.as_ref().map(|attrs| {
+                attrs
+                    .iter()
+                    .map(|(k, v)| (k.to_string(), secrets::redact(v)))
+                    .collect()
+            }),
+            payload: self.payload.as_deref().map(secrets::redact),
+        }
+    }
+
+    pub fn as_function_call_string(&self) -> String {
+        let mut parts = vec![];
+
+        if let Some(payload) = &self.payload {
+            parts.push(payload.to_owned());
+        }
+
+        if let Some(attributes) = &self.attributes {
+            let mut attributes: Vec<_> = attributes.iter().collect();
+            attributes.sort();
+            for (name, value) in attributes {
+                parts.push(format!("{}={}", name, value))
+            }
+        }
+
+        format!("{}({})", &self.action, parts.join(", "))
+    }
+}
+
+pub struct Agent {
+    events_chan: events::Sender,
+    generator: Box<dyn Client>,
+    state: SharedState,
+    task_timeout: Option<Duration>,
+    hooks: Hooks,
+    conversation_window: ConversationWindow,
+
+    serializer: serialization::Strategy,
+    use_native_tools_format: bool,
+    user_only: bool,
+
+    // optional checkpoint saved at every step
+    checkpoint: Option<Checkpoint>,
+    // where to save the session if the run is interrupted and there's no checkpoint
+    interrupt_checkpoint: Option<Checkpoint>,
+    // optional database recording history, storages and metrics of the run
+    #[cfg(feature = "sqlite")]
+    database: Option<Arc<Database>>,
+    // optional hash chained log of the executed invocations and the tasklet they belong to
+    audit: Option<(Arc<AuditLog>, String)>,
+    // set to stop the run after the current step
+    stop: Arc<AtomicBool>,
+    // let the user inspect and alter every invocation before it runs
+    step_mode: bool,
+    // which actions need the operator approval
+    confirm_policy: ConfirmPolicy,
+    // summarize older executions once the history exceeds this many tokens
+    summarize_after: Option<usize>,
+    // number of recent executions that are never summarized
+    summarize_keep: usize,
+    // collapse the outputs that are near duplicates of earlier ones
+    dedup: Option<Deduplicator>,
+    // ask the model to reflect on its progress every N steps
+    reflect_every: Option<usize>,
+    // number of samples of the final answer to vote on
+    consistency: Option<usize>,
+    // used to estimate the cost of the run
+    token_prices: Option<TokenPrices>,
+    // what to do after too many consecutive errors
+    breaker: Option<CircuitBreaker>,
+    // generator to switch to when the breaker trips
+    fallback: Option<Box<dyn Client>>,
+    // reflect at the next step regardless of the interval
+    force_reflection: bool,
+    // sampling parameters for the generator
+    sampling: Sampling,
+    // responses sampled at every step, the most promising one is executed
+    candidates: usize,
+    // outputs recorded in a previous run to use instead of executing the actions
+    pinned: Option<PinnedOutputs>,
+    // inspect responses and invocations before they are executed
+    guardrails: Guardrails,
+    // how many of the guardrails are the ones of the task, they come first
+    task_guardrails: usize,
+    // called around the execution of every valid invocation
+    middlewares: Middlewares,
+    // how many of the middlewares are the hooks of the task, they come first
+    task_middlewares: usize,
+    // limits shared by the actions tagged with them, across the agents of the process
+    rate_limits: HashMap<String, RateLimit>,
+    // mask the registered secrets in what is sent to the generator
+    redact_prompts: bool,
+}
+
+impl Agent {
+    #[allow(clippy::too_many_arguments)]
+    pub async fn new(
+        events_chan: events::Sender,
+        generator: Box<dyn Client>,
+        embedder: Box<dyn Embedder>,
+        task: Box<dyn Task>,
+        serializer: serialization::Strategy,
+        conversation_window: ConversationWindow,
+        force_strategy: bool,
+        user_only: bool,
+        max_iterations: usize,
+    ) -> Result<Self> {
+        // check if the model supports tools calling and system prompt natively
+        let supported_features = generator
+            .check_supported_features()
+            .await
+            .map_err(|e| Aborted::new(AbortReason::Generator, e))?;
+
+        let use_native_tools_format = if force_strategy {
+            log::info!("using {:?} serialization strategy", &serializer);
+            false
+        } else {
+            match supported_features.tools {
+                true => {
+                    log::debug!("model supports tools calling natively.");
+                    true
+                }
+                false => {
+                    log::info!("model does not support tools calling natively, using Nerve custom system prompt");
+                    false
+                }
+            }
+        };
+
+        let user_only = if !user_only && !supported_features.system_prompt {
+            log::info!("model does not support system prompt, forcing user prompt");
+            true
+        } else {
+            // leave whatever the user set
+            user_only
+        };
+
+        let task_timeout = task.get_timeout();
+        let reflect_every = task.get_reflection_interval().filter(|n| *n > 0);
+        let consistency = task.get_evaluation().and_then(|e| e.samples());
+        let hooks = task.hooks();
+        let middlewares = Self::task_middlewares(&hooks);
+        let task_middlewares = middlewares.len();
+        let guardrails = Guardrails::from_rules(&task.get_guardrails())?;
+        let task_guardrails = guardrails.len();
+        let rate_limits = task.get_rate_limits();
+        for (tag, limit) in &rate_limits {
+            ratelimit::configure(tag, limit)?;
+        }
+        let state = Arc::new(tokio::sync::Mutex::new(
+            State::new(
+                events_chan.clone(),
+                task,
+                embedder,
+                max_iterations,
+                use_native_tools_format,
+            )
+            .await?,
+        ));
+
+        Ok(Self {
+            events_chan,
+            generator,
+            state,
+            task_timeout,
+            hooks,
+            use_native_tools_format,
+            user_only,
+            serializer,
+            conversation_window,
+            checkpoint: None,
+            interrupt_checkpoint: None,
+            #[cfg(feature = "sqlite")]
+            database: None,
+            audit: None,
+            stop: Arc::new(AtomicBool::new(false)),
+            step_mode: false,
+            confirm_policy: ConfirmPolicy::default(),
+            summarize_after: None,
+            summarize_keep: 4,
+            dedup: None,
+            reflect_every,
+            consistency,
+            token_prices: None,
+            breaker: None,
+            fallback: None,
+            force_reflection: false,
+            sampling: Sampling::default(),
+            candidates: 1,
+            pinned: None,
+            guardrails,
+            task_guardrails,
+            middlewares,
+            task_middlewares,
+            rate_limits,
+            redact_prompts: false,
+        })
+    }
+
+    // the hooks of the task are the first middlewares, if they handle the actions
+    fn task_middlewares(hooks: &Hooks) -> Middlewares {
+        let mut middlewares = Middlewares::default();
+        if hooks.has_action_hooks() {
+            middlewares.push(Box::new(hooks.clone()));
+        }
+        middlewares
+    }
+
+    pub fn set_confirm_policy(&mut self, policy: ConfirmPolicy) {
+        self.confirm_policy = policy;
+    }
+
+    pub fn set_summarization(&mut self, max_tokens: usize, keep: usize) {
+        self.summarize_after = if max_tokens > 0 {
+            Some(max_tokens)
+        } else {
+            None
+        };
+        self.summarize_keep = keep;
+    }
+
+    // outputs whose embeddings are at least this similar to an earlier one are collapsed
+    pub fn set_deduplication(&mut self, threshold: f64) {
+        self.dedup = Some(Deduplicator::new(threshold));
+    }
+
+    // override the reflection interval of the task, 0 keeps the task one
+    pub fn set_reflection_interval(&mut self, every: usize) {
+        if every > 0 {
+            self.reflect_every = Some(every);
+        }
+    }
+
+    pub async fn set_budget(
+        &mut self,
+        max_time: Option<Duration>,
+        max_cost: Option<f64>,
+        token_prices: Option<TokenPrices>,
+    ) {
+        let mut state = self.state.lock().await;
+        if let Some(max_time) = max_time {
+            state.set_max_duration(max_time);
+        }
+        if let Some(max_cost) = max_cost {
+            state.set_max_cost(max_cost);
+        }
+        self.token_prices = token_prices;
+    }
+
+    pub fn set_circuit_breaker(
+        &mut self,
+        breaker: CircuitBreaker,
+        fallback: Option<Box<dyn Client>>,
+    ) {
+        self.breaker = Some(breaker);
+        self.fallback = fallback;
+    }
+
+    // check the consecutive errors and decide whether to keep going
+    async fn check_circuit_breaker(&mut self) -> Result<()> {
+        let breaker = if let Some(breaker) = self.breaker.as_mut() {
+            breaker
+        } else {
+            return Ok(());
+        };
+
+        let (errors, successes) = {
+            let metrics = &self.state.lock().await.metrics;
+            (metrics.consecutive_errors, metrics.success_actions)
+        };
+
+        let decision = breaker.check(errors, successes);
+        if decision == breaker::Decision::Continue {
+            return Ok(());
+        }
+
+        log::warn!("{} consecutive errors", errors);
+
+        match decision {
+            breaker::Decision::Reflect => {
+                self.force_reflection = true;
+            }
+            // one way only, the primary generator already failed so the next trip aborts
+            breaker::Decision::Fallback if self.fallback.is_some() => {
+                log::warn!("switching generator ...");
+                self.generator = self.fallback.take().unwrap();
+            }
+            _ => {
+                self.on_event(Event::TooManyErrors(errors))?;
+                return Err(Aborted::new(
+                    AbortReason::TooManyErrors,
+                    format!("aborting after {} consecutive errors", errors),
+                )
+                .into());
+            }
+        }
+
+        // give the recovery action a chance
+        self.state.lock().await.metrics.consecutive_errors = 0;
+
+        Ok(())
+    }
+
+    // after the before_action and after_action hooks of the task, if any
+    pub fn add_middleware(&mut self, middleware: Box<dyn Middleware>) {
+        self.middlewares.push(middleware);
+    }
+
+    // the configured rate limits the action counts against, by its own tags or by name
+    #[allow(clippy::borrowed_box)]
+    fn rate_limit_tags(&self, action: &Box<dyn Action>) -> Vec<String> {
+        let mut tags: Vec<String> = action
+            .rate_limits()
+            .into_iter()
+            .filter(|tag| ratelimit::is_configured(tag))
+            .collect();
+        for (tag, limit) in &self.rate_limits {
+            if !tags.contains(tag) && limit.actions.iter().any(|name| name == action.name()) {
+                tags.push(tag.to_string());
+            }
+        }
+        tags.sort();
+        tags
+    }
+
+    pub fn set_candidates(&mut self, candidates: usize) {
+        self.candidates = candidates.max(1);
+    }
+
+    pub fn set_sampling(&mut self, sampling: Sampling) {
+        self.sampling = sampling;
+    }
+
+    pub fn set_pinned_outputs(&mut self, pinned: PinnedOutputs) {
+        log::info!("pinned {} recorded outputs", pinned.len());
+        self.pinned = Some(pinned);
+    }
+
+    pub fn set_prompt_redaction(&mut self, enabled: bool) {
+        self.redact_prompts = enabled;
+    }
+
+    // add to the guardrails of the task
+    pub fn add_guardrails(&mut self, guardrails: Guardrails) {
+        self.guardrails.extend(guardrails);
+    }
+
+    pub fn set_checkpoint(&mut self, checkpoint: Checkpoint) {
+        self.checkpoint = Some(checkpoint);
+    }
+
+    pub fn set_step_mode(&mut self, enabled: bool) {
+        self.step_mode = enabled;
+    }
+
+    pub fn set_interrupt_checkpoint(&mut self, checkpoint: Checkpoint) {
+        self.interrupt_checkpoint = Some(checkpoint);
+    }
+
+    // the events must also be recorded with Database::record_events
+    #[cfg(feature = "sqlite")]
+    pub fn set_database(&mut self, database: Arc<Database>) {
+        self.database = Some(database);
+    }
+
+    pub fn set_audit_log(&mut self, log: Arc<AuditLog>, tasklet: &str) {
+        self.audit = Some((log, tasklet.to_string()));
+    }
+
+    // setting this makes the agent stop after the current step
+    pub fn stop_handle(&self) -> Arc<AtomicBool> {
+        self.stop.clone()
+    }
+
+    fn is_stopped(&self) -> bool {
+        self.stop.load(Ordering::SeqCst)
+    }
+
+    // save the session so that the interrupted run can be resumed
+    pub async fn on_interrupted(&self) {
+        let checkpoint = self
+            .checkpoint
+            .as_ref()
+            .or(self.interrupt_checkpoint.as_ref());
+        let saved = if let Some(checkpoint) = checkpoint {
+            self.save_checkpoint_to(checkpoint)
+                .await
+                .then(|| checkpoint.path.display().to_string())
+        } else {
+            None
+        };
+
+        self.on_event(Event::Interrupted { checkpoint: saved })
+            .unwrap();
+    }
+
+    // snapshot of the run
+    pub async fn to_session(&self) -> Result<Session> {
+        let checkpoint = self
+            .checkpoint
+            .as_ref()
+            .or(self.interrupt_checkpoint.as_ref())
+            .ok_or_else(|| anyhow!("no tasklet and generator to describe the session"))?;
+
+        self.state.lock().await.to_session(
+            &checkpoint.tasklet,
+            &checkpoint.generator,
+            checkpoint.created_at,
+        )
+    }
+
+    // restore history, storages and metrics from a previous session
+    pub async fn resume(&mut self, session: &Session) -> Result<()> {
+        self.state.lock().await.restore_session(session)?;
+
+        log::info!(
+            "resuming session from step {} ({} executions)",
+            session.metrics.current_step,
+            session.history.len()
+        );
+
+        Ok(())
+    }
+
+    async fn save_checkpoint(&self) {
+        if let Some(checkpoint) = &self.checkpoint {
+            self.save_checkpoint_to(checkpoint).await;
+        }
+
+        #[cfg(feature = "sqlite")]
+        if let Some(db) = &self.database {
+            let ret = self
+                .state
+                .lock()
+                .await
+                .to_session(&db.tasklet, &db.generator, db.created_at)
+                .and_then(|session| db.save_session(&session));
+            if let Err(err) = ret {
+                log::error!("could not save the session to the database: {}", err);
+            }
+        }
+    }
+
+    async fn save_checkpoint_to(&self, checkpoint: &Checkpoint) -> bool {
+        let ret = self
+            .state
+            .lock()
+            .await
+            .to_session(
+                &checkpoint.tasklet,
+                &checkpoint.generator,
+                checkpoint.created_at,
+            )
+            .and_then(|session| session.save(&checkpoint.path));
+
+        if let Err(err) = ret {
+            log::error!(
+                "could not save checkpoint to {}: {}",
+                checkpoint.path.display(),
+                err
+            );
+            false
+        } else {
+            true
+        }
+    }
+
+    #[allow(clippy::borrowed_box)]
+    pub fn validate(&self, invocation: &mut Invocation, action: &Box<dyn Action>) -> Result<()> {
+        // validate prerequisites
+        let payload_required = action.example_payload().is_some();
+        let attrs_required = action.example_attributes().is_some();
+        let mut has_payload = invocation.payload.is_some();
+        let mut has_attributes = invocation.attributes.is_some();
+
+        // sometimes when the tool expects a json payload, the model returns it as separate arguments
+        // in this case we need to convert it back to a single json string
+        if (payload_required && !has_payload) && (!attrs_required && has_attributes) {
+            log::warn!(
+                "model returned the payload as separate arguments, converting back to payload"
+            );
+            // sorted for reproducible payloads
+            let attributes: Option<BTreeMap<_, _>> = invocation
+                .attributes
+                .as_ref()
+                .map(|attrs| attrs.iter().collect());
+            invocation.payload = Some(serde_json::to_string(&attributes).unwrap());
+            invocation.attributes = None;
+            has_payload = true;
+            has_attributes = false;
+        }
+
+        if payload_required && !has_payload {
+            // payload required and not specified
+            return Err(anyhow!("no content specified for '{}'", invocation.action));
+        } else if attrs_required && !has_attributes {
+            // attributes required and not specified at all
+            return Err(anyhow!(
+                "no attributes specified for '{}'",
+                invocation.action
+            ));
+        } else if !payload_required && has_payload {
+            // payload not required but specified
+            return Err(anyhow!("no content needed for '{}'", invocation.action));
+        } else if !attrs_required && has_attributes {
+            // attributes not required but specified
+            return Err(anyhow!("no attributes needed for '{}'", invocation.action));
+        }
+
+        if attrs_required {
+            // validate each required attribute
+            let required_attrs: Vec<String> = action
+                .example_attributes()
+                .unwrap()
+                .keys()
+                .map(|s| s.to_owned())
+                .collect();
+            let passed_attrs: Vec<String> = invocation
+                .clone()
+                .attributes
+                .unwrap()
+                .keys()
+                .map(|s| s.to_owned())
+                .collect();
+
+            for required in required_attrs {
+                if !passed_attrs.contains(&required) {
+                    return Err(anyhow!(
+                        "no '{}' attribute specified for '{}'",
+                        required,
+                        invocation.action
+                    ));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    // an invocation edited by the user must still be valid for the action it was approved for
+    #[allow(clippy::borrowed_box)]
+    fn validate_edited(&self, invocation: &mut Invocation, action: &Box<dyn Action>) -> Result<()> {
+        if invocation.action != action.name() {
+            return Err(anyhow!(
+                "the action can't be changed from {} to {}",
+                action.name(),
+                invocation.action
+            ));
+        }
+        self.validate(invocation, action)
+    }
+
+    pub async fn is_done(&self) -> bool {
+        self.state.lock().await.is_complete()
+    }
+
+    async fn on_state_update(&self, options: &ChatOptions, refresh: bool) -> Result<()> {
+        let mut opts = options.clone();
+        if refresh {
+            opts.system_prompt = Some(
+                self.serializer
+                    .system_prompt_for_state(&*self.state.lock().await)?,
+            );
+
+            let state = self.state.lock().await;
+            let examples = state.examples_to_chat_history(&self.serializer)?;
+            let messages = state.to_chat_history(&self.serializer)?;
+
+            opts.history =
+                ChatHistory::create(messages, self.conversation_window).with_examples(examples);
+        }
+
+        self.on_event(events::Event::StateUpdate(opts))
+    }
+
+    // TODO: move these feedback strings to a common place
+
+    async fn on_empty_response(&self) {
+        let mut mut_state = self.state.lock().await;
+        mut_state.metrics.errors.empty_responses += 1;
+        mut_state.metrics.consecutive_errors += 1;
+        mut_state
+            .add_unparsed_response_to_history("", "Do not return an empty responses.".to_string());
+
+        self.on_event(events::Event::EmptyResponse).unwrap();
+    }
+
+    async fn on_invalid_response(&self, response: &str) {
+        let mut mut_state = self.state.lock().await;
+        mut_state.metrics.errors.unparsed_responses += 1;
+        mut_state.metrics.consecutive_errors += 1;
+        mut_state.add_unparsed_response_to_history(
+        response,
+        "I could not parse any valid actions from your response, please correct it according to the instructions.".to_string(),
+    );
+        self.on_event(events::Event::InvalidResponse(response.to_string()))
+            .unwrap();
+    }
+
+    async fn on_blocked_response(&self, response: &str, reason: String) {
+        let mut mut_state = self.state.lock().await;
+        mut_state.metrics.consecutive_errors += 1;
+        mut_state.add_unparsed_response_to_history(
+            response,
+            format!("Your response was blocked by guardrail {}.", reason),
+        );
+        self.on_guardrail(None, reason).unwrap();
+    }
+
+    fn on_guardrail(&self, invocation: Option<Invocation>, outcome: String) -> Result<()> {
+        self.on_event(Event::GuardrailTriggered {
+            invocation,
+            outcome,
+        })
+    }
+
+    async fn on_valid_response(&self) {
+        self.state.lock().await.metrics.valid_responses += 1;
+    }
+
+    async fn on_invalid_action(&self, invocation: Invocation, error: Option<String>) {
+        let mut mut_state = self.state.lock().await;
+        mut_state.metrics.errors.unknown_actions += 1;
+        mut_state.metrics.consecutive_errors += 1;
+        // tell the model that the action name is wrong
+        let name = invocation.action.clone();
+
+        mut_state.add_error_to_history(
+            invocation.clone(),
+            error
+                .clone()
+                .unwrap_or(format!("'{name}' is not a valid action name")),
+        );
+
+        self.on_event(events::Event::InvalidAction { invocation, error })
+            .unwrap();
+    }
+
+    async fn on_valid_action(&self) {
+        self.state.lock().await.metrics.valid_actions += 1;
+    }
+
+    async fn audit(
+        &self,
+        invocation: &Invocation,
+        approval: Approval,
+        outcome: audit::Outcome<'_>,
+        elapsed: Duration,
+    ) {
+        if let Some((log, tasklet)) = &self.audit {
+            let (step, namespace) = {
+                let state = self.state.lock().await;
+                (
+                    state.metrics.current_step,
+                    state.get_action_namespace(&invocation.action),
+                )
+            };
+            if let Err(err) = log.record(
+                tasklet, step, namespace, invocation, approval, outcome, elapsed,
+            ) {
+                log::error!(
+                    "could not write to the audit log {}: {}",
+                    log.path().display(),
+                    err
+                );
+            }
+        }
+    }
+
+    async fn on_timed_out_action(
+        &self,
+        invocation: Invocation,
+        approval: Approval,
+        start: &std::time::Instant,
+    ) {
+        self.audit(
+            &invocation,
+            approval,
+            audit::Outcome::TimedOut,
+            start.elapsed(),
+        )
+        .await;
+
+        let mut mut_state = self.state.lock().await;
+        mut_state.metrics.errors.timedout_actions += 1;
+        mut_state.metrics.consecutive_errors += 1;
+        // tell the model about the timeout
+        mut_state.add_error_to_history(invocation.clone(), "action timed out".to_string());
+        mut_state.set_last_execution_elapsed(start.elapsed());
+        mut_state.metrics.throughput.on_action(start.elapsed());
+
+        self.events_chan
+            .send(events::Event::ActionTimeout {
+                invocation,
+                elapsed: start.elapsed(),
+            })
+            .unwrap();
+    }
+
+    #[allow(clippy::borrowed_box)]
+    async fn on_executed_action(
+        &self,
+        action: &Box<dyn Action>,
+        invocation: Invocation,
+        ret: Result<Option<String>>,
+        approval: Approval,
+        start: &std::time::Instant,
+    ) {
+        let outcome = match approval {
+            Approval::Rejected | Approval::Blocked => audit::Outcome::NotExecuted,
+            // the result was typed by the operator, the action never ran
+            Approval::Injected => audit::Outcome::Injected(&ret),
+            _ => audit::Outcome::Executed(&ret),
+        };
+        self.audit(&invocation, approval, outcome, start.elapsed())
+            .await;
+
+        let mut mut_state = self.state.lock().await;
+        let mut error = None;
+        let mut result = None;
+
+        let namespace = mut_state
+            .get_action_namespace(action.name())
+            .unwrap_or_default();
+        *mut_state
+            .metrics
+            .executed_actions
+            .entry(namespace)
+            .or_default() += 1;
+
+        if let Err(err) = ret {
+            mut_state.metrics.errors.errored_actions += 1;
+            mut_state.metrics.consecutive_errors += 1;
+            // tell the model about the error
+            mut_state.add_error_to_history(invocation.clone(), err.to_string());
+
+            error = Some(err.to_string());
+        } else {
+            let ret = ret.unwrap();
+            mut_state.metrics.success_actions += 1;
+            mut_state.metrics.consecutive_errors = 0;
+            // tell the model about the output
+            mut_state.add_success_to_history(invocation.clone(), ret.clone());
+
+            result = ret;
+        }
+
+        mut_state.set_last_execution_elapsed(start.elapsed());
+        mut_state.metrics.throughput.on_action(start.elapsed());
+
+        self.on_event(events::Event::ActionExecuted {
+            invocation,
+            result,
+            error,
+            elapsed: start.elapsed(),
+            complete_task: action.complete_task(),
+        })
+        .unwrap();
+    }
+
+    async fn on_completion(&self, response: &ChatResponse, elapsed: std::time::Duration) {
+        let mut mut_state = self.state.lock().await;
+        mut_state.metrics.throughput.on_generation(
+            elapsed,
+            response.usage.as_ref().map_or(0, |u| u.output_tokens),
+        );
+
+        // update tokens usage if available from the generator
+        if let Some(usage) = &response.usage {
+            mut_state.metrics.usage.last_input_tokens = usage.input_tokens;
+            mut_state.metrics.usage.last_output_tokens = usage.output_tokens;
+            mut_state.metrics.usage.total_input_tokens += usage.input_tokens;
+            mut_state.metrics.usage.total_output_tokens += usage.output_tokens;
+            if let Some(prices) = &self.token_prices {
+                mut_state.metrics.cost += prices.cost(usage.input_tokens, usage.output_tokens);
+            }
+        }
+    }
+
+    pub async fn get_metrics(&self) -> state::metrics::Metrics {
+        self.state.lock().await.metrics.clone()
+    }
+
+    // replace the outputs of the last steps that are near duplicates of earlier ones with a reference
+    async fn deduplicate_history(&mut self) -> Result<()> {
+        let dedup = if let Some(dedup) = self.dedup.as_mut() {
+            dedup
+        } else {
+            return Ok(());
+        };
+
+        let (embedder, to_check, current_step) = {
+            let state = self.state.lock().await;
+            let to_check: Vec<(usize, usize, String)> = state
+                .get_history()
+                .iter()
+                .enumerate()
+                .filter(|(_, exec)| {
+                    !exec.is_summary()
+                        && exec.step() > dedup.checked_step()
+                        && exec.duplicate_of().is_none()
+                        && exec.error().is_none()
+                })
+                .filter_map(|(idx, exec)| {
+                    exec.result()
+                        .filter(|result| result.len() >= dedup::MIN_SIZE)
+                        .map(|result| (idx, exec.step(), result.to_string()))
+                })
+                .collect();
+            (state.get_embedder(), to_check, state.metrics.current_step)
+        };
+        // never checked twice, even if the embedder fails
+        dedup.set_checked_step(current_step);
+
+        for (idx, step, result) in to_check {
+            let embeddings = embedder.embed(&result).await?;
+            if let Some(original) = dedup.check(step, embeddings) {
+                log::debug!(
+                    "output of step {} is a duplicate of step {}, collapsing it",
+                    step,
+                    original
+                );
+                self.state.lock().await.set_duplicate_output(idx, original);
+            }
+        }
+
+        Ok(())
+    }
+
+    // compress older executions into a model generated summary if the history is over budget
+    async fn summarize_history(&self) -> Result<()> {
+        let max_tokens = if let Some(max_tokens) = self.summarize_after {
+            max_tokens
+        } else {
+            return Ok(());
+        };
+
+        let (prompt, to_summarize) = {
+            let state = self.state.lock().await;
+            let history = state.get_history();
+            let tokens = history.tokens(&self.serializer);
+            if tokens <= max_tokens || history.len() <= self.summarize_keep {
+                return Ok(());
+            }
+
+            log::info!(
+                "history is {} tokens (budget {}), summarizing {} executions ...",
+                tokens,
+                max_tokens,
+                history.len() - self.summarize_keep
+            );
+
+            let to_summarize = history.len() - self.summarize_keep;
+            let mut prompt = format!("TASK:\n{}\n\nHISTORY:\n", state.to_prompt()?);
+            for exec in history.iter().take(to_summarize) {
+                for message in exec.to_messages(&self.serializer) {
+                    prompt += &message.to_string();
+                }
+            }
+
+            (prompt, to_summarize)
+        };
+
+        let options = ChatOptions::new(
+            Some("You are summarizing the progress of an agent working on a task. Given the task and the history of the actions it executed with their outputs, write a concise summary preserving every finding, value and result that could be useful to complete the task, as well as what has been tried and failed. Only reply with the summary.".to_string()),
+            prompt,
+            vec![],
+            vec![],
+            ConversationWindow::Full,
+        )
+        .with_sampling(self.sampling);
+        let options = if self.redact_prompts {
+            options.redacted()
+        } else {
+            options
+        };
+
+        let start = std::time::Instant::now();
+        let response = self.generator.chat(self.state.clone(), &options).await?;
+        self.on_completion(&response, start.elapsed()).await;
+
+        let summary = response.content.trim().to_string();
+        if summary.is_empty() {
+            return Err(anyhow!("empty summary"));
+        }
+
+        self.state
+            .lock()
+            .await
+            .compress_history(to_summarize, summary);
+
+        Ok(())
+    }
+
+    // ask the model to compile what was found and done into a markdown report
+    pub async fn compile_report(&self, template: &ReportTemplate) -> Result<String> {
+        let prompt = {
+            let state = self.state.lock().await;
+            let storages: Vec<&state::storage::Storage> = state
+                .get_sorted_storages()
+                .into_iter()
+                .filter(|s| !matches!(s.get_type(), state::storage::StorageType::Time))
+                .collect();
+
+            let mut prompt = format!(
+                "TASK:\n{}\n\nSTORAGES:\n{}\n\nHISTORY:\n",
+                state.to_prompt()?,
+                self.serializer.serialize_storages(&storages, true)
+            );
+            for exec in state.get_history().iter() {
+                for message in exec.to_messages(&self.serializer) {
+                    prompt += &message.to_string();
+                }
+            }
+            prompt
+        };
+
+        let options = ChatOptions::new(
+            Some(template.to_system_prompt()),
+            prompt,
+            vec![],
+            vec![],
+            ConversationWindow::Full,
+        )
+        .with_sampling(self.sampling);
+        let options = if self.redact_prompts {
+            options.redacted()
+        } else {
+            options
+        };
+
+        let start = std::time::Instant::now();
+        let response = self.generator.chat(self.state.clone(), &options).await?;
+        self.on_completion(&response, start.elapsed()).await;
+
+        let report = response.content.trim().to_string();
+        if report.is_empty() {
+            return Err(anyhow!("the model returned an empty report"));
+        }
+
+        Ok(report)
+    }
+
+    async fn prepare_step(&mut self) -> Result<ChatOptions> {
+        let mut mut_state = self.state.lock().await;
+
+        mut_state.on_step()?;
+        mut_state.count_history_tokens(&self.serializer);
+        mut_state.update_storage_snapshot(&self.serializer);
+
+        self.on_event(events::Event::MetricsUpdate(mut_state.metrics.clone()))?;
+
+        let system_prompt = self.serializer.system_prompt_for_state(&mut_state)?;
+        let mut prompt = mut_state.to_prompt()?;
+
+        let step = mut_state.metrics.current_step;
+        // nothing to reflect on at the first step
+        let reflect = self.force_reflection
+            || self
+                .reflect_every
+                .is_some_and(|every| step > 1 && step % every == 0);
+        if reflect {
+            log::info!("step {}: reflecting on progress ...", step);
+            prompt = format!("{}\n\n{}", prompt, prompt!("task/reflection.prompt"));
+            self.force_reflection = false;
+        }
+
+        let (system_prompt, prompt) = if self.user_only {
+            // combine with user prompt for models like the openai/o1 family
+            (None, format!("{system_prompt}\n\n{prompt}"))
+        } else {
+            (Some(system_prompt), prompt)
+        };
+
+        let examples = mut_state.examples_to_chat_history(&self.serializer)?;
+        let history = mut_state.to_chat_history(&self.serializer)?;
+        let options = ChatOptions::new(
+            system_prompt,
+            prompt,
+            examples,
+            history,
+            self.conversation_window,
+        )
+        .with_sampling(self.sampling);
+
+        if self.redact_prompts {
+            Ok(options.redacted())
+        } else {
+            Ok(options)
+        }
+    }
+
+    pub fn on_event(&self, event: Event) -> Result<()> {
+        self.events_chan.send(event).map_err(|e| anyhow!(e))
+    }
+
+    pub fn get_state(&self) -> SharedState {
+        self.state.clone()
+    }
+
+    // run the agent until the task is complete or a fatal error is reached
+    pub async fn run(&mut self) -> Result<()> {
+        // run any setup hook
+        self.on_start().await?;
+
+        while !self.is_done().await {
+            if self.is_stopped() {
+                self.on_interrupted().await;
+                break;
+            }
+
+            // next step, followed by the handoff it requested if any
+            let ret = match self.step().await {
+                Ok(()) => self.hand_off().await,
+                Err(error) => Err(error),
+            };
+            if let Err(error) = ret {
+                log::error!("{}", error.to_string());
+                // save what we have so far so that the run can be resumed
+                self.save_checkpoint().await;
+                // make sure teardown hooks are executed
+                self.on_end().await?;
+                return Err(error);
+            }
+        }
+
+        self.on_end().await
+    }
+
+    // replace the task with the one the model handed it off to, keeping the generator, the
+    // settings and the session of the run, while the hooks, timeout and guardrails are the
+    // ones of the new task
+    async fn hand_off(&mut self) -> Result<()> {
+        let (to, message) = match self.state.lock().await.take_handoff() {
+            Some(handoff) => handoff,
+            None => return Ok(()),
+        };
+
+        let (handoff, package, embedder) = {
+            let state = self.state.lock().await;
+            let task = state.get_task();
+            let handoff = task
+                .get_handoffs()
+                .into_iter()
+                .find(|handoff| handoff.name == to)
+                .ok_or_else(|| anyhow!("unknown agent '{}'", to))?;
+            // only the rules of the task, every agent follows the basic ones already
+            let base = task.base_guidance()?;
+            let package = task::handoff::Package {
+                from: task.hooks().task_name,
+                goal: task.to_prompt()?,
+                message: message.to_string(),
+                constraints: task
+                    .guidance()?
+                    .into_iter()
+                    .filter(|rule| !base.contains(rule))
+                    .collect(),
+            };
+            (handoff, package, state.get_embedder())
+        };
+
+        let mut tasklet =
+            task::tasklet::Tasklet::from_path(&handoff.path.display().to_string(), &vec![])?;
+        tasklet.attach_handoff(&package)?;
+        // the goal stays the same, unless the tasklet has its own prompt
+        tasklet.prepare(&Some(package.goal.to_string())).await?;
+        let name = tasklet.name.to_string();
+
+        for (tag, limit) in tasklet.get_rate_limits() {
+            ratelimit::configure(&tag, &limit)?;
+            self.rate_limits.insert(tag, limit);
+        }
+
+        let guardrails = Guardrails::from_rules(&tasklet.get_guardrails())?;
+        self.task_guardrails = self
+            .guardrails
+            .replace_first(self.task_guardrails, guardrails);
+        self.task_timeout = tasklet.get_timeout();
+        self.hooks = tasklet.hooks();
+        self.task_middlewares = self
+            .middlewares
+            .replace_first(self.task_middlewares, Self::task_middlewares(&self.hooks));
+
+        let next = State::new(
+            self.events_chan.clone(),
+            Box::new(tasklet),
+            Box::new(generator::SharedEmbedder(embedder)),
+            0,
+            self.use_native_tools_format,
+        )
+        .await?;
+
+        let mut state = self.state.lock().await;
+        let previous = std::mem::replace(&mut *state, next);
+        state.continue_from(previous, handoff.storages.as_deref());
+        drop(state);
+
+        if let Some((_, tasklet)) = self.audit.as_mut() {
+            *tasklet = name;
+        }
+
+        log::debug!("handed off from {} to {}", package.from, to);
+        self.on_event(Event::Handoff {
+            from: package.from,
+            to,
+            message,
+        })
+    }
+
+    pub async fn on_start(&mut self) -> Result<()> {
+        let metrics = self.get_metrics().await;
+        self.hooks.on_start(&metrics).await
+    }
+
+    // parse the model response into invocations
+    fn parse_invocations(&self, response: &ChatResponse) -> Result<Vec<Invocation>> {
+        if self.use_native_tools_format && response.invocations.is_empty() {
+            // no tool calls, attempt to parse the content anyway
+            Ok(self
+                .serializer
+                .try_parse(response.content.trim())
+                .unwrap_or_default())
+        } else if !self.use_native_tools_format {
+            // use our own parsing strategy
+            self.serializer.try_parse(response.content.trim())
+        } else {
+            Ok(response.invocations.clone())
+        }
+    }
+
+    // how many of the invocations of a response are valid or a repetition of the last executions
+    async fn to_candidate(&self, response: &ChatResponse) -> Candidate {
+        let invocations = self.parse_invocations(response).unwrap_or_default();
+        let state = self.state.lock().await;
+        let recent: Vec<&Invocation> = state
+            .get_history()
+            .iter()
+            .rev()
+            .take(candidates::RECENT)
+            .filter_map(|exec| exec.invocation())
+            .collect();
+
+        let mut candidate = Candidate {
+            parsed: invocations.len(),
+            ..Default::default()
+        };
+        for inv in invocations {
+            let valid = state
+                .get_action(&inv.action)
+                .is_some_and(|action| self.validate(&mut inv.clone(), &action).is_ok());
+            if valid {
+                candidate.valid += 1;
+            }
+            if recent.contains(&&inv) {
+                candidate.repeated += 1;
+            }
+        }
+        candidate
+    }
+
+    // run the model inference, sampling the configured number of responses and picking the best one
+    async fn generate(&self, options: &ChatOptions) -> Result<ChatResponse> {
+        let start = std::time::Instant::now();
+        if self.candidates <= 1 {
+            let response = self.generator.chat(self.state.clone(), options).await?;
+            // update tokens usage and throughput
+            self.on_completion(&response, start.elapsed()).await;
+            return Ok(response);
+        }
+
+        let results = futures::future::join_all(
+            (0..self.candidates).map(|_| self.generator.chat(self.state.clone(), options)),
+        )
+        .await;
+        let elapsed = start.elapsed();
+
+        let mut responses = vec![];
+        let mut error = None;
+        for result in results {
+            match result {
+                Ok(response) => {
+                    // every candidate is paid for
+                    self.on_completion(&response, elapsed).await;
+                    responses.push(response);
+                }
+                Err(err) => {
+                    log::warn!("could not sample a candidate response: {}", err);
+                    error = Some(err);
+                }
+            }
+        }
+        if responses.is_empty() {
+            return Err(error.unwrap());
+        }
+
+        let mut candidates = vec![];
+        for response in &responses {
+            candidates.push(self.to_candidate(response).await);
+        }
+        let best = candidates::best(&candidates);
+        log::debug!(
+            "picked candidate {} of {} with scores {:?}",
+            best + 1,
+            candidates.len(),
+            candidates.iter().map(Candidate::score).collect::<Vec<_>>()
+        );
+
+        Ok(responses.swap_remove(best))
+    }
+
+    // sample the final answer again and complete with the one most of the samples agree on
+    async fn vote_answer(
+        &self,
+        options: &ChatOptions,
+        samples: usize,
+        answer: &mut Invocation,
+    ) -> Result<()> {
+        log::info!("sampling the final answer {} more times ...", samples - 1);
+
+        let start = std::time::Instant::now();
+        let results = futures::future::join_all(
+            (1..samples).map(|_| self.generator.chat(self.state.clone(), options)),
+        )
+        .await;
+        let elapsed = start.elapsed();
+
+        let mut answers = vec![answer.payload.clone().unwrap_or_default()];
+        for result in results {
+            match result {
+                Ok(response) => {
+                    self.on_completion(&response, elapsed).await;
+                    // the samples that don't complete the task don't vote
+                    if let Some(payload) = self
+                        .parse_invocations(&response)
+                        .unwrap_or_default()
+                        .into_iter()
+                        .find(|inv| inv.action == answer.action)
+                        .and_then(|inv| inv.payload)
+                    {
+                        answers.push(payload);
+                    }
+                }
+                Err(err) => log::warn!("could not sample the final answer: {}", err),
+            }
+        }
+
+        let winner = match task::evaluation::majority(&answers) {
+            Some(winner) => winner,
+            None => {
+                let embedder = self.state.lock().await.get_embedder();
+                let mut embeddings = vec![];
+                for answer in &answers {
+                    embeddings.push(embedder.embed(answer).await?);
+                }
+                task::evaluation::medoid(&embeddings)
+            }
+        };
+
+        log::info!(
+            "{} of {} samples completed the task, answer {} has the most agreement",
+            answers.len(),
+            samples,
+            winner + 1
+        );
+        answer.payload = Some(answers.swap_remove(winner));
+
+        Ok(())
+    }
+
+    pub async fn step(&mut self) -> Result<()> {
+        self.check_circuit_breaker().await?;
+
+        if let Err(err) = self.deduplicate_history().await {
+            // the outputs are left as they are
+            log::warn!("could not deduplicate history: {}", err);
+        }
+
+        if let Err(err) = self.summarize_history().await {
+            // the fixed conversation window will still apply
+            log::warn!("could not summarize history: {}", err);
+        }
+
+        let options = self.prepare_step().await?;
+
+        if let Err(err) = self.hooks.on_step(&self.get_metrics().await).await {
+            log::error!("{}", err);
+        }
+
+        self.on_state_update(&options, false).await?;
+
+        // run model inference
+        let mut response = self
+            .generate(&options)
+            .await
+            .map_err(|e| Aborted::new(AbortReason::Generator, e))?;
+
+        // inspect the response before parsing it
+        let mut confirm_all = false;
+        if !self.guardrails.is_empty() && !response.content.is_empty() {
+            let original = response.content.clone();
+            let outcome = self
+                .guardrails
+                .inspect_response(&self.state, &mut response.content)
+                .await?;
+
+            if let Some(reason) = outcome.blocked {
+                self.on_blocked_response(&original, reason).await;
+                self.save_checkpoint().await;
+                return Ok(());
+            }
+
+            if outcome.redacted {
+                self.on_guardrail(None, "response redacted".to_string())?;
+            }
+
+            if let Some(reason) = outcome.confirm {
+                self.on_guardrail(None, reason)?;
+                confirm_all = true;
+            }
+        }
+
+        if self.step_mode {
+            self.on_event(Event::ModelResponse(response.content.trim().to_string()))?;
+        }
+
+        let mut invocations = self.parse_invocations(&response)?;
+
+        // the final answer is voted on by more samples of this step
+        if let Some(samples) = self.consistency {
+            if let Some(answer) = invocations
+                .iter_mut()
+                .find(|inv| inv.action == namespaces::task::COMPLETE_ACTION)
+            {
+                if let Err(err) = self.vote_answer(&options, samples, answer).await {
+                    log::warn!("could not vote on the final answer: {}", err);
+                }
+            }
+        }
+
+        // nothing parsed, report the problem to the model
+        if invocations.is_empty() {
+            if response.content.is_empty() {
+                self.on_empty_response().await;
+            } else {
+                self.on_invalid_response(&response.content).await;
+            }
+        } else {
+            self.on_valid_response().await;
+        }
+
+        // for each parsed invocation
+        for mut inv in invocations {
+            // lookup action
+            let action = self.state.lock().await.get_action(&inv.action);
+            if let Some(action) = action {
+                // validate prerequisites
+                if let Err(err) = self.validate(&mut inv, &action) {
+                    self.on_invalid_action(inv.clone(), Some(err.to_string()))
+                        .await;
+                } else {
+                    self.on_valid_action().await;
+
+                    // determine if we have a timeout
+                    let task_action_tm = self
+                        .state
+                        .lock()
+                        .await
+                        .get_task()
+                        .get_action_timeout(action.name());
+                    let timeout =
+                        action_timeout(task_action_tm, action.timeout(), self.task_timeout);
+
+                    let mut execute = true;
+                    let mut confirm = confirm_all;
+                    let mut approval = Approval::NotRequired;
+
+                    if !self.guardrails.is_empty() {
+                        let outcome = self
+                            .guardrails
+                            .inspect_invocation(&self.state, &mut inv)
+                            .await?;
+
+                        if let Some(reason) = outcome.blocked {
+                            self.on_guardrail(Some(inv.clone()), reason.to_string())?;
+                            self.on_executed_action(
+                                &action,
+                                inv.clone(),
+                                Err(anyhow!("blocked by guardrail {}", reason)),
+                                Approval::Blocked,
+                                &std::time::Instant::now(),
+                            )
+                            .await;
+
+                            execute = false;
+                        } else {
+                            if outcome.redacted {
+                                self.on_guardrail(Some(inv.clone()), "redacted".to_string())?;
+                            }
+                            if let Some(reason) = outcome.confirm {
+                                self.on_guardrail(Some(inv.clone()), reason)?;
+                                confirm = true;
+                            }
+                        }
+                    }
+
+                    let namespace = self.state.lock().await.get_action_namespace(action.name());
+
+                    // the middlewares can change or veto the invocation
+                    let mut action_ctx = None;
+                    if execute && !self.middlewares.is_empty() {
+                        let mut ctx = ActionContext {
+                            task: self.hooks.task_name.clone(),
+                            namespace: namespace.clone(),
+                            metrics: self.get_metrics().await,
+                            notes: vec![],
+                        };
+                        let verdict = match self.middlewares.before_action(&mut ctx, &mut inv).await
+                        {
+                            Verdict::Proceed if inv.action != action.name() => Verdict::Veto(
+                                format!("middlewares can't change the action to {}", inv.action),
+                            ),
+                            Verdict::Proceed => match self.validate(&mut inv, &action) {
+                                Ok(_) => Verdict::Proceed,
+                                Err(err) => Verdict::Veto(err.to_string()),
+                            },
+                            veto => veto,
+                        };
+
+                        if let Verdict::Veto(reason) = verdict {
+                            log::warn!("invocation vetoed by middleware: {}", reason);
+                            self.on_executed_action(
+                                &action,
+                                inv.clone(),
+                                Err(anyhow!("vetoed: {}", reason)),
+                                Approval::Blocked,
+                                &std::time::Instant::now(),
+                            )
+                            .await;
+
+                            execute = false;
+                        }
+                        action_ctx = Some(ctx);
+                    }
+
+                    // in step mode the user decides what happens with every invocation
+                    let mut stepped = false;
+                    if execute && self.step_mode {
+                        stepped = true;
+                        match confirm::step(&inv) {
+                            StepDecision::Execute(edited) => {
+                                approval = Approval::Approved;
+                                if edited != inv {
+                                    log::info!(
+                                        "invocation edited by user: {}",
+                                        edited.as_function_call_string()
+                                    );
+                                    inv = edited;
+                                    approval = Approval::Edited;
+                                    if let Err(err) = self.validate_edited(&mut inv, &action) {
+                                        self.on_invalid_action(inv.clone(), Some(err.to_string()))
+                                            .await;
+                                        execute = false;
+                                    }
+                                }
+                            }
+                            StepDecision::Skip => {
+                                log::warn!("invocation skipped by user");
+                                self.audit(
+                                    &inv,
+                                    Approval::Skipped,
+                                    audit::Outcome::NotExecuted,
+                                    Duration::ZERO,
+                                )
+                                .await;
+                                execute = false;
+                            }
+                            StepDecision::Inject(output) => {
+                                log::info!("injected result for {}", inv.as_function_call_string());
+                                self.on_executed_action(
+                                    &action,
+                                    inv.clone(),
+                                    output.map_err(|e| anyhow!(e)),
+                                    Approval::Injected,
+                                    &std::time::Instant::now(),
+                                )
+                                .await;
+                                execute = false;
+                            }
+                        }
+                    }
+
+                    let confirm = confirm
+                        || self
+                            .state
+                            .lock()
+                            .await
+                            .get_policy()
+                            .requires_confirmation(namespace.as_deref());
+                    if execute
+                        && !stepped
+                        && (confirm
+                            || self
+                                .confirm_policy
+                                .requires_confirmation(namespace.as_deref(), &action))
+                    {
+                        log::warn!("user confirmation required");
+
+                        let start = std::time::Instant::now();
+                        match confirm::ask(&inv) {
+                            Decision::Approve(approved) => {
+                                approval = Approval::Approved;
+                                if approved != inv {
+                                    log::info!(
+                                        "invocation edited by user: {}",
+                                        approved.as_function_call_string()
+                                    );
+                                    inv = approved;
+                                    approval = Approval::Edited;
+                                    if let Err(err) = self.validate_edited(&mut inv, &action) {
+                                        self.on_invalid_action(inv.clone(), Some(err.to_string()))
+                                            .await;
+                                        execute = false;
+                                    }
+                                }
+                            }
+                            Decision::Reject => {
+                                log::warn!("invocation rejected by user");
+                                self.on_executed_action(
+                                    &action,
+                                    inv.clone(),
+                                    Err(anyhow!("rejected by user".to_owned())),
+                                    Approval::Rejected,
+                                    &start,
+                                )
+                                .await;
+
+                                execute = false;
+                            }
+                        }
+                    }
+
+                    if execute {
+                        let start = std::time::Instant::now();
+                        // in deterministic runs use the output recorded in the previous session,
+                        // actions changing the agent state are consumed but executed anyway
+                        let pinned = self
+                            .pinned
+                            .as_mut()
+                            .and_then(|pinned| pinned.take(&inv))
+                            .filter(|_| PinnedOutputs::is_pinnable(namespace.as_deref()));
+
+                        if let Some(output) = pinned {
+                            log::info!("pinned output for {}", inv.as_function_call_string());
+                            self.on_executed_action(
+                                &action,
+                                inv,
+                                output.map_err(|e| anyhow!(e)),
+                                approval,
+                                &start,
+                            )
+                            .await;
+                        } else {
+                            self.on_event(Event::ActionStarted {
+                                invocation: inv.clone(),
+                            })?;
+
+                            let retry_policy = {
+                                let mut state = self.state.lock().await;
+                                state.set_current_action(Some(action.name()));
+                                state
+                                    .get_task()
+                                    .get_retry_policy(action.name())
+                                    .or_else(|| action.retry_policy())
+                            };
+
+                            let rate_limits = self.rate_limit_tags(&action);
+
+                            // execute with a timeout extended by the output of the action, retrying
+                            // transient failures according to the policy
+                            let activity = self.state.lock().await.get_activity();
+                            let mut retry = 0;
+                            let ret = loop {
+                                // every attempt counts against the rate limits
+                                let waited = ratelimit::acquire(&rate_limits).await;
+                                if !waited.is_zero() {
+                                    log::info!(
+                                        "{} waited {:?} for the {} rate limit",
+                                        action.name(),
+                                        waited,
+                                        rate_limits.join(", ")
+                                    );
+                                }
+
+                                let ret = streaming::with_idle_timeout(
+                                    action.run(
+                                        self.state.clone(),
+                                        inv.attributes.to_owned(),
+                                        inv.payload.to_owned(),
+                                    ),
+                                    timeout,
+                                    &activity,
+                                    streaming::HEARTBEAT_INTERVAL,
+                                    |elapsed| {
+                                        let _ = self.events_chan.send(Event::ActionHeartbeat {
+                                            invocation: inv.clone(),
+                                            elapsed,
+                                        });
+                                    },
+                                )
+                                .await;
+
+                                if let Some(policy) = &retry_policy {
+                                    let should_retry = match &ret {
+                                        None => true,
+                                        Some(Err(err)) => policy.should_retry(err),
+                                        Some(Ok(_)) => false,
+                                    };
+
+                                    if should_retry && retry < policy.attempts {
+                                        retry += 1;
+                                        let delay = policy.delay(retry);
+                                        log::warn!(
+                                            "{} failed ({}), retry {}/{} in {:?} ...",
+                                            action.name(),
+                                            match &ret {
+                                                Some(Err(err)) => err.to_string(),
+                                                _ => "timeout".to_string(),
+                                            },
+                                            retry,
+                                            policy.attempts,
+                                            delay
+                                        );
+                                        self.state.lock().await.metrics.retried_actions += 1;
+                                        tokio::time::sleep(delay).await;
+                                        continue;
+                                    }
+                                }
+
+                                break ret;
+                            };
+                            self.state.lock().await.set_current_action(None);
+
+                            if let Some(mut ret) = ret {
+                                // apply any output filter before the result enters the history
+                                let filter = self
+                                    .state
+                                    .lock()
+                                    .await
+                                    .get_task()
+                                    .get_output_filter(action.name());
+                                if let (Some(filter), Ok(Some(out))) = (filter, &ret) {
+                                    match filter.apply(out) {
+                                        Ok(filtered) => ret = Ok(Some(filtered)),
+                                        Err(err) => log::warn!(
+                                            "could not filter output of {}: {}",
+                                            action.name(),
+                                            err
+                                        ),
+                                    }
+                                }
+
+                                if let Some(ctx) = action_ctx.as_mut() {
+                                    ctx.metrics = self.get_metrics().await;
+                                    self.middlewares.after_action(ctx, &inv, &mut ret).await;
+                                }
+
+                                // don't flood the context with huge outputs
+                                if let (Ok(Some(out)), false) =
+                                    (&ret, action.name() == "read_output")
+                                {
+                                    ret = Ok(Some(
+                                        self.state.lock().await.page_output(out.to_string()),
+                                    ));
+                                }
+
+                                self.on_executed_action(&action, inv, ret, approval, &start)
+                                    .await;
+                            } else {
+                                self.on_timed_out_action(inv, approval, &start).await;
+                            }
+                        }
+
+                        if action.complete_task() {
+                            log::info!("! task complete");
+                            self.state.lock().await.on_complete(false, None)?;
+                        }
+                    }
+                }
+            } else {
+                self.on_invalid_action(inv.clone(), None).await;
+            }
+
+            self.on_state_update(&options, true).await?;
+
+            // break the loop if we're done
+            if self.state.lock().await.is_complete() {
+                break;
+            }
+        }
+
+        self.save_checkpoint().await;
+
+        Ok(())
+    }
+
+    pub async fn on_end(&mut self) -> Result<()> {
+        // report final metrics on exit
+        let last_metrics = self.get_metrics().await;
+
+        if let Some(pinned) = &self.pinned {
+            let unused = pinned.unused();
+            if !unused.is_empty() {
+                log::warn!(
+                    "{} recorded outputs were not used, the run diverged from the pinned one",
+                    unused.len()
+                );
+            }
+        }
+
+        if let Err(err) = self.hooks.on_complete(&last_metrics).await {
+            log::error!("{}", err);
+        }
+
+        self.on_event(Event::MetricsUpdate(last_metrics))?;
+
+        // the session is recorded in the background, a failed write would go unnoticed
+        #[cfg(feature = "sqlite")]
+        if let Some(db) = &self.database {
+            db.flush().await?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_action_timeout_precedence() {
+        let secs = |s| Some(Duration::from_secs(s));
+
+        assert_eq!(
+            action_timeout(secs(1), secs(2), secs(3)),
+            Duration::from_secs(1)
+        );
+        assert_eq!(
+            action_timeout(None, secs(2), secs(3)),
+            Duration::from_secs(2)
+        );
+        assert_eq!(action_timeout(None, None, secs(3)), Duration::from_secs(3));
+        assert_eq!(
+            action_timeout(None, None, None),
+            Duration::from_secs(60 * 60 * 24 * 30)
+        );
+    }
+}
@@ -0,0 +1,123 @@
+use std::collections::HashMap;
+
+use anyhow::Result;
+use async_trait::async_trait;
+
+use super::{Action, Namespace};
+use crate::agent::state::SharedState;
+use crate::prompt;
+
+#[derive(Debug, Default, Clone)]
+struct Branch {}
+
+#[async_trait]
+impl Action for Branch {
+    fn name(&self) -> &str {
+        "branch"
+    }
+
+    fn description(&self) -> &str {
+        prompt!("branch.prompt")
+    }
+
+    fn example_payload(&self) -> Option<&str> {
+        Some("try the default credentials on the ssh service")
+    }
+
+    async fn run(
+        &self,
+        state: SharedState,
+        _: Option<HashMap<String, String>>,
+        payload: Option<String>,
+    ) -> Result<Option<String>> {
+        let mut state = state.lock().await;
+        let branch = state.open_branch(&payload.unwrap())?;
+        Ok(Some(format!(
+            "branch {} opened at step {}, use backtrack if it turns out to be a dead end or keep_branch once it works",
+            branch.id, branch.step
+        )))
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+struct Backtrack {}
+
+#[async_trait]
+impl Action for Backtrack {
+    fn name(&self) -> &str {
+        "backtrack"
+    }
+
+    fn description(&self) -> &str {
+        prompt!("backtrack.prompt")
+    }
+
+    fn example_payload(&self) -> Option<&str> {
+        Some("the ssh service only accepts key authentication")
+    }
+
+    async fn run(
+        &self,
+        state: SharedState,
+        _: Option<HashMap<String, String>>,
+        payload: Option<String>,
+    ) -> Result<Option<String>> {
+        let mut state = state.lock().await;
+        let branch = state.backtrack(&payload.unwrap())?;
+        let mut result = format!(
+            "backed out of branch {} ({}) after {} steps, the history and storages are back to when it was opened at step {}.",
+            branch.id,
+            branch.description,
+            state.metrics.current_step - branch.step,
+            branch.step
+        );
+
+        // what this point of the history doesn't remember anymore
+        result += "\nDead ends so far, don't try them again:";
+        for dead_end in state.get_dead_ends() {
+            result += &format!("\n- {}", dead_end);
+        }
+
+        Ok(Some(result))
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+struct KeepBranch {}
+
+#[async_trait]
+impl Action for KeepBranch {
+    fn name(&self) -> &str {
+        "keep_branch"
+    }
+
+    fn description(&self) -> &str {
+        prompt!("keep.prompt")
+    }
+
+    async fn run(
+        &self,
+        state: SharedState,
+        _: Option<HashMap<String, String>>,
+        _: Option<String>,
+    ) -> Result<Option<String>> {
+        let branch = state.lock().await.keep_branch()?;
+        Ok(Some(format!(
+            "branch {} ({}) kept",
+            branch.id, branch.description
+        )))
+    }
+}
+
+pub fn get_namespace() -> Namespace {
+    Namespace::new_non_default(
+        "Exploration".to_string(),
+        prompt!("ns.prompt").to_string(),
+        vec![
+            Box::<Branch>::default(),
+            Box::<Backtrack>::default(),
+            Box::<KeepBranch>::default(),
+        ],
+        None,
+    )
+}
@@ -1,11 +1,10 @@
 use std::collections::HashMap;
-use std::fs::{self, FileType, OpenOptions};
+use std::fs::{self, FileType, Metadata, OpenOptions};
 use std::io::Write;
-use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+use std::path::Path;
 
 use async_trait::async_trait;
 use chrono::{DateTime, Local};
-use libc::{S_IRGRP, S_IROTH, S_IRUSR, S_IWGRP, S_IWOTH, S_IWUSR, S_IXGRP, S_IXOTH, S_IXUSR};
 
 use anyhow::Result;
 use serde::Serialize;
@@ -13,22 +12,51 @@ use serde::Serialize;
 use super::{Action, Namespace};
 use crate::agent::state::SharedState;
 use crate::agent::task::variables::get_variable;
+use crate::prompt;
 
 // cast needed for Darwin apparently
+#[cfg(unix)]
 #[allow(clippy::unnecessary_cast)]
-fn parse_permissions(mode: u32) -> String {
+fn parse_permissions(metadata: &Metadata) -> String {
+    use libc::{S_IRGRP, S_IROTH, S_IRUSR, S_IWGRP, S_IWOTH, S_IWUSR, S_IXGRP, S_IXOTH, S_IXUSR};
+    use std::os::unix::fs::PermissionsExt;
+
+    let mode = metadata.permissions().mode();
     let user = triplet(mode, S_IRUSR as u32, S_IWUSR as u32, S_IXUSR as u32);
     let group = triplet(mode, S_IRGRP as u32, S_IWGRP as u32, S_IXGRP as u32);
     let other = triplet(mode, S_IROTH as u32, S_IWOTH as u32, S_IXOTH as u32);
     [user, group, other].join("")
 }
 
+// only the read-only attribute is meaningful outside of unix
+#[cfg(not(unix))]
+fn parse_permissions(metadata: &Metadata) -> String {
+    if metadata.permissions().readonly() {
+        "r--r--r--"
+    } else {
+        "rw-rw-rw-"
+    }
+    .to_string()
+}
+
 fn parse_type(file_type: FileType) -> String {
     if file_type.is_symlink() {
         "symlink"
     } else if file_type.is_dir() {
         "dir"
-    } else if file_type.is_block_device() {
+    } else if file_type.is_file() {
+        "file"
+    } else {
+        parse_special_type(file_type)
+    }
+    .to_string()
+}
+
+#[cfg(unix)]
+fn parse_special_type(file_type: FileType) -> &'static str {
+    use std::os::unix::fs::FileTypeExt;
+
+    if file_type.is_block_device() {
         "block device"
     } else if file_type.is_char_device() {
         "char device"
@@ -36,14 +64,37 @@ fn parse_type(file_type: FileType) -> String {
         "fifo"
     } else if file_type.is_socket() {
         "socket"
-    } else if file_type.is_file() {
-        "file"
     } else {
         "unknown"
     }
-    .to_string()
 }
 
+#[cfg(not(unix))]
+fn parse_special_type(_file_type: FileType) -> &'static str {
+    "unknown"
+}
+
+// canonical paths on windows are verbatim ones, like \\?\C:\Users, shown without the prefix
+fn display_path(path: &Path) -> String {
+    let path = path.display().to_string();
+    if cfg!(windows) {
+        strip_verbatim(&path)
+    } else {
+        path
+    }
+}
+
+fn strip_verbatim(path: &str) -> String {
+    if let Some(unc) = path.strip_prefix(r"\\?\UNC\") {
+        format!(r"\\{}", unc)
+    } else if let Some(local) = path.strip_prefix(r"\\?\") {
+        local.to_string()
+    } else {
+        path.to_string()
+    }
+}
+
+#[cfg(unix)]
 fn triplet(mode: u32, read: u32, write: u32, execute: u32) -> String {
     match (mode & read, mode & write, mode & execute) {
         (0, 0, 0) => "---",
@@ -68,7 +119,7 @@ impl Action for ReadFolder {
     }
 
     fn description(&self) -> &str {
-        include_str!("read_folder.prompt")
+        prompt!("read_folder.prompt")
     }
 
     fn example_payload(&self) -> Option<&str> {
@@ -77,12 +128,13 @@ impl Action for ReadFolder {
 
     async fn run(
         &self,
-        _: SharedState,
+        state: SharedState,
         _: Option<HashMap<String, String>>,
         payload: Option<String>,
     ) -> Result<Option<String>> {
         // adapted from https://gist.github.com/mre/91ebb841c34df69671bd117ead621a8b
         let folder = payload.unwrap();
+        state.lock().await.get_policy().filesystem.check(&folder)?;
         let ret = fs::read_dir(&folder);
         if let Ok(paths) = ret {
             let mut output = format!("Contents of {} :\n\n", &folder);
@@ -95,15 +147,14 @@ impl Action for ReadFolder {
                         let size = metadata.len();
                         let modified: DateTime<Local> =
                             DateTime::from(metadata.modified().unwrap());
-                        let mode = metadata.permissions().mode();
 
                         output += &format!(
                             "{} {:>5} {} [{}] {}\n",
-                            parse_permissions(mode),
+                            parse_permissions(&metadata),
                             size,
                             modified.format("%_d %b %H:%M"),
                             parse_type(metadata.file_type()),
-                            full_path.display()
+                            display_path(&full_path)
                         );
                     } else {
                         log::error!("can't canonicalize {:?}: {:?}", entry, full.err());
@@ -130,7 +181,7 @@ impl Action for ReadFile {
     }
 
     fn description(&self) -> &str {
-        include_str!("read_file.prompt")
+        prompt!("read_file.prompt")
     }
 
     fn example_payload(&self) -> Option<&str> {
@@ -139,11 +190,17 @@ impl Action for ReadFile {
 
     async fn run(
         &self,
-        _: SharedState,
+        state: SharedState,
         _: Option<HashMap<String, String>>,
         payload: Option<String>,
     ) -> Result<Option<String>> {
         let filepath = payload.unwrap();
+        state
+            .lock()
+            .await
+            .get_policy()
+            .filesystem
+            .check(&filepath)?;
         let ret = std::fs::read_to_string(&filepath);
         if let Ok(contents) = ret {
             Ok(Some(contents))
@@ -171,7 +228,11 @@ impl Action for AppendToFile {
     }
 
     fn description(&self) -> &str {
-        include_str!("append_to_file.prompt")
+        prompt!("append_to_file.prompt")
+    }
+
+    fn is_destructive(&self) -> bool {
+        true
     }
 
     fn example_payload(&self) -> Option<&str> {
@@ -185,7 +246,7 @@ impl Action for AppendToFile {
 
     async fn run(
         &self,
-        _: SharedState,
+        state: SharedState,
         _: Option<HashMap<String, String>>,
         payload: Option<String>,
     ) -> Result<Option<String>> {
@@ -202,6 +263,13 @@ impl Action for AppendToFile {
             }
         };
 
+        state
+            .lock()
+            .await
+            .get_policy()
+            .filesystem
+            .check(&filepath)?;
+
         // get lowercase file extension from filepath
         let extension = filepath.rsplit('.').next().unwrap_or("").to_lowercase();
 
@@ -239,7 +307,7 @@ impl Action for AppendToFile {
 pub fn get_namespace() -> Namespace {
     Namespace::new_non_default(
         "Filesystem".to_string(),
-        include_str!("ns.prompt").to_string(),
+        prompt!("ns.prompt").to_string(),
         vec![
             Box::<ReadFile>::default(),
             Box::<ReadFolder>::default(),
@@ -248,3 +316,27 @@ pub fn get_namespace() -> Namespace {
         None,
     )
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_strip_verbatim() {
+        assert_eq!(strip_verbatim(r"\\?\C:\Users\nerve"), r"C:\Users\nerve");
+        assert_eq!(
+            strip_verbatim(r"\\?\UNC\server\share\file.txt"),
+            r"\\server\share\file.txt"
+        );
+        assert_eq!(strip_verbatim("/home/nerve"), "/home/nerve");
+    }
+
+    #[test]
+    fn test_parse_metadata() {
+        let tmp = std::env::temp_dir();
+        let metadata = fs::metadata(&tmp).unwrap();
+        assert_eq!(parse_type(metadata.file_type()), "dir");
+        assert_eq!(parse_permissions(&metadata).len(), 9);
+        assert!(!display_path(&tmp.canonicalize().unwrap()).starts_with(r"\\?\"));
+    }
+}
@@ -0,0 +1,177 @@
+use anyhow::Result;
+use async_trait::async_trait;
+
+use std::collections::HashMap;
+
+use super::{Action, Namespace, StorageDescriptor};
+use crate::agent::state::SharedState;
+use crate::prompt;
+
+#[derive(Debug, Default, Clone)]
+struct UpdateGoal {}
+
+#[async_trait]
+impl Action for UpdateGoal {
+    fn name(&self) -> &str {
+        "update_goal"
+    }
+
+    fn description(&self) -> &str {
+        prompt!("update.prompt")
+    }
+
+    fn example_payload(&self) -> Option<&str> {
+        Some("your new goal")
+    }
+
+    async fn run(
+        &self,
+        state: SharedState,
+        _: Option<HashMap<String, String>>,
+        payload: Option<String>,
+    ) -> Result<Option<String>> {
+        state
+            .lock()
+            .await
+            .get_storage_mut("goal")?
+            .set_current(payload.as_ref().unwrap());
+        Ok(Some("goal updated".to_string()))
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+struct AddSubgoal {}
+
+#[async_trait]
+impl Action for AddSubgoal {
+    fn name(&self) -> &str {
+        "add_subgoal"
+    }
+
+    fn description(&self) -> &str {
+        prompt!("add-subgoal.prompt")
+    }
+
+    fn example_attributes(&self) -> Option<HashMap<String, String>> {
+        let mut attributes = HashMap::new();
+
+        attributes.insert("parent".to_string(), "1".to_string());
+
+        Some(attributes)
+    }
+
+    fn example_payload(&self) -> Option<&str> {
+        Some("your new subgoal")
+    }
+
+    async fn run(
+        &self,
+        state: SharedState,
+        attributes: Option<HashMap<String, String>>,
+        payload: Option<String>,
+    ) -> Result<Option<String>> {
+        let parent = attributes
+            .as_ref()
+            .and_then(|attrs| attrs.get("parent"))
+            .map(|parent| parent.trim())
+            .filter(|parent| !parent.is_empty());
+
+        let id = state
+            .lock()
+            .await
+            .get_storage_mut("subgoals")?
+            .add_child(parent, payload.as_ref().unwrap().trim())
+            .map_err(|e| anyhow!("parent subgoal {}", e))?;
+
+        Ok(Some(format!("subgoal {} added", id)))
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+struct CompleteSubgoal {}
+
+#[async_trait]
+impl Action for CompleteSubgoal {
+    fn name(&self) -> &str {
+        "complete_subgoal"
+    }
+
+    fn description(&self) -> &str {
+        prompt!("complete-subgoal.prompt")
+    }
+
+    fn example_payload(&self) -> Option<&str> {
+        Some("1.2")
+    }
+
+    async fn run(
+        &self,
+        state: SharedState,
+        _: Option<HashMap<String, String>>,
+        payload: Option<String>,
+    ) -> Result<Option<String>> {
+        let id = payload.unwrap().trim().to_string();
+        if state
+            .lock()
+            .await
+            .get_storage_mut("subgoals")?
+            .set_complete_item(&id)
+            .is_some()
+        {
+            Ok(Some(format!("subgoal {} marked as completed", id)))
+        } else {
+            Err(anyhow!("subgoal {} not found", id))
+        }
+    }
+}
+
+pub fn get_namespace() -> Namespace {
+    Namespace::new_default(
+        "Goal".to_string(),
+        prompt!("ns.prompt").to_string(),
+        vec![
+            Box::<UpdateGoal>::default(),
+            Box::<AddSubgoal>::default(),
+            Box::<CompleteSubgoal>::default(),
+        ],
+        Some(vec![
+            StorageDescriptor::previous_current("goal"),
+            StorageDescriptor::tree("subgoals"),
+        ]),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent::{events::create_channel, serialization::Strategy, state::storage::Storage};
+
+    #[test]
+    fn test_subgoals_tree() {
+        let namespace = get_namespace();
+        let descriptor = &namespace.storages.as_ref().unwrap()[1];
+        let (tx, _rx) = create_channel();
+        let mut storage = Storage::new(&descriptor.name, descriptor.type_, tx);
+
+        assert_eq!(storage.add_child(None, "recon").unwrap(), "1");
+        assert_eq!(storage.add_child(None, "exploit").unwrap(), "2");
+        assert_eq!(storage.add_child(Some("1"), "scan ports").unwrap(), "1.1");
+        assert_eq!(storage.add_child(Some("1"), "find vhosts").unwrap(), "1.2");
+        assert_eq!(storage.add_child(Some("1.2"), "try dns").unwrap(), "1.2.1");
+        assert!(storage.add_child(Some("3"), "nope").is_err());
+
+        assert_eq!(storage.set_complete_item("1.2"), Some(false));
+        assert_eq!(storage.set_complete_item("4"), None);
+
+        assert_eq!(
+            Strategy::Xml.serialize_storage(&storage, false),
+            "<subgoals>
+  - [ ] 1 recon
+    - [ ] 1.1 scan ports
+    - [x] 1.2 find vhosts
+      - [x] 1.2.1 try dns
+  - [ ] 2 exploit
+</subgoals>"
+        );
+    }
+}
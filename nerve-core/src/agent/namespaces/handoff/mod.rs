@@ -0,0 +1,79 @@
+use std::collections::HashMap;
+
+use anyhow::Result;
+use async_trait::async_trait;
+
+use super::{Action, Namespace};
+use crate::agent::{state::SharedState, task::handoff::Handoff};
+use crate::prompt;
+
+#[derive(Debug, Default, Clone)]
+struct HandOff {
+    // used in the example invocation
+    first_agent: String,
+}
+
+#[async_trait]
+impl Action for HandOff {
+    fn name(&self) -> &str {
+        "handoff"
+    }
+
+    fn description(&self) -> &str {
+        prompt!("handoff.prompt")
+    }
+
+    fn example_attributes(&self) -> Option<HashMap<String, String>> {
+        let mut attributes = HashMap::new();
+
+        attributes.insert("to".to_string(), self.first_agent.to_string());
+
+        Some(attributes)
+    }
+
+    fn example_payload(&self) -> Option<&str> {
+        Some("what you did, what you found and what is left to do")
+    }
+
+    async fn run(
+        &self,
+        state: SharedState,
+        attributes: Option<HashMap<String, String>>,
+        payload: Option<String>,
+    ) -> Result<Option<String>> {
+        let attrs = attributes.unwrap();
+        let to = attrs.get("to").unwrap().trim();
+
+        // the agent is replaced at the end of the step
+        state
+            .lock()
+            .await
+            .request_handoff(to, payload.unwrap().trim())?;
+
+        Ok(Some(format!("handing the task off to {}", to)))
+    }
+}
+
+// only available to the tasks declaring some handoffs
+pub fn get_namespace(handoffs: &[Handoff]) -> Namespace {
+    let agents = handoffs
+        .iter()
+        .map(|handoff| match &handoff.description {
+            Some(description) => format!("- {}: {}", handoff.name, description),
+            None => format!("- {}", handoff.name),
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    Namespace::new_non_default(
+        "Handoff".to_string(),
+        format!("{}\n\n{}", prompt!("ns.prompt"), agents),
+        vec![Box::new(HandOff {
+            first_agent: handoffs
+                .first()
+                .map(|handoff| handoff.name.to_string())
+                .unwrap_or_default(),
+        })],
+        None,
+    )
+}
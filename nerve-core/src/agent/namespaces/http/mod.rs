@@ -1,6 +1,7 @@
 use std::{
     collections::HashMap,
     fs::OpenOptions,
+    net::SocketAddr,
     str::FromStr,
     sync::Arc,
     time::{Duration, Instant},
@@ -13,11 +14,19 @@ use lazy_static::lazy_static;
 use reqwest_cookie_store::CookieStoreMutex;
 use url::Url;
 
-use crate::agent::state::SharedState;
+use crate::agent::{
+    cassette::{self, HttpRequest, HttpResponse},
+    policy::HttpPolicy,
+    secrets,
+    state::SharedState,
+    task::retry::{self, RetryPolicy},
+};
 
 use super::{Action, Namespace, StorageDescriptor};
+use crate::prompt;
 
 const DEFAULT_HTTP_SCHEMA: &str = "https";
+const MAX_REDIRECTS: usize = 10;
 
 lazy_static! {
     static ref COOKIE_STORE: Arc<CookieStoreMutex> = {
@@ -51,7 +60,7 @@ impl Action for ClearHeaders {
     }
 
     fn description(&self) -> &str {
-        include_str!("clear-headers.prompt")
+        prompt!("clear-headers.prompt")
     }
 
     async fn run(
@@ -75,7 +84,7 @@ impl Action for SetHeader {
     }
 
     fn description(&self) -> &str {
-        include_str!("set-header.prompt")
+        prompt!("set-header.prompt")
     }
 
     fn example_attributes(&self) -> Option<HashMap<String, String>> {
@@ -100,6 +109,8 @@ impl Action for SetHeader {
         let key = attrs.get("name").unwrap();
         let data = payload.unwrap();
 
+        secrets::register_header(key, &data);
+
         state
             .lock()
             .await
@@ -134,37 +145,71 @@ impl Request {
             .map_err(|e| anyhow!("can't join {req_page} to {http_target}: {e}"))
     }
 
-    async fn handle_success_response(res: reqwest::Response) -> Result<(String, String)> {
-        let reason = res.status().canonical_reason().unwrap();
-        let mut resp = format!("{} {}\n", res.status().as_u16(), &reason);
-
+    // the status, headers and, if it's textual, the body of the response
+    async fn read_response(res: reqwest::Response) -> Result<HttpResponse> {
+        let status = res.status().as_u16();
+        let mut headers = vec![];
         for (key, val) in res.headers() {
-            resp += &format!("{}: {}\n", key, val.to_str()?);
+            headers.push((key.to_string(), val.to_str()?.to_string()));
+        }
+
+        // non-textual content is not downloaded
+        let content_type = res
+            .headers()
+            .get("content-type")
+            .and_then(|val| val.to_str().ok())
+            .unwrap_or_default();
+        let body = if content_type.starts_with("application/") || content_type.starts_with("text/")
+        {
+            Some(String::from_utf8_lossy(&res.bytes().await?).to_string())
+        } else {
+            None
+        };
+
+        Ok(HttpResponse {
+            status,
+            headers,
+            body,
+        })
+    }
+
+    fn reason(res: &HttpResponse) -> &'static str {
+        reqwest::StatusCode::from_u16(res.status)
+            .ok()
+            .and_then(|status| status.canonical_reason())
+            .unwrap_or_default()
+    }
+
+    fn handle_success_response(res: HttpResponse) -> Result<(String, String)> {
+        let reason = Self::reason(&res);
+        let mut resp = format!("{} {}\n", res.status, &reason);
+
+        for (key, val) in &res.headers {
+            resp += &format!("{}: {}\n", key, val);
         }
 
         resp += "\n\n";
 
         // handle the response according to its content-type
-        let content_type = res.headers().get("content-type");
+        let content_type = res.header("content-type");
         if let Some(content_type) = content_type {
-            let content_type = content_type.to_str()?;
+            let body = res.body.as_deref().unwrap_or_default();
             if content_type == "application/octet-stream" {
-                // download the first few bytes to determine if it's binary or text
-                let partial_content = res.bytes().await?;
-                let is_binary = partial_content
-                    .iter()
-                    .any(|&byte| byte == 0 || (byte < 32 && byte != 9 && byte != 10 && byte != 13));
+                // determine if it's binary or text
+                let is_binary = body
+                    .bytes()
+                    .any(|byte| byte == 0 || (byte < 32 && byte != 9 && byte != 10 && byte != 13));
                 if is_binary {
                     log::warn!(
                         "ignoring binary data with http content type: application/octet-stream"
                     );
                     resp += "<BINARY DATA>";
                 } else {
-                    resp += std::str::from_utf8(&partial_content).unwrap_or("");
+                    resp += body;
                 }
             } else if content_type.starts_with("application/") || content_type.starts_with("text/")
             {
-                resp += &res.text().await?;
+                resp += body;
             } else {
                 log::warn!("ignoring non-textual http content type: {}", content_type);
                 resp += "<BINARY DATA>";
@@ -177,13 +222,29 @@ impl Request {
         Ok((reason.to_string(), resp))
     }
 
-    fn create_request(method: &str, target_url: Url) -> Result<reqwest::RequestBuilder> {
+    // the client connects to the addresses checked by the policy if any, so that the host
+    // can't be resolved again to something else
+    fn create_client(target_url: &Url, addrs: &[SocketAddr]) -> Result<reqwest::Client> {
+        let mut builder = reqwest::Client::builder()
+            .cookie_provider(COOKIE_STORE.clone())
+            // redirects are followed by execute in order to check each one with the policy
+            .redirect(reqwest::redirect::Policy::none());
+        if let (Some(host), false) = (target_url.host_str(), addrs.is_empty()) {
+            builder = builder.resolve_to_addrs(host, addrs);
+        }
+
+        Ok(builder.build()?)
+    }
+
+    fn create_request(
+        method: &str,
+        target_url: Url,
+        addrs: &[SocketAddr],
+    ) -> Result<reqwest::RequestBuilder> {
         let method = reqwest::Method::from_str(method)?;
 
-        let mut request = reqwest::Client::builder()
-            .cookie_provider(COOKIE_STORE.clone())
-            .build()?
-            .request(method.clone(), target_url.clone());
+        let mut request =
+            Self::create_client(&target_url, addrs)?.request(method.clone(), target_url.clone());
 
         // get query string if any
         let query_str = target_url.query().unwrap_or("").to_string();
@@ -198,6 +259,66 @@ impl Request {
 
         Ok(request)
     }
+
+    // perform the request and follow its redirects, as long as the policy allows them
+    async fn execute(
+        policy: &HttpPolicy,
+        client: reqwest::Client,
+        request: reqwest::Request,
+    ) -> Result<reqwest::Response> {
+        let mut method = request.method().clone();
+        let mut headers = request.headers().clone();
+        let mut body = request
+            .body()
+            .and_then(|body| body.as_bytes())
+            .map(|body| body.to_vec());
+        // connection errors might go away by retrying, the policy ones won't
+        let mut res = client
+            .execute(request)
+            .await
+            .map_err(|e| retry::transient(e.to_string()))?;
+
+        for _ in 0..MAX_REDIRECTS {
+            let location = match res.headers().get(reqwest::header::LOCATION) {
+                Some(location) if res.status().is_redirection() => location.to_str()?,
+                _ => return Ok(res),
+            };
+            let url = res
+                .url()
+                .join(location)
+                .map_err(|e| anyhow!("invalid redirect to {}: {}", location, e))?;
+            let addrs = policy.resolve(&url).await?;
+
+            log::debug!("   {} {}", "->".dimmed(), url);
+
+            // as the browsers do, only 307 and 308 keep the method and the body
+            let status = res.status().as_u16();
+            if status != 307 && status != 308 && method != reqwest::Method::HEAD {
+                method = reqwest::Method::GET;
+                body = None;
+                headers.remove(reqwest::header::CONTENT_TYPE);
+            }
+            // and the credentials are not sent to other hosts
+            if url.host_str() != res.url().host_str() {
+                headers.remove(reqwest::header::AUTHORIZATION);
+                headers.remove(reqwest::header::PROXY_AUTHORIZATION);
+                headers.remove(reqwest::header::COOKIE);
+            }
+
+            let mut next = Self::create_client(&url, &addrs)?
+                .request(method.clone(), url)
+                .headers(headers.clone());
+            if let Some(body) = &body {
+                next = next.body(body.clone());
+            }
+            res = next
+                .send()
+                .await
+                .map_err(|e| retry::transient(e.to_string()))?;
+        }
+
+        Err(anyhow!("more than {} redirects", MAX_REDIRECTS))
+    }
 }
 
 #[async_trait]
@@ -207,13 +328,26 @@ impl Action for Request {
     }
 
     fn description(&self) -> &str {
-        include_str!("request.prompt")
+        prompt!("request.prompt")
     }
 
     fn timeout(&self) -> Option<Duration> {
         Some(Duration::from_secs(30))
     }
 
+    fn is_destructive(&self) -> bool {
+        // requests can change the state of the target
+        true
+    }
+
+    fn retry_policy(&self) -> Option<RetryPolicy> {
+        Some(RetryPolicy::new(2, "1s"))
+    }
+
+    fn rate_limits(&self) -> Vec<String> {
+        vec!["network".to_string()]
+    }
+
     fn example_payload(&self) -> Option<&str> {
         Some("/index.php?id=1")
     }
@@ -240,8 +374,10 @@ impl Action for Request {
         let attrs = attrs.unwrap();
         let method = attrs.get("method").unwrap();
         let target_url = Self::create_target_url_from(&state, payload.clone()).await?;
+        let policy = state.lock().await.get_policy().http.clone();
+        let addrs = policy.resolve(&target_url).await?;
         let target_url_str = target_url.to_string();
-        let mut request = Self::create_request(method, target_url)?;
+        let mut request = Self::create_request(method, target_url, &addrs)?;
 
         // add defined headers
         for (key, value) in state.lock().await.get_storage("http-headers")?.iter() {
@@ -255,13 +391,29 @@ impl Action for Request {
             target_url_str,
         );
 
-        // perform the request
+        let (client, request) = request.build_split();
+        let request = request?;
+        let recorded = HttpRequest::new(
+            method,
+            &target_url_str,
+            request.body().and_then(|body| body.as_bytes()),
+        );
+
+        // perform the request, unless a cassette is replayed
         let start = Instant::now();
-        let res = request.send().await?;
+        let res = match cassette::replay_http(&recorded) {
+            Some(res) => res?,
+            None => {
+                let res = Self::execute(&policy, client, request).await?;
+                let res = Self::read_response(res).await?;
+                cassette::record_http(&recorded, &res)?;
+                res
+            }
+        };
         let elaps = start.elapsed();
 
-        return if res.status().is_success() {
-            let (reason, resp) = Self::handle_success_response(res).await?;
+        return if (200..300).contains(&res.status) {
+            let (reason, resp) = Self::handle_success_response(res)?;
             log::debug!(
                 "   {} {} -> {} bytes",
                 reason.green(),
@@ -271,12 +423,17 @@ impl Action for Request {
 
             Ok(Some(resp))
         } else {
-            let reason = res.status().canonical_reason().unwrap();
-            let resp = format!("{} {}", res.status().as_u16(), &reason);
+            let reason = Self::reason(&res);
+            let resp = format!("{} {}", res.status, &reason);
 
             log::debug!("   {} {}", reason.red(), format!("({:?})", elaps).dimmed(),);
 
-            Err(anyhow!(resp))
+            // server errors and rate limiting might go away by retrying
+            if res.status >= 500 || res.status == 429 {
+                Err(retry::transient(resp))
+            } else {
+                Err(anyhow!(resp))
+            }
         };
     }
 }
@@ -289,7 +446,7 @@ pub fn get_namespace() -> Namespace {
 
     Namespace::new_non_default(
         "Web".to_string(),
-        include_str!("ns.prompt").to_string(),
+        prompt!("ns.prompt").to_string(),
         vec![
             Box::<SetHeader>::default(),
             Box::<ClearHeaders>::default(),
@@ -305,44 +462,17 @@ pub fn get_namespace() -> Namespace {
 mod tests {
     use std::sync::Arc;
 
-    use crate::agent::state::State;
+    use crate::agent::testing::{new_state, TestTask};
 
     use super::*;
 
-    #[derive(Debug)]
-    struct TestTask {}
-
-    impl crate::agent::task::Task for TestTask {
-        fn to_system_prompt(&self) -> Result<String> {
-            Ok("test".to_string())
-        }
-
-        fn to_prompt(&self) -> Result<String> {
-            Ok("test".to_string())
-        }
-
-        fn get_functions(&self) -> Vec<Namespace> {
-            vec![]
-        }
-    }
-
-    struct TestEmbedder {}
-
-    #[async_trait]
-    impl mini_rag::Embedder for TestEmbedder {
-        async fn embed(&self, _text: &str) -> Result<mini_rag::Embeddings> {
-            todo!()
-        }
-    }
-
     #[allow(unused_variables)]
     async fn create_test_state(vars: Vec<(String, String)>) -> Result<SharedState> {
-        let (tx, _rx) = crate::agent::events::create_channel();
-
-        let task = Box::new(TestTask {});
-        let embedder = Box::new(TestEmbedder {});
-
-        let mut state = State::new(tx, task, embedder, 10, false).await?;
+        let mut state = new_state(TestTask {
+            namespaces: vec![get_namespace],
+            ..Default::default()
+        })
+        .await;
 
         for (name, value) in vars {
             state.set_variable(name, value);
@@ -478,7 +608,7 @@ mod tests {
 
         assert_eq!(target_url.to_string(), expected_target_url_string);
 
-        let request = Request::create_request(method, target_url)
+        let request = Request::create_request(method, target_url, &[])
             .unwrap()
             .build()
             .unwrap();
@@ -491,4 +621,128 @@ mod tests {
             Some(expected_body_string.as_bytes())
         );
     }
+
+    #[tokio::test]
+    async fn test_replay_cassette() {
+        let path = std::env::temp_dir().join(format!(
+            "nerve-test-http-cassette-{}.yml",
+            std::process::id()
+        ));
+        cassette::Cassette {
+            http: vec![
+                cassette::HttpInteraction {
+                    request: HttpRequest::new("GET", "http://www.example.com/robots.txt", None),
+                    response: HttpResponse {
+                        status: 200,
+                        headers: vec![("content-type".to_string(), "text/plain".to_string())],
+                        body: Some("Disallow: /admin".to_string()),
+                    },
+                },
+                cassette::HttpInteraction {
+                    request: HttpRequest::new("GET", "http://www.example.com/admin", None),
+                    response: HttpResponse {
+                        status: 403,
+                        ..Default::default()
+                    },
+                },
+            ],
+            ..Default::default()
+        }
+        .save(&path)
+        .unwrap();
+        cassette::load(&path, cassette::Mode::Replay).unwrap();
+
+        let state = create_test_state(vec![(
+            "HTTP_TARGET".to_string(),
+            "http://www.example.com".to_string(),
+        )])
+        .await
+        .unwrap();
+        let mut attrs = HashMap::new();
+        attrs.insert("method".to_string(), "GET".to_string());
+
+        let out = Request {}
+            .run(
+                state.clone(),
+                Some(attrs.clone()),
+                Some("/robots.txt".to_string()),
+            )
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(out.starts_with("200 OK\ncontent-type: text/plain\n"));
+        assert!(out.ends_with("Disallow: /admin"));
+
+        let err = Request {}
+            .run(
+                state.clone(),
+                Some(attrs.clone()),
+                Some("/admin".to_string()),
+            )
+            .await
+            .unwrap_err();
+        assert_eq!(err.to_string(), "403 Forbidden");
+
+        // nothing is sent when replaying
+        assert!(Request {}
+            .run(state, Some(attrs), Some("/login".to_string()))
+            .await
+            .unwrap_err()
+            .to_string()
+            .starts_with("no interaction recorded"));
+
+        cassette::unload();
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_redirects_are_checked() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        // redirects /in to /ok on the same host, and /out to a host out of scope
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut buffer = [0u8; 4096];
+                let read = stream.read(&mut buffer).await.unwrap();
+                let request = String::from_utf8_lossy(&buffer[..read]).to_string();
+                let response = if request.starts_with("GET /in ") {
+                    "HTTP/1.1 302 Found\r\nLocation: /ok\r\nContent-Length: 0\r\n\r\n".to_string()
+                } else if request.starts_with("GET /out ") {
+                    format!(
+                        "HTTP/1.1 302 Found\r\nLocation: http://127.0.0.2:{}/ok\r\nContent-Length: 0\r\n\r\n",
+                        port
+                    )
+                } else {
+                    "HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok".to_string()
+                };
+                let _ = stream.write_all(response.as_bytes()).await;
+            }
+        });
+
+        let policy = HttpPolicy {
+            targets: vec!["127.0.0.1".to_string()],
+            confirm: false,
+        };
+        let get = |path: &str| {
+            let url = Url::parse(&format!("http://127.0.0.1:{}{}", port, path)).unwrap();
+            let (client, request) = Request::create_request("GET", url, &[])
+                .unwrap()
+                .build_split();
+            (client, request.unwrap())
+        };
+
+        let (client, request) = get("/in");
+        let res = Request::execute(&policy, client, request).await.unwrap();
+        assert_eq!(res.status().as_u16(), 200);
+        assert_eq!(res.url().path(), "/ok");
+
+        let (client, request) = get("/out");
+        let err = Request::execute(&policy, client, request)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("not allowed by the http policy"));
+    }
 }
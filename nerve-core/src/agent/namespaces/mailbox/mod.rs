@@ -0,0 +1,196 @@
+use std::{collections::HashMap, sync::Arc, sync::Mutex};
+
+use anyhow::Result;
+use async_trait::async_trait;
+use lazy_static::lazy_static;
+
+use super::{Action, Namespace, StorageDescriptor};
+use crate::agent::state::SharedState;
+use crate::prompt;
+
+#[derive(Default)]
+struct Mailbox {
+    // set once the agent is running
+    state: Option<SharedState>,
+    // messages received before the agent started
+    pending: Vec<String>,
+}
+
+lazy_static! {
+    // agents that can exchange messages, by name
+    static ref AGENTS: Mutex<HashMap<String, Mailbox>> = Mutex::new(HashMap::new());
+}
+
+// make an agent that is not running yet reachable by the others, its messages will be queued
+pub fn declare(name: &str) {
+    AGENTS.lock().unwrap().entry(name.to_string()).or_default();
+}
+
+// make a running agent reachable by the others and deliver any queued message
+pub async fn register(name: &str, state: SharedState) -> Result<()> {
+    let pending = {
+        let mut agents = AGENTS.lock().unwrap();
+        let mailbox = agents.entry(name.to_string()).or_default();
+        mailbox.state = Some(state.clone());
+        std::mem::take(&mut mailbox.pending)
+    };
+
+    deliver(name, &state, &pending).await
+}
+
+pub fn unregister(name: &str) {
+    AGENTS.lock().unwrap().remove(name);
+}
+
+async fn deliver(name: &str, state: &SharedState, messages: &[String]) -> Result<()> {
+    if messages.is_empty() {
+        return Ok(());
+    }
+
+    let mut lock = state.lock().await;
+    let inbox = lock
+        .get_storage_mut("inbox")
+        .map_err(|_| anyhow!("agent '{}' can't receive messages", name))?;
+    for message in messages {
+        inbox.add_untagged(message);
+    }
+
+    Ok(())
+}
+
+fn agent_name(state: &SharedState) -> Option<String> {
+    AGENTS
+        .lock()
+        .unwrap()
+        .iter()
+        .find(|(_, mailbox)| {
+            mailbox
+                .state
+                .as_ref()
+                .map(|s| Arc::ptr_eq(s, state))
+                .unwrap_or(false)
+        })
+        .map(|(name, _)| name.to_string())
+}
+
+// running agents by name
+type Running = Vec<(String, SharedState)>;
+
+// queue the message for the recipients that are not running yet and return the running ones,
+// "all" means every agent but the sender
+fn route(from: &str, to: &str, message: &str) -> Result<(Vec<String>, Running)> {
+    let mut agents = AGENTS.lock().unwrap();
+
+    let names: Vec<String> = if to == "all" {
+        agents
+            .keys()
+            .filter(|name| name.as_str() != from)
+            .cloned()
+            .collect()
+    } else if agents.contains_key(to) {
+        vec![to.to_string()]
+    } else {
+        let mut known: Vec<&str> = agents.keys().map(|s| s.as_str()).collect();
+        known.sort();
+        return Err(anyhow!(
+            "unknown agent '{}', available agents are: {}",
+            to,
+            known.join(", ")
+        ));
+    };
+
+    let mut running = vec![];
+    for name in &names {
+        let mailbox = agents.get_mut(name).unwrap();
+        if let Some(state) = &mailbox.state {
+            running.push((name.to_string(), state.clone()));
+        } else {
+            mailbox.pending.push(message.to_string());
+        }
+    }
+
+    Ok((names, running))
+}
+
+#[derive(Debug, Default, Clone)]
+struct SendMessage {}
+
+#[async_trait]
+impl Action for SendMessage {
+    fn name(&self) -> &str {
+        "send_message"
+    }
+
+    fn description(&self) -> &str {
+        prompt!("send.prompt")
+    }
+
+    fn example_attributes(&self) -> Option<HashMap<String, String>> {
+        let mut attributes = HashMap::new();
+
+        attributes.insert("to".to_string(), "writer".to_string());
+
+        Some(attributes)
+    }
+
+    fn example_payload(&self) -> Option<&str> {
+        Some("the message for the other agent")
+    }
+
+    async fn run(
+        &self,
+        state: SharedState,
+        attributes: Option<HashMap<String, String>>,
+        payload: Option<String>,
+    ) -> Result<Option<String>> {
+        let attrs = attributes.unwrap();
+        let to = attrs.get("to").unwrap().trim();
+        let from = agent_name(&state).ok_or_else(|| anyhow!("this agent has no mailbox"))?;
+        let message = format!("[from {}] {}", from, payload.unwrap().trim());
+
+        let (names, running) = route(&from, to, &message)?;
+        if names.is_empty() {
+            return Err(anyhow!("there are no other agents"));
+        }
+
+        for (name, recipient) in &running {
+            deliver(name, recipient, &[message.to_string()]).await?;
+        }
+
+        Ok(Some(format!("message sent to {}", names.join(", "))))
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+struct ClearInbox {}
+
+#[async_trait]
+impl Action for ClearInbox {
+    fn name(&self) -> &str {
+        "clear_inbox"
+    }
+
+    fn description(&self) -> &str {
+        prompt!("clear.prompt")
+    }
+
+    async fn run(
+        &self,
+        state: SharedState,
+        _: Option<HashMap<String, String>>,
+        _: Option<String>,
+    ) -> Result<Option<String>> {
+        state.lock().await.get_storage_mut("inbox")?.clear();
+
+        Ok(Some("inbox cleared".to_string()))
+    }
+}
+
+pub fn get_namespace() -> Namespace {
+    Namespace::new_non_default(
+        "Mailbox".to_string(),
+        prompt!("ns.prompt").to_string(),
+        vec![Box::<SendMessage>::default(), Box::<ClearInbox>::default()],
+        Some(vec![StorageDescriptor::untagged("inbox")]),
+    )
+}
@@ -0,0 +1,362 @@
+use std::collections::HashMap;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use regex::Regex;
+
+use super::{Action, Namespace, StorageDescriptor};
+use crate::agent::{
+    dedup::cosine_similarity,
+    state::{
+        storage::{StorageType, CURRENT_TAG, PREVIOUS_TAG},
+        SharedState, State,
+    },
+};
+use crate::prompt;
+
+const MAX_RESULTS: usize = 10;
+
+// an entry of a storage as shown in the search results
+#[derive(Debug, Clone, PartialEq)]
+struct Found {
+    storage: String,
+    key: String,
+    data: String,
+    score: Option<f64>,
+}
+
+impl std::fmt::Display for Found {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "[{} {}]", self.storage, self.key)?;
+        if let Some(score) = self.score {
+            write!(f, " (score {:.2})", score)?;
+        }
+        write!(f, " {}", self.data.trim())
+    }
+}
+
+// the searchable entries of every storage, named by storage and by key or position
+fn searchable(state: &State) -> Vec<Found> {
+    let mut storages = state.get_storages();
+    storages.sort_by_key(|storage| storage.get_name().to_string());
+
+    let mut entries = vec![];
+    for storage in storages {
+        for (idx, (key, entry)) in storage.iter().enumerate() {
+            let key = match storage.get_type() {
+                StorageType::Time => continue,
+                StorageType::Tagged | StorageType::Tree => key.to_string(),
+                StorageType::Untagged | StorageType::Completion => format!("#{}", idx + 1),
+                StorageType::CurrentPrevious if key == CURRENT_TAG => "current".to_string(),
+                StorageType::CurrentPrevious if key == PREVIOUS_TAG => "previous".to_string(),
+                StorageType::CurrentPrevious => key.to_string(),
+            };
+            entries.push(Found {
+                storage: storage.get_name().to_string(),
+                key,
+                data: entry.data.to_string(),
+                score: None,
+            });
+        }
+    }
+    entries
+}
+
+// entries containing the query or matching it as a regular expression
+fn find_matches(entries: Vec<Found>, query: &str, regex: bool) -> Result<Vec<Found>> {
+    let expr = if regex {
+        Regex::new(query).map_err(|e| anyhow!("invalid regular expression: {}", e))?
+    } else {
+        Regex::new(&format!("(?i){}", regex::escape(query)))?
+    };
+
+    Ok(entries
+        .into_iter()
+        .filter(|found| expr.is_match(&found.key) || expr.is_match(&found.data))
+        .collect())
+}
+
+fn to_results(found: &[Found]) -> String {
+    let mut results: Vec<String> = found
+        .iter()
+        .take(MAX_RESULTS)
+        .map(|f| f.to_string())
+        .collect();
+    if found.len() > MAX_RESULTS {
+        results.push(format!(
+            "... and {} more, refine the query to see them",
+            found.len() - MAX_RESULTS
+        ));
+    }
+    results.join("\n")
+}
+
+#[derive(Debug, Default, Clone)]
+struct SearchMemory {}
+
+#[async_trait]
+impl Action for SearchMemory {
+    fn name(&self) -> &str {
+        "search_memory"
+    }
+
+    fn description(&self) -> &str {
+        prompt!("search.prompt")
+    }
+
+    fn example_attributes(&self) -> Option<HashMap<String, String>> {
+        let mut attributes = HashMap::new();
+
+        attributes.insert("mode".to_string(), "text".to_string());
+
+        Some(attributes)
+    }
+
+    fn example_payload(&self) -> Option<&str> {
+        Some("open ports")
+    }
+
+    async fn run(
+        &self,
+        state: SharedState,
+        attributes: Option<HashMap<String, String>>,
+        payload: Option<String>,
+    ) -> Result<Option<String>> {
+        let query = payload.unwrap_or_default().trim().to_string();
+        if query.is_empty() {
+            return Err(anyhow!("no search query provided"));
+        }
+        let mode = attributes
+            .as_ref()
+            .and_then(|attrs| attrs.get("mode"))
+            .map(|mode| mode.trim().to_lowercase())
+            .unwrap_or("text".to_string());
+
+        let (entries, embedder) = {
+            let state = state.lock().await;
+            (searchable(&state), state.get_embedder())
+        };
+
+        let found = match mode.as_str() {
+            "text" => find_matches(entries, &query, false)?,
+            "regex" => find_matches(entries, &query, true)?,
+            "semantic" => {
+                let query = embedder.embed(&query).await?;
+                let mut scored = vec![];
+                for mut found in entries {
+                    let embeddings = embedder.embed(&found.data).await?;
+                    found.score = Some(cosine_similarity(&query, &embeddings));
+                    scored.push(found);
+                }
+                scored.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap());
+                scored
+            }
+            _ => {
+                return Err(anyhow!(
+                    "invalid search mode '{}', allowed modes are: text, regex, semantic",
+                    mode
+                ))
+            }
+        };
+
+        if found.is_empty() {
+            Ok(Some("no entries found".to_string()))
+        } else {
+            Ok(Some(to_results(&found)))
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+struct SaveMemory {}
+
+#[async_trait]
+impl Action for SaveMemory {
+    fn name(&self) -> &str {
+        "save_memory"
+    }
+
+    fn description(&self) -> &str {
+        prompt!("save.prompt")
+    }
+
+    fn example_attributes(&self) -> Option<HashMap<String, String>> {
+        let mut attributes = HashMap::new();
+
+        attributes.insert("key".to_string(), "my-note".to_string());
+
+        Some(attributes)
+    }
+
+    fn example_payload(&self) -> Option<&str> {
+        Some("put here the custom data you want to keep for later")
+    }
+
+    async fn run(
+        &self,
+        state: SharedState,
+        attributes: Option<HashMap<String, String>>,
+        payload: Option<String>,
+    ) -> Result<Option<String>> {
+        let attrs = attributes.unwrap();
+        let key = attrs.get("key").unwrap();
+
+        state
+            .lock()
+            .await
+            .get_storage_mut("memories")?
+            .add_tagged(key, payload.unwrap().as_str());
+
+        Ok(Some("memory saved".to_string()))
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+struct DeleteMemory {}
+
+#[async_trait]
+impl Action for DeleteMemory {
+    fn name(&self) -> &str {
+        "delete_memory"
+    }
+
+    fn description(&self) -> &str {
+        prompt!("delete.prompt")
+    }
+
+    fn example_attributes(&self) -> Option<HashMap<String, String>> {
+        let mut attributes = HashMap::new();
+
+        attributes.insert("key".to_string(), "my-note".to_string());
+
+        Some(attributes)
+    }
+
+    async fn run(
+        &self,
+        state: SharedState,
+        attributes: Option<HashMap<String, String>>,
+        _: Option<String>,
+    ) -> Result<Option<String>> {
+        let attrs = attributes.unwrap();
+        let key = attrs.get("key").unwrap();
+        if state
+            .lock()
+            .await
+            .get_storage_mut("memories")?
+            .del_tagged(key)
+            .is_some()
+        {
+            Ok(Some("memory deleted".to_string()))
+        } else {
+            Err(anyhow!("memory '{}' not found", key))
+        }
+    }
+}
+
+/*
+#[derive(Debug, Default, Clone)]
+struct RecallMemory {}
+
+#[async_trait]
+impl Action for RecallMemory {
+    fn name(&self) -> &str {
+        "recall_memory"
+    }
+
+    fn description(&self) -> &str {
+        prompt!("recall.prompt")
+    }
+
+    fn example_attributes(&self) -> Option<HashMap<String, String>> {
+        let mut attributes = HashMap::new();
+
+        attributes.insert("key".to_string(), "my-note".to_string());
+
+        Some(attributes)
+    }
+
+    async fn run(
+        &self,
+        state: SharedState,
+        attributes: Option<HashMap<String, String>>,
+        _: Option<String>,
+    ) -> Result<Option<String>> {
+        let attrs = attributes.unwrap();
+        let key = attrs.get("key").unwrap();
+
+        if let Some(memory) = state.lock().await.get_storage("memories")?.get_tagged(key) {
+            Ok(Some(memory))
+        } else {
+            Err(anyhow!("memory '{}' not found", key))
+        }
+    }
+}
+ */
+pub fn get_namespace() -> Namespace {
+    Namespace::new_default(
+        "Memory".to_string(),
+        prompt!("ns.prompt").to_string(),
+        vec![
+            Box::<SaveMemory>::default(),
+            Box::<DeleteMemory>::default(),
+            Box::<SearchMemory>::default(),
+        ],
+        Some(vec![StorageDescriptor::tagged("memories")]),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(storage: &str, key: &str, data: &str) -> Found {
+        Found {
+            storage: storage.to_string(),
+            key: key.to_string(),
+            data: data.to_string(),
+            score: None,
+        }
+    }
+
+    fn entries() -> Vec<Found> {
+        vec![
+            entry("memories", "ports", "22 and 80 are OPEN on 10.0.0.1"),
+            entry("memories", "creds", "admin:admin works on the web panel"),
+            entry("plan", "#1", "scan 10.0.0.2"),
+        ]
+    }
+
+    #[test]
+    fn test_text_search() {
+        let found = find_matches(entries(), "open", false).unwrap();
+        assert_eq!(
+            found,
+            vec![entry("memories", "ports", "22 and 80 are OPEN on 10.0.0.1")]
+        );
+        // dots are not wildcards
+        assert!(find_matches(entries(), "10.0.0.3", false)
+            .unwrap()
+            .is_empty());
+        // keys are searched too
+        assert_eq!(find_matches(entries(), "CREDS", false).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_regex_search() {
+        let found = find_matches(entries(), r"10\.0\.0\.\d", true).unwrap();
+        let keys: Vec<&str> = found.iter().map(|f| f.key.as_str()).collect();
+        assert_eq!(keys, vec!["ports", "#1"]);
+        assert!(find_matches(entries(), "(", true).is_err());
+    }
+
+    #[test]
+    fn test_results() {
+        let mut found = entry("plan", "#1", "scan 10.0.0.2");
+        found.score = Some(0.5);
+        assert_eq!(to_results(&[found]), "[plan #1] (score 0.50) scan 10.0.0.2");
+
+        let many: Vec<Found> = (0..12).map(|i| entry("m", &i.to_string(), "x")).collect();
+        assert!(to_results(&many).ends_with("... and 2 more, refine the query to see them"));
+    }
+}
@@ -5,20 +5,32 @@ use async_trait::async_trait;
 use indexmap::IndexMap;
 use lazy_static::lazy_static;
 
-use super::state::{storage::StorageType, SharedState};
+use super::{
+    state::{storage::StorageType, SharedState},
+    task::retry::RetryPolicy,
+};
 
 // TODO: add more namespaces of actions: take screenshot (multimodal), move mouse, ui interactions, etc
 
+pub mod exploration;
+#[cfg(feature = "filesystem")]
 pub mod filesystem;
 pub mod goal;
+pub mod handoff;
+#[cfg(feature = "http")]
 pub mod http;
+pub mod mailbox;
 pub mod memory;
+pub mod outputs;
 pub mod planning;
 pub mod rag;
 pub mod shell;
 pub mod task;
 pub mod time;
 
+// namespaces that can be left out of the build, each by the feature with its name
+const OPTIONAL_NAMESPACES: [&str; 3] = ["filesystem", "http", "shell"];
+
 lazy_static! {
     // Available namespaces.
     pub static ref NAMESPACES: IndexMap<String, fn() -> Namespace> = {
@@ -29,15 +41,80 @@ lazy_static! {
         map.insert("goal".to_string(), goal::get_namespace as fn() -> Namespace);
         map.insert("planning".to_string(), planning::get_namespace as fn() -> Namespace);
         map.insert("task".to_string(), task::get_namespace as fn() -> Namespace);
+        #[cfg(feature = "filesystem")]
         map.insert("filesystem".to_string(), filesystem::get_namespace as fn() -> Namespace);
         map.insert("rag".to_string(), rag::get_namespace as fn() -> Namespace);
+        #[cfg(feature = "http")]
         map.insert("http".to_string(), http::get_namespace as fn() -> Namespace);
+        map.insert("mailbox".to_string(), mailbox::get_namespace as fn() -> Namespace);
+        map.insert("outputs".to_string(), outputs::get_namespace as fn() -> Namespace);
+        map.insert("exploration".to_string(), exploration::get_namespace as fn() -> Namespace);
+        #[cfg(feature = "shell")]
         map.insert("shell".to_string(), shell::get_namespace as fn() -> Namespace);
 
         map
     };
 }
 
+// resolve the names of the namespaces to use given the task `using` list, where "*" means all
+// default namespaces and "!name" excludes a namespace, if no list is provided the default ones are used
+pub fn resolve_used_namespaces(using: Option<Vec<String>>) -> Result<Vec<String>> {
+    let defaults = || {
+        NAMESPACES
+            .iter()
+            .filter(|(_, build_fn)| build_fn().default)
+            .map(|(name, _)| name.to_string())
+            .collect::<Vec<String>>()
+    };
+
+    let using = if let Some(using) = using {
+        using
+    } else {
+        return Ok(defaults());
+    };
+
+    let mut excluded = vec![];
+    let mut included = vec![];
+    for name in &using {
+        let name = name.trim();
+        if let Some(excl) = name.strip_prefix('!') {
+            excluded.push(excl.trim().to_string());
+        } else {
+            included.push(name.to_string());
+        }
+    }
+
+    for name in excluded.iter().chain(included.iter()) {
+        if name != "*" && !NAMESPACES.contains_key(name) {
+            if OPTIONAL_NAMESPACES.contains(&name.as_str()) {
+                return Err(anyhow!(
+                    "namespace '{}' is not available, nerve was built without the {} feature",
+                    name,
+                    name
+                ));
+            }
+            return Err(anyhow!("no namespace '{}' defined", name));
+        }
+    }
+
+    // only exclusions are the same as excluding from all the default namespaces
+    if included.is_empty() && !excluded.is_empty() {
+        included.push("*".to_string());
+    }
+
+    let mut resolved: Vec<String> = vec![];
+    for name in included {
+        let names = if name == "*" { defaults() } else { vec![name] };
+        for name in names {
+            if !excluded.contains(&name) && !resolved.contains(&name) {
+                resolved.push(name);
+            }
+        }
+    }
+
+    Ok(resolved)
+}
+
 #[derive(Debug, Default)]
 pub struct StorageDescriptor {
     pub name: String,
@@ -80,6 +157,17 @@ impl StorageDescriptor {
         }
     }
 
+    pub fn tree(name: &str) -> Self {
+        let name = name.to_string();
+        let type_ = StorageType::Tree;
+        let predefined = None;
+        Self {
+            name,
+            type_,
+            predefined,
+        }
+    }
+
     pub fn completion(name: &str) -> Self {
         let name = name.to_string();
         let type_ = StorageType::Completion;
@@ -169,6 +257,16 @@ pub trait Action: std::fmt::Debug + Sync + Send + ActionClone {
         None
     }
 
+    // optional retry policy for transient failures
+    fn retry_policy(&self) -> Option<RetryPolicy> {
+        None
+    }
+
+    // optional rate limits the action counts against, like network
+    fn rate_limits(&self) -> Vec<String> {
+        vec![]
+    }
+
     // optional example attributes
     fn example_attributes(&self) -> Option<HashMap<String, String>> {
         None
@@ -189,6 +287,11 @@ pub trait Action: std::fmt::Debug + Sync + Send + ActionClone {
         false
     }
 
+    // optional method to indicate if this action has side effects on the system, used by the destructive confirmation policy
+    fn is_destructive(&self) -> bool {
+        self.requires_user_confirmation()
+    }
+
     // complete the task after execution
     fn complete_task(&self) -> bool {
         false
@@ -222,3 +325,35 @@ impl Clone for Box<dyn Action> {
         self.clone_box()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn resolve(using: &[&str]) -> Result<Vec<String>> {
+        resolve_used_namespaces(Some(using.iter().map(|s| s.to_string()).collect()))
+    }
+
+    #[test]
+    fn test_wildcard_with_exclusions() {
+        let resolved = resolve(&["*", "!planning", "!goal", "shell"]).unwrap();
+        assert_eq!(resolved, vec!["memory", "time", "task", "shell"]);
+    }
+
+    #[test]
+    fn test_only_exclusions_implies_wildcard() {
+        let resolved = resolve(&["!time"]).unwrap();
+        assert_eq!(resolved, vec!["memory", "goal", "planning", "task"]);
+    }
+
+    #[test]
+    fn test_excluding_unknown_namespace_is_error() {
+        assert!(resolve(&["*", "!nope"]).is_err());
+    }
+
+    #[test]
+    fn test_no_using_means_defaults() {
+        let resolved = resolve_used_namespaces(None).unwrap();
+        assert_eq!(resolved, vec!["memory", "time", "goal", "planning", "task"]);
+    }
+}
@@ -0,0 +1,121 @@
+use std::collections::HashMap;
+
+use anyhow::Result;
+use async_trait::async_trait;
+
+use super::{Action, Namespace};
+use crate::agent::state::SharedState;
+use crate::prompt;
+
+// split text in pages of at most page_size characters, breaking at line boundaries when possible
+pub fn paginate(text: &str, page_size: usize) -> Vec<String> {
+    let mut pages = vec![];
+    let mut current = String::new();
+    let mut current_len = 0;
+
+    for line in text.split_inclusive('\n') {
+        let line_len = line.chars().count();
+        if current_len + line_len > page_size && !current.is_empty() {
+            pages.push(std::mem::take(&mut current));
+            current_len = 0;
+        }
+
+        if line_len > page_size {
+            // a single line longer than a page
+            let chars: Vec<char> = line.chars().collect();
+            for chunk in chars.chunks(page_size) {
+                if chunk.len() == page_size {
+                    pages.push(chunk.iter().collect());
+                } else {
+                    current = chunk.iter().collect();
+                    current_len = chunk.len();
+                }
+            }
+        } else {
+            current.push_str(line);
+            current_len += line_len;
+        }
+    }
+
+    if !current.is_empty() {
+        pages.push(current);
+    }
+
+    pages
+}
+
+#[derive(Debug, Default, Clone)]
+struct ReadOutput {}
+
+#[async_trait]
+impl Action for ReadOutput {
+    fn name(&self) -> &str {
+        "read_output"
+    }
+
+    fn description(&self) -> &str {
+        prompt!("read.prompt")
+    }
+
+    fn example_attributes(&self) -> Option<HashMap<String, String>> {
+        let mut attributes = HashMap::new();
+
+        attributes.insert("id".to_string(), "1".to_string());
+        attributes.insert("page".to_string(), "2".to_string());
+
+        Some(attributes)
+    }
+
+    async fn run(
+        &self,
+        state: SharedState,
+        attributes: Option<HashMap<String, String>>,
+        _: Option<String>,
+    ) -> Result<Option<String>> {
+        let attrs = attributes.unwrap();
+        let id = attrs
+            .get("id")
+            .unwrap()
+            .trim()
+            .parse::<usize>()
+            .map_err(|_| anyhow!("id must be a number"))?;
+        let page = attrs
+            .get("page")
+            .unwrap()
+            .trim()
+            .parse::<usize>()
+            .map_err(|_| anyhow!("page must be a number"))?;
+
+        let (data, total) = state.lock().await.get_output_page(id, page)?;
+
+        Ok(Some(format!(
+            "[output {} page {}/{}]\n{}",
+            id, page, total, data
+        )))
+    }
+}
+
+pub fn get_namespace() -> Namespace {
+    Namespace::new_non_default(
+        "Outputs".to_string(),
+        prompt!("ns.prompt").to_string(),
+        vec![Box::<ReadOutput>::default()],
+        None,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_paginate() {
+        assert_eq!(paginate("aa\nbb\ncc\n", 6), vec!["aa\nbb\n", "cc\n"]);
+        assert_eq!(paginate("abcdefgh", 3), vec!["abc", "def", "gh"]);
+        assert_eq!(
+            paginate("ab\ncdefgh\ni", 3),
+            vec!["ab\n", "cde", "fgh", "\ni"]
+        );
+        assert!(paginate("", 3).is_empty());
+    }
+}
@@ -5,6 +5,7 @@ use async_trait::async_trait;
 
 use super::{Action, Namespace, StorageDescriptor};
 use crate::agent::state::SharedState;
+use crate::prompt;
 
 #[derive(Debug, Default, Clone)]
 struct AddStep {}
@@ -16,7 +17,7 @@ impl Action for AddStep {
     }
 
     fn description(&self) -> &str {
-        include_str!("add.prompt")
+        prompt!("add.prompt")
     }
 
     fn example_payload(&self) -> Option<&str> {
@@ -48,7 +49,7 @@ impl Action for DeleteStep {
     }
 
     fn description(&self) -> &str {
-        include_str!("delete.prompt")
+        prompt!("delete.prompt")
     }
 
     fn example_payload(&self) -> Option<&str> {
@@ -80,7 +81,7 @@ impl Action for SetComplete {
     }
 
     fn description(&self) -> &str {
-        include_str!("set-complete.prompt")
+        prompt!("set-complete.prompt")
     }
 
     fn example_payload(&self) -> Option<&str> {
@@ -118,7 +119,7 @@ impl Action for SetIncomplete {
     }
 
     fn description(&self) -> &str {
-        include_str!("set-incomplete.prompt")
+        prompt!("set-incomplete.prompt")
     }
 
     fn example_payload(&self) -> Option<&str> {
@@ -156,7 +157,7 @@ impl Action for Clear {
     }
 
     fn description(&self) -> &str {
-        include_str!("clear.prompt")
+        prompt!("clear.prompt")
     }
 
     async fn run(
@@ -173,7 +174,7 @@ impl Action for Clear {
 pub fn get_namespace() -> Namespace {
     Namespace::new_default(
         "Planning".to_string(),
-        include_str!("ns.prompt").to_string(),
+        prompt!("ns.prompt").to_string(),
         vec![
             Box::<AddStep>::default(),
             Box::<DeleteStep>::default(),
@@ -0,0 +1,112 @@
+use std::{collections::HashMap, time::Instant};
+
+use anyhow::Result;
+use async_trait::async_trait;
+
+use crate::agent::{state::SharedState, task::rag::RetrievedDocument};
+
+use super::{Action, Namespace};
+use crate::prompt;
+
+// numbered results with their source so that the model can cite them
+fn to_results(docs: &[RetrievedDocument]) -> String {
+    docs.iter()
+        .enumerate()
+        .map(|(idx, doc)| {
+            format!(
+                "[{}] {} (score {:.2})\n{}",
+                idx + 1,
+                doc.citation(),
+                doc.score,
+                doc.data.trim_end()
+            )
+        })
+        .collect::<Vec<String>>()
+        .join("\n\n")
+}
+
+#[derive(Debug, Default, Clone)]
+struct Search {}
+
+#[async_trait]
+impl Action for Search {
+    fn name(&self) -> &str {
+        "search"
+    }
+
+    fn description(&self) -> &str {
+        prompt!("search.prompt")
+    }
+
+    fn example_payload(&self) -> Option<&str> {
+        Some("what is the biggest city in the world?")
+    }
+
+    async fn run(
+        &self,
+        state: SharedState,
+        _: Option<HashMap<String, String>>,
+        payload: Option<String>,
+    ) -> Result<Option<String>> {
+        let query = payload.unwrap();
+        let start = Instant::now();
+        // TODO: make top_k configurable?
+        let docs = state.lock().await.rag_query(&query, 1).await?;
+
+        if !docs.is_empty() {
+            log::debug!(
+                "rag search for '{}': {} results in {:?}",
+                query,
+                docs.len(),
+                start.elapsed()
+            );
+
+            Ok(Some(format!(
+                "Here is some supporting information:\n\n{}",
+                to_results(&docs)
+            )))
+        } else {
+            log::debug!(
+                "search: no results for query '{query}' in {:?}",
+                start.elapsed()
+            );
+            Ok(Some("no documents for this query".to_string()))
+        }
+    }
+}
+
+pub fn get_namespace() -> Namespace {
+    Namespace::new_non_default(
+        "Knowledge".to_string(),
+        prompt!("ns.prompt").to_string(),
+        vec![Box::<Search>::default()],
+        None,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_results_are_citable() {
+        let docs = vec![
+            RetrievedDocument {
+                path: "/docs/guide.md".to_string(),
+                offset: Some(120),
+                data: "the admin password is in the vault\n".to_string(),
+                score: 0.834,
+            },
+            RetrievedDocument {
+                path: "/docs/legacy.txt".to_string(),
+                offset: None,
+                data: "nothing here".to_string(),
+                score: 0.5,
+            },
+        ];
+        assert_eq!(
+            to_results(&docs),
+            "[1] /docs/guide.md@120 (score 0.83)\nthe admin password is in the vault\n\n[2] /docs/legacy.txt (score 0.50)\nnothing here"
+        );
+    }
+}
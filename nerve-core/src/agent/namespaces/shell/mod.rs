@@ -0,0 +1,272 @@
+#[cfg(feature = "shell")]
+use std::collections::HashMap;
+
+#[cfg(feature = "shell")]
+use anyhow::Result;
+#[cfg(feature = "shell")]
+use async_trait::async_trait;
+use base64::{engine::general_purpose::STANDARD, Engine};
+#[cfg(feature = "shell")]
+use tokio::process::Command;
+
+#[cfg(feature = "shell")]
+use crate::agent::{state::SharedState, streaming};
+
+#[cfg(feature = "shell")]
+use super::{Action, Namespace};
+#[cfg(feature = "shell")]
+use crate::prompt;
+
+#[cfg(windows)]
+const DEFAULT_SHELL: &str = "powershell";
+#[cfg(not(windows))]
+const DEFAULT_SHELL: &str = "/bin/sh";
+
+// the program executing the shell commands and hooks, selected by its name
+#[derive(Debug, Clone, PartialEq)]
+pub enum Interpreter {
+    Posix(String),
+    Cmd(String),
+    PowerShell(String),
+}
+
+impl Interpreter {
+    pub fn from_program(program: &str) -> Self {
+        // windows paths are accepted on the other platforms too, so that the selection is the same
+        let name = program
+            .rsplit(['/', '\\'])
+            .next()
+            .and_then(|file| file.split('.').next())
+            .unwrap_or_default()
+            .to_lowercase();
+        match name.as_str() {
+            "cmd" => Self::Cmd(program.to_string()),
+            "powershell" | "pwsh" => Self::PowerShell(program.to_string()),
+            _ => Self::Posix(program.to_string()),
+        }
+    }
+
+    // $NERVE_SHELL or the default one of the platform
+    pub fn system() -> Self {
+        Self::from_program(&std::env::var("NERVE_SHELL").unwrap_or(DEFAULT_SHELL.to_string()))
+    }
+
+    pub fn program(&self) -> &str {
+        match self {
+            Self::Posix(program) | Self::Cmd(program) | Self::PowerShell(program) => program,
+        }
+    }
+
+    #[cfg(feature = "shell")]
+    fn example(&self) -> &'static str {
+        match self {
+            Self::Posix(_) => "ls -la",
+            Self::Cmd(_) => "dir /a",
+            Self::PowerShell(_) => "Get-ChildItem -Force",
+        }
+    }
+
+    fn args(&self, script: &str) -> Vec<String> {
+        match self {
+            Self::Posix(_) => vec!["-c".to_string(), script.to_string()],
+            Self::Cmd(_) => vec!["/C".to_string(), script.to_string()],
+            // encoded as utf-16le base64 so that quotes survive the windows command line parsing
+            Self::PowerShell(_) => vec![
+                "-NoProfile".to_string(),
+                "-NonInteractive".to_string(),
+                "-EncodedCommand".to_string(),
+                STANDARD.encode(
+                    script
+                        .encode_utf16()
+                        .flat_map(|unit| unit.to_le_bytes())
+                        .collect::<Vec<u8>>(),
+                ),
+            ],
+        }
+    }
+
+    // the command executing the script
+    pub fn command(&self, script: &str) -> std::process::Command {
+        let mut cmd = std::process::Command::new(self.program());
+        crate::agent::secrets::scrub_env(&mut cmd);
+        let args = self.args(script);
+        match self {
+            // cmd doesn't follow the quoting rules of the other programs, the script is passed as is
+            #[cfg(windows)]
+            Self::Cmd(_) => {
+                use std::os::windows::process::CommandExt;
+
+                cmd.arg(&args[0]).raw_arg(&args[1]);
+            }
+            _ => {
+                cmd.args(args);
+            }
+        }
+        cmd
+    }
+}
+
+#[cfg(feature = "shell")]
+#[derive(Debug, Clone)]
+struct Shell {
+    interpreter: Interpreter,
+    description: String,
+}
+
+#[cfg(feature = "shell")]
+impl Shell {
+    fn new(interpreter: Interpreter) -> Self {
+        let mut description = prompt!("shell.prompt").to_string();
+        if !matches!(interpreter, Interpreter::Posix(_)) {
+            description += &format!(
+                " The commands are executed by {} on {}.",
+                interpreter.program(),
+                std::env::consts::OS
+            );
+        }
+        Self {
+            interpreter,
+            description,
+        }
+    }
+}
+
+#[cfg(feature = "shell")]
+#[async_trait]
+impl Action for Shell {
+    fn name(&self) -> &str {
+        "shell"
+    }
+
+    fn description(&self) -> &str {
+        &self.description
+    }
+
+    fn example_payload(&self) -> Option<&str> {
+        Some(self.interpreter.example())
+    }
+
+    fn requires_user_confirmation(&self) -> bool {
+        // this one definitely does
+        true
+    }
+
+    async fn run(
+        &self,
+        state: SharedState,
+        _: Option<HashMap<String, String>>,
+        payload: Option<String>,
+    ) -> Result<Option<String>> {
+        let command = payload.unwrap();
+        state.lock().await.get_policy().shell.check(&command)?;
+        log::warn!("executing command: {}", &command);
+
+        // streamed while it runs, and terminated if the step is aborted
+        let output = streaming::run_command(
+            Command::from(self.interpreter.command(&command)),
+            None,
+            &state,
+            None,
+        )
+        .await?;
+
+        let mut result = String::from_utf8_lossy(&output.stdout).to_string();
+
+        let stderr = String::from_utf8_lossy(&output.stderr).to_string();
+        if !stderr.is_empty() {
+            result += &format!("\nSTDERR: {}\n", stderr);
+        }
+
+        let exit_code = output.status.code().unwrap_or(-1);
+        if exit_code != 0 {
+            result += &format!("\nEXIT CODE: {}", exit_code);
+        }
+
+        log::debug!("{}", &result);
+
+        Ok(Some(result))
+    }
+}
+
+#[cfg(feature = "shell")]
+pub fn get_namespace() -> Namespace {
+    Namespace::new_non_default(
+        "Shell".to_string(),
+        prompt!("ns.prompt").to_string(),
+        vec![Box::new(Shell::new(Interpreter::system()))],
+        None,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_interpreter_selection() {
+        assert_eq!(
+            Interpreter::from_program("/bin/bash"),
+            Interpreter::Posix("/bin/bash".to_string())
+        );
+        assert_eq!(
+            Interpreter::from_program(r"C:\Windows\System32\cmd.exe"),
+            Interpreter::Cmd(r"C:\Windows\System32\cmd.exe".to_string())
+        );
+        assert_eq!(
+            Interpreter::from_program("PowerShell.exe"),
+            Interpreter::PowerShell("PowerShell.exe".to_string())
+        );
+        assert_eq!(
+            Interpreter::from_program("pwsh"),
+            Interpreter::PowerShell("pwsh".to_string())
+        );
+    }
+
+    #[test]
+    fn test_interpreter_args() {
+        assert_eq!(
+            Interpreter::Posix("sh".to_string()).args("echo 'hi'"),
+            ["-c", "echo 'hi'"]
+        );
+        assert_eq!(
+            Interpreter::Cmd("cmd".to_string()).args("dir /a"),
+            ["/C", "dir /a"]
+        );
+        assert_eq!(
+            Interpreter::PowerShell("pwsh".to_string()).args("dir"),
+            [
+                "-NoProfile",
+                "-NonInteractive",
+                "-EncodedCommand",
+                "ZABpAHIA"
+            ]
+        );
+    }
+
+    #[cfg(feature = "shell")]
+    #[test]
+    fn test_description() {
+        let shell = Shell::new(Interpreter::PowerShell("powershell".to_string()));
+        assert!(shell.description().ends_with(&format!(
+            "executed by powershell on {}.",
+            std::env::consts::OS
+        )));
+        assert_eq!(shell.example_payload(), Some("Get-ChildItem -Force"));
+        assert_eq!(
+            Shell::new(Interpreter::Posix("/bin/sh".to_string())).description(),
+            prompt!("shell.prompt")
+        );
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_command() {
+        let output = tokio::process::Command::from(
+            Interpreter::Posix("/bin/sh".to_string()).command("echo \"$0\""),
+        )
+        .output()
+        .await
+        .unwrap();
+        assert_eq!(String::from_utf8_lossy(&output.stdout), "/bin/sh\n");
+    }
+}
@@ -5,6 +5,10 @@ use async_trait::async_trait;
 
 use super::{Action, Namespace};
 use crate::agent::state::SharedState;
+use crate::prompt;
+
+// the action reporting the final answer of the task
+pub const COMPLETE_ACTION: &str = "task_complete";
 
 #[derive(Debug, Default, Clone)]
 struct Complete {}
@@ -12,11 +16,11 @@ struct Complete {}
 #[async_trait]
 impl Action for Complete {
     fn name(&self) -> &str {
-        "task_complete"
+        COMPLETE_ACTION
     }
 
     fn description(&self) -> &str {
-        include_str!("complete.prompt")
+        prompt!("complete.prompt")
     }
 
     fn example_payload(&self) -> Option<&str> {
@@ -44,7 +48,7 @@ impl Action for Impossible {
     }
 
     fn description(&self) -> &str {
-        include_str!("impossible.prompt")
+        prompt!("impossible.prompt")
     }
 
     fn example_payload(&self) -> Option<&str> {
@@ -65,7 +69,7 @@ impl Action for Impossible {
 pub fn get_namespace() -> Namespace {
     Namespace::new_default(
         "Task".to_string(),
-        include_str!("ns.prompt").to_string(),
+        prompt!("ns.prompt").to_string(),
         vec![Box::<Complete>::default(), Box::<Impossible>::default()],
         None,
     )
@@ -5,6 +5,7 @@ use std::{collections::HashMap, time::Duration};
 
 use super::{Action, Namespace, StorageDescriptor};
 use crate::agent::state::SharedState;
+use crate::prompt;
 
 #[derive(Debug, Default, Clone)]
 struct Wait {}
@@ -16,7 +17,7 @@ impl Action for Wait {
     }
 
     fn description(&self) -> &str {
-        include_str!("wait.prompt")
+        prompt!("wait.prompt")
     }
 
     fn example_payload(&self) -> Option<&str> {
@@ -0,0 +1,110 @@
+use super::{state::session::Session, Invocation};
+
+// namespaces whose actions only change the agent own state, these are always executed so that
+// memories, plans, goals and so on are rebuilt exactly as in the recorded run
+const STATEFUL_NAMESPACES: &[&str] = &["memory", "goal", "planning", "task", "outputs", "mailbox"];
+
+// action outputs recorded in a previous session, returned instead of executing the actions
+#[derive(Debug, Default)]
+pub struct PinnedOutputs {
+    // recorded invocations and their result or error, in order of execution
+    outputs: Vec<(Invocation, Result<Option<String>, String>)>,
+    // outputs that have been used already
+    used: Vec<bool>,
+}
+
+impl PinnedOutputs {
+    pub fn from_session(session: &Session) -> Self {
+        let mut outputs = vec![];
+        for execution in session.history.iter() {
+            if let Some(invocation) = execution.invocation() {
+                let output = match execution.error() {
+                    Some(error) => Err(error.to_string()),
+                    None => Ok(execution.result().cloned()),
+                };
+                outputs.push((invocation.clone(), output));
+            }
+        }
+
+        let used = vec![false; outputs.len()];
+        Self { outputs, used }
+    }
+
+    pub fn len(&self) -> usize {
+        self.outputs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.outputs.is_empty()
+    }
+
+    pub fn is_pinnable(namespace: Option<&str>) -> bool {
+        !namespace.is_some_and(|ns| STATEFUL_NAMESPACES.contains(&ns))
+    }
+
+    // return the first unused output recorded for the same invocation
+    pub fn take(&mut self, invocation: &Invocation) -> Option<Result<Option<String>, String>> {
+        let index = self
+            .outputs
+            .iter()
+            .enumerate()
+            .position(|(idx, (recorded, _))| !self.used[idx] && recorded == invocation)?;
+
+        self.used[index] = true;
+        Some(self.outputs[index].1.clone())
+    }
+
+    pub fn unused(&self) -> Vec<&Invocation> {
+        self.outputs
+            .iter()
+            .zip(self.used.iter())
+            .filter(|(_, used)| !**used)
+            .map(|((inv, _), _)| inv)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    fn invocation(action: &str, payload: &str) -> Invocation {
+        Invocation::new(action.to_string(), None, Some(payload.to_string()))
+    }
+
+    #[test]
+    fn test_take_in_order() {
+        let ls = invocation("shell", "ls");
+        let mut pinned = PinnedOutputs {
+            outputs: vec![
+                (ls.clone(), Ok(Some("a".to_string()))),
+                (invocation("shell", "id"), Err("denied".to_string())),
+                (ls.clone(), Ok(Some("a b".to_string()))),
+            ],
+            used: vec![false; 3],
+        };
+
+        assert_eq!(pinned.take(&ls), Some(Ok(Some("a".to_string()))));
+        assert_eq!(pinned.take(&ls), Some(Ok(Some("a b".to_string()))));
+        assert_eq!(pinned.take(&ls), None);
+        assert_eq!(
+            pinned.take(&invocation("shell", "id")),
+            Some(Err("denied".to_string()))
+        );
+        assert!(pinned.unused().is_empty());
+
+        let mut attributes = HashMap::new();
+        attributes.insert("path".to_string(), "/".to_string());
+        let other = Invocation::new("read_file".to_string(), Some(attributes), None);
+        assert_eq!(pinned.take(&other), None);
+    }
+
+    #[test]
+    fn test_is_pinnable() {
+        assert!(PinnedOutputs::is_pinnable(Some("shell")));
+        assert!(PinnedOutputs::is_pinnable(None));
+        assert!(!PinnedOutputs::is_pinnable(Some("memory")));
+    }
+}
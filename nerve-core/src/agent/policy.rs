@@ -0,0 +1,328 @@
+use std::{
+    net::{IpAddr, SocketAddr},
+    path::{Path, PathBuf},
+};
+
+use anyhow::Result;
+use regex::Regex;
+use serde::Deserialize;
+use url::{Host, Url};
+
+// where the filesystem actions can read and write
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct FilesystemPolicy {
+    // if not empty, only paths inside these folders can be accessed
+    #[serde(default)]
+    pub roots: Vec<String>,
+    #[serde(default)]
+    pub confirm: bool,
+}
+
+// resolve symlinks and .. for paths that might not exist yet
+fn normalize(path: &Path) -> PathBuf {
+    if let Ok(canon) = path.canonicalize() {
+        return canon;
+    }
+
+    match (path.parent(), path.file_name()) {
+        (Some(parent), Some(name)) => normalize(parent).join(name),
+        _ => path.to_path_buf(),
+    }
+}
+
+impl FilesystemPolicy {
+    pub fn check(&self, path: &str) -> Result<()> {
+        if self.roots.is_empty() {
+            return Ok(());
+        }
+
+        let path = normalize(&std::env::current_dir()?.join(path));
+        for root in &self.roots {
+            if path.starts_with(normalize(&std::env::current_dir()?.join(root))) {
+                return Ok(());
+            }
+        }
+
+        Err(anyhow!(
+            "access to {} is not allowed by the filesystem policy, allowed folders are: {}",
+            path.display(),
+            self.roots.join(", ")
+        ))
+    }
+}
+
+// which hosts the http actions can reach
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct HttpPolicy {
+    // if not empty, only these hosts (example.com or *.example.com), addresses and networks
+    // (10.0.0.0/8) can be reached
+    #[serde(default)]
+    pub targets: Vec<String>,
+    #[serde(default)]
+    pub confirm: bool,
+}
+
+// parse 10.0.0.0/8, fe80::/10 or a single address
+fn parse_network(target: &str) -> Option<(IpAddr, u32)> {
+    let (addr, prefix) = match target.split_once('/') {
+        Some((addr, prefix)) => (addr.parse::<IpAddr>().ok()?, Some(prefix.parse().ok()?)),
+        None => (target.parse::<IpAddr>().ok()?, None),
+    };
+
+    let max = if addr.is_ipv4() { 32 } else { 128 };
+    let prefix = prefix.unwrap_or(max);
+    if prefix > max {
+        return None;
+    }
+
+    Some((addr, prefix))
+}
+
+fn in_network(ip: &IpAddr, network: &(IpAddr, u32)) -> bool {
+    let (net, prefix) = network;
+    match (ip, net) {
+        (IpAddr::V4(ip), IpAddr::V4(net)) => {
+            let mask = u32::MAX.checked_shl(32 - prefix).unwrap_or(0);
+            u32::from(*ip) & mask == u32::from(*net) & mask
+        }
+        (IpAddr::V6(ip), IpAddr::V6(net)) => {
+            let mask = u128::MAX.checked_shl(128 - prefix).unwrap_or(0);
+            u128::from(*ip) & mask == u128::from(*net) & mask
+        }
+        _ => false,
+    }
+}
+
+impl HttpPolicy {
+    pub fn validate(&self) -> Result<()> {
+        for target in &self.targets {
+            if target.contains('/') && parse_network(target).is_none() {
+                return Err(anyhow!("invalid network '{}' in http policy", target));
+            }
+        }
+        Ok(())
+    }
+
+    fn matches_domain(&self, domain: &str) -> bool {
+        let domain = domain.to_lowercase();
+        self.targets.iter().any(|target| {
+            let target = target.to_lowercase();
+            if let Some(suffix) = target.strip_prefix("*.") {
+                domain.ends_with(&format!(".{}", suffix))
+            } else {
+                domain == target
+            }
+        })
+    }
+
+    fn matches_address(&self, ip: &IpAddr) -> bool {
+        self.targets
+            .iter()
+            .filter_map(|target| parse_network(target))
+            .any(|network| in_network(ip, &network))
+    }
+
+    pub async fn check(&self, url: &Url) -> Result<()> {
+        self.resolve(url).await.map(|_| ())
+    }
+
+    // check the url and return the addresses its host was allowed for, if it was allowed by
+    // address rather than by name: the request must connect to these ones so that the host
+    // can't resolve to something out of scope afterwards
+    pub async fn resolve(&self, url: &Url) -> Result<Vec<SocketAddr>> {
+        if self.targets.is_empty() {
+            return Ok(vec![]);
+        }
+
+        let (allowed, addrs) = match url.host() {
+            Some(Host::Ipv4(ip)) => (self.matches_address(&IpAddr::V4(ip)), vec![]),
+            Some(Host::Ipv6(ip)) => (self.matches_address(&IpAddr::V6(ip)), vec![]),
+            Some(Host::Domain(domain)) => {
+                if self.matches_domain(domain) {
+                    (true, vec![])
+                } else {
+                    // the host is allowed if all its addresses are in scope
+                    let port = url.port_or_known_default().unwrap_or(80);
+                    let addrs: Vec<_> = tokio::net::lookup_host((domain, port))
+                        .await
+                        .map(|addrs| addrs.collect())
+                        .unwrap_or_default();
+                    (
+                        !addrs.is_empty() && addrs.iter().all(|a| self.matches_address(&a.ip())),
+                        addrs,
+                    )
+                }
+            }
+            None => (false, vec![]),
+        };
+
+        if allowed {
+            Ok(addrs)
+        } else {
+            Err(anyhow!(
+                "{} is not allowed by the http policy, allowed targets are: {}",
+                url.host_str().unwrap_or_default(),
+                self.targets.join(", ")
+            ))
+        }
+    }
+}
+
+// what the shell action can execute
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct ShellPolicy {
+    // commands matching any of these expressions are rejected
+    #[serde(default)]
+    pub deny: Vec<String>,
+    #[serde(default)]
+    pub confirm: bool,
+}
+
+impl ShellPolicy {
+    pub fn validate(&self) -> Result<()> {
+        for expr in &self.deny {
+            Regex::new(expr).map_err(|e| anyhow!("invalid shell policy expression: {}", e))?;
+        }
+        Ok(())
+    }
+
+    pub fn check(&self, command: &str) -> Result<()> {
+        for expr in &self.deny {
+            if Regex::new(expr)?.is_match(command) {
+                return Err(anyhow!(
+                    "command not allowed by the shell policy ({})",
+                    expr
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+// per namespace constraints enforced by the actions themselves
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct Policy {
+    #[serde(default)]
+    pub filesystem: FilesystemPolicy,
+    #[serde(default)]
+    pub http: HttpPolicy,
+    #[serde(default)]
+    pub shell: ShellPolicy,
+}
+
+impl Policy {
+    pub fn from_path(path: &str) -> Result<Self> {
+        let yaml = std::fs::read_to_string(path)
+            .map_err(|e| anyhow!("could not read policy from {}: {}", path, e))?;
+        let policy: Self = serde_yaml::from_str(&yaml)?;
+        policy.validate()?;
+        Ok(policy)
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        self.http.validate()?;
+        self.shell.validate()
+    }
+
+    pub fn requires_confirmation(&self, namespace: Option<&str>) -> bool {
+        match namespace {
+            Some("filesystem") => self.filesystem.confirm,
+            Some("web") => self.http.confirm,
+            Some("shell") => self.shell.confirm,
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_filesystem() {
+        let tmp = std::env::temp_dir();
+        let policy = FilesystemPolicy {
+            roots: vec![tmp.display().to_string()],
+            confirm: false,
+        };
+
+        assert!(policy
+            .check(&tmp.join("foo/bar.txt").display().to_string())
+            .is_ok());
+        assert!(policy.check("/etc/passwd").is_err());
+        assert!(policy
+            .check(&tmp.join("../etc/passwd").display().to_string())
+            .is_err());
+        assert!(FilesystemPolicy::default().check("/etc/passwd").is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_http() {
+        let policy = HttpPolicy {
+            targets: vec![
+                "example.com".to_string(),
+                "*.test.org".to_string(),
+                "10.0.0.0/8".to_string(),
+                "::1".to_string(),
+            ],
+            confirm: false,
+        };
+        assert!(policy.validate().is_ok());
+
+        let check = |url: &str| {
+            let url = Url::parse(url).unwrap();
+            let policy = policy.clone();
+            async move { policy.check(&url).await.is_ok() }
+        };
+
+        assert!(check("https://example.com/index.php").await);
+        assert!(check("https://api.test.org/").await);
+        assert!(!check("https://test.org/").await);
+        assert!(check("http://10.1.2.3:8080/").await);
+        assert!(!check("http://192.168.1.1/").await);
+        assert!(check("http://[::1]/").await);
+        assert!(!check("http://[::2]/").await);
+
+        // allowed by address, the checked ones are returned to connect to them
+        let loopback = HttpPolicy {
+            targets: vec!["127.0.0.0/8".to_string(), "::1".to_string()],
+            confirm: false,
+        };
+        let addrs = loopback
+            .resolve(&Url::parse("http://localhost:8080/").unwrap())
+            .await
+            .unwrap();
+        assert!(!addrs.is_empty());
+        assert!(addrs.iter().all(|addr| addr.ip().is_loopback()));
+        assert!(policy
+            .resolve(&Url::parse("https://example.com/").unwrap())
+            .await
+            .unwrap()
+            .is_empty());
+
+        assert!(HttpPolicy {
+            targets: vec!["10.0.0.0/33".to_string()],
+            confirm: false
+        }
+        .validate()
+        .is_err());
+    }
+
+    #[test]
+    fn test_shell() {
+        let policy: Policy = serde_yaml::from_str(
+            r#"
+shell:
+  deny: ['rm\s+-rf', '^sudo ']
+  confirm: true
+"#,
+        )
+        .unwrap();
+
+        assert!(policy.shell.check("ls -la").is_ok());
+        assert!(policy.shell.check("rm  -rf /").is_err());
+        assert!(policy.shell.check("sudo id").is_err());
+        assert!(policy.requires_confirmation(Some("shell")));
+        assert!(!policy.requires_confirmation(Some("web")));
+    }
+}
@@ -0,0 +1,118 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{Mutex, RwLock},
+};
+
+use anyhow::Result;
+use lazy_static::lazy_static;
+
+lazy_static! {
+    // folder with the prompts to use instead of the built-in ones
+    static ref PROMPTS_DIR: RwLock<Option<PathBuf>> = RwLock::new(None);
+    // prompts read from it so far, or None for the ones it doesn't override
+    static ref OVERRIDES: Mutex<HashMap<String, Option<&'static str>>> = Mutex::new(HashMap::new());
+}
+
+// a built-in prompt, or the file at the same path relative to the agent sources in the prompts folder if set
+#[macro_export]
+#[doc(hidden)]
+macro_rules! prompt {
+    ($file:literal) => {
+        $crate::agent::prompts::get(file!(), $file, include_str!($file))
+    };
+}
+
+pub fn get_dir() -> Option<PathBuf> {
+    PROMPTS_DIR.read().unwrap().clone()
+}
+
+pub fn set_dir(path: &Path) -> Result<()> {
+    if !path.is_dir() {
+        return Err(anyhow!("prompts folder {} does not exist", path.display()));
+    }
+
+    *PROMPTS_DIR.write().unwrap() = Some(path.canonicalize()?);
+    OVERRIDES.lock().unwrap().clear();
+
+    Ok(())
+}
+
+// path of a prompt relative to the agent sources, given the source file including it
+pub fn path_of(source: &str, file: &str) -> String {
+    let source = source.replace('\\', "/");
+    let folder = match source.rfind("src/agent/") {
+        Some(idx) => Path::new(&source[idx + "src/agent/".len()..])
+            .parent()
+            .map(|parent| parent.to_string_lossy().to_string())
+            .unwrap_or_default(),
+        None => "".to_string(),
+    };
+
+    if folder.is_empty() {
+        file.to_string()
+    } else {
+        format!("{}/{}", folder, file)
+    }
+}
+
+pub fn get(source: &str, file: &str, builtin: &'static str) -> &'static str {
+    let dir = match get_dir() {
+        Some(dir) => dir,
+        None => return builtin,
+    };
+
+    let path = path_of(source, file);
+    let mut overrides = OVERRIDES.lock().unwrap();
+    let prompt = overrides.entry(path.clone()).or_insert_with(|| {
+        let full_path = dir.join(&path);
+        if !full_path.exists() {
+            return None;
+        }
+        match std::fs::read_to_string(&full_path) {
+            // read once and kept for the whole run, like the built-in ones
+            Ok(prompt) => Some(Box::leak(prompt.trim_end().to_string().into_boxed_str())),
+            Err(e) => {
+                log::warn!(
+                    "can't read {}, using the built-in prompt: {}",
+                    full_path.display(),
+                    e
+                );
+                None
+            }
+        }
+    });
+
+    prompt.unwrap_or(builtin)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_path_of() {
+        assert_eq!(
+            path_of("nerve-core/src/agent/namespaces/shell/mod.rs", "ns.prompt"),
+            "namespaces/shell/ns.prompt"
+        );
+        assert_eq!(
+            path_of("nerve-core/src/agent/mod.rs", "task/reflection.prompt"),
+            "task/reflection.prompt"
+        );
+        assert_eq!(
+            path_of(
+                "/home/user/.cargo/registry/src/nerve-core-0.3.0/src/agent/task/report.rs",
+                "report.prompt"
+            ),
+            "task/report.prompt"
+        );
+        assert_eq!(
+            path_of(
+                r"nerve-core\src\agent\serialization\mod.rs",
+                "system.prompt"
+            ),
+            "serialization/system.prompt"
+        );
+    }
+}
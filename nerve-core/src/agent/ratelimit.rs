@@ -0,0 +1,189 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use anyhow::Result;
+use duration_string::DurationString;
+use lazy_static::lazy_static;
+use serde::Deserialize;
+
+lazy_static! {
+    // shared by all the agents of the process, so that the actions of a workflow or batch
+    // respect a single pace
+    static ref WINDOWS: Mutex<HashMap<String, Window>> = Mutex::new(HashMap::new());
+}
+
+// at most `max` executions of the actions tagged with it every `per`
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct RateLimit {
+    pub max: usize,
+    pub per: String,
+    // actions counted against this limit besides the ones tagging themselves with it
+    #[serde(default)]
+    pub actions: Vec<String>,
+}
+
+impl RateLimit {
+    pub fn validate(&self) -> Result<()> {
+        if self.max == 0 {
+            return Err(anyhow!("rate limit max must be at least 1"));
+        }
+        self.period().map(|_| ())
+    }
+
+    fn period(&self) -> Result<Duration> {
+        self.per
+            .parse::<DurationString>()
+            .map(|d| d.into())
+            .map_err(|e| anyhow!("invalid rate limit period '{}': {}", self.per, e))
+    }
+}
+
+// the last executions counted against a limit
+#[derive(Debug)]
+struct Window {
+    max: usize,
+    period: Duration,
+    executions: VecDeque<Instant>,
+}
+
+impl Window {
+    // how long until another execution is allowed
+    fn wait(&mut self, now: Instant) -> Duration {
+        while self
+            .executions
+            .front()
+            .is_some_and(|started| now.duration_since(*started) >= self.period)
+        {
+            self.executions.pop_front();
+        }
+
+        if self.executions.len() < self.max {
+            Duration::ZERO
+        } else {
+            self.period - now.duration_since(self.executions[self.executions.len() - self.max])
+        }
+    }
+}
+
+// set the limit of a tag, keeping the executions already counted against it
+pub fn configure(tag: &str, limit: &RateLimit) -> Result<()> {
+    let period = limit.period()?;
+    let mut windows = WINDOWS.lock().unwrap();
+    let window = windows.entry(tag.to_string()).or_insert_with(|| Window {
+        max: limit.max,
+        period,
+        executions: VecDeque::new(),
+    });
+    window.max = limit.max;
+    window.period = period;
+    Ok(())
+}
+
+pub fn is_configured(tag: &str) -> bool {
+    WINDOWS.lock().unwrap().contains_key(tag)
+}
+
+// count an execution against the tags if all of them allow it, or return how long to wait
+fn try_acquire(tags: &[String], now: Instant) -> Option<Duration> {
+    let mut windows = WINDOWS.lock().unwrap();
+    let wait = windows
+        .iter_mut()
+        .filter(|(tag, _)| tags.contains(tag))
+        .map(|(_, window)| window.wait(now))
+        .max()
+        .unwrap_or_default();
+    if !wait.is_zero() {
+        return Some(wait);
+    }
+
+    for tag in tags {
+        if let Some(window) = windows.get_mut(tag) {
+            window.executions.push_back(now);
+        }
+    }
+    None
+}
+
+// wait until the limits of all the tags allow another execution, returning for how long
+pub async fn acquire(tags: &[String]) -> Duration {
+    let mut waited = Duration::ZERO;
+    while let Some(wait) = try_acquire(tags, Instant::now()) {
+        tokio::time::sleep(wait).await;
+        waited += wait;
+    }
+    waited
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limit(max: usize, per: &str) -> RateLimit {
+        RateLimit {
+            max,
+            per: per.to_string(),
+            actions: vec![],
+        }
+    }
+
+    #[test]
+    fn test_parse() {
+        let parsed: RateLimit =
+            serde_yaml::from_str("max: 10\nper: 1m\nactions: [shell, dns_lookup]").unwrap();
+        assert!(parsed.validate().is_ok());
+        assert_eq!(parsed.actions, ["shell", "dns_lookup"]);
+
+        assert!(limit(0, "1s").validate().is_err());
+        assert!(limit(1, "soon").validate().is_err());
+    }
+
+    #[test]
+    fn test_window() {
+        let start = Instant::now();
+        let mut window = Window {
+            max: 2,
+            period: Duration::from_secs(10),
+            executions: VecDeque::new(),
+        };
+
+        assert_eq!(window.wait(start), Duration::ZERO);
+        window.executions.push_back(start);
+        window.executions.push_back(start + Duration::from_secs(4));
+        assert_eq!(
+            window.wait(start + Duration::from_secs(6)),
+            Duration::from_secs(4)
+        );
+        // the first execution left the window
+        assert_eq!(window.wait(start + Duration::from_secs(10)), Duration::ZERO);
+        assert_eq!(window.executions.len(), 1);
+    }
+
+    #[test]
+    fn test_acquire_shared_tags() {
+        configure("test-network", &limit(2, "10s")).unwrap();
+        configure("test-target", &limit(1, "10s")).unwrap();
+        assert!(is_configured("test-network"));
+
+        let tags = |tags: &[&str]| tags.iter().map(|t| t.to_string()).collect::<Vec<_>>();
+        let now = Instant::now();
+
+        assert_eq!(
+            try_acquire(&tags(&["test-network", "test-target"]), now),
+            None
+        );
+        // the target limit is reached, and nothing is counted against the network one
+        assert!(try_acquire(&tags(&["test-network", "test-target"]), now).is_some());
+        assert_eq!(try_acquire(&tags(&["test-network"]), now), None);
+        assert!(try_acquire(&tags(&["test-network"]), now).is_some());
+        // tags without limits don't wait
+        assert_eq!(try_acquire(&tags(&["test-unlimited"]), now), None);
+
+        // reconfiguring keeps the executions counted so far
+        configure("test-network", &limit(3, "10s")).unwrap();
+        assert_eq!(try_acquire(&tags(&["test-network"]), now), None);
+        assert!(try_acquire(&tags(&["test-network"]), now).is_some());
+    }
+}
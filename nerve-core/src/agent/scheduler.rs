@@ -0,0 +1,179 @@
+use std::{
+    future::Future,
+    panic::AssertUnwindSafe,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc, Arc, Mutex,
+    },
+};
+
+use anyhow::Result;
+use futures::FutureExt;
+use tokio::sync::oneshot;
+
+type Job = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+#[derive(Debug, Default)]
+struct Counters {
+    queued: AtomicUsize,
+    running: AtomicUsize,
+}
+
+// runs concurrent agents, or any other job, on a fixed pool of worker threads each with its own
+// runtime, so that a blocking action can only stall its own agent
+pub struct Scheduler {
+    workers: usize,
+    jobs: Option<mpsc::Sender<Job>>,
+    counters: Arc<Counters>,
+    threads: Vec<std::thread::JoinHandle<()>>,
+}
+
+// the result of a scheduled job
+pub struct JobHandle<T> {
+    result: oneshot::Receiver<std::thread::Result<T>>,
+}
+
+impl<T> JobHandle<T> {
+    pub async fn join(self) -> Result<T> {
+        match self.result.await {
+            Ok(Ok(result)) => Ok(result),
+            Ok(Err(_)) => Err(anyhow!("job panicked")),
+            Err(_) => Err(anyhow!("job was dropped before completing")),
+        }
+    }
+}
+
+impl Scheduler {
+    pub fn new(workers: usize) -> Result<Self> {
+        let workers = workers.max(1);
+        let (tx, rx) = mpsc::channel::<Job>();
+        let rx = Arc::new(Mutex::new(rx));
+        let counters = Arc::new(Counters::default());
+
+        let mut threads = vec![];
+        for n in 0..workers {
+            let rx = rx.clone();
+            let counters = counters.clone();
+            let runtime = tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()?;
+            threads.push(
+                std::thread::Builder::new()
+                    .name(format!("nerve-worker-{}", n))
+                    .spawn(move || loop {
+                        // the lock is only held while waiting for the next job
+                        let job = match rx.lock().unwrap().recv() {
+                            Ok(job) => job,
+                            Err(_) => break,
+                        };
+                        counters.queued.fetch_sub(1, Ordering::SeqCst);
+                        counters.running.fetch_add(1, Ordering::SeqCst);
+                        runtime.block_on(job);
+                        counters.running.fetch_sub(1, Ordering::SeqCst);
+                    })?,
+            );
+        }
+
+        Ok(Self {
+            workers,
+            jobs: Some(tx),
+            counters,
+            threads,
+        })
+    }
+
+    pub fn workers(&self) -> usize {
+        self.workers
+    }
+
+    // jobs waiting for a free worker
+    pub fn queued(&self) -> usize {
+        self.counters.queued.load(Ordering::SeqCst)
+    }
+
+    pub fn running(&self) -> usize {
+        self.counters.running.load(Ordering::SeqCst)
+    }
+
+    // queue the job, it's executed as soon as a worker is free
+    pub fn spawn<F, T>(&self, job: F) -> JobHandle<T>
+    where
+        F: Future<Output = T> + Send + 'static,
+        T: Send + 'static,
+    {
+        let (tx, rx) = oneshot::channel();
+        let job = Box::pin(async move {
+            // a panicking job doesn't take its worker down
+            let result = AssertUnwindSafe(job).catch_unwind().await;
+            let _ = tx.send(result);
+        });
+
+        self.counters.queued.fetch_add(1, Ordering::SeqCst);
+        if let Some(jobs) = &self.jobs {
+            if jobs.send(job).is_err() {
+                self.counters.queued.fetch_sub(1, Ordering::SeqCst);
+            }
+        }
+
+        JobHandle { result: rx }
+    }
+}
+
+impl Drop for Scheduler {
+    // the queued jobs are completed before the workers exit
+    fn drop(&mut self) {
+        self.jobs.take();
+        for thread in self.threads.drain(..) {
+            let _ = thread.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_bounded_workers() {
+        let scheduler = Scheduler::new(2).unwrap();
+        let concurrent = Arc::new(AtomicUsize::new(0));
+        let peak = Arc::new(AtomicUsize::new(0));
+
+        let handles: Vec<_> = (0..6)
+            .map(|n| {
+                let concurrent = concurrent.clone();
+                let peak = peak.clone();
+                scheduler.spawn(async move {
+                    let now = concurrent.fetch_add(1, Ordering::SeqCst) + 1;
+                    peak.fetch_max(now, Ordering::SeqCst);
+                    tokio::time::sleep(Duration::from_millis(20)).await;
+                    concurrent.fetch_sub(1, Ordering::SeqCst);
+                    n * 2
+                })
+            })
+            .collect();
+        assert!(scheduler.queued() > 0);
+
+        let mut results = vec![];
+        for handle in handles {
+            results.push(handle.join().await.unwrap());
+        }
+        assert_eq!(results, [0, 2, 4, 6, 8, 10]);
+        assert_eq!(peak.load(Ordering::SeqCst), 2);
+        assert_eq!(scheduler.queued(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_panicking_job() {
+        let scheduler = Scheduler::new(1).unwrap();
+
+        let failed = scheduler.spawn(async { panic!("boom") });
+        let ok = scheduler.spawn(async { "still working" });
+
+        assert!(failed.join().await.is_err());
+        assert_eq!(ok.join().await.unwrap(), "still working");
+    }
+}
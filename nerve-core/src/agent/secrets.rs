@@ -0,0 +1,232 @@
+use std::sync::Mutex;
+
+use lazy_static::lazy_static;
+
+use super::generator::credentials;
+
+const MASK: &str = "********";
+// shorter values would mask too much unrelated text
+const MIN_SECRET_LEN: usize = 4;
+// the environment is not declared as secret by the user, so it takes longer values to be
+// sure that they're credentials rather than words
+const MIN_ENV_SECRET_LEN: usize = 12;
+// environment variables known to contain credentials, besides the API keys of the generators
+const SECRET_ENV_VARS: &[&str] = &[
+    "NERVE_SERVE_TOKEN",
+    "NERVE_EVENTS_TOKEN",
+    "AWS_SECRET_ACCESS_KEY",
+    "AWS_SESSION_TOKEN",
+    "GITHUB_TOKEN",
+    "GH_TOKEN",
+    "GITLAB_TOKEN",
+    "HF_TOKEN",
+    "PGPASSWORD",
+];
+// comma separated list of other environment variables to consider secrets
+const SECRET_ENV_LIST: &str = "NERVE_SECRET_ENV";
+// http headers used for authentication
+const AUTH_HEADERS: &[&str] = &[
+    "authorization",
+    "proxy-authorization",
+    "cookie",
+    "x-api-key",
+    "x-auth-token",
+];
+
+lazy_static! {
+    // registered secrets, longest first so that overlapping values are fully masked
+    static ref SECRETS: Mutex<Vec<String>> = Mutex::new(vec![]);
+}
+
+pub fn register(value: &str) {
+    let value = value.trim();
+    if value.len() < MIN_SECRET_LEN {
+        return;
+    }
+
+    let mut secrets = SECRETS.lock().unwrap();
+    if !secrets.iter().any(|s| s == value) {
+        secrets.push(value.to_string());
+        secrets.sort_by_key(|s| std::cmp::Reverse(s.len()));
+    }
+}
+
+// the names listed in $NERVE_SECRET_ENV
+fn listed_env() -> Vec<String> {
+    parse_listed(&std::env::var(SECRET_ENV_LIST).unwrap_or_default())
+}
+
+fn parse_listed(list: &str) -> Vec<String> {
+    list.split(',')
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty())
+        .collect()
+}
+
+fn is_secret_env(name: &str, listed: &[String]) -> bool {
+    credentials::env_names().any(|known| known == name)
+        || SECRET_ENV_VARS.contains(&name)
+        || listed.iter().any(|listed| listed == name)
+}
+
+// register the values of the environment variables known to contain credentials and of the
+// ones listed in $NERVE_SECRET_ENV
+pub fn register_from_env() {
+    register_from(std::env::vars(), &listed_env());
+}
+
+fn register_from(vars: impl IntoIterator<Item = (String, String)>, listed: &[String]) {
+    for (name, value) in vars {
+        if is_secret_env(&name, listed) && value.trim().len() >= MIN_ENV_SECRET_LEN {
+            register(&value);
+        }
+    }
+}
+
+// the commands whose environment can be scrubbed
+pub trait Env {
+    fn remove(&mut self, name: &str);
+}
+
+impl Env for std::process::Command {
+    fn remove(&mut self, name: &str) {
+        self.env_remove(name);
+    }
+}
+
+impl Env for tokio::process::Command {
+    fn remove(&mut self, name: &str) {
+        self.env_remove(name);
+    }
+}
+
+// don't pass the api keys of the generators to the processes nerve spawns, tools and hooks can
+// rely on their own credentials (GITHUB_TOKEN, AWS_*, ...) unless these are listed in
+// $NERVE_SECRET_ENV. The variables explicitly set for the process must be set after this.
+pub fn scrub_env(cmd: &mut impl Env) {
+    for name in credentials::env_names() {
+        cmd.remove(name);
+    }
+    for name in listed_env() {
+        cmd.remove(&name);
+    }
+}
+
+// register the value of an authentication header and its credentials part
+pub fn register_header(name: &str, value: &str) {
+    if AUTH_HEADERS.contains(&name.trim().to_lowercase().as_str()) {
+        register(value);
+        // "Bearer <token>", "Basic <credentials>", etc
+        if let Some((_, credentials)) = value.trim().split_once(' ') {
+            register(credentials);
+        }
+    }
+}
+
+pub fn is_empty() -> bool {
+    SECRETS.lock().unwrap().is_empty()
+}
+
+pub fn is_masked(value: &str) -> bool {
+    value.contains(MASK)
+}
+
+pub fn redact(text: &str) -> String {
+    let secrets = SECRETS.lock().unwrap();
+    let mut redacted = text.to_string();
+    for secret in secrets.iter() {
+        if redacted.contains(secret.as_str()) {
+            redacted = redacted.replace(secret.as_str(), MASK);
+        }
+    }
+    redacted
+}
+
+// same as redact but also matches the escaped form of the secrets in a json document
+pub fn redact_json(json: &str) -> String {
+    let secrets = SECRETS.lock().unwrap();
+    let mut redacted = json.to_string();
+    for secret in secrets.iter() {
+        let escaped = serde_json::to_string(secret).unwrap();
+        let escaped = &escaped[1..escaped.len() - 1];
+        if redacted.contains(escaped) {
+            redacted = redacted.replace(escaped, MASK);
+        }
+    }
+    redacted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_redact() {
+        register("abc");
+        register("s3cr3t-t0k3n");
+        register("s3cr3t");
+        register_header("Authorization", "Bearer b34r3r\"t0k3n");
+        register_header("X-Header", "not-a-secret");
+
+        assert!(!is_empty());
+        assert_eq!(
+            redact("abc uses s3cr3t-t0k3n and s3cr3t"),
+            "abc uses ******** and ********"
+        );
+        assert_eq!(redact("not-a-secret"), "not-a-secret");
+        assert_eq!(
+            redact_json(r#"{"header":"Bearer b34r3r\"t0k3n"}"#),
+            r#"{"header":"********"}"#
+        );
+        assert!(is_masked(&redact("b34r3r\"t0k3n")));
+    }
+
+    #[test]
+    fn test_scrub_env() {
+        let mut env = crate::agent::test_env::TestEnv::new();
+        env.set(SECRET_ENV_LIST, "NERVE_TEST_SCRUBBED");
+        let mut cmd = std::process::Command::new("true");
+        cmd.env("OPENAI_API_KEY", "sk-0123456789abcdef");
+        scrub_env(&mut cmd);
+        cmd.env("EXPLICIT", "value");
+
+        let removed = |name: &str| {
+            cmd.get_envs()
+                .any(|(key, value)| key == name && value.is_none())
+        };
+        // the api keys of every generator are known without being listed again
+        for name in credentials::env_names() {
+            assert!(removed(name), "{} not scrubbed", name);
+        }
+        assert!(removed("NERVE_TEST_SCRUBBED"));
+        // the credentials of the tools are left alone
+        assert!(!removed("GITHUB_TOKEN"));
+        assert!(!removed("EXPLICIT"));
+        assert!(!removed("PATH"));
+    }
+
+    #[test]
+    fn test_register_from_env() {
+        let vars = [
+            ("GITHUB_TOKEN", "ghp_0123456789abcdef"),
+            ("NOVITA_API_KEY", "novita-0123456789"),
+            ("NERVE_TEST_SHORT_PASSWORD", "root"),
+            ("NERVE_TEST_KEYBOARD", "us-international"),
+            ("NERVE_TEST_LISTED", "listed-credential-value"),
+        ]
+        .map(|(name, value)| (name.to_string(), value.to_string()));
+
+        register_from(
+            vars,
+            &parse_listed("NERVE_TEST_LISTED, NERVE_TEST_SHORT_PASSWORD,"),
+        );
+
+        assert_eq!(redact("ghp_0123456789abcdef"), MASK);
+        assert_eq!(redact("novita-0123456789"), MASK);
+        assert_eq!(redact("listed-credential-value"), MASK);
+        // too short, even if listed
+        assert_eq!(redact("root"), "root");
+        // neither known nor listed
+        assert_eq!(redact("us-international"), "us-international");
+    }
+}
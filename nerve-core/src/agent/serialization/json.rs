@@ -0,0 +1,182 @@
+use std::collections::HashMap;
+
+use anyhow::Result;
+use itertools::Itertools;
+use serde_json::Value;
+
+use crate::agent::{namespaces::Action, Invocation};
+
+fn quoted(data: &str) -> String {
+    Value::String(data.to_string()).to_string()
+}
+
+// {"action": "...", "attributes": {...}, "payload": "..."} with the keys in this order
+fn object(
+    action: &str,
+    attributes: Option<&HashMap<String, String>>,
+    payload: Option<&str>,
+) -> String {
+    let mut json = format!("{{\"action\": {}", quoted(action));
+    if let Some(attrs) = attributes.filter(|attrs| !attrs.is_empty()) {
+        // sorted for reproducible prompts
+        let attrs = attrs
+            .iter()
+            .sorted()
+            .map(|(key, value)| format!("{}: {}", quoted(key), quoted(value)))
+            .join(", ");
+        json += &format!(", \"attributes\": {{{}}}", attrs);
+    }
+    if let Some(payload) = payload {
+        json += &format!(", \"payload\": {}", quoted(payload));
+    }
+    json + "}"
+}
+
+pub fn invocation(inv: &Invocation) -> String {
+    object(&inv.action, inv.attributes.as_ref(), inv.payload.as_deref())
+}
+
+#[allow(clippy::borrowed_box)]
+pub fn action(action: &Box<dyn Action>) -> String {
+    object(
+        action.name(),
+        action.example_attributes().as_ref(),
+        action.example_payload(),
+    )
+}
+
+// objects and arrays given as payload or attribute are passed along as json
+fn to_string(value: &Value) -> Option<String> {
+    match value {
+        Value::Null => None,
+        Value::String(data) => Some(data.to_string()),
+        value => Some(value.to_string()),
+    }
+}
+
+fn to_invocation(value: &Value) -> Option<Invocation> {
+    let object = value.as_object()?;
+    let action = object.get("action")?.as_str()?.to_string();
+    let attributes = object
+        .get("attributes")
+        .and_then(|attrs| attrs.as_object())
+        .filter(|attrs| !attrs.is_empty())
+        .map(|attrs| {
+            attrs
+                .iter()
+                .filter_map(|(key, value)| Some((key.to_string(), to_string(value)?)))
+                .collect()
+        });
+    let payload = object.get("payload").and_then(to_string);
+
+    Some(Invocation::new(action, attributes, payload))
+}
+
+pub(crate) fn try_parse(raw: &str) -> Result<Vec<Invocation>> {
+    let mut ptr = raw;
+    let mut parsed = vec![];
+
+    // search for a potential object opening
+    while let Some(open_idx) = ptr.find('{') {
+        ptr = &ptr[open_idx..];
+
+        let mut values = serde_json::Deserializer::from_str(ptr).into_iter::<Value>();
+        if let Some(inv) = values
+            .next()
+            .and_then(|value| value.ok())
+            .as_ref()
+            .and_then(to_invocation)
+        {
+            parsed.push(inv);
+            ptr = &ptr[values.byte_offset()..];
+        } else {
+            // not a valid invocation, it might still contain one
+            ptr = &ptr[1..];
+        }
+    }
+
+    // avoid running the same command twince in a row
+    Ok(parsed.into_iter().unique().collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_simple() {
+        let invocations = try_parse(r#"{"action": "clear_plan"}"#).unwrap();
+        assert_eq!(
+            invocations,
+            vec![Invocation::new("clear_plan".to_string(), None, None)]
+        );
+    }
+
+    #[test]
+    fn test_parse_attributes() {
+        let invocations = try_parse(
+            r#"{"action": "http_request", "attributes": {"method": "GET", "retries": 3}, "payload": "/index.html"}"#,
+        )
+        .unwrap();
+
+        let attrs = HashMap::from([
+            ("method".to_string(), "GET".to_string()),
+            ("retries".to_string(), "3".to_string()),
+        ]);
+        assert_eq!(
+            invocations,
+            vec![Invocation::new(
+                "http_request".to_string(),
+                Some(attrs),
+                Some("/index.html".to_string())
+            )]
+        );
+    }
+
+    #[test]
+    fn test_parse_mixed_stuff() {
+        let raw = r#"Let me list the files first:
+
+```json
+{"action": "shell", "payload": "ls -la && echo \"done\""}
+```
+then {not json} {"plan": [{"action": "add_plan_step", "payload": "read the config"}]} and {"action": "task_complete"}"#;
+        let invocations = try_parse(raw).unwrap();
+
+        assert_eq!(invocations.len(), 3);
+        assert_eq!(&invocations[0].action, "shell");
+        assert_eq!(
+            invocations[0].payload,
+            Some("ls -la && echo \"done\"".to_string())
+        );
+        // found inside an object that is not an invocation
+        assert_eq!(&invocations[1].action, "add_plan_step");
+        assert_eq!(&invocations[2].action, "task_complete");
+        assert_eq!(invocations[2].payload, None);
+    }
+
+    #[test]
+    fn test_parse_structured_payload() {
+        let invocations =
+            try_parse(r#"{"action": "save_memory", "payload": {"port": 22}}"#).unwrap();
+        assert_eq!(invocations[0].payload, Some(r#"{"port":22}"#.to_string()));
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let inv = Invocation::new(
+            "http_request".to_string(),
+            Some(HashMap::from([
+                ("method".to_string(), "POST".to_string()),
+                ("content_type".to_string(), "text/plain".to_string()),
+            ])),
+            Some("line \"one\"\nline two".to_string()),
+        );
+        let json = invocation(&inv);
+        assert_eq!(
+            json,
+            r#"{"action": "http_request", "attributes": {"content_type": "text/plain", "method": "POST"}, "payload": "line \"one\"\nline two"}"#
+        );
+        assert_eq!(try_parse(&json).unwrap(), vec![inv]);
+    }
+}
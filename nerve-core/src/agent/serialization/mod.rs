@@ -0,0 +1,189 @@
+use anyhow::Result;
+use serde::Deserialize;
+use tera::Tera;
+
+use super::{namespaces::NAMESPACES, state::State};
+use crate::agent::{
+    namespaces::Action,
+    state::{
+        snapshot::StorageChange,
+        storage::{Storage, StorageType},
+    },
+    Invocation,
+};
+use crate::prompt;
+
+mod json;
+mod xml;
+mod yaml;
+
+// Serialization and deserialization strategy / format
+#[derive(Debug, Clone, Default, PartialEq, clap::ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Strategy {
+    // Use Xml format.
+    #[default]
+    Xml,
+    // Use Json objects.
+    Json,
+    // Use Yaml documents.
+    Yaml,
+}
+
+impl Strategy {
+    pub fn available_actions() -> String {
+        let default_serializer = Self::default();
+
+        let mut md = "".to_string();
+
+        for build_fn in NAMESPACES.values() {
+            let group = build_fn();
+            md += &format!("## {}\n\n", group.name);
+            if !group.description.is_empty() {
+                md += &format!("{}\n\n", group.description);
+            }
+            for action in &group.actions {
+                md += &format!(
+                    "{} {}\n\n",
+                    action.description(),
+                    default_serializer.serialize_action(action)
+                );
+            }
+        }
+
+        md.trim().to_string()
+    }
+
+    pub fn try_parse(&self, raw: &str) -> Result<Vec<Invocation>> {
+        match self {
+            Strategy::Xml => xml::parsing::try_parse(raw),
+            Strategy::Json => json::try_parse(raw),
+            Strategy::Yaml => yaml::try_parse(raw),
+        }
+    }
+
+    // with ages, every entry is followed by when, at which step and by which action it was saved,
+    // the storages are rendered the same way whatever the format of the invocations
+    pub fn serialize_storage(&self, storage: &Storage, ages: bool) -> String {
+        xml::serialize::storage(storage, ages)
+    }
+
+    pub fn serialize_storages(&self, storages: &[&Storage], ages: bool) -> String {
+        storages
+            .iter()
+            .map(|storage| self.serialize_storage(storage, ages))
+            .collect::<Vec<String>>()
+            .join("\n\n")
+    }
+
+    pub fn serialize_storage_changes(&self, changes: &[StorageChange]) -> String {
+        xml::serialize::storage_changes(changes)
+    }
+
+    #[allow(clippy::borrowed_box)]
+    pub fn serialize_action(&self, action: &Box<dyn Action>) -> String {
+        match self {
+            Strategy::Xml => xml::serialize::action(action),
+            Strategy::Json => json::action(action),
+            Strategy::Yaml => yaml::action(action),
+        }
+    }
+
+    pub fn serialize_invocation(&self, invocation: &Invocation) -> String {
+        match self {
+            Strategy::Xml => xml::serialize::invocation(invocation),
+            Strategy::Json => json::invocation(invocation),
+            Strategy::Yaml => yaml::invocation(invocation),
+        }
+    }
+
+    fn actions_for_state(&self, state: &State) -> Result<String> {
+        let mut md = "".to_string();
+
+        for group in state.get_namespaces() {
+            if group.actions.is_empty() {
+                continue;
+            }
+
+            md += &format!("## {}\n\n", group.name);
+            if !group.description.is_empty() {
+                md += &format!("{}\n\n", group.description);
+            }
+            for action in &group.actions {
+                md += &format!(
+                    "{} {}\n\n",
+                    action.description(),
+                    self.serialize_action(action)
+                );
+            }
+        }
+
+        Ok(md)
+    }
+
+    pub fn system_prompt_for_state(&self, state: &State) -> Result<String> {
+        let task = state.get_task();
+        let system_prompt = task.to_system_prompt()?;
+
+        let sorted = state.get_sorted_storages();
+        let ages = task.show_storage_ages();
+        let deltas = state.get_storage_snapshot().is_some();
+        let (storages, changes) = match state.get_storage_snapshot() {
+            // the snapshot is followed by what changed since then and by the current time
+            Some(snapshot) => {
+                let (volatile, stable): (Vec<&Storage>, Vec<&Storage>) = sorted
+                    .into_iter()
+                    .partition(|storage| *storage.get_type() == StorageType::Time);
+                let changes = [
+                    self.serialize_storage_changes(&snapshot.changes(&stable)),
+                    self.serialize_storages(&volatile, ages),
+                ];
+                (
+                    snapshot.rendered().to_string(),
+                    changes
+                        .into_iter()
+                        .filter(|part| !part.is_empty())
+                        .collect::<Vec<String>>()
+                        .join("\n"),
+                )
+            }
+            None => (self.serialize_storages(&sorted, ages), "".to_string()),
+        };
+        let guidance = task.guidance()?;
+
+        let available_actions = if state.use_native_tools_format {
+            // model supports tool calls, no need to add actions to the system prompt
+            "".to_string()
+        } else {
+            // model does not support tool calls, we need to provide the actions in its system prompt
+            let mut raw = prompt!("actions.prompt").to_owned();
+
+            raw.push('\n');
+            raw.push_str(&self.actions_for_state(state)?);
+
+            raw
+        };
+
+        let iterations = if state.metrics.max_steps > 0 {
+            format!(
+                "You are currently at step {} of a maximum of {}.",
+                state.metrics.current_step + 1,
+                state.metrics.max_steps
+            )
+        } else {
+            "".to_string()
+        };
+
+        let mut context = tera::Context::new();
+
+        context.insert("system_prompt", &system_prompt);
+        context.insert("storages", &storages);
+        context.insert("changes", &changes);
+        context.insert("deltas", &deltas);
+        context.insert("iterations", &iterations);
+        context.insert("available_actions", &available_actions);
+        context.insert("guidance", &guidance);
+
+        Tera::one_off(prompt!("system.prompt"), &context, false).map_err(|e| anyhow::anyhow!(e))
+    }
+}
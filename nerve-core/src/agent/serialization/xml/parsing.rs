@@ -114,7 +114,7 @@ fn try_parse_block(ptr: &str) -> Parsed {
                     encoding: _,
                     standalone: _,
                 } => {}
-                xml::reader::XmlEvent::EndDocument {} => {
+                xml::reader::XmlEvent::EndDocument => {
                     break;
                 }
                 xml::reader::XmlEvent::StartElement {
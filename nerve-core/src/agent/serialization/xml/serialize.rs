@@ -0,0 +1,354 @@
+use chrono::{DateTime, Local};
+
+use crate::agent::{
+    namespaces::Action,
+    state::{
+        snapshot::{ChangeKind, StorageChange},
+        storage::{Entry, Storage, StorageType, CURRENT_TAG, PREVIOUS_TAG},
+    },
+    Invocation,
+};
+
+pub fn invocation(inv: &Invocation) -> String {
+    let mut xml = format!("<{}", inv.action);
+    if let Some(attrs) = &inv.attributes {
+        // sorted for reproducible prompts
+        let mut attrs: Vec<_> = attrs.iter().collect();
+        attrs.sort();
+        for (key, value) in attrs {
+            xml += &format!(" {key}=\"{value}\"");
+        }
+    }
+    xml += &format!(
+        ">{}</{}>",
+        if let Some(data) = inv.payload.as_ref() {
+            data
+        } else {
+            ""
+        },
+        inv.action
+    );
+
+    xml
+}
+
+#[allow(clippy::borrowed_box)]
+pub fn action(action: &Box<dyn Action>) -> String {
+    let mut xml = format!("<{}", action.name());
+
+    if let Some(attrs) = action.example_attributes() {
+        let mut attrs: Vec<_> = attrs.into_iter().collect();
+        attrs.sort();
+        for (name, example_value) in &attrs {
+            xml += &format!(" {}=\"{}\"", name, example_value);
+        }
+    }
+
+    if let Some(payload) = action.example_payload() {
+        // TODO: escape payload?
+        xml += &format!(">{}</{}>", payload, action.name());
+    } else {
+        xml += "/>";
+    }
+
+    xml
+}
+
+// how long ago, in the largest unit
+fn ago(time: DateTime<Local>) -> String {
+    let secs = (Local::now() - time).num_seconds().max(0);
+    match secs {
+        0..=59 => format!("{}s ago", secs),
+        60..=3599 => format!("{}m ago", secs / 60),
+        3600..=86399 => format!("{}h ago", secs / 3600),
+        _ => format!("{}d ago", secs / 86400),
+    }
+}
+
+// when, at which step and by which action the entry was saved
+fn provenance(entry: &Entry) -> String {
+    let mut raw = format!(" (saved {}", ago(entry.saved_at));
+    if entry.step > 0 {
+        raw += &format!(" at step {}", entry.step);
+    }
+    if let Some(action) = &entry.action {
+        raw += &format!(" by {}", action);
+    }
+    raw + ")"
+}
+
+// a single entry of a storage of the given type, as listed in the prompt
+fn item(storage_type: StorageType, name: &str, key: &str, data: &str, complete: bool) -> String {
+    match storage_type {
+        StorageType::Tagged => format!("{}={}", key, data),
+        StorageType::Completion => format!(
+            "{} : {}",
+            data,
+            if complete {
+                "COMPLETED"
+            } else {
+                "not completed"
+            }
+        ),
+        StorageType::Tree => format!("[{}] {} {}", if complete { "x" } else { " " }, key, data),
+        StorageType::CurrentPrevious => format!(
+            "{} {}: {}",
+            if key == PREVIOUS_TAG {
+                "previous"
+            } else {
+                "current"
+            },
+            name,
+            data.trim()
+        ),
+        StorageType::Untagged | StorageType::Time => data.to_string(),
+    }
+}
+
+pub fn storage_changes(changes: &[StorageChange]) -> String {
+    if changes.is_empty() {
+        return "".to_string();
+    }
+
+    let mut raw = "## Changes since the storages above were saved
+"
+    .to_string();
+    for change in changes {
+        raw += &format!(
+            "- {} {}: {}\n",
+            match change.kind {
+                ChangeKind::Added => "added to",
+                ChangeKind::Updated => "updated in",
+                ChangeKind::Removed => "removed from",
+            },
+            &change.storage_name,
+            item(
+                change.storage_type,
+                &change.storage_name,
+                &change.key,
+                &change.data,
+                change.complete
+            )
+        );
+    }
+
+    raw
+}
+
+pub fn storage(storage: &Storage, ages: bool) -> String {
+    if storage.is_empty() {
+        return "".to_string();
+    }
+
+    let suffix = |entry: &Entry| {
+        if ages {
+            provenance(entry)
+        } else {
+            "".to_string()
+        }
+    };
+
+    match storage.get_type() {
+        StorageType::Time => {
+            let started_at = storage.get_started_at();
+
+            let mut raw = format!(
+                "## Current date: {}\n",
+                chrono::Local::now().format("%m %B %Y %H:%M")
+            );
+
+            raw.push_str(&format!(
+                "## Time since start: {:?}\n",
+                started_at.elapsed()
+            ));
+
+            raw
+        }
+        StorageType::Tagged => {
+            let mut xml: String = format!("<{}>\n", storage.get_name());
+
+            for (key, entry) in storage.iter() {
+                xml += &format!(
+                    "  - {}{}\n",
+                    item(
+                        StorageType::Tagged,
+                        storage.get_name(),
+                        key,
+                        &entry.data,
+                        false
+                    ),
+                    suffix(entry)
+                );
+            }
+
+            xml += &format!("</{}>", storage.get_name());
+
+            xml.to_string()
+        }
+        StorageType::Untagged => {
+            let mut xml = format!("<{}>\n", storage.get_name());
+
+            for entry in storage.values() {
+                xml += &format!("  - {}{}\n", &entry.data, suffix(entry));
+            }
+
+            xml += &format!("</{}>", storage.get_name());
+
+            xml.to_string()
+        }
+        StorageType::Completion => {
+            let mut xml = format!("<{}>\n", storage.get_name());
+
+            for (key, entry) in storage.iter() {
+                xml += &format!(
+                    "  - {}{}\n",
+                    item(
+                        StorageType::Completion,
+                        storage.get_name(),
+                        key,
+                        &entry.data,
+                        entry.complete
+                    ),
+                    suffix(entry)
+                );
+            }
+
+            xml += &format!("</{}>", storage.get_name());
+
+            xml.to_string()
+        }
+        StorageType::Tree => {
+            let mut xml = format!("<{}>\n", storage.get_name());
+
+            for (key, entry) in storage.iter() {
+                xml += &format!(
+                    "{}- {}{}\n",
+                    "  ".repeat(key.matches('.').count() + 1),
+                    item(
+                        StorageType::Tree,
+                        storage.get_name(),
+                        key,
+                        &entry.data,
+                        entry.complete
+                    ),
+                    suffix(entry)
+                );
+            }
+
+            xml += &format!("</{}>", storage.get_name());
+
+            xml.to_string()
+        }
+        StorageType::CurrentPrevious => {
+            if let Some(current) = storage.get(CURRENT_TAG) {
+                let mut str = format!("* Current {}: {}", storage.get_name(), current.data.trim());
+                if let Some(prev) = storage.get(PREVIOUS_TAG) {
+                    str += &format!("\n* Previous {}: {}", storage.get_name(), prev.data.trim());
+                }
+                str
+            } else {
+                "".to_string()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent::events::create_channel;
+
+    #[test]
+    fn test_ago() {
+        let now = Local::now();
+        assert_eq!(ago(now - chrono::Duration::seconds(90)), "1m ago");
+        assert_eq!(ago(now - chrono::Duration::hours(2)), "2h ago");
+        assert_eq!(ago(now - chrono::Duration::days(3)), "3d ago");
+        // clocks might be adjusted
+        assert_eq!(ago(now + chrono::Duration::hours(1)), "0s ago");
+    }
+
+    #[test]
+    fn test_storage_ages() {
+        let (tx, _rx) = create_channel();
+        let mut memories = Storage::new("memories", StorageType::Tagged, tx);
+        memories.set_step(3);
+        memories.set_action(Some("save_memory"));
+        memories.add_tagged("ports", "22 is open");
+
+        assert_eq!(
+            storage(&memories, false),
+            "<memories>\n  - ports=22 is open\n</memories>"
+        );
+        assert_eq!(
+            storage(&memories, true),
+            "<memories>\n  - ports=22 is open (saved 0s ago at step 3 by save_memory)\n</memories>"
+        );
+
+        // updates keep the origin of the entry
+        memories.set_step(5);
+        memories.set_action(Some("other_action"));
+        memories.add_tagged("ports", "22 and 80 are open");
+        assert_eq!(
+            storage(&memories, true),
+            "<memories>\n  - ports=22 and 80 are open (saved 0s ago at step 5 by save_memory)\n</memories>"
+        );
+    }
+
+    #[test]
+    fn test_storage_changes() {
+        let change = |kind, storage_name: &str, storage_type, key: &str, data: &str, complete| {
+            StorageChange {
+                kind,
+                storage_name: storage_name.to_string(),
+                storage_type,
+                key: key.to_string(),
+                data: data.to_string(),
+                complete,
+            }
+        };
+
+        assert_eq!(storage_changes(&[]), "");
+        assert_eq!(
+            storage_changes(&[
+                change(
+                    ChangeKind::Added,
+                    "memories",
+                    StorageType::Tagged,
+                    "os",
+                    "linux",
+                    false
+                ),
+                change(
+                    ChangeKind::Updated,
+                    "plan",
+                    StorageType::Completion,
+                    "1",
+                    "scan",
+                    true
+                ),
+                change(
+                    ChangeKind::Updated,
+                    "goal",
+                    StorageType::CurrentPrevious,
+                    CURRENT_TAG,
+                    "find x",
+                    false
+                ),
+                change(
+                    ChangeKind::Removed,
+                    "subgoals",
+                    StorageType::Tree,
+                    "1.2",
+                    "check",
+                    false
+                ),
+            ]),
+            "## Changes since the storages above were saved\n\
+            - added to memories: os=linux\n\
+            - updated in plan: scan : COMPLETED\n\
+            - updated in goal: current goal: find x\n\
+            - removed from subgoals: [ ] 1.2 check\n"
+        );
+    }
+}
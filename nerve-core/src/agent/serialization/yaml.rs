@@ -0,0 +1,237 @@
+use std::collections::HashMap;
+
+use anyhow::Result;
+use itertools::Itertools;
+use serde::Deserialize;
+use serde_yaml::{Mapping, Value};
+
+use crate::agent::{namespaces::Action, Invocation};
+
+// a yaml block with the action, attributes and payload keys in this order
+fn document(
+    action: &str,
+    attributes: Option<&HashMap<String, String>>,
+    payload: Option<&str>,
+) -> String {
+    let mut mapping = Mapping::new();
+    mapping.insert("action".into(), action.into());
+    if let Some(attrs) = attributes.filter(|attrs| !attrs.is_empty()) {
+        // sorted for reproducible prompts
+        let mut sorted = Mapping::new();
+        for (key, value) in attrs.iter().sorted() {
+            sorted.insert(key.as_str().into(), value.as_str().into());
+        }
+        mapping.insert("attributes".into(), Value::Mapping(sorted));
+    }
+    if let Some(payload) = payload {
+        mapping.insert("payload".into(), payload.into());
+    }
+
+    format!(
+        "\n```yaml\n{}\n```",
+        serde_yaml::to_string(&mapping)
+            .unwrap_or_default()
+            .trim_end()
+    )
+}
+
+pub fn invocation(inv: &Invocation) -> String {
+    document(&inv.action, inv.attributes.as_ref(), inv.payload.as_deref())
+        .trim_start()
+        .to_string()
+}
+
+#[allow(clippy::borrowed_box)]
+pub fn action(action: &Box<dyn Action>) -> String {
+    document(
+        action.name(),
+        action.example_attributes().as_ref(),
+        action.example_payload(),
+    )
+}
+
+// mappings and lists given as payload or attribute are passed along as yaml
+fn to_string(value: &Value) -> Option<String> {
+    match value {
+        Value::Null => None,
+        Value::String(data) => Some(data.to_string()),
+        Value::Bool(data) => Some(data.to_string()),
+        Value::Number(data) => Some(data.to_string()),
+        value => serde_yaml::to_string(value)
+            .ok()
+            .map(|data| data.trim_end().to_string()),
+    }
+}
+
+fn to_invocation(value: &Value) -> Option<Invocation> {
+    let mapping = value.as_mapping()?;
+    let action = mapping.get("action")?.as_str()?.to_string();
+    let attributes = mapping
+        .get("attributes")
+        .and_then(|attrs| attrs.as_mapping())
+        .filter(|attrs| !attrs.is_empty())
+        .map(|attrs| {
+            attrs
+                .iter()
+                .filter_map(|(key, value)| Some((to_string(key)?, to_string(value)?)))
+                .collect()
+        });
+    let payload = mapping.get("payload").and_then(to_string);
+
+    Some(Invocation::new(action, attributes, payload))
+}
+
+// the fenced blocks of the response, or what follows the first action if there are none
+fn blocks(raw: &str) -> Vec<&str> {
+    let fenced: Vec<&str> = raw
+        .split("```")
+        .skip(1)
+        .step_by(2)
+        .map(|block| {
+            // skip the language tag
+            match block.split_once('\n') {
+                Some((tag, rest)) if !tag.contains(':') => rest,
+                _ => block,
+            }
+        })
+        .collect();
+    if !fenced.is_empty() {
+        return fenced;
+    }
+
+    let mut offset = 0;
+    for line in raw.split_inclusive('\n') {
+        let trimmed = line.trim_start();
+        if trimmed.starts_with("action:") || trimmed.starts_with("- action:") {
+            return vec![&raw[offset..]];
+        }
+        offset += line.len();
+    }
+
+    vec![]
+}
+
+pub(crate) fn try_parse(raw: &str) -> Result<Vec<Invocation>> {
+    let mut parsed = vec![];
+
+    for block in blocks(raw) {
+        // one or more documents, each with an invocation or a list of them
+        for document in serde_yaml::Deserializer::from_str(block) {
+            let value = match Value::deserialize(document) {
+                Ok(value) => value,
+                Err(e) => {
+                    log::debug!("invalid yaml block: {}", e);
+                    break;
+                }
+            };
+            match &value {
+                Value::Sequence(values) => parsed.extend(values.iter().filter_map(to_invocation)),
+                value => parsed.extend(to_invocation(value)),
+            }
+        }
+    }
+
+    // avoid running the same command twince in a row
+    Ok(parsed.into_iter().unique().collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_simple() {
+        let invocations = try_parse("action: clear_plan").unwrap();
+        assert_eq!(
+            invocations,
+            vec![Invocation::new("clear_plan".to_string(), None, None)]
+        );
+    }
+
+    #[test]
+    fn test_parse_fenced() {
+        let raw = "Let me check the open ports first:
+
+```yaml
+action: shell
+payload: |
+  nmap -sV 10.0.0.1 &&
+  echo done
+```
+
+and then save them:
+
+```
+- action: save_memory
+  attributes:
+    key: ports
+    retries: 3
+  payload: 22, 80
+- action: task_complete
+```";
+        let invocations = try_parse(raw).unwrap();
+
+        assert_eq!(invocations.len(), 3);
+        assert_eq!(&invocations[0].action, "shell");
+        assert_eq!(
+            invocations[0].payload,
+            Some("nmap -sV 10.0.0.1 &&\necho done\n".to_string())
+        );
+        assert_eq!(&invocations[1].action, "save_memory");
+        assert_eq!(
+            invocations[1].attributes,
+            Some(HashMap::from([
+                ("key".to_string(), "ports".to_string()),
+                ("retries".to_string(), "3".to_string()),
+            ]))
+        );
+        assert_eq!(invocations[1].payload, Some("22, 80".to_string()));
+        assert_eq!(&invocations[2].action, "task_complete");
+        assert_eq!(invocations[2].payload, None);
+    }
+
+    #[test]
+    fn test_parse_unfenced() {
+        let raw = "I will read the file now.\naction: read_file\npayload: /etc/passwd\n";
+        let invocations = try_parse(raw).unwrap();
+        assert_eq!(
+            invocations,
+            vec![Invocation::new(
+                "read_file".to_string(),
+                None,
+                Some("/etc/passwd".to_string())
+            )]
+        );
+
+        assert!(try_parse("nothing to do: really").unwrap().is_empty());
+        assert!(try_parse("```yaml\naction: [broken\n```")
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let inv = Invocation::new(
+            "http_request".to_string(),
+            Some(HashMap::from([
+                ("method".to_string(), "POST".to_string()),
+                ("content_type".to_string(), "text/plain".to_string()),
+            ])),
+            Some("key: value\nline two".to_string()),
+        );
+        let yaml = invocation(&inv);
+        assert_eq!(
+            yaml,
+            "```yaml
+action: http_request
+attributes:
+  content_type: text/plain
+  method: POST
+payload: |-
+  key: value
+  line two
+```"
+        );
+        assert_eq!(try_parse(&yaml).unwrap(), vec![inv]);
+    }
+}
@@ -0,0 +1,132 @@
+use anyhow::Result;
+
+use super::{history::History, session::SessionStorage, State};
+
+// how deep the branches can be nested
+pub const MAX_BRANCHES: usize = 8;
+
+// a point of the run the agent can back out to, with the history and storages it had
+#[derive(Debug, Clone)]
+pub struct Branch {
+    pub id: usize,
+    // the alternative being explored
+    pub description: String,
+    pub step: usize,
+    history: History,
+    storages: Vec<SessionStorage>,
+}
+
+impl State {
+    pub fn get_branches(&self) -> &[Branch] {
+        &self.branches
+    }
+
+    // alternatives that turned out to be dead ends, kept across backtracking
+    pub fn get_dead_ends(&self) -> &[String] {
+        &self.dead_ends
+    }
+
+    // remember the current history and storages before exploring an alternative
+    pub fn open_branch(&mut self, description: &str) -> Result<&Branch> {
+        if self.branches.len() >= MAX_BRANCHES {
+            return Err(anyhow!(
+                "already {} branches deep, backtrack or keep one first",
+                MAX_BRANCHES
+            ));
+        }
+
+        self.branch_ids += 1;
+        self.branches.push(Branch {
+            id: self.branch_ids,
+            description: description.trim().to_string(),
+            step: self.metrics.current_step,
+            history: self.history.clone(),
+            storages: self.export_storages(),
+        });
+
+        Ok(self.branches.last().unwrap())
+    }
+
+    // restore the history and storages of the last branch point, the metrics keep counting
+    pub fn backtrack(&mut self, reason: &str) -> Result<Branch> {
+        let branch = self
+            .branches
+            .pop()
+            .ok_or_else(|| anyhow!("no branch to back out of"))?;
+
+        self.history = branch.history.clone();
+        self.import_storages(&branch.storages);
+        self.dead_ends
+            .push(format!("{}: {}", branch.description, reason.trim()));
+
+        Ok(branch)
+    }
+
+    // the alternative worked, forget its branch point
+    pub fn keep_branch(&mut self) -> Result<Branch> {
+        self.branches
+            .pop()
+            .ok_or_else(|| anyhow!("no branch to keep"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent::{
+        state::history::Execution,
+        testing::{new_state, TestTask},
+        Invocation,
+    };
+
+    fn execution(payload: &str) -> Execution {
+        Execution::with_result(
+            Invocation::new("shell".to_string(), None, Some(payload.to_string())),
+            Some("ok".to_string()),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_backtrack() {
+        let mut state = new_state(TestTask::default()).await;
+
+        state.history.push(execution("nmap"));
+        state
+            .get_storage_mut("memories")
+            .unwrap()
+            .add_tagged("ports", "22, 80");
+        state.metrics.current_step = 2;
+
+        let branch = state.open_branch("  exploit ssh ").unwrap();
+        assert_eq!((branch.id, branch.step), (1, 2));
+        assert_eq!(branch.description, "exploit ssh");
+
+        state.history.push(execution("hydra"));
+        state
+            .get_storage_mut("memories")
+            .unwrap()
+            .add_tagged("ssh", "no weak passwords");
+        state.metrics.current_step = 5;
+
+        let branch = state.backtrack("only key auth").unwrap();
+        assert_eq!(branch.id, 1);
+        assert_eq!(state.history.len(), 1);
+        let memories = state.get_storage("memories").unwrap();
+        assert_eq!(memories.get_tagged("ports").as_deref(), Some("22, 80"));
+        assert_eq!(memories.get_tagged("ssh"), None);
+        // the budget spent in the branch is not given back
+        assert_eq!(state.metrics.current_step, 5);
+        assert_eq!(state.get_dead_ends(), ["exploit ssh: only key auth"]);
+        assert!(state.backtrack("again").is_err());
+
+        state.open_branch("exploit http").unwrap();
+        assert_eq!(state.keep_branch().unwrap().id, 2);
+        assert!(state.get_branches().is_empty());
+        assert!(state.keep_branch().is_err());
+
+        for i in 0..MAX_BRANCHES {
+            state.open_branch(&format!("{}", i)).unwrap();
+        }
+        assert!(state.open_branch("too deep").is_err());
+    }
+}
@@ -0,0 +1,553 @@
+use std::{
+    io::{BufRead, BufReader},
+    path::{Path, PathBuf},
+    process::Stdio,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use anyhow::Result;
+use chrono::{DateTime, Local};
+use tokio::{
+    io::AsyncWriteExt,
+    process::Command,
+    sync::{mpsc, oneshot},
+};
+
+use super::session::Session;
+use crate::agent::{
+    events::{create_channel, Event, Receiver},
+    secrets,
+};
+
+const DEFAULT_SQLITE: &str = "sqlite3";
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS sessions (
+    id TEXT PRIMARY KEY,
+    tasklet TEXT,
+    generator TEXT,
+    prompt TEXT,
+    created_at TEXT,
+    updated_at TEXT,
+    status TEXT,
+    reason TEXT,
+    snapshot TEXT
+);
+CREATE TABLE IF NOT EXISTS history (
+    session_id TEXT,
+    step INTEGER,
+    action TEXT,
+    payload TEXT,
+    attributes TEXT,
+    result TEXT,
+    error TEXT,
+    elapsed_ms INTEGER,
+    created_at TEXT
+);
+CREATE TABLE IF NOT EXISTS storages (
+    session_id TEXT,
+    storage TEXT,
+    type TEXT,
+    key TEXT,
+    value TEXT,
+    updated_at TEXT,
+    PRIMARY KEY (session_id, storage, key)
+);
+CREATE TABLE IF NOT EXISTS metrics (
+    session_id TEXT,
+    step INTEGER,
+    data TEXT,
+    created_at TEXT,
+    PRIMARY KEY (session_id, step)
+);
+";
+
+// the sqlite3 executable, $NERVE_SQLITE or sqlite3 by default
+fn sqlite() -> String {
+    std::env::var("NERVE_SQLITE").unwrap_or(DEFAULT_SQLITE.to_string())
+}
+
+// sql string literal, secrets are never written, NUL would end the line read by sqlite3 and
+// leave the literal open
+fn quote(value: &str) -> String {
+    format!(
+        "'{}'",
+        secrets::redact(value).replace('\0', "").replace('\'', "''")
+    )
+}
+
+fn quote_opt(value: Option<&str>) -> String {
+    value.map(quote).unwrap_or("NULL".to_string())
+}
+
+fn now() -> String {
+    quote(&Local::now().to_rfc3339())
+}
+
+#[derive(Debug)]
+enum Op {
+    Execute(String),
+    // resolved once every previous statement has been executed
+    Flush(oneshot::Sender<()>),
+}
+
+// a session recorded to a sqlite database, statements are executed in order by a sqlite3
+// process running in the background
+#[derive(Debug)]
+pub struct Database {
+    path: PathBuf,
+    session_id: String,
+    // needed to create the session snapshots
+    pub tasklet: String,
+    pub generator: String,
+    pub created_at: DateTime<Local>,
+    current_step: AtomicUsize,
+    commands: mpsc::UnboundedSender<Op>,
+    // what went wrong since the last check, the statements are executed in the background
+    errors: Arc<Mutex<Vec<String>>>,
+}
+
+impl Database {
+    // open or create the database and start recording a session
+    pub async fn open<P: AsRef<Path>>(
+        path: P,
+        session_id: &str,
+        tasklet: &str,
+        generator: &str,
+        prompt: &str,
+        created_at: DateTime<Local>,
+    ) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        // errors are read from the same pipe as the results, so that they come before the
+        // result of the statements executed after them
+        let (output, output_writer) = std::io::pipe()?;
+        let mut child = Command::new(sqlite())
+            .arg("-batch")
+            .arg(&path)
+            .stdin(Stdio::piped())
+            .stdout(output_writer.try_clone()?)
+            .stderr(output_writer)
+            .spawn()
+            .map_err(|e| {
+                anyhow!(
+                    "could not execute {} (set $NERVE_SQLITE to its path): {}",
+                    sqlite(),
+                    e
+                )
+            })?;
+
+        let mut stdin = child.stdin.take().unwrap();
+        let (lines_tx, mut lines) = mpsc::unbounded_channel::<String>();
+        std::thread::spawn(move || {
+            for line in BufReader::new(output).lines().map_while(Result::ok) {
+                if lines_tx.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+
+        let errors = Arc::new(Mutex::new(vec![]));
+        let writer_errors = errors.clone();
+        let (commands, mut rx) = mpsc::unbounded_channel::<Op>();
+        tokio::spawn(async move {
+            // only the flush markers are selected, anything else is an error
+            let on_error = |line: String| {
+                log::error!("sqlite: {}", line);
+                writer_errors.lock().unwrap().push(line);
+            };
+            let mut flushes = 0;
+            while let Some(command) = rx.recv().await {
+                let (ret, done) = match command {
+                    Op::Execute(sql) => (
+                        stdin
+                            .write_all(format!("{}\n", sql).as_bytes())
+                            .await
+                            .map_err(|e| e.to_string()),
+                        None,
+                    ),
+                    Op::Flush(done) => {
+                        // wait for sqlite to reach this point
+                        flushes += 1;
+                        let marker = format!("flush-{}", flushes);
+                        let mut ret = stdin
+                            .write_all(format!("SELECT '{}';\n", marker).as_bytes())
+                            .await
+                            .map_err(|e| e.to_string());
+                        if ret.is_ok() {
+                            ret = Err(format!("{} exited", sqlite()));
+                            while let Some(line) = lines.recv().await {
+                                if line.trim() == marker {
+                                    ret = Ok(());
+                                    break;
+                                }
+                                on_error(line);
+                            }
+                        }
+                        (ret, Some(done))
+                    }
+                };
+
+                while let Ok(line) = lines.try_recv() {
+                    on_error(line);
+                }
+                let failed = ret.is_err();
+                if let Err(err) = ret {
+                    // the statements sent from now on are lost
+                    on_error(format!("could not write to the database: {}", err));
+                }
+                if let Some(done) = done {
+                    let _ = done.send(());
+                }
+                if failed {
+                    break;
+                }
+            }
+
+            drop(rx);
+            drop(stdin);
+            let _ = child.wait().await;
+        });
+
+        let db = Self {
+            path,
+            session_id: session_id.to_string(),
+            tasklet: tasklet.to_string(),
+            generator: generator.to_string(),
+            created_at,
+            current_step: AtomicUsize::new(0),
+            commands,
+            errors,
+        };
+
+        db.execute(SCHEMA.to_string());
+        // resumed sessions are already there
+        db.execute(format!(
+            "INSERT OR IGNORE INTO sessions (id, tasklet, generator, prompt, created_at) VALUES ({}, {}, {}, {}, {});",
+            quote(session_id),
+            quote(tasklet),
+            quote(generator),
+            quote(prompt),
+            quote(&created_at.to_rfc3339()),
+        ));
+        db.set_status("running", None);
+        db.flush().await?;
+
+        Ok(db)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn session_id(&self) -> &str {
+        &self.session_id
+    }
+
+    fn execute(&self, sql: String) {
+        if self.commands.send(Op::Execute(sql)).is_err() {
+            log::error!("the database {} is closed", self.path.display());
+        }
+    }
+
+    // wait for all the pending statements to be executed, fails if any of the statements
+    // executed since the last check failed or if sqlite is gone
+    pub async fn flush(&self) -> Result<()> {
+        let (tx, rx) = oneshot::channel();
+        if self.commands.send(Op::Flush(tx)).is_err() || rx.await.is_err() {
+            self.errors
+                .lock()
+                .unwrap()
+                .push(format!("{} is not running", sqlite()));
+        }
+
+        let errors = std::mem::take(&mut *self.errors.lock().unwrap());
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(anyhow!(
+                "could not write to the database {}: {}",
+                self.path.display(),
+                errors.join("; ")
+            ))
+        }
+    }
+
+    fn set_status(&self, status: &str, reason: Option<&str>) {
+        self.execute(format!(
+            "UPDATE sessions SET status = {}, reason = {}, updated_at = {} WHERE id = {};",
+            quote(status),
+            quote_opt(reason),
+            now(),
+            quote(&self.session_id)
+        ));
+    }
+
+    // store the snapshot used to resume the session
+    pub fn save_session(&self, session: &Session) -> Result<()> {
+        self.execute(format!(
+            "UPDATE sessions SET snapshot = {}, updated_at = {} WHERE id = {};",
+            quote(&secrets::redact_json(&serde_json::to_string(session)?)),
+            now(),
+            quote(&self.session_id)
+        ));
+        Ok(())
+    }
+
+    fn statements(&self, event: &Event) -> Vec<String> {
+        let id = quote(&self.session_id);
+        match event {
+            Event::MetricsUpdate(metrics) => {
+                self.current_step
+                    .store(metrics.current_step, Ordering::SeqCst);
+                vec![format!(
+                    "INSERT OR REPLACE INTO metrics (session_id, step, data, created_at) VALUES ({}, {}, {}, {});",
+                    id,
+                    metrics.current_step,
+                    quote(&serde_json::to_string(metrics).unwrap_or_default()),
+                    now()
+                )]
+            }
+            Event::ActionExecuted {
+                invocation,
+                error,
+                result,
+                elapsed,
+                ..
+            } => vec![format!(
+                "INSERT INTO history (session_id, step, action, payload, attributes, result, error, elapsed_ms, created_at) VALUES ({}, {}, {}, {}, {}, {}, {}, {}, {});",
+                id,
+                self.current_step.load(Ordering::SeqCst),
+                quote(&invocation.action),
+                quote_opt(invocation.payload.as_deref()),
+                quote_opt(
+                    invocation
+                        .attributes
+                        .as_ref()
+                        .and_then(|a| serde_json::to_string(a).ok())
+                        .as_deref()
+                ),
+                quote_opt(result.as_deref()),
+                quote_opt(error.as_deref()),
+                elapsed.as_millis(),
+                now()
+            )],
+            Event::StorageUpdate {
+                storage_name,
+                storage_type,
+                key,
+                new,
+                ..
+            } => vec![match new {
+                Some(value) => format!(
+                    "INSERT OR REPLACE INTO storages (session_id, storage, type, key, value, updated_at) VALUES ({}, {}, {}, {}, {}, {});",
+                    id,
+                    quote(storage_name),
+                    quote(&format!("{:?}", storage_type).to_lowercase()),
+                    quote(key),
+                    quote(value),
+                    now()
+                ),
+                None => format!(
+                    "DELETE FROM storages WHERE session_id = {} AND storage = {} AND key = {};",
+                    id,
+                    quote(storage_name),
+                    quote(key)
+                ),
+            }],
+            Event::StorageEvicted {
+                storage_name, key, ..
+            } => vec![format!(
+                "DELETE FROM storages WHERE session_id = {} AND storage = {} AND key = {};",
+                id,
+                quote(storage_name),
+                quote(key)
+            )],
+            _ => vec![],
+        }
+    }
+
+    // record the effects of an agent event
+    pub fn record(&self, event: &Event) {
+        for sql in self.statements(event) {
+            self.execute(sql);
+        }
+
+        match event {
+            Event::TaskComplete { impossible, reason } => self.set_status(
+                if *impossible {
+                    "impossible"
+                } else {
+                    "complete"
+                },
+                reason.as_deref(),
+            ),
+            Event::BudgetExhausted(reason) => self.set_status("budget_exhausted", Some(reason)),
+            Event::TooManyErrors(_) => self.set_status("too_many_errors", None),
+            Event::Interrupted { .. } => self.set_status("interrupted", None),
+            _ => {}
+        }
+    }
+
+    // record the events of the agent, they are forwarded to the returned receiver
+    pub fn record_events(self: &Arc<Self>, mut events_rx: Receiver) -> Receiver {
+        let (tx, rx) = create_channel();
+        let db = self.clone();
+        tokio::spawn(async move {
+            while let Some(event) = events_rx.recv().await {
+                db.record(&event);
+                // keep recording even if nobody is consuming them
+                let _ = tx.send(event);
+            }
+            // the run is over, make sure everything is written before the consumer exits
+            if let Err(err) = db.flush().await {
+                log::error!("{}", err);
+            }
+        });
+        rx
+    }
+
+    // read the snapshot of a session to resume it
+    pub async fn load_session<P: AsRef<Path>>(path: P, session_id: &str) -> Result<Session> {
+        let path = path.as_ref();
+        let output = Command::new(sqlite())
+            .arg("-batch")
+            .arg("-noheader")
+            .arg(path)
+            .arg(format!(
+                "SELECT snapshot FROM sessions WHERE id = {};",
+                quote(session_id)
+            ))
+            .output()
+            .await
+            .map_err(|e| anyhow!("could not execute {}: {}", sqlite(), e))?;
+
+        if !output.status.success() {
+            return Err(anyhow!(
+                "could not read session {} from {}: {}",
+                session_id,
+                path.display(),
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+
+        let raw = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        if raw.is_empty() {
+            return Err(anyhow!(
+                "session {} not found in {}",
+                session_id,
+                path.display()
+            ));
+        }
+
+        Ok(serde_json::from_str(&raw)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent::{state::storage::StorageType, Invocation};
+
+    #[test]
+    fn test_quote() {
+        assert_eq!(quote("it's"), "'it''s'");
+        assert_eq!(quote_opt(None), "NULL");
+        assert_eq!(quote_opt(Some("a;b")), "'a;b'");
+        assert_eq!(
+            quote("out\0'); DROP TABLE sessions; --"),
+            "'out''); DROP TABLE sessions; --'"
+        );
+    }
+
+    #[test]
+    fn test_statements() {
+        let (commands, _rx) = mpsc::unbounded_channel();
+        let db = Database {
+            path: PathBuf::from("test.db"),
+            session_id: "s1".to_string(),
+            tasklet: "t".to_string(),
+            generator: "g".to_string(),
+            created_at: Local::now(),
+            current_step: AtomicUsize::new(3),
+            commands,
+            errors: Arc::new(Mutex::new(vec![])),
+        };
+
+        let sql = db.statements(&Event::ActionExecuted {
+            invocation: Invocation::new(
+                "read_file".to_string(),
+                None,
+                Some("/etc/hosts".to_string()),
+            ),
+            error: None,
+            result: Some("it's".to_string()),
+            elapsed: std::time::Duration::from_millis(12),
+            complete_task: false,
+        });
+        assert_eq!(sql.len(), 1);
+        assert!(sql[0].starts_with("INSERT INTO history"));
+        assert!(sql[0]
+            .contains("VALUES ('s1', 3, 'read_file', '/etc/hosts', NULL, 'it''s', NULL, 12, "));
+
+        let sql = db.statements(&Event::StorageUpdate {
+            storage_name: "memories".to_string(),
+            storage_type: StorageType::Tagged,
+            key: "k".to_string(),
+            prev: Some("v".to_string()),
+            new: None,
+        });
+        assert_eq!(
+            sql,
+            vec!["DELETE FROM storages WHERE session_id = 's1' AND storage = 'memories' AND key = 'k';"]
+        );
+
+        assert!(db.statements(&Event::EmptyResponse).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_write_failures() {
+        let folder = std::env::temp_dir().join(format!("nerve-db-test-{}", std::process::id()));
+        std::fs::create_dir_all(&folder).unwrap();
+        // a fake sqlite3 complaining about every statement, then crashing
+        let fake = folder.join("sqlite3.sh");
+        std::fs::write(
+            &fake,
+            "while read -r line; do case \"$line\" in\n  SELECT*) echo \"$line\" | cut -d\"'\" -f2 ;;\n  *crash*) exit 1 ;;\n  *) echo \"Error: no such table\" >&2 ;;\nesac; done\n",
+        )
+        .unwrap();
+        let sqlite = folder.join("sqlite3");
+        std::fs::write(&sqlite, format!("#!/bin/sh\nexec sh {}\n", fake.display())).unwrap();
+        std::process::Command::new("chmod")
+            .arg("+x")
+            .arg(&sqlite)
+            .status()
+            .unwrap();
+
+        let mut env = crate::agent::test_env::TestEnv::new();
+        env.set("NERVE_SQLITE", &sqlite.display().to_string());
+
+        let err = Database::open(folder.join("test.db"), "s1", "t", "g", "p", Local::now())
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("no such table"), "{}", err);
+
+        std::fs::write(&fake, "while read -r line; do case \"$line\" in\n  SELECT*) echo \"$line\" | cut -d\"'\" -f2 ;;\n  *crash*) exit 1 ;;\nesac; done\n").unwrap();
+        let db = Database::open(folder.join("test.db"), "s1", "t", "g", "p", Local::now())
+            .await
+            .unwrap();
+        db.execute("crash".to_string());
+        let err = db.flush().await.unwrap_err();
+        assert!(
+            err.to_string().contains("could not write to the database"),
+            "{}",
+            err
+        );
+        assert!(db.flush().await.is_err());
+
+        drop(env);
+        std::fs::remove_dir_all(&folder).unwrap();
+    }
+}
@@ -0,0 +1,324 @@
+use std::time::Duration;
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::agent::{
+    generator::{tokenizer, Message},
+    serialization, Invocation,
+};
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Execution {
+    // unparsed response caused an error
+    response: Option<String>,
+    // parsed invocation
+    invocation: Option<Invocation>,
+
+    result: Option<String>,
+    error: Option<String>,
+
+    // step of the run this execution belongs to and how long it took, used by replay
+    #[serde(default)]
+    step: usize,
+    #[serde(default)]
+    elapsed: Option<Duration>,
+    // model generated summary of previous executions
+    #[serde(default)]
+    summary: bool,
+    // tokens of the messages this execution is rendered to, once counted
+    #[serde(default)]
+    tokens: Option<usize>,
+    // step of an earlier execution with the same output, rendered as a reference to it
+    #[serde(default)]
+    duplicate_of: Option<usize>,
+}
+
+impl Execution {
+    pub fn with_unparsed_response(response: &str, error: String) -> Self {
+        Self {
+            invocation: None,
+            response: Some(response.to_string()),
+            result: None,
+            error: Some(error),
+            ..Default::default()
+        }
+    }
+
+    pub fn with_error(invocation: Invocation, error: String) -> Self {
+        Self {
+            invocation: Some(invocation),
+            response: None,
+            result: None,
+            error: Some(error),
+            ..Default::default()
+        }
+    }
+
+    pub fn with_result(invocation: Invocation, result: Option<String>) -> Self {
+        Self {
+            invocation: Some(invocation),
+            response: None,
+            result,
+            error: None,
+            ..Default::default()
+        }
+    }
+
+    pub fn with_summary(summary: String) -> Self {
+        Self {
+            result: Some(summary),
+            summary: true,
+            ..Default::default()
+        }
+    }
+
+    pub fn is_summary(&self) -> bool {
+        self.summary
+    }
+
+    pub fn with_step(mut self, step: usize) -> Self {
+        self.step = step;
+        self
+    }
+
+    pub fn response(&self) -> Option<&String> {
+        self.response.as_ref()
+    }
+
+    pub fn invocation(&self) -> Option<&Invocation> {
+        self.invocation.as_ref()
+    }
+
+    pub fn result(&self) -> Option<&String> {
+        self.result.as_ref()
+    }
+
+    pub fn error(&self) -> Option<&String> {
+        self.error.as_ref()
+    }
+
+    pub fn step(&self) -> usize {
+        self.step
+    }
+
+    pub fn elapsed(&self) -> Option<Duration> {
+        self.elapsed
+    }
+
+    pub fn set_elapsed(&mut self, elapsed: Duration) {
+        self.elapsed = Some(elapsed);
+    }
+
+    pub fn duplicate_of(&self) -> Option<usize> {
+        self.duplicate_of
+    }
+
+    pub fn set_duplicate_of(&mut self, step: Option<usize>) {
+        self.duplicate_of = step;
+        // rendered differently now
+        self.tokens = None;
+    }
+
+    // tokens of the rendered execution, counted now if it hasn't been annotated yet
+    pub fn tokens(&self, serializer: &serialization::Strategy) -> usize {
+        self.tokens.unwrap_or_else(|| {
+            self.to_messages(serializer)
+                .iter()
+                .map(|m| match m {
+                    Message::Agent(data, _) | Message::Feedback(data, _) => {
+                        tokenizer::count_tokens(data)
+                    }
+                })
+                .sum()
+        })
+    }
+
+    pub fn counted_tokens(&self) -> Option<usize> {
+        self.tokens
+    }
+
+    pub fn count_tokens(&mut self, serializer: &serialization::Strategy) {
+        if self.tokens.is_none() {
+            self.tokens = Some(self.tokens(serializer));
+        }
+    }
+
+    pub fn to_messages(&self, serializer: &serialization::Strategy) -> Vec<Message> {
+        let mut messages = vec![];
+
+        if self.summary {
+            messages.push(Message::Feedback(
+                format!(
+                    "SUMMARY OF THE PREVIOUS STEPS:\n{}",
+                    self.result.as_deref().unwrap_or_default()
+                ),
+                None,
+            ));
+            return messages;
+        }
+
+        if let Some(response) = self.response.as_ref() {
+            messages.push(Message::Agent(response.to_string(), None));
+        } else if let Some(invocation) = self.invocation.as_ref() {
+            messages.push(Message::Agent(
+                serializer.serialize_invocation(invocation),
+                Some(invocation.clone()),
+            ));
+        }
+
+        messages.push(Message::Feedback(
+            if let Some(err) = &self.error {
+                format!("ERROR: {err}")
+            } else if let Some(step) = self.duplicate_of {
+                format!("(same output as step {step}, omitted)")
+            } else if let Some(out) = &self.result {
+                out.to_string()
+            } else {
+                "".to_string()
+            },
+            self.invocation.clone(),
+        ));
+
+        messages
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct History(Vec<Execution>);
+
+impl History {
+    pub fn new() -> Self {
+        Self(vec![])
+    }
+
+    pub fn to_chat_history(&self, serializer: &serialization::Strategy) -> Result<Vec<Message>> {
+        let mut history = vec![];
+
+        for entry in self.0.iter() {
+            history.extend(entry.to_messages(serializer));
+        }
+
+        Ok(history)
+    }
+
+    // tokens used by the history once rendered to messages
+    pub fn tokens(&self, serializer: &serialization::Strategy) -> usize {
+        self.0.iter().map(|entry| entry.tokens(serializer)).sum()
+    }
+
+    // annotate the executions that haven't been counted yet with their tokens
+    pub fn count_tokens(&mut self, serializer: &serialization::Strategy) -> usize {
+        for entry in self.0.iter_mut() {
+            entry.count_tokens(serializer);
+        }
+        self.tokens(serializer)
+    }
+
+    // replace the first n executions with a single summary entry
+    pub fn compress(&mut self, n: usize, summary: String) {
+        let n = n.min(self.0.len());
+        let step = self.0[..n].last().map(|e| e.step).unwrap_or(0);
+        self.0
+            .splice(..n, [Execution::with_summary(summary).with_step(step)]);
+
+        // the summarized outputs can't be referenced anymore
+        for idx in 1..self.0.len() {
+            if let Some(original) = self.0[idx].duplicate_of {
+                let found = self.0[1..idx]
+                    .iter()
+                    .any(|e| e.step == original && e.duplicate_of.is_none() && e.result.is_some());
+                if !found {
+                    self.0[idx].set_duplicate_of(None);
+                }
+            }
+        }
+    }
+}
+
+impl std::ops::Deref for History {
+    type Target = Vec<Execution>;
+    fn deref(&self) -> &Vec<Execution> {
+        &self.0
+    }
+}
+
+impl std::ops::DerefMut for History {
+    fn deref_mut(&mut self) -> &mut Vec<Execution> {
+        &mut self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compress() {
+        let mut history = History::new();
+        for i in 0..5 {
+            history.push(
+                Execution::with_result(
+                    Invocation::new("save_memory".to_string(), None, Some(format!("{i}"))),
+                    Some("x".repeat(100)),
+                )
+                .with_step(i + 1),
+            );
+        }
+
+        let serializer = serialization::Strategy::default();
+        let before = history.count_tokens(&serializer);
+
+        history.compress(3, "saved 0, 1 and 2".to_string());
+
+        assert_eq!(history.len(), 3);
+        assert!(history[0].is_summary());
+        assert_eq!(history[0].step(), 3);
+        assert_eq!(
+            history[1].invocation().unwrap().payload.as_deref(),
+            Some("3")
+        );
+        assert!(history[1].counted_tokens().is_some());
+        assert!(history[0].counted_tokens().is_none());
+        assert!(history.count_tokens(&serializer) < before);
+
+        let messages = history[0].to_messages(&serializer);
+        assert_eq!(messages.len(), 1);
+        assert!(
+            matches!(&messages[0], Message::Feedback(data, None) if data.contains("saved 0, 1 and 2"))
+        );
+    }
+
+    #[test]
+    fn test_duplicates() {
+        let page = "<html>".repeat(100);
+        let mut history = History::new();
+        for step in 1..=3 {
+            history.push(
+                Execution::with_result(
+                    Invocation::new("http_get".to_string(), None, Some("/".to_string())),
+                    Some(page.clone()),
+                )
+                .with_step(step),
+            );
+        }
+
+        let serializer = serialization::Strategy::default();
+        let before = history.count_tokens(&serializer);
+
+        history[1].set_duplicate_of(Some(1));
+        history[2].set_duplicate_of(Some(1));
+        assert!(history.count_tokens(&serializer) < before);
+
+        let messages = history.to_chat_history(&serializer).unwrap();
+        assert!(matches!(&messages[1], Message::Feedback(data, _) if data == &page));
+        assert!(
+            matches!(&messages[3], Message::Feedback(data, _) if data == "(same output as step 1, omitted)")
+        );
+
+        // step 1 is summarized, its duplicates are reported in full again
+        history.compress(1, "fetched /".to_string());
+        assert_eq!(history[1].duplicate_of(), None);
+        assert_eq!(history[2].duplicate_of(), None);
+    }
+}
@@ -0,0 +1,257 @@
+use std::{
+    collections::{BTreeMap, VecDeque},
+    fmt::Display,
+    time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ErrorMetrics {
+    pub empty_responses: usize,
+    pub unparsed_responses: usize,
+    pub unknown_actions: usize,
+    pub invalid_actions: usize,
+    pub errored_actions: usize,
+    pub timedout_actions: usize,
+}
+
+impl ErrorMetrics {
+    pub fn total(&self) -> usize {
+        self.empty_responses
+            + self.unparsed_responses
+            + self.unknown_actions
+            + self.invalid_actions
+            + self.errored_actions
+            + self.timedout_actions
+    }
+
+    fn has_response_errors(&self) -> bool {
+        self.empty_responses > 0 || self.unparsed_responses > 0
+    }
+
+    fn has_action_errors(&self) -> bool {
+        self.unknown_actions > 0 || self.invalid_actions > 0 || self.errored_actions > 0
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Usage {
+    pub last_input_tokens: u32,
+    pub last_output_tokens: u32,
+    pub total_input_tokens: u32,
+    pub total_output_tokens: u32,
+    // tokens of the execution history sent to the model
+    #[serde(default)]
+    pub history_tokens: usize,
+}
+
+// only the most recent samples are kept, so that a degraded backend shows right away
+const MAX_GENERATION_SAMPLES: usize = 20;
+const MAX_ACTION_SAMPLES: usize = 100;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Throughput {
+    // elapsed milliseconds and output tokens of the last generations
+    pub generations: VecDeque<(u64, u32)>,
+    // elapsed milliseconds of the last executed actions
+    pub actions: VecDeque<u64>,
+}
+
+impl Throughput {
+    pub fn on_generation(&mut self, elapsed: Duration, output_tokens: u32) {
+        self.generations
+            .push_back((elapsed.as_millis() as u64, output_tokens));
+        if self.generations.len() > MAX_GENERATION_SAMPLES {
+            self.generations.pop_front();
+        }
+    }
+
+    pub fn on_action(&mut self, elapsed: Duration) {
+        self.actions.push_back(elapsed.as_millis() as u64);
+        if self.actions.len() > MAX_ACTION_SAMPLES {
+            self.actions.pop_front();
+        }
+    }
+
+    // output tokens per second, if the generator reports its usage
+    pub fn tokens_per_second(&self) -> Option<f64> {
+        let (ms, tokens) = self
+            .generations
+            .iter()
+            .filter(|(_, tokens)| *tokens > 0)
+            .fold((0, 0), |(ms, tokens), (m, t)| (ms + m, tokens + *t as u64));
+        if ms > 0 && tokens > 0 {
+            Some(tokens as f64 * 1000.0 / ms as f64)
+        } else {
+            None
+        }
+    }
+
+    pub fn generation_latency(&self) -> Option<Duration> {
+        if self.generations.is_empty() {
+            return None;
+        }
+        let total: u64 = self.generations.iter().map(|(ms, _)| ms).sum();
+        Some(Duration::from_millis(total / self.generations.len() as u64))
+    }
+
+    // nearest rank percentile of the action latencies
+    pub fn action_latency(&self, percentile: usize) -> Option<Duration> {
+        if self.actions.is_empty() {
+            return None;
+        }
+        let mut sorted: Vec<u64> = self.actions.iter().copied().collect();
+        sorted.sort_unstable();
+        let rank = (percentile * sorted.len()).div_ceil(100).max(1);
+        Some(Duration::from_millis(sorted[rank.min(sorted.len()) - 1]))
+    }
+}
+
+impl Display for Throughput {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut parts = vec![];
+        if let Some(speed) = self.tokens_per_second() {
+            parts.push(format!("tok/s:{:.1}", speed));
+        }
+        if let Some(latency) = self.generation_latency() {
+            parts.push(format!("gen:{:?}", latency));
+        }
+        if let (Some(p50), Some(p95)) = (self.action_latency(50), self.action_latency(95)) {
+            parts.push(format!("act_p50:{:?} act_p95:{:?}", p50, p95));
+        }
+        write!(f, "{}", parts.join(" "))
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Metrics {
+    pub max_steps: usize,
+    pub current_step: usize,
+    pub valid_responses: usize,
+    pub valid_actions: usize,
+    pub success_actions: usize,
+    // number of times actions were retried after a transient failure
+    #[serde(default)]
+    pub retried_actions: usize,
+    // failed executions and unparsable responses since the last successful action
+    #[serde(default)]
+    pub consecutive_errors: usize,
+    // estimated cost of the run in USD, if token prices are known
+    #[serde(default)]
+    pub cost: f64,
+    // number of executed actions by namespace
+    #[serde(default)]
+    pub executed_actions: BTreeMap<String, usize>,
+    pub errors: ErrorMetrics,
+    pub usage: Usage,
+    #[serde(default)]
+    pub throughput: Throughput,
+}
+
+impl Display for Metrics {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "step:")?;
+        if self.max_steps > 0 {
+            write!(f, "{}/{} ", self.current_step, self.max_steps)?;
+        } else {
+            write!(f, "{} ", self.current_step)?;
+        }
+
+        if self.errors.has_response_errors() {
+            write!(
+                f,
+                "responses(valid:{} empty:{} broken:{}) ",
+                self.valid_responses, self.errors.empty_responses, self.errors.unparsed_responses
+            )?;
+        } else if self.valid_responses > 0 {
+            write!(f, "responses:{} ", self.valid_responses)?;
+        }
+
+        if self.errors.has_action_errors() {
+            write!(
+                f,
+                "actions(valid:{} ok:{} errored:{} unknown:{} invalid:{}) ",
+                self.valid_actions,
+                self.success_actions,
+                self.errors.errored_actions,
+                self.errors.unknown_actions,
+                self.errors.invalid_actions
+            )?;
+        } else if self.valid_actions > 0 {
+            write!(f, "actions:{} ", self.valid_actions,)?;
+        }
+
+        if self.retried_actions > 0 {
+            write!(f, "retries:{} ", self.retried_actions)?;
+        }
+
+        if self.cost > 0.0 {
+            write!(f, "cost:${:.4} ", self.cost)?;
+        }
+
+        if self.usage.last_input_tokens > 0 {
+            write!(
+                f,
+                "token_usage(in:{} out:{} tot_in:{} tot_out:{}) ",
+                self.usage.last_input_tokens,
+                self.usage.last_output_tokens,
+                self.usage.total_input_tokens,
+                self.usage.total_output_tokens
+            )?;
+        }
+
+        if self.usage.history_tokens > 0 {
+            write!(f, "history_tokens:{} ", self.usage.history_tokens)?;
+        }
+
+        let throughput = self.throughput.to_string();
+        if !throughput.is_empty() {
+            write!(f, "throughput({}) ", throughput)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_throughput() {
+        let mut throughput = Throughput::default();
+        assert_eq!(throughput.to_string(), "");
+
+        throughput.on_generation(Duration::from_millis(1000), 50);
+        throughput.on_generation(Duration::from_millis(3000), 150);
+        // no usage reported, only counts for the latency
+        throughput.on_generation(Duration::from_millis(2000), 0);
+        for ms in 1..=20 {
+            throughput.on_action(Duration::from_millis(ms * 10));
+        }
+
+        assert_eq!(throughput.tokens_per_second(), Some(50.0));
+        assert_eq!(
+            throughput.generation_latency(),
+            Some(Duration::from_secs(2))
+        );
+        assert_eq!(
+            throughput.action_latency(50),
+            Some(Duration::from_millis(100))
+        );
+        assert_eq!(
+            throughput.action_latency(95),
+            Some(Duration::from_millis(190))
+        );
+        assert_eq!(
+            throughput.to_string(),
+            "tok/s:50.0 gen:2s act_p50:100ms act_p95:190ms"
+        );
+
+        for _ in 0..MAX_GENERATION_SAMPLES {
+            throughput.on_generation(Duration::from_millis(500), 10);
+        }
+        assert_eq!(throughput.tokens_per_second(), Some(20.0));
+    }
+}
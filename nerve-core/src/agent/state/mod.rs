@@ -0,0 +1,1046 @@
+use std::{
+    collections::HashMap,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use anyhow::Result;
+use metrics::Metrics;
+
+use crate::agent::task::variables::parse_variable_expr;
+
+use super::{
+    abort::{AbortReason, Aborted},
+    access::ActionLists,
+    events::Event,
+    generator::{Message, SharedEmbedder},
+    namespaces::{self, Action, Namespace},
+    policy::Policy,
+    serialization,
+    streaming::Activity,
+    task::{
+        memory::MemoryPolicy,
+        rag::{RagStore, RetrievedDocument},
+        rerank::Reranker,
+        storages::StorageQuota,
+        watcher::RagWatcher,
+        Task,
+    },
+    Invocation,
+};
+use branch::Branch;
+use history::{Execution, History};
+#[cfg(feature = "redis")]
+use redis::Redis;
+use snapshot::StorageSnapshot;
+use storage::{Storage, StorageType};
+
+pub mod branch;
+#[cfg(feature = "sqlite")]
+pub mod database;
+pub mod history;
+pub mod metrics;
+#[cfg(feature = "redis")]
+pub mod redis;
+pub mod session;
+pub mod snapshot;
+pub mod storage;
+
+pub struct State {
+    // the task
+    task: Box<dyn Task>,
+    // predefined variables
+    variables: HashMap<String, String>,
+    // model memories, goals and other storages
+    storages: HashMap<String, Storage>,
+    // available actions and execution history
+    namespaces: Vec<Namespace>,
+    // list of executed actions
+    history: History,
+    // points the agent can back out to while exploring alternatives, innermost last
+    branches: Vec<Branch>,
+    // number of branches opened so far, used for their ids
+    branch_ids: usize,
+    // alternatives explored and abandoned, with the reason
+    dead_ends: Vec<String>,
+    // few-shot examples provided by the task
+    examples: History,
+    // embedder of the rag engine and of the semantic searches
+    embedder: Arc<dyn mini_rag::Embedder>,
+    // optional rag engine
+    rag: Option<Box<dyn RagStore>>,
+    // optional reranking of the rag results
+    reranker: Option<Reranker>,
+    // optional reindexing of the rag sources while running
+    rag_watcher: Option<RagWatcher>,
+    // optional expiry of the memories saved by the model
+    memory_policy: Option<MemoryPolicy>,
+    // storages as rendered at some step, if only their changes are rendered afterwards
+    storage_snapshot: Option<StorageSnapshot>,
+    // set to true when task is complete
+    complete: bool,
+    // set to true if the task has been declared impossible
+    impossible: bool,
+    // optional reason provided when the task was completed
+    completion_reason: Option<String>,
+    // events channel
+    events_tx: super::events::Sender,
+    // runtime metrics
+    pub metrics: Metrics,
+    // when the task started
+    started_at: Instant,
+    // optional maximum duration of the task
+    max_duration: Option<Duration>,
+    // optional maximum cost of the task in USD
+    max_cost: Option<f64>,
+    // outputs bigger than this many characters are stored and returned in pages of the given size
+    output_paging: Option<(usize, usize)>,
+    // pages of the stored outputs
+    outputs: Vec<Vec<String>>,
+    // actions the operator allowed or denied
+    action_lists: ActionLists,
+    // constraints enforced by the namespaces
+    policy: Policy,
+    // server and names of the storages shared with other agents, all if empty
+    #[cfg(feature = "redis")]
+    shared_storages: Option<(Arc<Redis>, Vec<String>)>,
+    // action being executed and when it last produced some output
+    current_action: Option<String>,
+    activity: Activity,
+    // agent to hand the task off to at the end of the step, with the message for it
+    handoff: Option<(String, String)>,
+    // model support stool
+    pub use_native_tools_format: bool,
+}
+
+pub type SharedState = Arc<tokio::sync::Mutex<State>>;
+
+impl State {
+    pub async fn new(
+        events_tx: super::events::Sender,
+        task: Box<dyn Task>,
+        embedder: Box<dyn mini_rag::Embedder>,
+        max_iterations: usize,
+        use_native_tools_format: bool,
+    ) -> Result<Self> {
+        let complete = false;
+        let mut variables = HashMap::new();
+        let mut storages = HashMap::new();
+        let history = History::new();
+
+        let mut namespaces = vec![];
+        for name in namespaces::resolve_used_namespaces(task.namespaces())? {
+            namespaces.push(namespaces::NAMESPACES.get(&name).unwrap()());
+        }
+
+        for namespace in &namespaces {
+            Self::resolve_required_variables(&mut variables, &namespace.actions)?;
+        }
+
+        // add RAG namespace
+        let embedder: Arc<dyn mini_rag::Embedder> = Arc::from(embedder);
+        let mut reranker = None;
+        let mut rag_watcher = None;
+        let rag: Option<Box<dyn RagStore>> = if let Some(config) = task.get_rag_config() {
+            let mut v_store = config
+                .create_store(Box::new(SharedEmbedder(embedder.clone())))
+                .await?;
+
+            // import new documents if needed
+            config.import(&mut v_store).await?;
+
+            namespaces.push(namespaces::NAMESPACES.get("rag").unwrap()());
+
+            if let Some(rerank) = &config.rerank {
+                reranker = Some(Reranker::new(rerank)?);
+            }
+            rag_watcher = RagWatcher::new(&config)?;
+
+            Some(v_store)
+        } else {
+            None
+        };
+
+        // add task defined actions
+        namespaces.append(&mut task.get_functions());
+
+        let handoffs = task.get_handoffs();
+        if !handoffs.is_empty() {
+            namespaces.push(namespaces::handoff::get_namespace(&handoffs));
+        }
+
+        // if any namespace requires a specific storage, create it
+        let quotas = task.get_storage_quotas();
+        for namespace in &namespaces {
+            Self::create_storages(&mut storages, namespace, &quotas, &events_tx);
+        }
+
+        // few-shot examples must refer to available actions
+        let mut examples = History::new();
+        for example in task.examples() {
+            let known = namespaces.iter().any(|ns| {
+                ns.actions
+                    .iter()
+                    .any(|a| a.name() == example.invocation.action)
+            });
+            if !known {
+                return Err(anyhow!(
+                    "example refers to unknown action '{}'",
+                    example.invocation.action
+                ));
+            }
+            examples.push(Execution::with_result(
+                example.invocation,
+                Some(example.response),
+            ));
+        }
+
+        // if the goal namespace is enabled, set the current goal
+        if let Some(goal) = storages.get_mut("goal") {
+            let prompt = task.to_prompt()?;
+            goal.set_current(&prompt);
+        }
+
+        // command line takes precedence over the tasklet limits
+        let max_steps = if max_iterations > 0 {
+            max_iterations
+        } else {
+            task.get_max_steps().unwrap_or(0)
+        };
+        let max_duration = task.get_max_duration();
+        let memory_policy = task.get_memory_policy();
+        let started_at = Instant::now();
+
+        let metrics = Metrics {
+            max_steps,
+            ..Default::default()
+        };
+
+        Ok(Self {
+            task,
+            variables,
+            storages,
+            history,
+            examples,
+            namespaces,
+            complete,
+            impossible: false,
+            completion_reason: None,
+            metrics,
+            started_at,
+            max_duration,
+            max_cost: None,
+            output_paging: None,
+            outputs: vec![],
+            action_lists: ActionLists::default(),
+            policy: Policy::default(),
+            #[cfg(feature = "redis")]
+            shared_storages: None,
+            embedder,
+            rag,
+            reranker,
+            rag_watcher,
+            memory_policy,
+            storage_snapshot: None,
+            branches: vec![],
+            branch_ids: 0,
+            dead_ends: vec![],
+            events_tx,
+            current_action: None,
+            activity: Activity::default(),
+            handoff: None,
+            use_native_tools_format,
+        })
+    }
+
+    // check if the actions require some variable
+    fn resolve_required_variables(
+        variables: &mut HashMap<String, String>,
+        actions: &[Box<dyn Action>],
+    ) -> Result<()> {
+        for action in actions {
+            if let Some(required_vars) = action.required_variables() {
+                log::debug!("action {} requires {:?}", action.name(), &required_vars);
+                for var_name in required_vars {
+                    let var_expr = format!("${var_name}");
+                    let (var_name, var_value) = parse_variable_expr(&var_expr)?;
+                    variables.insert(var_name, var_value);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // create the storages required by the namespace that don't exist yet
+    fn create_storages(
+        storages: &mut HashMap<String, Storage>,
+        namespace: &Namespace,
+        quotas: &HashMap<String, StorageQuota>,
+        events_tx: &super::events::Sender,
+    ) {
+        if let Some(ns_storages) = &namespace.storages {
+            for storage_descriptor in ns_storages {
+                // not created yet
+                if !storages.contains_key(&storage_descriptor.name) {
+                    let mut new_storage = Storage::new(
+                        &storage_descriptor.name,
+                        storage_descriptor.type_,
+                        events_tx.clone(),
+                    );
+
+                    if let Some(pre) = &storage_descriptor.predefined {
+                        // sort by key length first so that positional keys keep their order
+                        let mut pre: Vec<_> = pre.iter().collect();
+                        pre.sort_by_key(|(key, _)| (key.len(), key.to_string()));
+                        for (key, value) in pre {
+                            new_storage.add_data(key, value);
+                        }
+                    }
+
+                    if let Some(quota) = quotas.get(&storage_descriptor.name) {
+                        new_storage.set_quota(quota.clone());
+                    }
+
+                    storages.insert(storage_descriptor.name.to_string(), new_storage);
+                }
+            }
+        }
+    }
+
+    // add a namespace at runtime, its actions will be available from the next step
+    pub fn register_namespace(&mut self, mut namespace: Namespace) -> Result<()> {
+        if self
+            .namespaces
+            .iter()
+            .any(|ns| ns.name.eq_ignore_ascii_case(&namespace.name))
+        {
+            return Err(anyhow!("namespace '{}' already registered", namespace.name));
+        }
+
+        for action in &namespace.actions {
+            if self.get_action_namespace(action.name()).is_some() {
+                return Err(anyhow!("action '{}' already registered", action.name()));
+            }
+        }
+
+        // operator restrictions apply to these actions too
+        let name = namespace.name.to_string();
+        namespace
+            .actions
+            .retain(|action| self.action_lists.is_allowed(&name, action.name()));
+        if namespace.actions.is_empty() {
+            log::warn!("none of the actions of namespace {} are allowed", name);
+            return Ok(());
+        }
+
+        Self::resolve_required_variables(&mut self.variables, &namespace.actions)?;
+        Self::create_storages(
+            &mut self.storages,
+            &namespace,
+            &self.task.get_storage_quotas(),
+            &self.events_tx,
+        );
+
+        log::debug!("registered namespace {}", &name);
+        self.namespaces.push(namespace);
+        Ok(())
+    }
+
+    // add an action at runtime to an existing namespace or to a new one with the given name
+    pub fn register_action(&mut self, namespace: &str, action: Box<dyn Action>) -> Result<()> {
+        if self.get_action_namespace(action.name()).is_some() {
+            return Err(anyhow!("action '{}' already registered", action.name()));
+        }
+
+        let group = self
+            .namespaces
+            .iter_mut()
+            .find(|ns| ns.name.eq_ignore_ascii_case(namespace));
+        if let Some(group) = group {
+            if !self.action_lists.is_allowed(&group.name, action.name()) {
+                log::warn!("action {} is not allowed", action.name());
+                return Ok(());
+            }
+
+            Self::resolve_required_variables(&mut self.variables, std::slice::from_ref(&action))?;
+            group.actions.push(action);
+            Ok(())
+        } else {
+            self.register_namespace(Namespace::new_non_default(
+                namespace.to_string(),
+                "".to_string(),
+                vec![action],
+                None,
+            ))
+        }
+    }
+
+    // remove a namespace and its actions, returns false if it's not registered
+    pub fn unregister_namespace(&mut self, name: &str) -> bool {
+        let before = self.namespaces.len();
+        self.namespaces
+            .retain(|ns| !ns.name.eq_ignore_ascii_case(name));
+        self.namespaces.len() != before
+    }
+
+    // command line takes precedence over the tasklet limit
+    pub fn set_max_duration(&mut self, max_duration: Duration) {
+        self.max_duration = Some(max_duration);
+    }
+
+    pub fn set_max_cost(&mut self, max_cost: f64) {
+        self.max_cost = Some(max_cost);
+    }
+
+    pub fn set_output_paging(&mut self, max_size: usize, page_size: usize) {
+        self.output_paging = Some((max_size, page_size));
+    }
+
+    // remove the actions that are not allowed, they will also be rejected if invoked anyway
+    pub fn set_action_lists(&mut self, lists: ActionLists) {
+        for group in &mut self.namespaces {
+            let namespace = group.name.to_string();
+            group
+                .actions
+                .retain(|action| lists.is_allowed(&namespace, action.name()));
+        }
+        self.namespaces.retain(|group| !group.actions.is_empty());
+        self.action_lists = lists;
+    }
+
+    // share storages with other agents using the same redis server, if no names are given all
+    // the storages but the time and goal ones are shared
+    #[cfg(feature = "redis")]
+    pub fn share_storages(&mut self, redis: Arc<Redis>, names: Vec<String>) -> Result<()> {
+        for name in &names {
+            if !self.storages.contains_key(name) {
+                return Err(anyhow!("storage {name} not found"));
+            }
+        }
+        self.shared_storages = Some((redis, names));
+        self.sync_shared_storages()
+    }
+
+    // get the latest contents of the shared storages
+    #[cfg(feature = "redis")]
+    fn sync_shared_storages(&mut self) -> Result<()> {
+        let (redis, names) = match &self.shared_storages {
+            Some(shared) => shared,
+            None => return Ok(()),
+        };
+
+        for (name, storage) in self.storages.iter_mut() {
+            if storage.is_shared() {
+                storage.refresh()?;
+            } else if (names.is_empty()
+                && name != "goal"
+                && *storage.get_type() != StorageType::Time)
+                || names.contains(name)
+            {
+                // created after the sharing has been enabled
+                log::info!("sharing storage {}", name);
+                storage.share(redis.clone())?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn set_policy(&mut self, policy: Policy) {
+        self.policy = policy;
+    }
+
+    pub fn get_policy(&self) -> &Policy {
+        &self.policy
+    }
+
+    // store an oversized output and return its first page, the model can read the others with read_output
+    pub fn page_output(&mut self, output: String) -> String {
+        let (max_size, page_size) = match self.output_paging {
+            Some((max_size, page_size)) if output.chars().count() > max_size => {
+                (max_size, page_size)
+            }
+            _ => return output,
+        };
+
+        let pages = namespaces::outputs::paginate(&output, page_size.min(max_size));
+        let total = pages.len();
+        let first = pages[0].to_string();
+
+        self.outputs.push(pages);
+        let id = self.outputs.len();
+
+        // make the action available only once needed
+        if !self.namespaces.iter().any(|ns| ns.name == "Outputs") {
+            self.namespaces
+                .push(namespaces::NAMESPACES.get("outputs").unwrap()());
+        }
+
+        format!(
+            "[output {} is {} bytes, showing page 1/{}, use read_output to read the others]\n{}",
+            id,
+            output.len(),
+            total,
+            first
+        )
+    }
+
+    pub fn get_output_page(&self, id: usize, page: usize) -> Result<(String, usize)> {
+        let pages = self
+            .outputs
+            .get(id.wrapping_sub(1))
+            .ok_or_else(|| anyhow!("output {} not found", id))?;
+        let data = pages
+            .get(page.wrapping_sub(1))
+            .ok_or_else(|| anyhow!("output {} has {} pages", id, pages.len()))?;
+
+        Ok((data.to_string(), pages.len()))
+    }
+
+    // remove the memories that expired or are no longer relevant
+    fn forget_memories(&mut self) {
+        let (policy, storage) = match (&self.memory_policy, self.storages.get_mut("memories")) {
+            (Some(policy), Some(storage)) => (policy, storage),
+            _ => return,
+        };
+
+        let step = self.metrics.current_step;
+        let forgotten: Vec<String> = storage
+            .iter()
+            .filter(|(_, entry)| policy.forgets(entry, step))
+            .map(|(key, _)| key.to_string())
+            .collect();
+        for key in forgotten {
+            log::info!("forgetting memory '{}'", key);
+            storage.del_tagged(&key);
+        }
+    }
+
+    // entries saved while the action runs record it as their origin
+    pub fn set_current_action(&mut self, action: Option<&str>) {
+        for storage in self.storages.values_mut() {
+            storage.set_action(action);
+        }
+        self.current_action = action.map(|name| name.to_string());
+    }
+
+    pub fn get_activity(&self) -> Activity {
+        self.activity.clone()
+    }
+
+    // the running action is still alive, extending its timeout
+    pub fn touch_activity(&self) {
+        self.activity.touch();
+    }
+
+    // incremental output of the running action
+    pub fn on_action_output(&self, output: &str) {
+        self.touch_activity();
+        let _ = self.on_event(Event::ActionOutput {
+            action: self.current_action.clone().unwrap_or_default(),
+            output: output.to_string(),
+        });
+    }
+
+    pub fn on_step(&mut self) -> Result<()> {
+        self.metrics.current_step += 1;
+
+        #[cfg(feature = "redis")]
+        if let Err(err) = self.sync_shared_storages() {
+            // keep going with the local contents
+            log::error!("can't refresh shared storages: {}", err);
+        }
+
+        for storage in self.storages.values_mut() {
+            storage.set_step(self.metrics.current_step);
+        }
+        self.forget_memories();
+
+        let exhausted =
+            if self.metrics.max_steps > 0 && self.metrics.current_step >= self.metrics.max_steps {
+                Some("maximum number of steps reached".to_string())
+            } else if self
+                .max_duration
+                .is_some_and(|max| self.started_at.elapsed() >= max)
+            {
+                Some(format!(
+                    "maximum task duration of {:?} reached",
+                    self.max_duration.unwrap()
+                ))
+            } else if self.max_cost.is_some_and(|max| self.metrics.cost >= max) {
+                Some(format!(
+                    "maximum cost of ${:.2} reached (${:.4})",
+                    self.max_cost.unwrap(),
+                    self.metrics.cost
+                ))
+            } else {
+                None
+            };
+
+        if let Some(reason) = exhausted {
+            self.on_event(Event::BudgetExhausted(reason.to_string()))?;
+            Err(Aborted::new(AbortReason::BudgetExhausted, reason).into())
+        } else {
+            Ok(())
+        }
+    }
+
+    pub async fn rag_query(&mut self, query: &str, top_k: usize) -> Result<Vec<RetrievedDocument>> {
+        if let (Some(watcher), Some(rag)) = (&mut self.rag_watcher, &mut self.rag) {
+            if let Err(err) = watcher.refresh(rag).await {
+                log::error!("rag: can't reindex the sources: {}", err);
+            }
+        }
+
+        if let Some(rag) = &self.rag {
+            match &self.reranker {
+                Some(reranker) => {
+                    let docs = rag.retrieve(query, reranker.candidates(top_k)).await?;
+                    reranker.rerank(query, docs, top_k).await
+                }
+                None => rag.retrieve(query, top_k).await,
+            }
+        } else {
+            Err(anyhow!("no RAG engine has been configured"))
+        }
+    }
+
+    pub fn to_chat_history(&self, serializer: &serialization::Strategy) -> Result<Vec<Message>> {
+        self.history.to_chat_history(serializer)
+    }
+
+    pub fn get_history(&self) -> &History {
+        &self.history
+    }
+
+    // annotate the new executions with their tokens and update the metrics
+    pub fn count_history_tokens(&mut self, serializer: &serialization::Strategy) {
+        self.metrics.usage.history_tokens = self.history.count_tokens(serializer);
+    }
+
+    pub fn compress_history(&mut self, n: usize, summary: String) {
+        self.history.compress(n, summary);
+    }
+
+    // render an execution as a reference to the earlier one with the same output
+    pub fn set_duplicate_output(&mut self, idx: usize, original: usize) {
+        if let Some(exec) = self.history.get_mut(idx) {
+            exec.set_duplicate_of(Some(original));
+        }
+    }
+
+    pub fn examples_to_chat_history(
+        &self,
+        serializer: &serialization::Strategy,
+    ) -> Result<Vec<Message>> {
+        self.examples.to_chat_history(serializer)
+    }
+
+    #[allow(clippy::borrowed_box)]
+    pub fn get_task(&self) -> &Box<dyn Task> {
+        &self.task
+    }
+
+    pub fn get_variable(&self, name: &str) -> Option<&String> {
+        self.variables.get(name)
+    }
+
+    #[allow(dead_code)]
+    pub fn set_variable(&mut self, name: String, value: String) {
+        self.variables.insert(name, value);
+    }
+
+    pub fn get_embedder(&self) -> Arc<dyn mini_rag::Embedder> {
+        self.embedder.clone()
+    }
+
+    pub fn get_storages(&self) -> Vec<&Storage> {
+        self.storages.values().collect()
+    }
+
+    // storages in the order they are rendered in the prompt
+    pub fn get_sorted_storages(&self) -> Vec<&Storage> {
+        let mut sorted = self.get_storages();
+        sorted.sort_by_key(|storage| (storage.get_type().as_u8(), storage.get_name().to_string()));
+        sorted
+    }
+
+    pub fn get_storage_snapshot(&self) -> Option<&StorageSnapshot> {
+        self.storage_snapshot.as_ref()
+    }
+
+    // with storage deltas, render the storages again once too many changes piled up since the last snapshot
+    pub fn update_storage_snapshot(&mut self, serializer: &serialization::Strategy) {
+        let max_changes = match self.task.get_storage_deltas() {
+            Some(max_changes) => max_changes,
+            None => return,
+        };
+
+        let storages: Vec<&Storage> = self
+            .get_sorted_storages()
+            .into_iter()
+            .filter(|storage| *storage.get_type() != StorageType::Time)
+            .collect();
+
+        if let Some(snapshot) = &self.storage_snapshot {
+            let changes = snapshot.changes(&storages).len();
+            if changes <= max_changes {
+                return;
+            }
+            log::debug!(
+                "{} storage changes since step {}, taking a new snapshot",
+                changes,
+                snapshot.step()
+            );
+        }
+
+        let rendered = serializer.serialize_storages(&storages, self.task.show_storage_ages());
+        let snapshot = StorageSnapshot::new(rendered, self.metrics.current_step, &storages);
+        self.storage_snapshot = Some(snapshot);
+    }
+
+    pub fn get_storage(&self, name: &str) -> Result<&Storage> {
+        if let Some(storage) = self.storages.get(name) {
+            Ok(storage)
+        } else {
+            Err(anyhow!("storage {name} not found"))
+        }
+    }
+
+    pub fn get_storage_mut(&mut self, name: &str) -> Result<&mut Storage> {
+        if let Some(storage) = self.storages.get_mut(name) {
+            Ok(storage)
+        } else {
+            Err(anyhow!("storage {name} not found"))
+        }
+    }
+
+    pub fn to_prompt(&self) -> Result<String> {
+        self.task.to_prompt()
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.complete
+    }
+
+    pub fn get_namespaces(&self) -> &Vec<Namespace> {
+        &self.namespaces
+    }
+
+    pub fn add_success_to_history(&mut self, invocation: Invocation, result: Option<String>) {
+        self.history
+            .push(Execution::with_result(invocation, result).with_step(self.metrics.current_step));
+    }
+
+    pub fn add_error_to_history(&mut self, invocation: Invocation, error: String) {
+        self.history
+            .push(Execution::with_error(invocation, error).with_step(self.metrics.current_step));
+    }
+
+    pub fn add_unparsed_response_to_history(&mut self, response: &str, error: String) {
+        self.history.push(
+            Execution::with_unparsed_response(response, error).with_step(self.metrics.current_step),
+        );
+    }
+
+    pub fn set_last_execution_elapsed(&mut self, elapsed: std::time::Duration) {
+        if let Some(last) = self.history.last_mut() {
+            last.set_elapsed(elapsed);
+        }
+    }
+
+    pub fn get_action(&self, name: &str) -> Option<Box<dyn namespaces::Action>> {
+        for group in &self.namespaces {
+            for action in &group.actions {
+                if name == action.name() {
+                    if !self.action_lists.is_allowed(&group.name, name) {
+                        log::warn!("action {} is not allowed", name);
+                        return None;
+                    }
+                    return Some(action.clone());
+                }
+            }
+        }
+
+        None
+    }
+
+    pub fn get_action_namespace(&self, name: &str) -> Option<String> {
+        self.namespaces
+            .iter()
+            .find(|group| group.actions.iter().any(|action| action.name() == name))
+            .map(|group| group.name.to_lowercase())
+    }
+
+    pub fn is_impossible(&self) -> bool {
+        self.impossible
+    }
+
+    pub fn get_completion_reason(&self) -> Option<&String> {
+        self.completion_reason.as_ref()
+    }
+
+    // copy the contents of any storage with the same name and type from another state
+    pub fn inherit_storages(&mut self, other: &State) {
+        for (name, storage) in self.storages.iter_mut() {
+            if let Some(other_storage) = other.storages.get(name) {
+                storage.copy_from(other_storage);
+            }
+        }
+    }
+
+    pub fn request_handoff(&mut self, to: &str, message: &str) -> Result<()> {
+        let handoffs = self.task.get_handoffs();
+        if !handoffs.iter().any(|handoff| handoff.name == to) {
+            return Err(anyhow!(
+                "unknown agent '{}', available agents are: {}",
+                to,
+                handoffs
+                    .iter()
+                    .map(|handoff| handoff.name.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ));
+        }
+        self.handoff = Some((to.to_string(), message.to_string()));
+        Ok(())
+    }
+
+    pub fn take_handoff(&mut self) -> Option<(String, String)> {
+        self.handoff.take()
+    }
+
+    // continue the session of the agent that handed the task off: the history, run limits,
+    // metrics and operator restrictions carry over, as well as the given storages or, if not
+    // set, the contents of the ones with the same name
+    pub fn continue_from(&mut self, mut previous: State, storages: Option<&[String]>) {
+        match storages {
+            Some(names) => {
+                for name in names {
+                    if let Some(storage) = previous.storages.remove(name) {
+                        match self.storages.get_mut(name) {
+                            Some(existing) => existing.copy_from(&storage),
+                            None => {
+                                self.storages.insert(name.to_string(), storage);
+                            }
+                        }
+                    }
+                }
+            }
+            None => self.inherit_storages(&previous),
+        }
+
+        self.history = previous.history;
+        self.metrics = previous.metrics;
+        self.started_at = previous.started_at;
+        self.max_duration = previous.max_duration;
+        self.max_cost = previous.max_cost;
+        self.output_paging = previous.output_paging;
+        self.policy = previous.policy;
+        #[cfg(feature = "redis")]
+        {
+            self.shared_storages = previous.shared_storages;
+        }
+        self.set_action_lists(previous.action_lists);
+    }
+
+    pub fn on_complete(&mut self, impossible: bool, reason: Option<String>) -> Result<()> {
+        self.complete = true;
+        self.impossible = impossible;
+        self.completion_reason.clone_from(&reason);
+        self.on_event(Event::TaskComplete { impossible, reason })
+    }
+
+    pub fn on_event(&self, event: Event) -> Result<()> {
+        self.events_tx.send(event).map_err(|e| anyhow!(e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent::{
+        events::create_channel,
+        testing::{TestEmbedder, TestTask},
+    };
+
+    async fn test_state() -> State {
+        let (tx, _rx) = create_channel();
+        state_for(TestTask::default(), tx).await
+    }
+
+    async fn state_for(task: TestTask, tx: crate::agent::events::Sender) -> State {
+        State::new(tx, Box::new(task), Box::new(TestEmbedder {}), 10, false)
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_register_namespace() {
+        let mut state = test_state().await;
+        assert!(state.get_action("read_file").is_none());
+
+        state
+            .register_namespace(namespaces::NAMESPACES.get("filesystem").unwrap()())
+            .unwrap();
+        assert!(state.get_action("read_file").is_some());
+        assert_eq!(
+            state.get_action_namespace("read_file"),
+            Some("filesystem".to_string())
+        );
+
+        // conflicting names are rejected
+        assert!(state
+            .register_namespace(namespaces::NAMESPACES.get("filesystem").unwrap()())
+            .is_err());
+
+        assert!(state.unregister_namespace("FileSystem"));
+        assert!(state.get_action("read_file").is_none());
+        assert!(!state.unregister_namespace("filesystem"));
+    }
+
+    #[tokio::test]
+    async fn test_register_action() {
+        let mut state = test_state().await;
+        let shell = namespaces::NAMESPACES.get("shell").unwrap()()
+            .actions
+            .remove(0);
+
+        assert!(state.register_action("custom", shell.clone()).is_ok());
+        assert_eq!(
+            state.get_action_namespace("shell"),
+            Some("custom".to_string())
+        );
+        assert!(state.register_action("memory", shell).is_err());
+
+        state.set_action_lists(ActionLists::new(vec![], vec!["read_file".to_string()]));
+        let read_file = namespaces::NAMESPACES.get("filesystem").unwrap()()
+            .actions
+            .into_iter()
+            .find(|a| a.name() == "read_file")
+            .unwrap();
+        state.register_action("memory", read_file).unwrap();
+        assert!(state.get_action_namespace("read_file").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_continue_from() {
+        let (tx, _rx) = create_channel();
+        let mut previous = state_for(TestTask::default(), tx.clone()).await;
+        previous
+            .register_namespace(namespaces::NAMESPACES.get("mailbox").unwrap()())
+            .unwrap();
+        previous
+            .get_storage_mut("memories")
+            .unwrap()
+            .add_tagged("ports", "22");
+        previous
+            .get_storage_mut("inbox")
+            .unwrap()
+            .add_untagged("hi");
+        previous.metrics.current_step = 5;
+        previous.set_max_cost(1.5);
+        previous.add_success_to_history(
+            crate::agent::Invocation::new("shell".to_string(), None, Some("id".to_string())),
+            Some("uid=0(root)".to_string()),
+        );
+
+        // only the given storages, created if missing
+        let mut next = state_for(TestTask::default(), tx.clone()).await;
+        next.continue_from(previous, Some(&["inbox".to_string()]));
+        assert_eq!(next.metrics.current_step, 5);
+        assert_eq!(next.max_cost, Some(1.5));
+        assert_eq!(next.get_history().len(), 1);
+        assert_eq!(next.get_history()[0].invocation().unwrap().action, "shell");
+        assert_eq!(next.get_storage("inbox").unwrap().size(), 2);
+        assert!(next
+            .get_storage("memories")
+            .unwrap()
+            .get_tagged("ports")
+            .is_none());
+
+        // or the contents of the storages with the same name
+        let mut last = state_for(TestTask::default(), tx).await;
+        next.get_storage_mut("memories")
+            .unwrap()
+            .add_tagged("ports", "22");
+        last.continue_from(next, None);
+        assert_eq!(
+            last.get_storage("memories")
+                .unwrap()
+                .get_tagged("ports")
+                .as_deref(),
+            Some("22")
+        );
+        assert!(last.get_storage("inbox").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_max_steps_precedence() {
+        let task = || TestTask {
+            max_steps: Some(5),
+            ..Default::default()
+        };
+        let new = |task: TestTask, max_iterations: usize| async move {
+            let (tx, _rx) = create_channel();
+            State::new(
+                tx,
+                Box::new(task),
+                Box::new(TestEmbedder {}),
+                max_iterations,
+                false,
+            )
+            .await
+            .unwrap()
+            .metrics
+            .max_steps
+        };
+
+        // the command line wins over the tasklet, which wins over no limit
+        assert_eq!(new(task(), 10).await, 10);
+        assert_eq!(new(task(), 0).await, 5);
+        assert_eq!(new(TestTask::default(), 0).await, 0);
+    }
+
+    #[tokio::test]
+    async fn test_storage_deltas() {
+        let serializer = serialization::Strategy::default();
+        let (tx, _rx) = create_channel();
+        let mut state = state_for(
+            TestTask {
+                storage_deltas: Some(1),
+                ..Default::default()
+            },
+            tx,
+        )
+        .await;
+        state
+            .register_namespace(namespaces::NAMESPACES.get("memory").unwrap()())
+            .ok();
+        state.update_storage_snapshot(&serializer);
+        let before = state.get_storage_snapshot().unwrap().rendered().to_string();
+        assert!(!before.contains("ports"));
+
+        state
+            .get_storage_mut("memories")
+            .unwrap()
+            .add_tagged("ports", "22");
+        state.update_storage_snapshot(&serializer);
+        assert_eq!(state.get_storage_snapshot().unwrap().rendered(), before);
+        let prompt = serializer.system_prompt_for_state(&state).unwrap();
+        assert!(prompt.contains("- added to memories: ports=22"));
+
+        // too many changes, the storages are rendered again
+        state
+            .get_storage_mut("memories")
+            .unwrap()
+            .add_tagged("os", "linux");
+        state.update_storage_snapshot(&serializer);
+        assert!(state
+            .get_storage_snapshot()
+            .unwrap()
+            .rendered()
+            .contains("ports=22"));
+        let prompt = serializer.system_prompt_for_state(&state).unwrap();
+        assert!(!prompt.contains("Changes since"));
+    }
+}
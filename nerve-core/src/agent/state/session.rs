@@ -0,0 +1,306 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Result;
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+
+use super::{history::History, metrics::Metrics, storage::StorageType, State};
+
+pub const SESSION_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionEntry {
+    pub key: String,
+    pub data: String,
+    pub complete: bool,
+    // missing in sessions saved by older versions
+    #[serde(default)]
+    pub saved_at: Option<DateTime<Local>>,
+    #[serde(default)]
+    pub step: usize,
+    #[serde(default)]
+    pub created_at: Option<DateTime<Local>>,
+    // action that created the entry
+    #[serde(default)]
+    pub action: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionStorage {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub type_: StorageType,
+    pub entries: Vec<SessionEntry>,
+}
+
+// where and how to save checkpoints of a run
+#[derive(Debug, Clone)]
+pub struct Checkpoint {
+    pub path: PathBuf,
+    pub tasklet: String,
+    pub generator: String,
+    pub created_at: DateTime<Local>,
+}
+
+// a snapshot of a run that can be used to resume it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Session {
+    pub version: u32,
+    pub created_at: DateTime<Local>,
+    pub updated_at: DateTime<Local>,
+    // tasklet path and generator used for the run
+    pub tasklet: String,
+    pub generator: String,
+    pub prompt: String,
+    pub complete: bool,
+    pub impossible: bool,
+    pub completion_reason: Option<String>,
+    pub metrics: Metrics,
+    pub history: History,
+    pub storages: Vec<SessionStorage>,
+    // variables defined during the run (command line, tasklet and user input)
+    pub variables: BTreeMap<String, String>,
+    // path of the persistent RAG store, if any
+    pub rag_data_path: Option<String>,
+}
+
+// folder of the named sessions and of the interrupted runs
+pub fn sessions_path() -> Result<PathBuf> {
+    crate::agent::data_path("sessions")
+}
+
+impl Session {
+    // path of the session with the given name in the sessions folder
+    pub fn named_path(name: &str) -> Result<PathBuf> {
+        let valid = !name.is_empty()
+            && !name.starts_with('.')
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+        if !valid {
+            return Err(anyhow!(
+                "invalid session name '{}', only letters, digits, '-', '_' and '.' are allowed",
+                name
+            ));
+        }
+        Ok(sessions_path()?.join(format!("{}.json", name)))
+    }
+
+    // a path to a session file or the name of a session in the sessions folder
+    pub fn resolve(name_or_path: &str) -> Option<PathBuf> {
+        let path = PathBuf::from(name_or_path);
+        if path.is_file() {
+            return Some(path);
+        }
+        Self::named_path(name_or_path)
+            .ok()
+            .filter(|path| path.is_file())
+    }
+
+    // name and contents of the sessions in the sessions folder, most recently updated first
+    pub fn list() -> Result<Vec<(String, Session)>> {
+        let mut sessions = vec![];
+        for entry in std::fs::read_dir(sessions_path()?)? {
+            let path = entry?.path();
+            if path.extension().is_none_or(|ext| ext != "json") {
+                continue;
+            }
+            let name = match path.file_stem() {
+                Some(stem) => stem.to_string_lossy().to_string(),
+                None => continue,
+            };
+            match Self::from_path(&path) {
+                Ok(session) => sessions.push((name, session)),
+                Err(err) => log::warn!("{}", err),
+            }
+        }
+        sessions.sort_by_key(|(_, session)| std::cmp::Reverse(session.updated_at));
+        Ok(sessions)
+    }
+
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let raw = std::fs::read_to_string(path)
+            .map_err(|e| anyhow!("can't read session {}: {}", path.display(), e))?;
+        let session: Self = serde_json::from_str(&raw)
+            .map_err(|e| anyhow!("can't parse session {}: {}", path.display(), e))?;
+
+        if session.version > SESSION_VERSION {
+            return Err(anyhow!(
+                "session {} has version {} but only up to {} is supported",
+                path.display(),
+                session.version,
+                SESSION_VERSION
+            ));
+        }
+
+        Ok(session)
+    }
+
+    // write to a temporary file first so that a crash while saving won't corrupt the previous checkpoint
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        let tmp = path.with_extension("tmp");
+
+        // never export the registered secrets
+        std::fs::write(
+            &tmp,
+            crate::agent::secrets::redact_json(&serde_json::to_string_pretty(self)?),
+        )?;
+        std::fs::rename(&tmp, path)?;
+
+        Ok(())
+    }
+}
+
+impl State {
+    // the entries of the storages, except the time one
+    pub fn export_storages(&self) -> Vec<SessionStorage> {
+        let mut storages: Vec<SessionStorage> = self
+            .storages
+            .values()
+            .filter(|s| !matches!(s.get_type(), StorageType::Time))
+            .map(|s| SessionStorage {
+                name: s.get_name().to_string(),
+                type_: *s.get_type(),
+                entries: s
+                    .iter()
+                    .map(|(key, entry)| SessionEntry {
+                        key: key.to_string(),
+                        data: entry.data.to_string(),
+                        complete: entry.complete,
+                        saved_at: Some(entry.saved_at),
+                        step: entry.step,
+                        created_at: Some(entry.created_at),
+                        action: entry.action.clone(),
+                    })
+                    .collect(),
+            })
+            .collect();
+
+        // keep the file stable across checkpoints
+        storages.sort_by(|a, b| a.name.cmp(&b.name));
+        storages
+    }
+
+    // replace the entries of the storages with the exported ones
+    pub fn import_storages(&mut self, storages: &[SessionStorage]) {
+        for saved in storages {
+            if let Some(storage) = self.storages.get_mut(&saved.name) {
+                if *storage.get_type() != saved.type_ {
+                    log::warn!(
+                        "storage {} type changed since the session was saved, skipping",
+                        saved.name
+                    );
+                    continue;
+                }
+
+                storage.restore(saved.entries.iter());
+            } else {
+                log::warn!("storage {} not found, skipping", saved.name);
+            }
+        }
+    }
+
+    pub fn to_session(
+        &self,
+        tasklet: &str,
+        generator: &str,
+        created_at: DateTime<Local>,
+    ) -> Result<Session> {
+        Ok(Session {
+            version: SESSION_VERSION,
+            created_at,
+            updated_at: Local::now(),
+            tasklet: tasklet.to_string(),
+            generator: generator.to_string(),
+            prompt: self.task.to_prompt()?,
+            complete: self.complete,
+            impossible: self.impossible,
+            completion_reason: self.completion_reason.clone(),
+            metrics: self.metrics.clone(),
+            history: self.history.clone(),
+            storages: self.export_storages(),
+            variables: crate::agent::task::variables::get_variables()
+                .into_iter()
+                .collect(),
+            rag_data_path: self
+                .task
+                .get_rag_config()
+                .map(|c| c.data_path)
+                .filter(|p| !p.is_empty()),
+        })
+    }
+
+    pub fn restore_session(&mut self, session: &Session) -> Result<()> {
+        if session.complete {
+            return Err(anyhow!("session is already complete"));
+        }
+
+        // keep limits from the current configuration
+        let max_steps = self.metrics.max_steps;
+        self.metrics = session.metrics.clone();
+        self.metrics.max_steps = max_steps;
+
+        self.history = session.history.clone();
+        self.import_storages(&session.storages);
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_invalid_session_names() {
+        for name in ["", ".hidden", "../escape", "a/b", "with space"] {
+            assert!(Session::named_path(name).is_err(), "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_session_roundtrip() {
+        let session = Session {
+            version: SESSION_VERSION,
+            created_at: Local::now(),
+            updated_at: Local::now(),
+            tasklet: "/tmp/tasklet".to_string(),
+            generator: "ollama://llama3@localhost:11434".to_string(),
+            prompt: "do something".to_string(),
+            complete: false,
+            impossible: false,
+            completion_reason: None,
+            metrics: Metrics::default(),
+            history: History::new(),
+            storages: vec![SessionStorage {
+                name: "memories".to_string(),
+                type_: StorageType::Tagged,
+                entries: vec![SessionEntry {
+                    key: "foo".to_string(),
+                    data: "bar".to_string(),
+                    complete: false,
+                    saved_at: None,
+                    step: 3,
+                    created_at: None,
+                    action: Some("save_memory".to_string()),
+                }],
+            }],
+            variables: BTreeMap::from([("TARGET".to_string(), "localhost".to_string())]),
+            rag_data_path: None,
+        };
+
+        let path = std::env::temp_dir().join("nerve-session-test.json");
+        session.save(&path).unwrap();
+
+        let loaded = Session::from_path(&path).unwrap();
+        assert_eq!(loaded.prompt, "do something");
+        assert_eq!(loaded.storages[0].entries[0].data, "bar");
+        assert_eq!(loaded.storages[0].entries[0].step, 3);
+        assert_eq!(loaded.variables.get("TARGET").unwrap(), "localhost");
+    }
+}
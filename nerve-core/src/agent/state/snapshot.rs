@@ -0,0 +1,160 @@
+use indexmap::IndexMap;
+
+use super::storage::{Storage, StorageType};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChangeKind {
+    Added,
+    Updated,
+    Removed,
+}
+
+// an entry that changed since the snapshot was taken, removed entries have their old data
+#[derive(Debug, Clone, PartialEq)]
+pub struct StorageChange {
+    pub kind: ChangeKind,
+    pub storage_name: String,
+    pub storage_type: StorageType,
+    pub key: String,
+    pub data: String,
+    pub complete: bool,
+}
+
+type Entries = IndexMap<String, (String, bool)>;
+
+// the storages as they were rendered at some step, the system prompt reports this same text
+// followed by the changes since then, so that its beginning stays the same across steps
+#[derive(Debug, Clone)]
+pub struct StorageSnapshot {
+    rendered: String,
+    step: usize,
+    storages: IndexMap<String, (StorageType, Entries)>,
+}
+
+impl StorageSnapshot {
+    pub fn new(rendered: String, step: usize, storages: &[&Storage]) -> Self {
+        let storages = storages
+            .iter()
+            .map(|storage| {
+                (
+                    storage.get_name().to_string(),
+                    (
+                        *storage.get_type(),
+                        storage
+                            .iter()
+                            .map(|(key, entry)| {
+                                (key.to_string(), (entry.data.to_string(), entry.complete))
+                            })
+                            .collect(),
+                    ),
+                )
+            })
+            .collect();
+
+        Self {
+            rendered,
+            step,
+            storages,
+        }
+    }
+
+    pub fn rendered(&self) -> &str {
+        &self.rendered
+    }
+
+    pub fn step(&self) -> usize {
+        self.step
+    }
+
+    pub fn changes(&self, storages: &[&Storage]) -> Vec<StorageChange> {
+        let empty = Entries::new();
+        let mut changes = vec![];
+
+        for storage in storages {
+            let before = self
+                .storages
+                .get(storage.get_name())
+                .map(|(_, entries)| entries)
+                .unwrap_or(&empty);
+
+            for (key, entry) in storage.iter() {
+                let kind = match before.get(key) {
+                    None => ChangeKind::Added,
+                    Some((data, complete))
+                        if *data != entry.data || *complete != entry.complete =>
+                    {
+                        ChangeKind::Updated
+                    }
+                    _ => continue,
+                };
+                changes.push(StorageChange {
+                    kind,
+                    storage_name: storage.get_name().to_string(),
+                    storage_type: *storage.get_type(),
+                    key: key.to_string(),
+                    data: entry.data.to_string(),
+                    complete: entry.complete,
+                });
+            }
+        }
+
+        for (name, (type_, entries)) in &self.storages {
+            let current = storages.iter().find(|s| s.get_name() == name);
+            for (key, (data, complete)) in entries {
+                if current.is_some_and(|storage| storage.contains_key(key)) {
+                    continue;
+                }
+                changes.push(StorageChange {
+                    kind: ChangeKind::Removed,
+                    storage_name: name.to_string(),
+                    storage_type: *type_,
+                    key: key.to_string(),
+                    data: data.to_string(),
+                    complete: *complete,
+                });
+            }
+        }
+
+        changes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent::events::create_channel;
+
+    #[test]
+    fn test_changes() {
+        let (tx, _rx) = create_channel();
+        let mut memories = Storage::new("memories", StorageType::Tagged, tx.clone());
+        memories.add_tagged("ports", "22");
+        memories.add_tagged("os", "linux");
+        let mut plan = Storage::new("plan", StorageType::Completion, tx);
+        plan.add_completion("scan");
+
+        let snapshot = StorageSnapshot::new("rendered".to_string(), 1, &[&memories, &plan]);
+        assert!(snapshot.changes(&[&memories, &plan]).is_empty());
+
+        memories.add_tagged("ports", "22, 80");
+        memories.del_tagged("os");
+        memories.add_tagged("user", "root");
+        plan.set_complete(1);
+
+        let changes = snapshot.changes(&[&memories, &plan]);
+        let summary: Vec<_> = changes
+            .iter()
+            .map(|c| (c.kind, c.key.as_str(), c.data.as_str(), c.complete))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (ChangeKind::Updated, "ports", "22, 80", false),
+                (ChangeKind::Added, "user", "root", false),
+                (ChangeKind::Updated, "1", "scan", true),
+                (ChangeKind::Removed, "os", "linux", false),
+            ]
+        );
+        assert_eq!(changes[2].storage_name, "plan");
+    }
+}
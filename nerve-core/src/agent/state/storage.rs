@@ -0,0 +1,720 @@
+#[cfg(feature = "redis")]
+use std::sync::Arc;
+use std::{ops::Deref, time::Instant /* , time::SystemTime*/};
+
+use anyhow::Result;
+use chrono::{DateTime, Local};
+use indexmap::IndexMap;
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "redis")]
+use super::redis::Redis;
+use super::session::SessionEntry;
+use crate::agent::{
+    events::{Event, Sender},
+    task::storages::{Eviction, StorageQuota},
+};
+
+#[derive(Debug)]
+pub struct Entry {
+    pub time: Instant,
+    pub complete: bool, // for Completion storage
+    pub data: String,
+    pub seq: u64, // insertion order across the agents sharing the storage
+    // when and at which step the entry was last saved, kept across resumed runs
+    pub saved_at: DateTime<Local>,
+    pub step: usize,
+    // when the entry was first saved and by which action
+    pub created_at: DateTime<Local>,
+    pub action: Option<String>,
+    // how many times the entry has been saved again
+    pub updates: u64,
+}
+
+impl Entry {
+    pub fn new(data: String) -> Self {
+        let time: Instant = Instant::now();
+        let complete = false;
+        let seq = 0;
+        let saved_at = Local::now();
+        let step = 0;
+        let updates = 0;
+        let created_at = saved_at;
+        let action = None;
+        Self {
+            time,
+            data,
+            complete,
+            seq,
+            saved_at,
+            step,
+            updates,
+            created_at,
+            action,
+        }
+    }
+}
+
+// how an entry is saved in a shared storage
+#[cfg(feature = "redis")]
+#[derive(Debug, Serialize, Deserialize)]
+struct SharedEntry {
+    data: String,
+    complete: bool,
+    seq: u64,
+    #[serde(default)]
+    saved_at: Option<DateTime<Local>>,
+    #[serde(default)]
+    created_at: Option<DateTime<Local>>,
+    #[serde(default)]
+    action: Option<String>,
+}
+
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub enum StorageType {
+    // a list indexed by element position
+    #[default]
+    Untagged,
+    // a key=value store
+    Tagged,
+    // a single state with an optional previous state
+    CurrentPrevious,
+    // a list of tasks that can be set as complete
+    Completion,
+    // a checklist whose items can have sub items, keyed by their path (1, 1.2, ...)
+    Tree,
+    // current time
+    Time,
+}
+
+impl StorageType {
+    pub fn as_u8(&self) -> u8 {
+        match self {
+            StorageType::Time => 0,
+            StorageType::CurrentPrevious => 1,
+            StorageType::Tree => 2,
+            StorageType::Completion => 3,
+            StorageType::Untagged => 4,
+            StorageType::Tagged => 5,
+        }
+    }
+}
+
+pub const CURRENT_TAG: &str = "__current";
+pub const PREVIOUS_TAG: &str = "__previous";
+pub const STARTED_AT_TAG: &str = "__started_at";
+
+// position of a tree item from the root, 1.2 is the second child of the first item
+fn tree_path(key: &str) -> Vec<usize> {
+    key.split('.')
+        .filter_map(|part| part.parse().ok())
+        .collect()
+}
+
+// true if key is the item itself or one of its descendants
+fn in_subtree(key: &str, item: &str) -> bool {
+    key == item || key.starts_with(&format!("{}.", item))
+}
+
+#[derive(Debug)]
+pub struct Storage {
+    events_tx: Sender,
+    name: String,
+    type_: StorageType,
+    inner: IndexMap<String, Entry>,
+    // set if the storage is shared with other agents
+    #[cfg(feature = "redis")]
+    shared: Option<Arc<Redis>>,
+    // current step of the agent and action being executed, saved with the entries
+    step: usize,
+    action: Option<String>,
+    // optional size limits
+    quota: Option<StorageQuota>,
+}
+
+impl Deref for Storage {
+    type Target = IndexMap<String, Entry>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.inner
+    }
+}
+
+#[allow(dead_code)]
+impl Storage {
+    pub fn new(name: &str, type_: StorageType, events_tx: Sender) -> Self {
+        let name = name.to_string();
+        let mut inner = IndexMap::new();
+
+        if matches!(type_, StorageType::Time) {
+            inner.insert(STARTED_AT_TAG.to_string(), Entry::new(String::new()));
+        }
+
+        Self {
+            name,
+            type_,
+            inner,
+            events_tx,
+            #[cfg(feature = "redis")]
+            shared: None,
+            step: 0,
+            action: None,
+            quota: None,
+        }
+    }
+
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn get_type(&self) -> &StorageType {
+        &self.type_
+    }
+
+    pub fn set_step(&mut self, step: usize) {
+        self.step = step;
+    }
+
+    pub fn set_action(&mut self, action: Option<&str>) {
+        self.action = action.map(|name| name.to_string());
+    }
+
+    pub fn set_quota(&mut self, quota: StorageQuota) {
+        if matches!(self.type_, StorageType::Time | StorageType::CurrentPrevious) {
+            log::warn!("storage {} has a fixed size, ignoring its quota", self.name);
+            return;
+        }
+        self.quota = Some(quota);
+        self.enforce_quota(None);
+    }
+
+    // total size of the data of the entries
+    pub fn size(&self) -> usize {
+        self.inner.values().map(|entry| entry.data.len()).sum()
+    }
+
+    // evict entries until the storage is within its quota, the one just saved is never evicted
+    fn enforce_quota(&mut self, keep: Option<&str>) {
+        let quota = match &self.quota {
+            Some(quota) => quota.clone(),
+            None => return,
+        };
+
+        while quota.is_exceeded(self.inner.len(), self.size()) {
+            let candidates = self
+                .inner
+                .iter()
+                .filter(|(key, _)| Some(key.as_str()) != keep);
+            let victim = match quota.eviction {
+                Eviction::Lru => candidates.min_by_key(|(_, entry)| entry.time),
+                Eviction::Importance => {
+                    candidates.min_by_key(|(_, entry)| (!entry.complete, entry.updates, entry.time))
+                }
+            };
+            let key = match victim {
+                Some((key, _)) => key.to_string(),
+                None => break,
+            };
+
+            let old = self.inner.shift_remove(&key).unwrap();
+            self.sync_delete(Some(&key));
+            self.on_event(Event::StorageEvicted {
+                storage_name: self.name.to_string(),
+                storage_type: self.type_,
+                key,
+                data: old.data,
+            });
+        }
+    }
+
+    fn new_entry(&self, data: &str) -> Entry {
+        let mut entry = Entry::new(data.to_string());
+        entry.step = self.step;
+        entry.action = self.action.clone();
+        entry
+    }
+
+    fn on_event(&self, event: Event) {
+        self.events_tx.send(event).unwrap();
+    }
+
+    #[cfg(feature = "redis")]
+    pub fn is_shared(&self) -> bool {
+        self.shared.is_some()
+    }
+
+    // share the storage with other agents using the same server, if the shared storage is empty
+    // it is initialized with the local contents, otherwise the local contents are replaced
+    #[cfg(feature = "redis")]
+    pub fn share(&mut self, redis: Arc<Redis>) -> Result<()> {
+        let remote = redis
+            .command(&["HLEN", &redis.key(&self.name)])?
+            .as_integer()?;
+        self.shared = Some(redis);
+        if remote > 0 {
+            self.refresh()
+        } else {
+            let keys: Vec<String> = self.inner.keys().cloned().collect();
+            for key in keys {
+                self.push(&key)?;
+            }
+            Ok(())
+        }
+    }
+
+    // replace the local contents with the shared ones
+    #[cfg(feature = "redis")]
+    pub fn refresh(&mut self) -> Result<()> {
+        let redis = match &self.shared {
+            Some(redis) => redis.clone(),
+            None => return Ok(()),
+        };
+
+        let mut entries = vec![];
+        for (key, value) in redis
+            .command(&["HGETALL", &redis.key(&self.name)])?
+            .as_pairs()?
+        {
+            let entry: SharedEntry = serde_json::from_str(&value)
+                .map_err(|e| anyhow!("invalid entry {} in storage {}: {}", key, self.name, e))?;
+            entries.push((key, entry));
+        }
+        entries.sort_by_key(|(_, entry)| entry.seq);
+        if matches!(self.type_, StorageType::Tree) {
+            entries.sort_by_key(|(key, _)| tree_path(key));
+        }
+
+        let mut inner = IndexMap::new();
+        for (key, shared) in entries {
+            let mut entry = self.new_entry(&shared.data);
+            if let Some(saved_at) = shared.saved_at {
+                entry.saved_at = saved_at;
+            }
+            entry.created_at = shared.created_at.unwrap_or(entry.saved_at);
+            entry.action = shared.action;
+            if let Some(local) = self.inner.get(&key) {
+                entry.time = local.time;
+                entry.step = local.step;
+            }
+            entry.complete = shared.complete;
+            entry.seq = shared.seq;
+            inner.insert(key, entry);
+        }
+        self.inner = inner;
+
+        Ok(())
+    }
+
+    #[cfg(feature = "redis")]
+    fn next_seq(redis: &Redis, name: &str) -> Result<u64> {
+        Ok(redis
+            .command(&["INCR", &redis.key(&format!("{}:seq", name))])?
+            .as_integer()? as u64)
+    }
+
+    // save an entry to the shared storage
+    #[cfg(feature = "redis")]
+    fn push(&mut self, key: &str) -> Result<()> {
+        let redis = match &self.shared {
+            Some(redis) => redis.clone(),
+            None => return Ok(()),
+        };
+        let entry = match self.inner.get_mut(key) {
+            Some(entry) => entry,
+            None => return Ok(()),
+        };
+        if entry.seq == 0 {
+            entry.seq = Self::next_seq(&redis, &self.name)?;
+        }
+        let value = serde_json::to_string(&SharedEntry {
+            data: entry.data.to_string(),
+            complete: entry.complete,
+            seq: entry.seq,
+            saved_at: Some(entry.saved_at),
+            created_at: Some(entry.created_at),
+            action: entry.action.clone(),
+        })?;
+        redis.command(&["HSET", &redis.key(&self.name), key, &value])?;
+        Ok(())
+    }
+
+    #[cfg(feature = "redis")]
+    fn sync(&mut self, key: &str) {
+        if let Err(err) = self.push(key) {
+            log::error!("can't update shared storage {}: {}", self.name, err);
+        }
+    }
+
+    #[cfg(not(feature = "redis"))]
+    fn sync(&mut self, _key: &str) {}
+
+    #[cfg(feature = "redis")]
+    fn sync_delete(&self, key: Option<&str>) {
+        if let Some(redis) = &self.shared {
+            let hash = redis.key(&self.name);
+            let ret = match key {
+                Some(key) => redis.command(&["HDEL", &hash, key]),
+                None => redis.command(&["DEL", &hash]),
+            };
+            if let Err(err) = ret {
+                log::error!("can't update shared storage {}: {}", self.name, err);
+            }
+        }
+    }
+
+    #[cfg(not(feature = "redis"))]
+    fn sync_delete(&self, _key: Option<&str>) {}
+
+    // positional keys are unique across the agents sharing the storage
+    fn next_tag(&mut self) -> (String, u64) {
+        #[cfg(feature = "redis")]
+        if let Some(redis) = &self.shared {
+            match Self::next_seq(redis, &self.name) {
+                Ok(seq) => return (format!("{}", seq), seq),
+                Err(err) => log::error!("can't update shared storage {}: {}", self.name, err),
+            }
+        }
+        // elements might have been removed, so the length can't be used
+        let last = self
+            .inner
+            .keys()
+            .filter_map(|key| key.parse::<usize>().ok())
+            .max()
+            .unwrap_or(0);
+        (format!("{}", last + 1), 0)
+    }
+
+    // the key of the element at the given position, as rendered to the model
+    fn tag_at(&self, pos: usize) -> String {
+        if let Some((key, _)) = pos.checked_sub(1).and_then(|idx| self.inner.get_index(idx)) {
+            return key.to_string();
+        }
+        format!("{}", pos)
+    }
+
+    pub fn get_started_at(&self) -> Instant {
+        assert!(matches!(self.type_, StorageType::Time));
+        self.inner.get(STARTED_AT_TAG).unwrap().time
+    }
+
+    // insert or replace an entry, keeping its position
+    fn insert(&mut self, key: &str, data: &str) {
+        let mut entry = self.new_entry(data);
+        if let Some(old) = self.inner.get(key) {
+            entry.seq = old.seq;
+            entry.updates = old.updates + 1;
+            entry.created_at = old.created_at;
+            entry.action = old.action.clone();
+        }
+        self.inner.insert(key.to_string(), entry);
+        self.sync(key);
+    }
+
+    pub fn add_data(&mut self, key: &str, data: &str) {
+        self.insert(key, data);
+        self.on_event(Event::StorageUpdate {
+            storage_name: self.name.to_string(),
+            storage_type: self.type_,
+            key: key.to_string(),
+            prev: None,
+            new: Some(data.to_string()),
+        });
+        self.enforce_quota(Some(key));
+    }
+
+    pub fn add_tagged(&mut self, key: &str, data: &str) {
+        assert!(matches!(self.type_, StorageType::Tagged));
+
+        self.insert(key, data);
+
+        self.on_event(Event::StorageUpdate {
+            storage_name: self.name.to_string(),
+            storage_type: self.type_,
+            key: key.to_string(),
+            prev: None,
+            new: Some(data.to_string()),
+        });
+        self.enforce_quota(Some(key));
+    }
+
+    pub fn del_tagged(&mut self, key: &str) -> Option<String> {
+        assert!(matches!(self.type_, StorageType::Tagged));
+        if let Some(old) = self.inner.shift_remove(key) {
+            self.sync_delete(Some(key));
+            self.on_event(Event::StorageUpdate {
+                storage_name: self.name.to_string(),
+                storage_type: self.type_,
+                key: key.to_string(),
+                prev: Some(old.data.to_string()),
+                new: None,
+            });
+
+            Some(old.data)
+        } else {
+            None
+        }
+    }
+
+    pub fn get_tagged(&self, key: &str) -> Option<String> {
+        assert!(matches!(self.type_, StorageType::Tagged));
+        self.inner.get(key).map(|va| va.data.to_string())
+    }
+
+    pub fn add_completion(&mut self, data: &str) {
+        assert!(matches!(self.type_, StorageType::Completion));
+        let (tag, seq) = self.next_tag();
+        let mut entry = self.new_entry(data);
+        entry.seq = seq;
+        self.inner.insert(tag.to_string(), entry);
+        self.sync(&tag);
+
+        self.on_event(Event::StorageUpdate {
+            storage_name: self.name.to_string(),
+            storage_type: self.type_,
+            key: tag.to_string(),
+            prev: None,
+            new: Some(data.to_string()),
+        });
+        self.enforce_quota(Some(&tag));
+    }
+
+    pub fn del_completion(&mut self, pos: usize) -> Option<String> {
+        assert!(matches!(self.type_, StorageType::Completion));
+        let tag = self.tag_at(pos);
+        if let Some(old) = self.inner.shift_remove(&tag) {
+            self.sync_delete(Some(&tag));
+            self.on_event(Event::StorageUpdate {
+                storage_name: self.name.to_string(),
+                storage_type: self.type_,
+                key: tag,
+                prev: Some(old.data.to_string()),
+                new: None,
+            });
+
+            Some(old.data)
+        } else {
+            None
+        }
+    }
+
+    pub fn set_complete(&mut self, pos: usize) -> Option<bool> {
+        assert!(matches!(self.type_, StorageType::Completion));
+        let tag = self.tag_at(pos);
+        if let Some(entry) = self.inner.get_mut(&tag) {
+            let prev = entry.complete;
+            entry.complete = true;
+            self.sync(&tag);
+
+            self.on_event(Event::StorageUpdate {
+                storage_name: self.name.to_string(),
+                storage_type: self.type_,
+                key: tag,
+                prev: Some((if prev { "complete" } else { "incomplete" }).to_string()),
+                new: Some("complete".to_string()),
+            });
+
+            Some(prev)
+        } else {
+            None
+        }
+    }
+
+    // add an item to a tree storage, at the top level or under parent, returning its key
+    pub fn add_child(&mut self, parent: Option<&str>, data: &str) -> Result<String> {
+        assert!(matches!(self.type_, StorageType::Tree));
+        if let Some(parent) = parent {
+            if !self.inner.contains_key(parent) {
+                return Err(anyhow!("'{}' not found", parent));
+            }
+        }
+
+        let depth = parent.map(|p| tree_path(p).len()).unwrap_or(0);
+        let last = self
+            .inner
+            .keys()
+            .filter(|key| match parent {
+                Some(parent) => key.starts_with(&format!("{}.", parent)),
+                None => true,
+            })
+            .map(|key| tree_path(key))
+            .filter(|path| path.len() == depth + 1)
+            .map(|path| path[depth])
+            .max()
+            .unwrap_or(0);
+        let key = match parent {
+            Some(parent) => format!("{}.{}", parent, last + 1),
+            None => format!("{}", last + 1),
+        };
+
+        self.insert(&key, data);
+        self.inner
+            .sort_by(|k1, _, k2, _| tree_path(k1).cmp(&tree_path(k2)));
+
+        self.on_event(Event::StorageUpdate {
+            storage_name: self.name.to_string(),
+            storage_type: self.type_,
+            key: key.to_string(),
+            prev: None,
+            new: Some(data.to_string()),
+        });
+        self.enforce_quota(Some(&key));
+
+        Ok(key)
+    }
+
+    // mark a tree item and its sub items as complete, returning the previous state of the item
+    pub fn set_complete_item(&mut self, item: &str) -> Option<bool> {
+        assert!(matches!(self.type_, StorageType::Tree));
+        let prev = self.inner.get(item)?.complete;
+
+        let keys: Vec<String> = self
+            .inner
+            .iter()
+            .filter(|(key, entry)| in_subtree(key, item) && !entry.complete)
+            .map(|(key, _)| key.to_string())
+            .collect();
+        for key in keys {
+            self.inner.get_mut(&key).unwrap().complete = true;
+            self.sync(&key);
+
+            self.on_event(Event::StorageUpdate {
+                storage_name: self.name.to_string(),
+                storage_type: self.type_,
+                key,
+                prev: Some("incomplete".to_string()),
+                new: Some("complete".to_string()),
+            });
+        }
+
+        Some(prev)
+    }
+
+    pub fn set_incomplete(&mut self, pos: usize) -> Option<bool> {
+        assert!(matches!(self.type_, StorageType::Completion));
+        let tag = self.tag_at(pos);
+        if let Some(entry) = self.inner.get_mut(&tag) {
+            let prev = entry.complete;
+            entry.complete = false;
+            self.sync(&tag);
+
+            self.on_event(Event::StorageUpdate {
+                storage_name: self.name.to_string(),
+                storage_type: self.type_,
+                key: tag,
+                prev: Some((if prev { "complete" } else { "incomplete" }).to_string()),
+                new: Some("incomplete".to_string()),
+            });
+
+            Some(prev)
+        } else {
+            None
+        }
+    }
+
+    pub fn add_untagged(&mut self, data: &str) {
+        assert!(matches!(self.type_, StorageType::Untagged));
+        let (tag, seq) = self.next_tag();
+        let mut entry = self.new_entry(data);
+        entry.seq = seq;
+        self.inner.insert(tag.to_string(), entry);
+        self.sync(&tag);
+
+        self.on_event(Event::StorageUpdate {
+            storage_name: self.name.to_string(),
+            storage_type: self.type_,
+            key: tag.to_string(),
+            prev: None,
+            new: Some(data.to_string()),
+        });
+        self.enforce_quota(Some(&tag));
+    }
+
+    pub fn del_untagged(&mut self, pos: usize) -> Option<String> {
+        assert!(matches!(self.type_, StorageType::Untagged));
+        let tag = self.tag_at(pos);
+        if let Some(old) = self.inner.shift_remove(&tag) {
+            self.sync_delete(Some(&tag));
+            self.on_event(Event::StorageUpdate {
+                storage_name: self.name.to_string(),
+                storage_type: self.type_,
+                key: tag,
+                prev: Some(old.data.to_string()),
+                new: None,
+            });
+            Some(old.data)
+        } else {
+            None
+        }
+    }
+
+    pub fn set_current(&mut self, data: &str) {
+        assert!(matches!(self.type_, StorageType::CurrentPrevious));
+
+        let old_current = self.inner.shift_remove(CURRENT_TAG);
+        self.inner
+            .insert(CURRENT_TAG.to_string(), self.new_entry(data));
+        self.sync(CURRENT_TAG);
+        let prev = if let Some(old_curr) = old_current {
+            let data = old_curr.data.to_string();
+            self.inner.insert(PREVIOUS_TAG.to_string(), old_curr);
+            self.sync(PREVIOUS_TAG);
+            Some(data)
+        } else {
+            None
+        };
+
+        self.on_event(Event::StorageUpdate {
+            storage_name: self.name.to_string(),
+            storage_type: self.type_,
+            key: CURRENT_TAG.to_string(),
+            prev,
+            new: Some(data.to_string()),
+        });
+    }
+
+    pub fn copy_from(&mut self, other: &Storage) {
+        // time storages are never inherited
+        if matches!(self.type_, StorageType::Time) || self.type_ != other.type_ {
+            return;
+        }
+
+        for (key, entry) in other.iter() {
+            self.add_data(key, &entry.data);
+            if let Some(new_entry) = self.inner.get_mut(key) {
+                new_entry.complete = entry.complete;
+            }
+        }
+    }
+
+    // replace the contents with previously saved entries, without emitting events
+    pub fn restore<'a, I>(&mut self, entries: I)
+    where
+        I: Iterator<Item = &'a SessionEntry>,
+    {
+        self.inner.clear();
+        for saved in entries {
+            let mut entry = Entry::new(saved.data.to_string());
+            entry.complete = saved.complete;
+            if let Some(saved_at) = saved.saved_at {
+                entry.saved_at = saved_at;
+            }
+            entry.created_at = saved.created_at.unwrap_or(entry.saved_at);
+            entry.step = saved.step;
+            entry.action = saved.action.clone();
+            self.inner.insert(saved.key.to_string(), entry);
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.inner.clear();
+        self.sync_delete(None);
+        self.on_event(Event::StorageUpdate {
+            storage_name: self.name.to_string(),
+            storage_type: self.type_,
+            key: "".to_string(),
+            prev: None,
+            new: None,
+        });
+    }
+}
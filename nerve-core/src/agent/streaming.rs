@@ -0,0 +1,185 @@
+use std::{
+    future::Future,
+    process::{Output, Stdio},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use tokio::{
+    io::{AsyncBufReadExt, AsyncRead, AsyncWriteExt, BufReader},
+    process::Command,
+};
+
+use super::state::SharedState;
+
+// how often a running action is reported as alive
+pub const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(10);
+
+// when the running action last produced some output
+#[derive(Debug, Clone)]
+pub struct Activity(Arc<Mutex<Instant>>);
+
+impl Default for Activity {
+    fn default() -> Self {
+        Self(Arc::new(Mutex::new(Instant::now())))
+    }
+}
+
+impl Activity {
+    pub fn touch(&self) {
+        *self.0.lock().unwrap() = Instant::now();
+    }
+
+    pub fn last(&self) -> Instant {
+        *self.0.lock().unwrap()
+    }
+}
+
+// run the future until it completes or there's no activity for longer than the timeout, in
+// which case None is returned; on_heartbeat is called with the elapsed time at every interval
+pub async fn with_idle_timeout<F: Future>(
+    future: F,
+    timeout: Duration,
+    activity: &Activity,
+    interval: Duration,
+    mut on_heartbeat: impl FnMut(Duration),
+) -> Option<F::Output> {
+    let started_at = Instant::now();
+    activity.touch();
+
+    tokio::pin!(future);
+    let mut heartbeat = tokio::time::interval_at(
+        tokio::time::Instant::from_std(started_at + interval),
+        interval,
+    );
+
+    loop {
+        let deadline = tokio::time::Instant::from_std(activity.last() + timeout);
+        tokio::select! {
+            output = &mut future => return Some(output),
+            _ = heartbeat.tick() => on_heartbeat(started_at.elapsed()),
+            _ = tokio::time::sleep_until(deadline) => {
+                // there might have been some activity in the meantime
+                if activity.last() + timeout <= Instant::now() {
+                    return None;
+                }
+            }
+        }
+    }
+}
+
+async fn stream_lines<R: AsyncRead + Unpin>(
+    reader: R,
+    state: &SharedState,
+    max_shown: Option<usize>,
+) -> Vec<u8> {
+    let mut reader = BufReader::new(reader);
+    let mut data = vec![];
+    let mut line = vec![];
+    let mut truncated = false;
+
+    while let Ok(read) = reader.read_until(b'\n', &mut line).await {
+        if read == 0 {
+            break;
+        }
+
+        let state = state.lock().await;
+        if max_shown.is_some_and(|max| data.len() + line.len() > max) {
+            // keep the activity going, but stop flooding the events
+            state.touch_activity();
+            if !truncated {
+                truncated = true;
+                state.on_action_output("... truncated ...\n");
+            }
+        } else {
+            state.on_action_output(&String::from_utf8_lossy(&line));
+        }
+        drop(state);
+
+        data.append(&mut line);
+    }
+
+    data
+}
+
+// execute the command reporting its output line by line while it runs, the command is
+// killed if the returned future is dropped
+pub async fn run_command(
+    mut cmd: Command,
+    stdin: Option<String>,
+    state: &SharedState,
+    max_shown: Option<usize>,
+) -> std::io::Result<Output> {
+    let mut child = cmd
+        .stdin(if stdin.is_some() {
+            Stdio::piped()
+        } else {
+            Stdio::null()
+        })
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()?;
+
+    if let (Some(data), Some(mut pipe)) = (stdin, child.stdin.take()) {
+        tokio::spawn(async move {
+            // the command is free not to read it
+            let _ = pipe.write_all(data.as_bytes()).await;
+        });
+    }
+
+    let stdout = child.stdout.take().unwrap();
+    let stderr = child.stderr.take().unwrap();
+    let (stdout, stderr) = tokio::join!(
+        stream_lines(stdout, state, max_shown),
+        stream_lines(stderr, state, max_shown)
+    );
+    let status = child.wait().await?;
+
+    Ok(Output {
+        status,
+        stdout,
+        stderr,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_idle_timeout() {
+        let activity = Activity::default();
+        let mut heartbeats = 0;
+
+        // completes, even after longer than the timeout since it keeps being active
+        let active = activity.clone();
+        let output = with_idle_timeout(
+            async move {
+                for _ in 0..6 {
+                    tokio::time::sleep(Duration::from_millis(20)).await;
+                    active.touch();
+                }
+                42
+            },
+            Duration::from_millis(60),
+            &activity,
+            Duration::from_millis(25),
+            |_| heartbeats += 1,
+        )
+        .await;
+        assert_eq!(output, Some(42));
+        assert!(heartbeats >= 3);
+
+        // silent for longer than the timeout
+        let output = with_idle_timeout(
+            tokio::time::sleep(Duration::from_secs(5)),
+            Duration::from_millis(50),
+            &activity,
+            HEARTBEAT_INTERVAL,
+            |_| {},
+        )
+        .await;
+        assert_eq!(output, None);
+    }
+}
@@ -0,0 +1,282 @@
+use std::path::Path;
+
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde::Deserialize;
+
+// how documents are split in chunks
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Splitter {
+    // fixed size chunks, regardless of the contents
+    #[default]
+    Characters,
+    // paragraphs, then sentences
+    Sentences,
+    // sections by heading level, then paragraphs and sentences
+    Markdown,
+    // top level definitions, then blocks and lines
+    Code,
+    // pick one from the file extension
+    Auto,
+}
+
+const MARKDOWN_EXTENSIONS: &[&str] = &["md", "markdown", "mdx", "rst"];
+const CODE_EXTENSIONS: &[&str] = &[
+    "rs", "py", "go", "c", "h", "cc", "cpp", "hpp", "cs", "java", "kt", "scala", "swift", "js",
+    "jsx", "ts", "tsx", "rb", "php", "pl", "lua", "sh", "bash", "zsh", "ps1", "sql", "yml", "yaml",
+    "toml", "json", "xml", "html", "css",
+];
+
+// where a piece of text can be split
+struct Boundary {
+    expr: &'static Regex,
+    // split after the match instead of before
+    after: bool,
+}
+
+lazy_static! {
+    static ref H1: Regex = Regex::new(r"(?m)^# ").unwrap();
+    static ref H2: Regex = Regex::new(r"(?m)^## ").unwrap();
+    static ref H3: Regex = Regex::new(r"(?m)^### ").unwrap();
+    static ref H4: Regex = Regex::new(r"(?m)^#{4,6} ").unwrap();
+    static ref PARAGRAPH: Regex = Regex::new(r"\n[ \t]*\n\s*").unwrap();
+    static ref SENTENCE: Regex = Regex::new(r"[.!?]+[\s]+").unwrap();
+    static ref LINE: Regex = Regex::new(r"\n").unwrap();
+    static ref WORD: Regex = Regex::new(r"\s+").unwrap();
+    // a line that is not indented nor closing a block
+    static ref TOP_LEVEL: Regex = Regex::new(r"(?m)^[^\s\}\)\]]").unwrap();
+}
+
+impl Splitter {
+    pub fn resolve(self, path: &Path) -> Self {
+        if self != Splitter::Auto {
+            return self;
+        }
+
+        let ext = path
+            .extension()
+            .map(|e| e.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        if MARKDOWN_EXTENSIONS.contains(&ext.as_str()) {
+            Splitter::Markdown
+        } else if CODE_EXTENSIONS.contains(&ext.as_str()) {
+            Splitter::Code
+        } else {
+            Splitter::Sentences
+        }
+    }
+
+    // from the preferred to the last resort
+    fn boundaries(&self) -> Vec<Boundary> {
+        let before = |expr| Boundary { expr, after: false };
+        let after = |expr| Boundary { expr, after: true };
+
+        match self {
+            Splitter::Characters | Splitter::Auto => vec![],
+            Splitter::Sentences => vec![after(&PARAGRAPH), after(&SENTENCE), after(&WORD)],
+            Splitter::Markdown => vec![
+                before(&H1),
+                before(&H2),
+                before(&H3),
+                before(&H4),
+                after(&PARAGRAPH),
+                after(&SENTENCE),
+                after(&WORD),
+            ],
+            Splitter::Code => vec![
+                before(&TOP_LEVEL),
+                after(&PARAGRAPH),
+                after(&LINE),
+                after(&WORD),
+            ],
+        }
+    }
+}
+
+fn len(text: &str) -> usize {
+    text.chars().count()
+}
+
+// split at every match of the boundary, the pieces joined together are the original text
+fn split_at<'a>(text: &'a str, boundary: &Boundary) -> Vec<&'a str> {
+    let mut pieces = vec![];
+    let mut start = 0;
+    for m in boundary.expr.find_iter(text) {
+        let pos = if boundary.after { m.end() } else { m.start() };
+        if pos > start && pos < text.len() {
+            pieces.push(&text[start..pos]);
+            start = pos;
+        }
+    }
+    pieces.push(&text[start..]);
+    pieces
+}
+
+// split in pieces no longer than size, using the first boundary that works
+fn segments(text: &str, boundaries: &[Boundary], size: usize) -> Vec<String> {
+    if len(text) <= size {
+        return vec![text.to_string()];
+    }
+
+    match boundaries.split_first() {
+        Some((boundary, rest)) => {
+            let pieces = split_at(text, boundary);
+            if pieces.len() == 1 {
+                return segments(text, rest, size);
+            }
+            pieces
+                .into_iter()
+                .flat_map(|piece| segments(piece, rest, size))
+                .collect()
+        }
+        None => {
+            // no boundary left, cut it
+            let chars: Vec<char> = text.chars().collect();
+            chars.chunks(size).map(|c| c.iter().collect()).collect()
+        }
+    }
+}
+
+// split text in chunks of up to size characters, breaking it at the boundaries of the splitter
+// and repeating up to overlap characters of whole segments from the previous chunk, each chunk
+// is returned with its offset in characters from the start of the text
+pub fn split(text: &str, splitter: Splitter, size: usize, overlap: usize) -> Vec<(usize, String)> {
+    let segments = segments(text, &splitter.boundaries(), size);
+    let lengths: Vec<usize> = segments.iter().map(|s| len(s)).collect();
+    let offsets: Vec<usize> = lengths
+        .iter()
+        .scan(0, |offset, len| {
+            let start = *offset;
+            *offset += len;
+            Some(start)
+        })
+        .collect();
+
+    let chunk = |indexes: &[usize]| {
+        (
+            offsets[indexes[0]],
+            indexes.iter().map(|i| segments[*i].as_str()).collect(),
+        )
+    };
+
+    let mut chunks = vec![];
+    // indexes of the segments of the chunk being built
+    let mut current: Vec<usize> = vec![];
+    let mut current_len = 0;
+    // whether current has anything besides the overlap
+    let mut has_new = false;
+
+    for (idx, segment_len) in lengths.iter().enumerate() {
+        if current_len + segment_len > size && has_new {
+            chunks.push(chunk(&current));
+
+            // keep the trailing segments that fit in the overlap
+            let mut kept = vec![];
+            let mut kept_len = 0;
+            for prev in current.iter().rev() {
+                let prev_len = lengths[*prev];
+                if kept_len + prev_len > overlap || kept_len + prev_len + segment_len > size {
+                    break;
+                }
+                kept.insert(0, *prev);
+                kept_len += prev_len;
+            }
+            current = kept;
+            current_len = kept_len;
+        }
+
+        current.push(idx);
+        current_len += segment_len;
+        has_new = true;
+    }
+
+    if has_new {
+        chunks.push(chunk(&current));
+    }
+
+    chunks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn split_text(text: &str, splitter: Splitter, size: usize, overlap: usize) -> Vec<String> {
+        split(text, splitter, size, overlap)
+            .into_iter()
+            .map(|(_, chunk)| chunk)
+            .collect()
+    }
+
+    #[test]
+    fn test_sentences() {
+        let text = "First sentence. Second one! Third?\n\nNew paragraph here.";
+        let chunks = split_text(text, Splitter::Sentences, 30, 0);
+        assert_eq!(
+            chunks,
+            vec![
+                "First sentence. Second one! ",
+                "Third?\n\nNew paragraph here."
+            ]
+        );
+        assert_eq!(chunks.concat(), text);
+        assert_eq!(split(text, Splitter::Sentences, 30, 0)[1].0, 28);
+    }
+
+    #[test]
+    fn test_overlap_keeps_whole_segments() {
+        let text = "One. Two. Three. Four.";
+        let chunks = split_text(text, Splitter::Sentences, 12, 6);
+        assert_eq!(chunks, vec!["One. Two. ", "Two. Three. ", "Four."]);
+
+        let offsets: Vec<usize> = split(text, Splitter::Sentences, 12, 6)
+            .into_iter()
+            .map(|(offset, _)| offset)
+            .collect();
+        assert_eq!(offsets, vec![0, 5, 17]);
+    }
+
+    #[test]
+    fn test_markdown_sections() {
+        let text = "# Title\nintro\n## A\nabout a\n## B\nabout b\n";
+        let chunks = split_text(text, Splitter::Markdown, 20, 0);
+        assert_eq!(
+            chunks,
+            vec!["# Title\nintro\n", "## A\nabout a\n", "## B\nabout b\n"]
+        );
+    }
+
+    #[test]
+    fn test_code_top_level() {
+        let text = "fn a() {\n    1\n}\n\nfn b() {\n    2\n}\n";
+        let chunks = split_text(text, Splitter::Code, 20, 0);
+        assert_eq!(
+            chunks,
+            vec!["fn a() {\n    1\n}\n\n", "fn b() {\n    2\n}\n"]
+        );
+    }
+
+    #[test]
+    fn test_long_words_are_cut() {
+        let chunks = split_text("abcdefghij", Splitter::Sentences, 4, 0);
+        assert_eq!(chunks, vec!["abcd", "efgh", "ij"]);
+    }
+
+    #[test]
+    fn test_auto() {
+        assert_eq!(
+            Splitter::Auto.resolve(Path::new("README.md")),
+            Splitter::Markdown
+        );
+        assert_eq!(Splitter::Auto.resolve(Path::new("main.rs")), Splitter::Code);
+        assert_eq!(
+            Splitter::Auto.resolve(Path::new("notes.txt")),
+            Splitter::Sentences
+        );
+        assert_eq!(
+            Splitter::Sentences.resolve(Path::new("main.rs")),
+            Splitter::Sentences
+        );
+    }
+}
@@ -0,0 +1,486 @@
+use std::collections::BTreeMap;
+#[cfg(feature = "crawler")]
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    time::Duration,
+};
+
+use anyhow::Result;
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde::Deserialize;
+use url::Url;
+
+use super::chunking::Splitter;
+
+#[cfg(feature = "crawler")]
+const USER_AGENT: &str = concat!("nerve/", env!("CARGO_PKG_VERSION"));
+#[cfg(feature = "crawler")]
+const TIMEOUT: Duration = Duration::from_secs(30);
+const DEFAULT_MAX_PAGES: usize = 50;
+#[cfg(feature = "crawler")]
+const MAX_REDIRECTS: usize = 10;
+// anything after this many bytes of a page is ignored
+#[cfg(feature = "crawler")]
+const MAX_BODY_SIZE: usize = 5 * 1024 * 1024;
+
+fn default_max_pages() -> usize {
+    DEFAULT_MAX_PAGES
+}
+
+// a website to crawl and import in the rag store
+#[derive(Deserialize, Debug, Clone)]
+pub struct RagUrl {
+    // the page to start from
+    pub url: String,
+    // how many links away from the start page to follow, 0 to only import it
+    #[serde(default)]
+    pub depth: usize,
+    #[serde(default = "default_max_pages")]
+    pub max_pages: usize,
+    // hosts that links can point to, the one of url if not set
+    pub domains: Option<Vec<String>>,
+    // override the global chunking options for this website
+    pub chunk_size: Option<usize>,
+    pub chunk_overlap: Option<usize>,
+    pub splitter: Option<Splitter>,
+    // metadata added as a header to every chunk of this website
+    pub metadata: Option<BTreeMap<String, String>>,
+}
+
+impl RagUrl {
+    pub fn validate(&self) -> Result<()> {
+        if cfg!(not(feature = "crawler")) {
+            return Err(anyhow!(
+                "can't crawl rag url '{}', nerve was built without the crawler feature",
+                self.url
+            ));
+        }
+        let url =
+            Url::parse(&self.url).map_err(|e| anyhow!("invalid rag url '{}': {}", self.url, e))?;
+        if !matches!(url.scheme(), "http" | "https") {
+            return Err(anyhow!("rag url '{}' must be http or https", self.url));
+        }
+        if self.max_pages == 0 {
+            return Err(anyhow!("rag url max_pages can't be 0"));
+        }
+        Ok(())
+    }
+
+    #[cfg(feature = "crawler")]
+    fn allows(&self, start: &Url, url: &Url) -> bool {
+        if !matches!(url.scheme(), "http" | "https") {
+            return false;
+        }
+        let host = url.host_str().unwrap_or_default();
+        match &self.domains {
+            Some(domains) => domains.iter().any(|domain| domain == host),
+            None => host == start.host_str().unwrap_or_default(),
+        }
+    }
+}
+
+// a crawled page converted to text
+#[cfg(feature = "crawler")]
+#[derive(Debug, Clone)]
+pub struct Page {
+    pub url: String,
+    pub title: Option<String>,
+    pub text: String,
+    // converted from html rather than served as text
+    pub html: bool,
+}
+
+lazy_static! {
+    static ref COMMENT: Regex = Regex::new(r"(?s)<!--.*?-->").unwrap();
+    // elements whose contents are not shown
+    static ref INVISIBLE: Vec<Regex> = ["head", "script", "style", "noscript", "template", "svg"]
+        .iter()
+        .map(|tag| Regex::new(&format!(r"(?is)<{tag}\b[^>]*>.*?</{tag}\s*>")).unwrap())
+        .collect();
+    // tags after which the text continues on a new line
+    static ref BLOCK: Regex = Regex::new(
+        r"(?i)<(br|/?p|/?div|/?li|/?ul|/?ol|/?tr|/?table|/?h[1-6]|/?pre|/?blockquote|/?section|/?article|/?header|/?footer|/?nav|hr)\b[^>]*>"
+    )
+    .unwrap();
+    static ref TAG: Regex = Regex::new(r"(?s)<[^>]*>").unwrap();
+    static ref ENTITY: Regex = Regex::new(r"&(#[0-9]+|#[xX][0-9a-fA-F]+|[a-zA-Z]+);").unwrap();
+    static ref SPACES: Regex = Regex::new(r"[ \t\r\x0c]+").unwrap();
+    static ref NEWLINES: Regex = Regex::new(r"\n\s*\n\s*").unwrap();
+}
+
+#[cfg(feature = "crawler")]
+lazy_static! {
+    static ref TITLE: Regex = Regex::new(r"(?is)<title\b[^>]*>(.*?)</title\s*>").unwrap();
+    static ref LINK: Regex =
+        Regex::new(r#"(?is)<a\b[^>]*?\shref\s*=\s*(?:"([^"]*)"|'([^']*)'|([^\s>]+))"#).unwrap();
+}
+
+fn decode_entities(text: &str) -> String {
+    ENTITY
+        .replace_all(text, |caps: &regex::Captures| {
+            let entity = &caps[1];
+            let decoded = match entity {
+                "amp" => Some('&'),
+                "lt" => Some('<'),
+                "gt" => Some('>'),
+                "quot" => Some('"'),
+                "apos" => Some('\''),
+                "nbsp" => Some(' '),
+                _ if entity.starts_with("#x") || entity.starts_with("#X") => {
+                    u32::from_str_radix(&entity[2..], 16)
+                        .ok()
+                        .and_then(char::from_u32)
+                }
+                _ if entity.starts_with('#') => entity[1..].parse().ok().and_then(char::from_u32),
+                _ => None,
+            };
+            decoded
+                .map(|c| c.to_string())
+                .unwrap_or(caps[0].to_string())
+        })
+        .to_string()
+}
+
+fn strip_invisible(html: &str) -> String {
+    let mut html = COMMENT.replace_all(html, "").to_string();
+    for expr in INVISIBLE.iter() {
+        html = expr.replace_all(&html, "").to_string();
+    }
+    html
+}
+
+#[cfg(feature = "crawler")]
+pub fn html_title(html: &str) -> Option<String> {
+    TITLE
+        .captures(html)
+        .map(|caps| decode_entities(TAG.replace_all(&caps[1], "").trim()))
+        .filter(|title| !title.is_empty())
+}
+
+// readable text of an html page
+pub fn html_to_text(html: &str) -> String {
+    let html = strip_invisible(html);
+    let html = BLOCK.replace_all(&html, "\n");
+    let text = decode_entities(&TAG.replace_all(&html, ""));
+
+    let text = text
+        .lines()
+        .map(|line| SPACES.replace_all(line, " ").trim().to_string())
+        .collect::<Vec<String>>()
+        .join("\n");
+
+    NEWLINES.replace_all(text.trim(), "\n\n").to_string()
+}
+
+// absolute urls of the links in the page, without fragments
+#[cfg(feature = "crawler")]
+pub fn html_links(base: &Url, html: &str) -> Vec<Url> {
+    let html = strip_invisible(html);
+    let mut links = vec![];
+    for caps in LINK.captures_iter(&html) {
+        let href = caps
+            .get(1)
+            .or(caps.get(2))
+            .or(caps.get(3))
+            .map(|m| decode_entities(m.as_str().trim()))
+            .unwrap_or_default();
+        if href.is_empty() || href.starts_with('#') {
+            continue;
+        }
+        if let Ok(mut url) = base.join(&href) {
+            url.set_fragment(None);
+            if !links.contains(&url) {
+                links.push(url);
+            }
+        }
+    }
+    links
+}
+
+// path prefixes that robots.txt disallows for every user agent
+#[cfg(feature = "crawler")]
+fn disallowed(robots: &str) -> Vec<String> {
+    let mut rules = vec![];
+    let mut applies = false;
+    // consecutive user-agent lines share the same rules
+    let mut in_agents = false;
+
+    for line in robots.lines() {
+        let line = line.split('#').next().unwrap_or_default().trim();
+        let Some((key, value)) = line.split_once(':') else {
+            continue;
+        };
+        let value = value.trim();
+        match key.trim().to_lowercase().as_str() {
+            "user-agent" => {
+                if !in_agents {
+                    applies = false;
+                }
+                in_agents = true;
+                applies |= value == "*";
+            }
+            "disallow" => {
+                in_agents = false;
+                if applies && !value.is_empty() {
+                    rules.push(value.to_string());
+                }
+            }
+            _ => in_agents = false,
+        }
+    }
+
+    rules
+}
+
+// read the body of the response up to MAX_BODY_SIZE
+#[cfg(feature = "crawler")]
+async fn read_body(mut response: reqwest::Response) -> Result<String> {
+    let mut body = vec![];
+    while let Some(chunk) = response.chunk().await? {
+        let left = MAX_BODY_SIZE - body.len();
+        if chunk.len() >= left {
+            log::debug!(
+                "crawler: {} is larger than {} bytes, truncating",
+                response.url(),
+                MAX_BODY_SIZE
+            );
+            body.extend_from_slice(&chunk[..left]);
+            break;
+        }
+        body.extend_from_slice(&chunk);
+    }
+    Ok(String::from_utf8_lossy(&body).to_string())
+}
+
+#[cfg(feature = "crawler")]
+pub async fn crawl(config: &RagUrl) -> Result<Vec<Page>> {
+    let start = Url::parse(&config.url)?;
+    // redirects are only followed to the hosts that can be crawled
+    let redirects = {
+        let (config, start) = (config.clone(), start.clone());
+        reqwest::redirect::Policy::custom(move |attempt| {
+            if attempt.previous().len() > MAX_REDIRECTS {
+                attempt.error("too many redirects")
+            } else if config.allows(&start, attempt.url()) {
+                attempt.follow()
+            } else {
+                attempt.stop()
+            }
+        })
+    };
+    let client = reqwest::Client::builder()
+        .user_agent(USER_AGENT)
+        .timeout(TIMEOUT)
+        .redirect(redirects)
+        .build()?;
+
+    let mut robots = HashMap::new();
+    let mut pages = vec![];
+    let mut seen = HashSet::from([start.clone()]);
+    let mut queue = VecDeque::from([(start.clone(), 0)]);
+
+    while let Some((url, depth)) = queue.pop_front() {
+        if pages.len() >= config.max_pages {
+            break;
+        }
+
+        // be polite and honor the robots.txt of every host
+        let origin = url.origin().ascii_serialization();
+        if !robots.contains_key(&origin) {
+            let rules = match client.get(format!("{}/robots.txt", origin)).send().await {
+                Ok(res) if res.status().is_success() => {
+                    disallowed(&read_body(res).await.unwrap_or_default())
+                }
+                _ => vec![],
+            };
+            robots.insert(origin.clone(), rules);
+        }
+        if robots[&origin]
+            .iter()
+            .any(|prefix| url.path().starts_with(prefix.as_str()))
+        {
+            log::debug!("crawler: {} is disallowed by robots.txt", url);
+            continue;
+        }
+
+        let response = match client.get(url.clone()).send().await {
+            Ok(res) if res.status().is_success() => res,
+            Ok(res) => {
+                log::warn!("crawler: {} returned {}", url, res.status());
+                continue;
+            }
+            Err(err) => {
+                log::warn!("crawler: {}: {}", url, err);
+                continue;
+            }
+        };
+
+        // redirects can lead to a different page, that must be allowed and not seen yet
+        let redirected = response.url() != &url;
+        let url = response.url().clone();
+        if redirected && !config.allows(&start, &url) {
+            log::warn!("crawler: {} is not an allowed domain", url);
+            continue;
+        }
+        if redirected && !seen.insert(url.clone()) {
+            continue;
+        }
+        let content_type = response
+            .headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or("text/html")
+            .to_lowercase();
+        let is_html = content_type.contains("html");
+        if !is_html && !content_type.starts_with("text/") {
+            log::debug!("crawler: skipping {} ({})", url, content_type);
+            continue;
+        }
+
+        let body = match read_body(response).await {
+            Ok(body) => body,
+            Err(err) => {
+                log::warn!("crawler: {}: {}", url, err);
+                continue;
+            }
+        };
+
+        if is_html {
+            if depth < config.depth {
+                for link in html_links(&url, &body) {
+                    if config.allows(&start, &link) && seen.insert(link.clone()) {
+                        queue.push_back((link, depth + 1));
+                    }
+                }
+            }
+            pages.push(Page {
+                url: url.to_string(),
+                title: html_title(&body),
+                text: html_to_text(&body),
+                html: true,
+            });
+        } else {
+            pages.push(Page {
+                url: url.to_string(),
+                title: None,
+                text: body,
+                html: false,
+            });
+        }
+    }
+
+    log::debug!("crawler: {} pages from {}", pages.len(), config.url);
+
+    Ok(pages)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PAGE: &str = r#"<html><head><title>Docs &amp; Guides</title><style>body { color: red }</style></head>
+<body>
+  <nav><a href="/">Home</a> <a href='guide.html#install'>Guide</a> <a href="https://other.com/x">Other</a></nav>
+  <h1>Welcome</h1>
+  <p>Nerve is an   <b>agent</b> development kit.</p>
+  <script>var x = "<p>hidden</p>";</script>
+  <!-- <a href="/secret">secret</a> -->
+  <ul><li>one</li><li>two &lt;3&#33;</li></ul>
+</body></html>"#;
+
+    #[test]
+    fn test_html_to_text() {
+        assert_eq!(html_title(PAGE), Some("Docs & Guides".to_string()));
+        assert_eq!(
+            html_to_text(PAGE),
+            "Home Guide Other\n\nWelcome\n\nNerve is an agent development kit.\n\none\n\ntwo <3!"
+        );
+    }
+
+    #[cfg(feature = "crawler")]
+    #[test]
+    fn test_html_links() {
+        let base = Url::parse("https://docs.example.com/en/index.html").unwrap();
+        let links: Vec<String> = html_links(&base, PAGE)
+            .into_iter()
+            .map(|u| u.to_string())
+            .collect();
+        assert_eq!(
+            links,
+            vec![
+                "https://docs.example.com/",
+                "https://docs.example.com/en/guide.html",
+                "https://other.com/x"
+            ]
+        );
+
+        let config: RagUrl = serde_yaml::from_str("url: https://docs.example.com/").unwrap();
+        let start = Url::parse(&config.url).unwrap();
+        assert!(config.allows(&start, &Url::parse("https://docs.example.com/a").unwrap()));
+        assert!(!config.allows(&start, &Url::parse("https://other.com/x").unwrap()));
+        assert!(!config.allows(&start, &Url::parse("ftp://docs.example.com/").unwrap()));
+    }
+
+    #[cfg(feature = "crawler")]
+    #[tokio::test]
+    async fn test_crawl_redirects_and_size() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut buffer = [0u8; 4096];
+                let read = stream.read(&mut buffer).await.unwrap();
+                let request = String::from_utf8_lossy(&buffer[..read]).to_string();
+                let path = request.split(' ').nth(1).unwrap_or_default().to_string();
+                let (head, body) = match path.as_str() {
+                    "/" => (
+                        "200 OK\r\nContent-Type: text/html".to_string(),
+                        "<a href=\"/out\">out</a> <a href=\"/big\">big</a>".to_string(),
+                    ),
+                    // same server, but not the same host
+                    "/out" => (
+                        format!("302 Found\r\nLocation: http://localhost:{}/", port),
+                        String::new(),
+                    ),
+                    "/big" => (
+                        "200 OK\r\nContent-Type: text/plain".to_string(),
+                        "a".repeat(MAX_BODY_SIZE + 1024),
+                    ),
+                    _ => ("404 Not Found".to_string(), String::new()),
+                };
+                let response = format!(
+                    "HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    head,
+                    body.len(),
+                    body
+                );
+                let _ = stream.write_all(response.as_bytes()).await;
+            }
+        });
+
+        let config: RagUrl =
+            serde_yaml::from_str(&format!("url: http://127.0.0.1:{}/\ndepth: 1", port)).unwrap();
+        let pages = crawl(&config).await.unwrap();
+        let urls: Vec<&str> = pages.iter().map(|page| page.url.as_str()).collect();
+
+        assert_eq!(
+            urls,
+            vec![
+                format!("http://127.0.0.1:{}/", port),
+                format!("http://127.0.0.1:{}/big", port)
+            ]
+        );
+        assert_eq!(pages[1].text.len(), MAX_BODY_SIZE);
+    }
+
+    #[cfg(feature = "crawler")]
+    #[test]
+    fn test_robots() {
+        let robots = "User-agent: googlebot\nDisallow: /google\n\nUser-agent: foo\nUser-agent: *\nDisallow: /private # comment\nDisallow:\n";
+        assert_eq!(disallowed(robots), vec!["/private"]);
+    }
+}
@@ -0,0 +1,302 @@
+#[cfg(feature = "documents")]
+use std::io::Read;
+use std::path::Path;
+
+use anyhow::Result;
+#[cfg(feature = "documents")]
+use flate2::read::DeflateDecoder;
+#[cfg(feature = "documents")]
+use xml::{reader::XmlEvent, EventReader};
+
+use super::crawler;
+
+// how the text of a document is extracted before being imported
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Text,
+    Html,
+    Pdf,
+    Docx,
+}
+
+impl Format {
+    pub fn from_path(path: &Path) -> Self {
+        let ext = path
+            .extension()
+            .map(|e| e.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        match ext.as_str() {
+            "html" | "htm" | "xhtml" => Format::Html,
+            "pdf" => Format::Pdf,
+            "docx" => Format::Docx,
+            _ => Format::Text,
+        }
+    }
+}
+
+// the text of the document at path
+pub fn read(path: &Path) -> Result<String> {
+    match Format::from_path(path) {
+        Format::Text => Ok(std::fs::read_to_string(path)?),
+        Format::Html => Ok(crawler::html_to_text(&String::from_utf8_lossy(
+            &std::fs::read(path)?,
+        ))),
+        #[cfg(feature = "documents")]
+        Format::Pdf => pdf_to_text(&std::fs::read(path)?),
+        #[cfg(feature = "documents")]
+        Format::Docx => docx_to_text(&std::fs::read(path)?),
+        #[cfg(not(feature = "documents"))]
+        Format::Pdf | Format::Docx => Err(anyhow!(
+            "can't read {}, nerve was built without the documents feature",
+            path.display()
+        )),
+    }
+}
+
+#[cfg(feature = "documents")]
+fn u16_at(data: &[u8], pos: usize) -> Result<usize> {
+    data.get(pos..pos + 2)
+        .map(|b| u16::from_le_bytes([b[0], b[1]]) as usize)
+        .ok_or_else(|| anyhow!("unexpected end of zip data"))
+}
+
+#[cfg(feature = "documents")]
+fn u32_at(data: &[u8], pos: usize) -> Result<usize> {
+    data.get(pos..pos + 4)
+        .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as usize)
+        .ok_or_else(|| anyhow!("unexpected end of zip data"))
+}
+
+// contents of a file in a zip archive, only stored and deflated entries are supported
+#[cfg(feature = "documents")]
+fn unzip(data: &[u8], name: &str) -> Result<Vec<u8>> {
+    // the end of central directory record is at the end, followed by an optional comment
+    let search_from = data.len().saturating_sub(22 + u16::MAX as usize);
+    let eocd = data[search_from..]
+        .windows(4)
+        .rposition(|w| w == b"PK\x05\x06")
+        .map(|pos| pos + search_from)
+        .ok_or_else(|| anyhow!("not a zip archive"))?;
+
+    let entries = u16_at(data, eocd + 10)?;
+    let mut pos = u32_at(data, eocd + 16)?;
+
+    for _ in 0..entries {
+        if data.get(pos..pos + 4) != Some(b"PK\x01\x02") {
+            return Err(anyhow!("invalid zip central directory"));
+        }
+        let method = u16_at(data, pos + 10)?;
+        let compressed_size = u32_at(data, pos + 20)?;
+        let name_len = u16_at(data, pos + 28)?;
+        let extra_len = u16_at(data, pos + 30)?;
+        let comment_len = u16_at(data, pos + 32)?;
+        let header = u32_at(data, pos + 42)?;
+        let entry_name = data
+            .get(pos + 46..pos + 46 + name_len)
+            .ok_or_else(|| anyhow!("unexpected end of zip data"))?;
+
+        if entry_name == name.as_bytes() {
+            let start = header + 30 + u16_at(data, header + 26)? + u16_at(data, header + 28)?;
+            let raw = data
+                .get(start..start + compressed_size)
+                .ok_or_else(|| anyhow!("unexpected end of zip data"))?;
+            return match method {
+                0 => Ok(raw.to_vec()),
+                8 => {
+                    let mut out = vec![];
+                    DeflateDecoder::new(raw).read_to_end(&mut out)?;
+                    Ok(out)
+                }
+                _ => Err(anyhow!("unsupported zip compression method {}", method)),
+            };
+        }
+
+        pos += 46 + name_len + extra_len + comment_len;
+    }
+
+    Err(anyhow!("{} not found in zip archive", name))
+}
+
+#[cfg(feature = "documents")]
+fn docx_to_text(data: &[u8]) -> Result<String> {
+    let xml = unzip(data, "word/document.xml")?;
+    let mut text = String::new();
+    let mut in_text = false;
+
+    for event in EventReader::new(xml.as_slice()) {
+        match event? {
+            XmlEvent::StartElement { name, .. } => match name.local_name.as_str() {
+                "t" => in_text = true,
+                "tab" => text.push('\t'),
+                "br" | "cr" => text.push('\n'),
+                _ => {}
+            },
+            XmlEvent::EndElement { name } => match name.local_name.as_str() {
+                "t" => in_text = false,
+                "p" => text.push('\n'),
+                _ => {}
+            },
+            XmlEvent::Characters(chars) | XmlEvent::Whitespace(chars) if in_text => {
+                text.push_str(&chars)
+            }
+            _ => {}
+        }
+    }
+
+    Ok(text.trim().to_string())
+}
+
+#[cfg(feature = "documents")]
+fn pdf_to_text(data: &[u8]) -> Result<String> {
+    let pdf = lopdf::Document::load_mem(data).map_err(|e| anyhow!("can't parse pdf: {}", e))?;
+    let mut pages = vec![];
+    for page_number in pdf.get_pages().keys() {
+        let text = pdf
+            .extract_text(&[*page_number])
+            .map_err(|e| anyhow!("can't extract text from pdf page {}: {}", page_number, e))?;
+        pages.push(text.trim().to_string());
+    }
+
+    Ok(pages.join("\n\n").trim().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(feature = "documents")]
+    use std::io::Write;
+
+    #[cfg(feature = "documents")]
+    use flate2::{write::DeflateEncoder, Compression};
+
+    use super::*;
+
+    #[test]
+    fn test_format() {
+        assert_eq!(Format::from_path(Path::new("a/b.PDF")), Format::Pdf);
+        assert_eq!(Format::from_path(Path::new("b.docx")), Format::Docx);
+        assert_eq!(Format::from_path(Path::new("b.htm")), Format::Html);
+        assert_eq!(Format::from_path(Path::new("b.md")), Format::Text);
+    }
+
+    #[cfg(feature = "documents")]
+    fn pdf(pages: &[&str]) -> Vec<u8> {
+        use lopdf::{
+            content::{Content, Operation},
+            dictionary, Object, Stream,
+        };
+
+        let mut doc = lopdf::Document::with_version("1.5");
+        let pages_id = doc.new_object_id();
+        let font_id = doc.add_object(dictionary! {
+            "Type" => "Font",
+            "Subtype" => "Type1",
+            "BaseFont" => "Courier",
+        });
+        let resources_id = doc.add_object(dictionary! {
+            "Font" => dictionary! { "F1" => font_id },
+        });
+
+        let mut kids = vec![];
+        for text in pages {
+            let content = Content {
+                operations: vec![
+                    Operation::new("BT", vec![]),
+                    Operation::new("Tf", vec!["F1".into(), 12.into()]),
+                    Operation::new("Td", vec![72.into(), 712.into()]),
+                    Operation::new("Tj", vec![Object::string_literal(*text)]),
+                    Operation::new("ET", vec![]),
+                ],
+            };
+            let content_id = doc.add_object(Stream::new(dictionary! {}, content.encode().unwrap()));
+            kids.push(
+                doc.add_object(dictionary! {
+                    "Type" => "Page",
+                    "Parent" => pages_id,
+                    "Contents" => content_id,
+                })
+                .into(),
+            );
+        }
+
+        doc.objects.insert(
+            pages_id,
+            Object::Dictionary(dictionary! {
+                "Type" => "Pages",
+                "Count" => kids.len() as u32,
+                "Kids" => kids,
+                "Resources" => resources_id,
+                "MediaBox" => vec![0.into(), 0.into(), 595.into(), 842.into()],
+            }),
+        );
+        let catalog_id = doc.add_object(dictionary! {
+            "Type" => "Catalog",
+            "Pages" => pages_id,
+        });
+        doc.trailer.set("Root", catalog_id);
+
+        let mut data = vec![];
+        doc.save_to(&mut data).unwrap();
+        data
+    }
+
+    #[cfg(feature = "documents")]
+    #[test]
+    fn test_pdf_to_text() {
+        assert_eq!(
+            pdf_to_text(&pdf(&["Hello PDF world", "Second page"])).unwrap(),
+            "Hello PDF world\n\nSecond page"
+        );
+        assert!(pdf_to_text(b"not a pdf").is_err());
+    }
+
+    #[cfg(feature = "documents")]
+    fn zip(name: &str, data: &[u8]) -> Vec<u8> {
+        let mut encoder = DeflateEncoder::new(vec![], Compression::default());
+        encoder.write_all(data).unwrap();
+        let compressed = encoder.finish().unwrap();
+
+        let mut zip = vec![];
+        zip.extend(b"PK\x03\x04");
+        zip.extend([20, 0, 0, 0, 8, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        zip.extend((compressed.len() as u32).to_le_bytes());
+        zip.extend((data.len() as u32).to_le_bytes());
+        zip.extend((name.len() as u16).to_le_bytes());
+        zip.extend([0, 0]);
+        zip.extend(name.as_bytes());
+        zip.extend(&compressed);
+
+        let directory = zip.len();
+        zip.extend(b"PK\x01\x02");
+        zip.extend([20, 0, 20, 0, 0, 0, 8, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        zip.extend((compressed.len() as u32).to_le_bytes());
+        zip.extend((data.len() as u32).to_le_bytes());
+        zip.extend((name.len() as u16).to_le_bytes());
+        zip.extend([0; 12]);
+        zip.extend(0u32.to_le_bytes());
+        zip.extend(name.as_bytes());
+        let directory_size = zip.len() - directory;
+
+        zip.extend(b"PK\x05\x06");
+        zip.extend([0, 0, 0, 0, 1, 0, 1, 0]);
+        zip.extend((directory_size as u32).to_le_bytes());
+        zip.extend((directory as u32).to_le_bytes());
+        zip.extend([0, 0]);
+        zip
+    }
+
+    #[cfg(feature = "documents")]
+    #[test]
+    fn test_docx_to_text() {
+        let document = r#"<?xml version="1.0" encoding="UTF-8"?>
+<w:document xmlns:w="http://schemas.openxmlformats.org/wordprocessingml/2006/main">
+<w:body>
+<w:p><w:r><w:t>Hello</w:t></w:r><w:r><w:t xml:space="preserve"> docx</w:t></w:r></w:p>
+<w:p><w:r><w:t>a</w:t><w:tab/><w:t>b &amp; c</w:t></w:r></w:p>
+</w:body>
+</w:document>"#;
+
+        let docx = zip("word/document.xml", document.as_bytes());
+        assert_eq!(docx_to_text(&docx).unwrap(), "Hello docx\na\tb & c");
+        assert!(docx_to_text(&zip("other.xml", b"<a/>")).is_err());
+    }
+}
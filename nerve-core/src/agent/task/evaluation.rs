@@ -0,0 +1,108 @@
+use anyhow::Result;
+use mini_rag::Embeddings;
+use serde::Deserialize;
+
+use crate::agent::dedup::cosine_similarity;
+
+// how the final answer of the task is evaluated before completing it
+#[derive(Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct Evaluation {
+    // generate the final answer this many times and complete with the one most of them agree on
+    pub consistency: Option<usize>,
+}
+
+impl Evaluation {
+    pub fn validate(&self) -> Result<()> {
+        if self.consistency == Some(0) {
+            return Err(anyhow!("evaluation consistency must be at least 1"));
+        }
+        Ok(())
+    }
+
+    // number of answers to vote on, if more than one
+    pub fn samples(&self) -> Option<usize> {
+        self.consistency.filter(|k| *k > 1)
+    }
+}
+
+fn normalized(answer: &str) -> String {
+    answer
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .trim_end_matches('.')
+        .to_lowercase()
+}
+
+// index of the answer given by more than half of the samples, ignoring case and whitespace
+pub fn majority(answers: &[String]) -> Option<usize> {
+    let answers: Vec<String> = answers.iter().map(|a| normalized(a)).collect();
+    answers.iter().position(|answer| {
+        answers.iter().filter(|other| *other == answer).count() * 2 > answers.len()
+    })
+}
+
+// index of the answer closest in meaning to all the others, the first one if they're equally close
+pub fn medoid(embeddings: &[Embeddings]) -> usize {
+    let mut best = (0, f64::MIN);
+    for (idx, embedding) in embeddings.iter().enumerate() {
+        let agreement: f64 = embeddings
+            .iter()
+            .enumerate()
+            .filter(|(other, _)| *other != idx)
+            .map(|(_, other)| cosine_similarity(embedding, other))
+            .sum();
+        if agreement > best.1 {
+            best = (idx, agreement);
+        }
+    }
+    best.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let evaluation: Evaluation = serde_yaml::from_str("consistency: 5").unwrap();
+        assert_eq!(evaluation.samples(), Some(5));
+        assert!(evaluation.validate().is_ok());
+
+        let evaluation: Evaluation = serde_yaml::from_str("consistency: 1").unwrap();
+        assert_eq!(evaluation.samples(), None);
+
+        let evaluation: Evaluation = serde_yaml::from_str("consistency: 0").unwrap();
+        assert!(evaluation.validate().is_err());
+    }
+
+    #[test]
+    fn test_majority() {
+        let answers = |answers: &[&str]| answers.iter().map(|a| a.to_string()).collect::<Vec<_>>();
+        assert_eq!(
+            majority(&answers(&[
+                "the port is 22",
+                "The port is  22.",
+                "port 8080"
+            ])),
+            Some(0)
+        );
+        assert_eq!(majority(&answers(&["22", "8080", "443", "8080"])), None);
+        assert_eq!(majority(&answers(&["22", "8080", "8080"])), Some(1));
+    }
+
+    #[test]
+    fn test_medoid() {
+        assert_eq!(
+            medoid(&[
+                vec![0.0, 1.0],
+                vec![1.0, 0.1],
+                vec![1.0, 0.0],
+                vec![0.9, 0.1]
+            ]),
+            3
+        );
+        assert_eq!(medoid(&[vec![1.0, 0.0], vec![1.0, 0.0]]), 0);
+        assert_eq!(medoid(&[vec![1.0, 0.0]]), 0);
+    }
+}
@@ -0,0 +1,235 @@
+use anyhow::Result;
+use regex::Regex;
+use serde::Deserialize;
+
+// post processing applied to the output of an action before it enters the history
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct OutputFilter {
+    // only keep the matches (or the first capture group if present) of this expression
+    pub regex: Option<String>,
+    // jq-like path expression to extract from a json output, for instance .items[].name
+    pub jq: Option<String>,
+    // only keep the first N lines
+    pub max_lines: Option<usize>,
+    // only keep the last N lines
+    pub tail: Option<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Selector {
+    Key(String),
+    Index(i64),
+    Iterate,
+}
+
+fn parse_jq(expr: &str) -> Result<Vec<Selector>> {
+    let mut selectors = vec![];
+
+    // pipes are just a sequence of selectors in this subset
+    for part in expr.split('|') {
+        let part = part.trim();
+        if !part.starts_with('.') {
+            return Err(anyhow!("invalid jq expression '{}'", expr));
+        }
+
+        let chars: Vec<char> = part.chars().collect();
+        let mut i = 0;
+        while i < chars.len() {
+            match chars[i] {
+                '.' => {
+                    i += 1;
+                    let start = i;
+                    while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                        i += 1;
+                    }
+                    if i > start {
+                        selectors.push(Selector::Key(chars[start..i].iter().collect()));
+                    }
+                }
+                '[' => {
+                    let end = chars[i..]
+                        .iter()
+                        .position(|c| *c == ']')
+                        .map(|p| p + i)
+                        .ok_or_else(|| anyhow!("unterminated [ in jq expression '{}'", expr))?;
+                    let inner: String = chars[i + 1..end].iter().collect();
+                    let inner = inner.trim();
+                    if inner.is_empty() {
+                        selectors.push(Selector::Iterate);
+                    } else if let Some(key) =
+                        inner.strip_prefix('"').and_then(|s| s.strip_suffix('"'))
+                    {
+                        selectors.push(Selector::Key(key.to_string()));
+                    } else {
+                        selectors.push(Selector::Index(inner.parse().map_err(|_| {
+                            anyhow!("invalid index '{}' in jq expression '{}'", inner, expr)
+                        })?));
+                    }
+                    i = end + 1;
+                }
+                c => return Err(anyhow!("unexpected '{}' in jq expression '{}'", c, expr)),
+            }
+        }
+    }
+
+    Ok(selectors)
+}
+
+fn select(values: Vec<serde_json::Value>, selector: &Selector) -> Vec<serde_json::Value> {
+    let mut selected = vec![];
+    for value in values {
+        match (selector, value) {
+            (Selector::Key(key), serde_json::Value::Object(mut map)) => {
+                selected.push(map.remove(key).unwrap_or(serde_json::Value::Null))
+            }
+            (Selector::Index(idx), serde_json::Value::Array(mut items)) => {
+                let idx = if *idx < 0 {
+                    items.len() as i64 + idx
+                } else {
+                    *idx
+                };
+                if idx >= 0 && (idx as usize) < items.len() {
+                    selected.push(items.swap_remove(idx as usize));
+                } else {
+                    selected.push(serde_json::Value::Null);
+                }
+            }
+            (Selector::Iterate, serde_json::Value::Array(items)) => selected.extend(items),
+            (Selector::Iterate, serde_json::Value::Object(map)) => {
+                selected.extend(map.into_iter().map(|(_, v)| v))
+            }
+            _ => selected.push(serde_json::Value::Null),
+        }
+    }
+    selected
+}
+
+impl OutputFilter {
+    pub fn validate(&self) -> Result<()> {
+        if let Some(regex) = &self.regex {
+            Regex::new(regex).map_err(|e| anyhow!("invalid filter regex '{}': {}", regex, e))?;
+        }
+        if let Some(jq) = &self.jq {
+            parse_jq(jq)?;
+        }
+        Ok(())
+    }
+
+    pub fn apply(&self, output: &str) -> Result<String> {
+        let mut output = output.to_string();
+
+        if let Some(jq) = &self.jq {
+            let selectors = parse_jq(jq)?;
+            let json: serde_json::Value = serde_json::from_str(&output)
+                .map_err(|e| anyhow!("output is not valid json: {}", e))?;
+
+            let mut values = vec![json];
+            for selector in &selectors {
+                values = select(values, selector);
+            }
+
+            output = values
+                .iter()
+                .map(|v| match v {
+                    serde_json::Value::String(s) => s.to_string(),
+                    _ => v.to_string(),
+                })
+                .collect::<Vec<String>>()
+                .join("\n");
+        }
+
+        if let Some(regex) = &self.regex {
+            let regex = Regex::new(regex)?;
+            output = regex
+                .captures_iter(&output)
+                .map(|caps| {
+                    caps.get(1)
+                        .unwrap_or_else(|| caps.get(0).unwrap())
+                        .as_str()
+                        .to_string()
+                })
+                .collect::<Vec<String>>()
+                .join("\n");
+        }
+
+        if let Some(tail) = self.tail {
+            let lines: Vec<&str> = output.lines().collect();
+            output = lines[lines.len().saturating_sub(tail)..].join("\n");
+        }
+
+        if let Some(max_lines) = self.max_lines {
+            let total = output.lines().count();
+            if total > max_lines {
+                output = format!(
+                    "{}\n<{} more lines>",
+                    output
+                        .lines()
+                        .take(max_lines)
+                        .collect::<Vec<&str>>()
+                        .join("\n"),
+                    total - max_lines
+                );
+            }
+        }
+
+        Ok(output)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_regex_filter() {
+        let filter = OutputFilter {
+            regex: Some(r"(\d+)/tcp\s+open".to_string()),
+            ..Default::default()
+        };
+        let out = filter
+            .apply("22/tcp open ssh\n23/tcp closed telnet\n80/tcp  open http")
+            .unwrap();
+        assert_eq!(out, "22\n80");
+    }
+
+    #[test]
+    fn test_jq_filter() {
+        let filter = OutputFilter {
+            jq: Some(".items[].name".to_string()),
+            ..Default::default()
+        };
+        let out = filter
+            .apply(r#"{"items":[{"name":"foo"},{"name":"bar","id":1}]}"#)
+            .unwrap();
+        assert_eq!(out, "foo\nbar");
+
+        let filter = OutputFilter {
+            jq: Some(".items[-1] | .id".to_string()),
+            ..Default::default()
+        };
+        let out = filter
+            .apply(r#"{"items":[{"name":"foo"},{"name":"bar","id":1}]}"#)
+            .unwrap();
+        assert_eq!(out, "1");
+    }
+
+    #[test]
+    fn test_invalid_jq_is_error() {
+        let filter = OutputFilter {
+            jq: Some("items".to_string()),
+            ..Default::default()
+        };
+        assert!(filter.validate().is_err());
+    }
+
+    #[test]
+    fn test_lines_filters() {
+        let filter = OutputFilter {
+            tail: Some(3),
+            max_lines: Some(2),
+            ..Default::default()
+        };
+        let out = filter.apply("1\n2\n3\n4\n5").unwrap();
+        assert_eq!(out, "3\n4\n<1 more lines>");
+    }
+}
@@ -0,0 +1,124 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Result;
+use serde::Deserialize;
+
+// name of the readonly storage with the package received from the previous agent
+pub const HANDOFF_STORAGE: &str = "handoff";
+
+// an agent the task can be handed off to
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct Handoff {
+    pub name: String,
+    // tasklet of the agent, relative to the one declaring it
+    pub tasklet: String,
+    // when the model should hand off to it
+    pub description: Option<String>,
+    // storages transferred to the agent, every one it uses too if not set
+    pub storages: Option<Vec<String>>,
+    #[serde(skip)]
+    pub path: PathBuf,
+}
+
+impl Handoff {
+    // resolve the path of the tasklet and make sure it exists
+    pub fn validate(&mut self, folder: &str) -> Result<()> {
+        if self.name.is_empty() {
+            return Err(anyhow!("handoff name can't be empty"));
+        }
+        let path = if Path::new(&self.tasklet).is_absolute() {
+            PathBuf::from(&self.tasklet)
+        } else {
+            PathBuf::from(folder).join(&self.tasklet)
+        };
+        if !path.exists() {
+            return Err(anyhow!(
+                "tasklet '{}' of handoff '{}' not found",
+                path.display(),
+                self.name
+            ));
+        }
+        self.path = path;
+        Ok(())
+    }
+}
+
+// what an agent passes to the next one along with the control of the task
+#[derive(Debug, Clone, PartialEq)]
+pub struct Package {
+    pub from: String,
+    pub goal: String,
+    pub message: String,
+    pub constraints: Vec<String>,
+}
+
+impl Package {
+    // the entries of the handoff storage
+    pub fn to_storage_data(&self) -> HashMap<String, String> {
+        let mut data = HashMap::new();
+        data.insert("from".to_string(), self.from.to_string());
+        data.insert("goal".to_string(), self.goal.to_string());
+        data.insert("message".to_string(), self.message.to_string());
+        if !self.constraints.is_empty() {
+            data.insert(
+                "constraints".to_string(),
+                self.constraints
+                    .iter()
+                    .map(|c| format!("- {}", c))
+                    .collect::<Vec<_>>()
+                    .join("\n"),
+            );
+        }
+        data
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate() {
+        let folder =
+            std::env::temp_dir().join(format!("nerve-test-handoff-{}", std::process::id()));
+        std::fs::create_dir_all(folder.join("exploit")).unwrap();
+        std::fs::write(folder.join("exploit").join("task.yml"), "").unwrap();
+
+        let mut handoff: Handoff =
+            serde_yaml::from_str("name: exploiter\ntasklet: exploit\nstorages: [findings]")
+                .unwrap();
+        handoff.validate(folder.to_str().unwrap()).unwrap();
+        assert_eq!(handoff.path, folder.join("exploit"));
+        assert_eq!(handoff.storages, Some(vec!["findings".to_string()]));
+
+        handoff.tasklet = "missing".to_string();
+        assert!(handoff.validate(folder.to_str().unwrap()).is_err());
+
+        std::fs::remove_dir_all(&folder).unwrap();
+    }
+
+    #[test]
+    fn test_package() {
+        let package = Package {
+            from: "recon".to_string(),
+            goal: "find a way in".to_string(),
+            message: "port 8080 runs an outdated jenkins".to_string(),
+            constraints: vec!["never run destructive commands".to_string()],
+        };
+        let data = package.to_storage_data();
+        assert_eq!(data.get("from").unwrap(), "recon");
+        assert_eq!(
+            data.get("constraints").unwrap(),
+            "- never run destructive commands"
+        );
+
+        let package = Package {
+            constraints: vec![],
+            ..package
+        };
+        assert!(!package.to_storage_data().contains_key("constraints"));
+    }
+}
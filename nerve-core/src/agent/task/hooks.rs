@@ -0,0 +1,424 @@
+use std::process::Stdio;
+use std::time::Duration;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use duration_string::DurationString;
+use serde::{Deserialize, Serialize};
+use tokio::{io::AsyncWriteExt, process::Command};
+
+use crate::agent::{
+    middleware::{ActionContext, Middleware, Verdict},
+    namespaces::shell::Interpreter,
+    state::metrics::Metrics,
+    Invocation,
+};
+
+// a hook that doesn't answer in time is killed so that it can't hold the run
+const DEFAULT_HOOK_TIMEOUT: Duration = Duration::from_secs(60);
+
+fn default_webhook_method() -> String {
+    "POST".to_string()
+}
+
+// a hook can either be a shell command or an http webhook
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum Hook {
+    Command(String),
+    Webhook {
+        url: String,
+        #[serde(default = "default_webhook_method")]
+        method: String,
+    },
+}
+
+// lifecycle hooks executed around the agent run
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Hooks {
+    #[serde(skip_deserializing)]
+    pub task_name: String,
+    #[serde(skip_deserializing)]
+    pub working_directory: String,
+    pub on_start: Option<Vec<Hook>>,
+    pub on_step: Option<Vec<Hook>>,
+    pub on_complete: Option<Vec<Hook>>,
+    // can change or veto every invocation before it's executed
+    pub before_action: Option<Vec<Hook>>,
+    // can change the result of every executed invocation
+    pub after_action: Option<Vec<Hook>>,
+    // how long every hook can take, one minute if not set
+    pub hook_timeout: Option<String>,
+}
+
+// data passed to every hook, as environment variables for commands and as json body for webhooks
+#[derive(Debug, Clone, Serialize)]
+pub struct HookContext {
+    pub hook: String,
+    pub task: String,
+    pub step: usize,
+    pub metrics: Metrics,
+    // for the action hooks, the invocation and its result once executed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub invocation: Option<Invocation>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub output: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+// what action hooks can print or respond with, nothing means leave everything as it is
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct HookResponse {
+    // don't execute the invocation for this reason
+    pub veto: Option<String>,
+    // execute this invocation instead
+    pub invocation: Option<Invocation>,
+    // replace the output or error of the action
+    pub output: Option<String>,
+    pub error: Option<String>,
+    // appended to the result of the action
+    pub note: Option<String>,
+}
+
+impl HookResponse {
+    fn parse(hook: &str, data: &str) -> Result<Self> {
+        if data.trim().is_empty() {
+            return Ok(Self::default());
+        }
+        serde_json::from_str(data).map_err(|e| anyhow!("invalid {} hook response: {}", hook, e))
+    }
+}
+
+impl Hook {
+    // what the command printed or the webhook responded
+    async fn run(
+        &self,
+        working_directory: &str,
+        timeout: Duration,
+        ctx: &HookContext,
+    ) -> Result<String> {
+        match self {
+            Hook::Command(command) => {
+                log::debug!("running {} hook: {}", &ctx.hook, command);
+
+                // the interpreter scrubs the environment like for the tools and shell commands
+                let mut cmd = Command::from(Interpreter::system().command(command));
+                cmd.env("NERVE_HOOK", &ctx.hook)
+                    .env("NERVE_TASK", &ctx.task)
+                    .env("NERVE_STEP", ctx.step.to_string())
+                    .env("NERVE_METRICS", serde_json::to_string(&ctx.metrics)?)
+                    .stdin(Stdio::piped())
+                    .stdout(Stdio::piped())
+                    .stderr(Stdio::piped())
+                    .kill_on_drop(true);
+                if let Some(invocation) = &ctx.invocation {
+                    cmd.env("NERVE_ACTION", &invocation.action);
+                }
+
+                if !working_directory.is_empty() {
+                    cmd.current_dir(working_directory);
+                }
+
+                // the whole context is also written as json to the standard input
+                let mut child = cmd.spawn()?;
+                let input = serde_json::to_vec(ctx)?;
+                // the child is killed when dropped on timeout
+                let output = tokio::time::timeout(timeout, async move {
+                    if let Some(mut stdin) = child.stdin.take() {
+                        // the command is free not to read it
+                        let _ = stdin.write_all(&input).await;
+                    }
+                    child.wait_with_output().await
+                })
+                .await
+                .map_err(|_| {
+                    anyhow!(
+                        "{} hook '{}' timed out after {:?}",
+                        &ctx.hook,
+                        command,
+                        timeout
+                    )
+                })??;
+                if !output.status.success() {
+                    return Err(anyhow!(
+                        "{} hook '{}' failed with {}: {}",
+                        &ctx.hook,
+                        command,
+                        output.status,
+                        String::from_utf8_lossy(&output.stderr).trim()
+                    ));
+                }
+
+                Ok(String::from_utf8_lossy(&output.stdout).to_string())
+            }
+            Hook::Webhook { url, method } => {
+                log::debug!("calling {} webhook: {} {}", &ctx.hook, method, url);
+
+                let method = reqwest::Method::from_bytes(method.to_uppercase().as_bytes())?;
+                let response = reqwest::Client::new()
+                    .request(method, url)
+                    .timeout(timeout)
+                    .json(ctx)
+                    .send()
+                    .await
+                    .map_err(|e| {
+                        if e.is_timeout() {
+                            anyhow!(
+                                "{} webhook {} timed out after {:?}",
+                                &ctx.hook,
+                                url,
+                                timeout
+                            )
+                        } else {
+                            anyhow!(e)
+                        }
+                    })?;
+
+                if !response.status().is_success() {
+                    return Err(anyhow!(
+                        "{} webhook {} returned {}",
+                        &ctx.hook,
+                        url,
+                        response.status()
+                    ));
+                }
+
+                Ok(response.text().await?)
+            }
+        }
+    }
+}
+
+impl Hooks {
+    pub fn validate(&self) -> Result<()> {
+        if let Some(timeout) = &self.hook_timeout {
+            timeout
+                .parse::<DurationString>()
+                .map_err(|e| anyhow!("invalid hook_timeout '{}': {}", timeout, e))?;
+        }
+        Ok(())
+    }
+
+    fn timeout(&self) -> Duration {
+        self.hook_timeout
+            .as_ref()
+            .and_then(|timeout| timeout.parse::<DurationString>().ok())
+            .map(|timeout| *timeout)
+            .unwrap_or(DEFAULT_HOOK_TIMEOUT)
+    }
+
+    async fn run(&self, hooks: &Option<Vec<Hook>>, ctx: HookContext) -> Result<()> {
+        if let Some(hooks) = hooks {
+            for hook in hooks {
+                hook.run(&self.working_directory, self.timeout(), &ctx)
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+
+    pub async fn on_start(&self, metrics: &Metrics) -> Result<()> {
+        self.run(&self.on_start, self.context("on_start", metrics))
+            .await
+    }
+
+    pub async fn on_step(&self, metrics: &Metrics) -> Result<()> {
+        self.run(&self.on_step, self.context("on_step", metrics))
+            .await
+    }
+
+    pub async fn on_complete(&self, metrics: &Metrics) -> Result<()> {
+        self.run(&self.on_complete, self.context("on_complete", metrics))
+            .await
+    }
+
+    fn context(&self, hook: &str, metrics: &Metrics) -> HookContext {
+        HookContext {
+            hook: hook.to_string(),
+            task: self.task_name.to_string(),
+            step: metrics.current_step,
+            metrics: metrics.clone(),
+            invocation: None,
+            output: None,
+            error: None,
+        }
+    }
+
+    pub fn has_action_hooks(&self) -> bool {
+        self.before_action.is_some() || self.after_action.is_some()
+    }
+}
+
+#[async_trait]
+impl Middleware for Hooks {
+    async fn before_action(
+        &self,
+        ctx: &mut ActionContext,
+        invocation: &mut Invocation,
+    ) -> Result<Verdict> {
+        for hook in self.before_action.iter().flatten() {
+            let mut hook_ctx = self.context("before_action", &ctx.metrics);
+            hook_ctx.invocation = Some(invocation.clone());
+
+            let response = HookResponse::parse(
+                "before_action",
+                &hook
+                    .run(&self.working_directory, self.timeout(), &hook_ctx)
+                    .await?,
+            )?;
+            if let Some(note) = response.note {
+                ctx.notes.push(note);
+            }
+            if let Some(reason) = response.veto {
+                return Ok(Verdict::Veto(reason));
+            }
+            if let Some(changed) = response.invocation {
+                *invocation = changed;
+            }
+        }
+
+        Ok(Verdict::Proceed)
+    }
+
+    async fn after_action(
+        &self,
+        ctx: &mut ActionContext,
+        invocation: &Invocation,
+        result: &mut Result<Option<String>>,
+    ) -> Result<()> {
+        for hook in self.after_action.iter().flatten() {
+            let mut hook_ctx = self.context("after_action", &ctx.metrics);
+            hook_ctx.invocation = Some(invocation.clone());
+            match result {
+                Ok(output) => hook_ctx.output = output.clone(),
+                Err(err) => hook_ctx.error = Some(err.to_string()),
+            }
+
+            let response = HookResponse::parse(
+                "after_action",
+                &hook
+                    .run(&self.working_directory, self.timeout(), &hook_ctx)
+                    .await?,
+            )?;
+            if let Some(note) = response.note {
+                ctx.notes.push(note);
+            }
+            if let Some(error) = response.error {
+                *result = Err(anyhow!(error));
+            } else if let Some(output) = response.output {
+                *result = Ok(Some(output));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_hooks() {
+        let hooks: Hooks = serde_yaml::from_str(
+            r#"
+on_start:
+  - docker start target
+on_complete:
+  - url: http://localhost:8080/done
+  - url: http://localhost:8080/archive
+    method: PUT
+"#,
+        )
+        .unwrap();
+
+        assert!(
+            matches!(&hooks.on_start.unwrap()[0], Hook::Command(cmd) if cmd == "docker start target")
+        );
+        assert!(hooks.on_step.is_none());
+
+        let on_complete = hooks.on_complete.unwrap();
+        assert!(
+            matches!(&on_complete[0], Hook::Webhook { url, method } if url == "http://localhost:8080/done" && method == "POST")
+        );
+        assert!(matches!(&on_complete[1], Hook::Webhook { method, .. } if method == "PUT"));
+    }
+
+    #[tokio::test]
+    async fn test_failing_command_is_error() {
+        let hooks = Hooks {
+            on_start: Some(vec![Hook::Command("exit 1".to_string())]),
+            ..Default::default()
+        };
+
+        assert!(hooks.on_start(&Metrics::default()).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_timeout() {
+        let hooks: Hooks = serde_yaml::from_str(
+            r#"
+hook_timeout: 200ms
+on_start:
+  - sleep 10
+"#,
+        )
+        .unwrap();
+        assert!(hooks.validate().is_ok());
+
+        let started = std::time::Instant::now();
+        let err = hooks.on_start(&Metrics::default()).await.unwrap_err();
+        assert!(err.to_string().contains("timed out"), "{}", err);
+        assert!(started.elapsed() < Duration::from_secs(5));
+
+        let hooks = Hooks {
+            hook_timeout: Some("a minute".to_string()),
+            ..Default::default()
+        };
+        assert!(hooks.validate().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_action_hooks() {
+        let hooks: Hooks = serde_yaml::from_str(
+            r#"
+before_action:
+  - test "$NERVE_ACTION" = shell || exit 0; grep -q 'rm -rf' && echo '{"veto":"destructive"}' || true
+  - echo '{"invocation":{"action":"shell","payload":"id"},"note":"rewritten"}'
+after_action:
+  - grep -q '"output":"uid=0"' && echo '{"output":"root"}'
+"#,
+        )
+        .unwrap();
+        assert!(hooks.has_action_hooks());
+
+        let mut ctx = ActionContext::default();
+        let mut inv = Invocation::new("shell".to_string(), None, Some("rm -rf /".to_string()));
+        assert_eq!(
+            hooks.before_action(&mut ctx, &mut inv).await.unwrap(),
+            Verdict::Veto("destructive".to_string())
+        );
+
+        let mut inv = Invocation::new("shell".to_string(), None, Some("whoami".to_string()));
+        assert_eq!(
+            hooks.before_action(&mut ctx, &mut inv).await.unwrap(),
+            Verdict::Proceed
+        );
+        assert_eq!(inv.payload.as_deref(), Some("id"));
+        assert_eq!(ctx.notes, ["rewritten"]);
+
+        let mut result = Ok(Some("uid=0".to_string()));
+        hooks
+            .after_action(&mut ctx, &inv, &mut result)
+            .await
+            .unwrap();
+        assert_eq!(result.unwrap().as_deref(), Some("root"));
+
+        let hooks = Hooks {
+            before_action: Some(vec![Hook::Command("echo not json".to_string())]),
+            ..Default::default()
+        };
+        assert!(hooks.before_action(&mut ctx, &mut inv).await.is_err());
+    }
+}
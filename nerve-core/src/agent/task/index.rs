@@ -0,0 +1,462 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Result;
+use mini_rag::{Embedder, Embeddings};
+use serde::{Deserialize, Serialize};
+
+// index files start with this, followed by the version of the format as a little endian u32
+const MAGIC: &[u8; 8] = b"NERVERAG";
+const HEADER_SIZE: usize = MAGIC.len() + 4;
+pub const VERSION: u32 = 1;
+
+const INDEX_FILE: &str = "index.bin";
+// unversioned store written by mini-rag, migrated to the index
+const LEGACY_FILE: &str = "rag.bin";
+
+// embedded when the index is loaded to tell whether the embedder changed since it was created
+const PROBE: &str = "The quick brown fox jumps over the lazy dog.";
+// the same model might not return exactly the same embeddings every time
+const MIN_PROBE_SIMILARITY: f64 = 0.99;
+
+// a chunk of a document and its embeddings
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct IndexedChunk {
+    // the source document and the offset of the chunk in it, in characters, if known
+    pub path: String,
+    pub offset: Option<usize>,
+    // file the text of the chunk is saved to
+    pub file: String,
+    pub embeddings: Embeddings,
+}
+
+// version 1 of the format
+#[derive(Serialize, Deserialize, Debug, Default)]
+struct Index {
+    // embeddings of PROBE by the embedder that created the index, empty if unknown
+    probe: Embeddings,
+    // chunks by sha256 of their text
+    chunks: HashMap<String, IndexedChunk>,
+}
+
+// what mini-rag used to save, the text of the documents is not included
+#[derive(Serialize, Deserialize)]
+struct LegacyDocument {
+    path: String,
+    ident: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct LegacyStore {
+    documents: HashMap<String, LegacyDocument>,
+    embeddings: HashMap<String, Embeddings>,
+}
+
+// where a chunk saved as a file by mini-rag comes from
+#[derive(Deserialize)]
+struct LegacyLocation {
+    path: String,
+    offset: usize,
+}
+
+fn encode(index: &Index) -> Result<Vec<u8>> {
+    let mut raw = MAGIC.to_vec();
+    raw.extend(VERSION.to_le_bytes());
+    raw.extend(bitcode::serialize(index)?);
+    Ok(raw)
+}
+
+fn decode(raw: &[u8]) -> Result<Index> {
+    if raw.len() < HEADER_SIZE || &raw[..MAGIC.len()] != MAGIC {
+        return Err(anyhow!("not a nerve rag index"));
+    }
+    let version = u32::from_le_bytes(raw[MAGIC.len()..HEADER_SIZE].try_into()?);
+    match version {
+        1 => bitcode::deserialize(&raw[HEADER_SIZE..])
+            .map_err(|e| anyhow!("corrupted rag index: {}", e)),
+        _ if version > VERSION => Err(anyhow!(
+            "rag index version {} was created by a newer version of nerve (supported up to {})",
+            version,
+            VERSION
+        )),
+        _ => Err(anyhow!("unknown rag index version {}", version)),
+    }
+}
+
+// only the chunks saved as files with their location can be migrated, documents imported as a
+// whole from the legacy source_path are indexed again
+fn migrate_legacy(raw: &[u8]) -> Result<Index> {
+    let legacy: LegacyStore =
+        bitcode::deserialize(raw).map_err(|e| anyhow!("corrupted legacy rag store: {}", e))?;
+
+    let mut index = Index::default();
+    for (id, document) in legacy.documents {
+        let location = std::fs::read_to_string(Path::new(&document.path).with_extension("json"))
+            .ok()
+            .and_then(|raw| serde_json::from_str::<LegacyLocation>(&raw).ok());
+        let (location, embeddings) = match (location, legacy.embeddings.get(&id)) {
+            (Some(location), Some(embeddings)) => (location, embeddings),
+            _ => continue,
+        };
+        index.chunks.insert(
+            document.ident,
+            IndexedChunk {
+                path: location.path,
+                offset: Some(location.offset),
+                file: document.path,
+                embeddings: embeddings.clone(),
+            },
+        );
+    }
+
+    Ok(index)
+}
+
+fn similarity(a: &[f64], b: &[f64]) -> f64 {
+    let dot: f64 = a.iter().zip(b).map(|(a, b)| a * b).sum();
+    let norm =
+        a.iter().map(|a| a * a).sum::<f64>().sqrt() * b.iter().map(|b| b * b).sum::<f64>().sqrt();
+    if norm == 0.0 {
+        0.0
+    } else {
+        dot / norm
+    }
+}
+
+// embeddings of the chunks of the local rag store, persisted in a versioned format
+pub struct VectorIndex {
+    path: PathBuf,
+    embedder: Box<dyn Embedder>,
+    index: Index,
+}
+
+impl VectorIndex {
+    pub async fn open(data_path: &str, embedder: Box<dyn Embedder>) -> Result<Self> {
+        let folder = PathBuf::from(data_path);
+        std::fs::create_dir_all(&folder)?;
+
+        let path = folder.join(INDEX_FILE);
+        let legacy = folder.join(LEGACY_FILE);
+        let (index, mut changed) = if path.exists() {
+            let index =
+                decode(&std::fs::read(&path)?).map_err(|e| anyhow!("{}: {}", path.display(), e))?;
+            (index, false)
+        } else if legacy.exists() {
+            let index = migrate_legacy(&std::fs::read(&legacy)?)
+                .map_err(|e| anyhow!("{}: {}", legacy.display(), e))?;
+            log::info!(
+                "migrated {} chunks from {} to {}",
+                index.chunks.len(),
+                legacy.display(),
+                path.display()
+            );
+            (index, true)
+        } else {
+            (Index::default(), true)
+        };
+
+        let mut store = Self {
+            path,
+            embedder,
+            index,
+        };
+
+        let probe = store.embedder.embed(PROBE).await?;
+        if store.index.probe.is_empty() {
+            store.index.probe = probe;
+            changed = true;
+        } else if store.index.probe.len() != probe.len()
+            || similarity(&store.index.probe, &probe) < MIN_PROBE_SIMILARITY
+        {
+            log::warn!(
+                "the embedder changed since {} was created, indexing {} chunks again ...",
+                store.path.display(),
+                store.index.chunks.len()
+            );
+            store.index.probe = probe;
+            store.reindex().await;
+            changed = true;
+        }
+
+        if changed {
+            store.save()?;
+        }
+        if legacy.exists() {
+            // keep it around in case of a downgrade
+            std::fs::rename(&legacy, legacy.with_extension("bin.old"))?;
+        }
+
+        Ok(store)
+    }
+
+    // compute again the embeddings of every chunk, the ones whose file is gone are dropped
+    async fn reindex(&mut self) {
+        let mut chunks = HashMap::new();
+        for (id, mut chunk) in std::mem::take(&mut self.index.chunks) {
+            let data = match std::fs::read_to_string(&chunk.file) {
+                Ok(data) => data,
+                Err(err) => {
+                    log::warn!("dropping chunk {}: {}", &chunk.file, err);
+                    continue;
+                }
+            };
+            match self.embedder.embed(&data).await {
+                Ok(embeddings) => {
+                    chunk.embeddings = embeddings;
+                    chunks.insert(id, chunk);
+                }
+                Err(err) => log::error!("indexing {}: {}", &chunk.file, err),
+            }
+        }
+        self.index.chunks = chunks;
+    }
+
+    fn save(&self) -> Result<()> {
+        // write to a temporary file first so that an interrupted run won't corrupt the index
+        let tmp = self.path.with_extension("bin.tmp");
+        std::fs::write(&tmp, encode(&self.index)?)?;
+        std::fs::rename(&tmp, &self.path)?;
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.index.chunks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.index.chunks.is_empty()
+    }
+
+    pub fn contains(&self, id: &str) -> bool {
+        self.index.chunks.contains_key(id)
+    }
+
+    // index the text of a chunk saved to file, its embeddings are computed here
+    pub async fn add(
+        &mut self,
+        id: &str,
+        path: &str,
+        offset: Option<usize>,
+        file: &str,
+        data: &str,
+    ) -> Result<()> {
+        let embeddings = self.embedder.embed(data).await?;
+        self.index.chunks.insert(
+            id.to_string(),
+            IndexedChunk {
+                path: path.to_string(),
+                offset,
+                file: file.to_string(),
+                embeddings,
+            },
+        );
+        self.save()
+    }
+
+    // remove the chunks of the document at path whose id is not in keep, and return them
+    pub fn prune(&mut self, path: &str, keep: &[String]) -> Result<Vec<IndexedChunk>> {
+        let ids: Vec<String> = self
+            .index
+            .chunks
+            .iter()
+            .filter(|(id, chunk)| chunk.path == path && !keep.contains(id))
+            .map(|(id, _)| id.to_string())
+            .collect();
+        let removed: Vec<IndexedChunk> = ids
+            .iter()
+            .filter_map(|id| self.index.chunks.remove(id))
+            .collect();
+        if !removed.is_empty() {
+            self.save()?;
+        }
+        Ok(removed)
+    }
+
+    // the chunks most similar to the query, with their cosine similarity
+    pub async fn search(&self, query: &str, top_k: usize) -> Result<Vec<(&IndexedChunk, f64)>> {
+        let query = self.embedder.embed(query).await?;
+        let mut results: Vec<(&IndexedChunk, f64)> = self
+            .index
+            .chunks
+            .values()
+            .filter(|chunk| chunk.embeddings.len() == query.len())
+            .map(|chunk| (chunk, similarity(&query, &chunk.embeddings)))
+            .collect();
+        results.sort_by(|(_, a), (_, b)| b.total_cmp(a));
+        results.truncate(top_k);
+        Ok(results)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use async_trait::async_trait;
+
+    use super::*;
+
+    // embeds the length of the text in the given number of dimensions
+    struct TestEmbedder(usize);
+
+    #[async_trait]
+    impl Embedder for TestEmbedder {
+        async fn embed(&self, text: &str) -> Result<Embeddings> {
+            let mut embeddings = vec![1.0; self.0];
+            embeddings[0] = text.len() as f64;
+            Ok(embeddings)
+        }
+    }
+
+    fn temp_folder(name: &str) -> PathBuf {
+        let folder = std::env::temp_dir().join(name);
+        let _ = std::fs::remove_dir_all(&folder);
+        std::fs::create_dir_all(&folder).unwrap();
+        folder
+    }
+
+    #[test]
+    fn test_format_versions() {
+        let mut index = Index {
+            probe: vec![1.0, 2.0],
+            ..Default::default()
+        };
+        index.chunks.insert(
+            "id".to_string(),
+            IndexedChunk {
+                path: "doc.md".to_string(),
+                offset: Some(10),
+                file: "chunks/id.txt".to_string(),
+                embeddings: vec![0.5, 0.25],
+            },
+        );
+
+        let mut raw = encode(&index).unwrap();
+        let decoded = decode(&raw).unwrap();
+        assert_eq!(decoded.probe, index.probe);
+        assert_eq!(decoded.chunks, index.chunks);
+
+        raw[MAGIC.len()..HEADER_SIZE].copy_from_slice(&(VERSION + 1).to_le_bytes());
+        assert!(decode(&raw)
+            .unwrap_err()
+            .to_string()
+            .contains("newer version of nerve"));
+        assert!(decode(b"garbage").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_migrate_legacy() {
+        let folder = temp_folder("nerve-index-legacy-test");
+        let chunk_file = folder.join("chunk.txt");
+        std::fs::write(&chunk_file, "chunk text").unwrap();
+        std::fs::write(
+            chunk_file.with_extension("json"),
+            r#"{"path":"/docs/guide.md","offset":42}"#,
+        )
+        .unwrap();
+
+        let legacy = LegacyStore {
+            documents: HashMap::from([
+                (
+                    "a".to_string(),
+                    LegacyDocument {
+                        path: chunk_file.display().to_string(),
+                        ident: "a".to_string(),
+                    },
+                ),
+                // imported from the legacy source_path, without a location
+                (
+                    "b".to_string(),
+                    LegacyDocument {
+                        path: "/docs/whole.md".to_string(),
+                        ident: "b".to_string(),
+                    },
+                ),
+            ]),
+            embeddings: HashMap::from([
+                ("a".to_string(), vec![10.0, 1.0]),
+                ("b".to_string(), vec![1.0, 1.0]),
+            ]),
+        };
+        std::fs::write(
+            folder.join(LEGACY_FILE),
+            bitcode::serialize(&legacy).unwrap(),
+        )
+        .unwrap();
+
+        let data_path = folder.display().to_string();
+        let index = VectorIndex::open(&data_path, Box::new(TestEmbedder(2)))
+            .await
+            .unwrap();
+        assert_eq!(index.len(), 1);
+        let chunk = &index.index.chunks["a"];
+        assert_eq!(chunk.path, "/docs/guide.md");
+        assert_eq!(chunk.offset, Some(42));
+        assert!(folder.join(INDEX_FILE).exists());
+        assert!(!folder.join(LEGACY_FILE).exists());
+
+        // loaded from the new format
+        let index = VectorIndex::open(&data_path, Box::new(TestEmbedder(2)))
+            .await
+            .unwrap();
+        assert_eq!(index.len(), 1);
+
+        let _ = std::fs::remove_dir_all(&folder);
+    }
+
+    #[tokio::test]
+    async fn test_prune() {
+        let folder = temp_folder("nerve-index-prune-test");
+        let data_path = folder.display().to_string();
+        let mut index = VectorIndex::open(&data_path, Box::new(TestEmbedder(2)))
+            .await
+            .unwrap();
+        for (id, path) in [("a1", "a.md"), ("a2", "a.md"), ("b1", "b.md")] {
+            index.add(id, path, None, id, id).await.unwrap();
+        }
+
+        let removed = index.prune("a.md", &["a1".to_string()]).unwrap();
+        assert_eq!(removed.len(), 1);
+        assert_eq!(removed[0].file, "a2");
+        assert!(index.contains("a1") && !index.contains("a2") && index.contains("b1"));
+
+        // persisted
+        let index = VectorIndex::open(&data_path, Box::new(TestEmbedder(2)))
+            .await
+            .unwrap();
+        assert_eq!(index.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_embedder_change() {
+        let folder = temp_folder("nerve-index-embedder-test");
+        let data_path = folder.display().to_string();
+        let chunk_file = folder.join("chunk.txt").display().to_string();
+        std::fs::write(&chunk_file, "hello").unwrap();
+
+        let mut index = VectorIndex::open(&data_path, Box::new(TestEmbedder(2)))
+            .await
+            .unwrap();
+        index
+            .add("a", "doc.md", Some(0), &chunk_file, "hello")
+            .await
+            .unwrap();
+        index
+            .add("b", "doc.md", Some(5), "/gone.txt", "world")
+            .await
+            .unwrap();
+        assert_eq!(index.search("hi", 5).await.unwrap().len(), 2);
+
+        // a different model is detected and the chunks are embedded again
+        let index = VectorIndex::open(&data_path, Box::new(TestEmbedder(3)))
+            .await
+            .unwrap();
+        assert_eq!(index.len(), 1);
+        assert_eq!(index.index.chunks["a"].embeddings, vec![5.0, 1.0, 1.0]);
+        let results = index.search("hi", 5).await.unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].0.offset, Some(0));
+
+        let _ = std::fs::remove_dir_all(&folder);
+    }
+}
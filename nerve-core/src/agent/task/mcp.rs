@@ -0,0 +1,669 @@
+use std::collections::HashMap;
+#[cfg(feature = "mcp")]
+use std::{collections::BTreeMap, path::PathBuf, process::Stdio, sync::Arc, time::Duration};
+
+use anyhow::Result;
+#[cfg(feature = "mcp")]
+use async_trait::async_trait;
+#[cfg(feature = "mcp")]
+use futures::StreamExt;
+#[cfg(feature = "mcp")]
+use reqwest_eventsource::{Event, EventSource};
+use serde::Deserialize;
+#[cfg(feature = "mcp")]
+use serde_json::{json, Value};
+#[cfg(feature = "mcp")]
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader, Lines},
+    process::{Child, ChildStdin, ChildStdout, Command},
+    sync::{mpsc, Mutex},
+};
+#[cfg(feature = "mcp")]
+use url::Url;
+
+#[cfg(feature = "mcp")]
+use crate::agent::{
+    namespaces::{Action, Namespace},
+    secrets,
+    state::SharedState,
+    task::variables::interpolate_variables,
+};
+
+#[cfg(feature = "mcp")]
+const PROTOCOL_VERSION: &str = "2024-11-05";
+// how long to wait for the server to connect and answer the handshake and tools listing
+#[cfg(feature = "mcp")]
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(30);
+
+// a model context protocol server whose tools are exposed as a namespace, either started as a
+// process talking over stdio or reached via http with server-sent events
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct McpServer {
+    // namespace name
+    pub name: String,
+    // command line of the server process for the stdio transport
+    pub command: Option<String>,
+    pub env: Option<HashMap<String, String>>,
+    pub cwd: Option<String>,
+    // sse endpoint for the http transport
+    pub url: Option<String>,
+    pub headers: Option<HashMap<String, String>>,
+    // only expose these tools
+    pub tools: Option<Vec<String>>,
+}
+
+#[cfg(feature = "mcp")]
+#[derive(Debug)]
+enum Transport {
+    Stdio {
+        // kept so that the process is terminated when nerve exits
+        _child: Child,
+        stdin: ChildStdin,
+        stdout: Lines<BufReader<ChildStdout>>,
+    },
+    Sse {
+        client: reqwest::Client,
+        headers: HashMap<String, String>,
+        endpoint: Url,
+        messages: mpsc::UnboundedReceiver<String>,
+    },
+}
+
+// a json-rpc client connected to a server
+#[cfg(feature = "mcp")]
+#[derive(Debug)]
+struct Connection {
+    name: String,
+    transport: Transport,
+    next_id: u64,
+}
+
+#[cfg(feature = "mcp")]
+impl Connection {
+    async fn send(&mut self, message: Value) -> Result<()> {
+        match &mut self.transport {
+            Transport::Stdio { stdin, .. } => {
+                let mut line = serde_json::to_string(&message)?;
+                line.push('\n');
+                stdin.write_all(line.as_bytes()).await?;
+                stdin.flush().await?;
+            }
+            Transport::Sse {
+                client,
+                headers,
+                endpoint,
+                ..
+            } => {
+                let mut request = client.post(endpoint.clone()).json(&message);
+                for (key, value) in headers.iter() {
+                    request = request.header(key, value);
+                }
+                let response = request.send().await?;
+                if !response.status().is_success() {
+                    return Err(anyhow!(
+                        "mcp server {} returned {}",
+                        self.name,
+                        response.status()
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    async fn receive(&mut self) -> Result<Value> {
+        loop {
+            let line = match &mut self.transport {
+                Transport::Stdio { stdout, .. } => stdout.next_line().await?,
+                Transport::Sse { messages, .. } => messages.recv().await,
+            }
+            .ok_or_else(|| anyhow!("mcp server {} disconnected", self.name))?;
+
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            match serde_json::from_str(line) {
+                Ok(message) => return Ok(message),
+                Err(err) => log::warn!("invalid message from mcp server {}: {}", self.name, err),
+            }
+        }
+    }
+
+    async fn notify(&mut self, method: &str) -> Result<()> {
+        self.send(json!({"jsonrpc": "2.0", "method": method})).await
+    }
+
+    async fn request(&mut self, method: &str, params: Value) -> Result<Value> {
+        self.next_id += 1;
+        let id = self.next_id;
+
+        self.send(json!({"jsonrpc": "2.0", "id": id, "method": method, "params": params}))
+            .await?;
+
+        loop {
+            let message = self.receive().await?;
+            // skip notifications, server requests and responses to requests that timed out
+            if message.get("id").and_then(|i| i.as_u64()) != Some(id)
+                || message.get("method").is_some()
+            {
+                log::debug!(
+                    "ignoring message from mcp server {}: {}",
+                    self.name,
+                    message
+                );
+                continue;
+            }
+
+            if let Some(error) = message.get("error") {
+                return Err(anyhow!(
+                    "{}",
+                    error
+                        .get("message")
+                        .and_then(|m| m.as_str())
+                        .unwrap_or("unknown error")
+                ));
+            }
+
+            return Ok(message.get("result").cloned().unwrap_or(Value::Null));
+        }
+    }
+}
+
+// a tool of the server, its arguments are exposed as attributes of the action
+#[cfg(feature = "mcp")]
+#[derive(Debug, Clone)]
+pub struct McpAction {
+    name: String,
+    description: String,
+    // argument name -> json schema type
+    arguments: BTreeMap<String, String>,
+    // argument name -> description shown to the model
+    attributes: Option<HashMap<String, String>>,
+    required: Vec<String>,
+    destructive: bool,
+    connection: Arc<Mutex<Connection>>,
+}
+
+#[cfg(feature = "mcp")]
+impl McpAction {
+    fn from_tool(tool: &Value, connection: Arc<Mutex<Connection>>) -> Option<Self> {
+        let name = tool.get("name")?.as_str()?.to_string();
+        let description = tool
+            .get("description")
+            .and_then(|d| d.as_str())
+            .unwrap_or_default()
+            .to_string();
+
+        let schema = tool.get("inputSchema");
+        let required: Vec<String> = schema
+            .and_then(|s| s.get("required"))
+            .and_then(|r| r.as_array())
+            .map(|r| {
+                r.iter()
+                    .filter_map(|n| n.as_str().map(|s| s.to_string()))
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        let mut arguments = BTreeMap::new();
+        let mut attributes = HashMap::new();
+        if let Some(properties) = schema
+            .and_then(|s| s.get("properties"))
+            .and_then(|p| p.as_object())
+        {
+            for (arg_name, property) in properties {
+                let type_ = property
+                    .get("type")
+                    .and_then(|t| t.as_str())
+                    .unwrap_or("string")
+                    .to_string();
+                let mut arg_description = property
+                    .get("description")
+                    .and_then(|d| d.as_str())
+                    .unwrap_or(arg_name)
+                    .to_string();
+                // every attribute must be specified, optional ones can be left empty
+                if !required.contains(arg_name) {
+                    arg_description = format!("{} (optional, can be empty)", arg_description);
+                }
+
+                attributes.insert(arg_name.to_string(), arg_description);
+                arguments.insert(arg_name.to_string(), type_);
+            }
+        }
+
+        Some(Self {
+            name,
+            description,
+            arguments,
+            attributes: if attributes.is_empty() {
+                None
+            } else {
+                Some(attributes)
+            },
+            required,
+            destructive: is_destructive_tool(tool),
+            connection,
+        })
+    }
+
+    // convert the attributes to arguments of the right type
+    fn to_arguments(&self, attributes: &Option<HashMap<String, String>>) -> Value {
+        let mut arguments = serde_json::Map::new();
+        for (name, value) in attributes.iter().flatten() {
+            if value.is_empty() && !self.required.contains(name) {
+                continue;
+            }
+
+            let value = match self.arguments.get(name).map(|t| t.as_str()) {
+                Some("string") | None => Value::String(value.to_string()),
+                // numbers, booleans, arrays and objects
+                _ => serde_json::from_str(value).unwrap_or(Value::String(value.to_string())),
+            };
+            arguments.insert(name.to_string(), value);
+        }
+        Value::Object(arguments)
+    }
+}
+
+// tools are assumed destructive unless the server annotates them as read-only or non destructive
+#[cfg(feature = "mcp")]
+fn is_destructive_tool(tool: &Value) -> bool {
+    let hint = |name: &str| {
+        tool.get("annotations")
+            .and_then(|a| a.get(name))
+            .and_then(|h| h.as_bool())
+    };
+    hint("readOnlyHint") != Some(true) && hint("destructiveHint") != Some(false)
+}
+
+// join the text parts of a tool result
+#[cfg(feature = "mcp")]
+fn result_to_string(result: &Value) -> Option<String> {
+    let parts: Vec<String> = result
+        .get("content")?
+        .as_array()?
+        .iter()
+        .map(|part| match part.get("type").and_then(|t| t.as_str()) {
+            Some("text") => part
+                .get("text")
+                .and_then(|t| t.as_str())
+                .unwrap_or_default()
+                .to_string(),
+            Some(other) => format!("[{} content]", other),
+            None => part.to_string(),
+        })
+        .collect();
+
+    if parts.is_empty() {
+        None
+    } else {
+        Some(parts.join("\n"))
+    }
+}
+
+#[cfg(feature = "mcp")]
+#[async_trait]
+impl Action for McpAction {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn description(&self) -> &str {
+        &self.description
+    }
+
+    fn example_attributes(&self) -> Option<HashMap<String, String>> {
+        self.attributes.clone()
+    }
+
+    fn is_destructive(&self) -> bool {
+        self.destructive
+    }
+
+    async fn run(
+        &self,
+        _: SharedState,
+        attributes: Option<HashMap<String, String>>,
+        _: Option<String>,
+    ) -> Result<Option<String>> {
+        let result = self
+            .connection
+            .lock()
+            .await
+            .request(
+                "tools/call",
+                json!({"name": self.name, "arguments": self.to_arguments(&attributes)}),
+            )
+            .await?;
+
+        let output = result_to_string(&result);
+        if result.get("isError").and_then(|e| e.as_bool()) == Some(true) {
+            Err(anyhow!(output.unwrap_or("tool error".to_string())))
+        } else {
+            Ok(output)
+        }
+    }
+}
+
+// a connected server and the tools it exposes
+#[cfg(feature = "mcp")]
+#[derive(Debug, Clone)]
+pub struct McpNamespace {
+    name: String,
+    actions: Vec<McpAction>,
+}
+
+#[cfg(feature = "mcp")]
+impl McpNamespace {
+    pub fn to_namespace(&self) -> Namespace {
+        Namespace::new_default(
+            self.name.to_string(),
+            format!("Use these tools provided by the {} server.", self.name),
+            self.actions
+                .iter()
+                .map(|action| Box::new(action.clone()) as Box<dyn Action>)
+                .collect(),
+            None,
+        )
+    }
+}
+
+impl McpServer {
+    pub fn validate(&self) -> Result<()> {
+        if cfg!(not(feature = "mcp")) {
+            return Err(anyhow!(
+                "can't use mcp server {}, nerve was built without the mcp feature",
+                self.name
+            ));
+        }
+        match (&self.command, &self.url) {
+            (Some(_), None) | (None, Some(_)) => Ok(()),
+            _ => Err(anyhow!(
+                "mcp server {} must define either a command or an url",
+                self.name
+            )),
+        }
+    }
+}
+
+#[cfg(feature = "mcp")]
+impl McpServer {
+    fn start_process(&self, command: &str, folder: &str) -> Result<Transport> {
+        let parts: Vec<&str> = command.split_whitespace().collect();
+        if parts.is_empty() {
+            return Err(anyhow!("mcp server {} has no command", self.name));
+        }
+
+        let mut cmd = Command::new(parts[0]);
+        secrets::scrub_env(&mut cmd);
+        cmd.args(&parts[1..])
+            .current_dir(match &self.cwd {
+                Some(cwd) => PathBuf::from(folder).join(cwd),
+                None => PathBuf::from(folder),
+            })
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true);
+        for (key, value) in self.env.iter().flatten() {
+            cmd.env(key, interpolate_variables(value)?);
+        }
+
+        let mut child = cmd
+            .spawn()
+            .map_err(|e| anyhow!("could not start mcp server {}: {}", self.name, e))?;
+
+        let name = self.name.to_string();
+        let mut stderr = BufReader::new(child.stderr.take().unwrap()).lines();
+        tokio::spawn(async move {
+            while let Ok(Some(line)) = stderr.next_line().await {
+                log::debug!("[{}] {}", name, line);
+            }
+        });
+
+        Ok(Transport::Stdio {
+            stdin: child.stdin.take().unwrap(),
+            stdout: BufReader::new(child.stdout.take().unwrap()).lines(),
+            _child: child,
+        })
+    }
+
+    async fn connect_sse(&self, url: &str) -> Result<Transport> {
+        let url = Url::parse(url)?;
+        let client = reqwest::Client::new();
+        let mut headers = HashMap::new();
+        for (key, value) in self.headers.iter().flatten() {
+            let value = interpolate_variables(value)?;
+            secrets::register_header(key, &value);
+            headers.insert(key.to_string(), value);
+        }
+
+        let mut request = client
+            .get(url.clone())
+            .header("Accept", "text/event-stream");
+        for (key, value) in &headers {
+            request = request.header(key, value);
+        }
+        let mut stream = EventSource::new(request)?;
+
+        // the first event tells where to post the messages
+        let endpoint = loop {
+            match stream.next().await {
+                Some(Ok(Event::Open)) => continue,
+                Some(Ok(Event::Message(message))) if message.event == "endpoint" => {
+                    break url.join(message.data.trim())?;
+                }
+                Some(Ok(_)) => continue,
+                Some(Err(err)) => return Err(anyhow!("mcp server {}: {}", self.name, err)),
+                None => return Err(anyhow!("mcp server {} closed the stream", self.name)),
+            }
+        };
+
+        let (tx, messages) = mpsc::unbounded_channel();
+        let name = self.name.to_string();
+        tokio::spawn(async move {
+            while let Some(event) = stream.next().await {
+                match event {
+                    Ok(Event::Message(message)) if message.event == "message" => {
+                        if tx.send(message.data).is_err() {
+                            break;
+                        }
+                    }
+                    Ok(_) => {}
+                    Err(err) => {
+                        log::error!("mcp server {}: {}", name, err);
+                        break;
+                    }
+                }
+            }
+            stream.close();
+        });
+
+        Ok(Transport::Sse {
+            client,
+            headers,
+            endpoint,
+            messages,
+        })
+    }
+
+    async fn handshake(&self, connection: &mut Connection) -> Result<Vec<Value>> {
+        connection
+            .request(
+                "initialize",
+                json!({
+                    "protocolVersion": PROTOCOL_VERSION,
+                    "capabilities": {},
+                    "clientInfo": {"name": "nerve", "version": env!("CARGO_PKG_VERSION")}
+                }),
+            )
+            .await?;
+        connection.notify("notifications/initialized").await?;
+
+        let mut tools = vec![];
+        let mut cursor: Option<String> = None;
+        loop {
+            let params = match &cursor {
+                Some(cursor) => json!({ "cursor": cursor }),
+                None => json!({}),
+            };
+            let result = connection.request("tools/list", params).await?;
+            if let Some(page) = result.get("tools").and_then(|t| t.as_array()) {
+                tools.extend(page.iter().cloned());
+            }
+
+            cursor = result
+                .get("nextCursor")
+                .and_then(|c| c.as_str())
+                .map(|c| c.to_string());
+            if cursor.is_none() {
+                break;
+            }
+        }
+
+        Ok(tools)
+    }
+
+    // connect to the server and list its tools
+    pub async fn connect(&self, folder: &str) -> Result<McpNamespace> {
+        let transport = if let Some(command) = &self.command {
+            self.start_process(command, folder)?
+        } else {
+            tokio::time::timeout(
+                CONNECT_TIMEOUT,
+                self.connect_sse(self.url.as_ref().unwrap()),
+            )
+            .await
+            .map_err(|_| anyhow!("timeout while connecting to mcp server {}", self.name))??
+        };
+
+        let mut connection = Connection {
+            name: self.name.to_string(),
+            transport,
+            next_id: 0,
+        };
+
+        let tools = tokio::time::timeout(CONNECT_TIMEOUT, self.handshake(&mut connection))
+            .await
+            .map_err(|_| anyhow!("timeout while initializing mcp server {}", self.name))??;
+
+        let connection = Arc::new(Mutex::new(connection));
+        let actions: Vec<McpAction> = tools
+            .iter()
+            .filter_map(|tool| McpAction::from_tool(tool, connection.clone()))
+            .filter(|action| {
+                self.tools
+                    .as_ref()
+                    .map(|allowed| allowed.contains(&action.name))
+                    .unwrap_or(true)
+            })
+            .collect();
+
+        if actions.is_empty() {
+            return Err(anyhow!("mcp server {} exposes no tools", self.name));
+        }
+
+        log::info!("mcp server {} exposes {} tools", &self.name, actions.len());
+
+        Ok(McpNamespace {
+            name: self.name.to_string(),
+            actions,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(feature = "mcp")]
+    // a minimal server answering by request id: 1 initialize, 2 tools/list, 3+ tools/call
+    const SERVER: &str = r#"
+while read -r line; do
+  id=$(echo "$line" | sed -n 's/.*"id":\([0-9]*\).*/\1/p')
+  case "$line" in
+    *'"initialize"'*)
+      echo "{\"jsonrpc\":\"2.0\",\"id\":$id,\"result\":{\"protocolVersion\":\"2024-11-05\",\"capabilities\":{},\"serverInfo\":{\"name\":\"test\",\"version\":\"1.0\"}}}" ;;
+    *'"tools/list"'*)
+      echo '{"jsonrpc":"2.0","method":"notifications/message","params":{}}'
+      echo "{\"jsonrpc\":\"2.0\",\"id\":$id,\"result\":{\"tools\":[{\"name\":\"add\",\"description\":\"Add two numbers.\",\"inputSchema\":{\"type\":\"object\",\"properties\":{\"a\":{\"type\":\"number\"},\"b\":{\"type\":\"number\"},\"note\":{\"type\":\"string\"}},\"required\":[\"a\",\"b\"]}}]}}" ;;
+    *'"tools/call"'*)
+      args=$(echo "$line" | sed 's/.*"arguments":\({[^}]*}\).*/\1/' | sed 's/"/\\"/g')
+      echo "{\"jsonrpc\":\"2.0\",\"id\":$id,\"result\":{\"content\":[{\"type\":\"text\",\"text\":\"$args\"}]}}" ;;
+  esac
+done
+"#;
+
+    #[cfg(feature = "mcp")]
+    #[tokio::test]
+    async fn test_stdio_server() {
+        let folder = std::env::temp_dir().join("nerve-mcp-test");
+        std::fs::create_dir_all(&folder).unwrap();
+        std::fs::write(folder.join("server.sh"), SERVER).unwrap();
+
+        let server = McpServer {
+            name: "Math".to_string(),
+            command: Some("/bin/sh server.sh".to_string()),
+            ..Default::default()
+        };
+        assert!(server.validate().is_ok());
+
+        let namespace = server.connect(folder.to_str().unwrap()).await.unwrap();
+        assert_eq!(namespace.actions.len(), 1);
+
+        let action = &namespace.actions[0];
+        assert_eq!(action.name(), "add");
+        assert!(action.is_destructive());
+        let attributes = action.example_attributes().unwrap();
+        assert_eq!(attributes.len(), 3);
+        assert!(attributes["note"].contains("optional"));
+
+        let mut call = HashMap::new();
+        call.insert("a".to_string(), "1".to_string());
+        call.insert("b".to_string(), "2.5".to_string());
+        call.insert("note".to_string(), "".to_string());
+
+        let result = action
+            .connection
+            .lock()
+            .await
+            .request(
+                "tools/call",
+                json!({"name": "add", "arguments": action.to_arguments(&Some(call))}),
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            result_to_string(&result),
+            Some(r#"{"a":1,"b":2.5}"#.to_string())
+        );
+    }
+
+    #[cfg(feature = "mcp")]
+    #[test]
+    fn test_destructive_hints() {
+        assert!(is_destructive_tool(&json!({"name": "rm"})));
+        assert!(is_destructive_tool(
+            &json!({"name": "rm", "annotations": {"destructiveHint": true}})
+        ));
+        assert!(!is_destructive_tool(
+            &json!({"name": "ls", "annotations": {"destructiveHint": false}})
+        ));
+        assert!(!is_destructive_tool(
+            &json!({"name": "ls", "annotations": {"readOnlyHint": true}})
+        ));
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(McpServer {
+            name: "nope".to_string(),
+            ..Default::default()
+        }
+        .validate()
+        .is_err());
+    }
+}
@@ -0,0 +1,107 @@
+use std::time::Duration;
+
+use anyhow::Result;
+use chrono::Local;
+use duration_string::DurationString;
+use serde::Deserialize;
+
+use crate::agent::state::storage::Entry;
+
+fn default_min_relevance() -> f64 {
+    0.1
+}
+
+// how long the memories saved by the model are kept in the prompt
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct MemoryPolicy {
+    // memories saved longer ago than this are forgotten
+    pub ttl: Option<String>,
+    // number of steps after which the relevance of a memory halves, saving it again restores it
+    pub half_life: Option<usize>,
+    // memories whose relevance drops below this are forgotten
+    #[serde(default = "default_min_relevance")]
+    pub min_relevance: f64,
+}
+
+impl MemoryPolicy {
+    pub fn validate(&self) -> Result<()> {
+        if let Some(ttl) = &self.ttl {
+            ttl.parse::<DurationString>()
+                .map_err(|e| anyhow!("invalid memory ttl '{}': {}", ttl, e))?;
+        }
+        if self.half_life == Some(0) {
+            return Err(anyhow!("memory half_life can't be 0"));
+        }
+        if self.min_relevance <= 0.0 || self.min_relevance >= 1.0 {
+            return Err(anyhow!("memory min_relevance must be between 0 and 1"));
+        }
+        Ok(())
+    }
+
+    pub fn ttl(&self) -> Option<Duration> {
+        self.ttl
+            .as_ref()
+            .and_then(|ttl| ttl.parse::<DurationString>().ok())
+            .map(|ttl| ttl.into())
+    }
+
+    // relevance of a memory saved the given number of steps ago, from 1 down to 0
+    pub fn relevance(&self, age: usize) -> f64 {
+        match self.half_life {
+            Some(half_life) => 0.5f64.powf(age as f64 / half_life as f64),
+            None => 1.0,
+        }
+    }
+
+    // true if the entry is expired or no longer relevant at the given step
+    pub fn forgets(&self, entry: &Entry, step: usize) -> bool {
+        if let Some(ttl) = self.ttl() {
+            let age = (Local::now() - entry.saved_at).to_std().unwrap_or_default();
+            if age >= ttl {
+                return true;
+            }
+        }
+
+        self.relevance(step.saturating_sub(entry.step)) < self.min_relevance
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_relevance_decay() {
+        let policy: MemoryPolicy = serde_yaml::from_str("half_life: 5").unwrap();
+        policy.validate().unwrap();
+        assert_eq!(policy.relevance(0), 1.0);
+        assert_eq!(policy.relevance(5), 0.5);
+        assert_eq!(policy.relevance(10), 0.25);
+
+        let mut entry = Entry::new("note".to_string());
+        entry.step = 3;
+        // 0.5^(16/5) is just above 0.1, 0.5^(17/5) just below
+        assert!(!policy.forgets(&entry, 19));
+        assert!(policy.forgets(&entry, 20));
+    }
+
+    #[test]
+    fn test_ttl() {
+        let policy: MemoryPolicy = serde_yaml::from_str("ttl: 1h").unwrap();
+        policy.validate().unwrap();
+
+        let mut entry = Entry::new("note".to_string());
+        assert!(!policy.forgets(&entry, 1000));
+        entry.saved_at = Local::now() - chrono::Duration::hours(2);
+        assert!(policy.forgets(&entry, 0));
+
+        assert!(serde_yaml::from_str::<MemoryPolicy>("ttl: soon")
+            .unwrap()
+            .validate()
+            .is_err());
+        assert!(serde_yaml::from_str::<MemoryPolicy>("half_life: 0")
+            .unwrap()
+            .validate()
+            .is_err());
+    }
+}
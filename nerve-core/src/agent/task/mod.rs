@@ -0,0 +1,214 @@
+use std::{collections::HashMap, time::Duration};
+
+use anyhow::Result;
+use serde::Deserialize;
+
+use super::{guardrails::GuardrailRule, namespaces::Namespace, ratelimit::RateLimit, Invocation};
+use crate::prompt;
+use evaluation::Evaluation;
+use filters::OutputFilter;
+use handoff::Handoff;
+use hooks::Hooks;
+use memory::MemoryPolicy;
+use report::ReportTemplate;
+use retry::RetryPolicy;
+use storages::StorageQuota;
+
+pub mod chunking;
+pub mod crawler;
+pub mod documents;
+pub mod evaluation;
+pub mod filters;
+pub mod handoff;
+pub mod hooks;
+pub mod index;
+pub mod mcp;
+pub mod memory;
+pub mod openapi;
+pub mod pgvector;
+pub mod prompt;
+pub mod rag;
+pub mod report;
+pub mod rerank;
+pub mod retry;
+pub mod robopages;
+pub mod stdio;
+pub mod storages;
+pub mod tasklet;
+pub mod variables;
+pub mod wasm;
+pub mod watcher;
+
+// TODO: comment the shit out of everything.
+
+/// A sample invocation and its response, used as a few-shot example for the model.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Example {
+    #[serde(flatten)]
+    pub invocation: Invocation,
+    pub response: String,
+}
+
+/// What the agent has to accomplish and with which tools.
+///
+/// [`tasklet::Tasklet`] implements it for the YAML tasklets loaded from disk, embedders can
+/// implement it to create tasks dynamically, for instance from the tickets of an issue tracker.
+/// Only the prompts and the task specific actions are required, every other method has a
+/// default matching an empty tasklet.
+///
+/// ```
+/// use anyhow::Result;
+/// use nerve_core::agent::{namespaces::Namespace, task::Task};
+///
+/// #[derive(Debug)]
+/// struct TicketTask {
+///     title: String,
+///     body: String,
+/// }
+///
+/// impl Task for TicketTask {
+///     fn to_system_prompt(&self) -> Result<String> {
+///         Ok("You are a software engineer working on the issues of a project.".to_string())
+///     }
+///
+///     fn to_prompt(&self) -> Result<String> {
+///         Ok(format!("Solve this issue.\n\n# {}\n\n{}", self.title, self.body))
+///     }
+///
+///     fn get_functions(&self) -> Vec<Namespace> {
+///         vec![]
+///     }
+///
+///     fn namespaces(&self) -> Option<Vec<String>> {
+///         Some(vec!["*".to_string(), "filesystem".to_string()])
+///     }
+///
+///     fn get_max_steps(&self) -> Option<usize> {
+///         Some(50)
+///     }
+/// }
+/// ```
+pub trait Task: std::fmt::Debug + Send + Sync {
+    /// The system prompt, describing the role of the agent.
+    fn to_system_prompt(&self) -> Result<String>;
+    /// The prompt, describing what the agent has to do.
+    fn to_prompt(&self) -> Result<String>;
+    /// Task specific actions, added to the namespaces returned by [`Task::namespaces`].
+    fn get_functions(&self) -> Vec<Namespace>;
+
+    /// Optional timeout of every action.
+    fn get_timeout(&self) -> Option<Duration> {
+        None
+    }
+
+    /// Optional per-action timeout overriding the action default.
+    fn get_action_timeout(&self, _action_name: &str) -> Option<Duration> {
+        None
+    }
+
+    /// Optional retry policy overriding the action default.
+    fn get_retry_policy(&self, _action_name: &str) -> Option<RetryPolicy> {
+        None
+    }
+
+    /// Limits shared by the actions tagged with them, by tag.
+    fn get_rate_limits(&self) -> HashMap<String, RateLimit> {
+        HashMap::new()
+    }
+
+    /// Optional post processing of the output of an action.
+    fn get_output_filter(&self, _action_name: &str) -> Option<OutputFilter> {
+        None
+    }
+
+    /// Optional maximum number of steps, used when not set by the agent.
+    fn get_max_steps(&self) -> Option<usize> {
+        None
+    }
+
+    /// Optional maximum wall-clock duration of the task.
+    fn get_max_duration(&self) -> Option<Duration> {
+        None
+    }
+
+    /// Optional interval, in steps, at which the agent is asked to reflect on its progress.
+    fn get_reflection_interval(&self) -> Option<usize> {
+        None
+    }
+
+    /// Rules inspecting the responses and invocations of the model.
+    fn get_guardrails(&self) -> Vec<GuardrailRule> {
+        vec![]
+    }
+
+    /// Optional expiry and relevance decay of the memories saved by the model.
+    fn get_memory_policy(&self) -> Option<MemoryPolicy> {
+        None
+    }
+
+    /// Optional evaluation of the final answer before the task is completed.
+    fn get_evaluation(&self) -> Option<Evaluation> {
+        None
+    }
+
+    /// Show in the prompt when, at which step and by which action each storage entry was saved.
+    fn show_storage_ages(&self) -> bool {
+        false
+    }
+
+    /// Render the storages once and then only their changes, until more than this many piled up.
+    fn get_storage_deltas(&self) -> Option<usize> {
+        None
+    }
+
+    /// Optional template of the report compiled by the model at the end of the run.
+    fn get_report_template(&self) -> Option<ReportTemplate> {
+        None
+    }
+
+    /// Size limits of the storages, by storage name.
+    fn get_storage_quotas(&self) -> HashMap<String, StorageQuota> {
+        HashMap::new()
+    }
+
+    /// Agents the model can hand the task off to.
+    fn get_handoffs(&self) -> Vec<Handoff> {
+        vec![]
+    }
+
+    /// Optional documents to import and query with the rag namespace.
+    fn get_rag_config(&self) -> Option<rag::RagConfig> {
+        None
+    }
+
+    /// Rules the model must follow, the basic ones by default.
+    fn guidance(&self) -> Result<Vec<String>> {
+        self.base_guidance()
+    }
+
+    /// Names of the built-in namespaces to use, where `*` means all the default ones and
+    /// `!name` excludes a namespace, the default ones are used if `None`.
+    fn namespaces(&self) -> Option<Vec<String>> {
+        None
+    }
+
+    /// Few-shot examples, they must refer to available actions.
+    fn examples(&self) -> Vec<Example> {
+        vec![]
+    }
+
+    /// Commands and webhooks executed around the run.
+    fn hooks(&self) -> Hooks {
+        Hooks::default()
+    }
+
+    /// The basic rules every task should extend.
+    fn base_guidance(&self) -> Result<Vec<String>> {
+        // basic rules to extend
+        Ok(prompt!("basic_guidance.prompt")
+            .split('\n')
+            .map(|l| l.trim().to_string())
+            .filter(|l| !l.is_empty())
+            .collect())
+    }
+}
@@ -0,0 +1,886 @@
+#[cfg(feature = "openapi")]
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+#[cfg(feature = "openapi")]
+use std::{path::PathBuf, time::Duration};
+
+use anyhow::Result;
+#[cfg(feature = "openapi")]
+use async_trait::async_trait;
+use serde::Deserialize;
+#[cfg(feature = "openapi")]
+use serde_json::Value;
+#[cfg(feature = "openapi")]
+use url::Url;
+
+#[cfg(feature = "openapi")]
+use crate::agent::{
+    namespaces::{Action, Namespace},
+    secrets,
+    state::SharedState,
+    task::{retry, retry::RetryPolicy, variables::interpolate_variables},
+};
+
+#[cfg(feature = "openapi")]
+const METHODS: [&str; 7] = ["get", "put", "post", "delete", "patch", "head", "options"];
+// how deep references and schemas are followed
+#[cfg(feature = "openapi")]
+const MAX_DEPTH: usize = 8;
+
+// an openapi 3 or swagger 2 document whose operations are exposed as actions, either as the
+// path or url of the spec or with further options
+#[derive(Deserialize, Debug, Clone)]
+#[serde(untagged)]
+pub enum OpenApi {
+    Spec(String),
+    Config(OpenApiConfig),
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct OpenApiConfig {
+    // path relative to the tasklet folder or url of the document
+    pub spec: String,
+    // namespace name, the title of the api by default
+    pub name: Option<String>,
+    // use this instead of the server declared by the spec
+    pub base_url: Option<String>,
+    // sent with every request, values can use variables
+    pub headers: Option<HashMap<String, String>>,
+    // only expose these operations
+    pub operations: Option<Vec<String>>,
+}
+
+impl OpenApi {
+    pub fn validate(&self) -> Result<()> {
+        if cfg!(not(feature = "openapi")) {
+            return Err(anyhow!(
+                "can't use openapi spec {}, nerve was built without the openapi feature",
+                match self {
+                    Self::Spec(spec) => spec,
+                    Self::Config(config) => &config.spec,
+                }
+            ));
+        }
+        Ok(())
+    }
+
+    #[cfg(feature = "openapi")]
+    fn config(&self) -> OpenApiConfig {
+        match self {
+            Self::Spec(spec) => OpenApiConfig {
+                spec: spec.to_string(),
+                ..Default::default()
+            },
+            Self::Config(config) => config.clone(),
+        }
+    }
+
+    // read the spec and create the actions for its operations
+    #[cfg(feature = "openapi")]
+    pub async fn load(&self, folder: &str) -> Result<OpenApiNamespace> {
+        let config = self.config();
+        let (raw, spec_url) =
+            if config.spec.starts_with("http://") || config.spec.starts_with("https://") {
+                let url = Url::parse(&config.spec)?;
+                let response = reqwest::get(url.clone()).await?;
+                if !response.status().is_success() {
+                    return Err(anyhow!(
+                        "could not fetch openapi spec {}: {}",
+                        &config.spec,
+                        response.status()
+                    ));
+                }
+                (response.text().await?, Some(url))
+            } else {
+                let path = PathBuf::from(&config.spec);
+                let path = if path.is_relative() {
+                    PathBuf::from(folder).join(path)
+                } else {
+                    path
+                };
+                (
+                    std::fs::read_to_string(&path).map_err(|e| {
+                        anyhow!("could not read openapi spec {}: {}", path.display(), e)
+                    })?,
+                    None,
+                )
+            };
+
+        // yaml is a superset of json
+        let spec: Value = serde_yaml::from_str(&raw)
+            .map_err(|e| anyhow!("could not parse openapi spec {}: {}", &config.spec, e))?;
+
+        OpenApiNamespace::from_spec(&spec, &config, spec_url.as_ref())
+    }
+}
+
+// follow local references like #/components/schemas/Pet
+#[cfg(feature = "openapi")]
+fn resolve<'a>(root: &'a Value, mut value: &'a Value) -> &'a Value {
+    for _ in 0..MAX_DEPTH {
+        match value.get("$ref").and_then(|r| r.as_str()) {
+            Some(reference) if reference.starts_with('#') => match root.pointer(&reference[1..]) {
+                Some(target) => value = target,
+                None => break,
+            },
+            _ => break,
+        }
+    }
+    value
+}
+
+// an example value for a schema, shown to the model as the payload template
+#[cfg(feature = "openapi")]
+fn example_from_schema(root: &Value, schema: &Value, depth: usize) -> Value {
+    let schema = resolve(root, schema);
+    if let Some(example) = schema.get("example") {
+        return example.clone();
+    }
+    if let Some(first) = schema
+        .get("enum")
+        .and_then(|e| e.as_array())
+        .and_then(|e| e.first())
+    {
+        return first.clone();
+    }
+    if depth >= MAX_DEPTH {
+        return Value::Null;
+    }
+
+    match schema.get("type").and_then(|t| t.as_str()) {
+        Some("array") => Value::Array(vec![example_from_schema(
+            root,
+            schema.get("items").unwrap_or(&Value::Null),
+            depth + 1,
+        )]),
+        Some("string") => Value::String("string".to_string()),
+        Some("integer") => Value::from(0),
+        Some("number") => Value::from(0.0),
+        Some("boolean") => Value::Bool(false),
+        _ => match schema.get("properties").and_then(|p| p.as_object()) {
+            Some(properties) => Value::Object(
+                properties
+                    .iter()
+                    .map(|(name, property)| {
+                        (
+                            name.to_string(),
+                            example_from_schema(root, property, depth + 1),
+                        )
+                    })
+                    .collect(),
+            ),
+            None => Value::Object(Default::default()),
+        },
+    }
+}
+
+#[cfg(feature = "openapi")]
+#[derive(Debug, Clone, PartialEq)]
+enum Location {
+    Path,
+    Query,
+    Header,
+}
+
+#[cfg(feature = "openapi")]
+#[derive(Debug, Clone)]
+struct Parameter {
+    name: String,
+    location: Location,
+    description: String,
+    required: bool,
+    type_: String,
+    allowed: Option<Vec<String>>,
+}
+
+#[cfg(feature = "openapi")]
+impl Parameter {
+    fn from_spec(root: &Value, param: &Value) -> Option<Self> {
+        let param = resolve(root, param);
+        let location = match param.get("in")?.as_str()? {
+            "path" => Location::Path,
+            "query" => Location::Query,
+            "header" => Location::Header,
+            _ => return None,
+        };
+        // openapi 3 declares the type in a schema, swagger 2 in the parameter itself
+        let schema = param
+            .get("schema")
+            .map(|s| resolve(root, s))
+            .unwrap_or(param);
+
+        Some(Self {
+            name: param.get("name")?.as_str()?.to_string(),
+            required: location == Location::Path
+                || param.get("required").and_then(|r| r.as_bool()) == Some(true),
+            location,
+            description: param
+                .get("description")
+                .and_then(|d| d.as_str())
+                .unwrap_or_default()
+                .to_string(),
+            type_: schema
+                .get("type")
+                .and_then(|t| t.as_str())
+                .unwrap_or("string")
+                .to_string(),
+            allowed: schema.get("enum").and_then(|e| e.as_array()).map(|values| {
+                values
+                    .iter()
+                    .map(|v| match v {
+                        Value::String(s) => s.to_string(),
+                        other => other.to_string(),
+                    })
+                    .collect()
+            }),
+        })
+    }
+
+    fn validate(&self, value: &str) -> Result<()> {
+        let valid = match self.type_.as_str() {
+            "integer" => value.parse::<i64>().is_ok(),
+            "number" => value.parse::<f64>().is_ok(),
+            "boolean" => value == "true" || value == "false",
+            _ => true,
+        };
+        if !valid {
+            return Err(anyhow!(
+                "parameter '{}' must be of type {}",
+                self.name,
+                self.type_
+            ));
+        }
+
+        if let Some(allowed) = &self.allowed {
+            if !allowed.iter().any(|a| a == value) {
+                return Err(anyhow!(
+                    "parameter '{}' must be one of: {}",
+                    self.name,
+                    allowed.join(", ")
+                ));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// an operation of the api
+#[cfg(feature = "openapi")]
+#[derive(Debug, Clone)]
+pub struct OpenApiAction {
+    name: String,
+    description: String,
+    method: String,
+    base_url: String,
+    path: String,
+    parameters: Vec<Parameter>,
+    // json body template and whether it's required
+    body: Option<(String, bool)>,
+    headers: BTreeMap<String, String>,
+}
+
+#[cfg(feature = "openapi")]
+impl OpenApiAction {
+    // sanitized operation id or method and path
+    fn make_name(method: &str, path: &str, operation: &Value) -> String {
+        let raw = match operation.get("operationId").and_then(|o| o.as_str()) {
+            Some(id) => id.to_string(),
+            None => format!("{}_{}", method, path),
+        };
+        let name: String = raw
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+            .collect();
+        name.split('_')
+            .filter(|part| !part.is_empty())
+            .collect::<Vec<_>>()
+            .join("_")
+    }
+
+    fn request_body(root: &Value, operation: &Value) -> Option<Option<(String, bool)>> {
+        // swagger 2
+        if let Some(param) = operation
+            .get("parameters")
+            .and_then(|p| p.as_array())
+            .and_then(|params| {
+                params
+                    .iter()
+                    .map(|p| resolve(root, p))
+                    .find(|p| p.get("in").and_then(|i| i.as_str()) == Some("body"))
+            })
+        {
+            let example = example_from_schema(root, param.get("schema").unwrap_or(&Value::Null), 0);
+            let required = param.get("required").and_then(|r| r.as_bool()) == Some(true);
+            return Some(Some((example.to_string(), required)));
+        }
+
+        // openapi 3
+        let Some(body) = operation.get("requestBody") else {
+            return Some(None);
+        };
+        let body = resolve(root, body);
+        let required = body.get("required").and_then(|r| r.as_bool()) == Some(true);
+        // only json bodies are supported
+        let content = body
+            .get("content")
+            .and_then(|c| c.as_object())?
+            .iter()
+            .find(|(mime, _)| mime.starts_with("application/json") || mime.ends_with("+json"))?
+            .1;
+        let example = match content.get("example") {
+            Some(example) => example.clone(),
+            None => example_from_schema(root, content.get("schema").unwrap_or(&Value::Null), 0),
+        };
+
+        Some(Some((example.to_string(), required)))
+    }
+
+    fn from_operation(
+        root: &Value,
+        method: &str,
+        path: &str,
+        item: &Value,
+        operation: &Value,
+        base_url: &str,
+        headers: &BTreeMap<String, String>,
+    ) -> Option<Self> {
+        let name = Self::make_name(method, path, operation);
+        let Some(body) = Self::request_body(root, operation) else {
+            log::warn!("skipping operation {}: unsupported request body", name);
+            return None;
+        };
+
+        // operation parameters override the ones declared for the path
+        let mut parameters: Vec<Parameter> = vec![];
+        for param in [item, operation]
+            .iter()
+            .filter_map(|v| v.get("parameters").and_then(|p| p.as_array()))
+            .flatten()
+            .filter_map(|p| Parameter::from_spec(root, p))
+        {
+            parameters.retain(|p| p.name != param.name || p.location != param.location);
+            parameters.push(param);
+        }
+
+        let summary = operation
+            .get("summary")
+            .or_else(|| operation.get("description"))
+            .and_then(|s| s.as_str())
+            .unwrap_or_default()
+            .trim();
+
+        Some(Self {
+            name,
+            description: format!("{} ({} {})", summary, method.to_uppercase(), path)
+                .trim()
+                .to_string(),
+            method: method.to_uppercase(),
+            base_url: base_url.to_string(),
+            path: path.to_string(),
+            parameters,
+            body,
+            headers: headers.clone(),
+        })
+    }
+
+    // check the attributes against the spec and create the url of the request
+    fn build_url(&self, attributes: &HashMap<String, String>) -> Result<Url> {
+        // optional parameters can be left empty
+        let value_of = |param: &Parameter| -> Result<Option<String>> {
+            match attributes.get(&param.name).filter(|v| !v.is_empty()) {
+                Some(value) => {
+                    param.validate(value)?;
+                    Ok(Some(value.to_string()))
+                }
+                None if param.required => {
+                    Err(anyhow!("missing required parameter '{}'", param.name))
+                }
+                None => Ok(None),
+            }
+        };
+
+        let mut url = Url::parse(&self.base_url)?;
+        {
+            let mut segments = url
+                .path_segments_mut()
+                .map_err(|_| anyhow!("invalid base url {}", self.base_url))?;
+            segments.pop_if_empty();
+            for segment in self.path.split('/').filter(|s| !s.is_empty()) {
+                let mut segment = segment.to_string();
+                for param in self
+                    .parameters
+                    .iter()
+                    .filter(|p| p.location == Location::Path)
+                {
+                    let placeholder = format!("{{{}}}", param.name);
+                    if segment.contains(&placeholder) {
+                        segment = segment.replace(&placeholder, &value_of(param)?.unwrap());
+                    }
+                }
+                // values are percent encoded
+                segments.push(&segment);
+            }
+        }
+
+        for param in self
+            .parameters
+            .iter()
+            .filter(|p| p.location == Location::Query)
+        {
+            if let Some(value) = value_of(param)? {
+                url.query_pairs_mut().append_pair(&param.name, &value);
+            }
+        }
+
+        for param in self
+            .parameters
+            .iter()
+            .filter(|p| p.location == Location::Header)
+        {
+            value_of(param)?;
+        }
+
+        Ok(url)
+    }
+
+    fn build_body(&self, payload: &Option<String>) -> Result<Option<Value>> {
+        match (
+            &self.body,
+            payload.as_ref().filter(|p| !p.trim().is_empty()),
+        ) {
+            (Some(_), Some(payload)) => {
+                Ok(Some(serde_json::from_str(payload).map_err(|e| {
+                    anyhow!("the payload must be valid json: {}", e)
+                })?))
+            }
+            (Some((_, true)), None) => Err(anyhow!("the request body is required")),
+            _ => Ok(None),
+        }
+    }
+}
+
+#[cfg(feature = "openapi")]
+#[async_trait]
+impl Action for OpenApiAction {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn description(&self) -> &str {
+        &self.description
+    }
+
+    fn rate_limits(&self) -> Vec<String> {
+        vec!["network".to_string()]
+    }
+
+    fn example_payload(&self) -> Option<&str> {
+        self.body.as_ref().map(|(example, _)| example.as_str())
+    }
+
+    fn example_attributes(&self) -> Option<HashMap<String, String>> {
+        if self.parameters.is_empty() {
+            return None;
+        }
+
+        Some(
+            self.parameters
+                .iter()
+                .map(|param| {
+                    let mut description = if param.description.is_empty() {
+                        param.type_.to_string()
+                    } else {
+                        format!("{} ({})", param.description, param.type_)
+                    };
+                    if let Some(allowed) = &param.allowed {
+                        description = format!("{}, one of: {}", description, allowed.join(", "));
+                    }
+                    // every attribute must be specified, optional ones can be left empty
+                    if !param.required {
+                        description = format!("{} (optional, can be empty)", description);
+                    }
+                    (param.name.to_string(), description)
+                })
+                .collect(),
+        )
+    }
+
+    fn timeout(&self) -> Option<Duration> {
+        Some(Duration::from_secs(30))
+    }
+
+    fn is_destructive(&self) -> bool {
+        !matches!(self.method.as_str(), "GET" | "HEAD" | "OPTIONS")
+    }
+
+    fn retry_policy(&self) -> Option<RetryPolicy> {
+        // only safe to repeat for idempotent requests
+        if self.is_destructive() {
+            None
+        } else {
+            Some(RetryPolicy::new(2, "1s"))
+        }
+    }
+
+    async fn run(
+        &self,
+        state: SharedState,
+        attributes: Option<HashMap<String, String>>,
+        payload: Option<String>,
+    ) -> Result<Option<String>> {
+        let attributes = attributes.unwrap_or_default();
+        let url = self.build_url(&attributes)?;
+        let body = self.build_body(&payload)?;
+
+        let policy = state.lock().await.get_policy().http.clone();
+        policy.check(&url).await?;
+
+        log::debug!("{} {} ...", &self.method, url);
+
+        let method = reqwest::Method::from_bytes(self.method.as_bytes())?;
+        let mut request = reqwest::Client::new().request(method, url);
+        for (key, value) in &self.headers {
+            request = request.header(key, value);
+        }
+        for param in self
+            .parameters
+            .iter()
+            .filter(|p| p.location == Location::Header)
+        {
+            if let Some(value) = attributes.get(&param.name).filter(|v| !v.is_empty()) {
+                request = request.header(&param.name, value);
+            }
+        }
+        if let Some(body) = body {
+            request = request.json(&body);
+        }
+
+        let response = request
+            .send()
+            .await
+            .map_err(|e| retry::transient(e.to_string()))?;
+        let status = response.status();
+        let text = response.text().await.unwrap_or_default();
+
+        if status.is_success() {
+            Ok(if text.is_empty() { None } else { Some(text) })
+        } else {
+            let error = format!("{} {}", status, text).trim().to_string();
+            // server errors and rate limiting might go away by retrying
+            if status.is_server_error() || status.as_u16() == 429 {
+                Err(retry::transient(error))
+            } else {
+                Err(anyhow!(error))
+            }
+        }
+    }
+}
+
+#[cfg(feature = "openapi")]
+#[derive(Debug, Clone)]
+pub struct OpenApiNamespace {
+    name: String,
+    description: String,
+    actions: Vec<OpenApiAction>,
+}
+
+#[cfg(feature = "openapi")]
+impl OpenApiNamespace {
+    // the base url of the api, from the options or the spec
+    fn base_url(spec: &Value, config: &OpenApiConfig, spec_url: Option<&Url>) -> Result<String> {
+        let base_url = if let Some(base_url) = &config.base_url {
+            interpolate_variables(base_url)?
+        } else if let Some(server) = spec
+            .get("servers")
+            .and_then(|s| s.as_array())
+            .and_then(|s| s.first())
+        {
+            // openapi 3, server variables get their default value
+            let mut url = server
+                .get("url")
+                .and_then(|u| u.as_str())
+                .unwrap_or_default()
+                .to_string();
+            for (name, variable) in server
+                .get("variables")
+                .and_then(|v| v.as_object())
+                .into_iter()
+                .flatten()
+            {
+                if let Some(default) = variable.get("default").and_then(|d| d.as_str()) {
+                    url = url.replace(&format!("{{{}}}", name), default);
+                }
+            }
+            url
+        } else if let Some(host) = spec.get("host").and_then(|h| h.as_str()) {
+            // swagger 2
+            let scheme = spec
+                .get("schemes")
+                .and_then(|s| s.as_array())
+                .and_then(|s| s.first())
+                .and_then(|s| s.as_str())
+                .unwrap_or("https");
+            format!(
+                "{}://{}{}",
+                scheme,
+                host,
+                spec.get("basePath")
+                    .and_then(|b| b.as_str())
+                    .unwrap_or_default()
+            )
+        } else {
+            "".to_string()
+        };
+
+        // relative to the spec location
+        if Url::parse(&base_url).is_err() {
+            if let Some(spec_url) = spec_url {
+                return Ok(spec_url.join(&base_url)?.to_string());
+            }
+            return Err(anyhow!(
+                "the openapi spec {} doesn't declare an absolute server url, specify a base_url",
+                &config.spec
+            ));
+        }
+
+        Ok(base_url)
+    }
+
+    fn from_spec(spec: &Value, config: &OpenApiConfig, spec_url: Option<&Url>) -> Result<Self> {
+        let base_url = Self::base_url(spec, config, spec_url)?;
+
+        let mut headers = BTreeMap::new();
+        for (key, value) in config.headers.iter().flatten() {
+            let value = interpolate_variables(value)?;
+            secrets::register_header(key, &value);
+            headers.insert(key.to_string(), value);
+        }
+
+        let mut actions: Vec<OpenApiAction> = vec![];
+        for (path, item) in spec
+            .get("paths")
+            .and_then(|p| p.as_object())
+            .into_iter()
+            .flatten()
+        {
+            let item = resolve(spec, item);
+            for method in METHODS {
+                let Some(operation) = item.get(method) else {
+                    continue;
+                };
+                let Some(action) = OpenApiAction::from_operation(
+                    spec, method, path, item, operation, &base_url, &headers,
+                ) else {
+                    continue;
+                };
+                if let Some(allowed) = &config.operations {
+                    if !allowed.contains(&action.name) {
+                        continue;
+                    }
+                }
+                if actions.iter().any(|a| a.name == action.name) {
+                    log::warn!("skipping duplicated operation {}", &action.name);
+                    continue;
+                }
+                actions.push(action);
+            }
+        }
+
+        if actions.is_empty() {
+            return Err(anyhow!(
+                "the openapi spec {} has no operations",
+                &config.spec
+            ));
+        }
+
+        let info = spec.get("info");
+        let title = info
+            .and_then(|i| i.get("title"))
+            .and_then(|t| t.as_str())
+            .unwrap_or("API");
+        let name = config.name.clone().unwrap_or(title.to_string());
+
+        log::info!("openapi {} exposes {} operations", &name, actions.len());
+
+        Ok(Self {
+            description: info
+                .and_then(|i| i.get("description"))
+                .and_then(|d| d.as_str())
+                .map(|d| d.trim().to_string())
+                .unwrap_or(format!("Use these operations of the {} API.", title)),
+            name,
+            actions,
+        })
+    }
+
+    pub fn to_namespace(&self) -> Namespace {
+        Namespace::new_default(
+            self.name.to_string(),
+            self.description.to_string(),
+            self.actions
+                .iter()
+                .map(|action| Box::new(action.clone()) as Box<dyn Action>)
+                .collect(),
+            None,
+        )
+    }
+}
+
+#[cfg(all(test, feature = "openapi"))]
+mod tests {
+    use super::*;
+
+    const SPEC: &str = r#"
+openapi: 3.0.0
+info:
+  title: Petstore
+servers:
+  - url: https://{env}.example.com/v1
+    variables:
+      env:
+        default: api
+paths:
+  /pets/{petId}:
+    parameters:
+      - $ref: '#/components/parameters/PetId'
+    get:
+      operationId: getPet
+      summary: Get a pet.
+      parameters:
+        - name: fields
+          in: query
+          schema:
+            type: string
+            enum: [name, all]
+    delete:
+      summary: Delete a pet.
+  /pets:
+    post:
+      operationId: createPet
+      requestBody:
+        required: true
+        content:
+          application/json:
+            schema:
+              $ref: '#/components/schemas/Pet'
+    put:
+      operationId: uploadPet
+      requestBody:
+        content:
+          multipart/form-data: {}
+components:
+  parameters:
+    PetId:
+      name: petId
+      in: path
+      schema:
+        type: integer
+  schemas:
+    Pet:
+      type: object
+      properties:
+        name:
+          type: string
+          example: rex
+        tags:
+          type: array
+          items:
+            type: string
+"#;
+
+    fn namespace() -> OpenApiNamespace {
+        let spec: Value = serde_yaml::from_str(SPEC).unwrap();
+        OpenApiNamespace::from_spec(&spec, &OpenApiConfig::default(), None).unwrap()
+    }
+
+    #[test]
+    fn test_operations() {
+        let namespace = namespace();
+        assert_eq!(namespace.name, "Petstore");
+        // the multipart upload is not supported
+        assert_eq!(
+            namespace
+                .actions
+                .iter()
+                .map(|a| a.name.as_str())
+                .collect::<Vec<_>>(),
+            vec!["createPet", "getPet", "delete_pets_petId"]
+        );
+
+        let get = &namespace.actions[1];
+        assert_eq!(get.description, "Get a pet. (GET /pets/{petId})");
+        assert!(!get.is_destructive());
+        let attributes = get.example_attributes().unwrap();
+        assert_eq!(attributes["petId"], "integer");
+        assert_eq!(
+            attributes["fields"],
+            "string, one of: name, all (optional, can be empty)"
+        );
+
+        let create = &namespace.actions[0];
+        assert!(create.is_destructive());
+        assert_eq!(
+            create.example_payload(),
+            Some(r#"{"name":"rex","tags":["string"]}"#)
+        );
+    }
+
+    #[test]
+    fn test_validation() {
+        let namespace = namespace();
+        let get = &namespace.actions[1];
+
+        let mut attributes = HashMap::new();
+        attributes.insert("petId".to_string(), "12".to_string());
+        attributes.insert("fields".to_string(), "".to_string());
+        assert_eq!(
+            get.build_url(&attributes).unwrap().as_str(),
+            "https://api.example.com/v1/pets/12"
+        );
+
+        attributes.insert("fields".to_string(), "all".to_string());
+        assert_eq!(
+            get.build_url(&attributes).unwrap().as_str(),
+            "https://api.example.com/v1/pets/12?fields=all"
+        );
+
+        attributes.insert("fields".to_string(), "nope".to_string());
+        assert!(get.build_url(&attributes).is_err());
+
+        attributes.insert("fields".to_string(), "".to_string());
+        attributes.insert("petId".to_string(), "abc".to_string());
+        assert!(get.build_url(&attributes).is_err());
+        attributes.remove("petId");
+        assert!(get.build_url(&attributes).is_err());
+
+        let create = &namespace.actions[0];
+        assert!(create.build_body(&None).is_err());
+        assert!(create.build_body(&Some("nope".to_string())).is_err());
+        assert!(create.build_body(&Some("{}".to_string())).is_ok());
+    }
+
+    #[test]
+    fn test_swagger_base_url() {
+        let spec: Value = serde_json::from_str(
+            r#"{"swagger":"2.0","host":"api.test","basePath":"/v2","schemes":["http"],"paths":{}}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            OpenApiNamespace::base_url(&spec, &OpenApiConfig::default(), None).unwrap(),
+            "http://api.test/v2"
+        );
+
+        let spec: Value = serde_json::from_str(r#"{"servers":[{"url":"/api"}]}"#).unwrap();
+        assert!(OpenApiNamespace::base_url(&spec, &OpenApiConfig::default(), None).is_err());
+        assert_eq!(
+            OpenApiNamespace::base_url(
+                &spec,
+                &OpenApiConfig::default(),
+                Some(&Url::parse("https://test.com/docs/spec.json").unwrap())
+            )
+            .unwrap(),
+            "https://test.com/api"
+        );
+    }
+}
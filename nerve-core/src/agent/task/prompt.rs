@@ -0,0 +1,127 @@
+use std::{
+    io::{IsTerminal, Read},
+    path::PathBuf,
+};
+
+use anyhow::Result;
+
+// where to read the task prompt from when it's not provided inline
+#[derive(Debug, Clone, PartialEq)]
+pub enum PromptSource {
+    Stdin,
+    File(String),
+    Url(String),
+}
+
+impl PromptSource {
+    // "-" or "stdin" for standard input, http(s) urls or file paths (optionally prefixed by file://)
+    pub fn parse(source: &str) -> Self {
+        let source = source.trim();
+        if source == "-" || source == "stdin" {
+            Self::Stdin
+        } else if source.starts_with("http://") || source.starts_with("https://") {
+            Self::Url(source.to_string())
+        } else if let Some(path) = source.strip_prefix("file://") {
+            Self::File(path.to_string())
+        } else {
+            Self::File(source.to_string())
+        }
+    }
+
+    // returns true if the standard input is being piped
+    pub fn stdin_is_piped() -> bool {
+        !std::io::stdin().is_terminal()
+    }
+
+    // returns true if the standard input is a pipe or a file, and not for instance a socket
+    // inherited from a supervisor that would never be closed
+    #[cfg(unix)]
+    pub fn stdin_is_redirected() -> bool {
+        use std::os::{fd::AsFd, unix::fs::FileTypeExt};
+
+        std::io::stdin()
+            .as_fd()
+            .try_clone_to_owned()
+            .and_then(|fd| std::fs::File::from(fd).metadata())
+            .is_ok_and(|meta| meta.file_type().is_fifo() || meta.is_file())
+    }
+
+    #[cfg(not(unix))]
+    pub fn stdin_is_redirected() -> bool {
+        Self::stdin_is_piped()
+    }
+
+    // load the prompt, relative file paths are resolved from the base folder
+    pub async fn load(&self, base_folder: &str) -> Result<String> {
+        let prompt = match self {
+            Self::Stdin => {
+                let mut input = String::new();
+                std::io::stdin().read_to_string(&mut input)?;
+                input
+            }
+            Self::File(path) => {
+                let mut path = PathBuf::from(path);
+                if path.is_relative() && !base_folder.is_empty() {
+                    path = PathBuf::from(base_folder).join(path);
+                }
+                std::fs::read_to_string(&path)
+                    .map_err(|e| anyhow!("could not read prompt from {}: {e}", path.display()))?
+            }
+            Self::Url(url) => {
+                let response = reqwest::get(url).await?;
+                if !response.status().is_success() {
+                    return Err(anyhow!(
+                        "could not fetch prompt from {}: {}",
+                        url,
+                        response.status()
+                    ));
+                }
+                response.text().await?
+            }
+        };
+
+        let prompt = prompt.trim().to_string();
+        if prompt.is_empty() {
+            Err(anyhow!("empty prompt from {:?}", self))
+        } else {
+            Ok(prompt)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_sources() {
+        assert_eq!(PromptSource::parse("-"), PromptSource::Stdin);
+        assert_eq!(PromptSource::parse("stdin"), PromptSource::Stdin);
+        assert_eq!(
+            PromptSource::parse("https://example.com/prompt.txt"),
+            PromptSource::Url("https://example.com/prompt.txt".to_string())
+        );
+        assert_eq!(
+            PromptSource::parse("file:///tmp/prompt.txt"),
+            PromptSource::File("/tmp/prompt.txt".to_string())
+        );
+        assert_eq!(
+            PromptSource::parse("prompt.txt"),
+            PromptSource::File("prompt.txt".to_string())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_load_relative_file() {
+        let folder = std::env::temp_dir().join("nerve-prompt-test");
+        std::fs::create_dir_all(&folder).unwrap();
+        std::fs::write(folder.join("prompt.txt"), "  scan the network\n").unwrap();
+
+        let prompt = PromptSource::parse("prompt.txt")
+            .load(folder.to_str().unwrap())
+            .await
+            .unwrap();
+
+        assert_eq!(prompt, "scan the network");
+    }
+}
@@ -0,0 +1,597 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    time::Instant,
+};
+
+use anyhow::Result;
+use async_trait::async_trait;
+use duration_string::DurationString;
+use glob::glob;
+use serde::Deserialize;
+
+#[cfg(feature = "crawler")]
+use super::crawler;
+#[cfg(feature = "pgvector")]
+use super::pgvector::PgVectorStore;
+use super::{
+    chunking::{self, Splitter},
+    crawler::RagUrl,
+    documents::{self, Format},
+    index::VectorIndex,
+    pgvector::PgVectorConfig,
+    rerank::RerankConfig,
+};
+
+// a document retrieved from a rag store
+#[derive(Debug, Clone)]
+pub struct RetrievedDocument {
+    // the source document of the chunk
+    pub path: String,
+    // offset of the chunk in the source document, in characters, if known
+    pub offset: Option<usize>,
+    pub data: String,
+    pub score: f64,
+}
+
+impl RetrievedDocument {
+    // how this document is cited, path@offset
+    pub fn citation(&self) -> String {
+        match self.offset {
+            Some(offset) => format!("{}@{}", self.path, offset),
+            None => self.path.to_string(),
+        }
+    }
+}
+
+// where the embeddings are stored and searched
+#[async_trait]
+pub trait RagStore: Send + Sync {
+    // index a chunk found at offset of the document at path, returns false if it was already
+    // indexed
+    async fn add(&mut self, path: &Path, offset: usize, data: &str) -> Result<bool>;
+    async fn retrieve(&self, query: &str, top_k: usize) -> Result<Vec<RetrievedDocument>>;
+
+    // remove the chunks of the document at path that are not in chunks, if supported
+    async fn prune(&mut self, _path: &Path, _chunks: &[String]) -> Result<()> {
+        Ok(())
+    }
+}
+
+// local store persisted in the data_path folder
+pub struct LocalStore {
+    index: VectorIndex,
+    data_path: String,
+}
+
+#[async_trait]
+impl RagStore for LocalStore {
+    async fn add(&mut self, path: &Path, offset: usize, data: &str) -> Result<bool> {
+        // every chunk is saved as a file so that it can be retrieved on its own
+        let chunks_path = PathBuf::from(&self.data_path).join("chunks");
+        std::fs::create_dir_all(&chunks_path)?;
+        let id = sha256::digest(data);
+        if self.index.contains(&id) {
+            log::debug!("document with id '{}' already indexed", &id);
+            return Ok(false);
+        }
+
+        let chunk_file = chunks_path.join(format!("{}.txt", &id));
+        if !chunk_file.exists() {
+            std::fs::write(&chunk_file, data)?;
+        }
+
+        log::info!(
+            "indexing new document '{}' ({} bytes) ...",
+            path.display(),
+            data.len()
+        );
+        self.index
+            .add(
+                &id,
+                &path.display().to_string(),
+                Some(offset),
+                &chunk_file.display().to_string(),
+                data,
+            )
+            .await?;
+
+        Ok(true)
+    }
+
+    async fn retrieve(&self, query: &str, top_k: usize) -> Result<Vec<RetrievedDocument>> {
+        let mut docs = vec![];
+        for (chunk, score) in self.index.search(query, top_k).await? {
+            docs.push(RetrievedDocument {
+                path: chunk.path.to_string(),
+                offset: chunk.offset,
+                data: std::fs::read_to_string(&chunk.file)?,
+                score,
+            });
+        }
+        Ok(docs)
+    }
+
+    async fn prune(&mut self, path: &Path, chunks: &[String]) -> Result<()> {
+        let keep: Vec<String> = chunks
+            .iter()
+            .map(|chunk| sha256::digest(chunk.as_str()))
+            .collect();
+        let removed = self.index.prune(&path.display().to_string(), &keep)?;
+        for chunk in &removed {
+            let _ = std::fs::remove_file(&chunk.file);
+        }
+        if !removed.is_empty() {
+            log::info!(
+                "removed {} outdated chunks of '{}'",
+                removed.len(),
+                path.display()
+            );
+        }
+        Ok(())
+    }
+}
+
+fn default_recursive() -> bool {
+    true
+}
+
+// a set of documents to import in the rag store
+#[derive(Deserialize, Debug, Clone)]
+pub struct RagSource {
+    // a file, a folder or a glob pattern, relative to the tasklet folder
+    pub path: String,
+    // if path is a folder, also import documents in its subfolders
+    #[serde(default = "default_recursive")]
+    pub recursive: bool,
+    // only import files with these extensions
+    pub extensions: Option<Vec<String>>,
+    // override the global chunking options for this source
+    pub chunk_size: Option<usize>,
+    pub chunk_overlap: Option<usize>,
+    pub splitter: Option<Splitter>,
+    // metadata added as a header to every chunk of this source
+    pub metadata: Option<BTreeMap<String, String>>,
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct RagConfig {
+    // legacy single source folder
+    pub source_path: Option<String>,
+    // rag persistent data path, not needed with pgvector
+    #[serde(default)]
+    pub data_path: String,
+    // store the embeddings in postgres instead of data_path
+    pub pgvector: Option<PgVectorConfig>,
+    pub chunk_size: Option<usize>,
+    pub chunk_overlap: Option<usize>,
+    // how documents are split in chunks of chunk_size
+    pub splitter: Option<Splitter>,
+    pub sources: Option<Vec<RagSource>>,
+    // websites to crawl
+    pub urls: Option<Vec<RagUrl>>,
+    // rerank the retrieved documents before returning them
+    pub rerank: Option<RerankConfig>,
+    // check the sources for new or changed documents every so often, like 30s
+    pub watch: Option<String>,
+}
+
+fn validate_chunking(
+    chunk_size: Option<usize>,
+    chunk_overlap: Option<usize>,
+    splitter: Option<Splitter>,
+) -> Result<()> {
+    if chunk_size.is_none() && splitter.is_some_and(|s| s != Splitter::Characters) {
+        return Err(anyhow!("rag splitter requires chunk_size"));
+    }
+    match (chunk_size, chunk_overlap) {
+        (Some(0), _) => Err(anyhow!("rag chunk_size can't be 0")),
+        (None, Some(_)) => Err(anyhow!("rag chunk_overlap requires chunk_size")),
+        (Some(size), Some(overlap)) if overlap >= size => Err(anyhow!(
+            "rag chunk_overlap ({}) must be smaller than chunk_size ({})",
+            overlap,
+            size
+        )),
+        _ => Ok(()),
+    }
+}
+
+// split text in chunks of chunk_size characters, each one overlapping the previous by chunk_overlap,
+// and return them with their offset
+fn chunks(
+    text: &str,
+    chunk_size: Option<usize>,
+    chunk_overlap: Option<usize>,
+) -> Vec<(usize, String)> {
+    let chunk_size = if let Some(size) = chunk_size {
+        size
+    } else {
+        return vec![(0, text.to_string())];
+    };
+
+    let chars: Vec<char> = text.chars().collect();
+    let step = chunk_size - chunk_overlap.unwrap_or(0);
+    let mut chunks = vec![];
+    let mut start = 0;
+
+    while start < chars.len() {
+        let end = (start + chunk_size).min(chars.len());
+        chunks.push((start, chars[start..end].iter().collect()));
+        if end == chars.len() {
+            break;
+        }
+        start += step;
+    }
+
+    chunks
+}
+
+impl RagSource {
+    // pages are imported like files, using the options of the website
+    fn from_url(url: &RagUrl) -> Self {
+        Self {
+            path: url.url.to_string(),
+            recursive: false,
+            extensions: None,
+            chunk_size: url.chunk_size,
+            chunk_overlap: url.chunk_overlap,
+            splitter: url.splitter,
+            metadata: url.metadata.clone(),
+        }
+    }
+
+    // chunking options of this source, falling back to the global ones
+    fn chunking(&self, config: &RagConfig) -> (Option<usize>, Option<usize>, Option<Splitter>) {
+        let splitter = self.splitter.or(config.splitter);
+        if self.chunk_size.is_some() {
+            (self.chunk_size, self.chunk_overlap, splitter)
+        } else {
+            (
+                config.chunk_size,
+                self.chunk_overlap.or(config.chunk_overlap),
+                splitter,
+            )
+        }
+    }
+
+    pub fn resolve_path(&mut self, folder: &str) {
+        if Path::new(&self.path).is_relative() {
+            self.path = PathBuf::from(folder)
+                .join(self.path.trim_start_matches("./"))
+                .display()
+                .to_string();
+        }
+    }
+
+    fn files(&self) -> Result<Vec<PathBuf>> {
+        let path = PathBuf::from(&self.path);
+        let expr = if self.path.contains(['*', '?', '[']) {
+            self.path.to_string()
+        } else if path.is_dir() {
+            format!(
+                "{}/{}",
+                self.path.trim_end_matches('/'),
+                if self.recursive { "**/*" } else { "*" }
+            )
+        } else {
+            return Ok(vec![path]);
+        };
+
+        let extensions: Option<Vec<String>> = self.extensions.as_ref().map(|exts| {
+            exts.iter()
+                .map(|e| e.trim_start_matches('.').to_lowercase())
+                .collect()
+        });
+
+        let mut files = vec![];
+        for path in glob(&expr)?.flatten() {
+            if !path.is_file() {
+                continue;
+            }
+            if let Some(extensions) = &extensions {
+                let ext = path
+                    .extension()
+                    .map(|e| e.to_string_lossy().to_lowercase())
+                    .unwrap_or_default();
+                if !extensions.contains(&ext) {
+                    continue;
+                }
+            }
+            files.push(path);
+        }
+
+        files.sort();
+
+        Ok(files)
+    }
+
+    fn header(&self, file: &Path) -> String {
+        let mut header = format!("source: {}\n", file.display());
+        for (key, value) in self.metadata.iter().flatten() {
+            header += &format!("{}: {}\n", key, value);
+        }
+        header + "\n"
+    }
+}
+
+impl RagConfig {
+    pub fn validate(&self) -> Result<()> {
+        validate_chunking(self.chunk_size, self.chunk_overlap, self.splitter)?;
+        if self.source_path.is_none() && self.sources.is_none() && self.urls.is_none() {
+            return Err(anyhow!("rag requires either source_path, sources or urls"));
+        }
+        if let Some(pgvector) = &self.pgvector {
+            pgvector.validate()?;
+        } else if self.data_path.is_empty() {
+            return Err(anyhow!("rag requires data_path"));
+        }
+        for source in self.sources.iter().flatten() {
+            let (chunk_size, chunk_overlap, splitter) = source.chunking(self);
+            validate_chunking(chunk_size, chunk_overlap, splitter)?;
+        }
+        for url in self.urls.iter().flatten() {
+            url.validate()?;
+            let (chunk_size, chunk_overlap, splitter) = RagSource::from_url(url).chunking(self);
+            validate_chunking(chunk_size, chunk_overlap, splitter)?;
+        }
+        if let Some(rerank) = &self.rerank {
+            rerank.validate()?;
+        }
+        if let Some(watch) = &self.watch {
+            watch
+                .parse::<DurationString>()
+                .map_err(|e| anyhow!("invalid rag watch interval '{}': {}", watch, e))?;
+        }
+        Ok(())
+    }
+
+    pub async fn create_store(
+        &self,
+        embedder: Box<dyn mini_rag::Embedder>,
+    ) -> Result<Box<dyn RagStore>> {
+        Ok(match &self.pgvector {
+            #[cfg(feature = "pgvector")]
+            Some(config) => Box::new(PgVectorStore::new(config, embedder).await?),
+            #[cfg(not(feature = "pgvector"))]
+            Some(_) => return Err(anyhow!("nerve was built without the pgvector feature")),
+            None => Box::new(LocalStore {
+                index: VectorIndex::open(&self.data_path, embedder).await?,
+                data_path: self.data_path.to_string(),
+            }),
+        })
+    }
+
+    // the sources to import, including the legacy source_path
+    fn all_sources(&self) -> Vec<RagSource> {
+        let mut sources = self.sources.clone().unwrap_or_default();
+        if let Some(source_path) = &self.source_path {
+            sources.push(RagSource {
+                path: source_path.to_string(),
+                recursive: true,
+                extensions: None,
+                chunk_size: None,
+                chunk_overlap: None,
+                splitter: None,
+                metadata: None,
+            });
+        }
+        sources
+    }
+
+    // the files that would be imported from the configured sources
+    pub fn files(&self) -> Result<Vec<PathBuf>> {
+        let mut files = vec![];
+        for source in &self.all_sources() {
+            files.extend(source.files()?);
+        }
+        Ok(files)
+    }
+
+    pub fn watch_interval(&self) -> Option<std::time::Duration> {
+        self.watch
+            .as_ref()
+            .and_then(|watch| watch.parse::<DurationString>().ok())
+            .map(|watch| watch.into())
+    }
+
+    // import any new document from the configured sources in the store
+    pub async fn import(&self, store: &mut Box<dyn RagStore>) -> Result<()> {
+        self.import_files(store, None).await?;
+        #[cfg(feature = "crawler")]
+        self.import_urls(store).await?;
+        Ok(())
+    }
+
+    // crawl the configured websites and import their pages
+    #[cfg(feature = "crawler")]
+    pub async fn import_urls(&self, store: &mut Box<dyn RagStore>) -> Result<()> {
+        for url in self.urls.iter().flatten() {
+            let start = Instant::now();
+            let pages = crawler::crawl(url).await?;
+            let source = RagSource::from_url(url);
+            let (_, _, splitter) = source.chunking(self);
+            let mut new = 0;
+
+            for page in &pages {
+                let mut source = source.clone();
+                if let Some(title) = &page.title {
+                    source
+                        .metadata
+                        .get_or_insert_with(BTreeMap::new)
+                        .insert("title".to_string(), title.to_string());
+                }
+
+                let path = Path::new(&page.url);
+                let splitter = match splitter.unwrap_or_default() {
+                    Splitter::Auto if page.html => Splitter::Sentences,
+                    splitter => splitter.resolve(path),
+                };
+                new += self
+                    .import_text(store, &source, path, splitter, &page.text)
+                    .await?;
+            }
+
+            log::info!(
+                "{} pages crawled from {}, {} new documents indexed in {:?}",
+                pages.len(),
+                url.url,
+                new,
+                start.elapsed()
+            );
+        }
+
+        Ok(())
+    }
+
+    // import the documents of the sources, only the given files if set
+    pub async fn import_files(
+        &self,
+        store: &mut Box<dyn RagStore>,
+        only: Option<&[PathBuf]>,
+    ) -> Result<()> {
+        let sources = self.all_sources();
+
+        let start = Instant::now();
+        let mut new = 0;
+
+        for source in &sources {
+            for file in source.files()? {
+                if only.is_some_and(|only| !only.contains(&file)) {
+                    continue;
+                }
+                new += self.import_file(store, source, &file).await?;
+            }
+        }
+
+        if new > 0 {
+            log::info!("{} new documents indexed in {:?}\n", new, start.elapsed());
+        }
+
+        Ok(())
+    }
+
+    // import the chunks of a file, returns how many of them are new
+    async fn import_file(
+        &self,
+        store: &mut Box<dyn RagStore>,
+        source: &RagSource,
+        file: &Path,
+    ) -> Result<usize> {
+        let data = match documents::read(file) {
+            Ok(data) => data,
+            Err(err) => {
+                log::warn!("{} {}", file.display(), err);
+                return Ok(0);
+            }
+        };
+
+        let (_, _, splitter) = source.chunking(self);
+        let splitter = match splitter.unwrap_or_default() {
+            // converted documents are plain text
+            Splitter::Auto if Format::from_path(file) != Format::Text => Splitter::Sentences,
+            splitter => splitter.resolve(file),
+        };
+        self.import_text(store, source, file, splitter, &data).await
+    }
+
+    // import the chunks of the document at path, returns how many of them are new, failing
+    // if the store can't be written
+    async fn import_text(
+        &self,
+        store: &mut Box<dyn RagStore>,
+        source: &RagSource,
+        file: &Path,
+        splitter: Splitter,
+        data: &str,
+    ) -> Result<usize> {
+        let (chunk_size, chunk_overlap, _) = source.chunking(self);
+        let header = source.header(file);
+        let chunks: Vec<(usize, String)> = match (splitter, chunk_size) {
+            (Splitter::Characters, _) | (_, None) => chunks(data, chunk_size, chunk_overlap),
+            (splitter, Some(size)) => {
+                chunking::split(data, splitter, size, chunk_overlap.unwrap_or(0))
+            }
+        }
+        .into_iter()
+        .map(|(offset, chunk)| (offset, format!("{}{}", header, chunk)))
+        .collect();
+
+        let mut new = 0;
+        for (offset, chunk) in &chunks {
+            if store
+                .add(file, *offset, chunk)
+                .await
+                .map_err(|e| anyhow!("storing {}: {}", file.display(), e))?
+            {
+                new += 1;
+            }
+        }
+
+        // forget the chunks of previous versions of the file
+        let chunks: Vec<String> = chunks.into_iter().map(|(_, chunk)| chunk).collect();
+        store
+            .prune(file, &chunks)
+            .await
+            .map_err(|e| anyhow!("pruning {}: {}", file.display(), e))?;
+
+        Ok(new)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chunks_with_overlap() {
+        let text = |chunks: Vec<(usize, String)>| -> Vec<String> {
+            chunks.into_iter().map(|(_, chunk)| chunk).collect()
+        };
+        assert_eq!(
+            text(chunks("abcdefghij", Some(4), Some(2))),
+            vec!["abcd", "cdef", "efgh", "ghij"]
+        );
+        assert_eq!(
+            chunks("abcdefghij", Some(4), None),
+            vec![
+                (0, "abcd".to_string()),
+                (4, "efgh".to_string()),
+                (8, "ij".to_string())
+            ]
+        );
+        assert_eq!(text(chunks("abc", None, None)), vec!["abc"]);
+    }
+
+    #[test]
+    fn test_invalid_overlap_is_error() {
+        assert!(validate_chunking(Some(10), Some(10), None).is_err());
+        assert!(validate_chunking(None, Some(2), None).is_err());
+        assert!(validate_chunking(Some(10), Some(2), None).is_ok());
+        assert!(validate_chunking(None, None, Some(Splitter::Markdown)).is_err());
+    }
+
+    #[test]
+    fn test_source_files_filters() {
+        let folder = std::env::temp_dir().join("nerve-rag-test");
+        std::fs::create_dir_all(folder.join("sub")).unwrap();
+        std::fs::write(folder.join("a.md"), "a").unwrap();
+        std::fs::write(folder.join("b.txt"), "b").unwrap();
+        std::fs::write(folder.join("sub/c.md"), "c").unwrap();
+
+        let source: RagSource =
+            serde_yaml::from_str(&format!("path: {}\nextensions: [.md]", folder.display()))
+                .unwrap();
+        assert_eq!(source.files().unwrap().len(), 2);
+
+        let source: RagSource = serde_yaml::from_str(&format!(
+            "path: {}\nrecursive: false\nextensions: [md]",
+            folder.display()
+        ))
+        .unwrap();
+        assert_eq!(source.files().unwrap().len(), 1);
+
+        let source: RagSource =
+            serde_yaml::from_str(&format!("path: {}/**/*.txt", folder.display())).unwrap();
+        assert_eq!(source.files().unwrap().len(), 1);
+    }
+}
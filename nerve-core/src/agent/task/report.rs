@@ -0,0 +1,138 @@
+use anyhow::Result;
+use serde::Deserialize;
+
+use crate::prompt;
+
+// a section of the report, with an optional description of what goes in it
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum ReportSection {
+    Name(String),
+    Described { name: String, description: String },
+}
+
+impl ReportSection {
+    fn described(name: &str, description: &str) -> Self {
+        Self::Described {
+            name: name.to_string(),
+            description: description.to_string(),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        match self {
+            Self::Name(name) => name,
+            Self::Described { name, .. } => name,
+        }
+    }
+}
+
+fn default_sections() -> Vec<ReportSection> {
+    vec![
+        ReportSection::described(
+            "Executive Summary",
+            "a short overview of the task and of its most important results",
+        ),
+        ReportSection::described(
+            "Findings",
+            "every finding as a subsection with its severity, a description, the evidence (the commands that were executed and the relevant parts of their output) and the remediation",
+        ),
+        ReportSection::described(
+            "Recommendations",
+            "the actions to take, ordered by priority",
+        ),
+        ReportSection::described(
+            "Activity Log",
+            "what was done, in chronological order",
+        ),
+    ]
+}
+
+// how the model compiles the report of the run once it ends
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct ReportTemplate {
+    pub title: Option<String>,
+    #[serde(default = "default_sections")]
+    pub sections: Vec<ReportSection>,
+    // additional guidance for the model, like the audience or the severity scale
+    pub instructions: Option<String>,
+    // write <path>.md and <path>.html, unless given on the command line
+    pub path: Option<String>,
+}
+
+impl Default for ReportTemplate {
+    fn default() -> Self {
+        Self {
+            title: None,
+            sections: default_sections(),
+            instructions: None,
+            path: None,
+        }
+    }
+}
+
+impl ReportTemplate {
+    pub fn validate(&self) -> Result<()> {
+        if self.sections.is_empty() {
+            return Err(anyhow!("the report must have at least one section"));
+        }
+        if self.sections.iter().any(|s| s.name().trim().is_empty()) {
+            return Err(anyhow!("report sections must have a name"));
+        }
+        Ok(())
+    }
+
+    pub fn to_system_prompt(&self) -> String {
+        let mut prompt = prompt!("report.prompt").trim().to_string();
+
+        prompt += "\n\n";
+        match &self.title {
+            Some(title) => prompt += &format!("Start with the title: # {}\n", title),
+            None => prompt += "Start with a title describing the task, as a level 1 heading.\n",
+        }
+
+        prompt +=
+            "\nThe report must have these sections, in this order, each as a level 2 heading:\n\n";
+        for section in &self.sections {
+            prompt += &match section {
+                ReportSection::Name(name) => format!("- {}\n", name),
+                ReportSection::Described { name, description } => {
+                    format!("- {}: {}\n", name, description)
+                }
+            };
+        }
+
+        if let Some(instructions) = &self.instructions {
+            prompt += &format!("\n{}\n", instructions.trim());
+        }
+
+        prompt
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_template() {
+        let template: ReportTemplate = serde_yaml::from_str(
+            "title: Assessment of 10.0.0.0/24\nsections:\n  - Summary\n  - name: Findings\n    description: one subsection per host\ninstructions: Use the CVSS scale.",
+        )
+        .unwrap();
+        template.validate().unwrap();
+        assert_eq!(template.sections.len(), 2);
+
+        let prompt = template.to_system_prompt();
+        assert!(prompt.contains("# Assessment of 10.0.0.0/24"));
+        assert!(prompt.contains("- Summary\n- Findings: one subsection per host\n"));
+        assert!(prompt.ends_with("Use the CVSS scale.\n"));
+
+        let default: ReportTemplate = serde_yaml::from_str("path: out").unwrap();
+        assert_eq!(default.sections, ReportTemplate::default().sections);
+        assert!(serde_yaml::from_str::<ReportTemplate>("sections: []")
+            .unwrap()
+            .validate()
+            .is_err());
+    }
+}
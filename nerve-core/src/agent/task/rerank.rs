@@ -0,0 +1,272 @@
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use serde::Deserialize;
+use serde_json::json;
+
+use super::{rag::RetrievedDocument, variables::interpolate_variables};
+use crate::agent::secrets;
+
+const DEFAULT_CANDIDATES: usize = 10;
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+fn default_candidates() -> usize {
+    DEFAULT_CANDIDATES
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum RerankProvider {
+    // cohere rerank api, also implemented by many self hosted servers
+    Cohere,
+    Jina,
+    // local cross-encoder served by huggingface text-embeddings-inference
+    Tei,
+}
+
+impl RerankProvider {
+    fn default_url(&self) -> Option<&str> {
+        match self {
+            RerankProvider::Cohere => Some("https://api.cohere.com/v2/rerank"),
+            RerankProvider::Jina => Some("https://api.jina.ai/v1/rerank"),
+            RerankProvider::Tei => None,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct RerankConfig {
+    pub provider: RerankProvider,
+    // rerank endpoint, required by tei
+    pub url: Option<String>,
+    pub model: Option<String>,
+    // can use variables like $COHERE_API_KEY
+    pub api_key: Option<String>,
+    // how many documents are retrieved from the store and reranked
+    #[serde(default = "default_candidates")]
+    pub candidates: usize,
+}
+
+impl RerankConfig {
+    pub fn validate(&self) -> Result<()> {
+        if self.candidates == 0 {
+            return Err(anyhow!("rerank candidates can't be 0"));
+        }
+        if self.url.is_none() && self.provider.default_url().is_none() {
+            return Err(anyhow!("rerank provider {:?} requires url", self.provider));
+        }
+        if self.model.is_none() && self.provider != RerankProvider::Tei {
+            return Err(anyhow!(
+                "rerank provider {:?} requires model",
+                self.provider
+            ));
+        }
+        Ok(())
+    }
+}
+
+// index and relevance of a document in the list sent to the reranker
+#[derive(Deserialize, Debug)]
+struct Ranked {
+    index: usize,
+    #[serde(alias = "relevance_score")]
+    score: f64,
+}
+
+#[derive(Deserialize, Debug)]
+struct RankedResults {
+    results: Vec<Ranked>,
+}
+
+fn parse_response(provider: RerankProvider, body: &str) -> Result<Vec<Ranked>> {
+    Ok(match provider {
+        RerankProvider::Tei => serde_json::from_str(body)?,
+        RerankProvider::Cohere | RerankProvider::Jina => {
+            serde_json::from_str::<RankedResults>(body)?.results
+        }
+    })
+}
+
+// sort the documents by relevance and keep the best top_k of them
+fn apply(
+    docs: Vec<RetrievedDocument>,
+    ranked: Vec<Ranked>,
+    top_k: usize,
+) -> Vec<RetrievedDocument> {
+    let mut ranked: Vec<Ranked> = ranked
+        .into_iter()
+        .filter(|r| r.index < docs.len())
+        .collect();
+    ranked.sort_by(|a, b| b.score.total_cmp(&a.score));
+
+    ranked
+        .into_iter()
+        .take(top_k)
+        .map(|r| RetrievedDocument {
+            score: r.score,
+            ..docs[r.index].clone()
+        })
+        .collect()
+}
+
+pub struct Reranker {
+    provider: RerankProvider,
+    url: String,
+    model: Option<String>,
+    api_key: Option<String>,
+    candidates: usize,
+    client: reqwest::Client,
+}
+
+impl Reranker {
+    pub fn new(config: &RerankConfig) -> Result<Self> {
+        let url = match &config.url {
+            Some(url) => interpolate_variables(url)?,
+            None => config.provider.default_url().unwrap().to_string(),
+        };
+        let api_key = match &config.api_key {
+            Some(key) => {
+                let key = interpolate_variables(key)?;
+                secrets::register(&key);
+                Some(key)
+            }
+            None => None,
+        };
+
+        Ok(Self {
+            provider: config.provider,
+            url,
+            model: config.model.clone(),
+            api_key,
+            candidates: config.candidates,
+            client: reqwest::Client::builder().timeout(TIMEOUT).build()?,
+        })
+    }
+
+    // how many documents to retrieve in order to return top_k of them
+    pub fn candidates(&self, top_k: usize) -> usize {
+        self.candidates.max(top_k)
+    }
+
+    pub async fn rerank(
+        &self,
+        query: &str,
+        docs: Vec<RetrievedDocument>,
+        top_k: usize,
+    ) -> Result<Vec<RetrievedDocument>> {
+        if docs.is_empty() {
+            return Ok(docs);
+        }
+
+        let start = Instant::now();
+        let texts: Vec<&str> = docs.iter().map(|doc| doc.data.as_str()).collect();
+        let body = match self.provider {
+            RerankProvider::Tei => json!({
+                "query": query,
+                "texts": texts,
+            }),
+            RerankProvider::Cohere | RerankProvider::Jina => json!({
+                "model": self.model,
+                "query": query,
+                "documents": texts,
+                "top_n": top_k,
+            }),
+        };
+
+        let mut request = self.client.post(&self.url).json(&body);
+        if let Some(key) = &self.api_key {
+            request = request.bearer_auth(key);
+        }
+
+        let response = request.send().await?;
+        let status = response.status();
+        let text = response.text().await?;
+        if !status.is_success() {
+            return Err(anyhow!(
+                "rerank server returned {}: {}",
+                status,
+                text.trim()
+            ));
+        }
+
+        let ranked = parse_response(self.provider, &text)?;
+        log::debug!("reranked {} documents in {:?}", docs.len(), start.elapsed());
+
+        Ok(apply(docs, ranked, top_k))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn doc(data: &str, score: f64) -> RetrievedDocument {
+        RetrievedDocument {
+            path: format!("{}.txt", data),
+            offset: None,
+            data: data.to_string(),
+            score,
+        }
+    }
+
+    #[test]
+    fn test_parse_response() {
+        let ranked = parse_response(
+            RerankProvider::Cohere,
+            r#"{"id":"x","results":[{"index":1,"relevance_score":0.9},{"index":0,"relevance_score":0.1}]}"#,
+        )
+        .unwrap();
+        assert_eq!(ranked.len(), 2);
+        assert_eq!(ranked[0].index, 1);
+
+        let ranked = parse_response(
+            RerankProvider::Tei,
+            r#"[{"index":0,"score":0.2},{"index":2,"score":0.7}]"#,
+        )
+        .unwrap();
+        assert_eq!(ranked[1].index, 2);
+        assert_eq!(ranked[1].score, 0.7);
+    }
+
+    #[test]
+    fn test_apply() {
+        let docs = vec![doc("a", 0.9), doc("b", 0.8), doc("c", 0.7)];
+        let ranked = vec![
+            Ranked {
+                index: 0,
+                score: 0.1,
+            },
+            Ranked {
+                index: 2,
+                score: 0.95,
+            },
+            Ranked {
+                index: 1,
+                score: 0.5,
+            },
+            Ranked {
+                index: 7,
+                score: 1.0,
+            },
+        ];
+        let docs = apply(docs, ranked, 2);
+        assert_eq!(docs.len(), 2);
+        assert_eq!(docs[0].data, "c");
+        assert_eq!(docs[0].score, 0.95);
+        assert_eq!(docs[1].data, "b");
+    }
+
+    #[test]
+    fn test_validate() {
+        let config: RerankConfig = serde_yaml::from_str("provider: tei").unwrap();
+        assert!(config.validate().is_err());
+
+        let config: RerankConfig =
+            serde_yaml::from_str("provider: tei\nurl: http://localhost:8080/rerank").unwrap();
+        assert_eq!(config.candidates, DEFAULT_CANDIDATES);
+        assert!(config.validate().is_ok());
+
+        let config: RerankConfig = serde_yaml::from_str("provider: cohere").unwrap();
+        assert!(config.validate().is_err());
+    }
+}
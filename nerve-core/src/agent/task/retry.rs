@@ -0,0 +1,106 @@
+use std::time::Duration;
+
+use anyhow::Result;
+use duration_string::DurationString;
+use serde::Deserialize;
+
+// an error that might not happen again if the action is retried, like a network failure
+#[derive(Debug)]
+pub struct TransientError(pub String);
+
+impl std::fmt::Display for TransientError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for TransientError {}
+
+pub fn transient(message: impl Into<String>) -> anyhow::Error {
+    anyhow::Error::new(TransientError(message.into()))
+}
+
+fn default_backoff() -> String {
+    "1s".to_string()
+}
+
+fn default_multiplier() -> f64 {
+    2.0
+}
+
+// how many times and how often an action is retried before its error is reported to the model
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct RetryPolicy {
+    // maximum number of retries after the first attempt
+    pub attempts: usize,
+    // delay before the first retry
+    #[serde(default = "default_backoff")]
+    pub backoff: String,
+    // factor applied to the delay at every retry
+    #[serde(default = "default_multiplier")]
+    pub multiplier: f64,
+    // also retry on errors that are not known to be transient
+    #[serde(default)]
+    pub any_error: bool,
+}
+
+impl RetryPolicy {
+    pub fn new(attempts: usize, backoff: &str) -> Self {
+        Self {
+            attempts,
+            backoff: backoff.to_string(),
+            multiplier: default_multiplier(),
+            any_error: false,
+        }
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        self.backoff
+            .parse::<DurationString>()
+            .map_err(|e| anyhow!("invalid retry backoff '{}': {}", self.backoff, e))?;
+        if self.multiplier < 1.0 {
+            return Err(anyhow!("retry multiplier can't be less than 1"));
+        }
+        Ok(())
+    }
+
+    // delay before the given retry, starting from 1
+    pub fn delay(&self, retry: usize) -> Duration {
+        let base: Duration = self
+            .backoff
+            .parse::<DurationString>()
+            .map(|d| d.into())
+            .unwrap_or(Duration::from_secs(1));
+
+        base.mul_f64(self.multiplier.powi(retry.saturating_sub(1) as i32))
+    }
+
+    // semantic errors (wrong arguments, missing files, etc) are reported to the model right away
+    pub fn should_retry(&self, error: &anyhow::Error) -> bool {
+        self.any_error || error.downcast_ref::<TransientError>().is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_delay() {
+        let policy = RetryPolicy::new(3, "500ms");
+        assert_eq!(policy.delay(1), Duration::from_millis(500));
+        assert_eq!(policy.delay(2), Duration::from_secs(1));
+        assert_eq!(policy.delay(3), Duration::from_secs(2));
+    }
+
+    #[test]
+    fn test_should_retry() {
+        let policy = RetryPolicy::new(3, "1s");
+        assert!(policy.should_retry(&transient("502 Bad Gateway")));
+        assert!(!policy.should_retry(&anyhow!("404 Not Found")));
+
+        let policy: RetryPolicy = serde_yaml::from_str("attempts: 2\nany_error: true").unwrap();
+        assert!(policy.should_retry(&anyhow!("exit code 1")));
+        assert_eq!(policy.backoff, "1s");
+    }
+}
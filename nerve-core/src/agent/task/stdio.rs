@@ -0,0 +1,346 @@
+use std::{collections::HashMap, path::PathBuf, process::Stdio, sync::Arc, time::Duration};
+
+use anyhow::Result;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader, Lines},
+    process::{Child, ChildStdin, ChildStdout, Command},
+    sync::Mutex,
+};
+
+use crate::agent::{
+    namespaces::{Action, Namespace},
+    secrets,
+    state::SharedState,
+};
+
+// how long to wait for the process to register its actions
+const REGISTER_TIMEOUT: Duration = Duration::from_secs(10);
+
+// a long lived process serving a namespace of actions, it communicates with one json message
+// per line over its stdin and stdout
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct StdioPlugin {
+    // namespace name
+    pub name: String,
+    // command line of the process
+    pub command: String,
+    // environment variables and working directory of the process, relative to the tasklet folder
+    pub env: Option<HashMap<String, String>>,
+    pub cwd: Option<String>,
+}
+
+// an action as registered by the plugin
+#[derive(Deserialize, Serialize, Debug, Clone, Default, PartialEq)]
+pub struct ActionDescriptor {
+    pub name: String,
+    pub description: String,
+    pub example_payload: Option<String>,
+    pub example_attributes: Option<HashMap<String, String>>,
+    #[serde(default)]
+    pub requires_confirmation: bool,
+    #[serde(default)]
+    pub complete_task: bool,
+}
+
+// messages sent by nerve to the plugin
+#[derive(Serialize, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Request<'a> {
+    Invoke {
+        id: u64,
+        action: &'a str,
+        attributes: &'a Option<HashMap<String, String>>,
+        payload: &'a Option<String>,
+    },
+}
+
+// messages sent by the plugin to nerve
+#[derive(Deserialize, Debug, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Response {
+    Register {
+        description: Option<String>,
+        actions: Vec<ActionDescriptor>,
+    },
+    Result {
+        id: u64,
+        output: Option<String>,
+    },
+    Error {
+        id: u64,
+        error: String,
+    },
+}
+
+#[derive(Debug)]
+struct Process {
+    name: String,
+    // kept so that the process is terminated when nerve exits
+    _child: Child,
+    stdin: ChildStdin,
+    stdout: Lines<BufReader<ChildStdout>>,
+    next_id: u64,
+}
+
+impl Process {
+    async fn read(&mut self) -> Result<Response> {
+        loop {
+            let line = self
+                .stdout
+                .next_line()
+                .await?
+                .ok_or_else(|| anyhow!("plugin {} exited", self.name))?;
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            match serde_json::from_str(line) {
+                Ok(response) => return Ok(response),
+                Err(err) => log::warn!("invalid message from plugin {}: {}", self.name, err),
+            }
+        }
+    }
+
+    async fn invoke(
+        &mut self,
+        action: &str,
+        attributes: &Option<HashMap<String, String>>,
+        payload: &Option<String>,
+    ) -> Result<Option<String>> {
+        self.next_id += 1;
+        let id = self.next_id;
+
+        let mut request = serde_json::to_string(&Request::Invoke {
+            id,
+            action,
+            attributes,
+            payload,
+        })?;
+        request.push('\n');
+        self.stdin.write_all(request.as_bytes()).await?;
+        self.stdin.flush().await?;
+
+        loop {
+            // responses to invocations that timed out are skipped
+            match self.read().await? {
+                Response::Result {
+                    id: resp_id,
+                    output,
+                } if resp_id == id => return Ok(output),
+                Response::Error { id: resp_id, error } if resp_id == id => {
+                    return Err(anyhow!(error))
+                }
+                other => log::debug!("ignoring message from plugin {}: {:?}", self.name, other),
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct StdioAction {
+    descriptor: ActionDescriptor,
+    process: Arc<Mutex<Process>>,
+}
+
+#[async_trait]
+impl Action for StdioAction {
+    fn name(&self) -> &str {
+        &self.descriptor.name
+    }
+
+    fn description(&self) -> &str {
+        &self.descriptor.description
+    }
+
+    fn example_payload(&self) -> Option<&str> {
+        self.descriptor.example_payload.as_deref()
+    }
+
+    fn example_attributes(&self) -> Option<HashMap<String, String>> {
+        self.descriptor.example_attributes.clone()
+    }
+
+    fn requires_user_confirmation(&self) -> bool {
+        self.descriptor.requires_confirmation
+    }
+
+    fn complete_task(&self) -> bool {
+        self.descriptor.complete_task
+    }
+
+    async fn run(
+        &self,
+        _: SharedState,
+        attributes: Option<HashMap<String, String>>,
+        payload: Option<String>,
+    ) -> Result<Option<String>> {
+        self.process
+            .lock()
+            .await
+            .invoke(&self.descriptor.name, &attributes, &payload)
+            .await
+    }
+}
+
+// a running plugin and the actions it registered
+#[derive(Debug, Clone)]
+pub struct StdioNamespace {
+    name: String,
+    description: String,
+    actions: Vec<StdioAction>,
+}
+
+impl StdioNamespace {
+    pub fn to_namespace(&self) -> Namespace {
+        Namespace::new_default(
+            self.name.to_string(),
+            self.description.to_string(),
+            self.actions
+                .iter()
+                .map(|action| Box::new(action.clone()) as Box<dyn Action>)
+                .collect(),
+            None,
+        )
+    }
+}
+
+impl StdioPlugin {
+    // start the process and wait for it to register its actions
+    pub async fn start(&self, folder: &str) -> Result<StdioNamespace> {
+        let parts: Vec<&str> = self.command.split_whitespace().collect();
+        if parts.is_empty() {
+            return Err(anyhow!("plugin {} has no command", self.name));
+        }
+
+        let mut cmd = Command::new(parts[0]);
+        secrets::scrub_env(&mut cmd);
+        cmd.args(&parts[1..])
+            .current_dir(match &self.cwd {
+                Some(cwd) => PathBuf::from(folder).join(cwd),
+                None => PathBuf::from(folder),
+            })
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true);
+        for (key, value) in self.env.iter().flatten() {
+            cmd.env(key, value);
+        }
+
+        let mut child = cmd
+            .spawn()
+            .map_err(|e| anyhow!("could not start plugin {}: {}", self.name, e))?;
+
+        // the plugin can use stderr for its own logging
+        let name = self.name.to_string();
+        let mut stderr = BufReader::new(child.stderr.take().unwrap()).lines();
+        tokio::spawn(async move {
+            while let Ok(Some(line)) = stderr.next_line().await {
+                log::debug!("[{}] {}", name, line);
+            }
+        });
+
+        let mut process = Process {
+            name: self.name.to_string(),
+            stdin: child.stdin.take().unwrap(),
+            stdout: BufReader::new(child.stdout.take().unwrap()).lines(),
+            _child: child,
+            next_id: 0,
+        };
+
+        let (description, actions) = match tokio::time::timeout(REGISTER_TIMEOUT, process.read())
+            .await
+            .map_err(|_| anyhow!("plugin {} did not register its actions", self.name))??
+        {
+            Response::Register {
+                description,
+                actions,
+            } => (description.unwrap_or_default(), actions),
+            other => {
+                return Err(anyhow!(
+                    "plugin {} sent {:?} instead of registering its actions",
+                    self.name,
+                    other
+                ))
+            }
+        };
+
+        if actions.is_empty() {
+            return Err(anyhow!("plugin {} registered no actions", self.name));
+        }
+
+        log::debug!("plugin {} registered {} actions", &self.name, actions.len());
+
+        let process = Arc::new(Mutex::new(process));
+        Ok(StdioNamespace {
+            name: self.name.to_string(),
+            description,
+            actions: actions
+                .into_iter()
+                .map(|descriptor| StdioAction {
+                    descriptor,
+                    process: process.clone(),
+                })
+                .collect(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PLUGIN: &str = r#"
+echo 'not json'
+echo '{"type":"register","description":"Counter.","actions":[{"name":"increment","description":"Increment the counter."}]}'
+count=0
+while read -r line; do
+  id=$(echo "$line" | sed 's/.*"id":\([0-9]*\).*/\1/')
+  count=$((count + 1))
+  echo "{\"type\":\"result\",\"id\":$id,\"output\":\"$count\"}"
+done
+"#;
+
+    #[tokio::test]
+    async fn test_plugin() {
+        let folder = std::env::temp_dir().join("nerve-stdio-test");
+        std::fs::create_dir_all(&folder).unwrap();
+        std::fs::write(folder.join("plugin.sh"), PLUGIN).unwrap();
+
+        let plugin = StdioPlugin {
+            name: "Counter".to_string(),
+            command: "/bin/sh plugin.sh".to_string(),
+            ..Default::default()
+        };
+        let namespace = plugin.start(folder.to_str().unwrap()).await.unwrap();
+        assert_eq!(namespace.description, "Counter.");
+        assert_eq!(namespace.actions.len(), 1);
+
+        // the process keeps its state between invocations
+        let action = &namespace.actions[0];
+        let mut process = action.process.lock().await;
+        assert_eq!(
+            process.invoke("increment", &None, &None).await.unwrap(),
+            Some("1".to_string())
+        );
+        assert_eq!(
+            process.invoke("increment", &None, &None).await.unwrap(),
+            Some("2".to_string())
+        );
+    }
+
+    #[test]
+    fn test_parse_messages() {
+        assert_eq!(
+            serde_json::from_str::<Response>(r#"{"type":"error","id":3,"error":"nope"}"#).unwrap(),
+            Response::Error {
+                id: 3,
+                error: "nope".to_string()
+            }
+        );
+        assert!(serde_json::from_str::<Response>(r#"{"type":"unknown"}"#).is_err());
+    }
+}
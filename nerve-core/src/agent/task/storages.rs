@@ -0,0 +1,443 @@
+use std::collections::HashMap;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use serde::Deserialize;
+use serde_trim::*;
+
+use crate::agent::{
+    namespaces::{Action, Namespace, StorageDescriptor},
+    state::{
+        storage::{StorageType, CURRENT_TAG},
+        SharedState,
+    },
+};
+
+fn default_storage_type() -> String {
+    "tagged".to_string()
+}
+
+// which entries are removed first when a storage exceeds its quota
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Eviction {
+    // the entries saved or updated least recently
+    #[default]
+    Lru,
+    // completed tasks, then the entries updated the fewest times, then the oldest
+    Importance,
+}
+
+// size limits of a storage, enforced by evicting entries
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct StorageQuota {
+    pub max_entries: Option<usize>,
+    // total size of the data of the entries
+    pub max_bytes: Option<usize>,
+    #[serde(default)]
+    pub eviction: Eviction,
+}
+
+impl StorageQuota {
+    pub fn validate(&self, name: &str) -> Result<()> {
+        if self.max_entries.is_none() && self.max_bytes.is_none() {
+            return Err(anyhow!(
+                "quota of storage '{}' requires max_entries or max_bytes",
+                name
+            ));
+        }
+        if self.max_entries == Some(0) || self.max_bytes == Some(0) {
+            return Err(anyhow!("quota of storage '{}' can't be 0", name));
+        }
+        Ok(())
+    }
+
+    pub fn is_exceeded(&self, entries: usize, bytes: usize) -> bool {
+        self.max_entries.is_some_and(|max| entries > max)
+            || self.max_bytes.is_some_and(|max| bytes > max)
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(untagged)]
+pub enum PredefinedData {
+    Value(String),
+    List(Vec<String>),
+    Map(HashMap<String, String>),
+}
+
+// a storage declared by the tasklet
+#[derive(Deserialize, Debug, Clone)]
+pub struct TaskletStorage {
+    #[serde(deserialize_with = "string_trim")]
+    pub name: String,
+    #[serde(rename = "type", default = "default_storage_type")]
+    pub type_name: String,
+    pub description: Option<String>,
+    #[serde(default)]
+    pub readonly: bool,
+    pub predefined: Option<PredefinedData>,
+}
+
+impl TaskletStorage {
+    // a key=value storage with the given content that the agent can't change
+    pub fn readonly(name: &str, description: &str, data: HashMap<String, String>) -> Self {
+        Self {
+            name: name.to_string(),
+            type_name: default_storage_type(),
+            description: Some(description.to_string()),
+            readonly: true,
+            predefined: Some(PredefinedData::Map(data)),
+        }
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        self.to_descriptor().map(|_| ())
+    }
+
+    fn to_descriptor(&self) -> Result<StorageDescriptor> {
+        let descriptor = match self.type_name.as_str() {
+            "tagged" => StorageDescriptor::tagged(&self.name),
+            "untagged" => StorageDescriptor::untagged(&self.name),
+            "current_previous" => StorageDescriptor::previous_current(&self.name),
+            _ => {
+                return Err(anyhow!(
+                    "storage '{}' has invalid type '{}', allowed types are: tagged, untagged, current_previous",
+                    self.name,
+                    self.type_name
+                ))
+            }
+        };
+
+        let predefined = match (&self.predefined, descriptor.type_) {
+            (None, _) => return Ok(descriptor),
+            (Some(PredefinedData::Map(map)), StorageType::Tagged) => map.clone(),
+            (Some(PredefinedData::List(list)), StorageType::Untagged) => list
+                .iter()
+                .enumerate()
+                .map(|(idx, data)| (format!("{}", idx + 1), data.to_string()))
+                .collect(),
+            (Some(PredefinedData::Value(value)), StorageType::CurrentPrevious) => {
+                HashMap::from([(CURRENT_TAG.to_string(), value.to_string())])
+            }
+            _ => {
+                return Err(anyhow!(
+                    "predefined data of storage '{}' doesn't match its type '{}'",
+                    self.name,
+                    self.type_name
+                ))
+            }
+        };
+
+        Ok(descriptor.predefine(predefined))
+    }
+
+    fn actions(&self) -> Vec<Box<dyn Action>> {
+        if self.readonly {
+            return vec![];
+        }
+
+        let name = &self.name;
+        let storage = name.to_string();
+
+        match self.type_name.as_str() {
+            "tagged" => vec![
+                Box::new(StorageAction {
+                    name: format!("set_{name}"),
+                    description: format!("To store a value in {name}:"),
+                    storage: storage.clone(),
+                    op: StorageOp::SetTagged,
+                }),
+                Box::new(StorageAction {
+                    name: format!("delete_{name}"),
+                    description: format!("To delete a value from {name} given its key:"),
+                    storage,
+                    op: StorageOp::DeleteTagged,
+                }),
+            ],
+            "untagged" => vec![
+                Box::new(StorageAction {
+                    name: format!("add_{name}"),
+                    description: format!("To add an element to {name}:"),
+                    storage: storage.clone(),
+                    op: StorageOp::AddUntagged,
+                }),
+                Box::new(StorageAction {
+                    name: format!("delete_{name}"),
+                    description: format!("To remove an element from {name} given its position:"),
+                    storage,
+                    op: StorageOp::DeleteUntagged,
+                }),
+            ],
+            "current_previous" => vec![Box::new(StorageAction {
+                name: format!("update_{name}"),
+                description: format!("To update the current {name}:"),
+                storage,
+                op: StorageOp::SetCurrent,
+            })],
+            _ => vec![],
+        }
+    }
+}
+
+// compile all tasklet storages and their actions in a single namespace
+pub fn compile(storages: &[TaskletStorage]) -> Result<Namespace> {
+    let mut descriptors = vec![];
+    let mut actions = vec![];
+    let mut description = vec![];
+
+    for storage in storages {
+        descriptors.push(storage.to_descriptor()?);
+        actions.extend(storage.actions());
+        if let Some(desc) = &storage.description {
+            description.push(format!("{}: {}", storage.name, desc.trim()));
+        }
+    }
+
+    Ok(Namespace::new_default(
+        "Storages".to_string(),
+        description.join("\n"),
+        actions,
+        Some(descriptors),
+    ))
+}
+
+#[derive(Debug, Clone)]
+enum StorageOp {
+    SetTagged,
+    DeleteTagged,
+    AddUntagged,
+    DeleteUntagged,
+    SetCurrent,
+}
+
+#[derive(Debug, Clone)]
+struct StorageAction {
+    name: String,
+    description: String,
+    storage: String,
+    op: StorageOp,
+}
+
+#[async_trait]
+impl Action for StorageAction {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn description(&self) -> &str {
+        &self.description
+    }
+
+    fn example_attributes(&self) -> Option<HashMap<String, String>> {
+        match self.op {
+            StorageOp::SetTagged | StorageOp::DeleteTagged => {
+                let mut attributes = HashMap::new();
+                attributes.insert("key".to_string(), "my-key".to_string());
+                Some(attributes)
+            }
+            _ => None,
+        }
+    }
+
+    fn example_payload(&self) -> Option<&str> {
+        match self.op {
+            StorageOp::SetTagged | StorageOp::AddUntagged | StorageOp::SetCurrent => {
+                Some("the data to store")
+            }
+            StorageOp::DeleteUntagged => Some("2"),
+            StorageOp::DeleteTagged => None,
+        }
+    }
+
+    async fn run(
+        &self,
+        state: SharedState,
+        attributes: Option<HashMap<String, String>>,
+        payload: Option<String>,
+    ) -> Result<Option<String>> {
+        let mut state = state.lock().await;
+        let storage = state.get_storage_mut(&self.storage)?;
+
+        match self.op {
+            StorageOp::SetTagged => {
+                let attrs = attributes.unwrap();
+                let key = attrs.get("key").unwrap();
+                storage.add_tagged(key, payload.unwrap().as_str());
+                Ok(Some(format!("{} updated", self.storage)))
+            }
+            StorageOp::DeleteTagged => {
+                let attrs = attributes.unwrap();
+                let key = attrs.get("key").unwrap();
+                if storage.del_tagged(key).is_some() {
+                    Ok(Some(format!("{} deleted from {}", key, self.storage)))
+                } else {
+                    Err(anyhow!("'{}' not found in {}", key, self.storage))
+                }
+            }
+            StorageOp::AddUntagged => {
+                storage.add_untagged(payload.unwrap().as_str());
+                Ok(Some(format!("element added to {}", self.storage)))
+            }
+            StorageOp::DeleteUntagged => {
+                let pos = payload.unwrap().parse::<usize>()?;
+                if storage.del_untagged(pos).is_some() {
+                    Ok(Some(format!(
+                        "element {} removed from {}",
+                        pos, self.storage
+                    )))
+                } else {
+                    Err(anyhow!(
+                        "no element at position {} in {}",
+                        pos,
+                        self.storage
+                    ))
+                }
+            }
+            StorageOp::SetCurrent => {
+                storage.set_current(payload.unwrap().as_str());
+                Ok(Some(format!("{} updated", self.storage)))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent::{
+        events::{create_channel, Event},
+        state::storage::Storage,
+    };
+
+    fn parse(yaml: &str) -> TaskletStorage {
+        serde_yaml::from_str(yaml).unwrap()
+    }
+
+    #[test]
+    fn test_default_type_is_tagged() {
+        let storage = parse("name: scope");
+        let descriptor = storage.to_descriptor().unwrap();
+        assert!(matches!(descriptor.type_, StorageType::Tagged));
+        assert_eq!(storage.actions().len(), 2);
+    }
+
+    #[test]
+    fn test_readonly_has_no_actions() {
+        let storage = parse("name: scope\nreadonly: true");
+        assert!(storage.actions().is_empty());
+    }
+
+    #[test]
+    fn test_readonly_constructor() {
+        let storage = TaskletStorage::readonly(
+            "input",
+            "test",
+            HashMap::from([("stdin".to_string(), "data".to_string())]),
+        );
+        assert!(storage.actions().is_empty());
+        let descriptor = storage.to_descriptor().unwrap();
+        assert!(matches!(descriptor.type_, StorageType::Tagged));
+        assert_eq!(descriptor.predefined.unwrap().get("stdin").unwrap(), "data");
+    }
+
+    #[test]
+    fn test_untagged_predefined_list() {
+        let storage = parse("name: targets\ntype: untagged\npredefined:\n  - a\n  - b");
+        let descriptor = storage.to_descriptor().unwrap();
+        let predefined = descriptor.predefined.unwrap();
+        assert_eq!(predefined.get("1").unwrap(), "a");
+        assert_eq!(predefined.get("2").unwrap(), "b");
+    }
+
+    #[test]
+    fn test_current_previous_predefined_value() {
+        let storage = parse("name: phase\ntype: current_previous\npredefined: recon");
+        let descriptor = storage.to_descriptor().unwrap();
+        assert_eq!(
+            descriptor.predefined.unwrap().get(CURRENT_TAG).unwrap(),
+            "recon"
+        );
+    }
+
+    #[test]
+    fn test_mismatched_predefined_is_error() {
+        let storage = parse("name: scope\npredefined:\n  - a");
+        assert!(storage.validate().is_err());
+    }
+
+    #[test]
+    fn test_invalid_type_is_error() {
+        let storage = parse("name: scope\ntype: whatever");
+        assert!(storage.validate().is_err());
+    }
+    fn quota(yaml: &str) -> StorageQuota {
+        let quota: StorageQuota = serde_yaml::from_str(yaml).unwrap();
+        quota.validate("test").unwrap();
+        quota
+    }
+
+    #[test]
+    fn test_quota_lru_eviction() {
+        let (tx, mut rx) = create_channel();
+        let mut storage = Storage::new("memories", StorageType::Tagged, tx);
+        storage.set_quota(quota("max_entries: 2"));
+
+        storage.add_tagged("a", "first");
+        storage.add_tagged("b", "second");
+        storage.add_tagged("a", "first again");
+        storage.add_tagged("c", "third");
+
+        let keys: Vec<&String> = storage.keys().collect();
+        assert_eq!(keys, vec!["a", "c"]);
+
+        let mut evicted = vec![];
+        while let Ok(event) = rx.try_recv() {
+            if let Event::StorageEvicted { key, data, .. } = event {
+                evicted.push((key, data));
+            }
+        }
+        assert_eq!(evicted, vec![("b".to_string(), "second".to_string())]);
+    }
+
+    #[test]
+    fn test_quota_importance_eviction() {
+        let (tx, _rx) = create_channel();
+        let mut storage = Storage::new("plan", StorageType::Completion, tx);
+        storage.set_quota(quota("max_entries: 2\neviction: importance"));
+
+        storage.add_completion("one");
+        storage.add_completion("two");
+        storage.set_complete(2);
+        storage.add_completion("three");
+
+        let data: Vec<&str> = storage.values().map(|e| e.data.as_str()).collect();
+        assert_eq!(data, vec!["one", "three"]);
+        // positions follow what the model sees after the eviction
+        storage.set_complete(2);
+        assert!(storage.values().nth(1).unwrap().complete);
+    }
+
+    #[test]
+    fn test_quota_max_bytes() {
+        let (tx, _rx) = create_channel();
+        let mut storage = Storage::new("findings", StorageType::Untagged, tx);
+        storage.set_quota(quota("max_bytes: 10"));
+
+        storage.add_untagged("12345");
+        storage.add_untagged("67890");
+        storage.add_untagged("abc");
+        assert_eq!(storage.size(), 8);
+        storage.add_untagged("d");
+
+        let data: Vec<&str> = storage.values().map(|e| e.data.as_str()).collect();
+        assert_eq!(data, vec!["67890", "abc", "d"]);
+        let keys: Vec<&String> = storage.keys().collect();
+        assert_eq!(keys, vec!["2", "3", "4"]);
+
+        assert!(serde_yaml::from_str::<StorageQuota>("eviction: lru")
+            .unwrap()
+            .validate("test")
+            .is_err());
+    }
+}
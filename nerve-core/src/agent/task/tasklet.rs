@@ -0,0 +1,1200 @@
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::str::FromStr;
+use std::{
+    collections::{HashMap, HashSet},
+    time::Duration,
+};
+
+use anyhow::Result;
+use async_trait::async_trait;
+use colored::Colorize;
+use duration_string::DurationString;
+use serde::Deserialize;
+use serde_trim::*;
+
+use super::{
+    evaluation::Evaluation,
+    filters::OutputFilter,
+    handoff::{Handoff, Package, HANDOFF_STORAGE},
+    hooks::Hooks,
+    mcp::McpServer,
+    memory::MemoryPolicy,
+    openapi::OpenApi,
+    prompt::PromptSource,
+    rag::RagConfig,
+    report::ReportTemplate,
+    retry::RetryPolicy,
+    stdio::{StdioNamespace, StdioPlugin},
+    variables::{interpolate_variables, VariableDeclaration},
+    wasm::WasmModule,
+    Example, Task,
+};
+#[cfg(feature = "mcp")]
+use crate::agent::task::mcp::McpNamespace;
+#[cfg(feature = "openapi")]
+use crate::agent::task::openapi::OpenApiNamespace;
+use crate::agent::task::robopages;
+use crate::agent::task::storages::{StorageQuota, TaskletStorage};
+use crate::agent::task::variables::define_variable;
+use crate::agent::{get_user_input, namespaces, secrets};
+use crate::agent::{
+    guardrails::GuardrailRule,
+    namespaces::{Action, Namespace},
+    ratelimit::RateLimit,
+    state::SharedState,
+    streaming,
+    task::variables::{parse_pre_defined_values, parse_variable_expr},
+};
+
+const STATE_COMPLETE_EXIT_CODE: i32 = 65;
+
+// storage holding the data provided by the user with the task
+pub const INPUT_STORAGE: &str = "input";
+
+fn default_max_shown_output() -> usize {
+    256
+}
+
+#[derive(Default, Deserialize, Debug, Clone)]
+pub struct TaskletAction {
+    #[serde(skip_deserializing, skip_serializing)]
+    working_directory: String,
+    #[serde(skip_deserializing, skip_serializing)]
+    robopages_server_address: Option<String>,
+
+    #[serde(default = "default_max_shown_output")]
+    max_shown_output: usize,
+    #[serde(deserialize_with = "string_trim")]
+    name: String,
+    #[serde(deserialize_with = "string_trim")]
+    description: String,
+    args: Option<HashMap<String, String>>,
+    define: Option<HashMap<String, String>>,
+    example_payload: Option<String>,
+    timeout: Option<String>,
+    retry: Option<RetryPolicy>,
+    // rate limits the action counts against
+    rate_limits: Option<Vec<String>>,
+    filter: Option<OutputFilter>,
+
+    judge: Option<String>,
+    #[serde(skip_deserializing, skip_serializing)]
+    judge_path: Option<PathBuf>,
+
+    complete_task: Option<bool>,
+
+    tool: Option<String>,
+    // environment variables, working directory and stdin content for the tool process
+    env: Option<HashMap<String, String>>,
+    cwd: Option<String>,
+    stdin: Option<String>,
+
+    // sandboxed wasi module implementing the action
+    wasm: Option<WasmModule>,
+
+    alias: Option<String>,
+    #[serde(skip_deserializing, skip_serializing)]
+    aliased_to: Option<Box<dyn Action>>,
+}
+
+#[async_trait]
+impl Action for TaskletAction {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn description(&self) -> &str {
+        &self.description
+    }
+
+    fn example_payload(&self) -> Option<&str> {
+        if self.example_payload.is_some() {
+            return self.example_payload.as_deref();
+        } else if let Some(aliased_to) = &self.aliased_to {
+            return aliased_to.example_payload();
+        }
+        None
+    }
+
+    fn example_attributes(&self) -> Option<HashMap<String, String>> {
+        if self.args.is_some() {
+            return self.args.clone();
+        } else if let Some(aliased_to) = &self.aliased_to {
+            return aliased_to.example_attributes();
+        }
+        None
+    }
+
+    fn timeout(&self) -> Option<Duration> {
+        if let Some(timeout) = &self.timeout {
+            if let Ok(tm) = timeout.parse::<DurationString>() {
+                return Some(*tm);
+            } else {
+                log::error!("can't parse '{}' as duration string", timeout);
+            }
+        } else if let Some(aliased_to) = &self.aliased_to {
+            return aliased_to.timeout();
+        }
+        None
+    }
+
+    fn complete_task(&self) -> bool {
+        self.complete_task.unwrap_or(false)
+    }
+
+    fn is_destructive(&self) -> bool {
+        // tools, wasm modules and robopages can do anything on the host
+        if self.tool.is_some() || self.wasm.is_some() || self.robopages_server_address.is_some() {
+            true
+        } else if let Some(aliased_to) = &self.aliased_to {
+            aliased_to.is_destructive()
+        } else {
+            self.requires_user_confirmation()
+        }
+    }
+
+    fn retry_policy(&self) -> Option<RetryPolicy> {
+        if let Some(retry) = &self.retry {
+            Some(retry.clone())
+        } else if let Some(aliased_to) = &self.aliased_to {
+            aliased_to.retry_policy()
+        } else {
+            None
+        }
+    }
+
+    fn rate_limits(&self) -> Vec<String> {
+        if let Some(rate_limits) = &self.rate_limits {
+            rate_limits.clone()
+        } else if let Some(aliased_to) = &self.aliased_to {
+            aliased_to.rate_limits()
+        } else {
+            vec![]
+        }
+    }
+
+    async fn run(
+        &self,
+        state: SharedState,
+        attributes: Option<HashMap<String, String>>,
+        payload: Option<String>,
+    ) -> Result<Option<String>> {
+        // run via robopages server
+        if let Some(server_address) = &self.robopages_server_address {
+            let result = robopages::Client::new(server_address.clone())
+                .execute(&self.name, attributes.unwrap_or_default())
+                .await?;
+            return Ok(Some(result));
+        }
+
+        // run as alias of a builtin namespace.action, here we can unwrap as everything is validated earlier
+        if let Some(aliased_to) = &self.aliased_to {
+            return aliased_to.run(state, attributes, payload).await;
+        }
+
+        // run as another instance of nerve for the judge tool
+        if let Some(judge_path) = &self.judge_path {
+            let nerve_exe = std::env::current_exe()
+                .map_err(|e| anyhow!("Failed to get current executable path: {}", e))?;
+
+            let generator = std::env::var("NERVE_JUDGE").unwrap();
+
+            let mut cmd = Command::new(&nerve_exe);
+            cmd.args([
+                "-J",
+                &generator,
+                "-T",
+                judge_path.to_str().unwrap(),
+                "--judge-mode",
+            ]);
+
+            let mut child = cmd
+                .stdin(Stdio::piped())
+                .stdout(Stdio::piped())
+                .spawn()
+                .expect("Failed to spawn judge child process");
+
+            log::info!("running judge: {:?}", &cmd);
+
+            let mut stdin = child.stdin.take().expect("Failed to open stdin");
+            std::thread::spawn(move || {
+                stdin
+                    .write_all(format!("{}\n", payload.unwrap()).as_bytes())
+                    .expect("Failed to write to judge stdin");
+            });
+
+            // let output = cmd.output();
+            let output = child.wait_with_output();
+            let mut result = String::new();
+
+            if let Ok(output) = output {
+                let err = String::from_utf8_lossy(&output.stderr).trim().to_string();
+                let out = String::from_utf8_lossy(&output.stdout).trim().to_string();
+
+                if !err.is_empty() {
+                    result = format!("ERROR: {}\n", err);
+                }
+
+                if !out.is_empty() {
+                    result = out.to_string();
+                }
+            } else {
+                result = format!("ERROR: {}", output.err().unwrap());
+            }
+
+            log::info!("  > {}", &result);
+
+            return Ok(Some(result));
+        }
+
+        // run as sandboxed wasm module
+        #[cfg(feature = "wasm")]
+        if let Some(wasm) = &self.wasm {
+            let policy = state.lock().await.get_policy().filesystem.clone();
+            return wasm.run(&policy, &self.name, &attributes, &payload).await;
+        }
+
+        // run as local tool
+        let parts: Vec<String> = self
+            .tool
+            .as_ref()
+            .ok_or_else(|| anyhow!("tool not set"))?
+            .split_whitespace()
+            .map(|x| x.trim())
+            .filter(|x| !x.is_empty())
+            .map(|x| x.to_string())
+            .collect();
+
+        if parts.is_empty() {
+            return Err(anyhow!("no tool defined"));
+        }
+
+        // TODO: each action should have its own variables ... ?
+
+        // define action specific variables
+        if let Some(payload) = &payload {
+            define_variable("PAYLOAD", payload);
+        }
+
+        if let Some(attributes) = &attributes {
+            for (key, value) in attributes {
+                define_variable(&format!("ATTRIBUTES.{}", key), value);
+            }
+        }
+
+        let mut payload_consumed = false;
+        let mut cmd = Command::new(&parts[0]);
+        secrets::scrub_env(&mut cmd);
+        if parts.len() > 1 {
+            // more complex command line
+            for part in &parts[1..] {
+                if part.as_bytes()[0] == b'$' {
+                    let (var_name, var_value) = parse_variable_expr(part)?;
+                    if var_name == "PAYLOAD" {
+                        payload_consumed = true;
+                    }
+                    cmd.arg(var_value);
+                } else {
+                    // raw value
+                    cmd.arg(part);
+                }
+            }
+        }
+
+        cmd.current_dir(&self.working_directory);
+
+        if let Some(cwd) = &self.cwd {
+            let cwd = PathBuf::from(interpolate_variables(cwd)?);
+            cmd.current_dir(if cwd.is_relative() {
+                PathBuf::from(&self.working_directory).join(cwd)
+            } else {
+                cwd
+            });
+        }
+
+        if let Some(env) = &self.env {
+            for (key, value) in env {
+                cmd.env(key, interpolate_variables(value)?);
+            }
+        }
+
+        let stdin = if let Some(stdin) = &self.stdin {
+            if stdin.contains("$PAYLOAD") {
+                payload_consumed = true;
+            }
+            Some(interpolate_variables(stdin)?)
+        } else {
+            None
+        };
+
+        if let Some(attrs) = &attributes {
+            for (key, value) in attrs {
+                cmd.args([&format!("--{}", key), value]);
+            }
+        }
+
+        // if the payload was not excplicitly referenced by $PAYLOAD, add it as the last argument
+        if !payload_consumed {
+            if let Some(payload) = &payload {
+                cmd.arg(payload);
+            }
+        }
+
+        log::info!(
+            "{}{}{}",
+            self.name.bold(),
+            if let Some(payload) = &payload {
+                format!(" {}", payload.red())
+            } else {
+                "".to_string()
+            },
+            if let Some(attributes) = &attributes {
+                format!(
+                    " {}",
+                    attributes
+                        .iter()
+                        .map(|(key, value)| format!("{key}{}{}", "=".dimmed(), value.bright_blue()))
+                        .collect::<Vec<String>>()
+                        .join(" ")
+                )
+            } else {
+                "".to_string()
+            },
+        );
+
+        log::debug!("! {:?}", &cmd);
+
+        // the output is shown while the tool runs, up to max_shown_output bytes of each stream
+        let output = streaming::run_command(
+            tokio::process::Command::from(cmd),
+            stdin,
+            &state,
+            Some(self.max_shown_output),
+        )
+        .await;
+        if let Ok(output) = output {
+            let err = String::from_utf8_lossy(&output.stderr).trim().to_string();
+            let out = String::from_utf8_lossy(&output.stdout).trim().to_string();
+
+            let exit_code = output.status.code().unwrap_or(0);
+            log::debug!("exit_code={}", exit_code);
+            if exit_code == STATE_COMPLETE_EXIT_CODE {
+                state.lock().await.on_complete(false, Some(out))?;
+                return Ok(Some("task complete".to_string()));
+            }
+
+            if !err.is_empty() {
+                Err(anyhow!(err))
+            } else {
+                Ok(Some(out))
+            }
+        } else {
+            let err = output.err().unwrap().to_string();
+            log::error!("{}", &err);
+            Err(anyhow!(err))
+        }
+    }
+}
+
+#[derive(Default, Deserialize, Debug, Clone)]
+pub struct FunctionGroup {
+    #[serde(deserialize_with = "string_trim")]
+    pub name: String,
+    pub description: Option<String>,
+    pub actions: Vec<TaskletAction>,
+}
+
+impl FunctionGroup {
+    fn compile(
+        &self,
+        working_directory: &str,
+        robopages_server_address: Option<String>,
+    ) -> Result<Namespace> {
+        let mut actions: Vec<Box<dyn Action>> = vec![];
+        for tasklet_action in &self.actions {
+            let mut action = tasklet_action.clone();
+            action.working_directory = working_directory.to_string();
+            action.robopages_server_address = robopages_server_address.clone();
+            actions.push(Box::new(action));
+        }
+
+        Ok(Namespace::new_default(
+            self.name.to_string(),
+            if let Some(desc) = &self.description {
+                desc.to_string()
+            } else {
+                "".to_string()
+            },
+            actions,
+            None, // TODO: let tasklets declare custom storages?
+        ))
+    }
+}
+
+#[derive(Default, Deserialize, Debug, Clone)]
+pub struct Tasklet {
+    #[serde(skip_deserializing, skip_serializing)]
+    pub folder: String,
+    #[serde(skip_deserializing, skip_serializing)]
+    pub name: String,
+    #[serde(deserialize_with = "string_trim")]
+    system_prompt: String,
+    pub prompt: Option<String>,
+    prompt_from: Option<String>,
+    variables: Option<Vec<VariableDeclaration>>,
+    pub rag: Option<RagConfig>,
+    // expiry of the memories saved by the model
+    memory: Option<MemoryPolicy>,
+    timeout: Option<String>,
+    timeouts: Option<HashMap<String, String>>,
+    retries: Option<HashMap<String, RetryPolicy>>,
+    // limits shared by the actions tagged with them
+    rate_limits: Option<HashMap<String, RateLimit>>,
+    filters: Option<HashMap<String, OutputFilter>>,
+    max_steps: Option<usize>,
+    max_duration: Option<String>,
+    reflect_every: Option<usize>,
+    // how the final answer is evaluated before completing
+    evaluation: Option<Evaluation>,
+    guardrails: Option<Vec<GuardrailRule>>,
+    using: Option<Vec<String>>,
+    guidance: Option<Vec<String>>,
+    examples: Option<Vec<Example>>,
+    storages: Option<Vec<TaskletStorage>>,
+    // size limits of the storages by name
+    quotas: Option<HashMap<String, StorageQuota>>,
+    // render the age and origin of the storage entries
+    #[serde(default)]
+    storage_ages: bool,
+    // render the changes of the storages after a snapshot taken every this many changes
+    storage_deltas: Option<usize>,
+    // report compiled by the model at the end of the run
+    report: Option<ReportTemplate>,
+    functions: Option<Vec<FunctionGroup>>,
+    // external processes serving actions over stdio
+    plugins: Option<Vec<StdioPlugin>>,
+    // model context protocol servers whose tools are exposed as namespaces
+    mcp: Option<Vec<McpServer>>,
+    // rest api whose operations are exposed as a namespace
+    openapi: Option<OpenApi>,
+    // agents the task can be handed off to
+    handoffs: Option<Vec<Handoff>>,
+    #[serde(flatten)]
+    hooks: Hooks,
+
+    #[serde(skip_deserializing, skip_serializing)]
+    started_plugins: Vec<StdioNamespace>,
+    #[cfg(feature = "mcp")]
+    #[serde(skip_deserializing, skip_serializing)]
+    connected_mcp: Vec<McpNamespace>,
+    #[cfg(feature = "openapi")]
+    #[serde(skip_deserializing, skip_serializing)]
+    openapi_namespace: Option<OpenApiNamespace>,
+
+    #[serde(skip_deserializing, skip_serializing)]
+    robopages: Vec<FunctionGroup>,
+    #[serde(skip_deserializing, skip_serializing)]
+    robopages_server_address: Option<String>,
+}
+
+impl Tasklet {
+    pub fn from_path(tasklet_path: &str, defines: &Vec<String>) -> Result<Self> {
+        parse_pre_defined_values(defines)?;
+
+        let mut tasklet_path = PathBuf::from_str(tasklet_path)?;
+        // try to look it up in ~/.nerve/tasklets
+        if !tasklet_path.exists() {
+            let in_home = crate::agent::data_path("tasklets")?.join(&tasklet_path);
+            if in_home.exists() {
+                tasklet_path = in_home;
+            }
+        }
+
+        if tasklet_path.is_dir() {
+            Self::from_folder(tasklet_path.to_str().unwrap())
+        } else {
+            Self::from_yaml_file(tasklet_path.to_str().unwrap())
+        }
+    }
+
+    fn from_folder(path: &str) -> Result<Self> {
+        let filepath = PathBuf::from_str(path);
+        if let Err(err) = filepath {
+            Err(anyhow!("could not read {path}: {err}"))
+        } else {
+            Self::from_yaml_file(filepath.unwrap().join("task.yml").to_str().unwrap())
+        }
+    }
+
+    fn from_yaml_file(filepath: &str) -> Result<Self> {
+        let canon = std::fs::canonicalize(filepath);
+        if let Err(err) = canon {
+            Err(anyhow!("could not read {filepath}: {err}"))
+        } else {
+            let canon = canon.unwrap();
+            let tasklet_parent_folder = if let Some(folder) = canon.parent() {
+                folder
+            } else {
+                return Err(anyhow!("can't find parent folder of {}", canon.display()));
+            };
+
+            let yaml = std::fs::read_to_string(&canon)?;
+            let mut tasklet: Self = serde_yaml::from_str(&yaml)?;
+
+            // used to set the working directory while running the task
+            tasklet.folder = if let Some(folder) = tasklet_parent_folder.to_str() {
+                folder.to_string()
+            } else {
+                return Err(anyhow!("can't get string of {:?}", tasklet_parent_folder));
+            };
+
+            // set unique task name from the folder or yaml file itself
+            tasklet.name = if canon.ends_with("task.yaml") || canon.ends_with("task.yml") {
+                tasklet_parent_folder
+                    .file_name()
+                    .unwrap()
+                    .to_str()
+                    .unwrap()
+                    .to_owned()
+            } else {
+                canon.file_stem().unwrap().to_str().unwrap().to_owned()
+            };
+
+            tasklet.hooks.task_name = tasklet.name.clone();
+            tasklet.hooks.working_directory = tasklet.folder.clone();
+            tasklet.hooks.validate()?;
+
+            if let Some(rag) = &tasklet.rag {
+                rag.validate()?;
+            }
+
+            if let Some(memory) = &tasklet.memory {
+                memory.validate()?;
+            }
+
+            if let Some(evaluation) = &tasklet.evaluation {
+                evaluation.validate()?;
+            }
+
+            if let Some(report) = &tasklet.report {
+                report.validate()?;
+            }
+
+            for (name, quota) in tasklet.quotas.iter().flatten() {
+                quota.validate(name)?;
+            }
+
+            if let Some(storages) = &tasklet.storages {
+                for storage in storages {
+                    storage.validate()?;
+                }
+            }
+
+            if let Some(filters) = &tasklet.filters {
+                for filter in filters.values() {
+                    filter.validate()?;
+                }
+            }
+            for server in tasklet.mcp.iter().flatten() {
+                server.validate()?;
+            }
+            if let Some(openapi) = &tasklet.openapi {
+                openapi.validate()?;
+            }
+            for rule in tasklet.guardrails.iter().flatten() {
+                rule.validate()?;
+            }
+            let mut handoffs = HashSet::new();
+            for handoff in tasklet.handoffs.iter_mut().flatten() {
+                if !handoffs.insert(handoff.name.to_string()) {
+                    return Err(anyhow!(
+                        "handoff '{}' is defined more than once",
+                        handoff.name
+                    ));
+                }
+                handoff.validate(&tasklet.folder)?;
+            }
+            if let Some(retries) = &tasklet.retries {
+                for retry in retries.values() {
+                    retry.validate()?;
+                }
+            }
+            for limit in tasklet
+                .rate_limits
+                .iter()
+                .flat_map(|limits| limits.values())
+            {
+                limit.validate()?;
+            }
+            check_duration("timeout", tasklet.timeout.as_ref())?;
+            check_duration("max_duration", tasklet.max_duration.as_ref())?;
+            for (action, timeout) in tasklet.timeouts.iter().flatten() {
+                check_duration(&format!("timeout of {}", action), Some(timeout))?;
+            }
+            for group in tasklet.functions.iter().flatten() {
+                for action in &group.actions {
+                    check_duration(
+                        &format!("timeout of {}", action.name),
+                        action.timeout.as_ref(),
+                    )?;
+                    if let Some(filter) = &action.filter {
+                        filter.validate()?;
+                    }
+                    if let Some(retry) = &action.retry {
+                        retry.validate()?;
+                    }
+                }
+            }
+
+            // check any tool definied as alias of a builtin namespace and perform some preprocessing and validation
+            if let Some(functions) = tasklet.functions.as_mut() {
+                // for each group of functions
+                for group in functions {
+                    // for each action in the group
+                    for action in &mut group.actions {
+                        if let Some(defines) = &action.define {
+                            for (key, value) in defines {
+                                log::debug!(
+                                    "defining variable {} = '{}' for {}.{}",
+                                    key,
+                                    value,
+                                    group.name,
+                                    action.name
+                                );
+                                define_variable(key, value);
+                            }
+                        }
+
+                        // if the action is a judge, validate the judge file
+                        if let Some(judge) = &action.judge {
+                            let judge_path = if Path::new(judge).is_absolute() {
+                                PathBuf::from_str(judge)?
+                            } else {
+                                PathBuf::from(&tasklet.folder).join(judge)
+                            };
+                            if !judge_path.exists() {
+                                return Err(anyhow!(
+                                    "judge file '{}' not found",
+                                    judge_path.display()
+                                ));
+                            } else if !judge_path.is_file() {
+                                return Err(anyhow!(
+                                    "judge file '{}' is not a file",
+                                    judge_path.display()
+                                ));
+                            }
+                            action.judge_path = Some(judge_path);
+                        }
+
+                        if let Some(wasm) = action.wasm.as_mut() {
+                            if action.tool.is_some()
+                                || action.alias.is_some()
+                                || action.judge.is_some()
+                            {
+                                return Err(anyhow!(
+                                    "action '{}' can't define wasm together with tool, alias or judge",
+                                    action.name
+                                ));
+                            }
+                            wasm.prepare(&tasklet.folder)?;
+                        }
+
+                        // if the action has an alias perform some validation
+                        if let Some(alias) = &action.alias {
+                            if action.tool.is_some() {
+                                return Err(anyhow!("can't define both tool and alias"));
+                            }
+
+                            let (namespace_name, action_name) = alias
+                                .split_once('.')
+                                .ok_or_else(|| anyhow!("invalid alias format '{}', aliases must be provided as 'namespace.action'", alias))?;
+
+                            if let Some(get_namespace_fn) =
+                                namespaces::NAMESPACES.get(namespace_name)
+                            {
+                                let le_namespace = get_namespace_fn();
+                                let le_action = le_namespace
+                                    .actions
+                                    .iter()
+                                    .find(|a| a.name() == action_name);
+
+                                if let Some(le_action) = le_action {
+                                    log::debug!(
+                                        "aliased {}.{} to {}.{}",
+                                        group.name,
+                                        action.name,
+                                        le_namespace.name,
+                                        le_action.name()
+                                    );
+                                    action.aliased_to = Some(le_action.clone());
+                                } else {
+                                    return Err(anyhow!(
+                                        "action '{}' not found in namespace '{}'",
+                                        action_name,
+                                        namespace_name
+                                    ));
+                                }
+                            } else {
+                                return Err(anyhow!("namespace '{}' not found", namespace_name));
+                            }
+                        }
+                    }
+                }
+            }
+
+            log::debug!("tasklet = {:?}", &tasklet);
+
+            Ok(tasklet)
+        }
+    }
+
+    pub async fn prepare(&mut self, user_prompt: &Option<String>) -> Result<()> {
+        // resolve declared variables before anything else might need them
+        if let Some(variables) = &self.variables {
+            for var in variables {
+                var.resolve()?;
+            }
+        }
+
+        if self.prompt.is_none() {
+            self.prompt = Some(if let Some(source) = &self.prompt_from {
+                // if the tasklet specifies where to read it from
+                PromptSource::parse(source).load(&self.folder).await?
+            } else if let Some(prompt) = &user_prompt {
+                // if passed by command line
+                prompt.to_string()
+            } else if PromptSource::stdin_is_piped() {
+                // if piped via stdin
+                PromptSource::Stdin.load(&self.folder).await?
+            } else {
+                // ask the user
+                get_user_input("enter task> ")
+            });
+        }
+
+        // parse any variable
+        self.prompt = Some(interpolate_variables(self.prompt.as_ref().unwrap().trim())?);
+
+        // start the plugins, they run until nerve exits
+        for plugin in self.plugins.iter().flatten() {
+            self.started_plugins.push(plugin.start(&self.folder).await?);
+        }
+
+        #[cfg(feature = "mcp")]
+        for server in self.mcp.iter().flatten() {
+            self.connected_mcp.push(server.connect(&self.folder).await?);
+        }
+
+        #[cfg(feature = "openapi")]
+        if let Some(openapi) = &self.openapi {
+            self.openapi_namespace = Some(openapi.load(&self.folder).await?);
+        }
+
+        // fix paths
+        if let Some(rag) = self.rag.as_mut() {
+            if let Some(source_path) = &rag.source_path {
+                let src_path = PathBuf::from(source_path);
+                if src_path.is_relative() {
+                    rag.source_path = Some(
+                        std::fs::canonicalize(PathBuf::from(&self.folder).join(src_path))?
+                            .display()
+                            .to_string(),
+                    );
+                }
+            }
+
+            for source in rag.sources.iter_mut().flatten() {
+                source.resolve_path(&self.folder);
+            }
+
+            let data_path = PathBuf::from(&rag.data_path);
+            if !rag.data_path.is_empty() && data_path.is_relative() {
+                rag.data_path = std::fs::canonicalize(PathBuf::from(&self.folder).join(data_path))?
+                    .display()
+                    .to_string();
+            }
+        }
+
+        Ok(())
+    }
+
+    // override the namespaces declared by the tasklet
+    pub fn set_namespaces(&mut self, using: Vec<String>) {
+        self.using = Some(using);
+    }
+
+    // if preparing the tasklet would read the prompt from stdin when it's piped
+    pub fn prompt_from_stdin(&self, user_prompt: &Option<String>) -> bool {
+        match (&self.prompt, &self.prompt_from) {
+            (Some(_), _) => false,
+            (None, Some(source)) => PromptSource::parse(source) == PromptSource::Stdin,
+            (None, None) => user_prompt.is_none(),
+        }
+    }
+
+    pub fn has_storage(&self, name: &str) -> bool {
+        self.storages
+            .iter()
+            .flatten()
+            .any(|storage| storage.name == name)
+    }
+
+    // add data provided by the user, like what's piped to nerve, to the readonly input storage
+    pub fn attach_input(&mut self, data: HashMap<String, String>) -> Result<()> {
+        if self.has_storage(INPUT_STORAGE) {
+            return Err(anyhow!(
+                "can't attach the input, the tasklet already declares a storage named {}",
+                INPUT_STORAGE
+            ));
+        }
+        self.storages
+            .get_or_insert_with(Vec::new)
+            .push(TaskletStorage::readonly(
+                INPUT_STORAGE,
+                "data provided by the user for the task.",
+                data,
+            ));
+        Ok(())
+    }
+
+    // what the previous agent handed off, in the readonly handoff storage
+    pub fn attach_handoff(&mut self, package: &Package) -> Result<()> {
+        if self.has_storage(HANDOFF_STORAGE) {
+            return Err(anyhow!(
+                "can't attach the handoff, the tasklet already declares a storage named {}",
+                HANDOFF_STORAGE
+            ));
+        }
+        self.storages
+            .get_or_insert_with(Vec::new)
+            .push(TaskletStorage::readonly(
+                HANDOFF_STORAGE,
+                "what the previous agent working on the task handed off to you.",
+                package.to_storage_data(),
+            ));
+        Ok(())
+    }
+
+    pub fn set_robopages(&mut self, server_address: &str, robopages: Vec<FunctionGroup>) {
+        let mut host_port = if server_address.contains("://") {
+            server_address.split("://").last().unwrap().to_string()
+        } else {
+            server_address.to_string()
+        };
+
+        if host_port.contains("/") {
+            host_port = host_port.split('/').next().unwrap().to_string();
+        }
+
+        self.robopages_server_address = Some(host_port);
+        self.robopages = robopages;
+    }
+}
+
+// a typo must not silently disable a limit, so the durations are checked when loading
+fn check_duration(field: &str, value: Option<&String>) -> Result<()> {
+    if let Some(value) = value {
+        value
+            .parse::<DurationString>()
+            .map_err(|e| anyhow!("invalid {} '{}': {}", field, value, e))?;
+    }
+    Ok(())
+}
+
+fn parse_duration(value: Option<&String>) -> Option<Duration> {
+    if let Some(value) = value {
+        if let Ok(tm) = value.parse::<DurationString>() {
+            return Some(*tm);
+        } else {
+            log::error!("can't parse '{}' as duration string", value);
+        }
+    }
+    None
+}
+
+impl Task for Tasklet {
+    fn get_timeout(&self) -> Option<std::time::Duration> {
+        parse_duration(self.timeout.as_ref())
+    }
+
+    fn get_action_timeout(&self, action_name: &str) -> Option<Duration> {
+        parse_duration(self.timeouts.as_ref()?.get(action_name))
+    }
+
+    fn get_retry_policy(&self, action_name: &str) -> Option<RetryPolicy> {
+        self.retries.as_ref()?.get(action_name).cloned()
+    }
+
+    fn get_rate_limits(&self) -> HashMap<String, RateLimit> {
+        self.rate_limits.clone().unwrap_or_default()
+    }
+
+    fn get_max_steps(&self) -> Option<usize> {
+        self.max_steps
+    }
+
+    fn get_reflection_interval(&self) -> Option<usize> {
+        self.reflect_every
+    }
+
+    fn get_guardrails(&self) -> Vec<GuardrailRule> {
+        self.guardrails.clone().unwrap_or_default()
+    }
+
+    fn get_memory_policy(&self) -> Option<MemoryPolicy> {
+        self.memory.clone()
+    }
+
+    fn get_evaluation(&self) -> Option<Evaluation> {
+        self.evaluation.clone()
+    }
+
+    fn get_storage_quotas(&self) -> HashMap<String, StorageQuota> {
+        self.quotas.clone().unwrap_or_default()
+    }
+
+    fn show_storage_ages(&self) -> bool {
+        self.storage_ages
+    }
+
+    fn get_storage_deltas(&self) -> Option<usize> {
+        self.storage_deltas
+    }
+
+    fn get_report_template(&self) -> Option<ReportTemplate> {
+        self.report.clone()
+    }
+
+    fn get_output_filter(&self, action_name: &str) -> Option<OutputFilter> {
+        if let Some(filter) = self.filters.as_ref().and_then(|f| f.get(action_name)) {
+            return Some(filter.clone());
+        }
+
+        self.functions
+            .iter()
+            .flatten()
+            .flat_map(|group| group.actions.iter())
+            .find(|action| action.name == action_name)
+            .and_then(|action| action.filter.clone())
+    }
+
+    fn get_max_duration(&self) -> Option<Duration> {
+        parse_duration(self.max_duration.as_ref())
+    }
+
+    fn get_rag_config(&self) -> Option<RagConfig> {
+        self.rag.clone()
+    }
+
+    fn get_handoffs(&self) -> Vec<Handoff> {
+        self.handoffs.clone().unwrap_or_default()
+    }
+
+    fn to_system_prompt(&self) -> Result<String> {
+        Ok(self.system_prompt.to_string())
+    }
+
+    fn to_prompt(&self) -> Result<String> {
+        if let Some(prompt) = &self.prompt {
+            Ok(prompt.to_string())
+        } else {
+            Err(anyhow!("prompt not specified"))
+        }
+    }
+
+    fn namespaces(&self) -> Option<Vec<String>> {
+        self.using.clone()
+    }
+
+    fn examples(&self) -> Vec<Example> {
+        self.examples.clone().unwrap_or_default()
+    }
+
+    fn hooks(&self) -> Hooks {
+        self.hooks.clone()
+    }
+
+    fn guidance(&self) -> Result<Vec<String>> {
+        let base = self.base_guidance()?;
+        // extend the set of basic rules
+        Ok([base, self.guidance.as_ref().unwrap_or(&vec![]).clone()].concat())
+    }
+
+    fn get_functions(&self) -> Vec<Namespace> {
+        let mut groups = vec![];
+
+        if let Some(custom_functions) = self.functions.as_ref() {
+            for group in custom_functions {
+                groups.push(group.compile(&self.folder, None).unwrap());
+            }
+        }
+
+        if let Some(storages) = self.storages.as_ref() {
+            if !storages.is_empty() {
+                groups.push(super::storages::compile(storages).unwrap());
+            }
+        }
+
+        for plugin in &self.started_plugins {
+            groups.push(plugin.to_namespace());
+        }
+
+        #[cfg(feature = "mcp")]
+        for server in &self.connected_mcp {
+            groups.push(server.to_namespace());
+        }
+
+        #[cfg(feature = "openapi")]
+        if let Some(openapi) = &self.openapi_namespace {
+            groups.push(openapi.to_namespace());
+        }
+
+        if !self.robopages.is_empty() {
+            for group in &self.robopages {
+                groups.push(
+                    group
+                        .compile(&self.folder, self.robopages_server_address.clone())
+                        .unwrap(),
+                );
+            }
+        }
+
+        groups
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent::{
+        task::variables,
+        testing::{new_shared_state, TestTask},
+    };
+
+    fn write_tasklet(name: &str, yaml: &str) -> PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("nerve-tasklet-{}-{}.yml", name, std::process::id()));
+        std::fs::write(&path, yaml).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_timeouts() {
+        let path = write_tasklet(
+            "timeouts",
+            r#"
+system_prompt: test
+prompt: test
+timeout: 30s
+max_duration: 1h
+timeouts:
+  slow: 5m
+functions:
+  - name: Test
+    actions:
+      - name: slow
+        description: slow
+        tool: "true"
+        timeout: 1m
+      - name: quick
+        description: quick
+        tool: "true"
+        timeout: 10s
+      - name: other
+        description: other
+        tool: "true"
+"#,
+        );
+        let tasklet = Tasklet::from_path(path.to_str().unwrap(), &vec![]).unwrap();
+        let actions = &tasklet.functions.as_ref().unwrap()[0].actions;
+
+        assert_eq!(tasklet.get_timeout(), Some(Duration::from_secs(30)));
+        assert_eq!(tasklet.get_max_duration(), Some(Duration::from_secs(3600)));
+        // the timeouts of the tasklet win over the ones of the actions
+        assert_eq!(
+            tasklet.get_action_timeout("slow"),
+            Some(Duration::from_secs(300))
+        );
+        assert_eq!(actions[0].timeout(), Some(Duration::from_secs(60)));
+        assert_eq!(tasklet.get_action_timeout("quick"), None);
+        assert_eq!(actions[1].timeout(), Some(Duration::from_secs(10)));
+        assert_eq!(actions[2].timeout(), None);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_destructive_actions() {
+        let tool: TaskletAction =
+            serde_yaml::from_str("name: tool\ndescription: tool\ntool: \"true\"").unwrap();
+        assert!(tool.is_destructive());
+
+        let judge: TaskletAction =
+            serde_yaml::from_str("name: judge\ndescription: judge\njudge: judge.yml").unwrap();
+        assert!(!judge.is_destructive());
+    }
+
+    #[test]
+    fn test_invalid_durations() {
+        for (name, invalid) in [
+            ("max-duration", "max_duration: 30 minutes"),
+            ("timeout", "timeout: soon"),
+            ("timeouts", "timeouts:\n  slow: 5 mins"),
+            (
+                "action-timeout",
+                "functions:\n  - name: Test\n    actions:\n      - name: slow\n        description: slow\n        tool: \"true\"\n        timeout: 1 minute",
+            ),
+        ] {
+            let path = write_tasklet(name, &format!("system_prompt: test\n{}\n", invalid));
+            let err = Tasklet::from_path(path.to_str().unwrap(), &vec![])
+                .err()
+                .unwrap_or_else(|| panic!("{} accepted", invalid));
+            assert!(err.to_string().starts_with("invalid"), "{}", err);
+            std::fs::remove_file(&path).unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_action_env_cwd_and_stdin() {
+        let tmp = std::env::temp_dir().join(format!("nerve-tasklet-action-{}", std::process::id()));
+        std::fs::create_dir_all(tmp.join("sub")).unwrap();
+        let tmp = tmp.canonicalize().unwrap();
+        let script = tmp.join("probe.sh");
+        std::fs::write(&script, "echo \"$GREETING $TARGET\"\npwd\ncat\n").unwrap();
+
+        let mut action: TaskletAction = serde_yaml::from_str(&format!(
+            r#"
+name: probe
+description: prints its environment, folder and input
+tool: sh {}
+env:
+  GREETING: hello
+  TARGET: $NERVE_TEST_ACTION_TARGET
+cwd: sub
+stdin: "payload=$PAYLOAD"
+"#,
+            script.display()
+        ))
+        .unwrap();
+        action.working_directory = tmp.to_string_lossy().to_string();
+
+        let state = new_shared_state(TestTask::default()).await;
+        let out = variables::scoped(
+            HashMap::from([("NERVE_TEST_ACTION_TARGET".to_string(), "world".to_string())]),
+            action.run(state, None, Some("data".to_string())),
+        )
+        .await
+        .unwrap()
+        .unwrap();
+
+        // since the payload is sent via stdin it's not added to the arguments
+        assert_eq!(
+            out,
+            format!("hello world\n{}\npayload=data", tmp.join("sub").display())
+        );
+
+        std::fs::remove_dir_all(&tmp).unwrap();
+    }
+}
@@ -0,0 +1,323 @@
+use std::{collections::HashMap, future::Future, io::IsTerminal, sync::Mutex};
+
+use anyhow::Result;
+use colored::Colorize;
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde::Deserialize;
+use serde_trim::*;
+
+use crate::agent::{get_user_input, get_user_secret_input, secrets};
+
+lazy_static! {
+    static ref VAR_CACHE: Mutex<HashMap<String, String>> = Mutex::new(HashMap::new());
+    static ref VAR_PARSER: Regex =
+        Regex::new(r"(?m)(\$[A-Za-z][A-Za-z0-9_]+)(\|\|[^\s]+)?").unwrap();
+}
+
+tokio::task_local! {
+    // the variables of a single run when several runs share the process
+    static VAR_SCOPE: Mutex<HashMap<String, String>>;
+}
+
+// run the closure on the variables of the current scope if any, or on the global ones
+fn with_variables<R>(f: impl FnOnce(&mut HashMap<String, String>) -> R) -> R {
+    let mut f = Some(f);
+    match VAR_SCOPE.try_with(|scope| (f.take().unwrap())(&mut scope.lock().unwrap())) {
+        Ok(ret) => ret,
+        Err(_) => (f.take().unwrap())(&mut VAR_CACHE.lock().unwrap()),
+    }
+}
+
+// run the future with its own copy of the global variables plus the given ones, anything
+// defined while it runs is only visible to it
+pub async fn scoped<F: Future>(variables: HashMap<String, String>, f: F) -> F::Output {
+    let mut scope = get_variables();
+    scope.extend(variables);
+    VAR_SCOPE.scope(Mutex::new(scope), f).await
+}
+
+pub fn define_variable(name: &str, value: &str) {
+    with_variables(|vars| vars.insert(name.to_owned(), value.to_owned()));
+}
+
+pub fn get_variable(name: &str) -> Option<String> {
+    with_variables(|vars| vars.get(name).cloned())
+}
+
+pub fn get_variables() -> HashMap<String, String> {
+    with_variables(|vars| vars.clone())
+}
+
+// a variable declared by the tasklet and resolved before the task starts
+#[derive(Deserialize, Debug, Clone)]
+pub struct VariableDeclaration {
+    #[serde(deserialize_with = "string_trim")]
+    pub name: String,
+    pub description: Option<String>,
+    pub default: Option<String>,
+    #[serde(default)]
+    pub secret: bool,
+}
+
+impl VariableDeclaration {
+    pub fn resolve(&self) -> Result<()> {
+        self.lookup()?;
+
+        if self.secret {
+            if let Some(value) = get_variable(&self.name) {
+                secrets::register(&value);
+            }
+        }
+
+        Ok(())
+    }
+
+    // the order of lookup is cache (-D), environment, default and finally the user
+    fn lookup(&self) -> Result<()> {
+        if get_variable(&self.name).is_some() {
+            return Ok(());
+        }
+
+        if let Ok(value) = std::env::var(&self.name) {
+            define_variable(&self.name, &value);
+            return Ok(());
+        }
+
+        if let Some(default) = &self.default {
+            define_variable(&self.name, default);
+            return Ok(());
+        }
+
+        let description = if let Some(desc) = &self.description {
+            format!(" ({})", desc.trim())
+        } else {
+            "".to_string()
+        };
+
+        if !std::io::stdin().is_terminal() {
+            return Err(anyhow!(
+                "variable ${}{} is required, set it via environment or with --var {}=...",
+                &self.name,
+                description,
+                &self.name
+            ));
+        }
+
+        let prompt = format!(
+            "\nplease set ${}{}: ",
+            self.name.yellow(),
+            description.dimmed()
+        );
+        let value = if self.secret {
+            get_user_secret_input(&prompt)
+        } else {
+            get_user_input(&prompt)
+        };
+
+        define_variable(&self.name, &value);
+
+        Ok(())
+    }
+}
+
+pub fn parse_pre_defined_values(defines: &Vec<String>) -> Result<()> {
+    for keyvalue in defines {
+        let parts: Vec<&str> = keyvalue.splitn(2, '=').collect();
+        if parts.len() != 2 {
+            return Err(anyhow!("can't parse {keyvalue}, syntax is: key=value"));
+        }
+
+        define_variable(parts[0], parts[1]);
+    }
+
+    Ok(())
+}
+
+pub fn interpolate_variables(expr: &str) -> Result<String> {
+    let matches = VAR_PARSER.captures_iter(expr);
+    let mut interpolated = expr.to_string();
+
+    for m in matches {
+        let var_expr = m.get(0).unwrap().as_str();
+        let (_, var_value) = parse_variable_expr(var_expr)?;
+        interpolated = interpolated.replace(var_expr, &var_value);
+    }
+
+    Ok(interpolated)
+}
+
+pub fn parse_variable_expr(expr: &str) -> Result<(String, String)> {
+    if expr.as_bytes()[0] != b'$' {
+        return Err(anyhow!("'{}' is not a valid variable expression", expr));
+    }
+
+    // variable, the order of lookup is:
+    //  0. cache (-D, --var and --var-file)
+    //  1. environment variable
+    //  2. if an alternative default was provided via || use it
+    //  3. ask the user (and cache)
+    let var_name = expr.trim_start_matches('$');
+    let (var_name, var_default) = if let Some((name, default_value)) = var_name.split_once("||") {
+        (name, Some(default_value))
+    } else {
+        (var_name, None)
+    };
+
+    let var_value = if let Some(cached) = get_variable(var_name) {
+        // get from cached
+        cached
+    } else if let Ok(value) = std::env::var(var_name) {
+        // get from env
+        value
+    } else if let Some(var_default) = var_default {
+        // get from default
+        var_default.to_string()
+    } else {
+        // get from user
+        let var_value = get_user_input(&format!("\nplease set ${}: ", var_name.yellow()));
+        define_variable(var_name, &var_value);
+        var_value
+    };
+
+    Ok((var_name.to_string(), var_value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent::test_env::TestEnv;
+
+    #[test]
+    fn test_declared_variable_default() {
+        let decl: VariableDeclaration = serde_yaml::from_str(
+            "name: NERVE_TEST_DECL_DEFAULT\ndescription: some value\ndefault: foo",
+        )
+        .unwrap();
+
+        decl.resolve().unwrap();
+
+        assert_eq!(
+            get_variable("NERVE_TEST_DECL_DEFAULT"),
+            Some("foo".to_string())
+        );
+    }
+
+    #[test]
+    fn test_declared_variable_keeps_defined_value() {
+        define_variable("NERVE_TEST_DECL_DEFINED", "bar");
+
+        let decl: VariableDeclaration =
+            serde_yaml::from_str("name: NERVE_TEST_DECL_DEFINED\ndefault: foo\nsecret: true")
+                .unwrap();
+
+        decl.resolve().unwrap();
+
+        assert!(decl.secret);
+        assert_eq!(
+            get_variable("NERVE_TEST_DECL_DEFINED"),
+            Some("bar".to_string())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_scoped_variables() {
+        define_variable("NERVE_TEST_SCOPE_GLOBAL", "global");
+
+        let first = scoped(
+            HashMap::from([("NERVE_TEST_SCOPE".to_string(), "first".to_string())]),
+            async {
+                tokio::task::yield_now().await;
+                define_variable("NERVE_TEST_SCOPE_DEFINED", "first");
+                (
+                    get_variable("NERVE_TEST_SCOPE"),
+                    get_variable("NERVE_TEST_SCOPE_GLOBAL"),
+                )
+            },
+        );
+        let second = scoped(
+            HashMap::from([("NERVE_TEST_SCOPE".to_string(), "second".to_string())]),
+            async {
+                tokio::task::yield_now().await;
+                (
+                    get_variable("NERVE_TEST_SCOPE"),
+                    get_variable("NERVE_TEST_SCOPE_DEFINED"),
+                )
+            },
+        );
+
+        let (first, second) = tokio::join!(first, second);
+
+        assert_eq!(first.0, Some("first".to_string()));
+        assert_eq!(first.1, Some("global".to_string()));
+        assert_eq!(second, (Some("second".to_string()), None));
+        assert_eq!(get_variable("NERVE_TEST_SCOPE"), None);
+        assert_eq!(get_variable("NERVE_TEST_SCOPE_DEFINED"), None);
+    }
+
+    #[test]
+    fn test_declared_variable_precedence() {
+        let mut env = TestEnv::new();
+        env.set("NERVE_TEST_DECL_PRECEDENCE", "from-env");
+
+        let decl: VariableDeclaration =
+            serde_yaml::from_str("name: NERVE_TEST_DECL_PRECEDENCE\ndefault: from-default")
+                .unwrap();
+
+        // -D wins over the environment
+        parse_pre_defined_values(&vec!["NERVE_TEST_DECL_PRECEDENCE=from-define".to_string()])
+            .unwrap();
+        decl.resolve().unwrap();
+        assert_eq!(
+            get_variable("NERVE_TEST_DECL_PRECEDENCE"),
+            Some("from-define".to_string())
+        );
+
+        // the environment wins over the default
+        VAR_CACHE
+            .lock()
+            .unwrap()
+            .remove("NERVE_TEST_DECL_PRECEDENCE");
+        decl.resolve().unwrap();
+        assert_eq!(
+            get_variable("NERVE_TEST_DECL_PRECEDENCE"),
+            Some("from-env".to_string())
+        );
+
+        // the default is used when nothing else is set
+        VAR_CACHE
+            .lock()
+            .unwrap()
+            .remove("NERVE_TEST_DECL_PRECEDENCE");
+        env.remove("NERVE_TEST_DECL_PRECEDENCE");
+        decl.resolve().unwrap();
+        assert_eq!(
+            get_variable("NERVE_TEST_DECL_PRECEDENCE"),
+            Some("from-default".to_string())
+        );
+    }
+
+    #[test]
+    fn test_interpolation_precedence() {
+        let mut env = TestEnv::new();
+        env.set("NERVE_TEST_INTERPOLATION", "from-env");
+        assert_eq!(
+            interpolate_variables("target=$NERVE_TEST_INTERPOLATION").unwrap(),
+            "target=from-env"
+        );
+
+        // --var wins over the environment
+        parse_pre_defined_values(&vec!["NERVE_TEST_INTERPOLATION=from-var".to_string()]).unwrap();
+        assert_eq!(
+            interpolate_variables("target=$NERVE_TEST_INTERPOLATION").unwrap(),
+            "target=from-var"
+        );
+        assert_eq!(
+            parse_variable_expr("$NERVE_TEST_INTERPOLATION||default").unwrap(),
+            (
+                "NERVE_TEST_INTERPOLATION".to_string(),
+                "from-var".to_string()
+            )
+        );
+    }
+}
@@ -0,0 +1,329 @@
+use std::collections::BTreeMap;
+#[cfg(feature = "wasm")]
+use std::{collections::HashMap, path::PathBuf, process::Stdio};
+
+use anyhow::Result;
+use serde::Deserialize;
+#[cfg(feature = "wasm")]
+use serde::Serialize;
+#[cfg(feature = "wasm")]
+use tokio::{io::AsyncWriteExt, process::Command};
+
+#[cfg(feature = "wasm")]
+use crate::agent::policy::FilesystemPolicy;
+
+#[cfg(feature = "wasm")]
+const DEFAULT_RUNTIME: &str = "wasmtime";
+
+// an action implemented by a WASI module, executed by a sandboxed runtime that only gives it
+// access to its stdin, stdout, stderr and to the folders and variables explicitly allowed
+#[derive(Deserialize, Debug, Clone, Default)]
+// the runtime is up to whoever runs nerve, a tasklet setting it could run anything
+#[serde(deny_unknown_fields)]
+pub struct WasmModule {
+    // path of the .wasm file, relative to the tasklet folder
+    pub module: String,
+    // folders the module can access, relative to the tasklet folder
+    #[serde(default)]
+    pub dirs: Vec<String>,
+    // environment variables visible to the module
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+    // the tasklet folder, the only one the dirs can be in unless the filesystem policy allows more
+    #[cfg(feature = "wasm")]
+    #[serde(skip)]
+    folder: PathBuf,
+}
+
+// what the module receives as json on its stdin
+#[cfg(feature = "wasm")]
+#[derive(Serialize, Debug)]
+struct Request<'a> {
+    action: &'a str,
+    attributes: BTreeMap<&'a String, &'a String>,
+    payload: Option<&'a String>,
+}
+
+#[cfg(feature = "wasm")]
+impl WasmModule {
+    fn resolve(folder: &str, path: &str) -> PathBuf {
+        let path = PathBuf::from(path);
+        if path.is_relative() {
+            PathBuf::from(folder).join(path)
+        } else {
+            path
+        }
+    }
+
+    // make paths absolute and check that they exist, resolving .. and symlinks of the folders
+    pub fn prepare(&mut self, folder: &str) -> Result<()> {
+        let module = Self::resolve(folder, &self.module);
+        if !module.is_file() {
+            return Err(anyhow!("wasm module '{}' not found", module.display()));
+        }
+        self.module = module.display().to_string();
+        self.folder = PathBuf::from(folder)
+            .canonicalize()
+            .map_err(|e| anyhow!("tasklet folder '{}': {}", folder, e))?;
+
+        for dir in self.dirs.iter_mut() {
+            let path = Self::resolve(folder, dir);
+            let path = match path.canonicalize() {
+                Ok(path) if path.is_dir() => path,
+                _ => return Err(anyhow!("wasm module folder '{}' not found", path.display())),
+            };
+            *dir = path.display().to_string();
+        }
+
+        Ok(())
+    }
+
+    // folders outside of the tasklet one are only mounted if the filesystem policy allows them
+    pub fn check_dirs(&self, policy: &FilesystemPolicy) -> Result<()> {
+        for dir in &self.dirs {
+            if PathBuf::from(dir).starts_with(&self.folder) {
+                continue;
+            }
+            if policy.roots.is_empty() || policy.check(dir).is_err() {
+                return Err(anyhow!(
+                    "wasm module folder '{}' is outside of the tasklet folder and not allowed by the filesystem policy",
+                    dir
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    // runtime executable, $NERVE_WASM_RUNTIME or wasmtime by default
+    fn runtime(&self) -> String {
+        std::env::var("NERVE_WASM_RUNTIME").unwrap_or(DEFAULT_RUNTIME.to_string())
+    }
+
+    fn command(&self) -> Command {
+        let mut cmd = Command::new(self.runtime());
+        cmd.arg("run");
+        for dir in &self.dirs {
+            cmd.arg("--dir").arg(dir);
+        }
+        for (key, value) in &self.env {
+            cmd.arg("--env").arg(format!("{}={}", key, value));
+        }
+        cmd.arg(&self.module)
+            // the module doesn't inherit anything from nerve
+            .env_clear()
+            .env("PATH", std::env::var("PATH").unwrap_or_default())
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            // timed out invocations must not leave the module running
+            .kill_on_drop(true);
+        cmd
+    }
+
+    pub async fn run(
+        &self,
+        policy: &FilesystemPolicy,
+        action: &str,
+        attributes: &Option<HashMap<String, String>>,
+        payload: &Option<String>,
+    ) -> Result<Option<String>> {
+        self.check_dirs(policy)?;
+
+        let request = serde_json::to_string(&Request {
+            action,
+            attributes: attributes.iter().flatten().collect(),
+            payload: payload.as_ref(),
+        })?;
+
+        let mut child = self
+            .command()
+            .spawn()
+            .map_err(|e| anyhow!("could not execute wasm runtime '{}': {}", self.runtime(), e))?;
+
+        let mut stdin = child.stdin.take().unwrap();
+        stdin.write_all(request.as_bytes()).await?;
+        drop(stdin);
+
+        let output = child.wait_with_output().await?;
+        let out = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        let err = String::from_utf8_lossy(&output.stderr).trim().to_string();
+
+        if !output.status.success() {
+            Err(anyhow!(
+                "wasm module exited with {}: {}",
+                output.status,
+                if err.is_empty() { &out } else { &err }
+            ))
+        } else if out.is_empty() {
+            Ok(None)
+        } else {
+            Ok(Some(out))
+        }
+    }
+}
+
+#[cfg(not(feature = "wasm"))]
+impl WasmModule {
+    pub fn prepare(&mut self, _folder: &str) -> Result<()> {
+        Err(anyhow!(
+            "can't use wasm module {}, nerve was built without the wasm feature",
+            &self.module
+        ))
+    }
+}
+
+#[cfg(all(test, feature = "wasm"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_command() {
+        let tmp = std::env::temp_dir().join("nerve-wasm-test");
+        std::fs::create_dir_all(tmp.join("data")).unwrap();
+        std::fs::write(tmp.join("tool.wasm"), b"\0asm").unwrap();
+
+        let mut module: WasmModule = serde_yaml::from_str(
+            r#"
+module: tool.wasm
+dirs: [data]
+env: { MODE: fast }
+"#,
+        )
+        .unwrap();
+        module.prepare(tmp.to_str().unwrap()).unwrap();
+
+        let cmd = module.command();
+        let cmd = cmd.as_std();
+        assert_eq!(cmd.get_program().to_str().unwrap(), module.runtime());
+        assert_eq!(
+            cmd.get_args().collect::<Vec<_>>(),
+            vec![
+                "run",
+                "--dir",
+                tmp.join("data").canonicalize().unwrap().to_str().unwrap(),
+                "--env",
+                "MODE=fast",
+                tmp.join("tool.wasm").to_str().unwrap(),
+            ]
+        );
+
+        let mut missing = WasmModule {
+            module: "nope.wasm".to_string(),
+            ..Default::default()
+        };
+        assert!(missing.prepare(tmp.to_str().unwrap()).is_err());
+
+        // tasklets can't pick the runtime
+        assert!(serde_yaml::from_str::<WasmModule>("module: tool.wasm\nruntime: /bin/sh").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_dirs_outside_of_the_folder() {
+        let root = std::env::temp_dir().join(format!("nerve-wasm-dirs-{}", std::process::id()));
+        let tmp = root.join("tasklet");
+        std::fs::create_dir_all(tmp.join("data")).unwrap();
+        std::fs::create_dir_all(root.join("shared")).unwrap();
+        std::fs::write(tmp.join("tool.wasm"), b"\0asm").unwrap();
+        let no_policy = FilesystemPolicy::default();
+
+        let mut module = WasmModule {
+            module: "tool.wasm".to_string(),
+            dirs: vec!["./data/../data".to_string()],
+            ..Default::default()
+        };
+        module.prepare(tmp.to_str().unwrap()).unwrap();
+        assert!(module.check_dirs(&no_policy).is_ok());
+
+        for dir in ["/", "..", "../shared", "data/../.."] {
+            let mut module = WasmModule {
+                module: "tool.wasm".to_string(),
+                dirs: vec![dir.to_string()],
+                ..Default::default()
+            };
+            module.prepare(tmp.to_str().unwrap()).unwrap();
+            assert!(module.check_dirs(&no_policy).is_err(), "{} allowed", dir);
+            // refused before the runtime is executed
+            let err = module
+                .run(&no_policy, "tool", &None, &None)
+                .await
+                .unwrap_err();
+            assert!(err
+                .to_string()
+                .contains("not allowed by the filesystem policy"));
+        }
+
+        // unless the filesystem policy allows them
+        let policy = FilesystemPolicy {
+            roots: vec![root.join("shared").display().to_string()],
+            confirm: false,
+        };
+        let mut module = WasmModule {
+            module: "tool.wasm".to_string(),
+            dirs: vec!["../shared".to_string()],
+            ..Default::default()
+        };
+        module.prepare(tmp.to_str().unwrap()).unwrap();
+        assert!(module.check_dirs(&policy).is_ok());
+        module.dirs.push("/".to_string());
+        assert!(module.check_dirs(&policy).is_err());
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    // tries to open a file in the allowed folder and one outside of it, through the first
+    // preopened folder, printing allowed or denied for each
+    const SANDBOX_MODULE: &str = r#"
+(module
+  (import "wasi_snapshot_preview1" "path_open"
+    (func $path_open (param i32 i32 i32 i32 i32 i64 i64 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_write"
+    (func $fd_write (param i32 i32 i32 i32) (result i32)))
+  (memory (export "memory") 1)
+  (data (i32.const 100) "inside.txt")
+  (data (i32.const 120) "../outside.txt")
+  (data (i32.const 200) "allowed\n")
+  (data (i32.const 220) "denied\n")
+  (func $open (param $path i32) (param $len i32) (result i32)
+    (call $path_open
+      (i32.const 3) (i32.const 0) (local.get $path) (local.get $len)
+      (i32.const 0) (i64.const 2) (i64.const 0) (i32.const 0) (i32.const 16)))
+  (func $print (param $allowed i32)
+    (if (local.get $allowed)
+      (then
+        (i32.store (i32.const 0) (i32.const 200))
+        (i32.store (i32.const 4) (i32.const 8)))
+      (else
+        (i32.store (i32.const 0) (i32.const 220))
+        (i32.store (i32.const 4) (i32.const 7))))
+    (drop (call $fd_write (i32.const 1) (i32.const 0) (i32.const 1) (i32.const 8))))
+  (func (export "_start")
+    (call $print (i32.eqz (call $open (i32.const 100) (i32.const 10))))
+    (call $print (i32.eqz (call $open (i32.const 120) (i32.const 14))))))
+"#;
+
+    #[tokio::test]
+    #[ignore = "needs wasmtime"]
+    async fn test_sandbox() {
+        let tmp = std::env::temp_dir().join(format!("nerve-wasm-sandbox-{}", std::process::id()));
+        std::fs::create_dir_all(tmp.join("data")).unwrap();
+        std::fs::write(tmp.join("data").join("inside.txt"), "inside").unwrap();
+        std::fs::write(tmp.join("outside.txt"), "outside").unwrap();
+        // the runtime compiles the text format as well
+        std::fs::write(tmp.join("sandbox.wat"), SANDBOX_MODULE).unwrap();
+
+        let mut module = WasmModule {
+            module: "sandbox.wat".to_string(),
+            dirs: vec!["data".to_string()],
+            ..Default::default()
+        };
+        module.prepare(tmp.to_str().unwrap()).unwrap();
+
+        let out = module
+            .run(&FilesystemPolicy::default(), "sandbox", &None, &None)
+            .await
+            .unwrap();
+        assert_eq!(out, Some("allowed\ndenied".to_string()));
+
+        std::fs::remove_dir_all(&tmp).unwrap();
+    }
+}
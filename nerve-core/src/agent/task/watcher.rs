@@ -0,0 +1,188 @@
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    time::{Duration, Instant, SystemTime},
+};
+
+use anyhow::Result;
+
+use super::rag::{RagConfig, RagStore};
+
+// keeps the rag store in sync with its sources while the agent is running by periodically
+// comparing the modification times of the files, without requiring platform specific apis
+pub struct RagWatcher {
+    config: RagConfig,
+    interval: Duration,
+    last_scan: Instant,
+    known: HashMap<PathBuf, SystemTime>,
+}
+
+fn modified_times(files: Vec<PathBuf>) -> HashMap<PathBuf, SystemTime> {
+    files
+        .into_iter()
+        .filter_map(|path| {
+            let modified = path.metadata().and_then(|m| m.modified()).ok()?;
+            Some((path, modified))
+        })
+        .collect()
+}
+
+// files that are new or changed in current, and the ones that are gone
+fn diff(
+    known: &HashMap<PathBuf, SystemTime>,
+    current: &HashMap<PathBuf, SystemTime>,
+) -> (Vec<PathBuf>, Vec<PathBuf>) {
+    let mut changed: Vec<PathBuf> = current
+        .iter()
+        .filter(|(path, modified)| known.get(*path) != Some(modified))
+        .map(|(path, _)| path.clone())
+        .collect();
+    let mut removed: Vec<PathBuf> = known
+        .keys()
+        .filter(|path| !current.contains_key(*path))
+        .cloned()
+        .collect();
+
+    changed.sort();
+    removed.sort();
+
+    (changed, removed)
+}
+
+impl RagWatcher {
+    // returns None if watching is not enabled, must be created after the initial import
+    pub fn new(config: &RagConfig) -> Result<Option<Self>> {
+        let interval = match config.watch_interval() {
+            Some(interval) => interval,
+            None => return Ok(None),
+        };
+
+        Ok(Some(Self {
+            config: config.clone(),
+            interval,
+            last_scan: Instant::now(),
+            known: modified_times(config.files()?),
+        }))
+    }
+
+    // import the documents that changed since the last scan, if enough time passed
+    pub async fn refresh(&mut self, store: &mut Box<dyn RagStore>) -> Result<()> {
+        if self.last_scan.elapsed() < self.interval {
+            return Ok(());
+        }
+
+        let start = Instant::now();
+        let current = modified_times(self.config.files()?);
+        let (changed, removed) = diff(&self.known, &current);
+        self.last_scan = Instant::now();
+        self.known = current;
+
+        if changed.is_empty() && removed.is_empty() {
+            return Ok(());
+        }
+
+        log::info!(
+            "rag: {} new or changed and {} removed documents",
+            changed.len(),
+            removed.len()
+        );
+
+        for path in &removed {
+            store.prune(path, &[]).await?;
+        }
+        // importing a file prunes the chunks of its previous version
+        if !changed.is_empty() {
+            self.config.import_files(store, Some(&changed)).await?;
+        }
+
+        log::debug!("rag: reindexed in {:?}", start.elapsed());
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use async_trait::async_trait;
+    use mini_rag::{Embedder, Embeddings};
+
+    use super::*;
+
+    // embeds the length of the text
+    struct LengthEmbedder;
+
+    #[async_trait]
+    impl Embedder for LengthEmbedder {
+        async fn embed(&self, text: &str) -> Result<Embeddings> {
+            Ok(vec![text.len() as f64, 1.0])
+        }
+    }
+
+    #[tokio::test]
+    async fn test_refresh_prunes_changed_and_removed() {
+        let folder = std::env::temp_dir().join("nerve-watcher-test");
+        let _ = std::fs::remove_dir_all(&folder);
+        std::fs::create_dir_all(folder.join("docs")).unwrap();
+        let changed = folder.join("docs/changed.md");
+        let removed = folder.join("docs/removed.md");
+        std::fs::write(&changed, "first version").unwrap();
+        std::fs::write(&removed, "removed soon").unwrap();
+
+        let config: RagConfig = serde_yaml::from_str(&format!(
+            "data_path: {}\nsources:\n  - path: {}\nwatch: 1ms",
+            folder.join("data").display(),
+            folder.join("docs").display()
+        ))
+        .unwrap();
+        let mut store = config.create_store(Box::new(LengthEmbedder)).await.unwrap();
+        config.import(&mut store).await.unwrap();
+        let mut watcher = RagWatcher::new(&config).unwrap().unwrap();
+
+        std::fs::write(&changed, "second version").unwrap();
+        std::fs::File::options()
+            .write(true)
+            .open(&changed)
+            .unwrap()
+            .set_modified(SystemTime::now() + Duration::from_secs(10))
+            .unwrap();
+        std::fs::remove_file(&removed).unwrap();
+        std::thread::sleep(Duration::from_millis(5));
+        watcher.refresh(&mut store).await.unwrap();
+
+        let docs = store.retrieve("anything", 10).await.unwrap();
+        assert_eq!(docs.len(), 1);
+        assert!(docs[0].data.ends_with("second version"), "{}", docs[0].data);
+        assert_eq!(
+            std::fs::read_dir(folder.join("data/chunks"))
+                .unwrap()
+                .count(),
+            1
+        );
+
+        std::fs::remove_dir_all(&folder).unwrap();
+    }
+
+    #[test]
+    fn test_diff() {
+        let t0 = SystemTime::UNIX_EPOCH;
+        let t1 = t0 + Duration::from_secs(1);
+
+        let known = HashMap::from([
+            (PathBuf::from("same.md"), t0),
+            (PathBuf::from("changed.md"), t0),
+            (PathBuf::from("removed.md"), t0),
+        ]);
+        let current = HashMap::from([
+            (PathBuf::from("same.md"), t0),
+            (PathBuf::from("changed.md"), t1),
+            (PathBuf::from("new.md"), t1),
+        ]);
+
+        let (changed, removed) = diff(&known, &current);
+        assert_eq!(
+            changed,
+            vec![PathBuf::from("changed.md"), PathBuf::from("new.md")]
+        );
+        assert_eq!(removed, vec![PathBuf::from("removed.md")]);
+    }
+}
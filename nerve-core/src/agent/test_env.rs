@@ -0,0 +1,72 @@
+use std::{
+    ffi::OsString,
+    sync::{Mutex, MutexGuard},
+};
+
+static ENV_LOCK: Mutex<()> = Mutex::new(());
+
+// the process environment is shared by the tests running in parallel, so the ones changing it
+// run one at a time and the variables they set or remove get their previous values back when
+// done, even if they fail
+pub struct TestEnv {
+    saved: Vec<(String, Option<OsString>)>,
+    _lock: MutexGuard<'static, ()>,
+}
+
+impl TestEnv {
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> Self {
+        Self {
+            saved: vec![],
+            _lock: ENV_LOCK.lock().unwrap_or_else(|e| e.into_inner()),
+        }
+    }
+
+    fn save(&mut self, name: &str) {
+        if !self.saved.iter().any(|(saved, _)| saved == name) {
+            self.saved.push((name.to_string(), std::env::var_os(name)));
+        }
+    }
+
+    pub fn set(&mut self, name: &str, value: &str) {
+        self.save(name);
+        std::env::set_var(name, value);
+    }
+
+    pub fn remove(&mut self, name: &str) {
+        self.save(name);
+        std::env::remove_var(name);
+    }
+}
+
+impl Drop for TestEnv {
+    fn drop(&mut self) {
+        for (name, value) in &self.saved {
+            match value {
+                Some(value) => std::env::set_var(name, value),
+                None => std::env::remove_var(name),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_restores_previous_values() {
+        let mut env = TestEnv::new();
+        std::env::set_var("NERVE_TEST_ENV_SET", "before");
+        std::env::remove_var("NERVE_TEST_ENV_NEW");
+        env.set("NERVE_TEST_ENV_SET", "changed");
+        env.set("NERVE_TEST_ENV_SET", "changed again");
+        env.set("NERVE_TEST_ENV_NEW", "value");
+        env.remove("NERVE_TEST_ENV_SET");
+        drop(env);
+
+        assert_eq!(std::env::var("NERVE_TEST_ENV_SET").unwrap(), "before");
+        assert!(std::env::var("NERVE_TEST_ENV_NEW").is_err());
+        std::env::remove_var("NERVE_TEST_ENV_SET");
+    }
+}
@@ -0,0 +1,65 @@
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+
+use super::{
+    events::create_channel,
+    namespaces::Namespace,
+    state::{SharedState, State},
+    task::Task,
+};
+
+// a task without prompts, exposing the given namespaces
+#[derive(Debug, Default)]
+pub(crate) struct TestTask {
+    pub namespaces: Vec<fn() -> Namespace>,
+    pub storage_deltas: Option<usize>,
+    pub max_steps: Option<usize>,
+}
+
+impl Task for TestTask {
+    fn to_system_prompt(&self) -> Result<String> {
+        Ok("".to_string())
+    }
+
+    fn to_prompt(&self) -> Result<String> {
+        Ok("".to_string())
+    }
+
+    fn get_functions(&self) -> Vec<Namespace> {
+        self.namespaces
+            .iter()
+            .map(|namespace| namespace())
+            .collect()
+    }
+
+    fn get_storage_deltas(&self) -> Option<usize> {
+        self.storage_deltas
+    }
+
+    fn get_max_steps(&self) -> Option<usize> {
+        self.max_steps
+    }
+}
+
+// tests don't do rag, any attempt to embed is an error
+pub(crate) struct TestEmbedder {}
+
+#[async_trait]
+impl mini_rag::Embedder for TestEmbedder {
+    async fn embed(&self, _text: &str) -> Result<mini_rag::Embeddings> {
+        Err(anyhow!("embeddings are not supported in tests"))
+    }
+}
+
+// the events are consumed and discarded for as long as the state is around
+pub(crate) async fn new_state(task: TestTask) -> State {
+    let (tx, mut rx) = create_channel();
+    tokio::spawn(async move { while rx.recv().await.is_some() {} });
+    State::new(tx, Box::new(task), Box::new(TestEmbedder {}), 10, false)
+        .await
+        .unwrap()
+}
+
+pub(crate) async fn new_shared_state(task: TestTask) -> SharedState {
+    std::sync::Arc::new(tokio::sync::Mutex::new(new_state(task).await))
+}
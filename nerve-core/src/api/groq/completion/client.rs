@@ -216,6 +216,7 @@ mod completion_test {
     use crate::api::groq::completion::{client::Groq, message::Message, request::builder};
 
     #[test]
+    #[allow(clippy::unit_hash)]
     fn test_eq_and_hash() {
         Groq::new("api_key").add_messages(vec![Message::UserMessage {
             role: Some("user".to_string()),
@@ -9,6 +9,7 @@ use serde::{Deserialize, Serialize};
 ///
 #[derive(Debug, Serialize, Clone, Hash)]
 #[serde(untagged)]
+#[allow(clippy::enum_variant_names)]
 pub enum Message {
     SystemMessage {
         #[serde(skip_serializing_if = "Option::is_none")]
@@ -69,8 +69,8 @@ impl Hash for StreamChoice {
         self.delta.hash(state);
         self.finish_reason.hash(state);
 
-        if self.logprobs.is_some() {
-            ((self.logprobs.unwrap()) as i32).hash(state); // I understand that this is a little weird, but the logic is that even if we can't hash a float, we can convert it to int and hash that at least.
+        if let Some(logprobs) = self.logprobs {
+            (logprobs as i32).hash(state); // I understand that this is a little weird, but the logic is that even if we can't hash a float, we can convert it to int and hash that at least.
         }
     }
 }
@@ -147,8 +147,8 @@ impl Hash for Choice {
         self.index.hash(state);
         self.message.hash(state);
         self.finish_reason.hash(state);
-        if self.logprobs.is_some() {
-            ((self.logprobs.unwrap()) as i32).hash(state);
+        if let Some(logprobs) = self.logprobs {
+            (logprobs as i32).hash(state);
         }
     }
 }
@@ -1,3 +1,4 @@
+#[cfg(feature = "groq")]
 pub mod groq;
 pub mod ollama;
 pub mod openai;
@@ -91,6 +91,9 @@ pub struct ChatBody {
     /// which can help OpenAI to monitor and detect abuse. Learn more.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub user: Option<String>,
+    /// If specified, the system will make a best effort to sample deterministically.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seed: Option<u64>,
 
     #[serde(skip_serializing_if = "Option::is_none")]
     pub tools: Option<Vec<FunctionTool>>,
@@ -43,6 +43,7 @@ pub type LazyIoResult<'a, T> = Result<T, LazyIoError<'a>>;
 
 /// An error for lazily written multipart requests, including the original error as well
 /// as the field which caused the error, if applicable.
+#[allow(clippy::manual_non_exhaustive)]
 pub struct LazyError<'a, E> {
 	/// The field that caused the error.
 	/// If `None`, there was a problem opening the stream to write or finalizing the stream.
@@ -0,0 +1,37 @@
+//! The agent runtime of [Nerve](https://github.com/evilsocket/nerve): generators, namespaces,
+//! state, tasklets and events, usable by any Rust program to embed stateful agents.
+//!
+//! ```no_run
+//! use nerve_core::{agent::task::tasklet::Tasklet, AgentBuilder};
+//!
+//! # async fn example() -> anyhow::Result<()> {
+//! let mut tasklet = Tasklet::from_path("/path/to/tasklet.yml", &vec![])?;
+//! tasklet.prepare(&Some("list the files in the current folder".to_string())).await?;
+//!
+//! let (mut agent, mut events) = AgentBuilder::new()
+//!     .generator("openai://gpt-4o")
+//!     .task(tasklet)
+//!     .build()
+//!     .await?;
+//!
+//! tokio::spawn(async move {
+//!     while let Some(event) = events.recv().await {
+//!         println!("{:?}", event);
+//!     }
+//! });
+//!
+//! agent.run().await
+//! # }
+//! ```
+#![allow(dead_code)]
+#![allow(clippy::module_inception)]
+
+#[macro_use]
+extern crate anyhow;
+
+pub mod agent;
+pub mod api;
+
+pub use agent::builder::AgentBuilder;
+pub use agent::task::Task;
+pub use agent::Agent;
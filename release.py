@@ -34,15 +34,23 @@ if len(m) != 1:
 current_ver = m[0]
 next_ver = input("current version is %s, enter next: " % current_ver)
 
-# generate new manifest
-result = re.sub(
-    version_match_re, 'version = "%s"' % next_ver, manifest, 0, re.MULTILINE
-)
-with open("Cargo.toml", "w+t") as fp:
-    fp.write(result)
+# generate new manifests, the cli and the core library share the same version
+for path in ("Cargo.toml", "nerve-core/Cargo.toml"):
+    with open(path, "rt") as fp:
+        manifest = fp.read()
+
+    result = re.sub(
+        version_match_re, 'version = "%s"' % next_ver, manifest, 0, re.MULTILINE
+    )
+    result = result.replace(
+        'nerve-core = { path = "nerve-core", version = "%s" }' % current_ver,
+        'nerve-core = { path = "nerve-core", version = "%s" }' % next_ver,
+    )
+    with open(path, "w+t") as fp:
+        fp.write(result)
 
 # commit, push and create new tag
-print("git add Cargo.*")
+print("git add Cargo.* nerve-core/Cargo.toml")
 print("git commit -m 'releasing version %s'" % next_ver)
 print("git push")
 print("git tag -a v%s -m 'releasing v%s'" % (next_ver, next_ver))
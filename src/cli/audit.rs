@@ -0,0 +1,29 @@
+use anyhow::Result;
+use colored::Colorize;
+
+use crate::{agent::audit::AuditLog, cli::cli::AuditCommand};
+
+fn verify(path: &str) -> Result<()> {
+    let entries = AuditLog::verify(path).map_err(|e| anyhow!("{}: {}", path, e))?;
+    match (entries.first(), entries.last()) {
+        (Some(first), Some(last)) => {
+            println!(
+                "{} {} entries from {} to {}, the chain is intact",
+                "ok".green().bold(),
+                entries.len(),
+                first.time.to_rfc2822(),
+                last.time.to_rfc2822()
+            );
+            // storing it elsewhere also detects a log rewritten from scratch
+            println!("head: {}", last.hash);
+        }
+        _ => println!("{} the audit log is empty", "ok".green().bold()),
+    }
+    Ok(())
+}
+
+pub fn run(command: &AuditCommand) -> Result<()> {
+    match command {
+        AuditCommand::Verify { path } => verify(path),
+    }
+}
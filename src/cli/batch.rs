@@ -0,0 +1,515 @@
+use std::{
+    fmt::Display,
+    io::Write,
+    path::{Path, PathBuf},
+    sync::atomic::Ordering,
+    time::{Duration, Instant},
+};
+
+use anyhow::Result;
+use colored::Colorize;
+use serde::{Deserialize, Serialize};
+use tokio::sync::watch;
+
+use crate::{
+    agent::{events, scheduler::Scheduler, secrets, state::session::Session},
+    cli::{
+        self,
+        report::{self, Summary},
+        setup, ui,
+    },
+    APP_NAME, APP_VERSION,
+};
+
+// a tasklet to run as part of a batch
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Entry {
+    pub tasklet: String,
+    // used instead of the command line one
+    pub prompt: Option<String>,
+    pub generator: Option<String>,
+}
+
+// entries of a manifest can also be just the path of the tasklet
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum ManifestEntry {
+    Path(String),
+    Entry(Entry),
+}
+
+#[derive(Debug, Deserialize)]
+struct Manifest {
+    // how many tasklets to run at the same time, unless --parallel is set
+    parallel: Option<usize>,
+    tasklets: Vec<ManifestEntry>,
+}
+
+// a list of tasklets to run against the same target
+#[derive(Debug, Clone, PartialEq)]
+pub struct Batch {
+    pub parallel: Option<usize>,
+    pub entries: Vec<Entry>,
+}
+
+// tasklet folders and files, skipping the workflows, tests and manifests
+fn is_tasklet(path: &Path) -> bool {
+    if path.is_dir() {
+        return path.join("task.yml").exists() || path.join("task.yaml").exists();
+    }
+
+    let is_yaml = path
+        .extension()
+        .is_some_and(|ext| ext == "yml" || ext == "yaml");
+    if !is_yaml || path.file_stem().is_some_and(|stem| stem == "test") {
+        return false;
+    }
+
+    std::fs::read_to_string(path)
+        .ok()
+        .and_then(|yaml| serde_yaml::from_str::<serde_yaml::Value>(&yaml).ok())
+        .is_some_and(|yaml| {
+            yaml.is_mapping()
+                && ["tasks", "tests", "tasklets"]
+                    .iter()
+                    .all(|key| yaml.get(key).is_none())
+        })
+}
+
+impl Batch {
+    // every tasklet of a folder, or the ones listed by a manifest file
+    pub fn from_path(path: &str) -> Result<Self> {
+        let canon =
+            std::fs::canonicalize(path).map_err(|e| anyhow!("could not read {path}: {e}"))?;
+
+        if canon.is_dir() {
+            let mut paths: Vec<PathBuf> = std::fs::read_dir(&canon)?
+                .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                .filter(|path| is_tasklet(path))
+                .collect();
+            paths.sort();
+
+            if paths.is_empty() {
+                return Err(anyhow!("no tasklets found in {}", canon.display()));
+            }
+
+            return Ok(Self {
+                parallel: None,
+                entries: paths
+                    .into_iter()
+                    .map(|path| Entry {
+                        tasklet: path.display().to_string(),
+                        prompt: None,
+                        generator: None,
+                    })
+                    .collect(),
+            });
+        }
+
+        let yaml = std::fs::read_to_string(&canon)?;
+        let manifest: Manifest = serde_yaml::from_str(&yaml)
+            .map_err(|e| anyhow!("could not parse {}: {e}", canon.display()))?;
+        let folder = canon.parent().unwrap();
+
+        Ok(Self {
+            parallel: manifest.parallel,
+            entries: manifest
+                .tasklets
+                .into_iter()
+                .map(|entry| {
+                    let mut entry = match entry {
+                        ManifestEntry::Path(tasklet) => Entry {
+                            tasklet,
+                            prompt: None,
+                            generator: None,
+                        },
+                        ManifestEntry::Entry(entry) => entry,
+                    };
+                    // relative to the manifest
+                    let path = Path::new(&entry.tasklet);
+                    if path.is_relative() && folder.join(path).exists() {
+                        entry.tasklet = folder.join(path).display().to_string();
+                    }
+                    entry
+                })
+                .collect(),
+        })
+    }
+}
+
+// how a tasklet of the batch went
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Outcome {
+    pub tasklet: String,
+    // complete, impossible, incomplete or failed
+    pub status: String,
+    pub error: Option<String>,
+    pub summary: Option<Summary>,
+}
+
+impl Outcome {
+    pub fn failed(entry: &Entry, error: &anyhow::Error) -> Self {
+        Self {
+            tasklet: entry.tasklet.to_string(),
+            status: "failed".to_string(),
+            error: Some(error.to_string()),
+            summary: None,
+        }
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.status == "complete"
+    }
+}
+
+// the outcomes of all the tasklets of a batch
+#[derive(Debug, Serialize)]
+pub struct BatchReport {
+    generated_by: String,
+    source: String,
+    elapsed_ms: u128,
+    cost: f64,
+    pub outcomes: Vec<Outcome>,
+}
+
+impl BatchReport {
+    pub fn new(source: &str, started: Instant, outcomes: Vec<Outcome>) -> Self {
+        Self {
+            generated_by: format!("{} v{}", APP_NAME, APP_VERSION),
+            source: source.to_string(),
+            elapsed_ms: started.elapsed().as_millis(),
+            cost: outcomes
+                .iter()
+                .filter_map(|o| o.summary.as_ref())
+                .fold(0.0, |cost, s| cost + s.cost),
+            outcomes,
+        }
+    }
+
+    fn completed(&self) -> usize {
+        self.outcomes.iter().filter(|o| o.is_complete()).count()
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    pub fn to_markdown(&self) -> String {
+        let mut md = format!("# Batch {}\n\n", self.source);
+        md += &format!(
+            "{} of {} tasklets completed in {:?}",
+            self.completed(),
+            self.outcomes.len(),
+            Duration::from_millis(self.elapsed_ms as u64)
+        );
+        if self.cost > 0.0 {
+            md += &format!(", ${:.4}", self.cost);
+        }
+        md += "\n\n| Tasklet | Outcome | Steps | Duration | Cost | Errors |\n";
+        md += "|---------|---------|-------|----------|------|--------|\n";
+        for outcome in &self.outcomes {
+            let status = match &outcome.error {
+                Some(error) => format!("{}: {}", outcome.status, error.replace('|', "\\|")),
+                None => outcome.status.to_string(),
+            };
+            match &outcome.summary {
+                Some(summary) => {
+                    md += &format!(
+                        "| {} | {} | {} | {:.1}s | ${:.4} | {} |\n",
+                        outcome.tasklet,
+                        status,
+                        summary.steps,
+                        summary.elapsed_ms as f64 / 1000.0,
+                        summary.cost,
+                        summary.errors.total()
+                    )
+                }
+                None => md += &format!("| {} | {} | | | | |\n", outcome.tasklet, status),
+            }
+        }
+        md += &format!("\n_Generated by {}_\n", self.generated_by);
+        md
+    }
+
+    pub fn save(&self, path: &str) -> Result<(PathBuf, PathBuf)> {
+        let mut base = PathBuf::from(path);
+        if base
+            .extension()
+            .is_some_and(|ext| ext == "json" || ext == "md")
+        {
+            base.set_extension("");
+        }
+        let json_path = base.with_extension("json");
+        let md_path = base.with_extension("md");
+
+        // never export the registered secrets
+        std::fs::write(&json_path, secrets::redact_json(&self.to_json()?))
+            .map_err(|e| anyhow!("can't write {}: {}", json_path.display(), e))?;
+        std::fs::write(&md_path, secrets::redact(&self.to_markdown()))
+            .map_err(|e| anyhow!("can't write {}: {}", md_path.display(), e))?;
+
+        Ok((json_path, md_path))
+    }
+}
+
+impl Display for BatchReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for outcome in &self.outcomes {
+            let status = if outcome.is_complete() {
+                outcome.status.green()
+            } else {
+                outcome.status.red()
+            };
+            write!(f, "{} {}", status, outcome.tasklet)?;
+            if let Some(summary) = &outcome.summary {
+                write!(
+                    f,
+                    " ({} steps in {:.1}s",
+                    summary.steps,
+                    summary.elapsed_ms as f64 / 1000.0
+                )?;
+                if summary.cost > 0.0 {
+                    write!(f, ", ${:.4}", summary.cost)?;
+                }
+                write!(f, ")")?;
+            }
+            if let Some(error) = &outcome.error {
+                write!(f, ": {}", error)?;
+            }
+            writeln!(f)?;
+        }
+        write!(
+            f,
+            "{}/{} tasklets completed in {:?}",
+            self.completed(),
+            self.outcomes.len(),
+            Duration::from_millis(self.elapsed_ms as u64)
+        )?;
+        if self.cost > 0.0 {
+            write!(f, ", cost: ${:.4}", self.cost)?;
+        }
+        Ok(())
+    }
+}
+
+// run the tasklet of the entry, stopping it at the end of the current step once stop is set
+pub async fn run_entry(
+    args: &cli::Args,
+    entry: &Entry,
+    stop: Option<watch::Receiver<bool>>,
+) -> (Outcome, Option<Session>) {
+    let mut consumer = None;
+    let ret = run_entry_with(args, entry, stop, |args, events_rx| {
+        consumer = Some(tokio::spawn(ui::text::consume_events(
+            args.clone(),
+            events_rx,
+        )));
+    })
+    .await;
+
+    // the agent is gone, let the consumer print what's left
+    if let Some(consumer) = consumer {
+        let _ = consumer.await;
+    }
+
+    ret
+}
+
+// run the tasklet of the entry passing its events to on_events instead of printing them
+pub async fn run_entry_with(
+    args: &cli::Args,
+    entry: &Entry,
+    stop: Option<watch::Receiver<bool>>,
+    on_events: impl FnOnce(&cli::Args, events::Receiver),
+) -> (Outcome, Option<Session>) {
+    log::info!("starting tasklet {} ...", entry.tasklet.bold());
+
+    let mut args = args.clone();
+    args.tasklet = Some(entry.tasklet.to_string());
+    if let Some(generator) = &entry.generator {
+        args.generator = generator.to_string();
+    }
+    if let Some(prompt) = &entry.prompt {
+        args.prompt = Some(prompt.to_string());
+    }
+    // stdin can't be shared by the tasklets
+    args.no_stdin = true;
+
+    let (mut agent, events_rx) = match setup::setup_agent(&args).await {
+        Ok(setup) => setup,
+        Err(err) => {
+            log::error!("tasklet {} failed: {}", entry.tasklet, err);
+            return (Outcome::failed(entry, &err), None);
+        }
+    };
+
+    let watcher = stop.map(|mut stop| {
+        let agent_stop = agent.stop_handle();
+        tokio::spawn(async move {
+            if stop.wait_for(|stop| *stop).await.is_ok() {
+                agent_stop.store(true, Ordering::SeqCst);
+            }
+        })
+    });
+
+    // the consumer will stop once the state (holding the events channel) is dropped
+    on_events(&args, events_rx);
+
+    let ret = agent.run().await;
+    let session = agent.to_session().await;
+    if let Some(watcher) = watcher {
+        watcher.abort();
+    }
+
+    // close the events channel, the consumer returns once it's done with the last events
+    drop(agent);
+
+    if let Err(err) = &ret {
+        log::error!("tasklet {} failed: {}", entry.tasklet, err);
+    }
+
+    match session {
+        Ok(session) => (
+            Outcome {
+                tasklet: entry.tasklet.to_string(),
+                status: if ret.is_err() {
+                    "failed".to_string()
+                } else {
+                    report::outcome(&session).to_string()
+                },
+                error: ret.err().map(|err| err.to_string()),
+                summary: Some(Summary::from_session(&session)),
+            },
+            Some(session),
+        ),
+        Err(err) => (Outcome::failed(entry, &err), None),
+    }
+}
+
+pub async fn run(
+    args: &cli::Args,
+    path: &str,
+    document_out: Option<Box<dyn Write + Send>>,
+) -> Result<()> {
+    let batch = Batch::from_path(path)?;
+    let parallel = args.parallel.or(batch.parallel).unwrap_or(1).max(1);
+
+    log::info!(
+        "running {} tasklets from {}{}",
+        batch.entries.len(),
+        path.green().bold(),
+        if parallel > 1 {
+            format!(", {} at a time", parallel)
+        } else {
+            String::new()
+        }
+    );
+
+    // every tasklet runs as its own agent, on a pool of as many workers as tasklets at a time
+    let started = Instant::now();
+    let scheduler = Scheduler::new(parallel)?;
+    let handles: Vec<_> = batch
+        .entries
+        .iter()
+        .map(|entry| {
+            let args = args.clone();
+            let entry = entry.clone();
+            scheduler.spawn(async move { run_entry(&args, &entry, None).await.0 })
+        })
+        .collect();
+
+    let mut outcomes = vec![];
+    for (entry, handle) in batch.entries.iter().zip(handles) {
+        outcomes.push(match handle.join().await {
+            Ok(outcome) => outcome,
+            Err(err) => Outcome::failed(entry, &err),
+        });
+    }
+
+    conclude(
+        args,
+        &BatchReport::new(path, started, outcomes),
+        document_out,
+    )
+}
+
+// print the summary of the outcomes, save and export it, and fail if any tasklet did not complete
+pub fn conclude(
+    args: &cli::Args,
+    report: &BatchReport,
+    document_out: Option<Box<dyn Write + Send>>,
+) -> Result<()> {
+    for line in report.to_string().lines() {
+        log::info!("{}", line);
+    }
+
+    if let Some(path) = &args.report {
+        match report.save(path) {
+            Ok((json, md)) => log::info!("report saved to {} and {}", json.display(), md.display()),
+            Err(err) => log::error!("could not save the report: {}", err),
+        }
+    }
+
+    if let Some(mut out) = document_out {
+        // never export the registered secrets
+        writeln!(out, "{}", secrets::redact_json(&report.to_json()?))?;
+        out.flush()?;
+    }
+
+    let failed = report.outcomes.len() - report.completed();
+    if failed == 0 {
+        Ok(())
+    } else {
+        Err(anyhow!(
+            "{} of {} tasklets did not complete",
+            failed,
+            report.outcomes.len()
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_path() {
+        let folder = std::env::temp_dir().join(format!("nerve-test-batch-{}", std::process::id()));
+        std::fs::create_dir_all(folder.join("web")).unwrap();
+        let write = |name: &str, yaml: &str| std::fs::write(folder.join(name), yaml).unwrap();
+
+        write("web/task.yml", "system_prompt: test\n");
+        write("recon.yml", "system_prompt: test\n");
+        write("test.yml", "tests: []\n");
+        write("flow.yml", "tasks: []\n");
+        write("notes.txt", "not a tasklet\n");
+        write(
+            "suite.yml",
+            "parallel: 2\ntasklets:\n  - recon.yml\n  - tasklet: web\n    prompt: scan it\n",
+        );
+
+        let batch = Batch::from_path(&folder.display().to_string()).unwrap();
+        let names: Vec<String> = batch
+            .entries
+            .iter()
+            .map(|e| {
+                Path::new(&e.tasklet)
+                    .file_name()
+                    .unwrap()
+                    .to_string_lossy()
+                    .to_string()
+            })
+            .collect();
+        assert_eq!(names, vec!["recon.yml", "web"]);
+
+        let batch = Batch::from_path(&folder.join("suite.yml").display().to_string()).unwrap();
+        assert_eq!(batch.parallel, Some(2));
+        assert_eq!(batch.entries.len(), 2);
+        assert!(batch.entries[1]
+            .tasklet
+            .starts_with(folder.to_str().unwrap()));
+        assert_eq!(batch.entries[1].prompt.as_deref(), Some("scan it"));
+
+        let _ = std::fs::remove_dir_all(&folder);
+    }
+}
@@ -1,9 +1,132 @@
-use crate::agent::serialization::Strategy;
-use clap::Parser;
+use crate::{
+    agent::{
+        breaker::BreakerAction, cassette, generator::credentials, guardrails::Enforcement,
+        serialization::Strategy,
+    },
+    cli::{config::Config, output::Format},
+};
+use std::{collections::BTreeMap, ffi::OsString};
+
+use anyhow::Result;
+use clap::{Parser, Subcommand};
 
 /// Get things done with LLMs.
-#[derive(Parser, Debug, Default)]
-#[command(version, about, long_about = None)]
+#[derive(Parser, Debug, Clone)]
+#[command(version, about, long_about = None, arg_required_else_help = true)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+impl Cli {
+    pub fn parse_args() -> Result<Self> {
+        let mut cli = Self::parse_with_profile()?;
+        if let Some(args) = cli.command.args_mut() {
+            args.resolve_variables()?;
+        }
+        Ok(cli)
+    }
+
+    // nerve -G <generator> -T <tasklet> is still accepted as a shorthand of nerve run
+    fn parse_with_profile() -> Result<Self> {
+        let mut argv = with_default_command(std::env::args_os());
+        let cli = Self::parse_from(&argv);
+        let args = match cli.command.args() {
+            Some(args) => args,
+            None => return Ok(cli),
+        };
+
+        let config = Config::load(args.config.as_ref(), args.profile.as_deref())?;
+        for (generator, source) in &config.credentials {
+            credentials::configure(generator, source.clone())?;
+        }
+
+        let (name, profile) = match config.selected_profile() {
+            Some(selected) => selected,
+            None => return Ok(cli),
+        };
+
+        for (key, value) in &profile.env {
+            if std::env::var_os(key).is_none() {
+                std::env::set_var(key, value);
+            }
+        }
+
+        // the options of the profile go first, so that the command line ones override them
+        let arguments = profile
+            .arguments()
+            .map_err(|e| anyhow!("profile '{}': {}", name, e))?;
+        argv.splice(2..2, arguments);
+
+        Self::try_parse_from(&argv).map_err(|e| anyhow!("profile '{}': {}", name, e))
+    }
+}
+
+fn with_default_command(args: impl IntoIterator<Item = OsString>) -> Vec<OsString> {
+    let mut args: Vec<OsString> = args.into_iter().collect();
+    // the former --generate-doc flag, same as nerve namespaces --format markdown
+    if args.iter().skip(1).any(|arg| arg == "--generate-doc") {
+        args.truncate(1);
+        args.extend(["namespaces", "--format", "markdown"].map(OsString::from));
+        return args;
+    }
+
+    let is_option = args.get(1).is_some_and(|arg| {
+        let arg = arg.to_string_lossy();
+        arg.starts_with('-') && !["-h", "--help", "-V", "--version"].contains(&arg.as_ref())
+    });
+    if is_option {
+        args.insert(1, OsString::from("run"));
+    }
+    args
+}
+
+// NAME=VALUE lines, or a map of names to values if the file is YAML
+fn parse_var_file(path: &str) -> Result<Vec<String>> {
+    let data = std::fs::read_to_string(path)
+        .map_err(|e| anyhow!("can't read variables file {}: {}", path, e))?;
+
+    if path.ends_with(".yml") || path.ends_with(".yaml") {
+        let vars: BTreeMap<String, serde_yaml::Value> = serde_yaml::from_str(&data)
+            .map_err(|e| anyhow!("invalid variables file {}: {}", path, e))?;
+        return vars
+            .into_iter()
+            .map(|(name, value)| match value {
+                serde_yaml::Value::String(value) => Ok(format!("{}={}", name, value)),
+                serde_yaml::Value::Number(value) => Ok(format!("{}={}", name, value)),
+                serde_yaml::Value::Bool(value) => Ok(format!("{}={}", name, value)),
+                _ => Err(anyhow!("{}: variable {} must be a scalar", path, name)),
+            })
+            .collect();
+    }
+
+    let mut vars = vec![];
+    for (idx, line) in data.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let line = line.strip_prefix("export ").unwrap_or(line);
+        let (name, value) = line
+            .split_once('=')
+            .ok_or_else(|| anyhow!("{}:{}: syntax is NAME=VALUE", path, idx + 1))?;
+        let value = value.trim();
+        let value = ['"', '\'']
+            .iter()
+            .find_map(|quote| {
+                value
+                    .strip_prefix(*quote)
+                    .and_then(|value| value.strip_suffix(*quote))
+            })
+            .unwrap_or(value);
+        vars.push(format!("{}={}", name.trim(), value));
+    }
+    Ok(vars)
+}
+
+/// Options of the runs.
+#[derive(clap::Args, Debug, Default, Clone)]
+#[command(args_override_self = true)]
 pub struct Args {
     /// Generator string as <type>://<model name>@<host>:<port>
     #[arg(short = 'G', long, default_value = "ollama://llama3@localhost:11434")]
@@ -11,37 +134,83 @@ pub struct Args {
     /// Judge generator string as <type>://<model name>@<host>:<port>
     #[arg(short = 'J', long, default_value = "ollama://llama3@localhost:11434")]
     pub judge: String,
+    /// Have the --judge model review the final output of the run, a failed verdict exits with code 7.
+    #[arg(long)]
+    pub judge_output: bool,
     /// Run in judge mode.
     #[arg(long)]
     pub judge_mode: bool,
     /// Only rely on user prompt. Use for models like openai/o1 family that don't allow a system prompt.
     #[arg(long)]
     pub user_only: bool,
-    /// Embedder string as <type>://<model name>@<host>:<port>
+    /// Embedder string as <type>://<model name>@<host>:<port>, or candle://<model> and onnx://<model> for the local ones.
     #[arg(
         short = 'E',
         long,
         default_value = "ollama://all-minilm@localhost:11434"
     )]
     pub embedder: String,
-    /// Serialization strategy.
-    #[arg(short = 'S', long, default_value_t, value_enum)]
-    pub serialization: Strategy,
+    /// Don't cache the embeddings in ~/.nerve/embeddings.
+    #[arg(long)]
+    pub no_embeddings_cache: bool,
+    /// Serialization strategy, by default the one set for the generator in the models of the configuration or xml.
+    #[arg(short = 'S', long, value_enum)]
+    pub serialization: Option<Strategy>,
+    /// Folder with prompt files replacing the built-in ones at the same path relative to nerve-core/src/agent (e.g. serialization/system.prompt or namespaces/shell/ns.prompt).
+    #[arg(long)]
+    pub prompts_dir: Option<String>,
     /// Conversation window, it can be either "full" (full chat history), "summary" (report the last messages entirely and compress the previous ones) or "N" (last N messages).
     #[arg(long, default_value = "15")]
     pub window: String,
+    /// Summarize older actions and their outputs once the history exceeds this many tokens, 0 to disable.
+    #[arg(long, default_value_t = 0)]
+    pub summarize_after: usize,
+    /// Number of most recent actions that are never summarized.
+    #[arg(long, default_value_t = 4)]
+    pub summarize_keep: usize,
+    /// Replace the action outputs whose embeddings are at least this similar (0 to 1, e.g. 0.95) to an earlier output with a reference to it.
+    #[arg(long)]
+    pub dedup_history: Option<f64>,
     /// Force specified serialization format even if the model supports native tools calling.
     #[arg(long)]
     pub force_format: bool,
     /// Tasklet file.
     #[arg(short = 'T', long)]
     pub tasklet: Option<String>,
-    /// Specify the prompt if not provided by the tasklet.
+    /// Workflow file, run multiple tasklets respecting their dependencies.
+    #[arg(short = 'W', long)]
+    pub workflow: Option<String>,
+    /// Run every tasklet of this folder, or the ones listed in this manifest file, and summarize their outcomes.
+    #[arg(
+        long,
+        value_name = "PATH",
+        conflicts_with_all = ["tasklet", "workflow", "resume", "checkpoint", "session", "tui"]
+    )]
+    pub all: Option<String>,
+    /// Number of tasklets of --all to run at the same time, overrides the manifest setting.
+    #[arg(long, requires = "all")]
+    pub parallel: Option<usize>,
+    /// Specify the prompt if not provided by the tasklet, use - to read it from stdin.
     #[arg(short = 'P', long)]
     pub prompt: Option<String>,
+    /// Read the prompt from this file if not provided by the tasklet.
+    #[arg(long)]
+    pub prompt_file: Option<String>,
+    /// Fetch the prompt from this URL if not provided by the tasklet.
+    #[arg(long)]
+    pub prompt_url: Option<String>,
+    /// Don't attach the data piped to the standard input to the task.
+    #[arg(long)]
+    pub no_stdin: bool,
     /// Pre define variables.
     #[arg(short = 'D', long, value_parser, num_args = 1.., value_delimiter = ' ')]
     pub define: Vec<String>,
+    /// Define a variable as NAME=VALUE, can be repeated and the value can contain spaces.
+    #[arg(long = "var", value_name = "NAME=VALUE")]
+    pub vars: Vec<String>,
+    /// Define the variables of a file of NAME=VALUE lines, or of a YAML map if it ends with .yml or .yaml.
+    #[arg(long, value_name = "PATH")]
+    pub var_file: Vec<String>,
     /// Robopages server address. If specified, the agent will use the tools defined by the robopages server.
     #[arg(short = 'R', long)]
     pub robopages: Option<String>,
@@ -51,10 +220,541 @@ pub struct Args {
     /// Maximum number of steps to complete the task or 0 for no limit.
     #[arg(long, default_value_t = 0)]
     pub max_iterations: usize,
+    /// Ask for approval before executing actions: "default" (only actions that always require it, like shell), "all", "destructive" (actions with side effects) or a comma separated list of namespaces.
+    #[arg(long, default_value = "default")]
+    pub confirm: String,
+    /// Pause after every model response to inspect, edit or skip each invocation, or inject a result in place of its execution.
+    #[arg(long)]
+    pub step: bool,
+    /// Every N steps, ask the model to review its history, list its mistakes and update its plan and memories, 0 to use the tasklet setting.
+    #[arg(long, default_value_t = 0)]
+    pub reflect_every: usize,
+    /// Maximum wall-clock duration of the task (for instance 30m or 2h), overrides the tasklet max_duration.
+    #[arg(long)]
+    pub max_time: Option<String>,
+    /// Maximum estimated cost of the task in USD.
+    #[arg(long)]
+    pub max_cost: Option<f64>,
+    /// Generator token prices in USD per million tokens as <input>,<output>, needed by --max-cost for models with unknown prices.
+    #[arg(long)]
+    pub token_prices: Option<String>,
+    /// Trigger --on-max-errors after this many consecutive failed actions or unparsable responses, 0 to disable.
+    #[arg(long, default_value_t = 0)]
+    pub max_errors: usize,
+    /// What to do after --max-errors consecutive errors; if errors keep coming afterwards the run is aborted.
+    #[arg(long, default_value_t, value_enum)]
+    pub on_max_errors: BreakerAction,
+    /// Generator string to switch to with --on-max-errors fallback.
+    #[arg(long)]
+    pub fallback_generator: Option<String>,
+    /// Action outputs bigger than this many characters are stored and shown to the model one page at a time, 0 to disable.
+    #[arg(long, default_value_t = 16384)]
+    pub max_output_size: usize,
+    /// Size in characters of the pages of stored outputs.
+    #[arg(long, default_value_t = 4096)]
+    pub output_page_size: usize,
+    /// Comma separated list of the only actions or namespaces that can be used.
+    #[arg(long, value_delimiter = ',')]
+    pub allow_actions: Vec<String>,
+    /// Comma separated list of actions or namespaces that can't be used, even if the tasklet requires them.
+    #[arg(long, value_delimiter = ',')]
+    pub deny_actions: Vec<String>,
+    /// Per namespace security policy file, overrides the one in the configuration file.
+    #[arg(long)]
+    pub policy: Option<String>,
+    /// Operator configuration file, defaults to ~/.nerve/config.yml if it exists.
+    #[arg(long)]
+    pub config: Option<String>,
+    /// Use the generators, limits, sinks and policies of this profile of the configuration file.
+    #[arg(long)]
+    pub profile: Option<String>,
+    /// Load guardrail rules to inspect responses and invocations from this YAML file.
+    #[arg(long)]
+    pub guardrails: Option<String>,
+    /// Generator string of a model used to moderate responses and invocations.
+    #[arg(long)]
+    pub moderation_generator: Option<String>,
+    /// What to do when the moderation model flags a response or invocation.
+    #[arg(long, default_value_t, value_enum)]
+    pub on_moderation: Enforcement,
+    /// Use a fixed seed and zero temperature where supported by the generator, for reproducible runs.
+    #[arg(long)]
+    pub deterministic: bool,
+    /// Sample this many responses at every step and execute the one with the most valid and least repeated invocations.
+    #[arg(long, default_value_t = 1)]
+    pub candidates: usize,
+    /// Instead of executing the actions, use the outputs recorded in this session file when the
+    /// model invokes them with the same arguments.
+    #[arg(long)]
+    pub pin_outputs: Option<String>,
+    /// Record the http requests and the model responses of the run to this cassette, or replay them from it.
+    #[arg(long)]
+    pub cassette: Option<String>,
+    /// With "auto" an existing cassette is replayed and a missing one recorded.
+    #[arg(long, default_value_t, value_enum, requires = "cassette")]
+    pub cassette_mode: cassette::Mode,
+    /// At every step, save a checkpoint of the run to this file so that it can be resumed.
+    #[arg(long)]
+    pub checkpoint: Option<String>,
+    /// Name of the session, saved in ~/.nerve/sessions at every step so that it can be resumed with --resume <name>.
+    #[arg(long)]
+    pub session: Option<String>,
+    /// Resume a run from a checkpoint file or a named session, checkpoints will keep being saved to the same file unless --checkpoint is specified.
+    /// With --database, this can also be the id of a session recorded in the database.
+    #[arg(long)]
+    pub resume: Option<String>,
+    /// Record history, storages and metrics of the run to this SQLite database, requires the sqlite3 command line tool (or the executable set with $NERVE_SQLITE).
+    #[arg(long)]
+    pub database: Option<String>,
+    /// Share the storages with other agents through this Redis server, as redis://[:password@]host[:port][/db][?prefix=nerve].
+    #[arg(long)]
+    pub redis: Option<String>,
+    /// Comma separated list of storages to share with --redis, all of them but the time and goal ones by default.
+    #[arg(long, value_delimiter = ',')]
+    pub shared_storages: Vec<String>,
+    /// At the end of the run, export the task, invocations, results, metrics and storages to <path>.json and <path>.md.
+    #[arg(long)]
+    pub report: Option<String>,
+    /// Once the run ends, have the model compile its findings into a report with evidence and remediation, saved as <path>.md and <path>.html.
+    #[arg(long)]
+    pub engagement_report: Option<String>,
+    /// Append every executed invocation, with the operator approvals, to this hash chained audit log.
+    #[arg(long)]
+    pub audit_log: Option<String>,
     /// At every step, save the current system prompt and state data to this file.
     #[arg(long)]
     pub save_to: Option<String>,
-    /// Print the documentation of the available action namespaces.
+    /// Show a live dashboard with the plan, storages, metrics, current action and events of the run instead of the log lines.
+    #[arg(long, conflicts_with = "judge_mode")]
+    pub tui: bool,
+    /// With "json" the standard output only carries a document with the status, output, findings and metrics of the run once it ends, everything else is printed to the standard error.
+    #[arg(long, default_value_t, value_enum, conflicts_with_all = ["judge_mode", "tui"])]
+    pub output: Format,
+    /// Stream the events of the run over SSE and WebSocket at http://<address>/events.
     #[arg(long)]
-    pub generate_doc: bool,
+    pub events_address: Option<String>,
+    /// Origin allowed to read the events stream from a browser.
+    #[arg(long)]
+    pub events_allow_origin: Option<String>,
+    /// If set, clients of the events stream must send it as an `Authorization: Bearer` header. Can also be set with $NERVE_EVENTS_TOKEN.
+    #[arg(long)]
+    pub events_token: Option<String>,
+    /// Only print errors, without the transcript of the run.
+    #[arg(short = 'q', long, conflicts_with = "verbose")]
+    pub quiet: bool,
+    /// Print the debug logs of nerve, -vv for the trace logs and the debug logs of the dependencies.
+    #[arg(short = 'v', long, action = clap::ArgAction::Count)]
+    pub verbose: u8,
+    /// Log filter with per module levels, like "info,nerve_core::agent::namespaces=debug", overrides $RUST_LOG and the verbosity.
+    #[arg(long)]
+    pub log_level: Option<String>,
+    /// Write the transcript and the debug logs to this file, whatever the console verbosity.
+    #[arg(long)]
+    pub log_file: Option<String>,
+    /// Also send the logs to file:<path>[?max_size=10M&keep=5], syslog[:<socket>|:<host>[:port]][?facility=user] or journald, can be repeated; every sink accepts ?level=<level>.
+    #[arg(long)]
+    pub log: Vec<String>,
+}
+
+impl Args {
+    // the variables files go first, then -D and --var, the last definition wins
+    fn resolve_variables(&mut self) -> Result<()> {
+        let mut defines = vec![];
+        for path in &self.var_file {
+            defines.extend(parse_var_file(path)?);
+        }
+        defines.append(&mut self.define);
+        for var in self.vars.drain(..) {
+            if !var.contains('=') {
+                return Err(anyhow!("can't parse --var {}, syntax is: NAME=VALUE", var));
+            }
+            defines.push(var);
+        }
+        self.define = defines;
+        Ok(())
+    }
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum Command {
+    /// Run a tasklet, or the tasklets of a workflow.
+    Run(Args),
+    /// Check that tasklets and workflows can be loaded, without running them.
+    Validate {
+        /// Tasklet folders or files and workflow files.
+        #[arg(required = true)]
+        paths: Vec<String>,
+    },
+    /// List the namespaces with their actions, required variables and storages.
+    Namespaces {
+        /// Only these namespaces.
+        names: Vec<String>,
+        /// Output format, "markdown" is the documentation shown to the model.
+        #[arg(long, default_value_t, value_enum)]
+        format: crate::cli::namespaces::Format,
+    },
+    /// Run the tests bundled with one or more tasklets and exit with an error if any fails.
+    Test {
+        /// Test files or tasklet folders containing a test.yml file.
+        #[arg(required = true)]
+        paths: Vec<String>,
+        #[command(flatten)]
+        args: Args,
+    },
+    /// Run the tests of one or more tasklets several times with each model and compare their scores.
+    Eval {
+        /// Test files or tasklet folders containing a test.yml file.
+        #[arg(required = true)]
+        paths: Vec<String>,
+        /// Comma separated generators to compare, by default the one set with -G.
+        #[arg(long, value_delimiter = ',')]
+        models: Vec<String>,
+        /// How many times each test runs with each model.
+        #[arg(long, default_value_t = 3)]
+        runs: usize,
+        /// Also save the scores to this CSV file.
+        #[arg(long)]
+        csv: Option<String>,
+        #[command(flatten)]
+        args: Args,
+    },
+    /// Render a session saved with --checkpoint step by step.
+    Replay {
+        /// Session file or name.
+        session: String,
+        /// Instead of rendering it, re-execute the session against its tasklet using the recorded responses.
+        #[arg(long)]
+        mock: bool,
+        /// Milliseconds to wait between steps.
+        #[arg(long, default_value_t = 0)]
+        delay: u64,
+        /// Wait for the user to press enter before each step.
+        #[arg(long)]
+        interactive: bool,
+        #[command(flatten)]
+        args: Args,
+    },
+    /// Serve an HTTP API to submit, monitor and cancel runs.
+    Serve {
+        /// Address to listen on.
+        #[arg(long, default_value = "127.0.0.1:8667")]
+        address: String,
+        /// If set, clients must send it as an `Authorization: Bearer` header. Can also be set with $NERVE_SERVE_TOKEN.
+        #[arg(long)]
+        token: Option<String>,
+        /// Origin allowed to call the API from a browser.
+        #[arg(long)]
+        allow_origin: Option<String>,
+        /// Maximum number of runs executed at the same time, the others are queued.
+        #[arg(long, default_value_t = 4)]
+        workers: usize,
+        /// Number of finished runs kept in memory, the oldest ones are forgotten.
+        #[arg(long, default_value_t = 100)]
+        keep_runs: usize,
+        /// Generator that submissions can use instead of the one of the server, can be repeated.
+        #[arg(long)]
+        allow_generator: Vec<String>,
+        #[command(flatten)]
+        args: Args,
+    },
+    /// Claim the tasklets queued with `nerve dispatch` and execute them, reporting their events back.
+    Worker {
+        /// Queue to claim the tasklets from, as redis://[[user]:password@]host[:port][/db].
+        #[arg(long)]
+        queue: String,
+        /// Prefix of the queue keys, shared by the coordinators and the workers.
+        #[arg(long, default_value = "nerve")]
+        queue_name: String,
+        /// Maximum number of tasklets executed at the same time.
+        #[arg(long, default_value_t = 4)]
+        workers: usize,
+        #[command(flatten)]
+        args: Args,
+    },
+    /// Queue a tasklet, or the tasklets of a batch with --all, for the workers and follow their runs.
+    Dispatch {
+        /// Queue to send the tasklets to, as redis://[[user]:password@]host[:port][/db].
+        #[arg(long)]
+        queue: String,
+        /// Prefix of the queue keys, shared by the coordinators and the workers.
+        #[arg(long, default_value = "nerve")]
+        queue_name: String,
+        /// Seconds to wait for a worker before failing the tasklets that nobody claimed.
+        #[arg(long, default_value_t = 300)]
+        claim_timeout: u64,
+        #[command(flatten)]
+        args: Args,
+    },
+    /// Run tasklets on the cron schedules of a file, saving the report of every run.
+    Daemon {
+        /// Schedule file with the jobs to run.
+        schedule: String,
+        #[command(flatten)]
+        args: Args,
+    },
+    /// List, show and delete the sessions saved in ~/.nerve/sessions.
+    Sessions {
+        #[command(subcommand)]
+        command: SessionsCommand,
+    },
+    /// Compare the actions, steps, outcome and cost of two recorded runs.
+    Diff {
+        /// Session file or name of the first run.
+        a: String,
+        /// Session file or name of the second run.
+        b: String,
+    },
+    /// Verify the audit logs written with --audit-log.
+    Audit {
+        #[command(subcommand)]
+        command: AuditCommand,
+    },
+    /// Execute a single action outside of an agent and print its output.
+    Tool {
+        /// Action name, for instance http_request or http-request.
+        action: String,
+        /// Set an attribute of the invocation, as NAME=VALUE.
+        #[arg(long = "attr", value_name = "NAME=VALUE")]
+        attrs: Vec<String>,
+        /// Payload of the invocation, use - to read it from stdin.
+        #[arg(long)]
+        payload: Option<String>,
+        #[command(flatten)]
+        args: Args,
+    },
+    /// Show where the API keys of the generators are read from, and which ones are missing.
+    Credentials {
+        #[command(flatten)]
+        args: Args,
+    },
+    /// Expose the built-in namespaces as tools of an MCP server over stdio.
+    McpServe {
+        /// Comma separated namespaces to expose, "*" for the default ones and "!name" to exclude one.
+        #[arg(long, value_delimiter = ',')]
+        using: Vec<String>,
+        #[command(flatten)]
+        args: Args,
+    },
+}
+
+impl Command {
+    // the run options of the commands that execute tasklets
+    pub fn args(&self) -> Option<&Args> {
+        match self {
+            Self::Run(args)
+            | Self::Test { args, .. }
+            | Self::Eval { args, .. }
+            | Self::Replay { args, .. }
+            | Self::Serve { args, .. }
+            | Self::Daemon { args, .. }
+            | Self::Worker { args, .. }
+            | Self::Dispatch { args, .. }
+            | Self::Tool { args, .. }
+            | Self::Credentials { args }
+            | Self::McpServe { args, .. } => Some(args),
+            _ => None,
+        }
+    }
+
+    pub fn args_mut(&mut self) -> Option<&mut Args> {
+        match self {
+            Self::Run(args)
+            | Self::Test { args, .. }
+            | Self::Eval { args, .. }
+            | Self::Replay { args, .. }
+            | Self::Serve { args, .. }
+            | Self::Daemon { args, .. }
+            | Self::Worker { args, .. }
+            | Self::Dispatch { args, .. }
+            | Self::Tool { args, .. }
+            | Self::Credentials { args }
+            | Self::McpServe { args, .. } => Some(args),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum AuditCommand {
+    /// Check that no entry of an audit log was changed, removed or reordered.
+    Verify {
+        /// Audit log file.
+        path: String,
+    },
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum SessionsCommand {
+    /// List the saved sessions, most recently updated first.
+    List,
+    /// Show the status, prompt and storages of a session.
+    Show {
+        /// Session name or file.
+        name: String,
+    },
+    /// Delete one or more sessions.
+    Delete {
+        /// Session names or files.
+        #[arg(required = true)]
+        names: Vec<String>,
+    },
+    /// Export a session as a trace for LLM observability and evaluation tools.
+    Export {
+        /// Session name or file.
+        name: String,
+        /// "inspect" for an Inspect eval log, "langsmith" for a batch of LangSmith runs.
+        #[arg(long, value_enum)]
+        format: crate::cli::trace::Format,
+        /// Write the trace to this file instead of the standard output.
+        #[arg(long, short = 'o')]
+        output: Option<String>,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &str) -> Command {
+        Cli::try_parse_from(with_default_command(
+            args.split_whitespace().map(OsString::from),
+        ))
+        .unwrap()
+        .command
+    }
+
+    #[test]
+    fn test_commands() {
+        match parse("nerve run -G openai://gpt-4o -T recon.yml") {
+            Command::Run(args) => {
+                assert_eq!(args.generator, "openai://gpt-4o");
+                assert_eq!(args.tasklet.as_deref(), Some("recon.yml"));
+            }
+            command => panic!("unexpected {:?}", command),
+        }
+
+        // the shorthand without the run command
+        match parse("nerve -T recon.yml --max-iterations 5") {
+            Command::Run(args) => {
+                assert_eq!(args.generator, "ollama://llama3@localhost:11434");
+                assert_eq!(args.max_iterations, 5);
+            }
+            command => panic!("unexpected {:?}", command),
+        }
+
+        match parse("nerve serve -G openai://gpt-4o --address 0.0.0.0:8667") {
+            Command::Serve { address, args, .. } => {
+                assert_eq!(address, "0.0.0.0:8667");
+                assert_eq!(args.generator, "openai://gpt-4o");
+            }
+            command => panic!("unexpected {:?}", command),
+        }
+
+        // the options of the profiles are overridden by the command line ones
+        match parse("nerve run --generator openai://gpt-4o --max-cost 2.5 -G ollama://llama3 --tui")
+        {
+            Command::Run(args) => {
+                assert_eq!(args.generator, "ollama://llama3");
+                assert_eq!(args.max_cost, Some(2.5));
+            }
+            command => panic!("unexpected {:?}", command),
+        }
+
+        assert!(matches!(
+            parse("nerve --generate-doc"),
+            Command::Namespaces { names, format: crate::cli::namespaces::Format::Markdown } if names.is_empty()
+        ));
+        assert!(matches!(
+            parse("nerve -G openai://gpt-4o --generate-doc"),
+            Command::Namespaces { .. }
+        ));
+
+        assert!(matches!(
+            parse("nerve namespaces shell --format json"),
+            Command::Namespaces { names, .. } if names == vec!["shell"]
+        ));
+        match parse(
+            "nerve tool http-request --var HTTP_TARGET=http://x --attr method=GET --payload /",
+        ) {
+            Command::Tool {
+                action,
+                attrs,
+                payload,
+                args,
+            } => {
+                assert_eq!(action, "http-request");
+                assert_eq!(args.vars, vec!["HTTP_TARGET=http://x"]);
+                assert_eq!(attrs, vec!["method=GET"]);
+                assert_eq!(payload.as_deref(), Some("/"));
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        match parse("nerve eval recon --models ollama://llama3,groq://llama3 --runs 5") {
+            Command::Eval {
+                paths,
+                models,
+                runs,
+                ..
+            } => {
+                assert_eq!(paths, vec!["recon"]);
+                assert_eq!(models, vec!["ollama://llama3", "groq://llama3"]);
+                assert_eq!(runs, 5);
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        assert!(parse("nerve sessions list").args().is_none());
+        assert!(Cli::try_parse_from(["nerve", "sessions", "-G", "x"]).is_err());
+        assert!(Cli::try_parse_from(["nerve"]).is_err());
+    }
+
+    #[test]
+    fn test_variables() {
+        let folder =
+            std::env::temp_dir().join(format!("nerve-test-var-file-{}", std::process::id()));
+        std::fs::create_dir_all(&folder).unwrap();
+        let env = folder.join("vars.env");
+        std::fs::write(
+            &env,
+            "# target\nexport TARGET=\"10.0.0.1\"\n\nUSER = root\nMOTD='hello world'\n",
+        )
+        .unwrap();
+        let yaml = folder.join("vars.yml");
+        std::fs::write(&yaml, "PORT: 22\nUSER: admin\n").unwrap();
+
+        let mut args = match parse(&format!(
+            "nerve --var-file {} --var-file {} -D TARGET=10.0.0.2 --var PORT=2222",
+            env.display(),
+            yaml.display()
+        )) {
+            Command::Run(args) => args,
+            command => panic!("unexpected {:?}", command),
+        };
+        args.resolve_variables().unwrap();
+        assert_eq!(
+            args.define,
+            vec![
+                "TARGET=10.0.0.1",
+                "USER=root",
+                "MOTD=hello world",
+                "PORT=22",
+                "USER=admin",
+                "TARGET=10.0.0.2",
+                "PORT=2222"
+            ]
+        );
+
+        std::fs::write(&env, "TARGET\n").unwrap();
+        assert!(parse_var_file(&env.display().to_string())
+            .unwrap_err()
+            .to_string()
+            .ends_with(":1: syntax is NAME=VALUE"));
+
+        let mut args = Args {
+            vars: vec!["TARGET".to_string()],
+            ..Default::default()
+        };
+        assert!(args.resolve_variables().is_err());
+
+        let _ = std::fs::remove_dir_all(&folder);
+    }
 }
@@ -0,0 +1,429 @@
+use std::{collections::BTreeMap, ffi::OsString, path::PathBuf};
+
+use anyhow::Result;
+use clap::Args as _;
+use serde::Deserialize;
+
+use crate::{
+    agent::{
+        access::ActionLists,
+        generator::credentials,
+        policy::Policy,
+        serialization::Strategy,
+        task::hooks::{Hook, Hooks},
+    },
+    cli::{self, sinks::EventSink},
+};
+
+// operator settings applied to every run on top of the command line
+#[derive(Debug, Default, Deserialize)]
+pub struct Config {
+    #[serde(default)]
+    pub allow_actions: Vec<String>,
+    #[serde(default)]
+    pub deny_actions: Vec<String>,
+    // per namespace security policy
+    pub policy: Option<Policy>,
+    // where to send the events of the runs
+    #[serde(default)]
+    pub event_sinks: Vec<EventSink>,
+    // hash chained log of the executed invocations, unless --audit-log is set
+    pub audit_log: Option<String>,
+    // called around every invocation after the hooks of the tasklet
+    pub before_action: Option<Vec<Hook>>,
+    pub after_action: Option<Vec<Hook>>,
+    // how long each of them can take
+    pub hook_timeout: Option<String>,
+    // where the API keys of the generators are read from, by generator
+    #[serde(default)]
+    pub credentials: BTreeMap<String, credentials::Source>,
+    // settings of the generators matching a pattern, like ollama://qwen*
+    #[serde(default)]
+    pub models: BTreeMap<String, ModelSettings>,
+    // profile used when --profile is not set
+    pub profile: Option<String>,
+    #[serde(default)]
+    pub profiles: BTreeMap<String, Profile>,
+}
+
+#[derive(Debug, Default, Clone, Deserialize)]
+pub struct ModelSettings {
+    // format of the invocations in the prompts and responses, unless --serialization is set
+    pub serialization: Option<Strategy>,
+}
+
+// * matches any sequence of characters
+fn matches(pattern: &str, text: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let mut rest = match text.strip_prefix(first) {
+        Some(rest) => rest,
+        None => return false,
+    };
+
+    let parts: Vec<&str> = parts.collect();
+    for (idx, part) in parts.iter().enumerate() {
+        if idx == parts.len() - 1 {
+            return rest.ends_with(part);
+        }
+        match rest.find(part) {
+            Some(found) => rest = &rest[found + part.len()..],
+            None => return false,
+        }
+    }
+
+    // no wildcards
+    rest.is_empty()
+}
+
+// a named set of settings selected with --profile
+#[derive(Debug, Default, Clone, Deserialize)]
+pub struct Profile {
+    // environment variables, like the API keys of the generators, unless already set
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+    // override the top level credentials
+    #[serde(default)]
+    pub credentials: BTreeMap<String, credentials::Source>,
+    // replaces the top level policy
+    pub policy: Option<Policy>,
+    // added to the top level sinks
+    #[serde(default)]
+    pub event_sinks: Vec<EventSink>,
+    // replaces the top level audit log
+    pub audit_log: Option<String>,
+    // any other key is a command line option of the runs, like generator or max_cost
+    #[serde(flatten)]
+    pub options: BTreeMap<String, serde_yaml::Value>,
+}
+
+impl Profile {
+    // the options of the profile as command line arguments
+    pub fn arguments(&self) -> Result<Vec<OsString>> {
+        let command = cli::Args::augment_args(clap::Command::new("profile"));
+
+        let mut arguments = vec![];
+        for (key, value) in &self.options {
+            let option = key.replace('_', "-");
+            if option == "profile"
+                || option == "config"
+                || !command
+                    .get_arguments()
+                    .any(|arg| arg.get_long() == Some(option.as_str()))
+            {
+                return Err(anyhow!("unknown option '{}'", key));
+            }
+            let option = format!("--{}", option);
+
+            let values = match value {
+                serde_yaml::Value::Sequence(values) => values.clone(),
+                value => vec![value.clone()],
+            };
+            for value in values {
+                match value {
+                    serde_yaml::Value::Bool(true) => arguments.push(option.clone().into()),
+                    serde_yaml::Value::Bool(false) | serde_yaml::Value::Null => {}
+                    serde_yaml::Value::String(value) => {
+                        arguments.push(option.clone().into());
+                        arguments.push(value.into());
+                    }
+                    serde_yaml::Value::Number(value) => {
+                        arguments.push(option.clone().into());
+                        arguments.push(value.to_string().into());
+                    }
+                    _ => return Err(anyhow!("invalid value of '{}'", key)),
+                }
+            }
+        }
+
+        Ok(arguments)
+    }
+}
+
+impl Config {
+    // load the given file or ~/.nerve/config.yml (or .yaml) if it exists, with the settings of
+    // the profile, or of the default one of the file
+    pub fn load(path: Option<&String>, profile: Option<&str>) -> Result<Self> {
+        let path = match path {
+            Some(path) => PathBuf::from(path),
+            None => {
+                let data = crate::agent::data_path("")?;
+                match ["config.yml", "config.yaml"]
+                    .iter()
+                    .map(|name| data.join(name))
+                    .find(|path| path.exists())
+                {
+                    Some(path) => path,
+                    None => match profile {
+                        Some(name) => {
+                            return Err(anyhow!(
+                                "profile '{}' used but {} does not exist",
+                                name,
+                                data.join("config.yml").display()
+                            ))
+                        }
+                        None => return Ok(Self::default()),
+                    },
+                }
+            }
+        };
+
+        let yaml = std::fs::read_to_string(&path)
+            .map_err(|e| anyhow!("could not read {}: {}", path.display(), e))?;
+
+        let mut config: Self = serde_yaml::from_str(&yaml)
+            .map_err(|e| anyhow!("invalid {}: {}", path.display(), e))?;
+        if let Some(name) = profile.map(str::to_string).or(config.profile.clone()) {
+            let profile = config.profiles.get(&name).cloned().ok_or_else(|| {
+                anyhow!("profile '{}' is not defined in {}", name, path.display())
+            })?;
+            config.profile = Some(name);
+            config.credentials.extend(profile.credentials);
+            if profile.policy.is_some() {
+                config.policy = profile.policy;
+            }
+            config.event_sinks.extend(profile.event_sinks);
+            if profile.audit_log.is_some() {
+                config.audit_log = profile.audit_log;
+            }
+        }
+
+        for generator in config.credentials.keys() {
+            if credentials::env_var(generator).is_none() {
+                return Err(anyhow!(
+                    "invalid credentials in {}: generator '{}' does not use an API key",
+                    path.display(),
+                    generator
+                ));
+            }
+        }
+        if let Some(policy) = &config.policy {
+            policy.validate()?;
+        }
+        Hooks {
+            hook_timeout: config.hook_timeout.clone(),
+            ..Default::default()
+        }
+        .validate()?;
+        for sink in &config.event_sinks {
+            sink.validate()?;
+        }
+
+        Ok(config)
+    }
+
+    // the settings of the longest pattern matching the generator
+    pub fn model_settings(&self, generator: &str) -> Option<&ModelSettings> {
+        self.models
+            .iter()
+            .filter(|(pattern, _)| matches(pattern, generator))
+            .max_by_key(|(pattern, _)| pattern.len())
+            .map(|(_, settings)| settings)
+    }
+
+    pub fn serialization(&self, args: &cli::Args) -> Strategy {
+        args.serialization
+            .clone()
+            .or_else(|| {
+                self.model_settings(&args.generator)
+                    .and_then(|settings| settings.serialization.clone())
+            })
+            .unwrap_or_default()
+    }
+
+    // the action hooks as a middleware of the runs of the given task
+    pub fn action_hooks(&self, task_name: &str) -> Option<Hooks> {
+        let hooks = Hooks {
+            task_name: task_name.to_string(),
+            before_action: self.before_action.clone(),
+            after_action: self.after_action.clone(),
+            hook_timeout: self.hook_timeout.clone(),
+            ..Default::default()
+        };
+        Some(hooks).filter(|hooks| hooks.has_action_hooks())
+    }
+
+    pub fn action_lists(&self) -> ActionLists {
+        ActionLists::new(self.allow_actions.clone(), self.deny_actions.clone())
+    }
+
+    // the profile selected when loading
+    pub fn selected_profile(&self) -> Option<(&String, &Profile)> {
+        self.profile
+            .as_ref()
+            .and_then(|name| self.profiles.get_key_value(name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load() {
+        let path = std::env::temp_dir().join("nerve-test-config.yml");
+        std::fs::write(&path, "deny_actions: [shell, append_to_file]\n").unwrap();
+
+        let config = Config::load(Some(&path.display().to_string()), None).unwrap();
+        let lists = config.action_lists();
+        assert!(!lists.is_allowed("shell", "shell"));
+        assert!(lists.is_allowed("filesystem", "read_file"));
+
+        let _ = std::fs::remove_file(&path);
+        assert!(Config::load(Some(&path.display().to_string()), None).is_err());
+    }
+
+    #[test]
+    fn test_profiles() {
+        let path = std::env::temp_dir().join("nerve-test-config-profiles.yml");
+        std::fs::write(
+            &path,
+            r#"
+audit_log: /var/log/nerve/audit.jsonl
+profile: local
+profiles:
+  local:
+    generator: ollama://llama3@localhost:11434
+  work:
+    env:
+      OPENAI_API_KEY: sk-test
+    generator: openai://gpt-4o
+    max_cost: 2.5
+    tui: true
+    deny_actions: [shell, http]
+    audit_log: /tmp/work.jsonl
+    event_sinks:
+      - jsonl: /tmp/events.jsonl
+"#,
+        )
+        .unwrap();
+        let path = path.display().to_string();
+
+        let config = Config::load(Some(&path), None).unwrap();
+        assert_eq!(config.profile.as_deref(), Some("local"));
+        assert_eq!(
+            config.audit_log.as_deref(),
+            Some("/var/log/nerve/audit.jsonl")
+        );
+
+        let config = Config::load(Some(&path), Some("work")).unwrap();
+        assert_eq!(config.audit_log.as_deref(), Some("/tmp/work.jsonl"));
+        assert_eq!(config.event_sinks.len(), 1);
+
+        let (name, profile) = config.selected_profile().unwrap();
+        assert_eq!(name, "work");
+        assert_eq!(profile.env["OPENAI_API_KEY"], "sk-test");
+        assert_eq!(
+            profile.arguments().unwrap(),
+            vec![
+                "--deny-actions",
+                "shell",
+                "--deny-actions",
+                "http",
+                "--generator",
+                "openai://gpt-4o",
+                "--max-cost",
+                "2.5",
+                "--tui"
+            ]
+        );
+
+        assert!(Config::load(Some(&path), Some("home")).is_err());
+
+        let profile: Profile = serde_yaml::from_str("teleport: true").unwrap();
+        assert!(profile.arguments().is_err());
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_matches() {
+        assert!(matches(
+            "ollama://qwen*",
+            "ollama://qwen2.5@localhost:11434"
+        ));
+        assert!(matches("*gpt-4o*", "openai://gpt-4o-mini"));
+        assert!(matches("openai://*-mini", "openai://gpt-4o-mini"));
+        assert!(matches("openai://gpt-4o", "openai://gpt-4o"));
+        assert!(!matches("openai://gpt-4o", "openai://gpt-4o-mini"));
+        assert!(!matches("ollama://*", "openai://gpt-4o"));
+        assert!(!matches("*a*b", "ba"));
+    }
+
+    #[test]
+    fn test_models() {
+        let config: Config = serde_yaml::from_str(
+            r#"
+models:
+  "ollama://*":
+    serialization: json
+  "ollama://qwen*":
+    serialization: yaml
+"#,
+        )
+        .unwrap();
+
+        let args = |generator: &str, serialization: Option<Strategy>| cli::Args {
+            generator: generator.to_string(),
+            serialization,
+            ..Default::default()
+        };
+        assert_eq!(
+            config.serialization(&args("ollama://llama3@localhost:11434", None)),
+            Strategy::Json
+        );
+        // the most specific pattern wins
+        assert_eq!(
+            config.serialization(&args("ollama://qwen2.5@localhost:11434", None)),
+            Strategy::Yaml
+        );
+        assert_eq!(
+            config.serialization(&args("openai://gpt-4o", None)),
+            Strategy::Xml
+        );
+        assert_eq!(
+            config.serialization(&args("ollama://qwen2.5", Some(Strategy::Xml))),
+            Strategy::Xml
+        );
+    }
+
+    #[test]
+    fn test_credentials() {
+        let path = std::env::temp_dir().join("nerve-test-config-credentials.yml");
+        std::fs::write(
+            &path,
+            r#"
+credentials:
+  openai: env:PERSONAL_OPENAI_KEY
+  groq: keychain
+profiles:
+  work:
+    credentials:
+      openai: keychain:work-openai
+"#,
+        )
+        .unwrap();
+        let path = path.display().to_string();
+
+        let config = Config::load(Some(&path), None).unwrap();
+        assert_eq!(
+            config.credentials["openai"],
+            credentials::Source::Env("PERSONAL_OPENAI_KEY".to_string())
+        );
+
+        let config = Config::load(Some(&path), Some("work")).unwrap();
+        assert_eq!(
+            config.credentials["openai"],
+            credentials::Source::Keychain(Some("work-openai".to_string()))
+        );
+        assert_eq!(
+            config.credentials["groq"],
+            credentials::Source::Keychain(None)
+        );
+
+        std::fs::write(&path, "credentials:\n  ollama: key\n").unwrap();
+        assert!(Config::load(Some(&path), None).is_err());
+
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,47 @@
+use anyhow::Result;
+use colored::Colorize;
+
+use crate::agent::generator::credentials;
+
+// enough to tell keys apart without showing them
+fn masked(key: &str) -> String {
+    let chars = key.chars().collect::<Vec<_>>();
+    if chars.len() < 16 {
+        "********".to_string()
+    } else {
+        format!(
+            "********{}",
+            chars[chars.len() - 4..].iter().collect::<String>()
+        )
+    }
+}
+
+pub fn run() -> Result<()> {
+    for generator in credentials::generators() {
+        let var = credentials::env_var(generator).unwrap();
+        match credentials::resolve(generator) {
+            Ok(Some((key, origin))) => println!(
+                "{} {} ({}, {})",
+                "ok".green().bold(),
+                generator,
+                origin,
+                masked(&key).dimmed()
+            ),
+            Ok(None) => println!("{} {} ({} not set)", "missing".dimmed(), generator, var),
+            Err(err) => println!("{} {}: {}", "error".red().bold(), generator, err),
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_masked() {
+        assert_eq!(masked("short"), "********");
+        assert_eq!(masked("sk-0123456789abcdef"), "********cdef");
+    }
+}
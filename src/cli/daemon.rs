@@ -0,0 +1,523 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use anyhow::Result;
+use chrono::{DateTime, Datelike, Local, LocalResult, NaiveDate, TimeDelta, TimeZone, Timelike};
+use colored::Colorize;
+use serde::{Deserialize, Deserializer};
+use tokio::{sync::watch, task::JoinSet};
+
+use crate::{
+    agent::{data_path, task::variables},
+    cli::{self, batch, report::Report, setup, signals},
+    APP_NAME, APP_VERSION,
+};
+
+const MONTHS: [&str; 12] = [
+    "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+];
+const WEEKDAYS: [&str; 7] = ["sun", "mon", "tue", "wed", "thu", "fri", "sat"];
+
+// how long to sleep at most before checking the schedule again, in case the clock changed
+const MAX_SLEEP: Duration = Duration::from_secs(60);
+
+// a cron expression with the minute, hour, day of the month, month and day of the week fields,
+// each one stored as a bitmask of the matching values
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cron {
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    // if both days are restricted, either of them matches
+    any_day: bool,
+    any_weekday: bool,
+}
+
+fn parse_value(value: &str, min: u32, max: u32, names: &[&str]) -> Result<u32> {
+    let value = match names
+        .iter()
+        .position(|name| name.eq_ignore_ascii_case(value))
+    {
+        Some(idx) => idx as u32 + min,
+        None => value
+            .parse::<u32>()
+            .map_err(|_| anyhow!("invalid value '{}'", value))?,
+    };
+    if value < min || value > max {
+        return Err(anyhow!("{} is not between {} and {}", value, min, max));
+    }
+    Ok(value)
+}
+
+// *, 5, 1-5, */15, 0-30/10 or 10/10, comma separated
+fn parse_field(field: &str, min: u32, max: u32, names: &[&str]) -> Result<u64> {
+    let mut bits = 0u64;
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (
+                range,
+                step.parse::<usize>()
+                    .ok()
+                    .filter(|step| *step > 0)
+                    .ok_or_else(|| anyhow!("invalid step '{}'", step))?,
+            ),
+            None => (part, 1),
+        };
+        let (start, end) = if range == "*" {
+            (min, max)
+        } else if let Some((start, end)) = range.split_once('-') {
+            (
+                parse_value(start, min, max, names)?,
+                parse_value(end, min, max, names)?,
+            )
+        } else {
+            let start = parse_value(range, min, max, names)?;
+            // 10/10 is from 10 to the end
+            (start, if step > 1 { max } else { start })
+        };
+        if start > end {
+            return Err(anyhow!("invalid range '{}'", range));
+        }
+        for value in (start..=end).step_by(step) {
+            bits |= 1 << value;
+        }
+    }
+    Ok(bits)
+}
+
+impl FromStr for Cron {
+    type Err = anyhow::Error;
+
+    fn from_str(expr: &str) -> Result<Self> {
+        let expanded = match expr.trim() {
+            "@yearly" | "@annually" => "0 0 1 1 *",
+            "@monthly" => "0 0 1 * *",
+            "@weekly" => "0 0 * * 0",
+            "@daily" | "@midnight" => "0 0 * * *",
+            "@hourly" => "0 * * * *",
+            expr => expr,
+        };
+        let fields: Vec<&str> = expanded.split_whitespace().collect();
+        if fields.len() != 5 {
+            return Err(anyhow!(
+                "invalid schedule '{}': expected minute, hour, day of the month, month and day of the week",
+                expr
+            ));
+        }
+
+        let error = |err: anyhow::Error| anyhow!("invalid schedule '{}': {}", expr, err);
+        // 7 is sunday as well
+        let mut weekdays = parse_field(fields[4], 0, 7, &WEEKDAYS).map_err(error)?;
+        if weekdays & (1 << 7) != 0 {
+            weekdays = (weekdays | 1) & !(1 << 7);
+        }
+
+        Ok(Self {
+            minutes: parse_field(fields[0], 0, 59, &[]).map_err(error)?,
+            hours: parse_field(fields[1], 0, 23, &[]).map_err(error)?,
+            days: parse_field(fields[2], 1, 31, &[]).map_err(error)?,
+            months: parse_field(fields[3], 1, 12, &MONTHS).map_err(error)?,
+            weekdays,
+            any_day: fields[2] == "*",
+            any_weekday: fields[4] == "*",
+        })
+    }
+}
+
+impl Cron {
+    fn matches_day(&self, date: &NaiveDate) -> bool {
+        let day = self.days & (1 << date.day()) != 0;
+        let weekday = self.weekdays & (1 << date.weekday().num_days_from_sunday()) != 0;
+        if self.any_day || self.any_weekday {
+            day && weekday
+        } else {
+            day || weekday
+        }
+    }
+
+    // the first matching minute after the given time, if any in the next years
+    pub fn next_after(&self, after: &DateTime<Local>) -> Option<DateTime<Local>> {
+        let mut time =
+            after.naive_local().with_second(0)?.with_nanosecond(0)? + TimeDelta::minutes(1);
+        // february 29th can be up to 8 years away
+        let limit = time + TimeDelta::days(366 * 9);
+
+        while time < limit {
+            if self.months & (1 << time.month()) == 0 {
+                let (year, month) = if time.month() == 12 {
+                    (time.year() + 1, 1)
+                } else {
+                    (time.year(), time.month() + 1)
+                };
+                time = NaiveDate::from_ymd_opt(year, month, 1)?.and_hms_opt(0, 0, 0)?;
+            } else if !self.matches_day(&time.date()) {
+                time = time.date().succ_opt()?.and_hms_opt(0, 0, 0)?;
+            } else if self.hours & (1 << time.hour()) == 0 {
+                time = time.with_minute(0)? + TimeDelta::hours(1);
+            } else if self.minutes & (1 << time.minute()) == 0 {
+                time += TimeDelta::minutes(1);
+            } else {
+                match Local.from_local_datetime(&time) {
+                    LocalResult::Single(time) => return Some(time),
+                    // the first one when the clock goes back
+                    LocalResult::Ambiguous(time, _) => return Some(time),
+                    // skipped when the clock goes forward
+                    LocalResult::None => time += TimeDelta::minutes(1),
+                }
+            }
+        }
+
+        None
+    }
+}
+
+// a tasklet to run on a schedule
+#[derive(Debug, Clone, Deserialize)]
+pub struct Job {
+    pub name: String,
+    pub schedule: String,
+    #[serde(flatten)]
+    pub entry: batch::Entry,
+    #[serde(default, deserialize_with = "scalars")]
+    pub variables: BTreeMap<String, String>,
+}
+
+// variables can be written as numbers and booleans too
+fn scalars<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<BTreeMap<String, String>, D::Error> {
+    BTreeMap::<String, serde_yaml::Value>::deserialize(deserializer)?
+        .into_iter()
+        .map(|(name, value)| match value {
+            serde_yaml::Value::String(value) => Ok((name, value)),
+            serde_yaml::Value::Number(value) => Ok((name, value.to_string())),
+            serde_yaml::Value::Bool(value) => Ok((name, value.to_string())),
+            _ => Err(serde::de::Error::custom(format!(
+                "variable {} must be a scalar",
+                name
+            ))),
+        })
+        .collect()
+}
+
+#[derive(Debug, Deserialize)]
+struct ScheduleFile {
+    // where the reports of the runs are saved, ~/.nerve/reports by default
+    reports: Option<String>,
+    jobs: Vec<Job>,
+}
+
+#[derive(Debug)]
+pub struct Schedule {
+    pub reports: PathBuf,
+    pub jobs: Vec<(Job, Cron)>,
+}
+
+impl Schedule {
+    pub fn from_path(path: &str) -> Result<Self> {
+        let canon =
+            std::fs::canonicalize(path).map_err(|e| anyhow!("could not read {path}: {e}"))?;
+        let yaml = std::fs::read_to_string(&canon)?;
+        let file: ScheduleFile = serde_yaml::from_str(&yaml)
+            .map_err(|e| anyhow!("could not parse {}: {e}", canon.display()))?;
+        if file.jobs.is_empty() {
+            return Err(anyhow!("no jobs defined in {}", canon.display()));
+        }
+
+        // relative to the schedule file
+        let folder = canon.parent().unwrap();
+        let reports = match &file.reports {
+            Some(reports) => folder.join(reports).components().collect(),
+            None => data_path("reports")?,
+        };
+
+        let mut names = HashSet::new();
+        let mut jobs = vec![];
+        for mut job in file.jobs {
+            if !names.insert(job.name.to_string()) {
+                return Err(anyhow!("job '{}' is defined more than once", job.name));
+            }
+            // the name is used for the reports folder
+            if job.name.is_empty() || job.name.starts_with('.') || job.name.contains(['/', '\\']) {
+                return Err(anyhow!("invalid job name '{}'", job.name));
+            }
+            let cron =
+                Cron::from_str(&job.schedule).map_err(|e| anyhow!("job '{}': {}", job.name, e))?;
+
+            let tasklet = Path::new(&job.entry.tasklet);
+            if tasklet.is_relative() && folder.join(tasklet).exists() {
+                let tasklet = std::fs::canonicalize(folder.join(tasklet))?;
+                job.entry.tasklet = tasklet.display().to_string();
+            }
+            jobs.push((job, cron));
+        }
+
+        Ok(Self { reports, jobs })
+    }
+}
+
+// removes the job from the running ones even if its run panics
+struct Running {
+    jobs: Arc<Mutex<HashSet<String>>>,
+    name: String,
+}
+
+impl Drop for Running {
+    fn drop(&mut self) {
+        self.jobs.lock().unwrap().remove(&self.name);
+    }
+}
+
+async fn run_job(
+    args: cli::Args,
+    job: Job,
+    reports: PathBuf,
+    stop: watch::Receiver<bool>,
+    _running: Running,
+) {
+    log::info!("running job {} ...", job.name.bold());
+
+    let started = Local::now();
+    // the variables of the job are only visible to this run
+    let (outcome, session) = variables::scoped(
+        job.variables.clone().into_iter().collect(),
+        batch::run_entry(&args, &job.entry, Some(stop)),
+    )
+    .await;
+    match &outcome.error {
+        Some(error) => log::error!("job {} {}: {}", job.name, outcome.status, error),
+        None => log::info!("job {} {}", job.name.bold(), outcome.status),
+    }
+
+    if let Some(session) = session {
+        let folder = reports.join(&job.name);
+        let path = folder.join(started.format("%Y%m%d-%H%M%S").to_string());
+        match std::fs::create_dir_all(&folder)
+            .map_err(|e| anyhow!("can't create {}: {}", folder.display(), e))
+            .and_then(|_| Report::from_session(&session).save(&path.display().to_string()))
+        {
+            Ok((json, md)) => log::info!("report saved to {} and {}", json.display(), md.display()),
+            Err(err) => log::error!("could not save the report of {}: {}", job.name, err),
+        }
+    }
+}
+
+// run the jobs of the schedule until interrupted
+pub async fn run(args: &cli::Args, path: &str) -> Result<()> {
+    let schedule = Schedule::from_path(path)?;
+    for (job, _) in &schedule.jobs {
+        let mut args = args.clone();
+        if let Some(generator) = &job.entry.generator {
+            args.generator = generator.to_string();
+        }
+        setup::check_credentials(&args).map_err(|e| anyhow!("job '{}': {}", job.name, e))?;
+    }
+
+    log::info!(
+        "{} v{} daemon, {} jobs from {}, reports in {}",
+        APP_NAME,
+        APP_VERSION,
+        schedule.jobs.len(),
+        path.green().bold(),
+        schedule.reports.display()
+    );
+
+    let now = Local::now();
+    let mut next: Vec<Option<DateTime<Local>>> = schedule
+        .jobs
+        .iter()
+        .map(|(_, cron)| cron.next_after(&now))
+        .collect();
+    for ((job, _), at) in schedule.jobs.iter().zip(&next) {
+        match at {
+            Some(at) => log::info!(
+                "  {} ({}) {}, next run at {}",
+                job.name.bold(),
+                job.schedule.dimmed(),
+                job.entry.tasklet,
+                at.format("%Y-%m-%d %H:%M")
+            ),
+            None => log::warn!("  {} ({}) will never run", job.name, job.schedule),
+        }
+    }
+
+    let (stop_tx, stop_rx) = watch::channel(false);
+    let running: Arc<Mutex<HashSet<String>>> = Arc::new(Mutex::new(HashSet::new()));
+    let mut runs = JoinSet::new();
+
+    let interrupted = signals::signal();
+    tokio::pin!(interrupted);
+
+    loop {
+        let at = match next.iter().flatten().min() {
+            Some(at) => *at,
+            None => {
+                log::warn!("no more runs scheduled");
+                break;
+            }
+        };
+        let wait = (at - Local::now()).to_std().unwrap_or_default();
+
+        tokio::select! {
+            _ = tokio::time::sleep(wait.min(MAX_SLEEP)) => {},
+            Some(_) = runs.join_next() => continue,
+            _ = &mut interrupted => break,
+        }
+
+        let now = Local::now();
+        for (idx, (job, cron)) in schedule.jobs.iter().enumerate() {
+            if next[idx].is_none_or(|at| at > now) {
+                continue;
+            }
+            next[idx] = cron.next_after(&now);
+
+            // overlap protection
+            if !running.lock().unwrap().insert(job.name.to_string()) {
+                log::warn!(
+                    "job {} is still running, skipping this run",
+                    job.name.yellow().bold()
+                );
+                continue;
+            }
+            runs.spawn(run_job(
+                args.clone(),
+                job.clone(),
+                schedule.reports.clone(),
+                stop_rx.clone(),
+                Running {
+                    jobs: running.clone(),
+                    name: job.name.to_string(),
+                },
+            ));
+        }
+    }
+
+    if !runs.is_empty() {
+        log::warn!(
+            "stopping {} running jobs at the end of their current step (press again to exit immediately) ...",
+            runs.len()
+        );
+        let _ = stop_tx.send(true);
+        tokio::select! {
+            _ = async { while runs.join_next().await.is_some() {} } => {},
+            _ = signals::signal() => std::process::exit(130),
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(date: &str) -> DateTime<Local> {
+        Local
+            .from_local_datetime(
+                &chrono::NaiveDateTime::parse_from_str(date, "%Y-%m-%d %H:%M").unwrap(),
+            )
+            .unwrap()
+    }
+
+    fn next(expr: &str, after: &str) -> String {
+        Cron::from_str(expr)
+            .unwrap()
+            .next_after(&at(after))
+            .unwrap()
+            .format("%Y-%m-%d %H:%M")
+            .to_string()
+    }
+
+    #[test]
+    fn test_next_after() {
+        // 2026-06-10 is a wednesday
+        assert_eq!(next("*/15 * * * *", "2026-06-10 10:07"), "2026-06-10 10:15");
+        assert_eq!(next("*/15 * * * *", "2026-06-10 10:15"), "2026-06-10 10:30");
+        assert_eq!(
+            next("0 9 * * mon-fri", "2026-06-10 10:00"),
+            "2026-06-11 09:00"
+        );
+        assert_eq!(next("0 9 * * 1-5", "2026-06-12 10:00"), "2026-06-15 09:00");
+        assert_eq!(next("30 2 1 * *", "2026-06-10 10:00"), "2026-07-01 02:30");
+        assert_eq!(next("0 0 1 jan *", "2026-06-10 10:00"), "2027-01-01 00:00");
+        assert_eq!(next("@weekly", "2026-06-10 10:00"), "2026-06-14 00:00");
+        assert_eq!(next("0 0 * * 7", "2026-06-10 10:00"), "2026-06-14 00:00");
+        assert_eq!(next("@hourly", "2026-06-10 23:59"), "2026-06-11 00:00");
+        // either the day of the month or of the week
+        assert_eq!(next("0 0 13 * fri", "2026-06-10 10:00"), "2026-06-12 00:00");
+        assert_eq!(next("0 0 29 2 *", "2026-06-10 10:00"), "2028-02-29 00:00");
+        assert_eq!(
+            next("5,10-12 8/6 * * *", "2026-06-10 10:00"),
+            "2026-06-10 14:05"
+        );
+
+        assert_eq!(
+            Cron::from_str("0 0 31 2 *")
+                .unwrap()
+                .next_after(&at("2026-06-10 10:00")),
+            None
+        );
+        for invalid in [
+            "* * *",
+            "60 * * * *",
+            "*/0 * * * *",
+            "5-1 * * * *",
+            "* * * foo *",
+            "* * 0 * *",
+        ] {
+            assert!(Cron::from_str(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_schedule() {
+        let folder = std::env::temp_dir().join(format!("nerve-test-daemon-{}", std::process::id()));
+        std::fs::create_dir_all(&folder).unwrap();
+        std::fs::write(folder.join("monitor.yml"), "system_prompt: test\n").unwrap();
+        let write = |yaml: &str| {
+            let path = folder.join("schedule.yml");
+            std::fs::write(&path, yaml).unwrap();
+            Schedule::from_path(&path.display().to_string())
+        };
+
+        let schedule = write(
+            "reports: out\njobs:\n  - name: monitor\n    schedule: \"*/5 * * * *\"\n    tasklet: monitor.yml\n    variables:\n      TARGET: example.com\n      PORT: 8080\n",
+        )
+        .unwrap();
+        let folder = std::fs::canonicalize(&folder).unwrap();
+        assert_eq!(schedule.reports, folder.join("out"));
+        let (job, cron) = &schedule.jobs[0];
+        assert_eq!(
+            job.entry.tasklet,
+            folder.join("monitor.yml").display().to_string()
+        );
+        assert_eq!(job.variables.get("TARGET").unwrap(), "example.com");
+        assert_eq!(job.variables.get("PORT").unwrap(), "8080");
+        assert_eq!(
+            cron,
+            &Cron::from_str("0,5,10,15,20,25,30,35,40,45,50,55 * * * *").unwrap()
+        );
+
+        assert!(write(
+            "jobs:\n  - name: a\n    schedule: \"@daily\"\n    tasklet: monitor.yml\n  - name: a\n    schedule: \"@daily\"\n    tasklet: monitor.yml\n"
+        )
+        .unwrap_err()
+        .to_string()
+        .contains("more than once"));
+        assert!(write(
+            "jobs:\n  - name: a\n    schedule: \"every day\"\n    tasklet: monitor.yml\n"
+        )
+        .unwrap_err()
+        .to_string()
+        .starts_with("job 'a': invalid schedule"));
+
+        let _ = std::fs::remove_dir_all(&folder);
+    }
+}
@@ -0,0 +1,296 @@
+use std::{collections::BTreeSet, time::Duration};
+
+use anyhow::Result;
+use colored::Colorize;
+
+use crate::{
+    agent::state::session::Session,
+    cli::{report, sessions},
+};
+
+// an action taken during a run, compared by its call string
+#[derive(Debug, Clone, PartialEq)]
+struct Taken {
+    call: String,
+    failed: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Change<'a> {
+    Both(&'a Taken, &'a Taken),
+    OnlyA(&'a Taken),
+    OnlyB(&'a Taken),
+}
+
+fn taken(session: &Session) -> Vec<Taken> {
+    session
+        .history
+        .iter()
+        .filter(|exec| !exec.is_summary())
+        .map(|exec| Taken {
+            call: exec
+                .invocation()
+                .map(|inv| inv.as_function_call_string())
+                .unwrap_or("unparsed response".to_string()),
+            failed: exec.error().is_some(),
+        })
+        .collect()
+}
+
+// longest common subsequence of the two action sequences
+fn changes<'a>(a: &'a [Taken], b: &'a [Taken]) -> Vec<Change<'a>> {
+    let mut lengths = vec![vec![0usize; b.len() + 1]; a.len() + 1];
+    for i in (0..a.len()).rev() {
+        for j in (0..b.len()).rev() {
+            lengths[i][j] = if a[i].call == b[j].call {
+                lengths[i + 1][j + 1] + 1
+            } else {
+                lengths[i + 1][j].max(lengths[i][j + 1])
+            };
+        }
+    }
+
+    let mut changes = vec![];
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() && j < b.len() {
+        if a[i].call == b[j].call {
+            changes.push(Change::Both(&a[i], &b[j]));
+            i += 1;
+            j += 1;
+        } else if lengths[i + 1][j] >= lengths[i][j + 1] {
+            changes.push(Change::OnlyA(&a[i]));
+            i += 1;
+        } else {
+            changes.push(Change::OnlyB(&b[j]));
+            j += 1;
+        }
+    }
+    changes.extend(a[i..].iter().map(Change::OnlyA));
+    changes.extend(b[j..].iter().map(Change::OnlyB));
+    changes
+}
+
+fn delta(a: f64, b: f64, format: impl Fn(f64) -> String) -> String {
+    let delta = b - a;
+    if delta.abs() < f64::EPSILON {
+        String::new()
+    } else if delta > 0.0 {
+        format!(" (+{})", format(delta))
+    } else {
+        format!(" (-{})", format(-delta))
+    }
+}
+
+fn elapsed(session: &Session) -> Duration {
+    (session.updated_at - session.created_at)
+        .to_std()
+        .unwrap_or_default()
+}
+
+fn row(name: &str, a: String, b: String) {
+    println!("  {:<16} {:<28} {}", name, a, b);
+}
+
+fn count_row(name: &str, a: usize, b: usize) {
+    row(
+        name,
+        a.to_string(),
+        format!("{}{}", b, delta(a as f64, b as f64, |d| d.to_string())),
+    );
+}
+
+fn render(a: &Session, b: &Session) {
+    let (ma, mb) = (&a.metrics, &b.metrics);
+
+    row("", "a".to_string(), "b".to_string());
+    row(
+        "generator",
+        a.generator.to_string(),
+        b.generator.to_string(),
+    );
+    row(
+        "outcome",
+        report::outcome(a).to_string(),
+        report::outcome(b).to_string(),
+    );
+    count_row("steps", ma.current_step, mb.current_step);
+    let (ea, eb) = (elapsed(a), elapsed(b));
+    row(
+        "duration",
+        format!("{:.1}s", ea.as_secs_f64()),
+        format!(
+            "{:.1}s{}",
+            eb.as_secs_f64(),
+            delta(ea.as_secs_f64(), eb.as_secs_f64(), |d| format!("{:.1}s", d))
+        ),
+    );
+    count_row(
+        "input tokens",
+        ma.usage.total_input_tokens as usize,
+        mb.usage.total_input_tokens as usize,
+    );
+    count_row(
+        "output tokens",
+        ma.usage.total_output_tokens as usize,
+        mb.usage.total_output_tokens as usize,
+    );
+    row(
+        "cost",
+        format!("${:.4}", ma.cost),
+        format!(
+            "${:.4}{}",
+            mb.cost,
+            delta(ma.cost, mb.cost, |d| format!("${:.4}", d))
+        ),
+    );
+    count_row(
+        "actions",
+        ma.executed_actions.values().sum(),
+        mb.executed_actions.values().sum(),
+    );
+    let namespaces: BTreeSet<&String> = ma
+        .executed_actions
+        .keys()
+        .chain(mb.executed_actions.keys())
+        .collect();
+    for namespace in namespaces {
+        count_row(
+            &format!("  {}", namespace),
+            ma.executed_actions.get(namespace).copied().unwrap_or(0),
+            mb.executed_actions.get(namespace).copied().unwrap_or(0),
+        );
+    }
+    count_row("errors", ma.errors.total(), mb.errors.total());
+}
+
+fn render_changes(changes: &[Change]) {
+    let failed = |taken: &Taken| {
+        if taken.failed {
+            format!(" {}", "(failed)".red())
+        } else {
+            String::new()
+        }
+    };
+
+    for change in changes {
+        match change {
+            Change::Both(a, b) => {
+                let failed = match (a.failed, b.failed) {
+                    (false, false) => String::new(),
+                    (true, true) => format!(" {}", "(failed)".red()),
+                    (true, false) => format!(" {}", "(failed in a)".red()),
+                    (false, true) => format!(" {}", "(failed in b)".red()),
+                };
+                println!("  {}{}", a.call, failed)
+            }
+            Change::OnlyA(a) => println!("{}{}", format!("- {}", a.call).red(), failed(a)),
+            Change::OnlyB(b) => println!("{}{}", format!("+ {}", b.call).green(), failed(b)),
+        }
+    }
+
+    let common = changes
+        .iter()
+        .filter(|c| matches!(c, Change::Both(..)))
+        .count();
+    println!(
+        "\n{} actions in common, {} only in a, {} only in b",
+        common,
+        changes
+            .iter()
+            .filter(|c| matches!(c, Change::OnlyA(_)))
+            .count(),
+        changes
+            .iter()
+            .filter(|c| matches!(c, Change::OnlyB(_)))
+            .count()
+    );
+}
+
+pub fn run(session_a: &str, session_b: &str) -> Result<()> {
+    let a = sessions::load(session_a)?;
+    let b = sessions::load(session_b)?;
+
+    println!(
+        "{} {} ({})",
+        "a:".bold(),
+        session_a.green().bold(),
+        a.tasklet
+    );
+    println!(
+        "{} {} ({})",
+        "b:".bold(),
+        session_b.green().bold(),
+        b.tasklet
+    );
+    if a.tasklet != b.tasklet {
+        println!(
+            "\n{} the sessions were recorded with different tasklets",
+            "warning:".yellow().bold()
+        );
+    }
+    if a.prompt.trim() != b.prompt.trim() {
+        println!(
+            "\n{} the sessions have different prompts",
+            "warning:".yellow().bold()
+        );
+    }
+    println!();
+
+    render(&a, &b);
+
+    println!("\n{}", "[ACTIONS]".bold());
+    let (taken_a, taken_b) = (taken(&a), taken(&b));
+    render_changes(&changes(&taken_a, &taken_b));
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sequence(calls: &[&str]) -> Vec<Taken> {
+        calls
+            .iter()
+            .map(|call| Taken {
+                call: call.to_string(),
+                failed: false,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_changes() {
+        let a = sequence(&["shell(id)", "shell(whoami)", "task_complete(done)"]);
+        let b = sequence(&["shell(id)", "shell(ls)", "task_complete(done)", "wait(1)"]);
+
+        let changes: Vec<String> = changes(&a, &b)
+            .iter()
+            .map(|c| match c {
+                Change::Both(a, _) => format!("  {}", a.call),
+                Change::OnlyA(a) => format!("- {}", a.call),
+                Change::OnlyB(b) => format!("+ {}", b.call),
+            })
+            .collect();
+        assert_eq!(
+            changes,
+            vec![
+                "  shell(id)",
+                "- shell(whoami)",
+                "+ shell(ls)",
+                "  task_complete(done)",
+                "+ wait(1)"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_delta() {
+        assert_eq!(delta(12.0, 20.0, |d| d.to_string()), " (+8)");
+        assert_eq!(
+            delta(0.1437, 0.021, |d| format!("${:.4}", d)),
+            " (-$0.1227)"
+        );
+        assert_eq!(delta(3.0, 3.0, |d| d.to_string()), "");
+    }
+}
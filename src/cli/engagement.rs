@@ -0,0 +1,73 @@
+use std::path::PathBuf;
+
+use anyhow::Result;
+
+use crate::{agent::secrets, cli::markdown};
+
+const STYLE: &str = "body { max-width: 900px; margin: 40px auto; padding: 0 20px; font: 15px/1.6 system-ui, sans-serif; color: #222; }
+h1 { border-bottom: 2px solid #222; padding-bottom: 6px; }
+h2 { border-bottom: 1px solid #ddd; padding-bottom: 4px; margin-top: 36px; }
+pre { background: #f5f5f7; padding: 10px 12px; border-radius: 4px; overflow-x: auto; }
+code { font: 13px ui-monospace, monospace; background: #f5f5f7; padding: 1px 4px; border-radius: 3px; }
+pre code { padding: 0; }
+blockquote { border-left: 4px solid #ccc; margin: 0; padding: 4px 14px; color: #555; }
+table { border-collapse: collapse; }
+th, td { border: 1px solid #ddd; padding: 5px 10px; text-align: left; }
+th { background: #f5f5f7; }";
+
+// the title of the report is its first heading
+fn title(markdown: &str) -> String {
+    markdown
+        .lines()
+        .find_map(|line| line.trim().strip_prefix("# "))
+        .unwrap_or("Report")
+        .trim()
+        .to_string()
+}
+
+pub fn to_html(markdown: &str) -> String {
+    format!(
+        "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n<style>\n{}\n</style>\n</head>\n<body>\n{}</body>\n</html>\n",
+        title(markdown)
+            .replace('&', "&amp;")
+            .replace('<', "&lt;")
+            .replace('>', "&gt;"),
+        STYLE,
+        markdown::to_html(markdown)
+    )
+}
+
+// save the report compiled by the model to <path>.md and <path>.html, returns their paths
+pub fn save(path: &str, report: &str) -> Result<(PathBuf, PathBuf)> {
+    let mut base = PathBuf::from(path);
+    if base
+        .extension()
+        .is_some_and(|ext| ext == "html" || ext == "md")
+    {
+        base.set_extension("");
+    }
+    let md_path = base.with_extension("md");
+    let html_path = base.with_extension("html");
+
+    // never export the registered secrets
+    let report = secrets::redact(report);
+    std::fs::write(&md_path, format!("{}\n", report.trim_end()))
+        .map_err(|e| anyhow!("can't write {}: {}", md_path.display(), e))?;
+    std::fs::write(&html_path, to_html(&report))
+        .map_err(|e| anyhow!("can't write {}: {}", html_path.display(), e))?;
+
+    Ok((md_path, html_path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_html() {
+        let html = to_html("# Scan of <10.0.0.1>\n\n## Findings\n\nNone.");
+        assert!(html.contains("<title>Scan of &lt;10.0.0.1&gt;</title>"));
+        assert!(html.contains("<h2>Findings</h2>\n<p>None.</p>\n</body>"));
+        assert!(to_html("no heading").contains("<title>Report</title>"));
+    }
+}
@@ -0,0 +1,489 @@
+use std::{
+    fmt::Display,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use anyhow::Result;
+use colored::Colorize;
+use serde::Serialize;
+
+use crate::{
+    agent::secrets,
+    cli::{
+        self, setup,
+        test::{self, TestSuite},
+    },
+    APP_NAME, APP_VERSION,
+};
+
+// how a model did on the runs of a test
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Score {
+    pub model: String,
+    pub tasklet: String,
+    pub test: String,
+    pub runs: usize,
+    pub passed: usize,
+    // summed over the runs
+    pub steps: usize,
+    pub elapsed_ms: u128,
+    pub cost: f64,
+    // unparsable responses, unknown or failed actions and so on
+    pub errors: usize,
+    // distinct reasons of the failed runs
+    pub failures: Vec<String>,
+}
+
+impl Score {
+    fn add(&mut self, trial: Result<test::Trial>) {
+        self.runs += 1;
+        let failures = match trial {
+            Ok(trial) => {
+                if let Some(summary) = &trial.summary {
+                    self.steps += summary.steps;
+                    self.elapsed_ms += summary.elapsed_ms;
+                    self.cost += summary.cost;
+                    self.errors += summary.errors.total();
+                }
+                trial.failures
+            }
+            Err(err) => vec![err.to_string()],
+        };
+
+        if failures.is_empty() {
+            self.passed += 1;
+        }
+        for failure in failures {
+            if !self.failures.contains(&failure) {
+                self.failures.push(failure);
+            }
+        }
+    }
+
+    fn pass_rate(&self) -> f64 {
+        if self.runs == 0 {
+            0.0
+        } else {
+            self.passed as f64 / self.runs as f64
+        }
+    }
+
+    fn avg_steps(&self) -> f64 {
+        self.steps as f64 / self.runs.max(1) as f64
+    }
+
+    fn avg_secs(&self) -> f64 {
+        self.elapsed_ms as f64 / 1000.0 / self.runs.max(1) as f64
+    }
+}
+
+// the scores of every model on every test
+#[derive(Debug, Serialize)]
+pub struct EvalReport {
+    generated_by: String,
+    runs: usize,
+    models: Vec<String>,
+    elapsed_ms: u128,
+    pub scores: Vec<Score>,
+}
+
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+impl EvalReport {
+    // the scores of each model across all the tests, in the order the models were given
+    pub fn totals(&self) -> Vec<Score> {
+        self.models
+            .iter()
+            .map(|model| {
+                let mut total = Score {
+                    model: model.to_string(),
+                    ..Default::default()
+                };
+                for score in self.scores.iter().filter(|s| &s.model == model) {
+                    total.runs += score.runs;
+                    total.passed += score.passed;
+                    total.steps += score.steps;
+                    total.elapsed_ms += score.elapsed_ms;
+                    total.cost += score.cost;
+                    total.errors += score.errors;
+                }
+                total
+            })
+            .collect()
+    }
+
+    // tasklet and test names in the order they ran
+    fn tests(&self) -> Vec<(&str, &str)> {
+        let mut tests = vec![];
+        for score in &self.scores {
+            let test = (score.tasklet.as_str(), score.test.as_str());
+            if !tests.contains(&test) {
+                tests.push(test);
+            }
+        }
+        tests
+    }
+
+    fn score(&self, model: &str, tasklet: &str, test: &str) -> Option<&Score> {
+        self.scores
+            .iter()
+            .find(|s| s.model == model && s.tasklet == tasklet && s.test == test)
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    pub fn to_csv(&self) -> String {
+        let mut csv =
+            "model,tasklet,test,runs,passed,pass_rate,avg_steps,avg_seconds,cost,errors\n"
+                .to_string();
+        for score in &self.scores {
+            csv += &format!(
+                "{},{},{},{},{},{:.2},{:.1},{:.1},{:.4},{}\n",
+                csv_field(&score.model),
+                csv_field(&score.tasklet),
+                csv_field(&score.test),
+                score.runs,
+                score.passed,
+                score.pass_rate(),
+                score.avg_steps(),
+                score.avg_secs(),
+                score.cost,
+                score.errors
+            );
+        }
+        csv
+    }
+
+    pub fn to_markdown(&self) -> String {
+        let mut md = format!(
+            "# Evaluation\n\n{} models, {} tests, {} runs each in {:?}\n\n",
+            self.models.len(),
+            self.tests().len(),
+            self.runs,
+            Duration::from_millis(self.elapsed_ms as u64)
+        );
+
+        md += "| Model | Passed | Pass Rate | Avg Steps | Avg Duration | Errors | Cost |\n";
+        md += "|-------|--------|-----------|-----------|--------------|--------|------|\n";
+        for total in self.totals() {
+            md += &format!(
+                "| {} | {}/{} | {:.0}% | {:.1} | {:.1}s | {} | ${:.4} |\n",
+                total.model,
+                total.passed,
+                total.runs,
+                total.pass_rate() * 100.0,
+                total.avg_steps(),
+                total.avg_secs(),
+                total.errors,
+                total.cost
+            );
+        }
+
+        md += &format!("\n| Test | {} |\n", self.models.join(" | "));
+        md += &format!("|------|{}\n", "------|".repeat(self.models.len()));
+        for (tasklet, test) in self.tests() {
+            let cells = self
+                .models
+                .iter()
+                .map(|model| match self.score(model, tasklet, test) {
+                    Some(score) => format!("{}/{}", score.passed, score.runs),
+                    None => String::new(),
+                })
+                .collect::<Vec<_>>();
+            md += &format!(
+                "| {}: {} | {} |\n",
+                tasklet,
+                test.replace('|', "\\|"),
+                cells.join(" | ")
+            );
+        }
+
+        let failed = self
+            .scores
+            .iter()
+            .filter(|s| !s.failures.is_empty())
+            .collect::<Vec<_>>();
+        if !failed.is_empty() {
+            md += "\n## Failures\n\n";
+            for score in failed {
+                md += &format!("* **{}** {}: {}\n", score.model, score.tasklet, score.test);
+                for failure in &score.failures {
+                    md += &format!("  * {}\n", failure.replace('\n', " "));
+                }
+            }
+        }
+
+        md += &format!("\n_Generated by {}_\n", self.generated_by);
+        md
+    }
+
+    pub fn save(&self, path: &str) -> Result<(PathBuf, PathBuf)> {
+        let mut base = PathBuf::from(path);
+        if base
+            .extension()
+            .is_some_and(|ext| ext == "json" || ext == "md")
+        {
+            base.set_extension("");
+        }
+        let json_path = base.with_extension("json");
+        let md_path = base.with_extension("md");
+
+        // never export the registered secrets
+        std::fs::write(&json_path, secrets::redact_json(&self.to_json()?))
+            .map_err(|e| anyhow!("can't write {}: {}", json_path.display(), e))?;
+        std::fs::write(&md_path, secrets::redact(&self.to_markdown()))
+            .map_err(|e| anyhow!("can't write {}: {}", md_path.display(), e))?;
+
+        Ok((json_path, md_path))
+    }
+}
+
+impl Display for EvalReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (tasklet, test) in self.tests() {
+            writeln!(f, "{}: {}", tasklet, test)?;
+            for model in &self.models {
+                if let Some(score) = self.score(model, tasklet, test) {
+                    let passed = format!("{}/{}", score.passed, score.runs);
+                    let passed = if score.passed == score.runs {
+                        passed.green()
+                    } else if score.passed > 0 {
+                        passed.yellow()
+                    } else {
+                        passed.red()
+                    };
+                    writeln!(
+                        f,
+                        "  {} {} ({:.1} steps, {:.1}s)",
+                        passed,
+                        model,
+                        score.avg_steps(),
+                        score.avg_secs()
+                    )?;
+                }
+            }
+        }
+
+        let totals = self.totals();
+        for (idx, total) in totals.iter().enumerate() {
+            write!(
+                f,
+                "{} {:.0}% of {} runs passed, {} errors",
+                total.model.bold(),
+                total.pass_rate() * 100.0,
+                total.runs,
+                total.errors
+            )?;
+            if total.cost > 0.0 {
+                write!(f, ", ${:.4}", total.cost)?;
+            }
+            if idx < totals.len() - 1 {
+                writeln!(f)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// name shown for the tasklet of a suite, its folder or file name
+fn tasklet_name(suite: &TestSuite) -> String {
+    let path = suite.tasklet_path();
+    Path::new(&path)
+        .components()
+        .collect::<PathBuf>()
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or(path)
+}
+
+// run the tests of the suites with every model and compare how they score
+pub async fn run(
+    args: &cli::Args,
+    paths: &[String],
+    models: &[String],
+    runs: usize,
+    csv: Option<&str>,
+) -> Result<()> {
+    let models = if models.is_empty() {
+        vec![args.generator.to_string()]
+    } else {
+        models.to_vec()
+    };
+    let runs = runs.max(1);
+
+    let suites = paths
+        .iter()
+        .map(|path| TestSuite::from_path(path))
+        .collect::<Result<Vec<_>>>()?;
+
+    // fail early instead of on every run
+    for model in &models {
+        let mut args = args.clone();
+        args.generator = model.to_string();
+        setup::check_credentials(&args)?;
+    }
+
+    log::info!(
+        "evaluating {} models on {} tests, {} runs each",
+        models.len(),
+        suites.iter().map(|s| s.tests.len()).sum::<usize>(),
+        runs
+    );
+
+    let started = Instant::now();
+    let mut scores = vec![];
+    for suite in &suites {
+        let tasklet = tasklet_name(suite);
+        for test in &suite.tests {
+            // the model being evaluated answers and acts, not a recorded transcript or cassette
+            let mut test = test.clone();
+            test.transcript = None;
+            test.cassette = None;
+
+            for model in &models {
+                let mut args = args.clone();
+                args.generator = model.to_string();
+
+                let mut score = Score {
+                    model: model.to_string(),
+                    tasklet: tasklet.to_string(),
+                    test: test.name.to_string(),
+                    ..Default::default()
+                };
+                for run in 1..=runs {
+                    log::info!(
+                        "{} {} with {} ({}/{}) ...",
+                        "eval".bold(),
+                        test.name,
+                        model,
+                        run,
+                        runs
+                    );
+                    score.add(test::run_test(&args, suite, &test).await);
+                }
+                scores.push(score);
+            }
+        }
+    }
+
+    let report = EvalReport {
+        generated_by: format!("{} v{}", APP_NAME, APP_VERSION),
+        runs,
+        models,
+        elapsed_ms: started.elapsed().as_millis(),
+        scores,
+    };
+
+    for line in report.to_string().lines() {
+        log::info!("{}", line);
+    }
+
+    if let Some(path) = &args.report {
+        match report.save(path) {
+            Ok((json, md)) => log::info!("report saved to {} and {}", json.display(), md.display()),
+            Err(err) => log::error!("could not save the report: {}", err),
+        }
+    }
+
+    if let Some(path) = csv {
+        std::fs::write(path, secrets::redact(&report.to_csv()))
+            .map_err(|e| anyhow!("can't write {}: {}", path, e))?;
+        log::info!("results saved to {}", path);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn report() -> EvalReport {
+        let score = |model: &str, test: &str, passed: usize| Score {
+            model: model.to_string(),
+            tasklet: "recon".to_string(),
+            test: test.to_string(),
+            runs: 2,
+            passed,
+            steps: 6,
+            elapsed_ms: 3000,
+            cost: 0.0,
+            errors: 2 - passed,
+            failures: if passed < 2 {
+                vec!["output does not contain 'root'".to_string()]
+            } else {
+                vec![]
+            },
+        };
+
+        EvalReport {
+            generated_by: "nerve".to_string(),
+            runs: 2,
+            models: vec![
+                "ollama://llama3".to_string(),
+                "groq://llama, 70b".to_string(),
+            ],
+            elapsed_ms: 12000,
+            scores: vec![
+                score("ollama://llama3", "finds the user", 2),
+                score("groq://llama, 70b", "finds the user", 1),
+                score("ollama://llama3", "lists ports", 0),
+                score("groq://llama, 70b", "lists ports", 2),
+            ],
+        }
+    }
+
+    #[test]
+    fn test_score() {
+        let mut score = Score::default();
+        score.add(Ok(test::Trial {
+            failures: vec![],
+            summary: None,
+        }));
+        score.add(Ok(test::Trial {
+            failures: vec!["run failed: no more steps".to_string()],
+            summary: None,
+        }));
+        score.add(Err(anyhow!("run failed: no more steps")));
+        assert_eq!(score.runs, 3);
+        assert_eq!(score.passed, 1);
+        assert_eq!(score.failures, vec!["run failed: no more steps"]);
+    }
+
+    #[test]
+    fn test_totals() {
+        let totals = report().totals();
+        assert_eq!(totals[0].model, "ollama://llama3");
+        assert_eq!((totals[0].passed, totals[0].runs), (2, 4));
+        assert_eq!(totals[1].errors, 1);
+        assert_eq!(totals[1].pass_rate(), 0.75);
+    }
+
+    #[test]
+    fn test_csv() {
+        let csv = report().to_csv();
+        let lines = csv.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 5);
+        assert_eq!(
+            lines[2],
+            "\"groq://llama, 70b\",recon,finds the user,2,1,0.50,3.0,1.5,0.0000,1"
+        );
+    }
+
+    #[test]
+    fn test_markdown() {
+        let md = report().to_markdown();
+        assert!(md.contains("| ollama://llama3 | 2/4 | 50% | 3.0 | 1.5s | 2 | $0.0000 |"));
+        assert!(md.contains("| recon: lists ports | 0/2 | 2/2 |"));
+        assert!(md.contains("* **groq://llama, 70b** recon: finds the user"));
+    }
+}
@@ -0,0 +1,236 @@
+use anyhow::Result;
+use serde::Serialize;
+
+use crate::{
+    agent::{
+        generator::{self, history::ConversationWindow, ChatOptions, Client},
+        state::{session::Session, SharedState},
+    },
+    cli::{self, replay},
+};
+
+// last invocations of the run shown to the judge
+const MAX_INVOCATIONS: usize = 20;
+// characters of each action output shown to the judge
+const MAX_RESULT_SIZE: usize = 500;
+
+const SYSTEM_PROMPT: &str = "You are a strict reviewer of the work of an AI agent. Given the task it was assigned, the actions it executed and its final output, decide if the output actually accomplishes the task and is supported by what the agent did. Answer with PASS or FAIL on the first line, followed by a short critique.";
+
+// the assessment of the final output of a run by the judge model
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct Verdict {
+    pub judge: String,
+    pub pass: bool,
+    pub critique: String,
+}
+
+impl Verdict {
+    // PASS or FAIL on the first line, anything else is a failure
+    fn parse(judge: &str, response: &str) -> Self {
+        let response = response.trim();
+        let (first, rest) = response.split_once('\n').unwrap_or((response, ""));
+        // markdown and punctuation around the verdict, like **PASS**: ...
+        let first = first.trim_start_matches(|c: char| !c.is_alphanumeric());
+        let verdict = first.to_uppercase();
+        let critique = |first: &str| {
+            format!("{} {}", first, rest)
+                .trim_start_matches(|c: char| !c.is_alphanumeric())
+                .trim()
+                .to_string()
+        };
+
+        let (pass, critique) = if verdict.starts_with("PASS") {
+            (true, critique(first.get(4..).unwrap_or_default()))
+        } else if verdict.starts_with("FAIL") {
+            (false, critique(first.get(4..).unwrap_or_default()))
+        } else {
+            log::warn!("the judge did not answer with PASS or FAIL");
+            (false, response.to_string())
+        };
+
+        Self {
+            judge: judge.to_string(),
+            pass,
+            critique,
+        }
+    }
+}
+
+// the model set with --judge
+pub fn client(args: &cli::Args) -> Result<Box<dyn Client>> {
+    let options = generator::Options::parse(&args.judge, args.context_window)?;
+    generator::factory(
+        &options.type_name,
+        &options.host,
+        options.port,
+        &options.model_name,
+        options.context_window,
+    )
+}
+
+fn truncated(text: &str) -> String {
+    let text = text.trim();
+    match text.char_indices().nth(MAX_RESULT_SIZE) {
+        Some((idx, _)) => format!("{} ...", &text[..idx]),
+        None => text.to_string(),
+    }
+}
+
+// the task, what the agent did and what it concluded
+fn to_prompt(session: &Session) -> String {
+    let mut prompt = format!("TASK:\n{}\n", session.prompt.trim());
+
+    let executions = replay::steps(session)
+        .into_iter()
+        .flat_map(|(step, execs)| execs.into_iter().map(move |exec| (step, exec)))
+        .filter(|(_, exec)| !exec.is_summary())
+        .filter_map(|(step, exec)| exec.invocation().map(|inv| (step, inv, exec)))
+        .collect::<Vec<_>>();
+    if !executions.is_empty() {
+        prompt += "\nACTIONS:\n";
+        if executions.len() > MAX_INVOCATIONS {
+            prompt += &format!(
+                "({} earlier actions omitted)\n",
+                executions.len() - MAX_INVOCATIONS
+            );
+        }
+        for (step, invocation, exec) in executions
+            .iter()
+            .skip(executions.len().saturating_sub(MAX_INVOCATIONS))
+        {
+            let mut parts = vec![];
+            if let Some(payload) = &invocation.payload {
+                parts.push(truncated(payload));
+            }
+            for (name, value) in invocation.attributes.iter().flatten() {
+                parts.push(format!("{}={}", name, value));
+            }
+            prompt += &format!(
+                "- step {}: {}({})",
+                step,
+                invocation.action,
+                parts.join(", ")
+            );
+            if let Some(error) = exec.error() {
+                prompt += &format!(" -> ERROR: {}\n", truncated(error));
+            } else if let Some(result) = exec.result() {
+                prompt += &format!(" -> {}\n", truncated(result));
+            } else {
+                prompt += "\n";
+            }
+        }
+    }
+
+    for storage in session.storages.iter().filter(|s| !s.entries.is_empty()) {
+        prompt += &format!("\n{}:\n", storage.name.to_uppercase());
+        for entry in &storage.entries {
+            prompt += &format!("- {}: {}\n", entry.key, truncated(&entry.data));
+        }
+    }
+
+    prompt += &format!(
+        "\nFINAL OUTPUT:\n{}\n",
+        session
+            .completion_reason
+            .as_deref()
+            .unwrap_or("(none)")
+            .trim()
+    );
+    prompt
+}
+
+// have the judge model assess the final output of the run
+pub async fn judge(args: &cli::Args, state: SharedState, session: &Session) -> Result<Verdict> {
+    let chat = ChatOptions::new(
+        Some(SYSTEM_PROMPT.to_string()),
+        to_prompt(session),
+        vec![],
+        vec![],
+        ConversationWindow::Full,
+    );
+
+    let response = client(args)?.chat(state, &chat).await?;
+    Ok(Verdict::parse(&args.judge, &response.content))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent::{
+        state::{
+            history::{Execution, History},
+            metrics::Metrics,
+        },
+        Invocation,
+    };
+
+    #[test]
+    fn test_prompt() {
+        let mut history = History::new();
+        history.push(
+            Execution::with_result(
+                Invocation::new("shell".to_string(), None, Some("id".to_string())),
+                Some("uid=0(root)".to_string()),
+            )
+            .with_step(1),
+        );
+        history.push(
+            Execution::with_error(
+                Invocation::new("shell".to_string(), None, Some("nope".to_string())),
+                "command not found".to_string(),
+            )
+            .with_step(2),
+        );
+        let session: Session = serde_json::from_value(serde_json::json!({
+            "version": 1,
+            "created_at": chrono::Local::now(),
+            "updated_at": chrono::Local::now(),
+            "tasklet": "/tmp/tasklet",
+            "generator": "mock:///tmp/x.yml",
+            "prompt": "find the user",
+            "complete": true,
+            "impossible": false,
+            "completion_reason": "root",
+            "metrics": Metrics::default(),
+            "history": history,
+            "storages": [{
+                "name": "memories",
+                "type": "Tagged",
+                "entries": [{"key": "user", "data": "root", "complete": false}]
+            }],
+            "variables": {},
+            "rag_data_path": null,
+        }))
+        .unwrap();
+
+        assert_eq!(
+            to_prompt(&session),
+            "TASK:\nfind the user\n\nACTIONS:\n- step 1: shell(id) -> uid=0(root)\n- step 2: shell(nope) -> ERROR: command not found\n\nMEMORIES:\n- user: root\n\nFINAL OUTPUT:\nroot\n"
+        );
+    }
+
+    #[test]
+    fn test_parse() {
+        let verdict = Verdict::parse("mock://", "PASS\nthe ports were all scanned.");
+        assert!(verdict.pass);
+        assert_eq!(verdict.critique, "the ports were all scanned.");
+
+        let verdict = Verdict::parse("mock://", "**FAIL**: no evidence of the claim");
+        assert!(!verdict.pass);
+        assert_eq!(verdict.critique, "no evidence of the claim");
+
+        let verdict = Verdict::parse("mock://", "looks good to me");
+        assert!(!verdict.pass);
+        assert_eq!(verdict.critique, "looks good to me");
+    }
+
+    #[test]
+    fn test_truncated() {
+        assert_eq!(truncated(" short "), "short");
+        let long = "é".repeat(MAX_RESULT_SIZE + 10);
+        assert_eq!(
+            truncated(&long),
+            format!("{} ...", "é".repeat(MAX_RESULT_SIZE))
+        );
+    }
+}
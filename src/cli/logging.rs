@@ -0,0 +1,606 @@
+use std::{
+    collections::HashMap,
+    fs::{File, OpenOptions},
+    io::Write,
+    net::UdpSocket,
+    path::PathBuf,
+    str::FromStr,
+    sync::Mutex,
+};
+
+use anyhow::Result;
+use chrono::{DateTime, Local};
+use log::{Level, LevelFilter, Log, Metadata, Record};
+
+use crate::{agent::secrets, APP_NAME};
+
+// target of the run transcript printed by the text ui, apart from the internal logs
+pub const TRANSCRIPT: &str = "transcript";
+
+// the dependencies are quite verbose at info and debug
+const DEPENDENCIES: &str = "openai_api_rust=warn,rustls=warn,ureq=warn";
+
+const DEFAULT_MAX_SIZE: u64 = 10 * 1024 * 1024;
+const DEFAULT_KEEP: usize = 5;
+const DEFAULT_SYSLOG_PORT: u16 = 514;
+const DEFAULT_SYSLOG_SOCKET: &str = "/dev/log";
+const JOURNALD_SOCKET: &str = "/run/systemd/journal/socket";
+
+// where the log lines go besides the console
+#[derive(Debug, Clone, PartialEq)]
+enum Destination {
+    // file:<path>, rotated once it exceeds max_size bytes keeping the last `keep` ones
+    File {
+        path: PathBuf,
+        max_size: u64,
+        keep: usize,
+    },
+    // syslog, syslog:/path/to/socket or syslog:<host>[:port] over udp
+    SyslogSocket(PathBuf),
+    SyslogUdp(String),
+    // the native protocol of systemd-journald
+    Journald,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct SinkConfig {
+    destination: Destination,
+    // only send lines up to this level
+    level: LevelFilter,
+    // syslog facility code
+    facility: u8,
+}
+
+// 10M, 512K, 1G or a number of bytes
+fn parse_size(size: &str) -> Result<u64> {
+    let size = size.trim();
+    let (number, multiplier) = match size.chars().last() {
+        Some('K' | 'k') => (&size[..size.len() - 1], 1024),
+        Some('M' | 'm') => (&size[..size.len() - 1], 1024 * 1024),
+        Some('G' | 'g') => (&size[..size.len() - 1], 1024 * 1024 * 1024),
+        _ => (size, 1),
+    };
+    number
+        .parse::<u64>()
+        .map(|n| n * multiplier)
+        .map_err(|_| anyhow!("invalid size '{}'", size))
+}
+
+fn parse_facility(name: &str) -> Result<u8> {
+    let facility = match name {
+        "kern" => 0,
+        "user" => 1,
+        "mail" => 2,
+        "daemon" => 3,
+        "auth" => 4,
+        "syslog" => 5,
+        "authpriv" => 10,
+        _ => match name
+            .strip_prefix("local")
+            .and_then(|n| n.parse::<u8>().ok())
+        {
+            Some(n) if n <= 7 => 16 + n,
+            _ => return Err(anyhow!("unknown syslog facility '{}'", name)),
+        },
+    };
+    Ok(facility)
+}
+
+impl FromStr for SinkConfig {
+    type Err = anyhow::Error;
+
+    // <kind>[:<target>][?option=value&...]
+    fn from_str(spec: &str) -> Result<Self> {
+        let (base, query) = spec.split_once('?').unwrap_or((spec, ""));
+        let mut options: HashMap<&str, &str> = HashMap::new();
+        for pair in query.split('&').filter(|p| !p.is_empty()) {
+            let (key, value) = pair
+                .split_once('=')
+                .ok_or_else(|| anyhow!("invalid option '{}' in log sink {}", pair, spec))?;
+            options.insert(key, value);
+        }
+
+        let (kind, target) = base.split_once(':').unwrap_or((base, ""));
+        let destination = match (kind, target) {
+            ("file", "") => return Err(anyhow!("log sink {} requires a path", spec)),
+            ("file", path) => Destination::File {
+                path: PathBuf::from(path),
+                max_size: match options.remove("max_size") {
+                    Some(size) => parse_size(size)?,
+                    None => DEFAULT_MAX_SIZE,
+                },
+                keep: match options.remove("keep") {
+                    Some(keep) => keep
+                        .parse()
+                        .map_err(|_| anyhow!("invalid keep value '{}'", keep))?,
+                    None => DEFAULT_KEEP,
+                },
+            },
+            ("syslog", "") => Destination::SyslogSocket(PathBuf::from(DEFAULT_SYSLOG_SOCKET)),
+            ("syslog", path) if path.starts_with('/') => {
+                Destination::SyslogSocket(PathBuf::from(path))
+            }
+            ("syslog", host) if host.contains(':') => Destination::SyslogUdp(host.to_string()),
+            ("syslog", host) => {
+                Destination::SyslogUdp(format!("{}:{}", host, DEFAULT_SYSLOG_PORT))
+            }
+            ("journald", "") => Destination::Journald,
+            _ => {
+                return Err(anyhow!(
+                    "unknown log sink {}, use file:<path>, syslog[:<socket>|:<host>[:port]] or journald",
+                    spec
+                ))
+            }
+        };
+
+        let level = match options.remove("level") {
+            Some(level) => LevelFilter::from_str(level)
+                .map_err(|_| anyhow!("invalid log level '{}'", level))?,
+            None => LevelFilter::Trace,
+        };
+        let facility = match options.remove("facility") {
+            Some(name) => parse_facility(name)?,
+            None => 1,
+        };
+
+        if let Some(key) = options.keys().next() {
+            return Err(anyhow!("unknown option '{}' in log sink {}", key, spec));
+        }
+
+        Ok(Self {
+            destination,
+            level,
+            facility,
+        })
+    }
+}
+
+// ansi colors are only meant for the console
+fn strip_ansi(text: &str) -> String {
+    let mut stripped = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            for c in chars.by_ref() {
+                if c.is_ascii_alphabetic() {
+                    break;
+                }
+            }
+        } else {
+            stripped.push(c);
+        }
+    }
+    stripped
+}
+
+fn severity(level: Level) -> u8 {
+    match level {
+        Level::Error => 3,
+        Level::Warn => 4,
+        Level::Info => 6,
+        Level::Debug | Level::Trace => 7,
+    }
+}
+
+#[cfg(unix)]
+pub fn hostname() -> String {
+    let mut buf = [0u8; 256];
+    if unsafe { libc::gethostname(buf.as_mut_ptr() as *mut libc::c_char, buf.len()) } == 0 {
+        let len = buf.iter().position(|b| *b == 0).unwrap_or(buf.len());
+        if len > 0 {
+            return String::from_utf8_lossy(&buf[..len]).to_string();
+        }
+    }
+    "-".to_string()
+}
+
+#[cfg(not(unix))]
+pub fn hostname() -> String {
+    std::env::var("COMPUTERNAME").unwrap_or("-".to_string())
+}
+
+// the local daemon adds the timestamp and the hostname itself
+fn syslog_local_message(facility: u8, level: Level, message: &str) -> String {
+    format!(
+        "<{}>{}[{}]: {}",
+        facility * 8 + severity(level),
+        APP_NAME,
+        std::process::id(),
+        message
+    )
+}
+
+// RFC 5424 for remote servers
+fn syslog_remote_message(
+    facility: u8,
+    level: Level,
+    time: &DateTime<Local>,
+    hostname: &str,
+    message: &str,
+) -> String {
+    format!(
+        "<{}>1 {} {} {} {} - - {}",
+        facility * 8 + severity(level),
+        time.to_rfc3339_opts(chrono::SecondsFormat::Millis, false),
+        hostname,
+        APP_NAME,
+        std::process::id(),
+        message
+    )
+}
+
+// fields of the journald native protocol, values with new lines are length prefixed
+fn journald_message(level: Level, target: &str, message: &str) -> Vec<u8> {
+    let mut raw = vec![];
+    for (name, value) in [
+        ("MESSAGE", message),
+        ("PRIORITY", &severity(level).to_string()),
+        ("SYSLOG_IDENTIFIER", APP_NAME),
+        ("TARGET", target),
+    ] {
+        raw.extend(name.as_bytes());
+        if value.contains('\n') {
+            raw.push(b'\n');
+            raw.extend((value.len() as u64).to_le_bytes());
+        } else {
+            raw.push(b'=');
+        }
+        raw.extend(value.as_bytes());
+        raw.push(b'\n');
+    }
+    raw
+}
+
+struct RotatingFile {
+    path: PathBuf,
+    max_size: u64,
+    keep: usize,
+    file: File,
+    size: u64,
+}
+
+impl RotatingFile {
+    fn open(path: PathBuf, max_size: u64, keep: usize) -> Result<Self> {
+        if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            std::fs::create_dir_all(parent)?;
+        }
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .map_err(|e| anyhow!("can't open {}: {}", path.display(), e))?;
+        let size = file.metadata()?.len();
+
+        Ok(Self {
+            path,
+            max_size,
+            keep,
+            file,
+            size,
+        })
+    }
+
+    fn rotated(&self, index: usize) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(format!(".{}", index));
+        PathBuf::from(path)
+    }
+
+    // nerve.log -> nerve.log.1 -> nerve.log.2 ... and the oldest one is removed
+    fn rotate(&mut self) -> std::io::Result<()> {
+        if self.keep == 0 {
+            std::fs::remove_file(&self.path)?;
+        } else {
+            let _ = std::fs::remove_file(self.rotated(self.keep));
+            for index in (1..self.keep).rev() {
+                let _ = std::fs::rename(self.rotated(index), self.rotated(index + 1));
+            }
+            std::fs::rename(&self.path, self.rotated(1))?;
+        }
+
+        self.file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        self.size = 0;
+        Ok(())
+    }
+
+    fn write(&mut self, line: &str) -> std::io::Result<()> {
+        if self.max_size > 0 && self.size > 0 && self.size + line.len() as u64 > self.max_size {
+            self.rotate()?;
+        }
+        self.file.write_all(line.as_bytes())?;
+        self.size += line.len() as u64;
+        Ok(())
+    }
+}
+
+enum Output {
+    File(RotatingFile),
+    #[cfg(unix)]
+    SyslogSocket(std::os::unix::net::UnixDatagram),
+    SyslogUdp(UdpSocket, String),
+    #[cfg(unix)]
+    Journald(std::os::unix::net::UnixDatagram),
+}
+
+pub struct Sink {
+    level: LevelFilter,
+    facility: u8,
+    output: Output,
+}
+
+impl Sink {
+    pub fn open(spec: &str) -> Result<Self> {
+        let config = SinkConfig::from_str(spec)?;
+        let output = match config.destination {
+            Destination::File {
+                path,
+                max_size,
+                keep,
+            } => Output::File(RotatingFile::open(path, max_size, keep)?),
+            #[cfg(unix)]
+            Destination::SyslogSocket(path) => {
+                let socket = std::os::unix::net::UnixDatagram::unbound()?;
+                socket
+                    .connect(&path)
+                    .map_err(|e| anyhow!("can't connect to {}: {}", path.display(), e))?;
+                Output::SyslogSocket(socket)
+            }
+            Destination::SyslogUdp(address) => {
+                let socket = UdpSocket::bind("0.0.0.0:0")?;
+                socket
+                    .connect(&address)
+                    .map_err(|e| anyhow!("can't connect to {}: {}", address, e))?;
+                Output::SyslogUdp(socket, hostname())
+            }
+            #[cfg(unix)]
+            Destination::Journald => {
+                let socket = std::os::unix::net::UnixDatagram::unbound()?;
+                socket
+                    .connect(JOURNALD_SOCKET)
+                    .map_err(|e| anyhow!("can't connect to {}: {}", JOURNALD_SOCKET, e))?;
+                Output::Journald(socket)
+            }
+            #[cfg(not(unix))]
+            _ => return Err(anyhow!("log sink {} is only supported on unix", spec)),
+        };
+
+        Ok(Self {
+            level: config.level,
+            facility: config.facility,
+            output,
+        })
+    }
+
+    // a rotated file receiving every line
+    pub fn file(path: &str) -> Result<Self> {
+        Ok(Self {
+            level: LevelFilter::Trace,
+            facility: 1,
+            output: Output::File(RotatingFile::open(
+                PathBuf::from(path),
+                DEFAULT_MAX_SIZE,
+                DEFAULT_KEEP,
+            )?),
+        })
+    }
+
+    fn write(&mut self, level: Level, target: &str, message: &str) -> std::io::Result<()> {
+        if level > self.level {
+            return Ok(());
+        }
+
+        match &mut self.output {
+            Output::File(file) => file.write(&format!(
+                "{} {:<5} {}: {}\n",
+                Local::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, false),
+                level,
+                target,
+                message
+            )),
+            #[cfg(unix)]
+            Output::SyslogSocket(socket) => socket
+                .send(syslog_local_message(self.facility, level, message).as_bytes())
+                .map(|_| ()),
+            Output::SyslogUdp(socket, hostname) => socket
+                .send(
+                    syslog_remote_message(self.facility, level, &Local::now(), hostname, message)
+                        .as_bytes(),
+                )
+                .map(|_| ()),
+            #[cfg(unix)]
+            Output::Journald(socket) => socket
+                .send(&journald_message(level, target, message))
+                .map(|_| ()),
+        }
+    }
+}
+
+// log filters for -q, the default verbosity, -v and -vv
+pub fn verbosity_filters(quiet: bool, verbose: u8) -> String {
+    if quiet {
+        return format!("error,{}=off", TRANSCRIPT);
+    }
+    match verbose {
+        0 => format!("info,{}", DEPENDENCIES),
+        // nerve also matches nerve_core
+        1 => format!("info,nerve=debug,{}", DEPENDENCIES),
+        _ => "debug,nerve=trace".to_string(),
+    }
+}
+
+// sends every record to the console logger and to the configured sinks
+struct Logger {
+    console: env_logger::Logger,
+    sinks: Vec<Mutex<Sink>>,
+    // the sinks are not silenced with the console
+    sinks_filter: env_filter::Filter,
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.console.enabled(metadata)
+            || (!self.sinks.is_empty() && self.sinks_filter.enabled(metadata))
+    }
+
+    fn log(&self, record: &Record) {
+        if self.console.matches(record) {
+            self.console.log(record);
+        }
+
+        if !self.sinks.is_empty() && self.sinks_filter.matches(record) {
+            // mask any registered secret
+            let message = strip_ansi(&secrets::redact(&record.args().to_string()));
+            for sink in &self.sinks {
+                // a sink that can't be written to must not stop the run
+                let _ = sink
+                    .lock()
+                    .unwrap()
+                    .write(record.level(), record.target(), &message);
+            }
+        }
+    }
+
+    fn flush(&self) {
+        self.console.flush();
+    }
+}
+
+pub fn init(
+    console: env_logger::Logger,
+    sinks: Vec<Sink>,
+    sinks_filter: env_filter::Filter,
+) -> Result<()> {
+    let max_level = if sinks.is_empty() {
+        console.filter()
+    } else {
+        console.filter().max(sinks_filter.filter())
+    };
+    log::set_max_level(max_level);
+    log::set_boxed_logger(Box::new(Logger {
+        console,
+        sinks: sinks.into_iter().map(Mutex::new).collect(),
+        sinks_filter,
+    }))
+    .map_err(|e| anyhow!("can't set the logger: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            SinkConfig::from_str("file:/var/log/nerve.log?max_size=1M&keep=3&level=warn").unwrap(),
+            SinkConfig {
+                destination: Destination::File {
+                    path: PathBuf::from("/var/log/nerve.log"),
+                    max_size: 1024 * 1024,
+                    keep: 3,
+                },
+                level: LevelFilter::Warn,
+                facility: 1,
+            }
+        );
+        assert_eq!(
+            SinkConfig::from_str("syslog").unwrap().destination,
+            Destination::SyslogSocket(PathBuf::from("/dev/log"))
+        );
+        assert_eq!(
+            SinkConfig::from_str("syslog:logs.local?facility=local3")
+                .unwrap()
+                .destination,
+            Destination::SyslogUdp("logs.local:514".to_string())
+        );
+        assert_eq!(
+            SinkConfig::from_str("syslog:logs.local?facility=local3")
+                .unwrap()
+                .facility,
+            19
+        );
+        assert_eq!(
+            SinkConfig::from_str("journald").unwrap().destination,
+            Destination::Journald
+        );
+        assert!(SinkConfig::from_str("file:").is_err());
+        assert!(SinkConfig::from_str("file:x.log?size=1").is_err());
+        assert!(SinkConfig::from_str("syslog?facility=nope").is_err());
+        assert!(SinkConfig::from_str("kafka:x").is_err());
+    }
+
+    #[test]
+    fn test_verbosity() {
+        let filter = |spec: &str| env_filter::Builder::new().parse(spec).build();
+        let enabled = |filter: &env_filter::Filter, target: &str, level: Level| {
+            filter.enabled(&Metadata::builder().target(target).level(level).build())
+        };
+
+        let quiet = filter(&verbosity_filters(true, 0));
+        assert!(!enabled(&quiet, TRANSCRIPT, Level::Error));
+        assert!(!enabled(&quiet, "nerve_core::agent", Level::Warn));
+        assert!(enabled(&quiet, "nerve_core::agent", Level::Error));
+
+        let default = filter(&verbosity_filters(false, 0));
+        assert!(enabled(&default, TRANSCRIPT, Level::Info));
+        assert!(!enabled(&default, "nerve_core::agent", Level::Debug));
+        assert!(!enabled(&default, "rustls::client", Level::Info));
+
+        let verbose = filter(&verbosity_filters(false, 1));
+        assert!(enabled(&verbose, "nerve_core::agent", Level::Debug));
+        assert!(enabled(&verbose, "nerve::cli", Level::Debug));
+        assert!(!enabled(&verbose, "reqwest::connect", Level::Debug));
+
+        let very_verbose = filter(&verbosity_filters(false, 2));
+        assert!(enabled(&very_verbose, "nerve_core::agent", Level::Trace));
+        assert!(enabled(&very_verbose, "reqwest::connect", Level::Debug));
+    }
+
+    #[test]
+    fn test_messages() {
+        assert_eq!(
+            strip_ansi("\x1b[1mshell\x1b[0m(\x1b[2mid\x1b[0m)"),
+            "shell(id)"
+        );
+        assert!(syslog_local_message(3, Level::Warn, "hi").starts_with("<28>nerve["));
+
+        let time = DateTime::parse_from_rfc3339("2026-10-14T15:13:25.123+00:00")
+            .unwrap()
+            .with_timezone(&Local);
+        let remote = syslog_remote_message(1, Level::Error, &time, "box", "boom");
+        assert!(remote.starts_with("<11>1 "));
+        assert!(remote.ends_with(&format!(" box nerve {} - - boom", std::process::id())));
+
+        let raw = journald_message(Level::Info, "nerve", "two\nlines");
+        let mut expected = b"MESSAGE\n".to_vec();
+        expected.extend(9u64.to_le_bytes());
+        expected.extend(b"two\nlines\nPRIORITY=6\nSYSLOG_IDENTIFIER=nerve\nTARGET=nerve\n");
+        assert_eq!(raw, expected);
+    }
+
+    #[test]
+    fn test_rotation() {
+        let dir = std::env::temp_dir().join(format!("nerve-test-logs-{}", std::process::id()));
+        let path = dir.join("nerve.log");
+
+        let mut file = RotatingFile::open(path.clone(), 10, 2).unwrap();
+        for line in ["first\n", "second\n", "third\n", "fourth\n"] {
+            file.write(line).unwrap();
+        }
+
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "fourth\n");
+        assert_eq!(
+            std::fs::read_to_string(dir.join("nerve.log.1")).unwrap(),
+            "third\n"
+        );
+        assert_eq!(
+            std::fs::read_to_string(dir.join("nerve.log.2")).unwrap(),
+            "second\n"
+        );
+        assert!(!dir.join("nerve.log.3").exists());
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
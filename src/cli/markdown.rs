@@ -0,0 +1,216 @@
+// minimal markdown to html conversion for the reports written by the models: headings,
+// paragraphs, lists, code blocks, quotes, tables and the common inline styles
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+// wrap the text between pairs of the delimiter in the given tag
+fn wrap(text: &str, delimiter: &str, tag: &str) -> String {
+    let parts: Vec<&str> = text.split(delimiter).collect();
+    // an unpaired delimiter is left as it is
+    let paired = parts.len() - 1 - (parts.len() - 1) % 2;
+
+    let mut html = parts[0].to_string();
+    for (idx, part) in parts.iter().enumerate().skip(1) {
+        if idx <= paired {
+            html += &if idx % 2 == 1 {
+                format!("<{}>", tag)
+            } else {
+                format!("</{}>", tag)
+            };
+        } else {
+            html += delimiter;
+        }
+        html += part;
+    }
+    html
+}
+
+// [text](url) links, only to web pages and anchors
+fn links(text: &str) -> String {
+    let mut html = String::new();
+    let mut rest = text;
+
+    while let Some(start) = rest.find('[') {
+        let Some((label, after)) = rest[start + 1..].split_once("](") else {
+            break;
+        };
+        let Some((url, after)) = after.split_once(')') else {
+            break;
+        };
+        html += &rest[..start];
+        if url.starts_with("http://") || url.starts_with("https://") || url.starts_with('#') {
+            html += &format!("<a href=\"{}\">{}</a>", url, label);
+        } else {
+            html += &format!("{} ({})", label, url);
+        }
+        rest = after;
+    }
+
+    html + rest
+}
+
+fn inline(text: &str) -> String {
+    // code spans are taken as they are
+    let mut html = String::new();
+    for (idx, part) in text.split('`').enumerate() {
+        if idx % 2 == 1 {
+            html += &format!("<code>{}</code>", escape(part));
+        } else {
+            let part = escape(part);
+            let part = wrap(&part, "**", "strong");
+            let part = wrap(&part, "*", "em");
+            html += &links(&part);
+        }
+    }
+    html
+}
+
+fn is_table_separator(line: &str) -> bool {
+    line.starts_with('|')
+        && line.contains('-')
+        && line.chars().all(|c| matches!(c, '|' | '-' | ':' | ' '))
+}
+
+fn cells(line: &str) -> Vec<String> {
+    line.trim()
+        .trim_matches('|')
+        .split('|')
+        .map(|cell| inline(cell.trim()))
+        .collect()
+}
+
+fn list_item(line: &str) -> Option<(&'static str, &str)> {
+    if let Some(item) = line.strip_prefix("- ").or(line.strip_prefix("* ")) {
+        return Some(("ul", item));
+    }
+    let digits = line.chars().take_while(|c| c.is_ascii_digit()).count();
+    if digits > 0 {
+        if let Some(item) = line[digits..].strip_prefix(". ") {
+            return Some(("ol", item));
+        }
+    }
+    None
+}
+
+pub fn to_html(markdown: &str) -> String {
+    let mut html = String::new();
+    let mut lines = markdown.lines().peekable();
+    let mut paragraph: Vec<String> = vec![];
+
+    let flush = |html: &mut String, paragraph: &mut Vec<String>| {
+        if !paragraph.is_empty() {
+            *html += &format!("<p>{}</p>\n", paragraph.join("\n"));
+            paragraph.clear();
+        }
+    };
+
+    while let Some(line) = lines.next() {
+        let trimmed = line.trim();
+
+        if let Some(fence) = ["```", "~~~"].iter().find(|f| trimmed.starts_with(**f)) {
+            flush(&mut html, &mut paragraph);
+            let mut code = vec![];
+            for line in lines.by_ref() {
+                if line.trim().starts_with(fence) {
+                    break;
+                }
+                code.push(escape(line));
+            }
+            html += &format!("<pre><code>{}</code></pre>\n", code.join("\n"));
+        } else if trimmed.is_empty() {
+            flush(&mut html, &mut paragraph);
+        } else if let Some(level) =
+            (1..=6).find(|level| trimmed.starts_with(&format!("{} ", "#".repeat(*level))))
+        {
+            flush(&mut html, &mut paragraph);
+            html += &format!(
+                "<h{}>{}</h{}>\n",
+                level,
+                inline(trimmed[level + 1..].trim()),
+                level
+            );
+        } else if trimmed == "---" || trimmed == "***" {
+            flush(&mut html, &mut paragraph);
+            html += "<hr>\n";
+        } else if trimmed.starts_with('>') {
+            flush(&mut html, &mut paragraph);
+            let mut quote = vec![inline(trimmed.trim_start_matches('>').trim())];
+            while let Some(line) = lines.next_if(|l| l.trim().starts_with('>')) {
+                quote.push(inline(line.trim().trim_start_matches('>').trim()));
+            }
+            html += &format!("<blockquote>{}</blockquote>\n", quote.join("<br>\n"));
+        } else if trimmed.starts_with('|')
+            && lines.peek().is_some_and(|l| is_table_separator(l.trim()))
+        {
+            flush(&mut html, &mut paragraph);
+            lines.next();
+            html += "<table>\n<tr>";
+            for cell in cells(trimmed) {
+                html += &format!("<th>{}</th>", cell);
+            }
+            html += "</tr>\n";
+            while let Some(row) = lines.next_if(|l| l.trim().starts_with('|')) {
+                html += "<tr>";
+                for cell in cells(row) {
+                    html += &format!("<td>{}</td>", cell);
+                }
+                html += "</tr>\n";
+            }
+            html += "</table>\n";
+        } else if let Some((tag, item)) = list_item(trimmed) {
+            flush(&mut html, &mut paragraph);
+            html += &format!("<{}>\n<li>{}", tag, inline(item));
+            while let Some(line) = lines.next_if(|l| {
+                !l.trim().is_empty() && (list_item(l.trim()).is_some() || l.starts_with("  "))
+            }) {
+                match list_item(line.trim()) {
+                    Some((_, item)) if !line.starts_with("  ") => {
+                        html += &format!("</li>\n<li>{}", inline(item))
+                    }
+                    // nested items and continuations stay in the current item
+                    _ => html += &format!("<br>\n{}", inline(line.trim())),
+                }
+            }
+            html += &format!("</li>\n</{}>\n", tag);
+        } else {
+            paragraph.push(inline(trimmed));
+        }
+    }
+
+    flush(&mut html, &mut paragraph);
+    html
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_inline() {
+        assert_eq!(
+            inline("**bold** and *italic* with `<b>code</b>`"),
+            "<strong>bold</strong> and <em>italic</em> with <code>&lt;b&gt;code&lt;/b&gt;</code>"
+        );
+        assert_eq!(inline("2 * 3 <script>"), "2 * 3 &lt;script&gt;");
+        assert_eq!(
+            inline("[site](https://example.com) and [x](javascript:alert(1))"),
+            "<a href=\"https://example.com\">site</a> and x (javascript:alert(1))"
+        );
+    }
+
+    #[test]
+    fn test_to_html() {
+        let html = to_html(
+            "# Report\n\nSome text\non two lines.\n\n## Findings\n\n- one\n- two\n  detail\n\n1. first\n2. second\n\n```sh\nnmap -sV <host>\n```\n\n> quoted\n\n| Host | Port |\n|------|:----:|\n| a | 22 |\n",
+        );
+        assert_eq!(
+            html,
+            "<h1>Report</h1>\n<p>Some text\non two lines.</p>\n<h2>Findings</h2>\n<ul>\n<li>one</li>\n<li>two<br>\ndetail</li>\n</ul>\n<ol>\n<li>first</li>\n<li>second</li>\n</ol>\n<pre><code>nmap -sV &lt;host&gt;</code></pre>\n<blockquote>quoted</blockquote>\n<table>\n<tr><th>Host</th><th>Port</th></tr>\n<tr><td>a</td><td>22</td></tr>\n</table>\n"
+        );
+    }
+}
@@ -0,0 +1,299 @@
+use std::{collections::HashMap, sync::Arc};
+
+use anyhow::Result;
+use serde_json::{json, Value};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+use crate::{
+    agent::{
+        namespaces::{resolve_used_namespaces, Action, NAMESPACES},
+        state::SharedState,
+        task::variables::{get_variable, parse_pre_defined_values},
+    },
+    cli::{
+        self,
+        tool::{create_state, ToolTask},
+    },
+    APP_NAME, APP_VERSION,
+};
+
+const PROTOCOL_VERSION: &str = "2024-11-05";
+
+#[allow(clippy::borrowed_box)]
+fn tool_definition(action: &Box<dyn Action>) -> Value {
+    let mut properties = serde_json::Map::new();
+    let mut required = vec![];
+
+    if let Some(example) = action.example_payload() {
+        properties.insert(
+            "payload".to_string(),
+            json!({
+                "type": "string",
+                "description": format!("The main function argument, use this as a template: {}", example)
+            }),
+        );
+        required.push("payload".to_string());
+    }
+
+    let mut attributes: Vec<String> = action
+        .example_attributes()
+        .map(|attrs| attrs.into_keys().collect())
+        .unwrap_or_default();
+    attributes.sort();
+    for name in attributes {
+        properties.insert(
+            name.to_string(),
+            json!({"type": "string", "description": name}),
+        );
+        required.push(name);
+    }
+
+    json!({
+        "name": action.name(),
+        "description": action.description(),
+        "inputSchema": {
+            "type": "object",
+            "properties": properties,
+            "required": required,
+        }
+    })
+}
+
+// stdin is reserved to the protocol so required variables can't be asked interactively, return
+// the first undefined variable of each namespace
+fn undefined_variables(using: &[String]) -> Result<Vec<(String, String)>> {
+    let mut undefined = vec![];
+    for name in resolve_used_namespaces(Some(using.to_vec()))? {
+        let var_name = NAMESPACES.get(&name).unwrap()()
+            .actions
+            .iter()
+            .flat_map(|action| action.required_variables().unwrap_or_default())
+            .find(|var_name| {
+                !var_name.contains("||")
+                    && std::env::var(var_name).is_err()
+                    && get_variable(var_name).is_none()
+            });
+        if let Some(var_name) = var_name {
+            undefined.push((name, var_name));
+        }
+    }
+    Ok(undefined)
+}
+
+struct Server {
+    state: SharedState,
+    actions: Vec<Box<dyn Action>>,
+}
+
+impl Server {
+    async fn new(args: &cli::Args, using: &[String]) -> Result<Self> {
+        parse_pre_defined_values(&args.define)?;
+
+        let using = if using.is_empty() {
+            // everything that makes sense outside of an agent
+            let mut using: Vec<String> = ["*", "filesystem", "http", "!task", "!goal"]
+                .iter()
+                .map(|s| s.to_string())
+                .collect();
+            for (name, var_name) in undefined_variables(&using)? {
+                log::warn!("not exporting {} as ${} is not defined", name, var_name);
+                using.push(format!("!{}", name));
+            }
+            using
+        } else {
+            if let Some((name, var_name)) = undefined_variables(using)?.first() {
+                return Err(anyhow!(
+                    "namespace {} requires ${}, set it in the environment or with --var {}=...",
+                    name,
+                    var_name,
+                    var_name
+                ));
+            }
+            using.to_vec()
+        };
+
+        let state = create_state(args, Box::new(ToolTask { using })).await?;
+
+        let mut actions = vec![];
+        for namespace in state.get_namespaces() {
+            let confirm = state
+                .get_policy()
+                .requires_confirmation(Some(&namespace.name.to_lowercase()));
+            for action in &namespace.actions {
+                // there's nobody to ask for confirmation
+                if confirm || action.requires_user_confirmation() {
+                    log::warn!(
+                        "not exporting {} as it requires user confirmation",
+                        action.name()
+                    );
+                    continue;
+                }
+                actions.push(action.clone());
+            }
+        }
+
+        log::info!(
+            "serving {} tools via MCP: {}",
+            actions.len(),
+            actions
+                .iter()
+                .map(|a| a.name())
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+
+        Ok(Self {
+            state: Arc::new(tokio::sync::Mutex::new(state)),
+            actions,
+        })
+    }
+
+    async fn call_tool(&self, params: &Value) -> Result<Value> {
+        let name = params
+            .get("name")
+            .and_then(|n| n.as_str())
+            .ok_or_else(|| anyhow!("tool name not specified"))?;
+        let action = self
+            .actions
+            .iter()
+            .find(|a| a.name() == name)
+            .ok_or_else(|| anyhow!("unknown tool '{}'", name))?;
+
+        let mut payload = None;
+        let mut attributes = HashMap::new();
+        if let Some(arguments) = params.get("arguments").and_then(|a| a.as_object()) {
+            for (key, value) in arguments {
+                let value = match value {
+                    Value::String(s) => s.to_string(),
+                    other => other.to_string(),
+                };
+                if key == "payload" {
+                    payload = Some(value);
+                } else {
+                    attributes.insert(key.to_string(), value);
+                }
+            }
+        }
+
+        if action.example_payload().is_some() && payload.is_none() {
+            return Err(anyhow!("no payload specified for '{}'", name));
+        }
+
+        let ret = action
+            .run(
+                self.state.clone(),
+                if attributes.is_empty() {
+                    None
+                } else {
+                    Some(attributes)
+                },
+                payload,
+            )
+            .await;
+
+        Ok(match ret {
+            Ok(output) => json!({
+                "content": [{"type": "text", "text": output.unwrap_or_default()}],
+                "isError": false,
+            }),
+            Err(err) => json!({
+                "content": [{"type": "text", "text": err.to_string()}],
+                "isError": true,
+            }),
+        })
+    }
+
+    // handle a json-rpc message, returns the response if any
+    async fn handle(&self, message: &Value) -> Option<Value> {
+        // notifications don't have an id and don't get a response
+        let id = message.get("id")?.clone();
+        let method = message
+            .get("method")
+            .and_then(|m| m.as_str())
+            .unwrap_or_default();
+        let params = message.get("params").cloned().unwrap_or(Value::Null);
+
+        let result = match method {
+            "initialize" => Ok(json!({
+                "protocolVersion": params
+                    .get("protocolVersion")
+                    .and_then(|v| v.as_str())
+                    .unwrap_or(PROTOCOL_VERSION),
+                "capabilities": {"tools": {}},
+                "serverInfo": {"name": APP_NAME, "version": APP_VERSION},
+            })),
+            "ping" => Ok(json!({})),
+            "tools/list" => Ok(json!({
+                "tools": self.actions.iter().map(tool_definition).collect::<Vec<_>>()
+            })),
+            "tools/call" => self
+                .call_tool(&params)
+                .await
+                .map_err(|e| (-32602, e.to_string())),
+            _ => Err((-32601, format!("method '{}' not found", method))),
+        };
+
+        Some(match result {
+            Ok(result) => json!({"jsonrpc": "2.0", "id": id, "result": result}),
+            Err((code, message)) => {
+                json!({"jsonrpc": "2.0", "id": id, "error": {"code": code, "message": message}})
+            }
+        })
+    }
+}
+
+// serve the built-in namespaces as MCP tools over stdio until stdin is closed
+pub async fn serve(args: &cli::Args, using: &[String]) -> Result<()> {
+    let server = Server::new(args, using).await?;
+
+    let mut lines = BufReader::new(tokio::io::stdin()).lines();
+    let mut stdout = tokio::io::stdout();
+
+    while let Some(line) = lines.next_line().await? {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        let response = match serde_json::from_str::<Value>(line) {
+            Ok(message) => server.handle(&message).await,
+            Err(err) => Some(json!({
+                "jsonrpc": "2.0",
+                "id": null,
+                "error": {"code": -32700, "message": err.to_string()}
+            })),
+        };
+
+        if let Some(response) = response {
+            let mut response = serde_json::to_string(&response)?;
+            response.push('\n');
+            stdout.write_all(response.as_bytes()).await?;
+            stdout.flush().await?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent::namespaces::NAMESPACES;
+
+    #[test]
+    fn test_tool_definition() {
+        let read_file = NAMESPACES.get("filesystem").unwrap()()
+            .actions
+            .into_iter()
+            .find(|a| a.name() == "read_file")
+            .unwrap();
+
+        let tool = tool_definition(&read_file);
+        assert_eq!(tool["name"], "read_file");
+        assert_eq!(tool["inputSchema"]["required"], json!(["payload"]));
+        assert_eq!(
+            tool["inputSchema"]["properties"]["payload"]["type"],
+            "string"
+        );
+    }
+}
@@ -1,5 +1,39 @@
+pub mod audit;
+pub mod batch;
 pub mod cli;
+pub mod config;
+pub mod credentials;
+pub mod daemon;
+pub mod diff;
+pub mod engagement;
+pub mod eval;
+pub mod judge;
+pub mod logging;
+pub mod markdown;
+#[cfg(feature = "mcp")]
+pub mod mcp;
+pub mod namespaces;
+pub mod output;
+#[cfg(feature = "redis")]
+pub mod queue;
+#[cfg(feature = "redis")]
+pub mod redis;
+pub mod replay;
+pub mod report;
+#[cfg(feature = "serve")]
+pub mod serve;
+pub mod sessions;
 pub mod setup;
+pub mod signals;
+pub mod sinks;
+pub mod status;
+#[cfg(feature = "serve")]
+pub mod stream;
+pub mod test;
+pub mod tool;
+pub mod trace;
 pub mod ui;
+pub mod validate;
+pub mod workflow;
 
-pub use cli::Args;
+pub use cli::{Args, Cli};
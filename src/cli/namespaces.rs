@@ -0,0 +1,230 @@
+use std::collections::BTreeMap;
+
+use anyhow::Result;
+use colored::Colorize;
+use serde::Serialize;
+
+use crate::agent::{
+    namespaces::{Action, Namespace, NAMESPACES},
+    serialization::Strategy,
+    state::storage::StorageType,
+};
+
+// how the namespaces are printed
+#[derive(Debug, Clone, Copy, Default, PartialEq, clap::ValueEnum)]
+pub enum Format {
+    // a short listing for the terminal
+    #[default]
+    Text,
+    // the documentation as shown to the model
+    Markdown,
+    Json,
+}
+
+#[derive(Debug, Serialize)]
+struct StorageInfo {
+    name: String,
+    #[serde(rename = "type")]
+    type_: StorageType,
+}
+
+#[derive(Debug, Serialize)]
+struct ActionInfo {
+    name: String,
+    description: String,
+    // how the model is told to invoke it
+    usage: String,
+    example_payload: Option<String>,
+    example_attributes: Option<BTreeMap<String, String>>,
+    required_variables: Vec<String>,
+    requires_confirmation: bool,
+    destructive: bool,
+    completes_task: bool,
+    timeout_secs: Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
+struct NamespaceInfo {
+    name: String,
+    description: String,
+    // used by tasklets that don't list their namespaces
+    default: bool,
+    storages: Vec<StorageInfo>,
+    actions: Vec<ActionInfo>,
+}
+
+impl ActionInfo {
+    #[allow(clippy::borrowed_box)]
+    fn new(action: &Box<dyn Action>) -> Self {
+        Self {
+            name: action.name().to_string(),
+            description: action.description().to_string(),
+            usage: Strategy::default().serialize_action(action),
+            example_payload: action.example_payload().map(str::to_string),
+            example_attributes: action
+                .example_attributes()
+                .map(|attrs| attrs.into_iter().collect()),
+            required_variables: action.required_variables().unwrap_or_default(),
+            requires_confirmation: action.requires_user_confirmation(),
+            destructive: action.is_destructive(),
+            completes_task: action.complete_task(),
+            timeout_secs: action.timeout().map(|t| t.as_secs()),
+        }
+    }
+}
+
+impl NamespaceInfo {
+    fn new(namespace: &Namespace) -> Self {
+        Self {
+            name: namespace.name.to_string(),
+            description: namespace.description.to_string(),
+            default: namespace.default,
+            storages: namespace
+                .storages
+                .iter()
+                .flatten()
+                .map(|storage| StorageInfo {
+                    name: storage.name.to_string(),
+                    type_: storage.type_,
+                })
+                .collect(),
+            actions: namespace.actions.iter().map(ActionInfo::new).collect(),
+        }
+    }
+
+    fn to_markdown(&self) -> String {
+        let mut md = format!("## {}\n\n", self.name);
+        if !self.description.is_empty() {
+            md += &format!("{}\n\n", self.description);
+        }
+        for action in &self.actions {
+            md += &format!("{} {}\n\n", action.description, action.usage);
+        }
+        md
+    }
+
+    fn to_text(&self) -> String {
+        let mut text = self.name.bold().to_string();
+        if self.default {
+            text += &format!(" {}", "(default)".dimmed());
+        }
+        text += "\n";
+        if !self.description.trim().is_empty() {
+            text += &format!("  {}\n", self.description.trim().replace('\n', "\n  "));
+        }
+        if !self.storages.is_empty() {
+            let storages = self
+                .storages
+                .iter()
+                .map(|s| format!("{} ({:?})", s.name, s.type_))
+                .collect::<Vec<_>>();
+            text += &format!("  {} {}\n", "storages:".dimmed(), storages.join(", "));
+        }
+
+        for action in &self.actions {
+            text += &format!(
+                "\n  {} {}\n",
+                action.name.green().bold(),
+                action.description
+            );
+            text += &format!("    {}\n", action.usage.dimmed());
+
+            let mut notes = vec![];
+            if !action.required_variables.is_empty() {
+                notes.push(format!("requires {}", action.required_variables.join(", ")));
+            }
+            if action.requires_confirmation {
+                notes.push("asks for confirmation".to_string());
+            } else if action.destructive {
+                notes.push("destructive".to_string());
+            }
+            if action.completes_task {
+                notes.push("completes the task".to_string());
+            }
+            if let Some(timeout) = action.timeout_secs {
+                notes.push(format!("times out after {}s", timeout));
+            }
+            if !notes.is_empty() {
+                text += &format!("    {}\n", notes.join(", ").yellow());
+            }
+        }
+
+        text
+    }
+}
+
+fn selected(names: &[String]) -> Result<Vec<NamespaceInfo>> {
+    if names.is_empty() {
+        return Ok(NAMESPACES
+            .values()
+            .map(|build_fn| NamespaceInfo::new(&build_fn()))
+            .collect());
+    }
+
+    names
+        .iter()
+        .map(|name| {
+            NAMESPACES
+                .get(name)
+                .map(|build_fn| NamespaceInfo::new(&build_fn()))
+                .ok_or_else(|| anyhow!("no namespace '{}' defined", name))
+        })
+        .collect()
+}
+
+pub fn run(names: &[String], format: Format) -> Result<()> {
+    let namespaces = selected(names)?;
+
+    match format {
+        Format::Text => {
+            let text = namespaces
+                .iter()
+                .map(NamespaceInfo::to_text)
+                .collect::<Vec<_>>();
+            println!("{}", text.join("\n"));
+        }
+        Format::Markdown => {
+            let md = namespaces
+                .iter()
+                .map(NamespaceInfo::to_markdown)
+                .collect::<String>();
+            println!("{}", md.trim());
+        }
+        Format::Json => println!("{}", serde_json::to_string_pretty(&namespaces)?),
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_selected() {
+        let all = selected(&[]).unwrap();
+        assert_eq!(all.len(), NAMESPACES.len());
+
+        let shell = selected(&["shell".to_string()]).unwrap();
+        let action = &shell[0].actions[0];
+        assert_eq!(action.name, "shell");
+        assert!(action.requires_confirmation);
+        assert!(action.usage.starts_with("<shell>"));
+
+        let memory = selected(&["memory".to_string()]).unwrap();
+        assert!(memory[0].storages.iter().any(|s| s.name == "memories"));
+
+        assert!(selected(&["teleport".to_string()]).is_err());
+    }
+
+    #[test]
+    fn test_markdown() {
+        // the same documentation of the serializer
+        let md = selected(&[])
+            .unwrap()
+            .iter()
+            .map(NamespaceInfo::to_markdown)
+            .collect::<String>();
+        assert_eq!(md.trim(), Strategy::available_actions());
+    }
+}
@@ -0,0 +1,188 @@
+use std::io::Write;
+
+use anyhow::Result;
+use serde::Serialize;
+
+use crate::{
+    agent::{
+        secrets,
+        state::{metrics::Metrics, session::Session},
+    },
+    cli::{
+        judge::Verdict,
+        report::{self, ReportStorage, Summary},
+    },
+    APP_NAME, APP_VERSION,
+};
+
+// what goes to the standard output
+#[derive(Debug, Clone, Copy, Default, PartialEq, clap::ValueEnum)]
+pub enum Format {
+    // the log lines and the actions as they happen
+    #[default]
+    Text,
+    // a single JSON document once the run ends, everything else goes to the standard error
+    Json,
+}
+
+// point the standard output to the standard error, so that what is printed during the run by
+// nerve and by the commands it executes can't end up in the document; returns the original one
+#[cfg(unix)]
+pub fn reserve_stdout() -> Result<Box<dyn Write + Send>> {
+    use std::os::fd::FromRawFd;
+
+    std::io::stdout().flush()?;
+
+    let fd = unsafe { libc::dup(libc::STDOUT_FILENO) };
+    if fd < 0 {
+        return Err(anyhow!(
+            "can't duplicate the standard output: {}",
+            std::io::Error::last_os_error()
+        ));
+    }
+    if unsafe { libc::dup2(libc::STDERR_FILENO, libc::STDOUT_FILENO) } < 0 {
+        return Err(anyhow!(
+            "can't redirect the standard output: {}",
+            std::io::Error::last_os_error()
+        ));
+    }
+
+    Ok(Box::new(unsafe { std::fs::File::from_raw_fd(fd) }))
+}
+
+#[cfg(not(unix))]
+pub fn reserve_stdout() -> Result<Box<dyn Write + Send>> {
+    Ok(Box::new(std::io::stdout()))
+}
+
+// the result of a run for scripts and pipelines
+#[derive(Debug, Serialize)]
+pub struct Document {
+    generated_by: String,
+    // complete, impossible, incomplete, interrupted or failed
+    status: String,
+    // the reason given by the model when it completed the task
+    output: Option<String>,
+    error: Option<String>,
+    // set if the output was reviewed with --judge-output
+    verdict: Option<Verdict>,
+    tasklet: Option<String>,
+    prompt: Option<String>,
+    findings: Vec<ReportStorage>,
+    summary: Option<Summary>,
+    metrics: Option<Metrics>,
+}
+
+impl Document {
+    // a run that could not start or be described
+    pub fn failed(error: &anyhow::Error) -> Self {
+        Self {
+            generated_by: format!("{} v{}", APP_NAME, APP_VERSION),
+            status: "failed".to_string(),
+            output: None,
+            error: Some(error.to_string()),
+            verdict: None,
+            tasklet: None,
+            prompt: None,
+            findings: vec![],
+            summary: None,
+            metrics: None,
+        }
+    }
+
+    pub fn new(session: &Result<Session>, ret: &Result<()>, interrupted: bool) -> Self {
+        let session = match session {
+            Ok(session) => session,
+            Err(err) => return Self::failed(ret.as_ref().err().unwrap_or(err)),
+        };
+
+        let status = if ret.is_err() {
+            "failed"
+        } else if interrupted {
+            "interrupted"
+        } else {
+            report::outcome(session)
+        };
+
+        Self {
+            generated_by: format!("{} v{}", APP_NAME, APP_VERSION),
+            status: status.to_string(),
+            output: session.completion_reason.clone(),
+            error: ret.as_ref().err().map(|err| err.to_string()),
+            verdict: None,
+            tasklet: Some(session.tasklet.to_string()),
+            prompt: Some(session.prompt.to_string()),
+            findings: report::storages(session),
+            summary: Some(Summary::from_session(session)),
+            metrics: Some(session.metrics.clone()),
+        }
+    }
+
+    pub fn with_verdict(mut self, verdict: Option<Verdict>) -> Self {
+        self.verdict = verdict;
+        self
+    }
+
+    pub fn write(&self, out: &mut dyn Write) -> Result<()> {
+        // never export the registered secrets
+        let json = secrets::redact_json(&serde_json::to_string_pretty(self)?);
+        writeln!(out, "{}", json)?;
+        out.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent::state::history::History;
+
+    fn session(complete: bool) -> Session {
+        serde_json::from_value(serde_json::json!({
+            "version": 1,
+            "created_at": chrono::Local::now(),
+            "updated_at": chrono::Local::now(),
+            "tasklet": "/tmp/tasklet",
+            "generator": "mock:///tmp/x.yml",
+            "prompt": "find the user",
+            "complete": complete,
+            "impossible": false,
+            "completion_reason": if complete { Some("the user is root") } else { None },
+            "metrics": Metrics::default(),
+            "history": History::new(),
+            "storages": [
+                {"name": "memories", "type": "Tagged", "entries": [{"key": "user", "data": "root", "complete": false}]},
+                {"name": "goal", "type": "CurrentPrevious", "entries": []}
+            ],
+            "variables": {},
+            "rag_data_path": null,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_document() {
+        let doc = Document::new(&Ok(session(true)), &Ok(()), false);
+        assert_eq!(doc.status, "complete");
+        assert_eq!(doc.output.as_deref(), Some("the user is root"));
+        assert!(doc.error.is_none());
+        assert_eq!(doc.findings.len(), 1);
+
+        let mut out = vec![];
+        doc.write(&mut out).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&out).unwrap();
+        assert_eq!(json["findings"][0]["entries"][0]["data"], "root");
+        assert_eq!(json["summary"]["outcome"], "complete");
+
+        let doc = Document::new(&Ok(session(false)), &Ok(()), true);
+        assert_eq!(doc.status, "interrupted");
+
+        let doc = Document::new(&Ok(session(false)), &Err(anyhow!("no more steps")), false);
+        assert_eq!(doc.status, "failed");
+        assert_eq!(doc.error.as_deref(), Some("no more steps"));
+
+        let doc = Document::new(&Err(anyhow!("no session")), &Ok(()), false);
+        assert_eq!(doc.status, "failed");
+        assert!(doc.summary.is_none());
+    }
+}
@@ -0,0 +1,420 @@
+use std::{
+    io::Write,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use anyhow::Result;
+use colored::Colorize;
+use serde::{Deserialize, Serialize};
+use tokio::sync::{watch, Semaphore};
+
+use crate::{
+    agent::{events::Event, scheduler::Scheduler, secrets},
+    cli::{
+        self,
+        batch::{self, Batch, BatchReport, Entry, Outcome},
+        logging,
+        redis::Client,
+        setup, signals, ui,
+    },
+};
+
+// how long a blocking pop waits before checking again if the worker was stopped
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+// the messages of a run are kept for the coordinator to come back for them
+const RUN_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+// how often a worker reports that a run is still going
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(10);
+// a run with no messages for this long lost its worker
+const HEARTBEAT_TIMEOUT: Duration = Duration::from_secs(60);
+
+// a tasklet queued by the coordinator
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Job {
+    pub id: String,
+    #[serde(flatten)]
+    pub entry: Entry,
+    // variables defined by the coordinator, as NAME=VALUE
+    #[serde(default)]
+    pub define: Vec<String>,
+}
+
+// what a worker reports back about a run
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Message {
+    Claimed { worker: String },
+    Heartbeat,
+    Event(Event),
+    End(Outcome),
+}
+
+// the keys shared by the coordinators and the workers using the same queue name
+#[derive(Debug, Clone, PartialEq)]
+pub struct Keys {
+    name: String,
+}
+
+impl Keys {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+        }
+    }
+
+    // the jobs waiting for a worker
+    pub fn jobs(&self) -> String {
+        format!("{}:jobs", self.name)
+    }
+
+    // the messages of a run
+    pub fn run(&self, id: &str) -> String {
+        format!("{}:runs:{}", self.name, id)
+    }
+}
+
+// why a run that is waiting for messages won't get any, if it was waited for too long
+fn overdue(
+    worker: Option<&str>,
+    since_activity: Duration,
+    since_message: Duration,
+    claim_timeout: Duration,
+) -> Option<String> {
+    match worker {
+        // the runs of the dispatch keeping the workers busy postpone the claim deadline
+        None if since_activity > claim_timeout => Some(format!(
+            "not claimed by any worker within {}s",
+            claim_timeout.as_secs()
+        )),
+        Some(worker) if since_message > HEARTBEAT_TIMEOUT => Some(format!(
+            "worker {} stopped reporting for {}s",
+            worker,
+            since_message.as_secs()
+        )),
+        _ => None,
+    }
+}
+
+async fn report(client: &mut Client, key: &str, message: &Message) -> Result<()> {
+    // the registered secrets never leave the worker
+    let message = secrets::redact_json(&serde_json::to_string(message)?);
+    client.push(key, &message).await
+}
+
+// tell the coordinator that the run is still going, on a connection of its own
+async fn heartbeat(queue: String, key: String) -> Result<()> {
+    let mut client = Client::connect(&queue).await?;
+    loop {
+        tokio::time::sleep(HEARTBEAT_INTERVAL).await;
+        report(&mut client, &key, &Message::Heartbeat).await?;
+    }
+}
+
+async fn run_job(
+    mut args: cli::Args,
+    queue: String,
+    keys: Keys,
+    worker: String,
+    job: Job,
+    stop: watch::Receiver<bool>,
+) -> Result<()> {
+    let key = keys.run(&job.id);
+    let mut client = Client::connect(&queue).await?;
+    report(&mut client, &key, &Message::Claimed { worker }).await?;
+    let heartbeat = tokio::spawn(heartbeat(queue.clone(), key.clone()));
+
+    args.define.extend(job.define.iter().cloned());
+
+    // the events are forwarded on the connection of the run, which is returned once the state
+    // holding the events channel is dropped
+    let mut forwarder = None;
+    let (outcome, _) = batch::run_entry_with(&args, &job.entry, Some(stop), |_, mut events_rx| {
+        let key = key.clone();
+        forwarder = Some(tokio::spawn(async move {
+            while let Some(event) = events_rx.recv().await {
+                if let Err(err) = report(&mut client, &key, &Message::Event(event)).await {
+                    log::error!("could not report the event: {}", err);
+                }
+            }
+            client
+        }));
+    })
+    .await;
+    heartbeat.abort();
+
+    let mut client = match forwarder {
+        Some(forwarder) => forwarder.await?,
+        None => Client::connect(&queue).await?,
+    };
+    report(&mut client, &key, &Message::End(outcome)).await?;
+    client.expire(&key, RUN_TTL).await
+}
+
+// claim and execute the jobs of the queue until interrupted
+pub async fn work(args: &cli::Args, queue: &str, name: &str, workers: usize) -> Result<()> {
+    setup::check_credentials(args)?;
+
+    let keys = Keys::new(name);
+    let mut client = Client::connect(queue).await?;
+    let worker = format!("{}:{}", logging::hostname(), std::process::id());
+    let workers = workers.max(1);
+
+    log::info!(
+        "worker {} claiming jobs from {} with {} workers ...",
+        worker.bold(),
+        keys.jobs().green(),
+        workers
+    );
+
+    let (stop_tx, stop_rx) = watch::channel(false);
+    tokio::spawn(async move {
+        signals::signal().await;
+        let _ = stop_tx.send(true);
+    });
+
+    // jobs are only claimed when there's a free worker, leaving the others to the other instances
+    let scheduler = Scheduler::new(workers)?;
+    let slots = Arc::new(Semaphore::new(workers));
+
+    let mut stopped = stop_rx.clone();
+    while !*stop_rx.borrow() {
+        let slot = tokio::select! {
+            slot = slots.clone().acquire_owned() => slot?,
+            _ = stopped.wait_for(|stop| *stop) => break,
+        };
+        // never interrupted while waiting, or a job could be popped and lost
+        let data = match client.pop(&keys.jobs(), POLL_INTERVAL).await? {
+            Some(data) => data,
+            None => continue,
+        };
+        let job: Job = match serde_json::from_str(&data) {
+            Ok(job) => job,
+            Err(err) => {
+                log::error!("invalid job '{}': {}", data, err);
+                continue;
+            }
+        };
+
+        log::info!("claimed job {} ({})", job.id.bold(), job.entry.tasklet);
+
+        let args = args.clone();
+        let queue = queue.to_string();
+        let keys = keys.clone();
+        let worker = worker.clone();
+        let stop = stop_rx.clone();
+        drop(scheduler.spawn(async move {
+            let id = job.id.to_string();
+            if let Err(err) = run_job(args, queue, keys, worker, job, stop).await {
+                log::error!("job {} could not be reported: {}", id, err);
+            }
+            drop(slot);
+        }));
+    }
+
+    let running = workers - slots.available_permits();
+    if running > 0 {
+        log::warn!(
+            "stopping {} running jobs at the end of their current step (press again to exit immediately) ...",
+            running
+        );
+        tokio::select! {
+            _ = slots.acquire_many(workers as u32) => {},
+            _ = signals::signal() => std::process::exit(130),
+        }
+    }
+
+    Ok(())
+}
+
+// the entries to dispatch, from --all or the tasklet of the command line
+fn entries(args: &cli::Args) -> Result<Vec<Entry>> {
+    if let Some(path) = &args.all {
+        Ok(Batch::from_path(path)?.entries)
+    } else if let Some(tasklet) = &args.tasklet {
+        Ok(vec![Entry {
+            tasklet: tasklet.to_string(),
+            prompt: args.prompt.clone(),
+            generator: None,
+        }])
+    } else {
+        Err(anyhow!(
+            "a tasklet (-T) or a batch of tasklets (--all) is required"
+        ))
+    }
+}
+
+// a run followed by the coordinator
+struct Run {
+    queue: String,
+    keys: Keys,
+    job: Job,
+    claim_timeout: Duration,
+    // when a message of any run of the dispatch was last received
+    activity: Arc<Mutex<Instant>>,
+}
+
+// wait for the messages of the run until it ends, or until its worker is lost or there's none
+async fn follow(args: cli::Args, run: Run) -> Result<Outcome> {
+    let key = run.keys.run(&run.job.id);
+    let mut client = Client::connect(&run.queue).await?;
+    let mut worker: Option<String> = None;
+    let mut last_message = Instant::now();
+    loop {
+        let data = match client.pop(&key, POLL_INTERVAL).await? {
+            Some(data) => data,
+            None => {
+                let since_activity = run.activity.lock().unwrap().elapsed();
+                if let Some(reason) = overdue(
+                    worker.as_deref(),
+                    since_activity,
+                    last_message.elapsed(),
+                    run.claim_timeout,
+                ) {
+                    // a job still in the queue can't be claimed after failing
+                    let job = serde_json::to_string(&run.job)?;
+                    if worker.is_some() || client.remove(&run.keys.jobs(), &job).await? {
+                        return Err(anyhow!("{}", reason));
+                    }
+                }
+                continue;
+            }
+        };
+
+        last_message = Instant::now();
+        *run.activity.lock().unwrap() = last_message;
+        match serde_json::from_str(&data)? {
+            Message::Claimed { worker: claimed } => {
+                log::info!(
+                    "tasklet {} claimed by {}",
+                    run.job.entry.tasklet.bold(),
+                    claimed
+                );
+                worker = Some(claimed);
+            }
+            Message::Heartbeat => {}
+            Message::Event(event) => ui::text::on_event(&args, event),
+            Message::End(outcome) => return Ok(outcome),
+        }
+    }
+}
+
+// queue the tasklets for the workers and report their outcomes once they are all done
+pub async fn dispatch(
+    args: &cli::Args,
+    queue: &str,
+    name: &str,
+    claim_timeout: Duration,
+    document_out: Option<Box<dyn Write + Send>>,
+) -> Result<()> {
+    let keys = Keys::new(name);
+    let entries = entries(args)?;
+    let mut client = Client::connect(queue).await?;
+    let started = Instant::now();
+    let activity = Arc::new(Mutex::new(started));
+
+    let prefix = format!(
+        "{}-{}",
+        chrono::Local::now().format("%Y%m%d%H%M%S"),
+        std::process::id()
+    );
+    let mut follows = vec![];
+    for (idx, entry) in entries.iter().enumerate() {
+        let job = Job {
+            id: format!("{}-{}", prefix, idx + 1),
+            entry: entry.clone(),
+            define: args.define.clone(),
+        };
+        client
+            .push(&keys.jobs(), &serde_json::to_string(&job)?)
+            .await?;
+        follows.push(tokio::spawn(follow(
+            args.clone(),
+            Run {
+                queue: queue.to_string(),
+                keys: keys.clone(),
+                job,
+                claim_timeout,
+                activity: activity.clone(),
+            },
+        )));
+    }
+
+    log::info!(
+        "queued {} tasklets on {}, waiting for the workers ...",
+        entries.len(),
+        keys.jobs().green()
+    );
+
+    let mut outcomes = vec![];
+    for (entry, follow) in entries.iter().zip(follows) {
+        outcomes.push(match follow.await.map_err(anyhow::Error::from) {
+            Ok(Ok(outcome)) => outcome,
+            Ok(Err(err)) | Err(err) => Outcome::failed(entry, &err),
+        });
+    }
+
+    let source = args.all.as_ref().or(args.tasklet.as_ref()).unwrap();
+    batch::conclude(
+        args,
+        &BatchReport::new(source, started, outcomes),
+        document_out,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_job_message() {
+        let job: Job = serde_json::from_str(
+            r#"{"id":"1","tasklet":"recon/nmap.yml","prompt":null,"generator":"openai://gpt-4o"}"#,
+        )
+        .unwrap();
+        assert_eq!(job.entry.tasklet, "recon/nmap.yml");
+        assert_eq!(job.entry.generator.as_deref(), Some("openai://gpt-4o"));
+        assert!(job.define.is_empty());
+
+        let keys = Keys::new("nerve");
+        assert_eq!(keys.jobs(), "nerve:jobs");
+        assert_eq!(keys.run(&job.id), "nerve:runs:1");
+
+        let message: Message =
+            serde_json::from_str(r#"{"claimed":{"worker":"scanner-1:42"}}"#).unwrap();
+        assert!(matches!(message, Message::Claimed { worker } if worker == "scanner-1:42"));
+        let message: Message = serde_json::from_str(&format!(
+            r#"{{"end":{}}}"#,
+            r#"{"tasklet":"nmap.yml","status":"failed","error":"boom","summary":null}"#
+        ))
+        .unwrap();
+        assert!(
+            matches!(message, Message::End(outcome) if outcome.error.as_deref() == Some("boom"))
+        );
+        let message: Message = serde_json::from_str(r#""heartbeat""#).unwrap();
+        assert!(matches!(message, Message::Heartbeat));
+    }
+
+    #[test]
+    fn test_overdue() {
+        let claim_timeout = Duration::from_secs(300);
+        let secs = Duration::from_secs;
+
+        // waiting for a worker while the other runs keep the workers busy
+        assert_eq!(overdue(None, secs(10), secs(900), claim_timeout), None);
+        assert_eq!(
+            overdue(None, secs(301), secs(301), claim_timeout).as_deref(),
+            Some("not claimed by any worker within 300s")
+        );
+
+        // running, as long as the worker reports
+        assert_eq!(
+            overdue(Some("scanner-1:42"), secs(900), secs(10), claim_timeout),
+            None
+        );
+        assert_eq!(
+            overdue(Some("scanner-1:42"), secs(0), secs(61), claim_timeout).as_deref(),
+            Some("worker scanner-1:42 stopped reporting for 61s")
+        );
+    }
+}
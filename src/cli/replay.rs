@@ -0,0 +1,261 @@
+use std::time::Duration;
+
+use anyhow::Result;
+use colored::Colorize;
+
+use crate::{
+    agent::{
+        get_user_input, serialization,
+        state::{history::Execution, session::Session},
+    },
+    cli::{self, setup, ui},
+};
+
+fn render_execution(exec: &Execution) {
+    if exec.is_summary() {
+        println!("📝 {}", "summary of the previous steps".bold());
+        for line in exec
+            .result()
+            .map(|s| s.as_str())
+            .unwrap_or_default()
+            .lines()
+        {
+            println!("   {}", line.dimmed());
+        }
+        return;
+    }
+
+    if let Some(invocation) = exec.invocation() {
+        println!("🧠 {}", invocation.as_function_call_string().bold());
+    } else if let Some(response) = exec.response() {
+        println!("🧠 {}", "unparsed response".yellow());
+        if !response.is_empty() {
+            println!("{}", response.dimmed());
+        }
+    }
+
+    if let Some(error) = exec.error() {
+        println!("   {} {}", "ERROR".red().bold(), error);
+    } else if let Some(result) = exec.result() {
+        println!("   -> {} bytes", result.len());
+        for line in result.lines() {
+            println!("   {}", line.dimmed());
+        }
+    } else {
+        println!("   {}", "no output".dimmed());
+    }
+
+    if let Some(elapsed) = exec.elapsed() {
+        println!("   {}", format!("{:?}", elapsed).dimmed());
+    }
+
+    if let Some(tokens) = exec.counted_tokens() {
+        println!("   {}", format!("{} tokens", tokens).dimmed());
+    }
+}
+
+// group executions by the step they belong to, preserving order
+pub fn steps(session: &Session) -> Vec<(usize, Vec<&Execution>)> {
+    let mut steps: Vec<(usize, Vec<&Execution>)> = vec![];
+    for exec in session.history.iter() {
+        match steps.last_mut() {
+            // sessions saved before steps were recorded will have every execution at step 0
+            Some((step, execs)) if *step == exec.step() && exec.step() > 0 => execs.push(exec),
+            _ => steps.push((exec.step(), vec![exec])),
+        }
+    }
+    steps
+}
+
+// rebuild the model responses of the session so that they can be replayed by the mock generator
+fn to_transcript(session: &Session, serializer: &serialization::Strategy) -> Vec<String> {
+    steps(session)
+        .iter()
+        .filter(|(_, execs)| execs.iter().any(|exec| !exec.is_summary()))
+        .map(|(_, execs)| {
+            execs
+                .iter()
+                // summaries were not generated by the agent
+                .filter(|exec| !exec.is_summary())
+                .map(|exec| {
+                    if let Some(invocation) = exec.invocation() {
+                        serializer.serialize_invocation(invocation)
+                    } else {
+                        exec.response().cloned().unwrap_or_default()
+                    }
+                })
+                .collect::<Vec<String>>()
+                .join("\n")
+        })
+        .collect()
+}
+
+fn render(session: &Session, delay: Duration, interactive: bool) {
+    println!(
+        "{} {} ({})",
+        "session".bold(),
+        session.tasklet.green().bold(),
+        session.generator.dimmed()
+    );
+    println!(
+        "{}",
+        format!("{} -> {}", session.created_at, session.updated_at).dimmed()
+    );
+    println!("\n{}\n{}\n", "[PROMPT]".bold(), session.prompt.trim());
+
+    let steps = steps(session);
+    let total = steps.len();
+
+    for (idx, (step, execs)) in steps.iter().enumerate() {
+        if interactive {
+            get_user_input(&format!(
+                "{} ",
+                "press enter for the next step ...".dimmed()
+            ));
+        } else if !delay.is_zero() {
+            std::thread::sleep(delay);
+        }
+
+        println!(
+            "{}",
+            format!(
+                "[STEP {}/{}]{}",
+                idx + 1,
+                total,
+                if *step > 0 {
+                    format!(" (run step {})", step)
+                } else {
+                    "".to_string()
+                }
+            )
+            .bold()
+        );
+
+        for exec in execs {
+            render_execution(exec);
+        }
+        println!();
+    }
+
+    if session.complete {
+        let reason = session
+            .completion_reason
+            .as_deref()
+            .unwrap_or("no reason provided");
+        if session.impossible {
+            println!("{}: {}", "task is impossible".red().bold(), reason);
+        } else {
+            println!("{}: {}", "task complete".green().bold(), reason);
+        }
+    } else {
+        println!("{}", "task not complete".yellow().bold());
+    }
+
+    println!("{}", session.metrics.to_string().dimmed());
+}
+
+// re-run the recorded responses against the tasklet using the mock generator
+async fn reexecute(args: &cli::Args, session: &Session) -> Result<()> {
+    // any format works as long as the agent parses the responses with the same one
+    let serializer = args.serialization.clone().unwrap_or_default();
+    let transcript = to_transcript(session, &serializer);
+    let path = std::env::temp_dir().join(format!("nerve-replay-{}.yml", std::process::id()));
+    std::fs::write(&path, serde_yaml::to_string(&transcript)?)?;
+
+    for (name, value) in &session.variables {
+        crate::agent::task::variables::define_variable(name, value);
+    }
+
+    let mut args = args.clone();
+    args.generator = format!("mock://{}", path.display());
+    args.serialization = Some(serializer);
+
+    log::info!(
+        "re-executing {} responses against {} ...",
+        transcript.len(),
+        session.tasklet
+    );
+
+    let (mut agent, events_rx) =
+        setup::setup_agent_for_tasklet(&args, &session.tasklet, &Some(session.prompt.to_string()))
+            .await?;
+
+    // the consumer will stop once the state (holding the events channel) is dropped
+    let consumer = tokio::spawn(ui::text::consume_events(args.clone(), events_rx));
+
+    let ret = agent.run().await;
+
+    drop(agent);
+    let _ = consumer.await;
+
+    let _ = std::fs::remove_file(&path);
+
+    ret
+}
+
+pub async fn run(
+    args: &cli::Args,
+    path: &str,
+    mock: bool,
+    delay: u64,
+    interactive: bool,
+) -> Result<()> {
+    let session = Session::from_path(Session::resolve(path).unwrap_or(path.into()))?;
+
+    if mock {
+        reexecute(args, &session).await
+    } else {
+        render(&session, Duration::from_millis(delay), interactive);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent::{state::history::History, Invocation};
+
+    #[test]
+    fn test_transcript_groups_steps() {
+        let mut history = History::new();
+        history.push(
+            Execution::with_result(
+                Invocation::new("save_memory".to_string(), None, Some("a".to_string())),
+                None,
+            )
+            .with_step(1),
+        );
+        history.push(
+            Execution::with_result(
+                Invocation::new("save_memory".to_string(), None, Some("b".to_string())),
+                None,
+            )
+            .with_step(1),
+        );
+        history.push(Execution::with_unparsed_response("garbage", "nope".to_string()).with_step(2));
+
+        let session: Session = serde_json::from_value(serde_json::json!({
+            "version": 1,
+            "created_at": chrono::Local::now(),
+            "updated_at": chrono::Local::now(),
+            "tasklet": "/tmp/tasklet",
+            "generator": "mock:///tmp/x.yml",
+            "prompt": "test",
+            "complete": false,
+            "impossible": false,
+            "completion_reason": null,
+            "metrics": crate::agent::state::metrics::Metrics::default(),
+            "history": history,
+            "storages": [],
+            "variables": {},
+            "rag_data_path": null,
+        }))
+        .unwrap();
+
+        let transcript = to_transcript(&session, &serialization::Strategy::default());
+        assert_eq!(transcript.len(), 2);
+        assert!(transcript[0].contains("save_memory"));
+        assert_eq!(transcript[0].matches("save_memory").count(), 4);
+        assert_eq!(transcript[1], "garbage");
+    }
+}
@@ -0,0 +1,500 @@
+use std::{collections::BTreeMap, fmt::Display, path::PathBuf, time::Duration};
+
+use anyhow::Result;
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    agent::{
+        secrets,
+        state::{
+            metrics::{ErrorMetrics, Metrics},
+            session::Session,
+            storage::StorageType,
+        },
+    },
+    cli::{judge::Verdict, replay},
+    APP_NAME, APP_VERSION,
+};
+
+#[derive(Debug, Serialize)]
+struct ReportInvocation {
+    action: Option<String>,
+    attributes: Option<BTreeMap<String, String>>,
+    payload: Option<String>,
+    // set if the model response could not be parsed
+    response: Option<String>,
+    result: Option<String>,
+    error: Option<String>,
+    elapsed_ms: Option<u128>,
+    summary: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct ReportStep {
+    step: usize,
+    invocations: Vec<ReportInvocation>,
+}
+
+#[derive(Debug, Serialize)]
+struct ReportEntry {
+    key: String,
+    data: String,
+    complete: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ReportStorage {
+    name: String,
+    #[serde(rename = "type")]
+    type_: StorageType,
+    entries: Vec<ReportEntry>,
+}
+
+// totals of a run, printed when it ends
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Summary {
+    pub outcome: String,
+    pub steps: usize,
+    pub elapsed_ms: u128,
+    pub input_tokens: u32,
+    pub output_tokens: u32,
+    pub cost: f64,
+    // executed actions by namespace
+    pub actions: BTreeMap<String, usize>,
+    pub errors: ErrorMetrics,
+}
+
+impl Summary {
+    pub fn from_session(session: &Session) -> Self {
+        let metrics = &session.metrics;
+        Self {
+            outcome: outcome(session).to_string(),
+            steps: metrics.current_step,
+            elapsed_ms: (session.updated_at - session.created_at)
+                .to_std()
+                .unwrap_or_default()
+                .as_millis(),
+            input_tokens: metrics.usage.total_input_tokens,
+            output_tokens: metrics.usage.total_output_tokens,
+            cost: metrics.cost,
+            actions: metrics.executed_actions.clone(),
+            errors: metrics.errors.clone(),
+        }
+    }
+
+    fn errors(&self) -> String {
+        let errors = [
+            ("empty", self.errors.empty_responses),
+            ("unparsed", self.errors.unparsed_responses),
+            ("unknown", self.errors.unknown_actions),
+            ("invalid", self.errors.invalid_actions),
+            ("errored", self.errors.errored_actions),
+            ("timed out", self.errors.timedout_actions),
+        ]
+        .iter()
+        .filter(|(_, count)| *count > 0)
+        .map(|(name, count)| format!("{} {}", count, name))
+        .collect::<Vec<_>>();
+
+        if errors.is_empty() {
+            "none".to_string()
+        } else {
+            format!("{} ({})", self.errors.total(), errors.join(", "))
+        }
+    }
+}
+
+impl Display for Summary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let elapsed = Duration::from_millis(self.elapsed_ms as u64);
+        writeln!(f, "outcome: {}", self.outcome)?;
+        writeln!(f, "steps: {} in {:?}", self.steps, elapsed)?;
+        writeln!(
+            f,
+            "tokens: {} in, {} out",
+            self.input_tokens, self.output_tokens
+        )?;
+        if self.cost > 0.0 {
+            writeln!(f, "cost: ${:.4}", self.cost)?;
+        }
+        let actions: usize = self.actions.values().sum();
+        write!(f, "actions: {}", actions)?;
+        if actions > 0 {
+            let by_namespace = self
+                .actions
+                .iter()
+                .map(|(namespace, count)| format!("{} {}", namespace, count))
+                .collect::<Vec<_>>();
+            write!(f, " ({})", by_namespace.join(", "))?;
+        }
+        write!(f, "\nerrors: {}", self.errors())
+    }
+}
+
+pub fn outcome(session: &Session) -> &'static str {
+    if session.impossible {
+        "impossible"
+    } else if session.complete {
+        "complete"
+    } else {
+        "incomplete"
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct Report {
+    generated_by: String,
+    tasklet: String,
+    generator: String,
+    prompt: String,
+    started_at: DateTime<Local>,
+    ended_at: DateTime<Local>,
+    // complete, impossible or incomplete
+    outcome: String,
+    reason: Option<String>,
+    // set if the output was reviewed with --judge-output
+    verdict: Option<Verdict>,
+    summary: Summary,
+    metrics: Metrics,
+    storages: Vec<ReportStorage>,
+    steps: Vec<ReportStep>,
+}
+
+// pick a code fence that doesn't appear in the text
+fn fence(text: &str) -> String {
+    let mut longest = 0;
+    let mut current = 0;
+    for c in text.chars() {
+        if c == '`' {
+            current += 1;
+            longest = longest.max(current);
+        } else {
+            current = 0;
+        }
+    }
+    "`".repeat((longest + 1).max(3))
+}
+
+// the storages with something in them
+pub fn storages(session: &Session) -> Vec<ReportStorage> {
+    session
+        .storages
+        .iter()
+        .filter(|storage| !storage.entries.is_empty())
+        .map(|storage| ReportStorage {
+            name: storage.name.to_string(),
+            type_: storage.type_,
+            entries: storage
+                .entries
+                .iter()
+                .map(|entry| ReportEntry {
+                    key: entry.key.to_string(),
+                    data: entry.data.to_string(),
+                    complete: entry.complete,
+                })
+                .collect(),
+        })
+        .collect()
+}
+
+impl Report {
+    pub fn from_session(session: &Session) -> Self {
+        let steps = replay::steps(session)
+            .into_iter()
+            .map(|(step, execs)| ReportStep {
+                step,
+                invocations: execs
+                    .into_iter()
+                    .map(|exec| ReportInvocation {
+                        action: exec.invocation().map(|i| i.action.to_string()),
+                        attributes: exec
+                            .invocation()
+                            .and_then(|i| i.attributes.clone())
+                            .map(|attrs| attrs.into_iter().collect()),
+                        payload: exec.invocation().and_then(|i| i.payload.clone()),
+                        response: if exec.invocation().is_none() {
+                            exec.response().cloned()
+                        } else {
+                            None
+                        },
+                        result: exec.result().cloned(),
+                        error: exec.error().cloned(),
+                        elapsed_ms: exec.elapsed().map(|e| e.as_millis()),
+                        summary: exec.is_summary(),
+                    })
+                    .collect(),
+            })
+            .collect();
+
+        Self {
+            generated_by: format!("{} v{}", APP_NAME, APP_VERSION),
+            tasklet: session.tasklet.to_string(),
+            generator: session.generator.to_string(),
+            prompt: session.prompt.to_string(),
+            started_at: session.created_at,
+            ended_at: session.updated_at,
+            outcome: outcome(session).to_string(),
+            reason: session.completion_reason.clone(),
+            verdict: None,
+            summary: Summary::from_session(session),
+            metrics: session.metrics.clone(),
+            storages: storages(session),
+            steps,
+        }
+    }
+
+    pub fn with_verdict(mut self, verdict: Option<Verdict>) -> Self {
+        self.verdict = verdict;
+        self
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    pub fn to_markdown(&self) -> String {
+        let mut md = format!("# {}\n\n", self.tasklet);
+
+        md += &format!("* **Generator:** `{}`\n", self.generator);
+        md += &format!("* **Started:** {}\n", self.started_at.to_rfc2822());
+        md += &format!("* **Ended:** {}\n", self.ended_at.to_rfc2822());
+        md += &format!(
+            "* **Outcome:** {}{}\n",
+            self.outcome,
+            self.reason
+                .as_ref()
+                .map(|r| format!(" ({})", r.trim()))
+                .unwrap_or_default()
+        );
+        if let Some(verdict) = &self.verdict {
+            md += &format!(
+                "* **Verdict:** {} (`{}`)\n",
+                if verdict.pass { "pass" } else { "fail" },
+                verdict.judge
+            );
+        }
+        md += &format!("* **Metrics:** `{}`\n", self.metrics.to_string().trim());
+
+        md += &format!("\n## Task\n\n{}\n", self.prompt.trim());
+
+        if let Some(verdict) = self.verdict.as_ref().filter(|v| !v.critique.is_empty()) {
+            md += &format!("\n## Verdict\n\n{}\n", verdict.critique.trim());
+        }
+
+        md += "\n## Summary\n\n";
+        for line in self.summary.to_string().lines() {
+            md += &format!("* {}\n", line);
+        }
+
+        if !self.storages.is_empty() {
+            md += "\n## Findings\n";
+            for storage in &self.storages {
+                md += &format!("\n### {}\n\n", storage.name);
+                for entry in &storage.entries {
+                    md += &match storage.type_ {
+                        StorageType::Tagged => format!("* **{}**: {}\n", entry.key, entry.data),
+                        StorageType::Completion => format!(
+                            "* [{}] {}\n",
+                            if entry.complete { "x" } else { " " },
+                            entry.data
+                        ),
+                        StorageType::CurrentPrevious => {
+                            format!("* {}: {}\n", entry.key.trim_matches('_'), entry.data)
+                        }
+                        StorageType::Tree => format!(
+                            "{}* [{}] {} {}\n",
+                            "  ".repeat(entry.key.matches('.').count()),
+                            if entry.complete { "x" } else { " " },
+                            entry.key,
+                            entry.data
+                        ),
+                        _ => format!("* {}\n", entry.data),
+                    };
+                }
+            }
+        }
+
+        md += "\n## Steps\n";
+        for (idx, step) in self.steps.iter().enumerate() {
+            md += &format!("\n### Step {}\n", idx + 1);
+            for invocation in &step.invocations {
+                if invocation.summary {
+                    md += "\n*Summary of the previous steps:*\n";
+                } else if let Some(action) = &invocation.action {
+                    let mut parts = vec![];
+                    if let Some(payload) = &invocation.payload {
+                        parts.push(payload.to_string());
+                    }
+                    for (name, value) in invocation.attributes.iter().flatten() {
+                        parts.push(format!("{}={}", name, value));
+                    }
+                    let call = format!("{}({})", action, parts.join(", "));
+                    md += &format!("\n{}\n{}\n{}\n", fence(&call), call, fence(&call));
+                } else {
+                    let response = invocation.response.as_deref().unwrap_or_default();
+                    md += &format!(
+                        "\n*Unparsed response:*\n\n{}\n{}\n{}\n",
+                        fence(response),
+                        response,
+                        fence(response)
+                    );
+                }
+
+                if let Some(elapsed) = invocation.elapsed_ms {
+                    md += &format!("\n*Took {}ms*\n", elapsed);
+                }
+
+                if let Some(error) = &invocation.error {
+                    md += &format!("\n> **Error:** {}\n", error.trim());
+                } else if let Some(result) = &invocation.result {
+                    md += &format!(
+                        "\n{}\n{}\n{}\n",
+                        fence(result),
+                        result.trim_end(),
+                        fence(result)
+                    );
+                }
+            }
+        }
+
+        md
+    }
+
+    // save <path>.json and <path>.md, returns their paths
+    pub fn save(&self, path: &str) -> Result<(PathBuf, PathBuf)> {
+        let mut base = PathBuf::from(path);
+        if base
+            .extension()
+            .is_some_and(|ext| ext == "json" || ext == "md")
+        {
+            base.set_extension("");
+        }
+        let json_path = base.with_extension("json");
+        let md_path = base.with_extension("md");
+
+        // never export the registered secrets
+        std::fs::write(&json_path, secrets::redact_json(&self.to_json()?))
+            .map_err(|e| anyhow!("can't write {}: {}", json_path.display(), e))?;
+        std::fs::write(&md_path, secrets::redact(&self.to_markdown()))
+            .map_err(|e| anyhow!("can't write {}: {}", md_path.display(), e))?;
+
+        Ok((json_path, md_path))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent::{
+        state::history::{Execution, History},
+        Invocation,
+    };
+
+    #[test]
+    fn test_fence() {
+        assert_eq!(fence("no backticks"), "```");
+        assert_eq!(fence("a ```` b"), "`````");
+    }
+
+    #[test]
+    fn test_summary() {
+        let mut metrics = Metrics {
+            current_step: 4,
+            cost: 0.0123,
+            ..Default::default()
+        };
+        metrics.usage.total_input_tokens = 1200;
+        metrics.usage.total_output_tokens = 80;
+        metrics.executed_actions.insert("shell".to_string(), 3);
+        metrics.executed_actions.insert("task".to_string(), 1);
+        metrics.errors.errored_actions = 1;
+        metrics.errors.unparsed_responses = 2;
+
+        let started = chrono::Local::now();
+        let summary = Summary::from_session(
+            &serde_json::from_value(serde_json::json!({
+                "version": 1,
+                "created_at": started,
+                "updated_at": started + chrono::Duration::milliseconds(65500),
+                "tasklet": "/tmp/tasklet",
+                "generator": "mock:///tmp/x.yml",
+                "prompt": "find the user",
+                "complete": false,
+                "impossible": false,
+                "completion_reason": null,
+                "metrics": metrics,
+                "history": History::new(),
+                "storages": [],
+                "variables": {},
+                "rag_data_path": null,
+            }))
+            .unwrap(),
+        );
+
+        assert_eq!(
+            summary.to_string(),
+            "outcome: incomplete\nsteps: 4 in 65.5s\ntokens: 1200 in, 80 out\ncost: $0.0123\nactions: 4 (shell 3, task 1)\nerrors: 3 (2 unparsed, 1 errored)"
+        );
+    }
+
+    #[test]
+    fn test_report() {
+        let mut history = History::new();
+        history.push(
+            Execution::with_result(
+                Invocation::new("shell".to_string(), None, Some("id".to_string())),
+                Some("uid=0(root)".to_string()),
+            )
+            .with_step(1),
+        );
+        history.push(
+            Execution::with_error(
+                Invocation::new("shell".to_string(), None, Some("nope".to_string())),
+                "command not found".to_string(),
+            )
+            .with_step(1),
+        );
+        history.push(
+            Execution::with_result(
+                Invocation::new("task_complete".to_string(), None, Some("done".to_string())),
+                None,
+            )
+            .with_step(2),
+        );
+
+        let session: Session = serde_json::from_value(serde_json::json!({
+            "version": 1,
+            "created_at": chrono::Local::now(),
+            "updated_at": chrono::Local::now(),
+            "tasklet": "/tmp/tasklet",
+            "generator": "mock:///tmp/x.yml",
+            "prompt": "find the user",
+            "complete": true,
+            "impossible": false,
+            "completion_reason": "done",
+            "metrics": Metrics::default(),
+            "history": history,
+            "storages": [{
+                "name": "memories",
+                "type": "Tagged",
+                "entries": [{"key": "user", "data": "root", "complete": false}]
+            }],
+            "variables": {},
+            "rag_data_path": null,
+        }))
+        .unwrap();
+
+        let report = Report::from_session(&session);
+        assert_eq!(report.outcome, "complete");
+        assert_eq!(report.steps.len(), 2);
+        assert_eq!(report.steps[0].invocations.len(), 2);
+
+        let md = report.to_markdown();
+        assert!(md.contains("* **user**: root"));
+        assert!(md.contains("shell(id)"));
+        assert!(md.contains("> **Error:** command not found"));
+        assert!(md.contains("### Step 2"));
+        assert!(md.contains("* errors: none"));
+    }
+}
@@ -0,0 +1,963 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Mutex, Weak,
+    },
+};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    sync::{broadcast, Notify},
+};
+
+use crate::{
+    agent::{
+        events::Event,
+        scheduler::Scheduler,
+        secrets,
+        state::{session::Session, State},
+        task::variables,
+    },
+    cli::{self, report::Report, setup, stream},
+};
+
+// single page dashboard for the browsers
+const DASHBOARD: &str = include_str!("dashboard.html");
+
+// limits of the requests we accept
+const MAX_HEADERS: usize = 64;
+const MAX_REQUEST_SIZE: usize = 1024 * 1024;
+
+#[derive(Debug, PartialEq)]
+pub struct Request {
+    pub method: String,
+    pub path: String,
+    pub headers: HashMap<String, String>,
+    pub body: Vec<u8>,
+}
+
+#[derive(Debug, PartialEq)]
+enum Route {
+    Dashboard,
+    Submit,
+    List,
+    Status(String),
+    Events(String),
+    Report(String),
+    Cancel(String),
+    Sessions,
+    Session(String),
+    Preflight,
+    NotFound,
+}
+
+impl Route {
+    fn parse(method: &str, path: &str) -> Self {
+        // query strings are not used
+        let path = path.split('?').next().unwrap_or_default();
+        let parts: Vec<&str> = path.trim_matches('/').split('/').collect();
+        match (method, parts.as_slice()) {
+            ("OPTIONS", _) => Self::Preflight,
+            ("GET", [""]) => Self::Dashboard,
+            ("POST", ["runs"]) => Self::Submit,
+            ("GET", ["runs"]) => Self::List,
+            ("GET", ["runs", id]) => Self::Status(id.to_string()),
+            ("GET", ["runs", id, "events"]) => Self::Events(id.to_string()),
+            ("GET", ["runs", id, "report"]) => Self::Report(id.to_string()),
+            ("GET", ["sessions"]) => Self::Sessions,
+            ("GET", ["sessions", name]) => Self::Session(name.to_string()),
+            ("POST", ["runs", id, "cancel"]) => Self::Cancel(id.to_string()),
+            _ => Self::NotFound,
+        }
+    }
+}
+
+// compare the digests of the two strings without stopping at the first difference, so that
+// the response times don't tell how much of the token was guessed, nor its length
+fn constant_time_eq(a: &str, b: &str) -> bool {
+    sha256::digest(a)
+        .bytes()
+        .zip(sha256::digest(b).bytes())
+        .fold(0, |diff, (a, b)| diff | (a ^ b))
+        == 0
+}
+
+// the request carries the token as bearer, if any is required
+pub fn is_authorized(request: &Request, token: Option<&str>) -> bool {
+    match token {
+        Some(token) => request
+            .headers
+            .get("authorization")
+            .is_some_and(|auth| constant_time_eq(auth.trim(), &format!("Bearer {}", token))),
+        None => true,
+    }
+}
+
+// read a single http/1.1 request, returns None if the client closed the connection
+pub async fn read_request<R: AsyncRead + Unpin>(reader: &mut R) -> Result<Option<Request>> {
+    let mut buffer = vec![];
+    let mut chunk = [0u8; 4096];
+
+    loop {
+        let mut headers = [httparse::EMPTY_HEADER; MAX_HEADERS];
+        let mut parsed = httparse::Request::new(&mut headers);
+        if let httparse::Status::Complete(size) = parsed.parse(&buffer)? {
+            let headers: HashMap<String, String> = parsed
+                .headers
+                .iter()
+                .map(|h| {
+                    (
+                        h.name.to_lowercase(),
+                        String::from_utf8_lossy(h.value).to_string(),
+                    )
+                })
+                .collect();
+            let length: usize = match headers.get("content-length") {
+                Some(length) => length
+                    .trim()
+                    .parse()
+                    .map_err(|_| anyhow!("invalid content-length"))?,
+                None => 0,
+            };
+            if length > MAX_REQUEST_SIZE.saturating_sub(size) {
+                return Err(anyhow!("request too large"));
+            }
+
+            let mut request = Request {
+                method: parsed.method.unwrap_or_default().to_string(),
+                path: parsed.path.unwrap_or_default().to_string(),
+                headers,
+                body: buffer[size..].to_vec(),
+            };
+            while request.body.len() < length {
+                let wanted = (length - request.body.len()).min(chunk.len());
+                let read = reader.read(&mut chunk[..wanted]).await?;
+                if read == 0 {
+                    return Err(anyhow!("connection closed while reading the body"));
+                }
+                request.body.extend_from_slice(&chunk[..read]);
+            }
+            request.body.truncate(length);
+
+            return Ok(Some(request));
+        }
+
+        if buffer.len() > MAX_REQUEST_SIZE {
+            return Err(anyhow!("request too large"));
+        }
+
+        let read = reader.read(&mut chunk).await?;
+        if read == 0 {
+            return if buffer.is_empty() {
+                Ok(None)
+            } else {
+                Err(anyhow!("connection closed while reading the headers"))
+            };
+        }
+        buffer.extend_from_slice(&chunk[..read]);
+    }
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum Status {
+    // waiting for a free worker
+    Queued,
+    Running,
+    Completed,
+    Failed,
+    Cancelled,
+}
+
+// what clients send to start a run
+#[derive(Deserialize, Debug)]
+struct Submission {
+    tasklet: String,
+    prompt: Option<String>,
+    generator: Option<String>,
+    #[serde(default)]
+    variables: HashMap<String, String>,
+}
+
+struct Run {
+    id: String,
+    tasklet: String,
+    generator: String,
+    prompt: Option<String>,
+    created_at: chrono::DateTime<chrono::Local>,
+    status: Status,
+    error: Option<String>,
+    // without the state and metrics updates, only sent live
+    events: Vec<Event>,
+    // live events for the streaming clients, dropped when the run ends
+    updates: Option<broadcast::Sender<Event>>,
+    // stops the agent after the current step
+    stop: Option<Arc<AtomicBool>>,
+    // aborts the current step
+    cancel: Arc<Notify>,
+    // state of the agent while running, what it was at the end after
+    state: Option<Weak<tokio::sync::Mutex<State>>>,
+    session: Option<Session>,
+}
+
+impl Run {
+    fn snapshot(&self, state: &State) -> Result<Session> {
+        state.to_session(&self.tasklet, &self.generator, self.created_at)
+    }
+
+    fn summary(&self) -> Value {
+        // the completion reason, if any
+        let reason = self.events.iter().rev().find_map(|event| match event {
+            Event::TaskComplete { reason, .. } => reason.clone(),
+            _ => None,
+        });
+
+        json!({
+            "id": self.id,
+            "tasklet": self.tasklet,
+            "prompt": self.prompt,
+            "created_at": self.created_at.to_rfc3339(),
+            "status": self.status,
+            "error": self.error,
+            "reason": reason,
+            "steps": self.events.iter().filter(|e| matches!(e, Event::ActionExecuted { .. })).count(),
+        })
+    }
+}
+
+type Runs = Arc<Mutex<BTreeMap<u64, Run>>>;
+
+// settings of the server besides the options of the runs
+#[derive(Debug, Default)]
+pub struct Options {
+    pub token: Option<String>,
+    pub allow_origin: Option<String>,
+    pub workers: usize,
+    // finished runs kept in memory
+    pub keep_runs: usize,
+    // generators the submissions can pick, other than the one of the server
+    pub allowed_generators: Vec<String>,
+}
+
+struct Server {
+    args: cli::Args,
+    token: Option<String>,
+    allow_origin: Option<String>,
+    keep_runs: usize,
+    allowed_generators: Vec<String>,
+    runs: Runs,
+    next_id: AtomicU64,
+    scheduler: Scheduler,
+}
+
+impl Server {
+    fn new(args: &cli::Args, options: Options) -> Result<Self> {
+        Ok(Self {
+            args: args.clone(),
+            token: options.token,
+            allow_origin: options.allow_origin,
+            keep_runs: options.keep_runs,
+            allowed_generators: options.allowed_generators,
+            runs: Arc::new(Mutex::new(BTreeMap::new())),
+            next_id: AtomicU64::new(0),
+            scheduler: Scheduler::new(options.workers)?,
+        })
+    }
+
+    // forget the oldest finished runs beyond the ones to keep
+    fn evict(&self, runs: &mut BTreeMap<u64, Run>) {
+        let finished: Vec<u64> = runs
+            .iter()
+            .filter(|(_, run)| !matches!(run.status, Status::Queued | Status::Running))
+            .map(|(id, _)| *id)
+            .collect();
+        for id in &finished[..finished.len().saturating_sub(self.keep_runs)] {
+            runs.remove(id);
+        }
+    }
+
+    fn get_run<T>(&self, id: &str, f: impl FnOnce(&mut Run) -> T) -> Option<T> {
+        let id: u64 = id.parse().ok()?;
+        self.runs.lock().unwrap().get_mut(&id).map(f)
+    }
+
+    fn submit(&self, request: &Request) -> Result<Value> {
+        // requiring json makes browsers send a preflight request before any cross origin submission
+        if !request
+            .headers
+            .get("content-type")
+            .is_some_and(|t| t.starts_with("application/json"))
+        {
+            return Err(anyhow!("content type must be application/json"));
+        }
+
+        let submission: Submission = serde_json::from_slice(&request.body)?;
+        // the api keys of the server are sent to wherever the generator points to
+        if let Some(generator) = &submission.generator {
+            if generator != &self.args.generator && !self.allowed_generators.contains(generator) {
+                return Err(anyhow!(
+                    "generator {} is not allowed, see --allow-generator",
+                    generator
+                ));
+            }
+        }
+
+        let id = self.next_id.fetch_add(1, Ordering::SeqCst) + 1;
+        let (updates, _) = broadcast::channel(256);
+        let cancel = Arc::new(Notify::new());
+
+        let mut runs = self.runs.lock().unwrap();
+        self.evict(&mut runs);
+        runs.insert(
+            id,
+            Run {
+                id: id.to_string(),
+                tasklet: submission.tasklet.to_string(),
+                generator: submission
+                    .generator
+                    .clone()
+                    .unwrap_or(self.args.generator.to_string()),
+                prompt: submission.prompt.clone(),
+                created_at: chrono::Local::now(),
+                status: Status::Queued,
+                error: None,
+                events: vec![],
+                updates: Some(updates),
+                stop: None,
+                cancel: cancel.clone(),
+                state: None,
+                session: None,
+            },
+        );
+        drop(runs);
+
+        let mut args = self.args.clone();
+        if let Some(generator) = &submission.generator {
+            args.generator = generator.to_string();
+        }
+
+        // every worker has its own runtime so that blocking actions can't stall the api
+        let runs = self.runs.clone();
+        let handle = self.scheduler.spawn(async move {
+            match runs.lock().unwrap().get_mut(&id) {
+                // cancelled while queued
+                Some(run) if run.status == Status::Cancelled => return,
+                Some(run) => run.status = Status::Running,
+                None => return,
+            }
+
+            // the variables of the submission are only visible to its own run
+            let ret = variables::scoped(
+                submission.variables.clone(),
+                execute(args, submission, id, runs.clone(), cancel),
+            )
+            .await;
+            let (status, error) = match ret {
+                Ok(status) => (status, None),
+                Err(err) => (Status::Failed, Some(err.to_string())),
+            };
+
+            log::info!("run {} {:?}", id, status);
+
+            if let Some(run) = runs.lock().unwrap().get_mut(&id) {
+                run.status = status;
+                run.error = error;
+                run.updates = None;
+            }
+        });
+
+        let runs = self.runs.clone();
+        tokio::spawn(async move {
+            if let Err(err) = handle.join().await {
+                log::error!("run {} {}", id, err);
+                if let Some(run) = runs.lock().unwrap().get_mut(&id) {
+                    run.status = Status::Failed;
+                    run.error = Some(err.to_string());
+                    run.updates = None;
+                }
+            }
+        });
+
+        Ok(json!({ "id": id.to_string() }))
+    }
+
+    // history, storages and metrics of a run, as they are now or were at the end
+    async fn report(&self, id: &str) -> Option<Result<Report>> {
+        let (state, session) = self.get_run(id, |run| {
+            (
+                run.state.as_ref().and_then(|state| state.upgrade()),
+                run.session.clone(),
+            )
+        })?;
+
+        let session = match (session, state) {
+            (Some(session), _) => Ok(session),
+            (None, Some(state)) => {
+                let state = state.lock().await;
+                self.get_run(id, |run| run.snapshot(&state))?
+            }
+            (None, None) => Err(anyhow!("the run has no state")),
+        };
+
+        Some(session.map(|session| Report::from_session(&session)))
+    }
+
+    async fn respond_report<W: AsyncWrite + Unpin>(
+        &self,
+        stream: &mut W,
+        report: Result<Report>,
+    ) -> Result<()> {
+        // never export the registered secrets
+        match report.and_then(|report| {
+            Ok(serde_json::from_str(&secrets::redact_json(
+                &report.to_json()?,
+            ))?)
+        }) {
+            Ok(report) => self.respond(stream, 200, &report).await,
+            Err(err) => {
+                self.respond(stream, 500, &json!({"error": err.to_string()}))
+                    .await
+            }
+        }
+    }
+
+    async fn stream_events(
+        &self,
+        id: &str,
+        request: &Request,
+        stream: &mut TcpStream,
+    ) -> Result<()> {
+        // past events and subscription are taken atomically so that nothing is lost or repeated
+        let Some((past, updates)) = self.get_run(id, |run| {
+            (
+                run.events.clone(),
+                run.updates.as_ref().map(|updates| updates.subscribe()),
+            )
+        }) else {
+            return self
+                .respond(stream, 404, &json!({"error": "run not found"}))
+                .await;
+        };
+
+        if !stream::accepts_origin(request, self.allow_origin.as_deref()) {
+            return self
+                .respond(stream, 403, &json!({"error": "origin not allowed"}))
+                .await;
+        }
+
+        stream::stream_events(stream, request, &self.cors_headers(), past, updates, || {
+            self.get_run(id, |run| run.summary()).unwrap_or_default()
+        })
+        .await
+    }
+
+    fn cors_headers(&self) -> String {
+        match &self.allow_origin {
+            Some(origin) => format!(
+                "Access-Control-Allow-Origin: {}\r\nAccess-Control-Allow-Methods: GET, POST, OPTIONS\r\nAccess-Control-Allow-Headers: Authorization, Content-Type\r\n",
+                origin
+            ),
+            None => "".to_string(),
+        }
+    }
+
+    async fn respond<W: AsyncWrite + Unpin>(
+        &self,
+        stream: &mut W,
+        code: u16,
+        body: &Value,
+    ) -> Result<()> {
+        let reason = match code {
+            200 => "OK",
+            201 => "Created",
+            202 => "Accepted",
+            204 => "No Content",
+            400 => "Bad Request",
+            401 => "Unauthorized",
+            403 => "Forbidden",
+            404 => "Not Found",
+            409 => "Conflict",
+            _ => "Internal Server Error",
+        };
+        let body = if code == 204 {
+            "".to_string()
+        } else {
+            body.to_string()
+        };
+
+        let response = format!(
+            "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n{}\r\n{}",
+            code,
+            reason,
+            body.len(),
+            self.cors_headers(),
+            body
+        );
+        stream.write_all(response.as_bytes()).await?;
+        Ok(stream.flush().await?)
+    }
+
+    async fn dashboard(&self, stream: &mut TcpStream) -> Result<()> {
+        let response = format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: text/html; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            DASHBOARD.len(),
+            DASHBOARD
+        );
+        stream.write_all(response.as_bytes()).await?;
+        Ok(stream.flush().await?)
+    }
+
+    async fn handle(&self, mut stream: TcpStream) -> Result<()> {
+        let request = match read_request(&mut stream).await {
+            Ok(Some(request)) => request,
+            Ok(None) => return Ok(()),
+            Err(err) => {
+                return self
+                    .respond(&mut stream, 400, &json!({"error": err.to_string()}))
+                    .await
+            }
+        };
+
+        log::debug!("{} {}", &request.method, &request.path);
+
+        let route = Route::parse(&request.method, &request.path);
+        if route == Route::Preflight {
+            return self.respond(&mut stream, 204, &Value::Null).await;
+        }
+
+        // the page has no data, it asks for the token and sends it with the api requests
+        if route == Route::Dashboard {
+            return self.dashboard(&mut stream).await;
+        }
+
+        if !is_authorized(&request, self.token.as_deref()) {
+            return self
+                .respond(&mut stream, 401, &json!({"error": "unauthorized"}))
+                .await;
+        }
+
+        match route {
+            Route::Submit => match self.submit(&request) {
+                Ok(ret) => self.respond(&mut stream, 201, &ret).await,
+                Err(err) => {
+                    self.respond(&mut stream, 400, &json!({"error": err.to_string()}))
+                        .await
+                }
+            },
+            Route::List => {
+                let runs: Vec<Value> = self
+                    .runs
+                    .lock()
+                    .unwrap()
+                    .values()
+                    .map(|run| run.summary())
+                    .collect();
+                self.respond(&mut stream, 200, &json!({ "runs": runs }))
+                    .await
+            }
+            Route::Status(id) => {
+                let run = self.get_run(&id, |run| {
+                    let mut summary = run.summary();
+                    summary["events"] = serde_json::to_value(&run.events).unwrap_or_default();
+                    summary
+                });
+                match run {
+                    Some(run) => self.respond(&mut stream, 200, &run).await,
+                    None => {
+                        self.respond(&mut stream, 404, &json!({"error": "run not found"}))
+                            .await
+                    }
+                }
+            }
+            Route::Events(id) => self.stream_events(&id, &request, &mut stream).await,
+            Route::Report(id) => match self.report(&id).await {
+                Some(report) => self.respond_report(&mut stream, report).await,
+                None => {
+                    self.respond(&mut stream, 404, &json!({"error": "run not found"}))
+                        .await
+                }
+            },
+            Route::Sessions => {
+                let sessions: Vec<Value> = Session::list()
+                    .unwrap_or_default()
+                    .iter()
+                    .map(|(name, session)| {
+                        json!({
+                            "name": name,
+                            "tasklet": session.tasklet,
+                            "complete": session.complete,
+                            "impossible": session.impossible,
+                            "steps": session.metrics.current_step,
+                            "updated_at": session.updated_at.to_rfc3339(),
+                        })
+                    })
+                    .collect();
+                self.respond(&mut stream, 200, &json!({ "sessions": sessions }))
+                    .await
+            }
+            Route::Session(name) => {
+                // only names, never paths
+                match Session::named_path(&name)
+                    .ok()
+                    .filter(|path| path.is_file())
+                {
+                    Some(path) => {
+                        let report =
+                            Session::from_path(path).map(|session| Report::from_session(&session));
+                        self.respond_report(&mut stream, report).await
+                    }
+                    None => {
+                        self.respond(&mut stream, 404, &json!({"error": "session not found"}))
+                            .await
+                    }
+                }
+            }
+            Route::Cancel(id) => {
+                let cancelled = self.get_run(&id, |run| {
+                    if run.status == Status::Queued {
+                        // never started
+                        run.status = Status::Cancelled;
+                        run.updates = None;
+                        true
+                    } else if run.status == Status::Running {
+                        // blocking actions won't let the step be aborted, but the agent will
+                        // stop as soon as they return
+                        if let Some(stop) = &run.stop {
+                            stop.store(true, Ordering::SeqCst);
+                        }
+                        // notify_one keeps the permit if the run did not start waiting yet
+                        run.cancel.notify_one();
+                        true
+                    } else {
+                        false
+                    }
+                });
+                match cancelled {
+                    Some(true) => self.respond(&mut stream, 202, &json!({"id": id})).await,
+                    Some(false) => {
+                        self.respond(&mut stream, 409, &json!({"error": "run is not running"}))
+                            .await
+                    }
+                    None => {
+                        self.respond(&mut stream, 404, &json!({"error": "run not found"}))
+                            .await
+                    }
+                }
+            }
+            Route::Dashboard | Route::Preflight | Route::NotFound => {
+                self.respond(&mut stream, 404, &json!({"error": "not found"}))
+                    .await
+            }
+        }
+    }
+}
+
+// create the agent for a submission and run it until completion or cancellation
+async fn execute(
+    args: cli::Args,
+    submission: Submission,
+    id: u64,
+    runs: Runs,
+    cancel: Arc<Notify>,
+) -> Result<Status> {
+    let (mut agent, mut events_rx) =
+        setup::setup_agent_for_tasklet(&args, &submission.tasklet, &submission.prompt).await?;
+
+    // record the events and forward them to the streaming clients
+    let recorder_runs = runs.clone();
+    let recorder = tokio::spawn(async move {
+        while let Some(event) = events_rx.recv().await {
+            if let Some(run) = recorder_runs.lock().unwrap().get_mut(&id) {
+                if let Some(updates) = &run.updates {
+                    let _ = updates.send(event.clone());
+                }
+                if stream::is_recorded(&event) {
+                    run.events.push(event);
+                }
+            }
+        }
+    });
+
+    let stop = agent.stop_handle();
+    if let Some(run) = runs.lock().unwrap().get_mut(&id) {
+        run.stop = Some(stop.clone());
+        run.state = Some(Arc::downgrade(&agent.get_state()));
+    }
+
+    tokio::select! {
+        ret = agent.run() => ret?,
+        _ = cancel.notified() => {
+            stop.store(true, Ordering::SeqCst);
+            agent.on_interrupted().await;
+            agent.on_end().await?;
+        }
+    };
+    let cancelled = stop.load(Ordering::SeqCst);
+
+    // keep what the agent did once it's gone
+    {
+        let state = agent.get_state();
+        let state = state.lock().await;
+        if let Some(run) = runs.lock().unwrap().get_mut(&id) {
+            run.session = run.snapshot(&state).ok();
+        }
+    }
+
+    // close the events channel, the state included, and let the recorder store what's left
+    drop(agent);
+    let _ = recorder.await;
+
+    Ok(if cancelled {
+        Status::Cancelled
+    } else {
+        Status::Completed
+    })
+}
+
+// serve the http api until the process is stopped
+pub async fn serve(args: &cli::Args, address: &str, options: Options) -> Result<()> {
+    setup::check_credentials(args)?;
+    let listener = TcpListener::bind(address)
+        .await
+        .map_err(|e| anyhow!("could not listen on {}: {}", address, e))?;
+
+    log::info!(
+        "serving the http api on http://{} with {} workers ...",
+        address,
+        options.workers
+    );
+
+    let server = Arc::new(Server::new(args, options)?);
+
+    loop {
+        let (stream, peer) = listener.accept().await?;
+        let server = server.clone();
+        tokio::spawn(async move {
+            if let Err(err) = server.handle(stream).await {
+                log::debug!("error serving {}: {}", peer, err);
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+
+    use super::*;
+    use crate::agent::test_env::TestEnv;
+
+    #[test]
+    fn test_routes() {
+        assert_eq!(Route::parse("POST", "/runs"), Route::Submit);
+        assert_eq!(Route::parse("GET", "/runs/"), Route::List);
+        assert_eq!(
+            Route::parse("GET", "/runs/3"),
+            Route::Status("3".to_string())
+        );
+        assert_eq!(
+            Route::parse("GET", "/runs/3/events?x=1"),
+            Route::Events("3".to_string())
+        );
+        assert_eq!(
+            Route::parse("POST", "/runs/3/cancel"),
+            Route::Cancel("3".to_string())
+        );
+        assert_eq!(
+            Route::parse("GET", "/runs/3/report"),
+            Route::Report("3".to_string())
+        );
+        assert_eq!(Route::parse("GET", "/"), Route::Dashboard);
+        assert_eq!(Route::parse("GET", "/sessions"), Route::Sessions);
+        assert_eq!(
+            Route::parse("GET", "/sessions/scan-20241014"),
+            Route::Session("scan-20241014".to_string())
+        );
+        assert_eq!(Route::parse("DELETE", "/runs/3"), Route::NotFound);
+        assert_eq!(Route::parse("OPTIONS", "/runs"), Route::Preflight);
+    }
+
+    #[tokio::test]
+    async fn test_read_request() {
+        let raw = b"POST /runs HTTP/1.1\r\nHost: localhost\r\nContent-Type: application/json\r\nContent-Length: 17\r\n\r\n{\"tasklet\":\"t\"}\r\nextra";
+        let request = read_request(&mut &raw[..]).await.unwrap().unwrap();
+        assert_eq!(request.method, "POST");
+        assert_eq!(request.path, "/runs");
+        assert_eq!(request.headers["content-type"], "application/json");
+        assert_eq!(request.body, b"{\"tasklet\":\"t\"}\r\n");
+
+        assert_eq!(read_request(&mut &b""[..]).await.unwrap(), None);
+        assert!(read_request(&mut &b"GET /runs HTTP/1.1\r\n"[..])
+            .await
+            .is_err());
+
+        let huge = format!(
+            "POST /runs HTTP/1.1\r\nContent-Length: {}\r\n\r\n",
+            usize::MAX
+        );
+        assert!(read_request(&mut huge.as_bytes()).await.is_err());
+    }
+
+    fn test_server(generator: &str) -> Server {
+        let args = match cli::cli::Cli::try_parse_from(["nerve", "serve", "-G", generator])
+            .unwrap()
+            .command
+        {
+            cli::cli::Command::Serve { args, .. } => args,
+            command => panic!("unexpected {:?}", command),
+        };
+
+        Server::new(
+            &args,
+            Options {
+                workers: 1,
+                keep_runs: 1,
+                allowed_generators: vec!["ollama://llama3@localhost:11434".to_string()],
+                ..Default::default()
+            },
+        )
+        .unwrap()
+    }
+
+    fn submission(body: Value) -> Request {
+        Request {
+            method: "POST".to_string(),
+            path: "/runs".to_string(),
+            headers: HashMap::from([("content-type".to_string(), "application/json".to_string())]),
+            body: body.to_string().into_bytes(),
+        }
+    }
+
+    async fn wait_for(server: &Server, id: u64) -> Status {
+        for _ in 0..200 {
+            let status = server.runs.lock().unwrap()[&id].status;
+            if !matches!(status, Status::Queued | Status::Running) {
+                return status;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        }
+        panic!("run {} did not end", id);
+    }
+
+    #[tokio::test]
+    async fn test_submission_variables() {
+        let tmp = std::env::temp_dir().join(format!("nerve-serve-vars-{}", std::process::id()));
+        std::fs::create_dir_all(&tmp).unwrap();
+        std::fs::write(
+            tmp.join("task.yml"),
+            r#"
+using:
+  - task
+system_prompt: you are a test agent
+prompt: probe the target
+functions:
+  - name: Probe
+    actions:
+      - name: probe
+        description: prints the target
+        tool: echo $NERVE_TEST_SERVE_TARGET
+"#,
+        )
+        .unwrap();
+        std::fs::write(
+            tmp.join("transcript.yml"),
+            "- \"<probe></probe>\"\n- \"<task_complete>done</task_complete>\"\n",
+        )
+        .unwrap();
+
+        // set in the environment of the server, the submission must still win
+        let mut env = TestEnv::new();
+        env.set("NERVE_TEST_SERVE_TARGET", "from-env");
+
+        let server = test_server(&format!("mock://{}", tmp.join("transcript.yml").display()));
+        server
+            .submit(&submission(json!({
+                "tasklet": tmp.join("task.yml"),
+                "variables": { "NERVE_TEST_SERVE_TARGET": "from-submission" },
+            })))
+            .unwrap();
+
+        assert_eq!(wait_for(&server, 1).await, Status::Completed);
+        let results: Vec<Option<String>> = server.runs.lock().unwrap()[&1]
+            .events
+            .iter()
+            .filter_map(|event| match event {
+                Event::ActionExecuted {
+                    invocation, result, ..
+                } if invocation.action == "probe" => Some(result.clone()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(results, vec![Some("from-submission".to_string())]);
+
+        // one finished run is kept besides the new ones
+        server
+            .submit(&submission(json!({ "tasklet": tmp.join("task.yml") })))
+            .unwrap();
+        assert_eq!(wait_for(&server, 2).await, Status::Completed);
+        server
+            .submit(&submission(json!({ "tasklet": tmp.join("task.yml") })))
+            .unwrap();
+        assert_eq!(wait_for(&server, 3).await, Status::Completed);
+        assert_eq!(
+            server.runs.lock().unwrap().keys().collect::<Vec<_>>(),
+            vec![&2, &3]
+        );
+
+        std::fs::remove_dir_all(&tmp).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_allowed_generators() {
+        let server = test_server("mock:///tmp/transcript.yml");
+        assert!(server
+            .submit(&submission(json!({
+                "tasklet": "task.yml",
+                "generator": "openai://gpt-4o@attacker.example.com:443",
+            })))
+            .is_err());
+        assert!(server.runs.lock().unwrap().is_empty());
+
+        for generator in [
+            "mock:///tmp/transcript.yml",
+            "ollama://llama3@localhost:11434",
+        ] {
+            assert!(server
+                .submit(&submission(json!({
+                    "tasklet": "task.yml",
+                    "generator": generator,
+                })))
+                .is_ok());
+        }
+    }
+
+    #[test]
+    fn test_constant_time_eq() {
+        assert!(constant_time_eq("Bearer secret", "Bearer secret"));
+        assert!(!constant_time_eq("Bearer secret", "Bearer secreT"));
+        assert!(!constant_time_eq("Bearer", "Bearer secret"));
+    }
+
+    #[test]
+    fn test_is_authorized() {
+        let mut request = Request {
+            method: "GET".to_string(),
+            path: "/runs".to_string(),
+            headers: HashMap::new(),
+            body: vec![],
+        };
+        assert!(is_authorized(&request, None));
+        assert!(!is_authorized(&request, Some("secret")));
+
+        request
+            .headers
+            .insert("authorization".to_string(), "Bearer secret".to_string());
+        assert!(is_authorized(&request, Some("secret")));
+        assert!(!is_authorized(&request, Some("other")));
+    }
+}
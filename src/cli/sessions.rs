@@ -0,0 +1,148 @@
+use anyhow::Result;
+use colored::Colorize;
+
+use crate::{
+    agent::state::{session::Session, storage::StorageType},
+    cli::{cli::SessionsCommand, trace},
+    APP_NAME,
+};
+
+// padded before being colored so that columns are aligned
+fn status(session: &Session, width: usize) -> String {
+    if session.impossible {
+        format!("{:<width$}", "impossible").red().to_string()
+    } else if session.complete {
+        format!("{:<width$}", "complete").green().to_string()
+    } else {
+        format!("{:<width$}", "incomplete").yellow().to_string()
+    }
+}
+
+pub fn load(name: &str) -> Result<Session> {
+    match Session::resolve(name) {
+        Some(path) => Session::from_path(path),
+        None => Err(anyhow!("session {} not found", name)),
+    }
+}
+
+fn list() -> Result<()> {
+    let sessions = Session::list()?;
+    if sessions.is_empty() {
+        println!("no sessions saved yet");
+        return Ok(());
+    }
+
+    let width = sessions
+        .iter()
+        .map(|(name, _)| name.len())
+        .max()
+        .unwrap_or_default();
+
+    for (name, session) in &sessions {
+        println!(
+            "{}  {}  step {:<4} {}  {}",
+            format!("{:<width$}", name).bold(),
+            status(session, 10),
+            session.metrics.current_step,
+            session
+                .updated_at
+                .format("%Y-%m-%d %H:%M")
+                .to_string()
+                .dimmed(),
+            session.tasklet,
+        );
+    }
+
+    Ok(())
+}
+
+fn show(name: &str) -> Result<()> {
+    let session = load(name)?;
+
+    println!("{} {}", "session".bold(), name.green().bold());
+    println!("  tasklet:   {}", session.tasklet);
+    println!("  generator: {}", session.generator);
+    println!("  created:   {}", session.created_at);
+    println!("  updated:   {}", session.updated_at);
+    println!(
+        "  status:    {}{}",
+        status(&session, 0),
+        session
+            .completion_reason
+            .as_ref()
+            .map(|r| format!(" ({})", r.trim()))
+            .unwrap_or_default()
+    );
+    println!("  metrics:   {}", session.metrics.to_string().trim());
+    println!("  history:   {} executions", session.history.len());
+
+    println!("\n{}\n{}", "[PROMPT]".bold(), session.prompt.trim());
+
+    for storage in &session.storages {
+        if storage.entries.is_empty() {
+            continue;
+        }
+        println!("\n{}", format!("[{}]", storage.name.to_uppercase()).bold());
+        for entry in &storage.entries {
+            match storage.type_ {
+                StorageType::Tagged => println!("  {}={}", entry.key, entry.data),
+                StorageType::Completion => println!(
+                    "  [{}] {}",
+                    if entry.complete { "x" } else { " " },
+                    entry.data
+                ),
+                StorageType::CurrentPrevious => {
+                    println!("  {}: {}", entry.key.trim_matches('_'), entry.data)
+                }
+                StorageType::Tree => println!(
+                    "  {}[{}] {} {}",
+                    "  ".repeat(entry.key.matches('.').count()),
+                    if entry.complete { "x" } else { " " },
+                    entry.key,
+                    entry.data
+                ),
+                _ => println!("  {}", entry.data),
+            }
+        }
+    }
+
+    println!();
+    if !session.complete {
+        println!("resume with: {} --resume {}", APP_NAME, name);
+    }
+    println!("replay with: {} replay {}", APP_NAME, name);
+
+    Ok(())
+}
+
+fn delete(names: &[String]) -> Result<()> {
+    // check them all before deleting anything
+    let mut paths = vec![];
+    for name in names {
+        match Session::resolve(name) {
+            Some(path) => paths.push(path),
+            None => return Err(anyhow!("session {} not found", name)),
+        }
+    }
+
+    for path in paths {
+        std::fs::remove_file(&path)
+            .map_err(|e| anyhow!("can't delete {}: {}", path.display(), e))?;
+        println!("deleted {}", path.display());
+    }
+
+    Ok(())
+}
+
+pub fn run(command: &SessionsCommand) -> Result<()> {
+    match command {
+        SessionsCommand::List => list(),
+        SessionsCommand::Show { name } => show(name),
+        SessionsCommand::Delete { names } => delete(names),
+        SessionsCommand::Export {
+            name,
+            format,
+            output,
+        } => trace::export(name, *format, output.as_deref()),
+    }
+}
@@ -1,64 +1,298 @@
+use std::{collections::HashMap, io::Read, path::PathBuf};
+
 use anyhow::Result;
 use colored::Colorize;
+use duration_string::DurationString;
 
 use crate::agent::{
-    events::{self, create_channel},
-    generator::{self, history::ConversationWindow},
-    task::{robopages, tasklet::Tasklet},
+    access::ActionLists,
+    audit::AuditLog,
+    breaker::{BreakerAction, CircuitBreaker},
+    builder::AgentBuilder,
+    cassette,
+    confirm::ConfirmPolicy,
+    events,
+    generator::{self, credentials, history::ConversationWindow, pricing::TokenPrices, Sampling},
+    guardrails::{Guardrails, ModerationGuardrail},
+    pinned::PinnedOutputs,
+    policy::Policy,
+    secrets,
+    state::session::{sessions_path, Checkpoint, Session},
+    task::{
+        prompt::PromptSource,
+        robopages,
+        tasklet::{Tasklet, INPUT_STORAGE},
+        variables::define_variable,
+    },
     Agent,
 };
 
-use crate::{cli, APP_NAME, APP_VERSION};
-
-#[allow(clippy::type_complexity)]
-fn setup_models(
-    args: &cli::Args,
-) -> Result<(
-    generator::Options,
-    Box<dyn generator::Client>,
-    Box<dyn mini_rag::Embedder>,
-)> {
-    // create generator
-    let gen_options = generator::Options::parse(&args.generator, args.context_window)?;
-    let generator = generator::factory(
-        &gen_options.type_name,
-        &gen_options.host,
-        gen_options.port,
-        &gen_options.model_name,
-        gen_options.context_window,
-    )?;
-
-    // create embedder
-    let emb_options = generator::Options::parse(&args.embedder, args.context_window)?;
-    let embedder = generator::factory_embedder(
-        &emb_options.type_name,
-        &emb_options.host,
-        emb_options.port,
-        &emb_options.model_name,
-        emb_options.context_window,
-    )?;
-
-    Ok((gen_options, generator, embedder))
-}
+#[cfg(feature = "sqlite")]
+use crate::agent::state::database::Database;
+#[cfg(feature = "redis")]
+use crate::agent::state::redis::Redis;
+use crate::{cli, cli::config::Config, APP_NAME, APP_VERSION};
 
 pub async fn setup_agent(args: &cli::Args) -> Result<(Agent, events::Receiver)> {
-    // create generator and embedder
-    let (gen_options, generator, embedder) = setup_models(args)?;
+    let named_path = match &args.session {
+        Some(name) => {
+            let path = Session::named_path(name)?;
+            if path.exists() {
+                return Err(anyhow!(
+                    "session {} already exists, continue it with --resume {}",
+                    name,
+                    name
+                ));
+            }
+            Some(path)
+        }
+        None => None,
+    };
 
-    // create the conversation window
-    let conversation_window = ConversationWindow::parse(&args.window)?;
+    #[cfg(not(feature = "sqlite"))]
+    if args.database.is_some() {
+        return Err(anyhow!(
+            "nerve was built without the sqlite feature, enable it to use --database"
+        ));
+    }
+
+    // a session file, the name of a session or the id of a session recorded in the database
+    let resume_path = args.resume.as_ref().map(|name_or_path| {
+        Session::resolve(name_or_path).unwrap_or_else(|| PathBuf::from(name_or_path))
+    });
+    let resumed_from_db =
+        args.database.is_some() && resume_path.as_ref().is_some_and(|p| !p.exists());
+
+    // load the session to resume if any
+    let session = if let Some(path) = &resume_path {
+        #[cfg(feature = "sqlite")]
+        let session = if resumed_from_db {
+            Database::load_session(
+                args.database.as_ref().unwrap(),
+                args.resume.as_ref().unwrap(),
+            )
+            .await?
+        } else {
+            Session::from_path(path)?
+        };
+        #[cfg(not(feature = "sqlite"))]
+        let session = Session::from_path(path)?;
+        // restore the variables so that the user won't be asked again, secrets are not exported
+        for (name, value) in &session.variables {
+            if !secrets::is_masked(value) {
+                define_variable(name, value);
+            }
+        }
+        Some(session)
+    } else {
+        None
+    };
 
     // read and create the tasklet
     let tasklet = if let Some(t) = &args.tasklet {
-        t
+        t.to_string()
+    } else if let Some(session) = &session {
+        session.tasklet.to_string()
     } else {
         return Err(anyhow!("--tasklet/-T not specified"));
     };
 
-    let mut tasklet = Tasklet::from_path(tasklet, &args.define)?;
+    let prompt = if let Some(session) = &session {
+        Some(session.prompt.to_string())
+    } else {
+        get_user_prompt(args).await?
+    };
+
+    let mut loaded = Tasklet::from_path(&tasklet, &args.define)?;
+    if let Some(session) = &session {
+        // the input attached to the run being resumed
+        let input = session
+            .storages
+            .iter()
+            .find(|storage| storage.name == INPUT_STORAGE);
+        if let Some(input) = input.filter(|_| !loaded.has_storage(INPUT_STORAGE)) {
+            loaded.attach_input(
+                input
+                    .entries
+                    .iter()
+                    .map(|entry| (entry.key.to_string(), entry.data.to_string()))
+                    .collect(),
+            )?;
+        }
+    } else if let Some(input) = get_piped_input(args, &loaded, &prompt)? {
+        log::info!("attaching {} bytes from stdin", input.len());
+        loaded.attach_input(HashMap::from([("stdin".to_string(), input)]))?;
+    }
+
+    #[cfg_attr(not(feature = "sqlite"), allow(unused_mut))]
+    let (mut agent, mut events_rx) = setup_agent_with_tasklet(args, loaded, &prompt).await?;
+
+    if let Some(session) = &session {
+        agent.resume(session).await?;
+    }
+
+    let created_at = session
+        .as_ref()
+        .map(|s| s.created_at)
+        .unwrap_or_else(chrono::Local::now);
+    let sessions_dir = sessions_path()?;
+    let session_id = if let Some(name) = &args.session {
+        name.to_string()
+    } else if resumed_from_db {
+        args.resume.clone().unwrap()
+    } else if let Some(path) = resume_path
+        .as_ref()
+        .filter(|path| path.parent() == Some(sessions_dir.as_path()))
+    {
+        // keep the name of the session being resumed
+        path.file_stem().unwrap().to_string_lossy().to_string()
+    } else {
+        format!(
+            "{}-{}",
+            PathBuf::from(&tasklet)
+                .file_stem()
+                .map(|s| s.to_string_lossy().to_string())
+                .unwrap_or("session".to_string()),
+            created_at.format("%Y%m%d-%H%M%S")
+        )
+    };
+    // sessions saved at every step, the others are only saved if the run is interrupted
+    let saved_path = args
+        .checkpoint
+        .as_ref()
+        .map(PathBuf::from)
+        .or(named_path)
+        .or(resume_path.filter(|_| !resumed_from_db));
+    let checkpoint_path = match &saved_path {
+        Some(path) => path.to_path_buf(),
+        None => sessions_dir.join(format!("{}.json", session_id)),
+    };
+    let checkpoint = Checkpoint {
+        path: checkpoint_path,
+        tasklet: std::fs::canonicalize(&tasklet)
+            .map(|p| p.display().to_string())
+            .unwrap_or(tasklet),
+        generator: args.generator.to_string(),
+        created_at,
+    };
+
+    #[cfg(feature = "sqlite")]
+    if let Some(path) = &args.database {
+        let prompt = agent.get_state().lock().await.get_task().to_prompt()?;
+        let db = std::sync::Arc::new(
+            Database::open(
+                path,
+                &session_id,
+                &checkpoint.tasklet,
+                &checkpoint.generator,
+                &prompt,
+                created_at,
+            )
+            .await?,
+        );
+        log::info!("recording session {} to {}", session_id, path);
+        events_rx = db.record_events(events_rx);
+        agent.set_database(db);
+    }
+
+    if saved_path.is_some() {
+        agent.set_checkpoint(checkpoint);
+    } else {
+        agent.set_interrupt_checkpoint(checkpoint);
+    }
+
+    Ok((agent, events_rx))
+}
+
+// resolve the prompt from --prompt, --prompt-file or --prompt-url
+async fn get_user_prompt(args: &cli::Args) -> Result<Option<String>> {
+    let source = if let Some(prompt) = &args.prompt {
+        if prompt.trim() != "-" {
+            return Ok(Some(prompt.to_string()));
+        }
+        PromptSource::Stdin
+    } else if let Some(path) = &args.prompt_file {
+        PromptSource::File(path.to_string())
+    } else if let Some(url) = &args.prompt_url {
+        PromptSource::Url(url.to_string())
+    } else {
+        return Ok(None);
+    };
+
+    Ok(Some(source.load("").await?))
+}
+
+// what's piped to nerve, unless it's the prompt or the judge mode input
+fn get_piped_input(
+    args: &cli::Args,
+    tasklet: &Tasklet,
+    prompt: &Option<String>,
+) -> Result<Option<String>> {
+    if args.no_stdin
+        || args.judge_mode
+        || !PromptSource::stdin_is_redirected()
+        || tasklet.prompt_from_stdin(prompt)
+    {
+        return Ok(None);
+    }
+
+    let mut input = String::new();
+    std::io::stdin()
+        .read_to_string(&mut input)
+        .map_err(|e| anyhow!("could not read stdin: {e}"))?;
+
+    Ok(if input.trim().is_empty() {
+        None
+    } else {
+        Some(input.trim_end().to_string())
+    })
+}
+
+pub async fn setup_agent_for_tasklet(
+    args: &cli::Args,
+    tasklet: &str,
+    prompt: &Option<String>,
+) -> Result<(Agent, events::Receiver)> {
+    let tasklet = Tasklet::from_path(tasklet, &args.define)?;
+
+    setup_agent_with_tasklet(args, tasklet, prompt).await
+}
+
+// make sure the API keys of the generators of the run are available before starting it
+pub fn check_credentials(args: &cli::Args) -> Result<()> {
+    let generators = [&args.generator, &args.embedder]
+        .into_iter()
+        .chain(args.fallback_generator.as_ref())
+        .chain(args.moderation_generator.as_ref())
+        .chain(Some(&args.judge).filter(|_| args.judge_output))
+        // no key is needed to replay the responses of a cassette
+        .filter(|generator| !(cassette::replays_chat() && *generator == &args.generator));
+    for generator in generators {
+        let options = generator::Options::parse(generator, args.context_window)?;
+        credentials::check(&options.type_name)?;
+    }
+    Ok(())
+}
+
+pub async fn setup_agent_with_tasklet(
+    args: &cli::Args,
+    mut tasklet: Tasklet,
+    prompt: &Option<String>,
+) -> Result<(Agent, events::Receiver)> {
+    if let Some(path) = &args.cassette {
+        cassette::load(path, args.cassette_mode)?;
+    }
+    check_credentials(args)?;
+    let gen_options = generator::Options::parse(&args.generator, args.context_window)?;
+
+    // create the conversation window
+    let conversation_window = ConversationWindow::parse(&args.window)?;
+
+    let confirm_policy = ConfirmPolicy::parse(&args.confirm)?;
+
     let tasklet_name = tasklet.name.clone();
 
-    if !args.judge_mode {
+    if !args.judge_mode && !args.quiet {
         println!(
             "{} v{} 🧠 {}{} > {} ({})\n",
             APP_NAME,
@@ -78,7 +312,9 @@ pub async fn setup_agent(args: &cli::Args) -> Result<(Agent, events::Receiver)>
         );
     }
 
-    tasklet.prepare(&args.prompt)?;
+    // secret variables are registered while preparing the tasklet
+    secrets::register_from_env();
+    tasklet.prepare(prompt).await?;
 
     if let Some(server_address) = &args.robopages {
         tasklet.set_robopages(
@@ -89,22 +325,177 @@ pub async fn setup_agent(args: &cli::Args) -> Result<(Agent, events::Receiver)>
         );
     }
 
-    let task = Box::new(tasklet);
-    let (tx, rx) = create_channel();
+    // operator settings on top of the tasklet
+    let config = Config::load(args.config.as_ref(), args.profile.as_deref())?;
 
     // create the agent
-    let agent = Agent::new(
-        tx,
-        generator,
-        embedder,
-        task,
-        args.serialization.clone(),
-        conversation_window,
-        args.force_format,
-        args.user_only,
-        args.max_iterations,
-    )
-    .await?;
-
-    Ok((agent, rx))
+    let (mut agent, rx) = AgentBuilder::new()
+        .generator(&args.generator)
+        .embedder(&args.embedder)
+        .embeddings_cache(!args.no_embeddings_cache)
+        .context_window(args.context_window)
+        .task(tasklet)
+        .serializer(config.serialization(args))
+        .conversation_window(&args.window)?
+        .force_strategy(args.force_format)
+        .user_only(args.user_only)
+        .max_iterations(args.max_iterations)
+        .confirm_policy(confirm_policy)
+        .step_mode(args.step)
+        .build()
+        .await?;
+
+    agent.set_summarization(args.summarize_after, args.summarize_keep);
+    if let Some(threshold) = args.dedup_history {
+        if !(0.0..=1.0).contains(&threshold) {
+            return Err(anyhow!("--dedup-history must be between 0 and 1"));
+        }
+        agent.set_deduplication(threshold);
+    }
+    agent.set_reflection_interval(args.reflect_every);
+
+    let max_time = if let Some(max_time) = &args.max_time {
+        Some(
+            *max_time
+                .parse::<DurationString>()
+                .map_err(|e| anyhow!("invalid --max-time '{}': {}", max_time, e))?,
+        )
+    } else {
+        None
+    };
+    let token_prices = if let Some(prices) = &args.token_prices {
+        Some(TokenPrices::parse(prices)?)
+    } else {
+        TokenPrices::lookup(&gen_options.type_name, &gen_options.model_name)
+    };
+    if args.max_cost.is_some() && token_prices.is_none() {
+        return Err(anyhow!(
+            "unknown token prices for {}, specify them with --token-prices",
+            &gen_options.model_name
+        ));
+    }
+    agent
+        .set_budget(max_time, args.max_cost, token_prices)
+        .await;
+
+    if args.max_output_size > 0 {
+        if args.output_page_size == 0 {
+            return Err(anyhow!("--output-page-size can't be 0"));
+        }
+        agent
+            .get_state()
+            .lock()
+            .await
+            .set_output_paging(args.max_output_size, args.output_page_size);
+    }
+
+    // operator restrictions on top of the tasklet
+    let policy = match &args.policy {
+        Some(path) => Some(Policy::from_path(path)?),
+        None => config.policy.clone(),
+    };
+    if let Some(policy) = policy {
+        agent.get_state().lock().await.set_policy(policy);
+    }
+
+    if let Some(path) = args.audit_log.as_ref().or(config.audit_log.as_ref()) {
+        agent.set_audit_log(AuditLog::shared(path)?, &tasklet_name);
+        log::info!("recording the executed actions to the audit log {}", path);
+    }
+
+    if let Some(hooks) = config.action_hooks(&tasklet_name) {
+        agent.add_middleware(Box::new(hooks));
+    }
+
+    let mut action_lists = config.action_lists();
+    action_lists.extend(ActionLists::new(
+        args.allow_actions.clone(),
+        args.deny_actions.clone(),
+    ));
+    if !action_lists.is_empty() {
+        agent
+            .get_state()
+            .lock()
+            .await
+            .set_action_lists(action_lists);
+    }
+
+    if args.deterministic {
+        agent.set_sampling(Sampling::deterministic());
+    }
+
+    if args.candidates > 1 {
+        if args.deterministic {
+            log::warn!("--deterministic makes the generator return the same candidate every time");
+        }
+        agent.set_candidates(args.candidates);
+    }
+
+    if let Some(path) = &args.pin_outputs {
+        agent.set_pinned_outputs(PinnedOutputs::from_session(&Session::from_path(path)?));
+    }
+
+    if args.max_errors > 0 {
+        let fallback = if let Some(fallback) = &args.fallback_generator {
+            let options = generator::Options::parse(fallback, args.context_window)?;
+            Some(generator::factory(
+                &options.type_name,
+                &options.host,
+                options.port,
+                &options.model_name,
+                options.context_window,
+            )?)
+        } else if args.on_max_errors == BreakerAction::Fallback {
+            return Err(anyhow!(
+                "--on-max-errors fallback requires --fallback-generator"
+            ));
+        } else {
+            None
+        };
+
+        agent.set_circuit_breaker(
+            CircuitBreaker::new(args.max_errors, args.on_max_errors),
+            fallback,
+        );
+    }
+
+    if let Some(path) = &args.guardrails {
+        agent.add_guardrails(Guardrails::from_path(path)?);
+    }
+
+    if let Some(moderator) = &args.moderation_generator {
+        let options = generator::Options::parse(moderator, args.context_window)?;
+        let mut guardrails = Guardrails::default();
+        guardrails.add(Box::new(ModerationGuardrail::new(
+            generator::factory(
+                &options.type_name,
+                &options.host,
+                options.port,
+                &options.model_name,
+                options.context_window,
+            )?,
+            args.on_moderation,
+        )?));
+        agent.add_guardrails(guardrails);
+    }
+
+    #[cfg(feature = "redis")]
+    if let Some(url) = &args.redis {
+        let redis = Redis::new(url)?;
+        agent
+            .get_state()
+            .lock()
+            .await
+            .share_storages(std::sync::Arc::new(redis), args.shared_storages.clone())?;
+    } else if !args.shared_storages.is_empty() {
+        return Err(anyhow!("--shared-storages requires --redis"));
+    }
+    #[cfg(not(feature = "redis"))]
+    if args.redis.is_some() || !args.shared_storages.is_empty() {
+        return Err(anyhow!(
+            "nerve was built without the redis feature, enable it to use --redis"
+        ));
+    }
+
+    Ok((agent, cli::sinks::forward(&config.event_sinks, rx)))
 }
@@ -0,0 +1,45 @@
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+use tokio::sync::Notify;
+
+// wait for ctrl+c or, on unix, SIGTERM
+pub async fn signal() {
+    #[cfg(unix)]
+    {
+        let mut term = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("failed to install the SIGTERM handler");
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => {},
+            _ = term.recv() => {},
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = tokio::signal::ctrl_c().await;
+    }
+}
+
+// the first signal stops the agent once the current step is complete, the second cancels the
+// in-flight step and the third terminates the process right away
+pub fn handle(stop: Arc<AtomicBool>) -> Arc<Notify> {
+    let abort = Arc::new(Notify::new());
+    let notify = abort.clone();
+
+    tokio::spawn(async move {
+        signal().await;
+        log::warn!("interrupted, finishing the current step (press again to abort) ...");
+        stop.store(true, Ordering::SeqCst);
+
+        signal().await;
+        log::warn!("aborting the current step (press again to exit immediately) ...");
+        notify.notify_one();
+
+        signal().await;
+        std::process::exit(130);
+    });
+
+    abort
+}
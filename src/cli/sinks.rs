@@ -0,0 +1,268 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs::OpenOptions,
+    io::Write,
+    time::Duration,
+};
+
+use anyhow::Result;
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::agent::{
+    events::{create_channel, Event, Receiver},
+    secrets,
+};
+
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
+// name of an event as exposed to the sinks and the streaming clients
+pub fn event_name(event: &Value) -> String {
+    match event {
+        Value::String(name) => name.to_string(),
+        Value::Object(map) => map.keys().next().cloned().unwrap_or_default(),
+        _ => "event".to_string(),
+    }
+}
+
+// where the events are sent
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Target {
+    // append one json document per line to this file
+    Jsonl(String),
+    // POST every event as json to this url
+    Webhook(String),
+}
+
+// an events sink of the operator configuration
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct EventSink {
+    #[serde(flatten)]
+    pub target: Target,
+    // only these events, all of them if empty; "errors" matches every kind of error
+    #[serde(default)]
+    pub events: Vec<String>,
+    // never these events
+    #[serde(default)]
+    pub exclude: Vec<String>,
+    // only send one every N events of these types
+    #[serde(default)]
+    pub sample: BTreeMap<String, usize>,
+}
+
+fn is_error(event: &Event) -> bool {
+    match event {
+        Event::EmptyResponse
+        | Event::InvalidResponse(_)
+        | Event::InvalidAction { .. }
+        | Event::ActionTimeout { .. }
+        | Event::TooManyErrors(_) => true,
+        Event::ActionExecuted { error, .. } => error.is_some(),
+        _ => false,
+    }
+}
+
+fn matches(pattern: &str, name: &str, event: &Event) -> bool {
+    match pattern {
+        "errors" => is_error(event),
+        _ => pattern == name,
+    }
+}
+
+impl EventSink {
+    pub fn validate(&self) -> Result<()> {
+        if let Some((name, _)) = self.sample.iter().find(|(_, every)| **every == 0) {
+            return Err(anyhow!("the sample rate of {} can't be 0", name));
+        }
+        Ok(())
+    }
+
+    fn accepts(&self, name: &str, event: &Event) -> bool {
+        (self.events.is_empty() || self.events.iter().any(|p| matches(p, name, event)))
+            && !self.exclude.iter().any(|p| matches(p, name, event))
+    }
+}
+
+// decides which events a sink receives, keeping count of the sampled ones
+struct Filter {
+    sink: EventSink,
+    seen: HashMap<String, usize>,
+}
+
+impl Filter {
+    fn new(sink: EventSink) -> Self {
+        Self {
+            sink,
+            seen: HashMap::new(),
+        }
+    }
+
+    fn accepts(&mut self, name: &str, event: &Event) -> bool {
+        if !self.sink.accepts(name, event) {
+            return false;
+        }
+        match self.sink.sample.get(name) {
+            Some(every) => {
+                let seen = self.seen.entry(name.to_string()).or_default();
+                *seen += 1;
+                // the first one is always sent
+                (*seen - 1).is_multiple_of(*every)
+            }
+            None => true,
+        }
+    }
+}
+
+fn record(event: &Value) -> String {
+    // never export the registered secrets
+    secrets::redact_json(
+        &serde_json::json!({
+            "time": chrono::Local::now(),
+            "event": event,
+        })
+        .to_string(),
+    )
+}
+
+async fn deliver(target: Target, mut records: tokio::sync::mpsc::UnboundedReceiver<String>) {
+    match target {
+        Target::Jsonl(path) => {
+            let mut file = match OpenOptions::new().create(true).append(true).open(&path) {
+                Ok(file) => file,
+                Err(err) => {
+                    log::error!("can't open events sink {}: {}", path, err);
+                    return;
+                }
+            };
+            while let Some(record) = records.recv().await {
+                if let Err(err) = writeln!(file, "{}", record) {
+                    log::error!("can't write to events sink {}: {}", path, err);
+                    return;
+                }
+            }
+        }
+        Target::Webhook(url) => {
+            let client = reqwest::Client::new();
+            // only warn once until it works again
+            let mut failing = false;
+            while let Some(record) = records.recv().await {
+                let result = client
+                    .post(&url)
+                    .header("Content-Type", "application/json")
+                    .body(record)
+                    .timeout(WEBHOOK_TIMEOUT)
+                    .send()
+                    .await
+                    .map_err(|e| e.to_string())
+                    .and_then(|response| {
+                        if response.status().is_success() {
+                            Ok(())
+                        } else {
+                            Err(format!("returned {}", response.status()))
+                        }
+                    });
+                match result {
+                    Err(err) if !failing => {
+                        log::warn!("events webhook {}: {}", url, err);
+                        failing = true;
+                    }
+                    Err(_) => {}
+                    Ok(_) => failing = false,
+                }
+            }
+        }
+    }
+}
+
+// send the events accepted by each sink to it, and forward all of them to the consumer
+pub fn forward(sinks: &[EventSink], mut events_rx: Receiver) -> Receiver {
+    if sinks.is_empty() {
+        return events_rx;
+    }
+
+    let mut filters = vec![];
+    for sink in sinks {
+        // every sink has its own queue, a slow webhook must not hold back the others
+        let (records_tx, records_rx) = tokio::sync::mpsc::unbounded_channel();
+        tokio::spawn(deliver(sink.target.clone(), records_rx));
+        filters.push((Filter::new(sink.clone()), records_tx));
+    }
+
+    let (tx, rx) = create_channel();
+    tokio::spawn(async move {
+        while let Some(event) = events_rx.recv().await {
+            if let Ok(value) = serde_json::to_value(&event) {
+                let name = event_name(&value);
+                for (filter, records_tx) in filters.iter_mut() {
+                    if filter.accepts(&name, &event) {
+                        let _ = records_tx.send(record(&value));
+                    }
+                }
+            }
+            let _ = tx.send(event);
+        }
+    });
+
+    rx
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn accepted(sink: &str, events: &[Event]) -> Vec<String> {
+        let sink: EventSink = serde_yaml::from_str(sink).unwrap();
+        sink.validate().unwrap();
+        let mut filter = Filter::new(sink);
+        events
+            .iter()
+            .map(|event| (event_name(&serde_json::to_value(event).unwrap()), event))
+            .filter(|(name, event)| filter.accepts(name, event))
+            .map(|(name, _)| name)
+            .collect()
+    }
+
+    #[test]
+    fn test_event_name() {
+        let event = serde_json::to_value(Event::EmptyResponse).unwrap();
+        assert_eq!(event_name(&event), "EmptyResponse");
+        let event = serde_json::to_value(Event::TooManyErrors(3)).unwrap();
+        assert_eq!(event_name(&event), "TooManyErrors");
+    }
+
+    #[test]
+    fn test_filter() {
+        let events = vec![
+            Event::MetricsUpdate(Default::default()),
+            Event::EmptyResponse,
+            Event::MetricsUpdate(Default::default()),
+            Event::TooManyErrors(3),
+            Event::MetricsUpdate(Default::default()),
+            Event::TaskComplete {
+                impossible: false,
+                reason: None,
+            },
+        ];
+
+        assert_eq!(accepted("jsonl: all.jsonl", &events).len(), 6);
+        assert_eq!(
+            accepted("webhook: http://localhost/hook\nevents: [errors]", &events),
+            vec!["EmptyResponse", "TooManyErrors"]
+        );
+        assert_eq!(
+            accepted(
+                "jsonl: events.jsonl\nexclude: [errors]\nsample:\n  MetricsUpdate: 2",
+                &events
+            ),
+            vec!["MetricsUpdate", "MetricsUpdate", "TaskComplete"]
+        );
+    }
+
+    #[test]
+    fn test_validate() {
+        let sink: EventSink = serde_yaml::from_str("jsonl: x\nsample:\n  StateUpdate: 0").unwrap();
+        assert!(sink.validate().is_err());
+        assert!(serde_yaml::from_str::<EventSink>("kafka: x").is_err());
+    }
+}
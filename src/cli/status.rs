@@ -0,0 +1,124 @@
+use std::process::ExitCode;
+
+use anyhow::Result;
+
+use crate::{
+    agent::{
+        abort::{AbortReason, Aborted},
+        state::session::Session,
+    },
+    cli::{judge::Verdict, report},
+};
+
+// exit codes of the runs, so that scripts and pipelines can tell how they ended
+pub const COMPLETE: u8 = 0;
+// any other error, including the ones parsing the command line
+pub const FAILED: u8 = 1;
+pub const IMPOSSIBLE: u8 = 3;
+pub const BUDGET_EXHAUSTED: u8 = 4;
+pub const GENERATOR_FAILED: u8 = 5;
+// too many consecutive unparsable responses or failed actions
+pub const TOO_MANY_ERRORS: u8 = 6;
+// complete, but the judge model rejected the output
+pub const REJECTED: u8 = 7;
+// same as the shells for SIGINT
+pub const INTERRUPTED: u8 = 130;
+
+pub fn code(
+    ret: &Result<()>,
+    session: Option<&Session>,
+    verdict: Option<&Verdict>,
+    interrupted: bool,
+) -> u8 {
+    if interrupted {
+        return INTERRUPTED;
+    }
+
+    match ret {
+        Err(err) => match Aborted::reason_of(err) {
+            Some(AbortReason::BudgetExhausted) => BUDGET_EXHAUSTED,
+            Some(AbortReason::Generator) => GENERATOR_FAILED,
+            Some(AbortReason::TooManyErrors) => TOO_MANY_ERRORS,
+            None => FAILED,
+        },
+        Ok(_) => match session.map(report::outcome) {
+            Some("complete") if verdict.is_some_and(|v| !v.pass) => REJECTED,
+            Some("complete") => COMPLETE,
+            Some("impossible") => IMPOSSIBLE,
+            _ => FAILED,
+        },
+    }
+}
+
+// errors the agent reported already exit with their code, the others are returned to be printed
+pub fn exit(
+    ret: Result<()>,
+    session: Option<&Session>,
+    verdict: Option<&Verdict>,
+    interrupted: bool,
+) -> Result<ExitCode> {
+    let code = code(&ret, session, verdict, interrupted);
+    match ret {
+        Err(err) if code == FAILED => Err(err),
+        _ => Ok(ExitCode::from(code)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_code() {
+        let aborted = |reason| -> Result<()> { Err(Aborted::new(reason, "aborting").into()) };
+
+        assert_eq!(code(&Ok(()), None, None, true), INTERRUPTED);
+        assert_eq!(
+            code(&aborted(AbortReason::BudgetExhausted), None, None, false),
+            BUDGET_EXHAUSTED
+        );
+        assert_eq!(
+            code(&aborted(AbortReason::Generator), None, None, false),
+            GENERATOR_FAILED
+        );
+        assert_eq!(
+            code(&aborted(AbortReason::TooManyErrors), None, None, false),
+            TOO_MANY_ERRORS
+        );
+        assert_eq!(code(&Err(anyhow!("no tasklet")), None, None, false), FAILED);
+        assert_eq!(code(&Ok(()), None, None, false), FAILED);
+
+        let now = chrono::Local::now();
+        let complete: Session = serde_json::from_value(serde_json::json!({
+            "version": 1,
+            "created_at": now,
+            "updated_at": now,
+            "tasklet": "/tmp/tasklet",
+            "generator": "mock:///tmp/x.yml",
+            "prompt": "find the user",
+            "complete": true,
+            "impossible": false,
+            "completion_reason": "root",
+            "metrics": crate::agent::state::metrics::Metrics::default(),
+            "history": [],
+            "storages": [],
+            "variables": {},
+            "rag_data_path": null,
+        }))
+        .unwrap();
+        let verdict = |pass| Verdict {
+            judge: "mock://".to_string(),
+            pass,
+            critique: "".to_string(),
+        };
+        assert_eq!(code(&Ok(()), Some(&complete), None, false), COMPLETE);
+        assert_eq!(
+            code(&Ok(()), Some(&complete), Some(&verdict(true)), false),
+            COMPLETE
+        );
+        assert_eq!(
+            code(&Ok(()), Some(&complete), Some(&verdict(false)), false),
+            REJECTED
+        );
+    }
+}
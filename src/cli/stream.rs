@@ -0,0 +1,483 @@
+use std::sync::{Arc, Mutex};
+
+use anyhow::Result;
+use base64::Engine;
+use serde_json::{json, Value};
+use tokio::{
+    io::{AsyncWrite, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    sync::broadcast,
+};
+
+use super::{
+    serve::{is_authorized, read_request, Request},
+    sinks::event_name,
+};
+use crate::agent::{
+    events::{create_channel, Event, Receiver},
+    secrets,
+};
+
+// appended to the key of the client to accept a websocket connection, from rfc 6455
+const WEBSOCKET_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+fn sha1(data: &[u8]) -> [u8; 20] {
+    let mut h: [u32; 5] = [0x67452301, 0xEFCDAB89, 0x98BADCFE, 0x10325476, 0xC3D2E1F0];
+
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend((data.len() as u64 * 8).to_be_bytes());
+
+    for block in message.chunks(64) {
+        let mut w = [0u32; 80];
+        for (i, word) in block.chunks(4).enumerate() {
+            w[i] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+        }
+        for i in 16..80 {
+            w[i] = (w[i - 3] ^ w[i - 8] ^ w[i - 14] ^ w[i - 16]).rotate_left(1);
+        }
+
+        let [mut a, mut b, mut c, mut d, mut e] = h;
+        for (i, word) in w.iter().enumerate() {
+            let (f, k) = match i {
+                0..=19 => ((b & c) | (!b & d), 0x5A827999),
+                20..=39 => (b ^ c ^ d, 0x6ED9EBA1),
+                40..=59 => ((b & c) | (b & d) | (c & d), 0x8F1BBCDC),
+                _ => (b ^ c ^ d, 0xCA62C1D6),
+            };
+            let temp = a
+                .rotate_left(5)
+                .wrapping_add(f)
+                .wrapping_add(e)
+                .wrapping_add(k)
+                .wrapping_add(*word);
+            e = d;
+            d = c;
+            c = b.rotate_left(30);
+            b = a;
+            a = temp;
+        }
+
+        for (h, v) in h.iter_mut().zip([a, b, c, d, e]) {
+            *h = h.wrapping_add(v);
+        }
+    }
+
+    let mut digest = [0u8; 20];
+    for (chunk, v) in digest.chunks_mut(4).zip(h) {
+        chunk.copy_from_slice(&v.to_be_bytes());
+    }
+    digest
+}
+
+fn websocket_accept(key: &str) -> String {
+    base64::engine::general_purpose::STANDARD
+        .encode(sha1(format!("{}{}", key.trim(), WEBSOCKET_GUID).as_bytes()))
+}
+
+// an unmasked frame from the server with the given opcode
+fn websocket_frame(opcode: u8, payload: &[u8]) -> Vec<u8> {
+    let mut frame = vec![0x80 | opcode];
+    match payload.len() {
+        len if len < 126 => frame.push(len as u8),
+        len if len <= u16::MAX as usize => {
+            frame.push(126);
+            frame.extend((len as u16).to_be_bytes());
+        }
+        len => {
+            frame.push(127);
+            frame.extend((len as u64).to_be_bytes());
+        }
+    }
+    frame.extend(payload);
+    frame
+}
+
+// the state and metrics updates are only useful live, keeping them for the clients connecting
+// later would grow the buffers with every step
+pub fn is_recorded(event: &Event) -> bool {
+    !matches!(event, Event::StateUpdate(_) | Event::MetricsUpdate(_))
+}
+
+fn is_websocket(request: &Request) -> bool {
+    request
+        .headers
+        .get("upgrade")
+        .is_some_and(|upgrade| upgrade.eq_ignore_ascii_case("websocket"))
+}
+
+// websockets are not subject to the same origin policy, so browsers connecting from other
+// origins than the one allowed are rejected, server-sent events are protected by cors already
+pub fn accepts_origin(request: &Request, allow_origin: Option<&str>) -> bool {
+    if !is_websocket(request) {
+        return true;
+    }
+    match request.headers.get("origin") {
+        Some(origin) => allow_origin.is_some_and(|allowed| allowed == "*" || allowed == origin),
+        None => true,
+    }
+}
+
+// how the events are sent to a client, as server-sent events or websocket text messages
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Sink {
+    Sse,
+    WebSocket,
+}
+
+impl Sink {
+    async fn open<W: AsyncWrite + Unpin>(
+        stream: &mut W,
+        request: &Request,
+        headers: &str,
+    ) -> Result<Self> {
+        let (sink, head) = if is_websocket(request) {
+            let key = request
+                .headers
+                .get("sec-websocket-key")
+                .ok_or_else(|| anyhow!("missing sec-websocket-key"))?;
+            (
+                Sink::WebSocket,
+                format!(
+                    "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Accept: {}\r\n",
+                    websocket_accept(key)
+                ),
+            )
+        } else {
+            (
+                Sink::Sse,
+                "HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nCache-Control: no-cache\r\nConnection: close\r\n".to_string(),
+            )
+        };
+
+        stream
+            .write_all(format!("{}{}\r\n", head, headers).as_bytes())
+            .await?;
+        Ok(sink)
+    }
+
+    async fn send<W: AsyncWrite + Unpin>(
+        &self,
+        stream: &mut W,
+        name: &str,
+        data: &Value,
+    ) -> Result<()> {
+        // the events carry the prompts and the outputs of the actions
+        let raw = match self {
+            Sink::Sse => format!(
+                "event: {}\ndata: {}\n\n",
+                name,
+                secrets::redact_json(&data.to_string())
+            )
+            .into_bytes(),
+            Sink::WebSocket => websocket_frame(
+                0x1,
+                secrets::redact_json(&json!({ "event": name, "data": data }).to_string())
+                    .as_bytes(),
+            ),
+        };
+        stream.write_all(&raw).await?;
+        Ok(stream.flush().await?)
+    }
+
+    async fn close<W: AsyncWrite + Unpin>(&self, stream: &mut W) -> Result<()> {
+        if *self == Sink::WebSocket {
+            stream.write_all(&websocket_frame(0x8, &[])).await?;
+        }
+        Ok(stream.flush().await?)
+    }
+}
+
+// send the past events and the new ones until the run ends, then an end event with its summary
+pub async fn stream_events<W: AsyncWrite + Unpin>(
+    stream: &mut W,
+    request: &Request,
+    headers: &str,
+    past: Vec<Event>,
+    updates: Option<broadcast::Receiver<Event>>,
+    summary: impl FnOnce() -> Value,
+) -> Result<()> {
+    let sink = Sink::open(stream, request, headers).await?;
+
+    for event in past {
+        let data = serde_json::to_value(&event)?;
+        sink.send(stream, &event_name(&data), &data).await?;
+    }
+
+    if let Some(mut updates) = updates {
+        loop {
+            match updates.recv().await {
+                Ok(event) => {
+                    let data = serde_json::to_value(&event)?;
+                    sink.send(stream, &event_name(&data), &data).await?;
+                }
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    log::warn!("event stream skipped {} events", skipped)
+                }
+                Err(broadcast::error::RecvError::Closed) => break,
+            }
+        }
+    }
+
+    sink.send(stream, "end", &summary()).await?;
+    sink.close(stream).await
+}
+
+// events of the run so far and the channel of the new ones, closed when the run ends
+type Recorded = Arc<Mutex<(Vec<Event>, Option<broadcast::Sender<Event>>)>>;
+
+async fn handle(
+    mut stream: TcpStream,
+    recorded: Recorded,
+    allow_origin: Option<String>,
+    token: Option<String>,
+) -> Result<()> {
+    let request = match read_request(&mut stream).await? {
+        Some(request) => request,
+        None => return Ok(()),
+    };
+
+    let headers = allow_origin
+        .as_ref()
+        .map(|origin| {
+            format!(
+                "Access-Control-Allow-Origin: {}\r\nAccess-Control-Allow-Headers: Authorization\r\n",
+                origin
+            )
+        })
+        .unwrap_or_default();
+    let path = request.path.split('?').next().unwrap_or_default();
+    let status = if path.trim_end_matches('/') != "/events" {
+        "404 Not Found"
+    } else if request.method == "OPTIONS" {
+        // preflight of the browsers sending the token
+        "204 No Content"
+    } else if request.method != "GET" {
+        "404 Not Found"
+    } else if !accepts_origin(&request, allow_origin.as_deref()) {
+        "403 Forbidden"
+    } else if !is_authorized(&request, token.as_deref()) {
+        "401 Unauthorized"
+    } else {
+        let (past, updates) = {
+            let recorded = recorded.lock().unwrap();
+            (
+                recorded.0.clone(),
+                recorded.1.as_ref().map(|updates| updates.subscribe()),
+            )
+        };
+        let summary = || {
+            let actions = recorded
+                .lock()
+                .unwrap()
+                .0
+                .iter()
+                .filter(|event| matches!(event, Event::ActionExecuted { .. }))
+                .count();
+            json!({ "actions": actions })
+        };
+        return stream_events(&mut stream, &request, &headers, past, updates, summary).await;
+    };
+
+    stream
+        .write_all(
+            format!(
+                "HTTP/1.1 {}\r\nContent-Length: 0\r\nConnection: close\r\n{}\r\n",
+                status, headers
+            )
+            .as_bytes(),
+        )
+        .await?;
+    Ok(stream.flush().await?)
+}
+
+// stream the events of the run to the clients of http://address/events, returns the events for the
+// console output
+pub async fn serve_events(
+    address: &str,
+    allow_origin: Option<String>,
+    token: Option<String>,
+    mut events_rx: Receiver,
+) -> Result<Receiver> {
+    let listener = TcpListener::bind(address)
+        .await
+        .map_err(|e| anyhow!("could not listen on {}: {}", address, e))?;
+
+    log::info!("streaming events on http://{}/events ...", address);
+
+    let (updates, _) = broadcast::channel(256);
+    let recorded: Recorded = Arc::new(Mutex::new((vec![], Some(updates))));
+    let (tx, rx) = create_channel();
+
+    let forwarded = recorded.clone();
+    tokio::spawn(async move {
+        while let Some(event) = events_rx.recv().await {
+            {
+                let mut recorded = forwarded.lock().unwrap();
+                if let Some(updates) = &recorded.1 {
+                    let _ = updates.send(event.clone());
+                }
+                if is_recorded(&event) {
+                    recorded.0.push(event.clone());
+                }
+            }
+            let _ = tx.send(event);
+        }
+        // the run is over, let the clients know
+        forwarded.lock().unwrap().1 = None;
+    });
+
+    tokio::spawn(async move {
+        loop {
+            let (stream, peer) = match listener.accept().await {
+                Ok(conn) => conn,
+                Err(err) => {
+                    log::error!("events stream: {}", err);
+                    continue;
+                }
+            };
+            let recorded = recorded.clone();
+            let allow_origin = allow_origin.clone();
+            let token = token.clone();
+            tokio::spawn(async move {
+                if let Err(err) = handle(stream, recorded, allow_origin, token).await {
+                    log::debug!("error streaming events to {}: {}", peer, err);
+                }
+            });
+        }
+    });
+
+    Ok(rx)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    fn request(headers: &[(&str, &str)]) -> Request {
+        Request {
+            method: "GET".to_string(),
+            path: "/events".to_string(),
+            headers: headers
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect::<HashMap<String, String>>(),
+            body: vec![],
+        }
+    }
+
+    #[test]
+    fn test_websocket_accept() {
+        // from rfc 6455
+        assert_eq!(
+            websocket_accept("dGhlIHNhbXBsZSBub25jZQ=="),
+            "s3pPLMBiTxaQ9kYGzzhZRbK+xOo="
+        );
+        assert_eq!(
+            sha1(b"abc"),
+            [
+                0xa9, 0x99, 0x3e, 0x36, 0x47, 0x06, 0x81, 0x6a, 0xba, 0x3e, 0x25, 0x71, 0x78, 0x50,
+                0xc2, 0x6c, 0x9c, 0xd0, 0xd8, 0x9d
+            ]
+        );
+    }
+
+    #[test]
+    fn test_websocket_frame() {
+        assert_eq!(websocket_frame(0x1, b"hi"), vec![0x81, 2, b'h', b'i']);
+        let frame = websocket_frame(0x1, &[0; 300]);
+        assert_eq!(&frame[..4], &[0x81, 126, 1, 44]);
+        assert_eq!(frame.len(), 304);
+        assert_eq!(websocket_frame(0x8, &[]), vec![0x88, 0]);
+    }
+
+    #[test]
+    fn test_is_recorded() {
+        assert!(is_recorded(&Event::EmptyResponse));
+        assert!(!is_recorded(&Event::MetricsUpdate(Default::default())));
+    }
+
+    #[tokio::test]
+    async fn test_redacted() {
+        secrets::register("stream-s3cr3t-value");
+        let mut sse = vec![];
+        Sink::Sse
+            .send(
+                &mut sse,
+                "test",
+                &json!({"output": "token=stream-s3cr3t-value"}),
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(sse).unwrap(),
+            "event: test\ndata: {\"output\":\"token=********\"}\n\n"
+        );
+    }
+
+    #[test]
+    fn test_allowed_origin() {
+        let upgrade = ("upgrade", "websocket");
+        assert!(accepts_origin(&request(&[upgrade]), None));
+        assert!(accepts_origin(
+            &request(&[("origin", "https://evil.com")]),
+            None
+        ));
+        assert!(!accepts_origin(
+            &request(&[upgrade, ("origin", "https://evil.com")]),
+            None
+        ));
+        assert!(accepts_origin(
+            &request(&[upgrade, ("origin", "http://localhost:3000")]),
+            Some("http://localhost:3000")
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_stream_events() {
+        let mut sse = vec![];
+        stream_events(
+            &mut sse,
+            &request(&[]),
+            "",
+            vec![Event::EmptyResponse],
+            None,
+            || json!({"steps": 0}),
+        )
+        .await
+        .unwrap();
+        let sse = String::from_utf8(sse).unwrap();
+        assert!(sse.starts_with("HTTP/1.1 200 OK\r\nContent-Type: text/event-stream"));
+        assert!(sse.ends_with(
+            "\r\n\r\nevent: EmptyResponse\ndata: \"EmptyResponse\"\n\nevent: end\ndata: {\"steps\":0}\n\n"
+        ));
+
+        let mut ws = vec![];
+        stream_events(
+            &mut ws,
+            &request(&[
+                ("upgrade", "websocket"),
+                ("sec-websocket-key", "dGhlIHNhbXBsZSBub25jZQ=="),
+            ]),
+            "",
+            vec![Event::EmptyResponse],
+            None,
+            || json!({}),
+        )
+        .await
+        .unwrap();
+        let head = b"HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Accept: s3pPLMBiTxaQ9kYGzzhZRbK+xOo=\r\n\r\n";
+        assert_eq!(&ws[..head.len()], head);
+        let message = br#"{"data":"EmptyResponse","event":"EmptyResponse"}"#;
+        assert_eq!(
+            &ws[head.len()..head.len() + 2 + message.len()],
+            [&[0x81, message.len() as u8], &message[..]].concat()
+        );
+        assert!(ws.ends_with(&[0x88, 0]));
+    }
+}
@@ -0,0 +1,329 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Result;
+use colored::Colorize;
+use serde::Deserialize;
+
+use crate::{
+    agent::{
+        cassette,
+        generator::{history::ConversationWindow, ChatOptions},
+        state::{SharedState, State},
+        task::variables,
+    },
+    cli::{self, judge, report::Summary, setup, ui},
+};
+
+// expected outcome of a test run
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Assertions {
+    pub complete: Option<bool>,
+    pub impossible: Option<bool>,
+    // substrings that must be present in the completion reason
+    pub output_contains: Option<Vec<String>>,
+    // storage name -> key -> substring that must be present in the value, use * to match any key
+    pub storages: Option<HashMap<String, HashMap<String, String>>>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct TestCase {
+    pub name: String,
+    pub prompt: Option<String>,
+    pub variables: Option<HashMap<String, String>>,
+    // yaml list of model responses to replay instead of using the generator
+    pub transcript: Option<String>,
+    // http interactions, and the model responses if recorded, to replay
+    pub cassette: Option<String>,
+    // criteria the final output must satisfy according to the judge model
+    pub judge: Option<String>,
+    pub max_steps: Option<usize>,
+    #[serde(default, rename = "assert")]
+    pub assertions: Assertions,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct TestSuite {
+    #[serde(skip_deserializing)]
+    pub folder: PathBuf,
+    // path of the tasklet to test, by default the folder containing the tests file
+    pub tasklet: Option<String>,
+    pub tests: Vec<TestCase>,
+}
+
+impl TestSuite {
+    // accepts either a tests file or a tasklet folder containing a test.yml file
+    pub fn from_path(path: &str) -> Result<Self> {
+        let mut path = PathBuf::from(path);
+        if path.is_dir() {
+            path = path.join("test.yml");
+        }
+
+        let canon =
+            std::fs::canonicalize(&path).map_err(|e| anyhow!("could not read {:?}: {e}", path))?;
+        let yaml = std::fs::read_to_string(&canon)?;
+        let mut suite: Self = serde_yaml::from_str(&yaml)
+            .map_err(|e| anyhow!("could not parse {}: {e}", canon.display()))?;
+
+        suite.folder = canon.parent().unwrap().to_path_buf();
+
+        Ok(suite)
+    }
+
+    fn resolve(&self, path: &str) -> String {
+        let path = Path::new(path);
+        if path.is_relative() {
+            self.folder.join(path).display().to_string()
+        } else {
+            path.display().to_string()
+        }
+    }
+
+    pub fn tasklet_path(&self) -> String {
+        self.resolve(self.tasklet.as_deref().unwrap_or("."))
+    }
+}
+
+impl Assertions {
+    fn check(&self, state: &State) -> Vec<String> {
+        let mut failures = vec![];
+
+        if let Some(complete) = self.complete {
+            if state.is_complete() != complete {
+                failures.push(format!(
+                    "expected complete={} got {}",
+                    complete,
+                    state.is_complete()
+                ));
+            }
+        }
+
+        if let Some(impossible) = self.impossible {
+            if state.is_impossible() != impossible {
+                failures.push(format!(
+                    "expected impossible={} got {}",
+                    impossible,
+                    state.is_impossible()
+                ));
+            }
+        }
+
+        let output = state.get_completion_reason().cloned().unwrap_or_default();
+        for expected in self.output_contains.iter().flatten() {
+            if !output.contains(expected) {
+                failures.push(format!("output does not contain '{}'", expected));
+            }
+        }
+
+        for (storage_name, expected) in self.storages.iter().flatten() {
+            let storage = match state.get_storage(storage_name) {
+                Ok(storage) => storage,
+                Err(err) => {
+                    failures.push(err.to_string());
+                    continue;
+                }
+            };
+
+            for (key, substring) in expected {
+                let found = if key == "*" {
+                    storage.values().any(|e| e.data.contains(substring))
+                } else {
+                    storage
+                        .get(key)
+                        .map(|e| e.data.contains(substring))
+                        .unwrap_or(false)
+                };
+
+                if !found {
+                    failures.push(format!(
+                        "storage {}.{} does not contain '{}'",
+                        storage_name, key, substring
+                    ));
+                }
+            }
+        }
+
+        failures
+    }
+}
+
+async fn judge(args: &cli::Args, state: SharedState, criteria: &str) -> Result<Option<String>> {
+    let output = state
+        .lock()
+        .await
+        .get_completion_reason()
+        .cloned()
+        .unwrap_or_default();
+
+    let judge = judge::client(args)?;
+
+    let chat = ChatOptions::new(
+        Some("You are a strict evaluator. Given some criteria and the output of a task, answer only YES if the output satisfies the criteria or NO followed by a short reason otherwise.".to_string()),
+        format!("CRITERIA:\n{}\n\nOUTPUT:\n{}", criteria.trim(), output),
+        vec![],
+        vec![],
+        ConversationWindow::Full,
+    );
+
+    let response = judge.chat(state, &chat).await?;
+    let verdict = response.content.trim();
+
+    if verdict.to_uppercase().starts_with("YES") {
+        Ok(None)
+    } else {
+        Ok(Some(format!("judge: {}", verdict)))
+    }
+}
+
+// how a single run of a test went
+#[derive(Debug)]
+pub struct Trial {
+    pub failures: Vec<String>,
+    pub summary: Option<Summary>,
+}
+
+pub async fn run_test(args: &cli::Args, suite: &TestSuite, test: &TestCase) -> Result<Trial> {
+    // the variables of a test are not seen by the next ones
+    let trial = variables::scoped(
+        test.variables.clone().unwrap_or_default(),
+        run_trial(args, suite, test),
+    )
+    .await;
+    // the cassette of a test is not replayed by the next ones
+    if test.cassette.is_some() {
+        cassette::unload();
+    }
+    trial
+}
+
+async fn run_trial(args: &cli::Args, suite: &TestSuite, test: &TestCase) -> Result<Trial> {
+    let mut args = args.clone();
+
+    if let Some(transcript) = &test.transcript {
+        args.generator = format!("mock://{}", suite.resolve(transcript));
+    }
+    if let Some(path) = &test.cassette {
+        args.cassette = Some(suite.resolve(path));
+        args.cassette_mode = cassette::Mode::Replay;
+    }
+    if let Some(max_steps) = test.max_steps {
+        args.max_iterations = max_steps;
+    }
+
+    let (mut agent, events_rx) =
+        setup::setup_agent_for_tasklet(&args, &suite.tasklet_path(), &test.prompt).await?;
+
+    // the consumer will stop once the state (holding the events channel) is dropped
+    let consumer = tokio::spawn(ui::text::consume_events(args.clone(), events_rx));
+
+    let started = chrono::Local::now();
+    let result = agent.run().await;
+    let state = agent.get_state();
+    let summary = state
+        .lock()
+        .await
+        .to_session(&suite.tasklet_path(), &args.generator, started)
+        .ok()
+        .map(|session| Summary::from_session(&session));
+
+    let mut failures = vec![];
+    if let Err(err) = result {
+        // running out of steps or transcript is only a failure if completion was expected
+        if test.assertions.complete.unwrap_or(true) {
+            failures.push(format!("run failed: {}", err));
+        }
+    }
+
+    failures.extend(test.assertions.check(&*state.lock().await));
+
+    let judged = match &test.judge {
+        Some(criteria) => judge(&args, state.clone(), criteria).await,
+        None => Ok(None),
+    };
+
+    // close the events channel and let the consumer print what's left
+    drop(state);
+    drop(agent);
+    let _ = consumer.await;
+
+    if let Some(failure) = judged? {
+        failures.push(failure);
+    }
+
+    Ok(Trial { failures, summary })
+}
+
+pub async fn run(args: &cli::Args, paths: &[String]) -> Result<()> {
+    let mut total = 0;
+    let mut failed = vec![];
+
+    for path in paths {
+        let suite = TestSuite::from_path(path)?;
+
+        for test in &suite.tests {
+            total += 1;
+
+            log::info!("{} {} ...", "test".bold(), test.name);
+
+            let failures = match run_test(args, &suite, test).await {
+                Ok(trial) => trial.failures,
+                Err(err) => vec![err.to_string()],
+            };
+
+            if failures.is_empty() {
+                log::info!("{} {}", test.name, "PASS".green().bold());
+            } else {
+                log::error!("{} {}", test.name, "FAIL".red().bold());
+                for failure in &failures {
+                    log::error!("  {}", failure);
+                }
+                failed.push(test.name.to_string());
+            }
+        }
+    }
+
+    log::info!("{}/{} tests passed", total - failed.len(), total);
+
+    if failed.is_empty() {
+        Ok(())
+    } else {
+        Err(anyhow!("failed tests: {}", failed.join(", ")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_suite() {
+        let suite: TestSuite = serde_yaml::from_str(
+            r#"
+tests:
+  - name: finds the process
+    prompt: find which process is using the most RAM
+    transcript: fixtures/ram.yml
+    cassette: fixtures/ram-http.yml
+    max_steps: 5
+    assert:
+      complete: true
+      output_contains: [firefox]
+      storages:
+        memories:
+          "*": firefox
+"#,
+        )
+        .unwrap();
+
+        let test = &suite.tests[0];
+        assert_eq!(test.transcript.as_deref(), Some("fixtures/ram.yml"));
+        assert_eq!(test.cassette.as_deref(), Some("fixtures/ram-http.yml"));
+        assert_eq!(test.assertions.complete, Some(true));
+        assert_eq!(
+            test.assertions.storages.as_ref().unwrap()["memories"]["*"],
+            "firefox"
+        );
+    }
+}
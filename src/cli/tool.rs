@@ -0,0 +1,211 @@
+use std::{collections::HashMap, io::Read, sync::Arc, time::Duration};
+
+use anyhow::Result;
+
+use crate::{
+    agent::{
+        access::ActionLists,
+        events::create_channel,
+        generator,
+        namespaces::{Namespace, NAMESPACES},
+        policy::Policy,
+        state::State,
+        task::{tasklet::Tasklet, variables::parse_pre_defined_values, Task},
+    },
+    cli::{self, config::Config},
+};
+
+// only provides the namespaces of the actions to run outside of an agent
+#[derive(Debug)]
+pub struct ToolTask {
+    pub using: Vec<String>,
+}
+
+impl Task for ToolTask {
+    fn to_system_prompt(&self) -> Result<String> {
+        Ok("".to_string())
+    }
+
+    fn to_prompt(&self) -> Result<String> {
+        Ok("".to_string())
+    }
+
+    fn get_functions(&self) -> Vec<Namespace> {
+        vec![]
+    }
+
+    fn namespaces(&self) -> Option<Vec<String>> {
+        Some(self.using.clone())
+    }
+}
+
+// create a state for the task with the same operator restrictions as the agent runs
+pub async fn create_state(args: &cli::Args, task: Box<dyn Task>) -> Result<State> {
+    let options = generator::Options::parse(&args.embedder, args.context_window)?;
+    let embedder = generator::factory_embedder(
+        &options.type_name,
+        &options.host,
+        options.port,
+        &options.model_name,
+        options.context_window,
+    )?;
+
+    let (tx, mut rx) = create_channel();
+    // nobody is watching the state updates
+    tokio::spawn(async move { while rx.recv().await.is_some() {} });
+
+    let mut state = State::new(tx, task, embedder, 0, true).await?;
+
+    let config = Config::load(args.config.as_ref(), args.profile.as_deref())?;
+    let policy = match &args.policy {
+        Some(path) => Some(Policy::from_path(path)?),
+        None => config.policy.clone(),
+    };
+    if let Some(policy) = policy {
+        state.set_policy(policy);
+    }
+    let mut action_lists = config.action_lists();
+    action_lists.extend(ActionLists::new(
+        args.allow_actions.clone(),
+        args.deny_actions.clone(),
+    ));
+    if !action_lists.is_empty() {
+        state.set_action_lists(action_lists);
+    }
+
+    Ok(state)
+}
+
+// the name as given, or with the command line naming tolerated: http-request for http_request
+fn action_names(name: &str) -> Vec<String> {
+    let mut names = vec![name.to_string()];
+    if name.contains('-') {
+        names.push(name.replace('-', "_"));
+    }
+    names
+}
+
+// name of the built-in namespace defining the action, and the action name
+fn namespace_of(name: &str) -> Option<(String, String)> {
+    action_names(name).into_iter().find_map(|action| {
+        NAMESPACES
+            .iter()
+            .find(|(_, build_fn)| build_fn().actions.iter().any(|a| a.name() == action))
+            .map(|(namespace, _)| (namespace.to_string(), action))
+    })
+}
+
+fn parse_attributes(attrs: &[String]) -> Result<Option<HashMap<String, String>>> {
+    let mut attributes = HashMap::new();
+    for keyvalue in attrs {
+        let (key, value) = keyvalue
+            .split_once('=')
+            .ok_or_else(|| anyhow!("can't parse {keyvalue}, syntax is: key=value"))?;
+        attributes.insert(key.to_string(), value.to_string());
+    }
+    Ok(if attributes.is_empty() {
+        None
+    } else {
+        Some(attributes)
+    })
+}
+
+// execute a single action and print its output
+pub async fn run(
+    args: &cli::Args,
+    name: &str,
+    attrs: &[String],
+    payload: Option<String>,
+) -> Result<()> {
+    parse_pre_defined_values(&args.define)?;
+    let attributes = parse_attributes(attrs)?;
+    let payload = match payload.as_deref() {
+        Some("-") => {
+            let mut payload = String::new();
+            std::io::stdin().read_to_string(&mut payload)?;
+            Some(payload.trim_end_matches(['\r', '\n']).to_string())
+        }
+        _ => payload,
+    };
+
+    let task: Box<dyn Task> = if let Some(tasklet) = &args.tasklet {
+        // the tasklet namespaces and the tools it defines
+        let mut tasklet = Tasklet::from_path(tasklet, &args.define)?;
+        tasklet
+            .prepare(&Some(args.prompt.clone().unwrap_or_default()))
+            .await?;
+        Box::new(tasklet)
+    } else {
+        let (namespace, _) = namespace_of(name).ok_or_else(|| {
+            anyhow!(
+                "action '{}' not found, use 'nerve namespaces' to list the available ones",
+                name
+            )
+        })?;
+        // only resolve the variables of the namespace being used
+        Box::new(ToolTask {
+            using: vec![namespace],
+        })
+    };
+
+    let state = create_state(args, task).await?;
+    let action = action_names(name)
+        .iter()
+        .find_map(|name| state.get_action(name))
+        .ok_or_else(|| anyhow!("action '{}' not found or not allowed", name))?;
+    let name = action.name().to_string();
+    if action.example_payload().is_some() && payload.is_none() {
+        return Err(anyhow!("action '{}' requires --payload", name));
+    }
+
+    let timeout = state
+        .get_task()
+        .get_action_timeout(&name)
+        .or(action.timeout())
+        // one month, like the agent
+        .unwrap_or(Duration::from_secs(60 * 60 * 24 * 30));
+
+    log::debug!(
+        "running {} with attributes={:?} payload={:?}",
+        name,
+        &attributes,
+        &payload
+    );
+
+    let state = Arc::new(tokio::sync::Mutex::new(state));
+    let output = tokio::time::timeout(timeout, action.run(state, attributes, payload))
+        .await
+        .map_err(|_| anyhow!("action '{}' timed out after {:?}", name, timeout))??;
+
+    if let Some(output) = output {
+        println!("{}", output);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve() {
+        assert_eq!(
+            namespace_of("http-request"),
+            Some(("http".to_string(), "http_request".to_string()))
+        );
+        assert_eq!(
+            namespace_of("read_file"),
+            Some(("filesystem".to_string(), "read_file".to_string()))
+        );
+        assert_eq!(namespace_of("teleport"), None);
+
+        let attributes = parse_attributes(&["method=GET".to_string(), "a=b=c".to_string()])
+            .unwrap()
+            .unwrap();
+        assert_eq!(attributes.get("method").unwrap(), "GET");
+        assert_eq!(attributes.get("a").unwrap(), "b=c");
+        assert_eq!(parse_attributes(&[]).unwrap(), None);
+        assert!(parse_attributes(&["method".to_string()]).is_err());
+    }
+}
@@ -0,0 +1,525 @@
+use std::{collections::BTreeMap, time::Duration};
+
+use anyhow::Result;
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde_json::{json, Map, Value};
+
+use crate::{
+    agent::{
+        state::{history::Execution, session::Session},
+        Invocation,
+    },
+    cli::sessions,
+    APP_NAME, APP_VERSION,
+};
+
+// the trace formats a session can be exported to
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+pub enum Format {
+    // an Inspect eval log with the session as its only sample
+    Inspect,
+    // a LangSmith batch of runs, a chain for the session with the llm and tool runs of its steps
+    Langsmith,
+}
+
+// an execution of the history with its place in time, sessions only record how long the actions
+// took so the timeline is rebuilt from the creation of the session
+struct Step<'a> {
+    number: usize,
+    started_at: DateTime<Utc>,
+    ended_at: DateTime<Utc>,
+    execution: &'a Execution,
+}
+
+fn steps(session: &Session) -> Vec<Step<'_>> {
+    let mut time = session.created_at.with_timezone(&Utc);
+    session
+        .history
+        .iter()
+        .filter(|exec| !exec.is_summary())
+        .enumerate()
+        .map(|(idx, execution)| {
+            let started_at = time;
+            time += execution.elapsed().unwrap_or(Duration::ZERO);
+            Step {
+                number: idx + 1,
+                started_at,
+                ended_at: time,
+                execution,
+            }
+        })
+        .collect()
+}
+
+fn timestamp(time: &DateTime<Utc>) -> String {
+    time.to_rfc3339_opts(SecondsFormat::Micros, true)
+}
+
+// openai://gpt-4o@host:port -> openai/gpt-4o
+fn model_name(generator: &str) -> String {
+    let (provider, model) = generator.split_once("://").unwrap_or(("", generator));
+    let model = model.split('@').next().unwrap_or(model);
+    if provider.is_empty() {
+        model.to_string()
+    } else {
+        format!("{}/{}", provider, model)
+    }
+}
+
+// stable identifiers, so that exporting the same session twice doesn't duplicate its runs
+fn uuid(seed: &str) -> String {
+    let hash = sha256::digest(seed);
+    format!(
+        "{}-{}-5{}-8{}-{}",
+        &hash[0..8],
+        &hash[8..12],
+        &hash[13..16],
+        &hash[17..20],
+        &hash[20..32]
+    )
+}
+
+fn arguments(invocation: &Invocation) -> Value {
+    let mut arguments = Map::new();
+    if let Some(attributes) = &invocation.attributes {
+        let attributes: BTreeMap<_, _> = attributes.iter().collect();
+        for (name, value) in attributes {
+            arguments.insert(name.to_string(), json!(value));
+        }
+    }
+    if let Some(payload) = &invocation.payload {
+        arguments.insert("payload".to_string(), json!(payload));
+    }
+    Value::Object(arguments)
+}
+
+fn storages(session: &Session) -> Value {
+    let mut storages = Map::new();
+    for storage in &session.storages {
+        if storage.entries.is_empty() {
+            continue;
+        }
+        let entries: BTreeMap<_, _> = storage
+            .entries
+            .iter()
+            .map(|entry| (entry.key.to_string(), entry.data.to_string()))
+            .collect();
+        storages.insert(storage.name.to_string(), json!(entries));
+    }
+    Value::Object(storages)
+}
+
+fn usage(session: &Session) -> Value {
+    let usage = &session.metrics.usage;
+    json!({
+        "input_tokens": usage.total_input_tokens,
+        "output_tokens": usage.total_output_tokens,
+        "total_tokens": usage.total_input_tokens + usage.total_output_tokens,
+    })
+}
+
+fn score(session: &Session) -> (&'static str, &'static str) {
+    if session.complete {
+        ("C", "the task was completed")
+    } else if session.impossible {
+        ("I", "the task was flagged as impossible")
+    } else {
+        ("I", "the run was interrupted before completing the task")
+    }
+}
+
+fn inspect_messages(session: &Session, steps: &[Step]) -> Vec<Value> {
+    let mut messages = vec![json!({"role": "user", "content": session.prompt})];
+    for step in steps {
+        let exec = step.execution;
+        if let Some(invocation) = exec.invocation() {
+            let id = format!("call_{}", step.number);
+            messages.push(json!({
+                "role": "assistant",
+                "content": "",
+                "source": "generate",
+                "tool_calls": [{
+                    "id": id,
+                    "function": invocation.action,
+                    "arguments": arguments(invocation),
+                    "type": "function",
+                }],
+            }));
+            let mut message = json!({
+                "role": "tool",
+                "content": exec.result().cloned().unwrap_or_default(),
+                "tool_call_id": id,
+                "function": invocation.action,
+            });
+            if let Some(error) = exec.error() {
+                message["error"] = json!({"type": "unknown", "message": error});
+            }
+            messages.push(message);
+        } else {
+            // a response that couldn't be parsed, and the feedback the model got for it
+            messages.push(json!({
+                "role": "assistant",
+                "content": exec.response().cloned().unwrap_or_default(),
+                "source": "generate",
+            }));
+            if let Some(error) = exec.error() {
+                messages.push(json!({"role": "user", "content": format!("ERROR: {}", error)}));
+            }
+        }
+    }
+    messages
+}
+
+pub fn to_inspect(name: &str, session: &Session) -> Value {
+    let steps = steps(session);
+    let model = model_name(&session.generator);
+    let (value, explanation) = score(session);
+    let started_at = session.created_at.with_timezone(&Utc);
+    let completed_at = session.updated_at.with_timezone(&Utc);
+    let answer = session
+        .completion_reason
+        .as_deref()
+        .unwrap_or_default()
+        .trim();
+
+    json!({
+        "version": 2,
+        "status": if session.complete || session.impossible { "success" } else { "cancelled" },
+        "eval": {
+            "run_id": uuid(&format!("{}:run", name)),
+            "created": timestamp(&started_at),
+            "task": session.tasklet,
+            "task_id": uuid(&format!("{}:task", name)),
+            "task_version": 0,
+            "task_args": {},
+            "dataset": {"name": name, "samples": 1, "sample_ids": [1], "shuffled": false},
+            "model": model,
+            "config": {},
+            "packages": {APP_NAME: APP_VERSION},
+            "metadata": {"session": name, "generator": session.generator},
+        },
+        "plan": {
+            "name": "plan",
+            "steps": [{"solver": APP_NAME, "params": {}}],
+            "config": {},
+        },
+        "results": {
+            "total_samples": 1,
+            "completed_samples": 1,
+            "scores": [{
+                "name": "complete",
+                "scorer": "complete",
+                "params": {},
+                "metrics": {
+                    "accuracy": {
+                        "name": "accuracy",
+                        "value": if value == "C" { 1.0 } else { 0.0 },
+                        "params": {},
+                    },
+                },
+            }],
+        },
+        "stats": {
+            "started_at": timestamp(&started_at),
+            "completed_at": timestamp(&completed_at),
+            "model_usage": {model.to_string(): usage(session)},
+        },
+        "samples": [{
+            "id": 1,
+            "epoch": 1,
+            "input": session.prompt,
+            "target": "",
+            "messages": inspect_messages(session, &steps),
+            "output": {
+                "model": model,
+                "choices": [{
+                    "message": {"role": "assistant", "content": answer, "source": "generate"},
+                    "stop_reason": "stop",
+                }],
+                "usage": usage(session),
+            },
+            "scores": {
+                "complete": {"value": value, "answer": answer, "explanation": explanation},
+            },
+            "metadata": {
+                "steps": session.metrics.current_step,
+                "cost": session.metrics.cost,
+            },
+            "store": storages(session),
+        }],
+    })
+}
+
+// runs are ordered by the start time and id of each of their ancestors
+fn dotted_order(parent: Option<&str>, time: &DateTime<Utc>, id: &str) -> String {
+    let own = format!("{}{}", time.format("%Y%m%dT%H%M%S%6fZ"), id);
+    match parent {
+        Some(parent) => format!("{}.{}", parent, own),
+        None => own,
+    }
+}
+
+pub fn to_langsmith(name: &str, session: &Session) -> Value {
+    let steps = steps(session);
+    let model = model_name(&session.generator);
+    let (provider, model_name) = model.split_once('/').unwrap_or(("", &model));
+    let started_at = session.created_at.with_timezone(&Utc);
+
+    let trace_id = uuid(&format!("{}:trace", name));
+    let root_order = dotted_order(None, &started_at, &trace_id);
+
+    let mut root = json!({
+        "id": trace_id,
+        "trace_id": trace_id,
+        "dotted_order": root_order,
+        "name": session.tasklet,
+        "run_type": "chain",
+        "start_time": timestamp(&started_at),
+        "end_time": timestamp(&session.updated_at.with_timezone(&Utc)),
+        "inputs": {"prompt": session.prompt},
+        "outputs": {
+            "complete": session.complete,
+            "impossible": session.impossible,
+            "reason": session.completion_reason.as_deref().map(str::trim),
+            "storages": storages(session),
+        },
+        "extra": {
+            "metadata": {
+                "session": name,
+                "generator": session.generator,
+                "steps": session.metrics.current_step,
+                "cost": session.metrics.cost,
+                "usage_metadata": usage(session),
+            },
+        },
+        "tags": [APP_NAME],
+    });
+    if !session.complete && !session.impossible {
+        root["error"] = json!(score(session).1);
+    }
+
+    let mut runs = vec![root];
+    for step in &steps {
+        let exec = step.execution;
+        let llm_id = uuid(&format!("{}:llm:{}", name, step.number));
+        let mut message = json!({"role": "assistant", "content": ""});
+        if let Some(invocation) = exec.invocation() {
+            message["tool_calls"] = json!([{
+                "id": format!("call_{}", step.number),
+                "type": "function",
+                "function": {
+                    "name": invocation.action,
+                    "arguments": arguments(invocation).to_string(),
+                },
+            }]);
+        } else {
+            message["content"] = json!(exec.response().cloned().unwrap_or_default());
+        }
+
+        let mut llm = json!({
+            "id": llm_id,
+            "trace_id": trace_id,
+            "parent_run_id": trace_id,
+            "dotted_order": dotted_order(Some(&root_order), &step.started_at, &llm_id),
+            "name": model,
+            "run_type": "llm",
+            "start_time": timestamp(&step.started_at),
+            "end_time": timestamp(&step.started_at),
+            "inputs": {"step": step.number},
+            "outputs": {"choices": [{"message": message}]},
+            "extra": {"metadata": {"ls_provider": provider, "ls_model_name": model_name}},
+        });
+        if exec.invocation().is_none() {
+            llm["error"] = json!(exec.error().cloned().unwrap_or_default());
+        }
+        runs.push(llm);
+
+        if let Some(invocation) = exec.invocation() {
+            let tool_id = uuid(&format!("{}:tool:{}", name, step.number));
+            let mut tool = json!({
+                "id": tool_id,
+                "trace_id": trace_id,
+                "parent_run_id": trace_id,
+                "dotted_order": dotted_order(Some(&root_order), &step.started_at, &tool_id),
+                "name": invocation.action,
+                "run_type": "tool",
+                "start_time": timestamp(&step.started_at),
+                "end_time": timestamp(&step.ended_at),
+                "inputs": arguments(invocation),
+                "outputs": {"output": exec.result()},
+            });
+            if let Some(error) = exec.error() {
+                tool["error"] = json!(error);
+            }
+            runs.push(tool);
+        }
+    }
+
+    // the body of a POST to the /runs/batch endpoint
+    json!({ "post": runs })
+}
+
+pub fn export(name: &str, format: Format, output: Option<&str>) -> Result<()> {
+    let session = sessions::load(name)?;
+    let trace = match format {
+        Format::Inspect => to_inspect(name, &session),
+        Format::Langsmith => to_langsmith(name, &session),
+    };
+    let data = serde_json::to_string_pretty(&trace)?;
+
+    match output {
+        Some(path) => {
+            std::fs::write(path, data).map_err(|e| anyhow!("can't write {}: {}", path, e))?;
+            log::info!("session {} exported to {}", name, path);
+        }
+        None => println!("{}", data),
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    fn session() -> Session {
+        let mut session: Session = serde_json::from_value(json!({
+            "version": 1,
+            "created_at": "2024-10-01T10:00:00+00:00",
+            "updated_at": "2024-10-01T10:01:00+00:00",
+            "tasklet": "recon",
+            "generator": "ollama://llama3@localhost:11434",
+            "prompt": "scan the target",
+            "complete": true,
+            "impossible": false,
+            "completion_reason": "found two open ports\n",
+            "metrics": {
+                "max_steps": 0,
+                "current_step": 2,
+                "valid_responses": 2,
+                "valid_actions": 2,
+                "success_actions": 1,
+                "errors": {
+                    "empty_responses": 0,
+                    "unparsed_responses": 0,
+                    "unknown_actions": 0,
+                    "invalid_actions": 0,
+                    "errored_actions": 1,
+                    "timedout_actions": 0,
+                },
+                "usage": {
+                    "last_input_tokens": 0,
+                    "last_output_tokens": 0,
+                    "total_input_tokens": 100,
+                    "total_output_tokens": 20,
+                },
+            },
+            "history": [],
+            "storages": [],
+            "variables": {},
+            "rag_data_path": null,
+        }))
+        .unwrap();
+
+        let mut attributes = HashMap::new();
+        attributes.insert("timeout".to_string(), "10".to_string());
+        let mut scan = Execution::with_result(
+            Invocation::new(
+                "shell".to_string(),
+                Some(attributes),
+                Some("nmap target".to_string()),
+            ),
+            Some("22/tcp open".to_string()),
+        );
+        scan.set_elapsed(Duration::from_secs(5));
+        session.history.push(scan);
+        session.history.push(Execution::with_error(
+            Invocation::new(
+                "read_file".to_string(),
+                None,
+                Some("/etc/ports".to_string()),
+            ),
+            "not found".to_string(),
+        ));
+        session
+    }
+
+    #[test]
+    fn test_model_name() {
+        assert_eq!(model_name("openai://gpt-4o"), "openai/gpt-4o");
+        assert_eq!(
+            model_name("ollama://llama3@localhost:11434"),
+            "ollama/llama3"
+        );
+        assert_eq!(model_name("gpt-4o"), "gpt-4o");
+    }
+
+    #[test]
+    fn test_to_inspect() {
+        let log = to_inspect("recon-1", &session());
+        assert_eq!(log["status"], "success");
+        assert_eq!(log["eval"]["model"], "ollama/llama3");
+        assert_eq!(
+            log["stats"]["model_usage"]["ollama/llama3"]["total_tokens"],
+            120
+        );
+
+        let sample = &log["samples"][0];
+        assert_eq!(sample["scores"]["complete"]["value"], "C");
+        assert_eq!(
+            sample["output"]["choices"][0]["message"]["content"],
+            "found two open ports"
+        );
+
+        let messages = sample["messages"].as_array().unwrap();
+        assert_eq!(messages.len(), 5);
+        assert_eq!(messages[0]["content"], "scan the target");
+        assert_eq!(messages[1]["tool_calls"][0]["function"], "shell");
+        assert_eq!(
+            messages[1]["tool_calls"][0]["arguments"],
+            json!({"timeout": "10", "payload": "nmap target"})
+        );
+        assert_eq!(messages[2]["tool_call_id"], "call_1");
+        assert_eq!(messages[2]["content"], "22/tcp open");
+        assert_eq!(messages[4]["error"]["message"], "not found");
+    }
+
+    #[test]
+    fn test_to_langsmith() {
+        let batch = to_langsmith("recon-1", &session());
+        let runs = batch["post"].as_array().unwrap();
+        assert_eq!(runs.len(), 5);
+
+        let root = &runs[0];
+        let trace_id = root["id"].as_str().unwrap();
+        assert_eq!(root["run_type"], "chain");
+        assert!(root.get("error").is_none());
+        assert_eq!(
+            root["dotted_order"],
+            format!("20241001T100000000000Z{}", trace_id)
+        );
+        // the same session always gets the same ids
+        assert_eq!(
+            to_langsmith("recon-1", &session())["post"][0]["id"],
+            trace_id
+        );
+
+        assert_eq!(runs[1]["run_type"], "llm");
+        assert_eq!(runs[1]["extra"]["metadata"]["ls_provider"], "ollama");
+        assert_eq!(runs[2]["run_type"], "tool");
+        assert_eq!(runs[2]["outputs"]["output"], "22/tcp open");
+        assert_eq!(runs[2]["end_time"], "2024-10-01T10:00:05.000000Z");
+        // the second step starts when the first action ended
+        assert_eq!(runs[3]["start_time"], "2024-10-01T10:00:05.000000Z");
+        assert_eq!(runs[4]["error"], "not found");
+        for run in &runs[1..] {
+            assert_eq!(run["parent_run_id"], trace_id);
+            assert!(run["dotted_order"]
+                .as_str()
+                .unwrap()
+                .starts_with(&format!("{}.", root["dotted_order"].as_str().unwrap())));
+        }
+    }
+}
@@ -7,7 +7,7 @@ use crate::{
         events::{Event, Receiver},
         Invocation,
     },
-    cli,
+    cli::{self, logging::TRANSCRIPT},
 };
 
 fn on_action_executed(
@@ -50,139 +50,215 @@ fn on_action_executed(
     view.push(')');
 
     if let Some(err) = error {
-        log::error!("{}: {}", view, err);
+        log::error!(target: TRANSCRIPT, "{}: {}", view, err);
     } else if let Some(res) = result {
-        log::info!(
-            "{} -> {} bytes in {:?}",
-            view,
-            res.as_bytes().len(),
-            elapsed
-        );
+        log::info!(target: TRANSCRIPT, "{} -> {} bytes in {:?}", view, res.len(), elapsed);
     } else {
-        log::info!("{} {} in {:?}", view, "no output".dimmed(), elapsed);
+        log::info!(target: TRANSCRIPT, "{} {} in {:?}", view, "no output".dimmed(), elapsed);
     }
 }
 
-pub async fn consume_events(args: cli::Args, mut events_rx: Receiver) {
-    while let Some(event) = events_rx.recv().await {
-        match event {
-            Event::MetricsUpdate(metrics) => {
-                log::info!("{}", metrics.to_string().dimmed());
-            }
-            Event::StateUpdate(opts) => {
-                if let Some(prompt_path) = &args.save_to {
-                    let data = format!(
-                        "[SYSTEM PROMPT]\n\n{}\n\n[PROMPT]\n\n{}\n\n[CHAT]\n\n{}",
-                        &opts.system_prompt.unwrap_or_default(),
-                        &opts.prompt,
-                        opts.history
-                            .iter()
-                            .map(|m| m.to_string())
-                            .collect::<Vec<String>>()
-                            .join("\n")
-                    );
+// print an event of the run to the console
+pub fn on_event(args: &cli::Args, event: Event) {
+    match event {
+        Event::MetricsUpdate(metrics) => {
+            log::info!(target: TRANSCRIPT, "{}", metrics.to_string().dimmed());
+        }
+        Event::StateUpdate(opts) => {
+            if let Some(prompt_path) = &args.save_to {
+                let data = format!(
+                    "[SYSTEM PROMPT]\n\n{}\n\n[PROMPT]\n\n{}\n\n[CHAT]\n\n{}",
+                    &opts.system_prompt.unwrap_or_default(),
+                    &opts.prompt,
+                    opts.history
+                        .iter()
+                        .map(|m| m.to_string())
+                        .collect::<Vec<String>>()
+                        .join("\n")
+                );
 
-                    if let Err(e) = std::fs::write(prompt_path, data) {
-                        log::error!("error writing {}: {:?}", prompt_path, e);
-                    }
+                if let Err(e) = std::fs::write(prompt_path, data) {
+                    log::error!("error writing {}: {:?}", prompt_path, e);
                 }
             }
-            Event::EmptyResponse => {
-                log::warn!("agent did not provide valid instructions: empty response");
-            }
-            Event::InvalidResponse(response) => {
-                log::warn!(
-                    "agent did not provide valid instructions: \n\n{}\n\n",
-                    response.dimmed()
-                );
-            }
-            Event::InvalidAction { invocation, error } => {
-                log::warn!("invalid action {} : {:?}", &invocation.action, error);
-            }
-            Event::ActionTimeout {
-                invocation,
-                elapsed,
-            } => {
-                log::warn!(
-                    "action '{}' timed out after {:?}",
-                    invocation.action,
-                    elapsed
-                );
-            }
-            Event::ActionExecuted {
-                invocation,
+        }
+        Event::EmptyResponse => {
+            log::warn!(target: TRANSCRIPT, "agent did not provide valid instructions: empty response");
+        }
+        Event::InvalidResponse(response) => {
+            log::warn!(target: TRANSCRIPT,
+                "agent did not provide valid instructions: \n\n{}\n\n",
+                response.dimmed()
+            );
+        }
+        Event::ModelResponse(response) => {
+            log::info!(target: TRANSCRIPT, "model response:\n\n{}\n", response);
+        }
+        Event::InvalidAction { invocation, error } => {
+            log::warn!(target: TRANSCRIPT, "invalid action {} : {:?}", &invocation.action, error);
+        }
+        Event::ActionStarted { invocation } => {
+            log::debug!(target: TRANSCRIPT, "executing {} ...", invocation.as_function_call_string());
+        }
+        Event::ActionOutput { output, .. } => {
+            log::info!(target: TRANSCRIPT, "{}", output.trim_end().dimmed());
+        }
+        Event::ActionHeartbeat {
+            invocation,
+            elapsed,
+        } => {
+            log::info!(target: TRANSCRIPT,
+                "{} still running after {}s ...",
+                invocation.as_function_call_string(),
+                elapsed.as_secs()
+            );
+        }
+        Event::ActionTimeout {
+            invocation,
+            elapsed,
+        } => {
+            log::warn!(target: TRANSCRIPT,
+                "action '{}' timed out after {:?}",
+                invocation.action,
+                elapsed
+            );
+        }
+        Event::ActionExecuted {
+            invocation,
+            error,
+            result,
+            elapsed,
+            complete_task,
+        } => {
+            on_action_executed(
+                args.judge_mode,
                 error,
+                invocation,
                 result,
                 elapsed,
                 complete_task,
-            } => {
-                on_action_executed(
-                    args.judge_mode,
-                    error,
-                    invocation,
-                    result,
-                    elapsed,
-                    complete_task,
+            );
+        }
+        Event::BudgetExhausted(reason) => {
+            log::error!(target: TRANSCRIPT, "{}: {}", "budget exhausted".bold().red(), reason);
+        }
+        Event::TooManyErrors(errors) => {
+            log::error!(target: TRANSCRIPT,
+                "{}: {} consecutive errors",
+                "circuit breaker tripped".bold().red(),
+                errors
+            );
+        }
+        Event::Interrupted { checkpoint } => {
+            if let Some(path) = checkpoint {
+                log::warn!(target: TRANSCRIPT,
+                    "{}, session saved, resume with: {} --resume {}",
+                    "interrupted".bold().yellow(),
+                    crate::APP_NAME,
+                    path
                 );
+            } else {
+                log::warn!(target: TRANSCRIPT, "{}", "interrupted".bold().yellow());
             }
-            Event::TaskComplete { impossible, reason } => {
-                if impossible {
-                    log::error!(
-                        "{}: '{}'",
-                        "task is impossible".bold().red(),
-                        if let Some(r) = &reason {
-                            r
-                        } else {
-                            "no reason provided"
-                        }
-                    );
-                } else {
-                    log::info!(
-                        "{}: '{}'",
-                        "task complete".bold().green(),
-                        if let Some(r) = &reason {
-                            r
-                        } else {
-                            "no reason provided"
-                        }
-                    );
-                }
+        }
+        Event::GuardrailTriggered {
+            invocation,
+            outcome,
+        } => {
+            log::warn!(target: TRANSCRIPT,
+                "{}{}: {}",
+                "guardrail".bold().yellow(),
+                invocation
+                    .map(|inv| format!(" on {}", inv.as_function_call_string()))
+                    .unwrap_or_default(),
+                outcome
+            );
+        }
+        Event::TaskComplete { impossible, reason } => {
+            if impossible {
+                log::error!(target: TRANSCRIPT,
+                    "{}: '{}'",
+                    "task is impossible".bold().red(),
+                    if let Some(r) = &reason {
+                        r
+                    } else {
+                        "no reason provided"
+                    }
+                );
+            } else {
+                log::info!(target: TRANSCRIPT,
+                    "{}: '{}'",
+                    "task complete".bold().green(),
+                    if let Some(r) = &reason {
+                        r
+                    } else {
+                        "no reason provided"
+                    }
+                );
             }
-            Event::StorageUpdate {
-                storage_name,
-                storage_type: _,
+        }
+        Event::Handoff { from, to, message } => {
+            log::info!(target: TRANSCRIPT,
+                "{} from {} to {}: '{}'",
+                "handoff".bold().cyan(),
+                from.bold(),
+                to.bold(),
+                message
+            );
+        }
+        Event::StorageEvicted {
+            storage_name,
+            storage_type: _,
+            key,
+            data,
+        } => {
+            log::warn!(target: TRANSCRIPT,
+                "{}.{} evicted ({} bytes), storage quota exceeded",
+                storage_name.yellow().bold(),
                 key,
-                prev,
-                new,
-            } => {
-                if prev.is_none() && new.is_none() {
-                    log::info!("storage.{} cleared", storage_name.yellow().bold());
-                } else if prev.is_none() && new.is_some() {
-                    log::info!(
-                        "storage.{}.{} > {}",
-                        storage_name.yellow().bold(),
-                        key,
-                        new.unwrap().green()
-                    );
-                } else if prev.is_some() && new.is_none() {
-                    log::info!("{}.{} removed", storage_name.yellow().bold(), key);
-                } else if new.is_some() {
-                    log::info!(
-                        "{}.{} > {}",
-                        storage_name.yellow().bold(),
-                        key,
-                        new.unwrap().green()
-                    );
-                } else {
-                    log::info!(
-                        "{}.{} prev={:?} new={:?}",
-                        storage_name.yellow().bold(),
-                        key,
-                        prev,
-                        new
-                    );
-                }
+                data.len()
+            );
+        }
+        Event::StorageUpdate {
+            storage_name,
+            storage_type: _,
+            key,
+            prev,
+            new,
+        } => {
+            if prev.is_none() && new.is_none() {
+                log::info!(target: TRANSCRIPT, "storage.{} cleared", storage_name.yellow().bold());
+            } else if prev.is_none() && new.is_some() {
+                log::info!(target: TRANSCRIPT,
+                    "storage.{}.{} > {}",
+                    storage_name.yellow().bold(),
+                    key,
+                    new.unwrap().green()
+                );
+            } else if prev.is_some() && new.is_none() {
+                log::info!(target: TRANSCRIPT, "{}.{} removed", storage_name.yellow().bold(), key);
+            } else if new.is_some() {
+                log::info!(target: TRANSCRIPT,
+                    "{}.{} > {}",
+                    storage_name.yellow().bold(),
+                    key,
+                    new.unwrap().green()
+                );
+            } else {
+                log::info!(target: TRANSCRIPT,
+                    "{}.{} prev={:?} new={:?}",
+                    storage_name.yellow().bold(),
+                    key,
+                    prev,
+                    new
+                );
             }
         }
     }
 }
+
+pub async fn consume_events(args: cli::Args, mut events_rx: Receiver) {
+    while let Some(event) = events_rx.recv().await {
+        on_event(&args, event);
+    }
+}
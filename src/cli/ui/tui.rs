@@ -0,0 +1,512 @@
+use std::{
+    collections::VecDeque,
+    io::{self, IsTerminal, Write},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex, Weak,
+    },
+    time::{Duration, Instant},
+};
+
+use colored::Colorize;
+
+use crate::{
+    agent::{
+        events::{Event, Receiver},
+        state::{
+            metrics::Metrics,
+            storage::{Storage, StorageType, CURRENT_TAG},
+            State,
+        },
+        Invocation,
+    },
+    cli,
+};
+
+use super::text;
+
+// lines of the feed kept in memory
+const MAX_FEED_LINES: usize = 1000;
+const REFRESH_INTERVAL: Duration = Duration::from_millis(100);
+
+// the log lines shown in the feed while the dashboard is open
+static FEED: Mutex<VecDeque<String>> = Mutex::new(VecDeque::new());
+// set while the dashboard owns the terminal, the logs go to stderr otherwise
+static CAPTURING: AtomicBool = AtomicBool::new(false);
+
+#[derive(Default)]
+struct Screen {
+    // on the alternate screen
+    open: bool,
+    // the terminal was given back while the user is asked for input
+    suspended: bool,
+}
+
+static SCREEN: Mutex<Screen> = Mutex::new(Screen {
+    open: false,
+    suspended: false,
+});
+
+// the dashboard can only be drawn on a terminal
+pub fn is_supported() -> bool {
+    io::stdout().is_terminal()
+}
+
+// the time of the log lines takes too much room in the feed, "[<time> INFO ] ..." becomes "[INFO ] ..."
+fn without_timestamp(line: &str) -> String {
+    match line.split_once(' ') {
+        Some((time, rest)) if line.starts_with('[') && time.contains(':') => format!("[{}", rest),
+        _ => line.to_string(),
+    }
+}
+
+// log target that feeds the dashboard
+pub struct LogWriter;
+
+impl Write for LogWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if !CAPTURING.load(Ordering::SeqCst) {
+            return io::stderr().write(buf);
+        }
+
+        let mut feed = FEED.lock().unwrap();
+        for line in String::from_utf8_lossy(buf).lines() {
+            if !line.trim().is_empty() {
+                feed.push_back(without_timestamp(line));
+            }
+        }
+        while feed.len() > MAX_FEED_LINES {
+            feed.pop_front();
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+fn enter(screen: &mut Screen) {
+    // alternate screen, hidden cursor
+    print!("\x1b[?1049h\x1b[?25l");
+    let _ = io::stdout().flush();
+    screen.open = true;
+    CAPTURING.store(true, Ordering::SeqCst);
+}
+
+fn leave(screen: &mut Screen) {
+    CAPTURING.store(false, Ordering::SeqCst);
+    print!("\x1b[?25h\x1b[?1049l");
+    let _ = io::stdout().flush();
+    screen.open = false;
+}
+
+// give the terminal back to the user, it's safe to call it more than once
+pub fn close() {
+    let mut screen = SCREEN.lock().unwrap();
+    if screen.open {
+        leave(&mut screen);
+    }
+}
+
+// number of columns taken by a character, wide for emojis and east asian scripts
+fn char_width(c: char) -> usize {
+    match c as u32 {
+        0x1100..=0x115F
+        | 0x2E80..=0xA4CF
+        | 0xAC00..=0xD7A3
+        | 0xF900..=0xFAFF
+        | 0xFF00..=0xFF60
+        | 0x1F300..=0x1FAFF => 2,
+        _ => 1,
+    }
+}
+
+// cut a line to the given number of columns and pad it with spaces, color escape sequences are
+// kept but don't count
+fn fit(line: &str, width: usize) -> String {
+    let mut fitted = String::new();
+    let mut used = 0;
+    let mut chars = line.chars();
+    let mut styled = false;
+
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            fitted.push(c);
+            for c in chars.by_ref() {
+                fitted.push(c);
+                if c.is_ascii_alphabetic() {
+                    break;
+                }
+            }
+            styled = true;
+            continue;
+        }
+
+        let c = if c == '\t' || c.is_control() { ' ' } else { c };
+        if used + char_width(c) > width {
+            break;
+        }
+        used += char_width(c);
+        fitted.push(c);
+    }
+
+    if styled {
+        fitted.push_str("\x1b[0m");
+    }
+    fitted.push_str(&" ".repeat(width - used));
+    fitted
+}
+
+fn storage_lines(storage: &Storage) -> Vec<String> {
+    storage
+        .iter()
+        .filter(|(key, _)| {
+            // the previous state is of no interest here
+            *storage.get_type() != StorageType::CurrentPrevious || key.as_str() == CURRENT_TAG
+        })
+        .map(|(key, entry)| {
+            let data = entry.data.replace('\n', " ");
+            match storage.get_type() {
+                StorageType::Tagged => format!("{}: {}", key.bold(), data),
+                StorageType::Completion => {
+                    if entry.complete {
+                        format!("[x] {}", data.dimmed())
+                    } else {
+                        format!("[ ] {}", data)
+                    }
+                }
+                StorageType::Tree => format!(
+                    "{}[{}] {} {}",
+                    "  ".repeat(key.matches('.').count()),
+                    if entry.complete { "x" } else { " " },
+                    key,
+                    data
+                ),
+                _ => data,
+            }
+        })
+        .collect()
+}
+
+// the goal and the plan first, then the other storages with something in them
+fn storages_panel(state: &State) -> Vec<String> {
+    let mut storages = state.get_sorted_storages();
+    storages.retain(|storage| !storage.is_empty() && *storage.get_type() != StorageType::Time);
+    storages.sort_by_key(|storage| match storage.get_name() {
+        "goal" => 0,
+        "plan" => 1,
+        _ => 2,
+    });
+
+    let mut lines = vec![];
+    for storage in storages {
+        if !lines.is_empty() {
+            lines.push(String::new());
+        }
+        lines.push(
+            storage
+                .get_name()
+                .to_uppercase()
+                .yellow()
+                .bold()
+                .to_string(),
+        );
+        lines.extend(storage_lines(storage));
+    }
+    lines
+}
+
+fn status_line(metrics: &Metrics, elapsed: Duration) -> String {
+    let mut status = vec![if metrics.max_steps > 0 {
+        format!("step {}/{}", metrics.current_step, metrics.max_steps)
+    } else {
+        format!("step {}", metrics.current_step)
+    }];
+    status.push(format!("{}s", elapsed.as_secs()));
+    status.push(format!(
+        "tokens {} in / {} out",
+        metrics.usage.total_input_tokens, metrics.usage.total_output_tokens
+    ));
+    if metrics.cost > 0.0 {
+        status.push(format!("${:.4}", metrics.cost));
+    }
+    status.push(format!(
+        "actions {} ok / {} errors",
+        metrics.success_actions,
+        metrics.errors.total()
+    ));
+    if let Some(speed) = metrics.throughput.tokens_per_second() {
+        status.push(format!("{:.1} tok/s", speed));
+    }
+    if let Some(latency) = metrics.throughput.generation_latency() {
+        status.push(format!("gen {:?}", latency));
+    }
+    if let (Some(p50), Some(p95)) = (
+        metrics.throughput.action_latency(50),
+        metrics.throughput.action_latency(95),
+    ) {
+        status.push(format!("actions p50 {:?} p95 {:?}", p50, p95));
+    }
+    status.join(" · ")
+}
+
+// what is shown, updated from the events and the state of the agent
+struct View {
+    tasklet: String,
+    started_at: Instant,
+    metrics: Metrics,
+    in_flight: Option<(Invocation, Instant)>,
+    outcome: Option<String>,
+    storages: Vec<String>,
+}
+
+impl View {
+    fn on_event(&mut self, event: &Event) {
+        match event {
+            Event::MetricsUpdate(metrics) => self.metrics = metrics.clone(),
+            Event::ActionStarted { invocation } => {
+                self.in_flight = Some((invocation.clone(), Instant::now()))
+            }
+            Event::ActionExecuted { .. } | Event::ActionTimeout { .. } => self.in_flight = None,
+            Event::TaskComplete { impossible, reason } => {
+                self.outcome = Some(format!(
+                    "{}: {}",
+                    if *impossible {
+                        "task is impossible".red().bold()
+                    } else {
+                        "task complete".green().bold()
+                    },
+                    reason.as_deref().unwrap_or("no reason provided")
+                ))
+            }
+            Event::BudgetExhausted(reason) => {
+                self.outcome = Some(format!("{}: {}", "budget exhausted".red().bold(), reason))
+            }
+            Event::TooManyErrors(errors) => {
+                self.outcome = Some(format!(
+                    "{}: {} consecutive errors",
+                    "circuit breaker tripped".red().bold(),
+                    errors
+                ))
+            }
+            Event::Interrupted { .. } => {
+                self.outcome = Some("interrupted".yellow().bold().to_string())
+            }
+            _ => {}
+        }
+    }
+
+    fn activity(&self) -> String {
+        if let Some(outcome) = &self.outcome {
+            outcome.to_string()
+        } else if let Some((invocation, started_at)) = &self.in_flight {
+            format!(
+                "▶ {} for {}s",
+                invocation.as_function_call_string().bold(),
+                started_at.elapsed().as_secs()
+            )
+        } else {
+            "waiting for the model ...".dimmed().to_string()
+        }
+    }
+
+    fn render(&self, feed: &VecDeque<String>, width: usize, height: usize) -> Vec<String> {
+        let mut lines = vec![
+            fit(
+                &format!(" nerve · {}", self.tasklet).reversed().to_string(),
+                width,
+            ),
+            fit(
+                &status_line(&self.metrics, self.started_at.elapsed())
+                    .dimmed()
+                    .to_string(),
+                width,
+            ),
+            fit(&self.activity(), width),
+            "─".repeat(width),
+        ];
+
+        // storages on the left, the most recent lines of the feed on the right
+        let rows = height.saturating_sub(lines.len());
+        let left = (width * 2 / 5).min(60);
+        let right = width.saturating_sub(left + 3);
+        let feed: Vec<&String> = feed.iter().skip(feed.len().saturating_sub(rows)).collect();
+        for row in 0..rows {
+            lines.push(format!(
+                "{} │ {}",
+                fit(
+                    self.storages.get(row).map(|s| s.as_str()).unwrap_or(""),
+                    left
+                ),
+                fit(feed.get(row).map(|s| s.as_str()).unwrap_or(""), right)
+            ));
+        }
+
+        lines
+    }
+}
+
+#[cfg(unix)]
+fn terminal_size() -> (usize, usize) {
+    let mut size: libc::winsize = unsafe { std::mem::zeroed() };
+    if unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut size) } == 0
+        && size.ws_col > 0
+        && size.ws_row > 0
+    {
+        (size.ws_col as usize, size.ws_row as usize)
+    } else {
+        (80, 24)
+    }
+}
+
+// the size set by the terminal, if any
+#[cfg(not(unix))]
+fn terminal_size() -> (usize, usize) {
+    let size = |var: &str, default: usize| {
+        std::env::var(var)
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .filter(|value| *value > 0)
+            .unwrap_or(default)
+    };
+    (size("COLUMNS", 80), size("LINES", 24))
+}
+
+fn draw(view: &View) {
+    let screen = SCREEN.lock().unwrap();
+    if !screen.open || screen.suspended {
+        return;
+    }
+
+    let (width, height) = terminal_size();
+    let lines = view.render(&FEED.lock().unwrap(), width, height);
+
+    let mut frame = "\x1b[H".to_string();
+    frame.push_str(&lines.join("\r\n"));
+    frame.push_str("\x1b[J");
+    print!("{}", frame);
+    let _ = io::stdout().flush();
+}
+
+// live dashboard of the run, the events are also printed to the feed as the console would
+pub async fn consume_events(
+    args: cli::Args,
+    mut events_rx: Receiver,
+    state: Weak<tokio::sync::Mutex<State>>,
+) {
+    // leave the screen while the user is asked for input, get it back once answered
+    crate::agent::set_user_input_hook(Box::new(|asking| {
+        let mut screen = SCREEN.lock().unwrap();
+        if asking && screen.open {
+            leave(&mut screen);
+            screen.suspended = true;
+        } else if !asking && screen.suspended {
+            screen.suspended = false;
+            enter(&mut screen);
+        }
+    }));
+
+    enter(&mut SCREEN.lock().unwrap());
+
+    let mut view = View {
+        tasklet: args.tasklet.clone().unwrap_or_default(),
+        started_at: Instant::now(),
+        metrics: Metrics::default(),
+        in_flight: None,
+        outcome: None,
+        storages: vec![],
+    };
+    let mut refresh = tokio::time::interval(REFRESH_INTERVAL);
+
+    loop {
+        tokio::select! {
+            event = events_rx.recv() => {
+                let Some(event) = event else {
+                    break;
+                };
+                view.on_event(&event);
+                // the metrics are already in the status line
+                if !matches!(event, Event::MetricsUpdate(_)) {
+                    text::on_event(&args, event);
+                }
+            }
+            _ = refresh.tick() => {
+                if let Some(state) = state.upgrade() {
+                    // don't wait for the agent, the storages will be updated at the next tick
+                    if let Ok(state) = state.try_lock() {
+                        view.storages = storages_panel(&state);
+                    }
+                }
+                draw(&view);
+            }
+        }
+    }
+
+    draw(&view);
+    close();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fit() {
+        assert_eq!(fit("hello", 8), "hello   ");
+        assert_eq!(fit("hello world", 5), "hello");
+        assert_eq!(fit("\x1b[1mbold\x1b[0m", 6), "\x1b[1mbold\x1b[0m\x1b[0m  ");
+        assert_eq!(fit("🧠 x", 3), "🧠 ");
+        assert_eq!(fit("a\tb", 3), "a b");
+    }
+
+    #[test]
+    fn test_without_timestamp() {
+        assert_eq!(
+            without_timestamp("[2024-10-14T15:03:40Z INFO ] task complete"),
+            "[INFO ] task complete"
+        );
+        assert_eq!(without_timestamp("plain line"), "plain line");
+    }
+
+    #[test]
+    fn test_render() {
+        colored::control::set_override(false);
+
+        let mut view = View {
+            tasklet: "scan".to_string(),
+            started_at: Instant::now(),
+            metrics: Metrics {
+                current_step: 2,
+                max_steps: 10,
+                ..Default::default()
+            },
+            in_flight: None,
+            outcome: None,
+            storages: vec!["PLAN".to_string(), "[ ] scan".to_string()],
+        };
+        view.on_event(&Event::ActionStarted {
+            invocation: Invocation::new("shell".to_string(), None, Some("id".to_string())),
+        });
+
+        let feed: VecDeque<String> = (0..10).map(|n| format!("line {}", n)).collect();
+        let lines = view.render(&feed, 40, 7);
+        assert_eq!(lines.len(), 7);
+        assert!(lines[1].starts_with("step 2/10 · 0s · tokens 0 in / 0 out"));
+        assert!(lines[2].starts_with("▶ shell(id) for 0s"));
+        assert_eq!(
+            lines[4..],
+            [
+                "PLAN             │ line 7               ",
+                "[ ] scan         │ line 8               ",
+                "                 │ line 9               ",
+            ]
+        );
+
+        view.on_event(&Event::TaskComplete {
+            impossible: false,
+            reason: Some("done".to_string()),
+        });
+        assert!(view.render(&feed, 40, 7)[2].starts_with("task complete: done"));
+    }
+}
@@ -0,0 +1,109 @@
+use std::path::Path;
+
+use anyhow::Result;
+use colored::Colorize;
+
+use crate::{
+    agent::{
+        namespaces,
+        task::{tasklet::Tasklet, Task},
+    },
+    cli::{test::TestSuite, workflow::Workflow},
+};
+
+fn is_workflow(path: &Path) -> bool {
+    path.is_file()
+        && std::fs::read_to_string(path)
+            .ok()
+            .and_then(|yaml| serde_yaml::from_str::<serde_yaml::Value>(&yaml).ok())
+            .is_some_and(|yaml| yaml.get("tasks").is_some())
+}
+
+// load the tasklet like a run would, without preparing it
+fn tasklet(path: &str, using: Option<Vec<String>>) -> Result<String> {
+    let tasklet = Tasklet::from_path(path, &vec![])?;
+    let namespaces = namespaces::resolve_used_namespaces(using.or(tasklet.namespaces()))?;
+    let mut details = format!("namespaces {}", namespaces.join(", "));
+
+    if Path::new(path).join("test.yml").exists() {
+        let suite = TestSuite::from_path(path)?;
+        details += &format!(", {} tests", suite.tests.len());
+    }
+
+    Ok(details)
+}
+
+fn workflow(path: &str) -> Result<String> {
+    let workflow = Workflow::from_path(path)?;
+    for node in &workflow.tasks {
+        tasklet(&workflow.tasklet_path(node), node.using.clone())
+            .map_err(|e| anyhow!("task '{}': {}", node.name, e))?;
+    }
+    Ok(format!("{} tasks", workflow.tasks.len()))
+}
+
+fn validate(path: &str) -> Result<String> {
+    if is_workflow(Path::new(path)) {
+        workflow(path)
+    } else {
+        tasklet(path, None)
+    }
+}
+
+pub fn run(paths: &[String]) -> Result<()> {
+    let mut failed = 0;
+    for path in paths {
+        match validate(path) {
+            Ok(details) => println!("{} {} ({})", "ok".green().bold(), path, details.dimmed()),
+            Err(err) => {
+                println!("{} {}: {}", "error".red().bold(), path, err);
+                failed += 1;
+            }
+        }
+    }
+
+    if failed == 0 {
+        Ok(())
+    } else {
+        Err(anyhow!("{} of {} files are not valid", failed, paths.len()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate() {
+        let folder =
+            std::env::temp_dir().join(format!("nerve-test-validate-{}", std::process::id()));
+        std::fs::create_dir_all(&folder).unwrap();
+        let write = |name: &str, yaml: &str| {
+            let path = folder.join(name);
+            std::fs::write(&path, yaml).unwrap();
+            path.display().to_string()
+        };
+
+        let valid = write("valid.yml", "using: [task, memory]\nsystem_prompt: test\n");
+        let invalid = write(
+            "invalid.yml",
+            "using: [task, teleport]\nsystem_prompt: test\n",
+        );
+        let flow = write(
+            "flow.yml",
+            "tasks:\n  - name: first\n    tasklet: valid.yml\n  - name: second\n    tasklet: invalid.yml\n    depends_on: [first]\n",
+        );
+
+        assert_eq!(validate(&valid).unwrap(), "namespaces task, memory");
+        assert!(validate(&invalid)
+            .unwrap_err()
+            .to_string()
+            .contains("teleport"));
+        assert!(validate(&flow)
+            .unwrap_err()
+            .to_string()
+            .starts_with("task 'second'"));
+
+        let _ = std::fs::remove_dir_all(&folder);
+    }
+}
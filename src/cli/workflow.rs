@@ -0,0 +1,435 @@
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+};
+
+use anyhow::Result;
+use colored::Colorize;
+use serde::Deserialize;
+
+use crate::{
+    agent::{
+        namespaces::mailbox,
+        state::SharedState,
+        task::{
+            tasklet::Tasklet,
+            variables::{define_variable, interpolate_variables},
+        },
+    },
+    cli::{self, setup, ui},
+};
+
+// a single tasklet execution in a workflow
+#[derive(Debug, Clone, Deserialize)]
+pub struct Node {
+    pub name: String,
+    pub tasklet: String,
+    pub prompt: Option<String>,
+    #[serde(default)]
+    pub depends_on: Vec<String>,
+    // name of the node to inherit the storages from, by default every node has isolated state
+    pub inherit: Option<String>,
+    // variables to define before running this node, values are interpolated
+    pub variables: Option<HashMap<String, String>>,
+    // generator to use for this node instead of the command line one
+    pub generator: Option<String>,
+    // namespaces to use instead of the ones declared by the tasklet
+    pub using: Option<Vec<String>>,
+}
+
+// a list of tasklets with dependencies between them
+#[derive(Debug, Clone, Deserialize)]
+pub struct Workflow {
+    #[serde(skip_deserializing)]
+    pub folder: PathBuf,
+    pub name: Option<String>,
+    // run tasks with no pending dependencies at the same time
+    #[serde(default)]
+    pub concurrent: bool,
+    pub tasks: Vec<Node>,
+}
+
+// name of the variable containing the completion reason of a node
+pub fn output_variable_name(node: &str) -> String {
+    format!(
+        "{}_OUTPUT",
+        node.chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+            .collect::<String>()
+            .to_uppercase()
+    )
+}
+
+impl Workflow {
+    pub fn from_path(path: &str) -> Result<Self> {
+        let canon =
+            std::fs::canonicalize(path).map_err(|e| anyhow!("could not read {path}: {e}"))?;
+        let yaml = std::fs::read_to_string(&canon)?;
+        let mut workflow: Self = serde_yaml::from_str(&yaml)?;
+
+        workflow.folder = canon
+            .parent()
+            .ok_or_else(|| anyhow!("can't find parent folder of {}", canon.display()))?
+            .to_path_buf();
+
+        // make sure the graph can be resolved
+        workflow.execution_order()?;
+
+        Ok(workflow)
+    }
+
+    // return the nodes sorted so that every node comes after its dependencies
+    pub fn execution_order(&self) -> Result<Vec<&Node>> {
+        let by_name: HashMap<&str, &Node> =
+            self.tasks.iter().map(|n| (n.name.as_str(), n)).collect();
+
+        if by_name.len() != self.tasks.len() {
+            return Err(anyhow!("workflow task names must be unique"));
+        }
+
+        for node in &self.tasks {
+            for dep in node.depends_on.iter().chain(node.inherit.iter()) {
+                if !by_name.contains_key(dep.as_str()) {
+                    return Err(anyhow!(
+                        "task '{}' depends on unknown task '{}'",
+                        node.name,
+                        dep
+                    ));
+                }
+            }
+            if let Some(inherit) = &node.inherit {
+                if !node.depends_on.contains(inherit) {
+                    return Err(anyhow!(
+                        "task '{}' inherits from '{}' but does not depend on it",
+                        node.name,
+                        inherit
+                    ));
+                }
+            }
+        }
+
+        let mut order = vec![];
+        let mut done = HashSet::new();
+        let mut visiting = HashSet::new();
+
+        fn visit<'a>(
+            node: &'a Node,
+            by_name: &HashMap<&str, &'a Node>,
+            done: &mut HashSet<&'a str>,
+            visiting: &mut HashSet<&'a str>,
+            order: &mut Vec<&'a Node>,
+        ) -> Result<()> {
+            if done.contains(node.name.as_str()) {
+                return Ok(());
+            }
+            if !visiting.insert(node.name.as_str()) {
+                return Err(anyhow!("dependency cycle detected at task '{}'", node.name));
+            }
+
+            for dep in &node.depends_on {
+                visit(by_name[dep.as_str()], by_name, done, visiting, order)?;
+            }
+
+            visiting.remove(node.name.as_str());
+            done.insert(node.name.as_str());
+            order.push(node);
+
+            Ok(())
+        }
+
+        for node in &self.tasks {
+            visit(node, &by_name, &mut done, &mut visiting, &mut order)?;
+        }
+
+        Ok(order)
+    }
+
+    pub fn tasklet_path(&self, node: &Node) -> String {
+        let path = Path::new(&node.tasklet);
+        if path.is_relative() && self.folder.join(path).exists() {
+            self.folder.join(path).display().to_string()
+        } else {
+            node.tasklet.to_string()
+        }
+    }
+}
+
+// run a single node and return its state and whether it completed
+async fn run_node(
+    args: &cli::Args,
+    workflow: &Workflow,
+    node: &Node,
+    states: &HashMap<String, SharedState>,
+) -> Result<(SharedState, bool)> {
+    if let Some(variables) = &node.variables {
+        for (name, value) in variables {
+            define_variable(name, &interpolate_variables(value)?);
+        }
+    }
+
+    log::info!("starting task {} ...", node.name.bold());
+
+    let mut args = args.clone();
+    if let Some(generator) = &node.generator {
+        args.generator = generator.to_string();
+    }
+
+    let mut tasklet = Tasklet::from_path(&workflow.tasklet_path(node), &args.define)?;
+    if let Some(using) = &node.using {
+        tasklet.set_namespaces(using.clone());
+    }
+
+    let (mut agent, mut events_rx) =
+        setup::setup_agent_with_tasklet(&args, tasklet, &node.prompt).await?;
+
+    if let Some(inherit) = &node.inherit {
+        let parent = states.get(inherit).unwrap().lock().await;
+        agent.get_state().lock().await.inherit_storages(&parent);
+    }
+
+    // let the other agents send messages to this one
+    mailbox::register(&node.name, agent.get_state()).await?;
+
+    let result = {
+        let run = agent.run();
+        tokio::pin!(run);
+        loop {
+            tokio::select! {
+                ret = &mut run => break ret,
+                Some(event) = events_rx.recv() => ui::text::on_event(&args, event),
+            }
+        }
+    };
+    let state = agent.get_state();
+
+    // the state, holding the events channel, is kept for the tasks inheriting from this one,
+    // so rather than waiting for the channel to close print whatever the run left in it
+    while let Ok(event) = events_rx.try_recv() {
+        ui::text::on_event(&args, event);
+    }
+
+    let (impossible, output) = {
+        let lock = state.lock().await;
+        (
+            lock.is_impossible(),
+            lock.get_completion_reason().cloned().unwrap_or_default(),
+        )
+    };
+
+    if let Err(err) = &result {
+        log::error!("task {} failed: {}", node.name, err);
+    }
+
+    let completed = result.is_ok() && !impossible;
+    if completed {
+        define_variable(&output_variable_name(&node.name), &output);
+    }
+
+    Ok((state, completed))
+}
+
+pub async fn run(args: &cli::Args, path: &str) -> Result<()> {
+    let workflow = Workflow::from_path(path)?;
+    let mut states: HashMap<String, SharedState> = HashMap::new();
+    let mut failed: HashSet<String> = HashSet::new();
+
+    log::info!(
+        "running workflow {} ({} tasks{})",
+        workflow
+            .name
+            .as_ref()
+            .unwrap_or(&path.to_string())
+            .green()
+            .bold(),
+        workflow.tasks.len(),
+        if workflow.concurrent {
+            ", concurrent"
+        } else {
+            ""
+        }
+    );
+
+    // agents can send messages to the ones that did not start yet
+    for node in &workflow.tasks {
+        mailbox::declare(&node.name);
+    }
+
+    let mut pending = workflow.execution_order()?;
+    while !pending.is_empty() {
+        // nodes are sorted by dependencies, so the first one is always ready
+        let ready = if workflow.concurrent {
+            let (ready, rest): (Vec<&Node>, Vec<&Node>) = pending.into_iter().partition(|n| {
+                n.depends_on
+                    .iter()
+                    .all(|d| states.contains_key(d) || failed.contains(d))
+            });
+            pending = rest;
+            ready
+        } else {
+            vec![pending.remove(0)]
+        };
+
+        let mut batch = vec![];
+        for node in ready {
+            if let Some(dep) = node.depends_on.iter().find(|d| failed.contains(*d)) {
+                log::error!("skipping task {}: '{}' did not complete", node.name, dep);
+                failed.insert(node.name.to_string());
+            } else {
+                batch.push(node);
+            }
+        }
+
+        let results = futures::future::join_all(
+            batch
+                .iter()
+                .map(|node| run_node(args, &workflow, node, &states)),
+        )
+        .await;
+
+        for (node, result) in batch.iter().zip(results) {
+            match result {
+                Ok((state, completed)) => {
+                    if !completed {
+                        failed.insert(node.name.to_string());
+                    }
+                    states.insert(node.name.to_string(), state);
+                }
+                // a task that can't be set up fails alone, like one that can't complete
+                Err(err) => {
+                    log::error!("task {} failed: {}", node.name, err);
+                    failed.insert(node.name.to_string());
+                }
+            }
+        }
+    }
+
+    for node in &workflow.tasks {
+        mailbox::unregister(&node.name);
+    }
+
+    if failed.is_empty() {
+        Ok(())
+    } else {
+        Err(anyhow!(
+            "{} of {} workflow tasks did not complete: {}",
+            failed.len(),
+            workflow.tasks.len(),
+            failed.into_iter().collect::<Vec<String>>().join(", ")
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(yaml: &str) -> Workflow {
+        serde_yaml::from_str(yaml).unwrap()
+    }
+
+    #[test]
+    fn test_execution_order() {
+        let workflow = parse(
+            r#"
+tasks:
+  - name: report
+    tasklet: report.yml
+    depends_on: [exploit, recon]
+  - name: exploit
+    tasklet: exploit.yml
+    depends_on: [recon]
+  - name: recon
+    tasklet: recon.yml
+"#,
+        );
+
+        let order: Vec<&str> = workflow
+            .execution_order()
+            .unwrap()
+            .iter()
+            .map(|n| n.name.as_str())
+            .collect();
+
+        assert_eq!(order, vec!["recon", "exploit", "report"]);
+    }
+
+    #[test]
+    fn test_cycle_is_error() {
+        let workflow = parse(
+            r#"
+tasks:
+  - name: a
+    tasklet: a.yml
+    depends_on: [b]
+  - name: b
+    tasklet: b.yml
+    depends_on: [a]
+"#,
+        );
+
+        assert!(workflow.execution_order().is_err());
+    }
+
+    #[test]
+    fn test_unknown_dependency_is_error() {
+        let workflow = parse(
+            r#"
+tasks:
+  - name: a
+    tasklet: a.yml
+    depends_on: [nope]
+"#,
+        );
+
+        assert!(workflow.execution_order().is_err());
+    }
+
+    #[test]
+    fn test_inherit_requires_dependency() {
+        let workflow = parse(
+            r#"
+tasks:
+  - name: a
+    tasklet: a.yml
+  - name: b
+    tasklet: b.yml
+    inherit: a
+"#,
+        );
+
+        assert!(workflow.execution_order().is_err());
+    }
+
+    #[test]
+    fn test_parse_agents() {
+        let workflow = parse(
+            r#"
+concurrent: true
+tasks:
+  - name: researcher
+    tasklet: research.yml
+    generator: openai://gpt-4o
+    using: [memory, task, mailbox, http]
+  - name: writer
+    tasklet: write.yml
+    using: [task, mailbox, filesystem]
+"#,
+        );
+
+        assert!(workflow.concurrent);
+        assert_eq!(
+            workflow.tasks[0].generator.as_deref(),
+            Some("openai://gpt-4o")
+        );
+        assert_eq!(
+            workflow.tasks[1].using.as_ref().unwrap(),
+            &vec!["task", "mailbox", "filesystem"]
+        );
+    }
+
+    #[test]
+    fn test_output_variable_name() {
+        assert_eq!(output_variable_name("recon"), "RECON_OUTPUT");
+        assert_eq!(output_variable_name("web-scan"), "WEB_SCAN_OUTPUT");
+    }
+}
@@ -4,22 +4,19 @@
 #[macro_use]
 extern crate anyhow;
 
-mod agent;
-mod api;
 mod cli;
 
+use std::{io::Write, process::ExitCode};
+
 use anyhow::Result;
-use clap::Parser;
 use cli::{setup, ui};
+use nerve_core::agent;
 
 const APP_NAME: &str = env!("CARGO_BIN_NAME");
 const APP_VERSION: &str = env!("CARGO_PKG_VERSION");
 
-#[tokio::main]
-async fn main() -> Result<()> {
-    // TODO: save/restore session
-    let mut args = cli::Args::parse();
-
+// environment variables override the command line
+fn apply_env(args: &mut cli::Args) {
     // set generator url if env variable is set
     if let Ok(env_generator) = std::env::var("NERVE_GENERATOR") {
         args.generator = env_generator;
@@ -45,30 +42,238 @@ async fn main() -> Result<()> {
     if let Ok(env_tasklet) = std::env::var("NERVE_TASKLET") {
         args.tasklet = Some(env_tasklet);
     }
+}
 
-    // TODO: handle max tokens
+// console logger, in the dashboard feed if tui is set, plus the file, syslog and journald sinks
+fn setup_logging(args: Option<&cli::Args>, tui: bool) -> Result<()> {
+    // judge mode only prints the judgement
+    let quiet = args.is_some_and(|args| args.quiet || args.judge_mode);
+    let verbose = args.map(|args| args.verbose).unwrap_or(0);
+
+    // $RUST_LOG is used unless the verbosity is set on the command line
+    let from_env = std::env::var("RUST_LOG")
+        .ok()
+        .filter(|_| !args.is_some_and(|args| args.quiet || args.verbose > 0));
+
+    let mut logger = env_logger::Builder::new();
+    logger.parse_filters(&match &from_env {
+        Some(filters) if !args.is_some_and(|args| args.judge_mode) => filters.to_string(),
+        _ => cli::logging::verbosity_filters(quiet, verbose),
+    });
+
+    // the sinks keep the transcript when the console is quiet, --log-file also the debug logs
+    let log_file = args.and_then(|args| args.log_file.as_ref());
+    let mut sinks_filter = env_filter::Builder::new();
+    sinks_filter.parse(&match (log_file, from_env) {
+        (Some(_), _) => cli::logging::verbosity_filters(false, verbose.max(1)),
+        (None, Some(filters)) => filters,
+        (None, None) => cli::logging::verbosity_filters(false, verbose),
+    });
 
-    if args.generate_doc {
-        // generate action namespaces documentation and exit
-        println!("{}", agent::serialization::Strategy::available_actions());
-        std::process::exit(0);
+    if let Some(filter) = args.and_then(|args| args.log_level.as_ref()) {
+        logger.parse_filters(filter);
+        sinks_filter.parse(filter);
     }
+    if tui {
+        // the logs are shown in the events feed of the dashboard
+        logger.target(env_logger::Target::Pipe(Box::new(ui::tui::LogWriter)));
+    }
+    logger
+        // mask any registered secret in the console output
+        .format(|buf, record| {
+            let style = buf.default_level_style(record.level());
+            writeln!(
+                buf,
+                "[{} {style}{:<5}{style:#}] {}",
+                buf.timestamp(),
+                record.level(),
+                agent::secrets::redact(&record.args().to_string())
+            )
+        });
 
-    if std::env::var_os("RUST_LOG").is_none() {
-        // set `RUST_LOG=debug` to see debug logs
-        std::env::set_var(
-            "RUST_LOG",
-            "info,openai_api_rust=warn,rustls=warn,ureq=warn",
-        );
+    // file, syslog and journald sinks for unattended runs
+    let mut sinks = args
+        .map(|args| args.log.as_slice())
+        .unwrap_or_default()
+        .iter()
+        .map(|spec| cli::logging::Sink::open(spec))
+        .collect::<Result<Vec<_>>>()?;
+    if let Some(path) = log_file {
+        sinks.push(cli::logging::Sink::file(path)?);
     }
+    cli::logging::init(logger.build(), sinks, sinks_filter.build())
+}
 
-    if args.judge_mode {
-        // disable most logging
-        std::env::set_var(
-            "RUST_LOG",
-            "error,openai_api_rust=error,rustls=error,ureq=error",
-        );
+#[tokio::main]
+async fn main() -> Result<ExitCode> {
+    // TODO: save/restore session
+    let mut command = cli::Cli::parse_args()?.command;
+    if let Some(args) = command.args_mut() {
+        apply_env(args);
+    }
+
+    // TODO: handle max tokens
 
+    // in json mode the real standard output is kept for the final document
+    let document_out = match &command {
+        cli::cli::Command::Run(args) | cli::cli::Command::Dispatch { args, .. }
+            if args.output == cli::output::Format::Json =>
+        {
+            Some(cli::output::reserve_stdout()?)
+        }
+        _ => None,
+    };
+
+    // the dashboard needs a terminal, fall back to the log lines otherwise
+    let tui =
+        matches!(&command, cli::cli::Command::Run(args) if args.tui) && ui::tui::is_supported();
+
+    setup_logging(command.args(), tui)?;
+
+    if let Some(path) = command.args().and_then(|args| args.prompts_dir.as_ref()) {
+        agent::prompts::set_dir(std::path::Path::new(path))?;
+    }
+
+    let ret = match command {
+        cli::cli::Command::Run(args) => return run(args, tui, document_out).await,
+        cli::cli::Command::Validate { paths } => {
+            // load tasklets and workflows without running them
+            cli::validate::run(&paths)
+        }
+        cli::cli::Command::Namespaces { names, format } => {
+            // describe the namespaces, their actions and requirements
+            cli::namespaces::run(&names, format)
+        }
+        cli::cli::Command::Test { paths, args } => {
+            // run tasklet tests and exit with an error if any fails
+            cli::test::run(&args, &paths).await
+        }
+        cli::cli::Command::Eval {
+            paths,
+            models,
+            runs,
+            csv,
+            args,
+        } => {
+            // compare how the models score on the tasklet tests
+            cli::eval::run(&args, &paths, &models, runs, csv.as_deref()).await
+        }
+        cli::cli::Command::Replay {
+            session,
+            mock,
+            delay,
+            interactive,
+            args,
+        } => {
+            // render or re-execute a recorded session
+            cli::replay::run(&args, &session, mock, delay, interactive).await
+        }
+        #[cfg(feature = "serve")]
+        cli::cli::Command::Serve {
+            address,
+            token,
+            allow_origin,
+            workers,
+            keep_runs,
+            allow_generator,
+            args,
+        } => {
+            // run tasklets on behalf of http clients
+            let token = token.or_else(|| std::env::var("NERVE_SERVE_TOKEN").ok());
+            let server = cli::serve::Options {
+                token,
+                allow_origin,
+                workers,
+                keep_runs,
+                allowed_generators: allow_generator,
+            };
+            cli::serve::serve(&args, &address, server).await
+        }
+        #[cfg(not(feature = "serve"))]
+        cli::cli::Command::Serve { .. } => {
+            Err(anyhow!("nerve was built without the serve feature"))
+        }
+        cli::cli::Command::Daemon { schedule, args } => {
+            // run tasklets on schedule
+            cli::daemon::run(&args, &schedule).await
+        }
+        #[cfg(feature = "redis")]
+        cli::cli::Command::Worker {
+            queue,
+            queue_name,
+            workers,
+            args,
+        } => {
+            // execute the tasklets of a distributed queue
+            cli::queue::work(&args, &queue, &queue_name, workers).await
+        }
+        #[cfg(feature = "redis")]
+        cli::cli::Command::Dispatch {
+            queue,
+            queue_name,
+            claim_timeout,
+            args,
+        } => {
+            // run tasklets on the workers of a distributed queue
+            cli::queue::dispatch(
+                &args,
+                &queue,
+                &queue_name,
+                std::time::Duration::from_secs(claim_timeout),
+                document_out,
+            )
+            .await
+        }
+        #[cfg(not(feature = "redis"))]
+        cli::cli::Command::Worker { .. } | cli::cli::Command::Dispatch { .. } => {
+            Err(anyhow!("nerve was built without the redis feature"))
+        }
+        cli::cli::Command::Sessions { command } => {
+            // manage the saved sessions
+            cli::sessions::run(&command)
+        }
+        cli::cli::Command::Diff { a, b } => {
+            // compare two recorded runs
+            cli::diff::run(&a, &b)
+        }
+        cli::cli::Command::Audit { command } => {
+            // check the integrity of an audit log
+            cli::audit::run(&command)
+        }
+        cli::cli::Command::Tool {
+            action,
+            attrs,
+            payload,
+            args,
+        } => {
+            // execute a single action, for debugging namespaces and tasklet tools
+            cli::tool::run(&args, &action, &attrs, payload).await
+        }
+        cli::cli::Command::Credentials { .. } => {
+            // the credentials are configured while parsing the command line
+            cli::credentials::run()
+        }
+        #[cfg(feature = "mcp")]
+        cli::cli::Command::McpServe { using, args } => {
+            // export the built-in namespaces as MCP tools over stdio
+            cli::mcp::serve(&args, &using).await
+        }
+        #[cfg(not(feature = "mcp"))]
+        cli::cli::Command::McpServe { .. } => {
+            Err(anyhow!("nerve was built without the mcp feature"))
+        }
+    };
+
+    ret.map(|_| ExitCode::SUCCESS)
+}
+
+// run a tasklet or a workflow
+async fn run(
+    args: cli::Args,
+    tui: bool,
+    mut document_out: Option<Box<dyn Write + Send>>,
+) -> Result<ExitCode> {
+    if args.judge_mode {
         // read STDIN and preemptively set $STDIN
         let mut input = String::new();
         match std::io::stdin().read_line(&mut input) {
@@ -78,24 +283,165 @@ async fn main() -> Result<()> {
         agent::task::variables::define_variable("STDIN", input.trim());
     }
 
-    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info"))
-        .format_module_path(false)
-        .format_target(false)
-        .init();
+    if args.tui && !tui {
+        log::warn!("standard output is not a terminal, --tui ignored");
+    }
+
+    if let Some(path) = &args.all {
+        // run many tasklets and summarize their outcomes
+        return cli::batch::run(&args, path, document_out)
+            .await
+            .map(|_| ExitCode::SUCCESS);
+    }
+
+    if let Some(workflow) = &args.workflow {
+        // run every tasklet of the workflow in dependency order
+        return cli::workflow::run(&args, workflow)
+            .await
+            .map(|_| ExitCode::SUCCESS);
+    }
+
+    #[cfg_attr(not(feature = "serve"), allow(unused_mut))]
+    let (mut agent, mut events_rx) = match setup::setup_agent(&args).await {
+        Ok(setup) => setup,
+        Err(err) => {
+            if let Some(out) = document_out.as_mut() {
+                cli::output::Document::failed(&err).write(out.as_mut())?;
+            }
+            if agent::abort::Aborted::reason_of(&err).is_some() {
+                // exits with its own code instead of being printed
+                log::error!("{}", err);
+            }
+            return cli::status::exit(Err(err), None, None, false);
+        }
+    };
+    #[cfg(feature = "serve")]
+    if let Some(address) = &args.events_address {
+        let token = args
+            .events_token
+            .clone()
+            .or_else(|| std::env::var("NERVE_EVENTS_TOKEN").ok());
+        events_rx =
+            cli::stream::serve_events(address, args.events_allow_origin.clone(), token, events_rx)
+                .await?;
+    }
+    #[cfg(not(feature = "serve"))]
+    if args.events_address.is_some() {
+        return Err(anyhow!(
+            "nerve was built without the serve feature, enable it to use --events-address"
+        ));
+    }
 
-    let (mut agent, events_rx) = setup::setup_agent(&args).await?;
+    let report = args.report.clone();
+    let engagement_report = args.engagement_report.clone();
+    let judge_args = args.judge_output.then(|| args.clone());
 
     // spawn the events consumer
-    tokio::spawn(ui::text::consume_events(args, events_rx));
+    let consumer = if tui {
+        let state = std::sync::Arc::downgrade(&agent.get_state());
+        tokio::spawn(ui::tui::consume_events(args, events_rx, state))
+    } else {
+        tokio::spawn(ui::text::consume_events(args, events_rx))
+    };
+
+    // stop gracefully on ctrl+c and SIGTERM
+    let stop = agent.stop_handle();
+    let abort = cli::signals::handle(stop.clone());
+
+    // keep going until the task is complete, a fatal error is reached or the user interrupts
+    let mut interrupted = false;
+    let ret = tokio::select! {
+        ret = agent.run() => ret,
+        _ = abort.notified() => {
+            // the in-flight step has been cancelled, save what we have and wrap up
+            interrupted = true;
+            agent.on_interrupted().await;
+            agent.on_end().await
+        }
+    };
+    // or it stopped at the end of the step
+    interrupted = interrupted
+        || (stop.load(std::sync::atomic::Ordering::SeqCst)
+            && !agent.get_state().lock().await.is_complete());
+
+    // the deliverable of the run, unless the user wants it to stop
+    let template = agent
+        .get_state()
+        .lock()
+        .await
+        .get_task()
+        .get_report_template();
+    let engagement_report =
+        engagement_report.or_else(|| template.as_ref().and_then(|t| t.path.clone()));
+    if let Some(path) = engagement_report.filter(|_| !interrupted) {
+        log::info!("compiling the report ...");
+        match agent
+            .compile_report(&template.unwrap_or_default())
+            .await
+            .and_then(|report| cli::engagement::save(&path, &report))
+        {
+            Ok((md, html)) => log::info!("report saved to {} and {}", md.display(), html.display()),
+            Err(err) => log::error!("could not compile the report: {}", err),
+        }
+    }
+
+    let session = agent.to_session().await;
+
+    // have another model review the output of the completed task
+    let (ret, verdict) = match (&judge_args, &session) {
+        (Some(args), Ok(session))
+            if ret.is_ok() && !interrupted && cli::report::outcome(session) == "complete" =>
+        {
+            log::info!("judging the output with {} ...", args.judge);
+            match cli::judge::judge(args, agent.get_state(), session).await {
+                Ok(verdict) => (ret, Some(verdict)),
+                Err(err) => (Err(anyhow!("could not judge the output: {}", err)), None),
+            }
+        }
+        _ => (ret, None),
+    };
+
+    // close the events channel and give the consumer a chance to print what's left
+    drop(agent);
+    let _ = tokio::time::timeout(std::time::Duration::from_secs(1), consumer).await;
+    if tui {
+        ui::tui::close();
+    }
+
+    // summarize and export the run regardless of how it ended
+    if let Some(out) = document_out.as_mut() {
+        cli::output::Document::new(&session, &ret, interrupted)
+            .with_verdict(verdict.clone())
+            .write(out.as_mut())?;
+    }
+
+    match &session {
+        Ok(session) => {
+            for line in cli::report::Summary::from_session(session)
+                .to_string()
+                .lines()
+            {
+                log::info!("{}", line);
+            }
+            if let Some(verdict) = &verdict {
+                let status = if verdict.pass { "pass" } else { "fail" };
+                log::info!("verdict: {} ({})", status, verdict.critique);
+            }
 
-    // keep going until the task is complete or a fatal error is reached
-    while !agent.is_done().await {
-        // next step
-        if let Err(error) = agent.step().await {
-            log::error!("{}", error.to_string());
-            return Err(error);
+            if let Some(path) = &report {
+                match cli::report::Report::from_session(session)
+                    .with_verdict(verdict.clone())
+                    .save(path)
+                {
+                    Ok((json, md)) => {
+                        log::info!("report saved to {} and {}", json.display(), md.display())
+                    }
+                    Err(err) => log::error!("could not save the report: {}", err),
+                }
+            }
         }
+        Err(err) => log::error!("could not summarize the run: {}", err),
     }
 
-    agent.on_end().await
+    cli::status::exit(ret, session.as_ref().ok(), verdict.as_ref(), interrupted)
 }